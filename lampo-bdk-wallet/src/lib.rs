@@ -5,6 +5,7 @@ use std::sync::{Arc, Mutex};
 use bdk::bitcoin::bip32::ExtendedPrivKey;
 use bdk::bitcoin::consensus::serialize;
 use bdk::bitcoin::{Amount, ScriptBuf};
+use bdk::chain::ConfirmationTime;
 use bdk::keys::bip39::{Language, Mnemonic, WordCount};
 use bdk::keys::GeneratableKey;
 use bdk::keys::{DerivableKey, ExtendedKey, GeneratedKey};
@@ -16,7 +17,7 @@ use bdk_file_store::Store;
 
 use lampo_common::bitcoin::consensus::deserialize;
 use lampo_common::bitcoin::hashes::hex::ToHex;
-use lampo_common::bitcoin::{PrivateKey, Script, Transaction};
+use lampo_common::bitcoin::{OutPoint, PrivateKey, Script, Transaction, TxOut};
 use lampo_common::conf::{LampoConf, Network};
 use lampo_common::error;
 use lampo_common::keys::LampoKeys;
@@ -63,7 +64,13 @@ impl BDKWalletManager {
             format!("{}/onchain", conf.path()),
         )
         .map_err(|err| bdk::Error::Generic(format!("{err}")))?;
-        let ldk_keys = LampoKeys::new(xprv.private_key.secret_bytes());
+        let mut ldk_keys = LampoKeys::new(xprv.private_key.secret_bytes());
+        let phantom_seed = conf
+            .phantom_seed()
+            .map_err(|err| bdk::Error::Generic(format!("{err}")))?;
+        if let Some(seed) = phantom_seed {
+            ldk_keys = ldk_keys.with_phantom_seed(seed);
+        }
         // Create a BDK wallet structure using BIP 84 descriptor ("m/84h/1h/0h/0" and "m/84h/1h/0h/1")
         let wallet = Wallet::new(
             Bip84(xprv, KeychainKind::External),
@@ -148,6 +155,8 @@ impl WalletManager for BDKWalletManager {
             .get_address(bdk::wallet::AddressIndex::New);
         Ok(NewAddress {
             address: address.address.to_string(),
+            qr: None,
+            label: None,
         })
     }
 
@@ -181,6 +190,96 @@ impl WalletManager for BDKWalletManager {
         Ok(tx)
     }
 
+    fn create_transaction_with_inputs(
+        &self,
+        inputs: &[OutPoint],
+        outputs: &[(Script, u64)],
+        fee_rate: u32,
+    ) -> error::Result<Transaction> {
+        self.sync()?;
+        let wallet = self.wallet.borrow_mut();
+        let mut wallet = wallet.lock().unwrap();
+        // the bitcoin types of bdk can be of another version, so
+        // we convert them with the consensus encoding.
+        let inputs = inputs
+            .iter()
+            .map(|input| {
+                Ok(deserialize(&lampo_common::bitcoin::consensus::serialize(
+                    input,
+                ))?)
+            })
+            .collect::<error::Result<Vec<bdk::bitcoin::OutPoint>>>()?;
+        let mut tx = wallet.build_tx();
+        for (script, amount_sat) in outputs {
+            tx.add_recipient(ScriptBuf::from_bytes(script.to_bytes()), *amount_sat);
+        }
+        tx.add_utxos(&inputs)?
+            .manually_selected_only()
+            .fee_rate(FeeRate::from_sat_per_vb(fee_rate as f32 / 250.0));
+        let mut psbt = tx.finish()?;
+        if !wallet.sign(&mut psbt, SignOptions::default())? {
+            error::bail!("wallet not able to sing the psbt {psbt}");
+        }
+        if !wallet.finalize_psbt(&mut psbt, SignOptions::default())? {
+            error::bail!("wallet impossible finalize the psbt: {psbt}");
+        };
+        let tx: Transaction = deserialize(&serialize(&psbt.extract_tx()))?;
+        Ok(tx)
+    }
+
+    fn list_confirmed_utxos(&self) -> error::Result<Vec<(OutPoint, TxOut)>> {
+        self.sync()?;
+        let wallet = self.wallet.borrow();
+        let wallet = wallet.lock().unwrap();
+        wallet
+            .list_unspent()
+            .filter(|utxo| {
+                !utxo.is_spent
+                    && matches!(utxo.confirmation_time, ConfirmationTime::Confirmed { .. })
+            })
+            .map(|utxo| {
+                let outpoint = deserialize(&serialize(&utxo.outpoint))?;
+                let output = deserialize(&serialize(&utxo.txout))?;
+                Ok((outpoint, output))
+            })
+            .collect()
+    }
+
+    fn create_withdrawal(
+        &self,
+        script: Script,
+        amount_sat: Option<u64>,
+        fee_rate: u32,
+        rbf: bool,
+    ) -> error::Result<Transaction> {
+        self.sync()?;
+        let wallet = self.wallet.borrow_mut();
+        let mut wallet = wallet.lock().unwrap();
+        let script = ScriptBuf::from_bytes(script.into_bytes());
+        let mut tx = wallet.build_tx();
+        match amount_sat {
+            Some(amount_sat) => {
+                tx.add_recipient(script, amount_sat);
+            }
+            None => {
+                tx.drain_wallet().drain_to(script);
+            }
+        }
+        tx.fee_rate(FeeRate::from_sat_per_vb(fee_rate as f32 / 250.0));
+        if rbf {
+            tx.enable_rbf();
+        }
+        let mut psbt = tx.finish()?;
+        if !wallet.sign(&mut psbt, SignOptions::default())? {
+            error::bail!("wallet not able to sing the psbt {psbt}");
+        }
+        if !wallet.finalize_psbt(&mut psbt, SignOptions::default())? {
+            error::bail!("wallet impossible finalize the psbt: {psbt}");
+        };
+        let tx: Transaction = deserialize(&serialize(&psbt.extract_tx()))?;
+        Ok(tx)
+    }
+
     fn list_transactions(&self) -> error::Result<Vec<Utxo>> {
         self.sync()?;
         let wallet = self.wallet.borrow();
@@ -193,6 +292,8 @@ impl WalletManager for BDKWalletManager {
                 reserved: tx.is_spent,
                 confirmed: 0,
                 amount_msat: Amount::from_btc(tx.txout.value as f64).unwrap().to_sat() * 1000_u64,
+                address: None,
+                label: None,
             })
             .collect::<Vec<_>>();
         Ok(txs)
@@ -1,6 +1,8 @@
 //! Implementation of the bitcoin backend for
 //! lampo.
 use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ops::Range;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
@@ -16,13 +18,26 @@ use lampo_common::backend::{deserialize, serialize};
 use lampo_common::backend::{Backend, TxResult};
 use lampo_common::backend::{Block, BlockData, BlockHash};
 use lampo_common::bitcoin::absolute::Height;
-use lampo_common::bitcoin::{Transaction, Txid};
+use lampo_common::bitcoin::{Network, Transaction, Txid};
 use lampo_common::error;
 use lampo_common::event::onchain::OnChainEvent;
 use lampo_common::event::Event;
 use lampo_common::handler::Handler;
 use lampo_common::json;
 
+/// Max number of blocks that we download and filter in parallel
+/// while catching up with the chain.
+const MAX_SYNC_WORKERS: usize = 8;
+
+/// Block downloaded during the catch up, with the watched
+/// transactions that it contains.
+struct FilteredBlock {
+    height: u64,
+    hash: BlockHash,
+    block: Block,
+    txs: Vec<(usize, Transaction)>,
+}
+
 pub struct BitcoinCore {
     inner: Client,
     handler: RefCell<Option<Arc<dyn Handler>>>,
@@ -110,37 +125,148 @@ impl BitcoinCore {
         Ok(block_hash)
     }
 
+    /// The transactions that we are waiting to see inside a block.
+    fn watched_txids(&self) -> HashSet<Txid> {
+        self.others_txs
+            .lock()
+            .unwrap()
+            .borrow()
+            .iter()
+            .map(|(txid, _)| *txid)
+            .collect()
+    }
+
+    /// Return the transactions of the block that are inside `watched`.
+    fn filter_block(block: &Block, watched: &HashSet<Txid>) -> Vec<(usize, Transaction)> {
+        if watched.is_empty() {
+            return vec![];
+        }
+        block
+            .txdata
+            .iter()
+            .enumerate()
+            .filter(|(_, tx)| watched.contains(&tx.txid()))
+            .map(|(idx, tx)| (idx, tx.clone()))
+            .collect()
+    }
+
     pub fn find_tx_in_block(&self, block: &Block) -> error::Result<()> {
         log::debug!(target: "bitcoin", "looking the tx inside the new block");
-        let utxos = self.others_txs.lock().unwrap();
-        let mut utxos = utxos.borrow_mut();
-        let mut still_unconfirmed: Vec<(Txid, ScriptBuf)> = vec![];
-        for (utxo, script) in utxos.iter() {
-            log::debug!(target: "bitcoind", "looking for UTXO {} inside the block at height: {}", utxo, self.best_height.borrow());
-            if let Some((idx, tx)) = block
-                .txdata
-                .iter()
-                .enumerate()
-                .find(|(_, tx)| tx.txid() == *utxo)
-            {
-                // Confirmed!
-                let handler = self.handler.borrow();
-                let handler = handler
-                    .as_ref()
-                    .ok_or(error::anyhow!("handler is not sent"))?;
-                handler.emit(Event::OnChain(OnChainEvent::ConfirmedTransaction((
-                    tx.clone(),
-                    idx as u32,
+        let txs = Self::filter_block(block, &self.watched_txids());
+        self.confirm_txs(block, txs)
+    }
+
+    /// Emit the confirmation of the watched transactions `txs`
+    /// found inside the block at the current best height.
+    fn confirm_txs(&self, block: &Block, txs: Vec<(usize, Transaction)>) -> error::Result<()> {
+        if txs.is_empty() {
+            return Ok(());
+        }
+        let handler = self.handler.borrow();
+        let handler = handler
+            .as_ref()
+            .ok_or(error::anyhow!("handler is not sent"))?;
+        let height = Height::from_consensus(*self.best_height.borrow() as u32)?;
+        let confirmed = txs.iter().map(|(_, tx)| tx.txid()).collect::<HashSet<_>>();
+        for (idx, tx) in txs {
+            log::debug!(target: "bitcoind", "transaction `{}` confirmed at height {height}", tx.txid());
+            handler.emit(Event::OnChain(OnChainEvent::ConfirmedTransaction((
+                tx,
+                idx as u32,
+                block.header,
+                height,
+            ))));
+        }
+        self.others_txs
+            .lock()
+            .unwrap()
+            .borrow_mut()
+            .retain(|(txid, _)| !confirmed.contains(txid));
+        Ok(())
+    }
+
+    /// Download the blocks in `heights` and filter them against
+    /// `watched` with a pool of workers, the blocks are returned
+    /// in order and the one that we are not able to fetch are skipped.
+    fn fetch_blocks(&self, heights: Range<u64>, watched: &HashSet<Txid>) -> Vec<FilteredBlock> {
+        std::thread::scope(|scope| {
+            let workers = heights
+                .map(|height| {
+                    scope.spawn(move || -> error::Result<FilteredBlock> {
+                        let hash = self.get_block_hash(height)?;
+                        let BlockData::FullBlock(block) = self.get_block(&hash)? else {
+                            error::bail!("bitcoin core returned only the header of `{hash}`");
+                        };
+                        let txs = Self::filter_block(&block, watched);
+                        Ok(FilteredBlock {
+                            height,
+                            hash,
+                            block,
+                            txs,
+                        })
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .filter_map(|worker| match worker.join() {
+                    Ok(Ok(block)) => Some(block),
+                    Ok(Err(err)) => {
+                        log::warn!(target: "bitcoind", "Impossible retrieval the block: {err}");
+                        None
+                    }
+                    Err(_) => {
+                        log::error!(target: "bitcoind", "block filter worker panicked");
+                        None
+                    }
+                })
+                .collect()
+        })
+    }
+
+    /// Scan the blocks in `[start..end]`, the blocks are downloaded
+    /// and filtered in parallel but the events are emitted in order.
+    fn catch_up(&self, handler: &Arc<dyn Handler>, start: u64, end: u64) {
+        let workers = std::thread::available_parallelism()
+            .map(|workers| workers.get())
+            .unwrap_or(1)
+            .min(MAX_SYNC_WORKERS) as u64;
+        let mut height = start;
+        while height <= end {
+            let batch_end = (height + workers).min(end + 1);
+            log::trace!(target: "bitcoind", "Looking at blocks in range [{height}..{batch_end})");
+            let watched = self.watched_txids();
+            for filtered in self.fetch_blocks(height..batch_end, &watched) {
+                let FilteredBlock {
+                    height,
+                    hash,
+                    block,
+                    mut txs,
+                } = filtered;
+                if !self.best_height.borrow().lt(&height) {
+                    continue;
+                }
+                *self.best_height.borrow_mut() = height;
+                *self.last_bloch_hash.borrow_mut() = Some(hash);
+                log::trace!(target: "bitcoind", "new best block with hash `{hash}` at height `{height}`");
+                handler.emit(Event::OnChain(OnChainEvent::NewBestBlock((
                     block.header,
-                    Height::from_consensus(*self.best_height.borrow() as u32)?,
+                    // SAFETY: the height should be always a valid u32
+                    Height::from_consensus(height as u32).unwrap(),
                 ))));
-            } else {
-                still_unconfirmed.push((*utxo, script.clone()));
+                handler.emit(Event::OnChain(OnChainEvent::NewBlock(block.clone())));
+                // the outputs registered while we were downloading
+                // the batch are not inside `watched`.
+                let new_watched = self
+                    .watched_txids()
+                    .difference(&watched)
+                    .cloned()
+                    .collect::<HashSet<_>>();
+                txs.extend(Self::filter_block(&block, &new_watched));
+                let _ = self.confirm_txs(&block, txs);
             }
+            height = batch_end;
         }
-        utxos.clear();
-        utxos.append(&mut still_unconfirmed);
-        Ok(())
     }
 }
 
@@ -173,6 +299,31 @@ impl Backend for BitcoinCore {
         }
     }
 
+    fn network(&self) -> error::Result<Network> {
+        let chain_info = self.inner.get_blockchain_info()?;
+        let network = match chain_info.chain.as_str() {
+            "main" => Network::Bitcoin,
+            "test" => Network::Testnet,
+            "signet" => Network::Signet,
+            "regtest" => Network::Regtest,
+            "testnet4" => {
+                error::bail!("bitcoin core is running on `testnet4` that is not supported yet")
+            }
+            chain => error::bail!("bitcoin core is running on an unknown chain `{chain}`"),
+        };
+        Ok(network)
+    }
+
+    fn signet_challenge(&self) -> error::Result<Option<String>> {
+        // the `signet_challenge` is not inside the typed response.
+        let chain_info: json::Value = self.inner.call("getblockchaininfo", &[])?;
+        let challenge = chain_info
+            .get("signet_challenge")
+            .and_then(|challenge| challenge.as_str())
+            .map(|challenge| challenge.to_owned());
+        Ok(challenge)
+    }
+
     /// Returning the fee rate estimation in sats.
     fn fee_rate_estimation(&self, blocks: u64) -> error::Result<u32> {
         let result = self.inner.estimate_smart_fee(blocks as u16, None)?;
@@ -440,28 +591,7 @@ impl Backend for BitcoinCore {
                     let start: u64 = *self.best_height.borrow();
                     let end: u64 = height.into();
                     log::trace!(target: "bitcoind", "Scan blocks in range [{start}..{end}]");
-                    for height in start..end + 1 {
-                        log::trace!(target: "bitcoind", "Looking at block with height {height}");
-                        let block_hash = self.get_block_hash(height).unwrap();
-                        let Ok(lampo_common::backend::BlockData::FullBlock(block)) =
-                            self.get_block(&block_hash)
-                        else {
-                            log::warn!(target: "bitcoind", "Impossible retrieval the block information with hash `{block_hash}`");
-                            continue;
-                        };
-                        if self.best_height.borrow().lt(&height) {
-                            *self.best_height.borrow_mut() = height;
-                            *self.last_bloch_hash.borrow_mut() = Some(block_hash);
-                            log::trace!(target: "bitcoind", "new best block with hash `{block_hash}` at height `{height}`");
-                            handler.emit(Event::OnChain(OnChainEvent::NewBestBlock((
-                                block.header,
-                                // SAFETY: the height should be always a valid u32
-                                Height::from_consensus(height as u32).unwrap(),
-                            ))));
-                            handler.emit(Event::OnChain(OnChainEvent::NewBlock(block.clone())));
-                            let _ = self.find_tx_in_block(&block);
-                        }
-                    }
+                    self.catch_up(&handler, start, end);
                     // ok when the wallet is in full sync with the blockchain, we can query the
                     // bitcoind wallet for our transaction.
                     //
@@ -473,7 +603,13 @@ impl Backend for BitcoinCore {
                     // to answer quickly while the lampo wallet is still looking
                     // for external transaction inside the blocks.
                     let _ = self.process_transactions();
-                } else if self.best_height.borrow().lt(&height.into()) {
+                } else if self.best_height.borrow().lt(&height.into())
+                    || self
+                        .last_bloch_hash
+                        .borrow()
+                        .is_some_and(|hash| hash != block_hash)
+                {
+                    // a new tip that is not higher than the previous one is a reorg
                     log::trace!(target: "bitcoind", "New best block at height {height}, out current best block is {}", self.best_height.borrow());
                     *self.best_height.borrow_mut() = height.into();
                     *self.last_bloch_hash.borrow_mut() = Some(block_hash);
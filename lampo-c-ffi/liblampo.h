@@ -18,6 +18,12 @@
  */
 typedef struct LampoDaemon LampoDaemon;
 
+/**
+ * Version of the C ABI, it is increased every time that
+ * a function signature change.
+ */
+#define LAMPO_FFI_VERSION 1
+
 /**
  * Add a JSON RPC 2.0 Sever that listen on a unixsocket, and return a error code
  * < 0 is an error happens, or 0 is all goes well.
@@ -25,21 +31,44 @@ typedef struct LampoDaemon LampoDaemon;
 int64_t add_jsonrpc_on_unixsocket(struct LampoDaemon *lampod);
 
 /**
- * Allow to create a lampo daemon from a configuration patch!
+ * Release the lampo daemon created with `new_lampod`.
  */
 void free_lampod(struct LampoDaemon *lampod);
 
 /**
- * Add a JSON RPC 2.0 Sever that listen on a unixsocket, and return a error code
- * < 0 is an error happens, or 0 is all goes well.
+ * Return the version of the C ABI exposed by the library.
+ */
+uint32_t lampo_ffi_version(void);
+
+/**
+ * Release a string returned by the library.
+ */
+void lampo_free_string(char *value);
+
+/**
+ * Return the last error happened inside the library, or null. The
+ * error must be released with `lampo_free_string`.
  */
 const char *lampo_last_errror(void);
 
 /**
- * Allow to create a lampo daemon from a configuration patch!
+ * Start the node in background, without blocking the
+ * binding language.
  */
 void lampo_listen(struct LampoDaemon *lampod);
 
+/**
+ * Stop the node started with `lampo_listen`, and return a error code
+ * < 0 is an error happens, or 0 is all goes well.
+ */
+int64_t lampo_stop(struct LampoDaemon *lampod);
+
+/**
+ * Call a method of the daemon with a JSON payload, and return the
+ * JSON response or null in case of error, the error can be
+ * read with `lampo_last_errror`. The response must be released
+ * with `lampo_free_string`.
+ */
 const char *lampod_call(struct LampoDaemon *lampod, const char *method, const char *buffer);
 
 /**
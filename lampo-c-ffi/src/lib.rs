@@ -58,26 +58,40 @@ macro_rules! c_free {
     ($x:expr) => {{
         if !$x.is_null() {
             unsafe {
-                let _ = Box::from_raw($x);
+                let _ = Arc::from_raw($x);
             };
         }
     }};
 }
 
+/// Borrow the daemon from the pointer returned by `new_lampod` without
+/// taking the ownership, the pointer is still valid until `free_lampod`.
 #[macro_export]
 macro_rules! as_rust {
     ($x:expr) => {{
         if !$x.is_null() {
-            unsafe { Some(Arc::from_raw($x)) }
+            unsafe {
+                Arc::increment_strong_count($x);
+                Some(Arc::from_raw($x))
+            }
         } else {
             None
         }
     }};
 }
 
+/// Version of the C ABI, it is increased every time that
+/// a function signature change.
+pub const LAMPO_FFI_VERSION: u32 = 1;
+
 static INIT: Once = Once::new();
 static LAST_ERR: Mutex<Cell<Option<String>>> = Mutex::new(Cell::new(None));
 
+fn set_last_err(err: String) {
+    log::error!("{err}");
+    LAST_ERR.lock().unwrap().set(Some(err));
+}
+
 fn init_logger() {
     #[cfg(not(target_os = "android"))]
     {
@@ -159,7 +173,10 @@ pub extern "C" fn new_lampod(conf_path: *const libc::c_char) -> *mut LampoDaemon
             wallet
         }
         #[cfg(not(debug_assertions))]
-        unimplemented!()
+        {
+            set_last_err("the private key is supported only by the debug builds".to_owned());
+            return null!();
+        }
     } else {
         // FIXME: add the possibility to create it from the mnemonic
         let Ok((wallet, _mnemonic)) = CoreWalletManager::new(conf.clone()) else {
@@ -172,27 +189,36 @@ pub extern "C" fn new_lampod(conf_path: *const libc::c_char) -> *mut LampoDaemon
         wallet
     };
 
-    // FIXME: return an error and not just unwrap the value
     let client: Arc<dyn Backend> = match conf.node.clone().as_str() {
-        "core" => Arc::new(
-            BitcoinCore::new(
-                &conf.core_url.clone().expect("please add the url"),
-                &conf.core_user.clone().expect("plaase add the user"),
-                &conf.core_pass.clone().expect("please add the pass"),
-                Arc::new(false),
-                Some(1),
-            )
-            .expect("impossible connect to core"),
-        ),
+        "core" => {
+            let (Some(url), Some(user), Some(pass)) = (
+                conf.core_url.as_ref(),
+                conf.core_user.as_ref(),
+                conf.core_pass.as_ref(),
+            ) else {
+                set_last_err("the core url, user and pass are required".to_owned());
+                return null!();
+            };
+            match BitcoinCore::new(url, user, pass, Arc::new(false), Some(1)) {
+                Ok(core) => Arc::new(core),
+                Err(err) => {
+                    set_last_err(format!("impossible connect to core: {err}"));
+                    return null!();
+                }
+            }
+        }
         _ => {
-            LAST_ERR
-                .lock()
-                .unwrap()
-                .set(Some(format!("backend `{}` not supported", conf.node)));
+            set_last_err(format!("backend `{}` not supported", conf.node));
+            return null!();
+        }
+    };
+    let mut lampod = match LampoDaemon::new(conf.as_ref().clone(), Arc::new(wallet)) {
+        Ok(lampod) => lampod,
+        Err(err) => {
+            set_last_err(format!("error while loading the node {:?}", err));
             return null!();
         }
     };
-    let mut lampod = LampoDaemon::new(conf.as_ref().clone(), Arc::new(wallet));
     if let Err(err) = lampod.init(client) {
         LAST_ERR
             .lock()
@@ -200,12 +226,11 @@ pub extern "C" fn new_lampod(conf_path: *const libc::c_char) -> *mut LampoDaemon
             .set(Some(format!("error while init the node {:?}", err)));
         return null!();
     }
-    let lampod = Box::new(lampod);
-    Box::into_raw(lampod)
+    Arc::into_raw(Arc::new(lampod)) as *mut LampoDaemon
 }
 
-/// Add a JSON RPC 2.0 Sever that listen on a unixsocket, and return a error code
-/// < 0 is an error happens, or 0 is all goes well.
+/// Return the last error happened inside the library, or null. The
+/// error must be released with `lampo_free_string`.
 #[no_mangle]
 pub extern "C" fn lampo_last_errror() -> *const libc::c_char {
     let value = LAST_ERR.lock().unwrap().take();
@@ -249,6 +274,10 @@ pub extern "C" fn add_jsonrpc_on_unixsocket(lampod: *mut LampoDaemon) -> i64 {
     0
 }
 
+/// Call a method of the daemon with a JSON payload, and return the
+/// JSON response or null in case of error, the error can be
+/// read with `lampo_last_errror`. The response must be released
+/// with `lampo_free_string`.
 #[no_mangle]
 pub extern "C" fn lampod_call(
     lampod: *mut LampoDaemon,
@@ -258,24 +287,31 @@ pub extern "C" fn lampod_call(
     use lampo_common::json;
 
     let Some(lampod) = as_rust!(lampod) else {
+        set_last_err("invalid lampod pointer".to_owned());
         return null!();
     };
-    let method = from_cstr!(method);
-    let buffer = from_cstr!(buffer);
-    // FIXME: check for error here before unwrap
-    let Ok(payload) = json::from_str::<json::Value>(buffer.unwrap()) else {
+    let (Some(method), Some(buffer)) = (from_cstr!(method), from_cstr!(buffer)) else {
+        set_last_err("invalid c string for the method or the payload".to_owned());
         return null!();
     };
-    // FIXME: check for error before unwrap
-    let response = lampod.call(method.unwrap(), payload);
-    // FIXME Encode this to a string
-    match response {
+    let payload = match json::from_str::<json::Value>(buffer) {
+        Ok(payload) => payload,
+        Err(err) => {
+            set_last_err(format!("invalid JSON payload: {err}"));
+            return null!();
+        }
+    };
+    match lampod.call(method, payload) {
         Ok(resp) => json_buffer!(&resp),
-        Err(_) => null!(),
+        Err(err) => {
+            set_last_err(format!("{err}"));
+            null!()
+        }
     }
 }
 
-/// Allow to create a lampo daemon from a configuration patch!
+/// Start the node in background, without blocking the
+/// binding language.
 #[no_mangle]
 pub extern "C" fn lampo_listen(lampod: *mut LampoDaemon) {
     let Some(lampod) = as_rust!(lampod) else {
@@ -286,7 +322,39 @@ pub extern "C" fn lampo_listen(lampod: *mut LampoDaemon) {
     std::thread::spawn(move || lampod.listen().map(|lampod| lampod.join()));
 }
 
-/// Allow to create a lampo daemon from a configuration patch!
+/// Stop the node started with `lampo_listen`, and return a error code
+/// < 0 is an error happens, or 0 is all goes well.
+#[no_mangle]
+pub extern "C" fn lampo_stop(lampod: *mut LampoDaemon) -> i64 {
+    let Some(lampod) = as_rust!(lampod) else {
+        return -1;
+    };
+    if let Err(err) = lampod.stop() {
+        set_last_err(format!("{err}"));
+        return -2;
+    }
+    0
+}
+
+/// Return the version of the C ABI exposed by the library.
+#[no_mangle]
+pub extern "C" fn lampo_ffi_version() -> u32 {
+    LAMPO_FFI_VERSION
+}
+
+/// Release a string returned by the library.
+#[no_mangle]
+pub extern "C" fn lampo_free_string(value: *mut libc::c_char) {
+    use std::ffi::CString;
+
+    if !value.is_null() {
+        unsafe {
+            let _ = CString::from_raw(value);
+        }
+    }
+}
+
+/// Release the lampo daemon created with `new_lampod`.
 #[no_mangle]
 pub extern "C" fn free_lampod(lampod: *mut LampoDaemon) {
     c_free!(lampod);
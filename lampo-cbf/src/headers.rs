@@ -0,0 +1,142 @@
+//! Chain of the block headers downloaded from the peers.
+//!
+//! The headers are stored inside a file, one after the other in
+//! the consensus encoding, so the height of a header is its
+//! position inside the file and the restart does not download
+//! the whole chain again.
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use lampo_common::backend::{deserialize, serialize, BlockHash};
+use lampo_common::bitcoin::block::Header;
+use lampo_common::bitcoin::blockdata::constants::genesis_block;
+use lampo_common::bitcoin::Network;
+use lampo_common::error;
+
+/// Size of a block header in the consensus encoding.
+const HEADER_SIZE: usize = 80;
+
+pub struct HeaderChain {
+    file: File,
+    headers: Vec<Header>,
+}
+
+impl HeaderChain {
+    /// Load the headers stored inside `path`, the chain
+    /// starts from the genesis block of `network`.
+    pub fn load(path: &Path, network: Network) -> error::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let mut headers = Vec::with_capacity(bytes.len() / HEADER_SIZE);
+        for chunk in bytes.chunks_exact(HEADER_SIZE) {
+            headers.push(deserialize::<Header>(chunk)?);
+        }
+        let genesis = genesis_block(network).header;
+        if headers.first() != Some(&genesis) {
+            if !headers.is_empty() {
+                log::warn!(target: "cbf", "the headers inside `{}` are not of `{network}`, downloading them again", path.display());
+            }
+            headers = vec![];
+            file.set_len(0)?;
+            file.write_all(&serialize(&genesis))?;
+            headers.push(genesis);
+        }
+        // a header written only in part during a crash
+        file.set_len((headers.len() * HEADER_SIZE) as u64)?;
+        Ok(Self { file, headers })
+    }
+
+    pub fn tip(&self) -> (BlockHash, u32) {
+        // SAFETY: the chain contains at least the genesis block.
+        let tip = self.headers.last().unwrap();
+        (tip.block_hash(), self.height())
+    }
+
+    pub fn height(&self) -> u32 {
+        (self.headers.len() - 1) as u32
+    }
+
+    pub fn header(&self, height: u32) -> Option<&Header> {
+        self.headers.get(height as usize)
+    }
+
+    pub fn height_of(&self, hash: &BlockHash) -> Option<u32> {
+        // the blocks that we look for are usually the recent ones
+        self.headers
+            .iter()
+            .rposition(|header| header.block_hash() == *hash)
+            .map(|height| height as u32)
+    }
+
+    /// The hashes that we send to the peers to find where our
+    /// chain forks from their chain, the first is our tip and
+    /// then they are more and more spaced until the genesis.
+    pub fn locator(&self) -> Vec<BlockHash> {
+        let mut locator = Vec::new();
+        let mut height = self.headers.len() - 1;
+        let mut step = 1;
+        loop {
+            locator.push(self.headers[height].block_hash());
+            if height == 0 {
+                break;
+            }
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step);
+        }
+        locator
+    }
+
+    /// Connect the `headers` received from a peer to the chain, a
+    /// chain that forks from our chain replaces the blocks after the
+    /// fork only if it is longer. Return the height of the last block
+    /// in common with the previous chain, if it changed.
+    pub fn connect(&mut self, headers: &[Header]) -> error::Result<Option<u32>> {
+        let Some(first) = headers.first() else {
+            return Ok(None);
+        };
+        let Some(fork) = self.height_of(&first.prev_blockhash) else {
+            error::bail!(
+                "the header `{}` does not connect to our chain",
+                first.block_hash()
+            );
+        };
+        let mut prev = first.prev_blockhash;
+        for header in headers {
+            if header.prev_blockhash != prev {
+                error::bail!(
+                    "the header `{}` does not connect to the previous one",
+                    header.block_hash()
+                );
+            }
+            prev = header
+                .validate_pow(header.target())
+                .map_err(|err| error::anyhow!("invalid header `{}`: {err}", header.block_hash()))?;
+        }
+        if fork + headers.len() as u32 <= self.height() {
+            log::debug!(target: "cbf", "ignoring a fork at height {fork} that is not longer than our chain");
+            return Ok(None);
+        }
+        let reorg = fork < self.height();
+        if reorg {
+            log::warn!(target: "cbf", "the chain forked at height {fork}, {} blocks are replaced", self.height() - fork);
+            self.headers.truncate(fork as usize + 1);
+            self.file
+                .set_len((self.headers.len() * HEADER_SIZE) as u64)?;
+        }
+        let mut bytes = Vec::with_capacity(headers.len() * HEADER_SIZE);
+        for header in headers {
+            bytes.extend(serialize(header));
+        }
+        self.file.write_all(&bytes)?;
+        self.headers.extend_from_slice(headers);
+        Ok(reorg.then_some(fork))
+    }
+}
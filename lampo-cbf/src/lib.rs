@@ -0,0 +1,661 @@
+//! Compact block filters (BIP 157/158) backend for lampo.
+//!
+//! The backend is a light client of the bitcoin network, it
+//! downloads the block headers and the filters of the blocks
+//! from the peers that serve them, and it downloads a full block
+//! only when its filter matches one of the scripts that ldk asked
+//! to watch (`register_tx` and `register_output`, e.g: when the
+//! channel monitors are loaded with `load_outputs_to_watch`).
+//!
+//! We do not trust a single peer with the filters: the filters
+//! are checked against the filter headers of all the connected
+//! peers, and a block is downloaded also when the peers do not
+//! agree on its filter.
+//!
+//! The `NewBlock` event is emitted only for the blocks that we
+//! download, so the components that look inside every block
+//! only see the blocks that match the scripts of ldk.
+mod headers;
+mod peer;
+
+use std::collections::{HashMap, HashSet};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use lampo_common::backend::BlockSourceError;
+use lampo_common::backend::{AsyncBlockSourceResult, BlockData, BlockHeaderData, TxResult};
+use lampo_common::backend::{Backend, BackendKind, UtxoResult, WatchedOutput};
+use lampo_common::backend::{Block, BlockHash, Script, Transaction, Txid};
+use lampo_common::bitcoin::absolute::Height;
+use lampo_common::bitcoin::bip158::{BlockFilter, FilterHash};
+use lampo_common::bitcoin::hashes::Hash;
+use lampo_common::bitcoin::network::constants::Magic;
+use lampo_common::bitcoin::network::message_filter::CFilter;
+use lampo_common::bitcoin::{Network, OutPoint, ScriptBuf};
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::event::onchain::OnChainEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::ldk::routing::utxo::UtxoLookupError;
+
+use crate::headers::HeaderChain;
+use crate::peer::Peer;
+
+/// Max number of peers that we keep connected.
+const MAX_PEERS: usize = 3;
+/// Max number of connection attempts for each sync, the
+/// DNS seeds return also nodes that are not reachable.
+const MAX_CONNECT_ATTEMPTS: usize = 12;
+/// Max number of filters that a peer sends for a single request.
+const MAX_FILTERS: u32 = 1000;
+/// A peer sends at most 2000 headers for each request.
+const MAX_HEADERS: usize = 2000;
+/// Seconds between two syncs with the peers.
+const POLL_TIME: Duration = Duration::from_secs(30);
+/// The min fee rate accepted by ldk, in sat per 1000 weight.
+const MIN_FEE_RATE: u32 = 253;
+/// Blocks after that we stop to watch a confirmed transaction or
+/// a spent output, a reorg deeper than this is not handled by
+/// the chain manager anyway.
+const REORG_DEPTH: u32 = 144;
+
+/// The outputs that ldk asked to watch.
+#[derive(Default)]
+struct Watched {
+    /// Transactions that we are waiting to see inside
+    /// a block, with one of their outputs.
+    txs: HashMap<Txid, ScriptBuf>,
+    /// Outputs that we are waiting to see spent.
+    outputs: HashMap<OutPoint, ScriptBuf>,
+    /// Height of the block that confirmed a watched transaction.
+    confirmed_at: HashMap<Txid, u32>,
+    /// Height of the block that spent a watched output.
+    spent_at: HashMap<OutPoint, u32>,
+}
+
+impl Watched {
+    fn scripts(&self) -> HashSet<ScriptBuf> {
+        self.txs
+            .values()
+            .chain(self.outputs.values())
+            .cloned()
+            .collect()
+    }
+
+    /// Return the transactions of the block at `height` that ldk is
+    /// waiting for, we keep to watch them until they are deeper than
+    /// `REORG_DEPTH` because a reorg can move them inside another block.
+    fn filter_block(&mut self, block: &Block, height: u32) -> Vec<(usize, Transaction)> {
+        let mut found = Vec::new();
+        for (idx, tx) in block.txdata.iter().enumerate() {
+            let txid = tx.txid();
+            let confirmed = self.txs.contains_key(&txid);
+            if confirmed {
+                self.confirmed_at.insert(txid, height);
+            }
+            let mut spent = false;
+            for input in &tx.input {
+                if self.outputs.contains_key(&input.previous_output) {
+                    self.spent_at.insert(input.previous_output, height);
+                    spent = true;
+                }
+            }
+            if confirmed || spent {
+                found.push((idx, tx.clone()));
+            }
+        }
+        found
+    }
+
+    /// Stop to watch the transactions and the outputs that are
+    /// deeper than `REORG_DEPTH` at the `height` of the tip.
+    fn prune(&mut self, height: u32) {
+        let txs = &mut self.txs;
+        self.confirmed_at.retain(|txid, confirmed_at| {
+            let keep = *confirmed_at + REORG_DEPTH > height;
+            if !keep {
+                txs.remove(txid);
+            }
+            keep
+        });
+        let outputs = &mut self.outputs;
+        self.spent_at.retain(|outpoint, spent_at| {
+            let keep = *spent_at + REORG_DEPTH > height;
+            if !keep {
+                outputs.remove(outpoint);
+            }
+            keep
+        });
+    }
+}
+
+pub struct LampoCbf {
+    network: Network,
+    magic: Magic,
+    /// Peers of the configuration, when empty they are
+    /// looked up with the DNS seeds.
+    addrs: Vec<String>,
+    peers: Mutex<Vec<Peer>>,
+    headers: Mutex<HeaderChain>,
+    /// Height of the last block that we scanned.
+    scan_height: Mutex<Option<u32>>,
+    scan_height_path: PathBuf,
+    watched: Mutex<Watched>,
+    handler: Mutex<Option<Arc<dyn Handler>>>,
+}
+
+impl LampoCbf {
+    pub fn new(conf: &LampoConf) -> error::Result<Self> {
+        let network = conf.network;
+        let magic = match network {
+            Network::Signet => Magic::from_bytes(conf.signet_magic()?),
+            network => network.magic(),
+        };
+        let path = PathBuf::from(conf.path()).join("cbf");
+        std::fs::create_dir_all(&path)?;
+        let headers = HeaderChain::load(&path.join("headers"), network)?;
+        let scan_height_path = path.join("scan_height");
+        let scan_height = match std::fs::read_to_string(&scan_height_path) {
+            Ok(height) => Some(u32::from_str(height.trim())?),
+            Err(_) => None,
+        };
+        log::info!(target: "cbf", "loaded {} block headers", headers.height());
+        Ok(Self {
+            network,
+            magic,
+            addrs: conf.cbf_peers.clone(),
+            peers: Mutex::new(Vec::new()),
+            headers: Mutex::new(headers),
+            scan_height: Mutex::new(scan_height),
+            scan_height_path,
+            watched: Mutex::new(Watched::default()),
+            handler: Mutex::new(None),
+        })
+    }
+
+    fn handler(&self) -> error::Result<Arc<dyn Handler>> {
+        self.handler
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(error::anyhow!("handler is not set"))
+    }
+
+    /// The DNS seeds that return the nodes with the
+    /// compact block filters, and the default port.
+    fn dns_seeds(&self) -> (&'static [&'static str], u16) {
+        match self.network {
+            Network::Bitcoin => (
+                &[
+                    "seed.bitcoin.sipa.be",
+                    "dnsseed.bluematt.me",
+                    "seed.bitcoin.sprovoost.nl",
+                    "dnsseed.emzy.de",
+                    "seed.bitcoin.wiz.biz",
+                ],
+                8333,
+            ),
+            Network::Testnet => (
+                &[
+                    "testnet-seed.bluematt.me",
+                    "seed.tbtc.petertodd.net",
+                    "seed.testnet.bitcoin.sprovoost.nl",
+                ],
+                18333,
+            ),
+            Network::Signet => (&["seed.signet.bitcoin.sprovoost.nl"], 38333),
+            _ => (&[], 18444),
+        }
+    }
+
+    /// The addresses of the peers that we can connect with.
+    fn candidates(&self) -> Vec<SocketAddr> {
+        if !self.addrs.is_empty() {
+            return self
+                .addrs
+                .iter()
+                .filter_map(|addr| addr.to_socket_addrs().ok())
+                .flatten()
+                .collect();
+        }
+        let (seeds, port) = self.dns_seeds();
+        seeds
+            .iter()
+            .filter_map(|seed| {
+                // `x49` asks only the nodes with the compact block
+                // filters (bit 6), the witness (bit 3) and the full
+                // chain (bit 0), not all the seeds support it.
+                (format!("x49.{seed}"), port)
+                    .to_socket_addrs()
+                    .or_else(|_| (*seed, port).to_socket_addrs())
+                    .ok()
+            })
+            .flatten()
+            .collect()
+    }
+
+    fn connect_peers(&self, peers: &mut Vec<Peer>) {
+        if peers.len() >= MAX_PEERS {
+            return;
+        }
+        let candidates = self.candidates();
+        for addr in candidates.into_iter().take(MAX_CONNECT_ATTEMPTS) {
+            if peers.len() >= MAX_PEERS {
+                break;
+            }
+            if peers.iter().any(|peer| peer.addr == addr) {
+                continue;
+            }
+            match Peer::connect(addr, self.magic) {
+                Ok(peer) => peers.push(peer),
+                Err(err) => log::debug!(target: "cbf", "impossible connect with `{addr}`: {err}"),
+            }
+        }
+    }
+
+    /// Run `request` with the first peer that answers, the
+    /// peers that fail are disconnected.
+    fn with_peer<T>(
+        &self,
+        mut request: impl FnMut(&mut Peer) -> error::Result<T>,
+    ) -> error::Result<T> {
+        let mut peers = self.peers.lock().unwrap();
+        self.connect_peers(&mut peers);
+        while !peers.is_empty() {
+            match request(&mut peers[0]) {
+                Ok(result) => return Ok(result),
+                Err(err) => {
+                    let peer = peers.remove(0);
+                    log::warn!(target: "cbf", "disconnecting from `{}`: {err}", peer.addr);
+                }
+            }
+            if peers.is_empty() {
+                self.connect_peers(&mut peers);
+            }
+        }
+        error::bail!("no peer serves the compact block filters")
+    }
+
+    /// Download the headers until we reach the tip of our peers.
+    fn sync_headers(&self) -> error::Result<()> {
+        loop {
+            let (received, fork) = self.with_peer(|peer| {
+                let locator = self.headers.lock().unwrap().locator();
+                let headers = peer.get_headers(locator)?;
+                let fork = self.headers.lock().unwrap().connect(&headers)?;
+                Ok((headers.len(), fork))
+            })?;
+            if let Some(fork) = fork {
+                // the blocks after the fork must be scanned again
+                let mut scan_height = self.scan_height.lock().unwrap();
+                if scan_height.map(|height| height > fork).unwrap_or(false) {
+                    *scan_height = Some(fork);
+                }
+            }
+            if received < MAX_HEADERS {
+                return Ok(());
+            }
+            log::info!(target: "cbf", "downloaded the block headers until height {}", self.headers.lock().unwrap().height());
+        }
+    }
+
+    /// The filter hashes of the blocks from `start_height` to
+    /// `stop_hash` known by all the peers, `None` for the blocks
+    /// where the peers do not agree.
+    fn filter_hashes(
+        &self,
+        start_height: u32,
+        stop_hash: BlockHash,
+    ) -> error::Result<Vec<Option<FilterHash>>> {
+        let mut peers = self.peers.lock().unwrap();
+        let mut agreed: Option<Vec<Option<FilterHash>>> = None;
+        peers.retain_mut(|peer| {
+            let hashes = match peer.get_filter_headers(start_height, stop_hash) {
+                Ok((_, hashes)) => hashes,
+                Err(err) => {
+                    log::warn!(target: "cbf", "disconnecting from `{}`: {err}", peer.addr);
+                    return false;
+                }
+            };
+            match agreed.as_mut() {
+                Some(agreed) => {
+                    for (idx, hash) in agreed.iter_mut().enumerate() {
+                        if *hash != hashes.get(idx).copied() {
+                            log::warn!(target: "cbf", "the peer `{}` has another filter for the block at height {}", peer.addr, start_height + idx as u32);
+                            *hash = None;
+                        }
+                    }
+                }
+                None => agreed = Some(hashes.into_iter().map(Some).collect()),
+            }
+            true
+        });
+        agreed.ok_or(error::anyhow!("no peer sent the filter headers"))
+    }
+
+    /// Scan the blocks from `start` to `stop` (included) and emit
+    /// the transactions that ldk is waiting for.
+    fn scan_range(&self, handler: &Arc<dyn Handler>, start: u32, stop: u32) -> error::Result<()> {
+        let headers = {
+            let chain = self.headers.lock().unwrap();
+            (start..=stop)
+                .filter_map(|height| chain.header(height).copied())
+                .collect::<Vec<_>>()
+        };
+        let stop_header = *headers
+            .last()
+            .ok_or(error::anyhow!("the block {stop} is not inside our chain"))?;
+        let stop_hash = stop_header.block_hash();
+        let scripts = self.watched.lock().unwrap().scripts();
+        if !scripts.is_empty() {
+            let filters = self
+                .with_peer(|peer| peer.get_filters(start, stop_hash, headers.len()))?
+                .into_iter()
+                .map(|filter| (filter.block_hash, filter))
+                .collect::<HashMap<BlockHash, CFilter>>();
+            let hashes = self.filter_hashes(start, stop_hash)?;
+            for (idx, header) in headers.iter().enumerate() {
+                let hash = header.block_hash();
+                let height = start + idx as u32;
+                let matched = match (filters.get(&hash), hashes.get(idx)) {
+                    (Some(filter), Some(Some(expected)))
+                        if FilterHash::hash(&filter.filter) == *expected =>
+                    {
+                        BlockFilter::new(&filter.filter)
+                            .match_any(&hash, scripts.iter().map(|script| script.as_bytes()))
+                            .unwrap_or(true)
+                    }
+                    // we can not trust the filter, so we look inside the block
+                    _ => true,
+                };
+                if !matched {
+                    continue;
+                }
+                log::debug!(target: "cbf", "the filter of the block `{hash}` at height {height} matches");
+                let block = self.with_peer(|peer| peer.get_block(hash))?;
+                self.process_block(handler, block, height)?;
+            }
+            self.watched.lock().unwrap().prune(stop);
+        }
+        handler.emit(Event::OnChain(OnChainEvent::NewBestBlock((
+            stop_header,
+            Height::from_consensus(stop)?,
+        ))));
+        Ok(())
+    }
+
+    fn process_block(
+        &self,
+        handler: &Arc<dyn Handler>,
+        block: Block,
+        height: u32,
+    ) -> error::Result<()> {
+        let height = Height::from_consensus(height)?;
+        let txs = self
+            .watched
+            .lock()
+            .unwrap()
+            .filter_block(&block, height.to_consensus_u32());
+        handler.emit(Event::OnChain(OnChainEvent::NewBestBlock((
+            block.header,
+            height,
+        ))));
+        for (idx, tx) in txs {
+            log::debug!(target: "cbf", "transaction `{}` confirmed at height {height}", tx.txid());
+            handler.emit(Event::OnChain(OnChainEvent::ConfirmedTransaction((
+                tx,
+                idx as u32,
+                block.header,
+                height,
+            ))));
+        }
+        handler.emit(Event::OnChain(OnChainEvent::NewBlock(block)));
+        Ok(())
+    }
+
+    fn set_scan_height(&self, height: u32) -> error::Result<()> {
+        *self.scan_height.lock().unwrap() = Some(height);
+        std::fs::write(&self.scan_height_path, height.to_string())?;
+        Ok(())
+    }
+
+    /// Sync the headers and scan the new blocks.
+    fn sync(&self, handler: &Arc<dyn Handler>) -> error::Result<()> {
+        self.sync_headers()?;
+        let tip = self.headers.lock().unwrap().height();
+        let scan_height = *self.scan_height.lock().unwrap();
+        let Some(mut scan_height) = scan_height else {
+            // a new node does not have anything to look for
+            // inside the old blocks.
+            log::info!(target: "cbf", "starting to scan the blocks from height {tip}");
+            self.set_scan_height(tip)?;
+            let header = *self.headers.lock().unwrap().header(tip).unwrap();
+            handler.emit(Event::OnChain(OnChainEvent::NewBestBlock((
+                header,
+                Height::from_consensus(tip)?,
+            ))));
+            return Ok(());
+        };
+        while scan_height < tip {
+            let start = scan_height + 1;
+            let stop = tip.min(start + MAX_FILTERS - 1);
+            log::trace!(target: "cbf", "scanning the blocks in range [{start}..{stop}]");
+            self.scan_range(handler, start, stop)?;
+            self.set_scan_height(stop)?;
+            scan_height = stop;
+        }
+        Ok(())
+    }
+}
+
+impl Backend for LampoCbf {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Cbf
+    }
+
+    fn network(&self) -> error::Result<Network> {
+        Ok(self.network)
+    }
+
+    fn fee_rate_estimation(&self, _: u64) -> error::Result<u32> {
+        // the regtest does not have fees to look at, as
+        // bitcoin core we return the min fee rate.
+        if self.network == Network::Regtest {
+            return Ok(MIN_FEE_RATE);
+        }
+        error::bail!("the compact block filters backend can not estimate the fees")
+    }
+
+    fn minimum_mempool_fee(&self) -> error::Result<u32> {
+        // the fee filter is in sat/kvB, ldk wants sat per 1000 weight.
+        let fee = self
+            .peers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|peer| peer.fee_filter)
+            .max()
+            .map(|fee| (fee / 4) as u32)
+            .unwrap_or_default();
+        Ok(fee.max(MIN_FEE_RATE))
+    }
+
+    fn brodcast_tx(&self, tx: &Transaction) {
+        let mut peers = self.peers.lock().unwrap();
+        self.connect_peers(&mut peers);
+        peers.retain_mut(|peer| match peer.broadcast(tx) {
+            Ok(()) => true,
+            Err(err) => {
+                log::warn!(target: "cbf", "disconnecting from `{}`: {err}", peer.addr);
+                false
+            }
+        });
+        if peers.is_empty() {
+            log::error!(target: "cbf", "impossible broadcast the transaction `{}`, no peer connected", tx.txid());
+            return;
+        }
+        log::info!(target: "cbf", "transaction `{}` sent to {} peers", tx.txid(), peers.len());
+        if let Ok(handler) = self.handler() {
+            handler.emit(Event::OnChain(OnChainEvent::SendRawTransaction(tx.clone())));
+        }
+    }
+
+    fn is_lightway(&self) -> bool {
+        true
+    }
+
+    fn watch_utxo(&self, txid: &Txid, script: &Script) {
+        log::debug!(target: "cbf", "watching the transaction `{txid}`");
+        self.watched
+            .lock()
+            .unwrap()
+            .txs
+            .insert(*txid, script.to_owned());
+    }
+
+    fn register_output(&self, output: WatchedOutput) -> Option<(usize, Transaction)> {
+        log::debug!(target: "cbf", "watching the output `{}`", output.outpoint);
+        self.watched
+            .lock()
+            .unwrap()
+            .outputs
+            .insert(output.outpoint, output.script_pubkey);
+        None
+    }
+
+    fn get_header<'a>(
+        &'a self,
+        _header_hash: &'a BlockHash,
+        _height_hint: Option<u32>,
+    ) -> AsyncBlockSourceResult<'a, BlockHeaderData> {
+        Box::pin(async {
+            Err(BlockSourceError::persistent(
+                "the compact block filters backend does not serve the headers",
+            ))
+        })
+    }
+
+    fn get_block<'a>(&'a self, header_hash: &'a BlockHash) -> error::Result<BlockData> {
+        let block = self.with_peer(|peer| peer.get_block(*header_hash))?;
+        Ok(BlockData::FullBlock(block))
+    }
+
+    fn get_best_block(&self) -> error::Result<(BlockHash, Option<u32>)> {
+        if let Err(err) = self.sync_headers() {
+            log::warn!(target: "cbf", "impossible sync the block headers: {err}");
+        }
+        let (hash, height) = self.headers.lock().unwrap().tip();
+        Ok((hash, Some(height)))
+    }
+
+    fn get_utxo(&self, _: &BlockHash, _: u64) -> UtxoResult {
+        UtxoResult::Sync(Err(UtxoLookupError::UnknownTx))
+    }
+
+    fn get_utxo_by_txid(&self, txid: &Txid, _: &Script) -> error::Result<TxResult> {
+        self.get_transaction(txid)
+    }
+
+    fn set_handler(&self, handler: Arc<dyn Handler>) {
+        *self.handler.lock().unwrap() = Some(handler);
+    }
+
+    // The transactions are found by the filters of the blocks
+    // with the scripts that ldk registers, we can not look for
+    // a transaction only with its txid.
+    fn manage_transactions(&self, _: &mut Vec<Txid>) -> error::Result<()> {
+        Ok(())
+    }
+
+    fn listen(self: Arc<Self>) -> error::Result<JoinHandle<()>> {
+        let handler = self.handler()?;
+        log::info!(target: "cbf", "Starting the compact block filters sync ...");
+        Ok(std::thread::spawn(move || loop {
+            if let Err(err) = self.sync(&handler) {
+                log::error!(target: "cbf", "impossible sync with the peers: {err}");
+            }
+            std::thread::sleep(POLL_TIME);
+        }))
+    }
+
+    fn get_transaction(&self, txid: &Txid) -> error::Result<TxResult> {
+        error::bail!("the compact block filters backend can not look up the transaction `{txid}`")
+    }
+
+    fn process_transactions(&self) -> error::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lampo_common::backend::{Block, Transaction};
+    use lampo_common::bitcoin::absolute::LockTime;
+    use lampo_common::bitcoin::block::{Header, Version};
+    use lampo_common::bitcoin::hash_types::TxMerkleNode;
+    use lampo_common::bitcoin::hashes::Hash;
+    use lampo_common::bitcoin::{BlockHash, CompactTarget, OutPoint, ScriptBuf, TxIn, Txid};
+
+    use super::{Watched, REORG_DEPTH};
+
+    fn block(txdata: Vec<Transaction>) -> Block {
+        let header = Header {
+            version: Version::ONE,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 0,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        };
+        Block { header, txdata }
+    }
+
+    fn spend(outpoint: OutPoint) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                ..Default::default()
+            }],
+            output: vec![],
+        }
+    }
+
+    #[test]
+    fn prune_the_deep_transactions() {
+        let outpoint = OutPoint::new(Txid::all_zeros(), 0);
+        let tx = spend(outpoint);
+        let mut watched = Watched::default();
+        watched.txs.insert(tx.txid(), ScriptBuf::new());
+        watched.outputs.insert(outpoint, ScriptBuf::new());
+
+        let found = watched.filter_block(&block(vec![tx.clone()]), 100);
+        assert_eq!(found.len(), 1);
+
+        // a reorg can still move the transaction in another block
+        watched.prune(100 + REORG_DEPTH - 1);
+        assert!(watched.txs.contains_key(&tx.txid()));
+        assert!(watched.outputs.contains_key(&outpoint));
+
+        watched.prune(100 + REORG_DEPTH);
+        assert!(watched.txs.is_empty());
+        assert!(watched.outputs.is_empty());
+        assert!(watched.scripts().is_empty());
+    }
+
+    #[test]
+    fn keep_the_unconfirmed_transactions() {
+        let mut watched = Watched::default();
+        watched.txs.insert(Txid::all_zeros(), ScriptBuf::new());
+        let found = watched.filter_block(&block(vec![spend(OutPoint::null())]), 100);
+        assert!(found.is_empty());
+        watched.prune(100 + REORG_DEPTH);
+        assert_eq!(watched.txs.len(), 1);
+    }
+}
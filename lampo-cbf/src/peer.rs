@@ -0,0 +1,215 @@
+//! Connection with a peer of the bitcoin network that
+//! serves the compact block filters (BIP 157).
+//!
+//! The connection is blocking, every request waits the
+//! answer of the peer until the read timeout.
+use std::io::{BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, SystemTime};
+
+use lampo_common::backend::{serialize, Block, BlockHash};
+use lampo_common::bitcoin::bip158::{FilterHash, FilterHeader};
+use lampo_common::bitcoin::block::Header;
+use lampo_common::bitcoin::consensus::Decodable;
+use lampo_common::bitcoin::hashes::Hash;
+use lampo_common::bitcoin::network::address::Address;
+use lampo_common::bitcoin::network::constants::{Magic, ServiceFlags};
+use lampo_common::bitcoin::network::message::{NetworkMessage, RawNetworkMessage};
+use lampo_common::bitcoin::network::message_blockdata::{GetHeadersMessage, Inventory};
+use lampo_common::bitcoin::network::message_filter::{
+    CFHeaders, CFilter, GetCFHeaders, GetCFilters,
+};
+use lampo_common::bitcoin::network::message_network::VersionMessage;
+use lampo_common::bitcoin::Transaction;
+use lampo_common::error;
+
+/// The basic filter type, the only one defined by BIP 158.
+pub const BASIC_FILTER: u8 = 0;
+/// The version 70016 is the first with the `wtxidrelay`, we
+/// do not relay anything so 70015 is enough for us.
+const PROTOCOL_VERSION: u32 = 70015;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Peer {
+    pub addr: SocketAddr,
+    /// Height of the peer when we connected with it.
+    pub start_height: i32,
+    /// Min fee rate (sat/kvB) of the transactions relayed by the peer.
+    pub fee_filter: Option<u64>,
+    magic: Magic,
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Peer {
+    /// Connect with `addr` and complete the handshake, the peers
+    /// that do not serve the compact block filters are refused.
+    pub fn connect(addr: SocketAddr, magic: Magic) -> error::Result<Self> {
+        let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut peer = Self {
+            addr,
+            start_height: 0,
+            fee_filter: None,
+            magic,
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs() as i64;
+        let mut version = VersionMessage::new(
+            ServiceFlags::NONE,
+            timestamp,
+            Address::new(&addr, ServiceFlags::NONE),
+            Address::new(&addr, ServiceFlags::NONE),
+            timestamp as u64,
+            format!("/lampo:{}/", env!("CARGO_PKG_VERSION")),
+            0,
+        );
+        version.version = PROTOCOL_VERSION;
+        // we do not want the transactions of the mempool
+        version.relay = false;
+        peer.send(NetworkMessage::Version(version))?;
+
+        let mut services = None;
+        let mut verack = false;
+        while services.is_none() || !verack {
+            match peer.recv()? {
+                NetworkMessage::Version(version) => {
+                    peer.start_height = version.start_height;
+                    services = Some(version.services);
+                    peer.send(NetworkMessage::Verack)?;
+                }
+                NetworkMessage::Verack => verack = true,
+                _ => {}
+            }
+        }
+        // SAFETY: we exit from the loop only when we know the services.
+        if !services.unwrap().has(ServiceFlags::COMPACT_FILTERS) {
+            error::bail!("the peer `{addr}` does not serve the compact block filters");
+        }
+        log::info!(target: "cbf", "connected with `{addr}` at height {}", peer.start_height);
+        Ok(peer)
+    }
+
+    pub fn send(&mut self, payload: NetworkMessage) -> error::Result<()> {
+        let message = RawNetworkMessage {
+            magic: self.magic,
+            payload,
+        };
+        self.writer.write_all(&serialize(&message))?;
+        Ok(())
+    }
+
+    /// Receive the next message, the pings are answered here.
+    fn recv(&mut self) -> error::Result<NetworkMessage> {
+        loop {
+            // a block can be bigger than the limit of `consensus_decode`.
+            let message = RawNetworkMessage::consensus_decode_from_finite_reader(&mut self.reader)?;
+            if message.magic != self.magic {
+                error::bail!("the peer `{}` is on another network", self.addr);
+            }
+            match message.payload {
+                NetworkMessage::Ping(nonce) => self.send(NetworkMessage::Pong(nonce))?,
+                NetworkMessage::FeeFilter(fee) => self.fee_filter = Some(fee.max(0) as u64),
+                payload => return Ok(payload),
+            }
+        }
+    }
+
+    /// Send `request` and wait for the message accepted by
+    /// `answer`, the other messages are ignored.
+    fn request<T>(
+        &mut self,
+        request: NetworkMessage,
+        mut answer: impl FnMut(NetworkMessage) -> Option<T>,
+    ) -> error::Result<T> {
+        self.send(request)?;
+        loop {
+            if let Some(result) = answer(self.recv()?) {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// The headers after the first hash of `locator` that the
+    /// peer has in its chain, at most 2000.
+    pub fn get_headers(&mut self, locator: Vec<BlockHash>) -> error::Result<Vec<Header>> {
+        let request = GetHeadersMessage::new(locator, BlockHash::all_zeros());
+        self.request(
+            NetworkMessage::GetHeaders(request),
+            |message| match message {
+                NetworkMessage::Headers(headers) => Some(headers),
+                _ => None,
+            },
+        )
+    }
+
+    /// The filter headers of the blocks from `start_height`
+    /// to `stop_hash`, at most 2000.
+    pub fn get_filter_headers(
+        &mut self,
+        start_height: u32,
+        stop_hash: BlockHash,
+    ) -> error::Result<(FilterHeader, Vec<FilterHash>)> {
+        let request = GetCFHeaders {
+            filter_type: BASIC_FILTER,
+            start_height,
+            stop_hash,
+        };
+        let headers: CFHeaders = self.request(
+            NetworkMessage::GetCFHeaders(request),
+            |message| match message {
+                NetworkMessage::CFHeaders(headers) if headers.stop_hash == stop_hash => {
+                    Some(headers)
+                }
+                _ => None,
+            },
+        )?;
+        Ok((headers.previous_filter_header, headers.filter_hashes))
+    }
+
+    /// The filters of the blocks from `start_height` to
+    /// `stop_hash`, at most 1000.
+    pub fn get_filters(
+        &mut self,
+        start_height: u32,
+        stop_hash: BlockHash,
+        count: usize,
+    ) -> error::Result<Vec<CFilter>> {
+        let request = GetCFilters {
+            filter_type: BASIC_FILTER,
+            start_height,
+            stop_hash,
+        };
+        let mut filters = Vec::with_capacity(count);
+        self.request(NetworkMessage::GetCFilters(request), |message| {
+            if let NetworkMessage::CFilter(filter) = message {
+                filters.push(filter);
+            }
+            (filters.len() == count).then_some(())
+        })?;
+        Ok(filters)
+    }
+
+    pub fn get_block(&mut self, hash: BlockHash) -> error::Result<Block> {
+        let request = vec![Inventory::WitnessBlock(hash)];
+        let block = self.request(NetworkMessage::GetData(request), |message| match message {
+            NetworkMessage::Block(block) if block.block_hash() == hash => Some(Ok(block)),
+            NetworkMessage::NotFound(_) => Some(Err(error::anyhow!(
+                "the peer does not have the block `{hash}`"
+            ))),
+            _ => None,
+        })??;
+        if !block.check_merkle_root() || !block.check_witness_commitment() {
+            error::bail!("the peer `{}` sent an invalid block `{hash}`", self.addr);
+        }
+        Ok(block)
+    }
+
+    pub fn broadcast(&mut self, tx: &Transaction) -> error::Result<()> {
+        self.send(NetworkMessage::Tx(tx.clone()))
+    }
+}
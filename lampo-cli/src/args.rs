@@ -5,11 +5,16 @@ use radicle_term as term;
 use lampo_common::error;
 use lampo_common::json;
 
+use crate::output::{Output, OutputMode};
+
 #[derive(Debug)]
 pub struct LampoCliArgs {
     pub socket: String,
-    pub method: String,
+    /// The method is `None` only in interactive mode.
+    pub method: Option<String>,
     pub args: HashMap<String, json::Value>,
+    pub output: Output,
+    pub interactive: bool,
 }
 
 struct Help {
@@ -27,12 +32,18 @@ const HELP: Help = Help {
 Usage
 
     lampod-cli [<option> ...] <method> [arg=value]
+    lampod-cli [<option> ...] --interactive
 
 Options
 
     -d | --data-dir     Specify lampo data directory (used to get socket path)
     -n | --network      Set the network for lampo (default: testnet)
     -s | --socket       Specify Unix Socket patch of the lampod node directely
+    -i | --interactive  Run an interactive shell connected to the daemon
+    --qr                Print the QR code of the invoice or address (invoice, newaddr)
+    --output <mode>     Print the response as `json` (default) or as a table with `human`
+    --json              Same as `--output json`
+    --field <name>      Show only the field `name`, can be repeated
     -h | --help         Print help
 "#,
 };
@@ -44,6 +55,8 @@ pub fn parse_args() -> Result<LampoCliArgs, lexopt::Error> {
     let mut network: Option<String> = None;
     let mut socket: Option<String> = None;
     let mut method: Option<String> = None;
+    let mut output = Output::default();
+    let mut interactive = false;
     let mut args = HashMap::<String, json::Value>::new();
 
     let mut parser = lexopt::Parser::from_env();
@@ -61,6 +74,24 @@ pub fn parse_args() -> Result<LampoCliArgs, lexopt::Error> {
                 let val: String = parser.value()?.parse()?;
                 socket = Some(val);
             }
+            Short('i') | Long("interactive") => {
+                interactive = true;
+            }
+            Long("json") => {
+                output.mode = OutputMode::Json;
+            }
+            Long("output") => {
+                let val: OutputMode = parser.value()?.parse()?;
+                output.mode = val;
+            }
+            Long("field") => {
+                let val: String = parser.value()?.parse()?;
+                output.fields.push(val);
+            }
+            Long("qr") => {
+                output.qr = true;
+                args.insert("qr".to_owned(), json::json!(true));
+            }
             Long("help") => {
                 let _ = print_help();
                 std::process::exit(0);
@@ -76,16 +107,7 @@ pub fn parse_args() -> Result<LampoCliArgs, lexopt::Error> {
                     Long(val) => {
                         let key = val.to_string();
                         let val: String = parser.value()?.parse()?;
-                        if let Ok(val) = val.parse::<u64>() {
-                            let val = json::json!(val);
-                            args.insert(key.clone(), val);
-                        } else if let Ok(val) = val.parse::<bool>() {
-                            let val = json::json!(val);
-                            args.insert(key.clone(), val);
-                        } else {
-                            let val = json::json!(val);
-                            args.insert(key, val);
-                        }
+                        args.insert(key, parse_value(&val));
                     }
                     _ => return Err(arg.unexpected()),
                 }
@@ -121,20 +143,37 @@ pub fn parse_args() -> Result<LampoCliArgs, lexopt::Error> {
     }
 
     log::debug!("args parser are {:?} {:?}", method, args);
-    Ok(LampoCliArgs {
-        socket: socket.ok_or_else(|| lexopt::Error::MissingValue {
-            option: Some("Socket path need to be specified".to_owned()),
-        })?,
-        method: method.ok_or_else(|| lexopt::Error::MissingValue {
+    if method.is_none() && !interactive {
+        return Err(lexopt::Error::MissingValue {
             option: Some(
                 "Too few params, a method need to be specified. Try run `lampo-cli --help`"
                     .to_owned(),
             ),
+        });
+    }
+    Ok(LampoCliArgs {
+        socket: socket.ok_or_else(|| lexopt::Error::MissingValue {
+            option: Some("Socket path need to be specified".to_owned()),
         })?,
+        method,
         args,
+        output,
+        interactive,
     })
 }
 
+/// Parse the value of a method param, numbers and booleans
+/// are sent as JSON numbers and booleans.
+pub fn parse_value(val: &str) -> json::Value {
+    if let Ok(val) = val.parse::<u64>() {
+        json::json!(val)
+    } else if let Ok(val) = val.parse::<bool>() {
+        json::json!(val)
+    } else {
+        json::json!(val)
+    }
+}
+
 // Print helps
 pub fn print_help() -> error::Result<()> {
     println!(
@@ -1,10 +1,11 @@
 mod args;
+mod output;
+mod repl;
 
 use std::process::exit;
 
 use radicle_term as term;
 
-use lampo_client::errors::Error;
 use lampo_client::UnixClient;
 use lampo_common::error;
 use lampo_common::json;
@@ -19,23 +20,17 @@ fn main() -> error::Result<()> {
             exit(1);
         }
     };
-    let resp = run(args);
-    match resp {
-        Ok(resp) => {
-            term::print(json::to_string_pretty(&resp)?);
-        }
-        Err(Error::Rpc(rpc)) => {
-            term::print(json::to_string_pretty(&rpc)?);
-        }
-        Err(err) => {
-            term::error(format!("{err}"));
-        }
+    if args.interactive {
+        return repl::run(&args.socket, args.output);
     }
-    Ok(())
+    let output = args.output.clone();
+    let resp = run(args);
+    output.print(resp)
 }
 
 fn run(args: LampoCliArgs) -> Result<json::Value, lampo_client::errors::Error> {
     let client = UnixClient::new(&args.socket).unwrap();
-    let resp = client.call(&args.method, args.args)?;
+    // SAFETY: the method is always present outside the interactive mode.
+    let resp = client.call(&args.method.unwrap(), args.args)?;
     Ok(resp)
 }
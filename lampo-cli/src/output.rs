@@ -0,0 +1,194 @@
+//! Output formatting of the daemon responses.
+//!
+//! The JSON output is meant for the scripts, while the human
+//! output renders the list commands as a table.
+use std::str::FromStr;
+
+use radicle_term as term;
+
+use lampo_client::errors::Error;
+use lampo_common::error;
+use lampo_common::json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    #[default]
+    Json,
+    Human,
+}
+
+impl FromStr for OutputMode {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "human" => Ok(Self::Human),
+            _ => error::bail!("`{s}` is not an output, use `json` or `human`"),
+        }
+    }
+}
+
+/// The JSON output is the default, so the scripts do not
+/// depend on the terminal where they run.
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+    pub mode: OutputMode,
+    /// Show only these fields, empty means all the fields.
+    pub fields: Vec<String>,
+    /// Print the QR code returned by the daemon.
+    pub qr: bool,
+}
+
+impl Output {
+    pub fn print(&self, resp: Result<json::Value, Error>) -> error::Result<()> {
+        let mut resp = match resp {
+            Ok(resp) => resp,
+            Err(Error::Rpc(rpc)) => {
+                term::print(json::to_string_pretty(&rpc)?);
+                return Ok(());
+            }
+            Err(err) => {
+                term::error(format!("{err}"));
+                return Ok(());
+            }
+        };
+        // the png is not useful inside the terminal
+        let ascii = resp
+            .as_object_mut()
+            .and_then(|resp| resp.remove("qr"))
+            .and_then(|qr| {
+                qr.get("ascii")
+                    .and_then(|ascii| ascii.as_str().map(str::to_owned))
+            });
+        let resp = self.select(resp);
+        match self.mode {
+            OutputMode::Json => term::print(json::to_string_pretty(&resp)?),
+            OutputMode::Human => term::print(Self::human(&resp)),
+        }
+        if let (true, Some(ascii)) = (self.qr, ascii) {
+            term::print(ascii);
+        }
+        Ok(())
+    }
+
+    /// Keep only the selected fields, inside each row of the list
+    /// or inside the object.
+    fn select(&self, resp: json::Value) -> json::Value {
+        if self.fields.is_empty() {
+            return resp;
+        }
+        let select = |value: &json::Value| -> json::Value {
+            match value.as_object() {
+                Some(object) => json::Value::Object(
+                    object
+                        .iter()
+                        .filter(|(key, _)| self.fields.contains(key))
+                        .map(|(key, value)| (key.clone(), value.clone()))
+                        .collect(),
+                ),
+                None => value.clone(),
+            }
+        };
+        match Self::rows(&resp) {
+            Some((name, rows)) => {
+                let rows = json::Value::Array(rows.iter().map(select).collect());
+                match name {
+                    Some(name) => {
+                        let mut object = json::Map::new();
+                        object.insert(name, rows);
+                        json::Value::Object(object)
+                    }
+                    None => rows,
+                }
+            }
+            None => select(&resp),
+        }
+    }
+
+    /// Return the list inside the response, the list commands
+    /// return an array or an object with a single array field,
+    /// e.g. `{ "channels": [...] }`.
+    fn rows(resp: &json::Value) -> Option<(Option<String>, Vec<json::Value>)> {
+        match resp {
+            json::Value::Array(rows) => Some((None, rows.clone())),
+            json::Value::Object(object) if object.len() == 1 => {
+                let (name, value) = object.iter().next()?;
+                let rows = value.as_array()?;
+                Some((Some(name.clone()), rows.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn cell(value: &json::Value) -> String {
+        match value {
+            json::Value::Null => "-".to_owned(),
+            json::Value::String(value) => value.clone(),
+            value => value.to_string(),
+        }
+    }
+
+    fn human(resp: &json::Value) -> String {
+        let Some((_, rows)) = Self::rows(resp) else {
+            return match resp.as_object() {
+                Some(object) => {
+                    let width = object.keys().map(|key| key.len()).max().unwrap_or(0);
+                    object
+                        .iter()
+                        .map(|(key, value)| format!("{key:width$}  {}", Self::cell(value)))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+                None => Self::cell(resp),
+            };
+        };
+        if rows.is_empty() {
+            return "no entries".to_owned();
+        }
+        let mut columns: Vec<String> = Vec::new();
+        for row in rows.iter().filter_map(|row| row.as_object()) {
+            for key in row.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+        }
+        let table = rows
+            .iter()
+            .map(|row| {
+                columns
+                    .iter()
+                    .map(|column| Self::cell(row.get(column).unwrap_or(&json::Value::Null)))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let widths = columns
+            .iter()
+            .enumerate()
+            .map(|(idx, column)| {
+                table
+                    .iter()
+                    .map(|row| row[idx].len())
+                    .chain(std::iter::once(column.len()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        let line = |cells: Vec<&str>| {
+            cells
+                .iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+        };
+        let mut out = vec![line(columns.iter().map(String::as_str).collect())];
+        out.extend(
+            table
+                .iter()
+                .map(|row| line(row.iter().map(String::as_str).collect())),
+        );
+        out.join("\n")
+    }
+}
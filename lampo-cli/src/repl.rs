@@ -0,0 +1,205 @@
+//! Interactive mode of `lampo-cli`.
+//!
+//! The shell keeps the same client for the whole session, and
+//! uses the command registry of the daemon to complete the
+//! methods and their params.
+use std::collections::HashMap;
+use std::path::Path;
+
+use radicle_term as term;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Context, Editor, Helper, Highlighter, Hinter, Validator};
+
+use lampo_client::UnixClient;
+use lampo_common::commands::{self, CommandInfo};
+use lampo_common::error;
+use lampo_common::json;
+
+use crate::args::parse_value;
+use crate::output::Output;
+
+const HISTORY_FILE: &str = "lampo-cli.history";
+
+#[derive(Helper, Hinter, Highlighter, Validator)]
+struct LampoHelper {
+    commands: Vec<CommandInfo>,
+}
+
+impl Completer for LampoHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(' ').map(|idx| idx + 1).unwrap_or(0);
+        let word = &line[start..];
+        let mut tokens = line[..start].split_whitespace();
+        let Some(method) = tokens.next() else {
+            let candidates = self
+                .commands
+                .iter()
+                .filter(|command| command.name.starts_with(word))
+                .map(|command| command.name.clone())
+                .collect();
+            return Ok((start, candidates));
+        };
+        let used = tokens.collect::<Vec<_>>();
+        // the params are in the form `--key value`, so with an odd
+        // number of tokens we are completing a value.
+        if used.len() % 2 != 0 {
+            return Ok((start, vec![]));
+        }
+        let Some(command) = self.commands.iter().find(|command| command.name == method) else {
+            return Ok((start, vec![]));
+        };
+        let candidates = command
+            .params
+            .iter()
+            .map(|param| format!("--{param}"))
+            .filter(|param| param.starts_with(word) && !used.contains(&param.as_str()))
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+/// Split the line in tokens, the double quotes can be used
+/// for values that contains spaces, e.g. `--description "a coffee"`.
+fn split_line(line: &str) -> error::Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if quoted {
+        error::bail!("unterminated quote");
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+fn parse_tokens(tokens: &[String]) -> error::Result<(String, HashMap<String, json::Value>)> {
+    let Some((method, params)) = tokens.split_first() else {
+        error::bail!("empty command");
+    };
+    let mut args = HashMap::new();
+    for pair in params.chunks(2) {
+        let Some(key) = pair[0].strip_prefix("--") else {
+            error::bail!("expected a param in the form `--key`, got `{}`", pair[0]);
+        };
+        let Some(value) = pair.get(1) else {
+            error::bail!("missing value for the param `{key}`");
+        };
+        args.insert(key.to_owned(), parse_value(value));
+    }
+    Ok((method.clone(), args))
+}
+
+fn print_help(commands: &[CommandInfo], method: Option<&str>) {
+    for command in commands
+        .iter()
+        .filter(|command| method.map_or(true, |method| command.name == method))
+    {
+        let params = command
+            .params
+            .iter()
+            .map(|param| format!("[--{param} <value>]"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        term::print(format!(
+            "{} {}\n\t{}",
+            term::format::bold(&command.name),
+            term::format::dim(params),
+            command.description
+        ));
+    }
+}
+
+pub fn run(socket: &str, output: Output) -> error::Result<()> {
+    let client = UnixClient::new(socket)?;
+    // old daemons do not expose the registry, so fallback to the one
+    // that we know.
+    let commands = client
+        .call::<_, json::Value>("commands", json::json!({}))
+        .ok()
+        .and_then(|resp| json::from_value::<Vec<CommandInfo>>(resp["commands"].clone()).ok())
+        .unwrap_or_else(commands::commands);
+
+    let history = Path::new(socket)
+        .parent()
+        .map(|dir| dir.join(HISTORY_FILE))
+        .unwrap_or(HISTORY_FILE.into());
+    let mut editor: Editor<LampoHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(LampoHelper {
+        commands: commands.clone(),
+    }));
+    let _ = editor.load_history(&history);
+
+    term::print(term::format::secondary(
+        "lampo interactive shell, type `help` to list the commands and `exit` to quit",
+    ));
+    loop {
+        let line = match editor.readline("lampo> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+        let tokens = match split_line(line) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                term::error(format!("{err}"));
+                continue;
+            }
+        };
+        match tokens.first().map(String::as_str) {
+            Some("exit") | Some("quit") => break,
+            Some("help") => {
+                print_help(&commands, tokens.get(1).map(String::as_str));
+                continue;
+            }
+            _ => {}
+        }
+        let (method, args) = match parse_tokens(&tokens) {
+            Ok(cmd) => cmd,
+            Err(err) => {
+                term::error(format!("{err}"));
+                continue;
+            }
+        };
+        let output = Output {
+            qr: args.get("qr").and_then(|qr| qr.as_bool()).unwrap_or(false),
+            ..output.clone()
+        };
+        let resp = client.call::<_, json::Value>(&method, args);
+        output.print(resp)?;
+    }
+    if let Err(err) = editor.save_history(&history) {
+        log::warn!(
+            "impossible save the history inside `{}`: {err}",
+            history.display()
+        );
+    }
+    Ok(())
+}
@@ -6,13 +6,14 @@ use std::thread::JoinHandle;
 
 use bitcoin::absolute::Height;
 use bitcoin::block::Header as BlockHeader;
+use bitcoin::Network;
 
 pub use bitcoin::consensus::{deserialize, serialize};
 pub use bitcoin::{Block, BlockHash, Script, Transaction, Txid};
 pub use lightning::chain::WatchedOutput;
 pub use lightning::routing::utxo::UtxoResult;
 pub use lightning_block_sync::{
-    AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSourceResult,
+    AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSourceError, BlockSourceResult,
 };
 use serde::{Deserialize, Serialize};
 
@@ -30,13 +31,26 @@ pub enum TxResult {
 pub enum BackendKind {
     Core,
     Nakamoto,
+    /// Light client with the compact block filters (BIP 157/158).
+    Cbf,
+    /// Deterministic chain used for testing.
+    Mock,
 }
 
 /// Bakend Trait specification
-pub trait Backend {
+pub trait Backend: Send + Sync {
     /// Return the kind of backend
     fn kind(&self) -> BackendKind;
 
+    /// Return the network where the backend is running
+    fn network(&self) -> error::Result<Network>;
+
+    /// Return the hex encoded challenge of the signet where the
+    /// backend is running, `None` when it is unknown.
+    fn signet_challenge(&self) -> error::Result<Option<String>> {
+        Ok(None)
+    }
+
     /// Fetch feerate give a number of blocks
     fn fee_rate_estimation(&self, blocks: u64) -> error::Result<u32>;
 
@@ -0,0 +1,284 @@
+//! Coin selection of the wallet utxos.
+//!
+//! Here we choose only the utxos that pay the outputs and the fee,
+//! the wallet builds the transaction and adds the change. The fee of
+//! the inputs is estimated with the weight of a P2WPKH input, that is
+//! the input type of the wallets.
+//!
+//! The fee rates are in sat per 1000 weight, like inside ldk.
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::bitcoin::{OutPoint, TxOut};
+use crate::error;
+
+/// Weight of a P2WPKH input with its witness.
+const INPUT_WEIGHT: u64 = 272;
+/// Weight of the version, the locktime, the counters
+/// and the segwit marker.
+const BASE_WEIGHT: u64 = 42;
+/// Weight of a P2WPKH change output.
+const CHANGE_WEIGHT: u64 = 124;
+/// Outputs below this value are not relayed by the nodes.
+pub const DUST_LIMIT: u64 = 546;
+/// Max number of branches explored by the branch and bound.
+const BNB_MAX_TRIES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoinSelection {
+    /// The largest utxos first, the transaction has the fewest inputs.
+    LargestFirst,
+    /// Look for the utxos that pay the exact amount without a change
+    /// (branch and bound), or fallback to the largest first.
+    #[default]
+    BranchAndBound,
+    /// The largest utxos first, but the utxos that are dust or that
+    /// cost more than their value to be spent are never used.
+    AvoidDust,
+}
+
+impl FromStr for CoinSelection {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "largest-first" => Ok(Self::LargestFirst),
+            "bnb" => Ok(Self::BranchAndBound),
+            "avoid-dust" => Ok(Self::AvoidDust),
+            _ => error::bail!(
+                "`{s}` is not a coin selection, use `largest-first`, `bnb` or `avoid-dust`"
+            ),
+        }
+    }
+}
+
+impl Display for CoinSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LargestFirst => write!(f, "largest-first"),
+            Self::BranchAndBound => write!(f, "bnb"),
+            Self::AvoidDust => write!(f, "avoid-dust"),
+        }
+    }
+}
+
+fn fee(weight: u64, fee_rate: u32) -> u64 {
+    (weight * fee_rate as u64 + 999) / 1000
+}
+
+/// Weight of an output that pays `script_len` bytes of script.
+pub fn output_weight(script_len: usize) -> u64 {
+    // amount, script length and script
+    (8 + 1 + script_len as u64) * 4
+}
+
+/// Fee of a transaction that spends `inputs` wallet utxos and pays
+/// outputs of `outputs_weight`, without a change.
+pub fn transaction_fee(inputs: usize, outputs_weight: u64, fee_rate: u32) -> u64 {
+    fee(
+        BASE_WEIGHT + inputs as u64 * INPUT_WEIGHT + outputs_weight,
+        fee_rate,
+    )
+}
+
+/// Choose the utxos that pay `amount_sat` and the fee of the transaction
+/// at `fee_rate`, `outputs_weight` is the weight of the outputs that the
+/// transaction pays.
+pub fn select_coins(
+    strategy: CoinSelection,
+    utxos: &[(OutPoint, TxOut)],
+    amount_sat: u64,
+    outputs_weight: u64,
+    fee_rate: u32,
+) -> error::Result<Vec<OutPoint>> {
+    let input_fee = fee(INPUT_WEIGHT, fee_rate);
+    // the value of the utxos after the fee to spend them
+    let mut candidates = utxos
+        .iter()
+        .filter(|(_, output)| match strategy {
+            CoinSelection::AvoidDust => output.value >= DUST_LIMIT && output.value >= 2 * input_fee,
+            _ => output.value > input_fee,
+        })
+        .map(|(outpoint, output)| (*outpoint, output.value - input_fee))
+        .collect::<Vec<_>>();
+    candidates.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+    let target = amount_sat + fee(BASE_WEIGHT + outputs_weight, fee_rate);
+    let available = candidates.iter().map(|(_, value)| value).sum::<u64>();
+    if available < target {
+        error::bail!(
+            "not enough funds: the utxos pay {available} sat after the fee, {target} sat are needed"
+        );
+    }
+
+    if strategy == CoinSelection::BranchAndBound {
+        // the change is not worth it when it costs more than its value
+        let cost_of_change = fee(CHANGE_WEIGHT, fee_rate) + input_fee + DUST_LIMIT;
+        let values = candidates
+            .iter()
+            .map(|(_, value)| *value)
+            .collect::<Vec<_>>();
+        let mut selected = Vec::new();
+        let mut tries = 0;
+        if branch_and_bound(
+            &values,
+            0,
+            0,
+            available,
+            (target, target + cost_of_change),
+            &mut selected,
+            &mut tries,
+        ) {
+            return Ok(selected
+                .into_iter()
+                .map(|index| candidates[index].0)
+                .collect());
+        }
+        log::debug!(target: "coin_selection", "no selection without a change after {tries} tries, using the largest first");
+    }
+
+    let mut selected = Vec::new();
+    let mut value = 0;
+    for (outpoint, utxo_value) in candidates {
+        if value >= target {
+            break;
+        }
+        selected.push(outpoint);
+        value += utxo_value;
+    }
+    Ok(selected)
+}
+
+/// Depth first search of the utxos (sorted from the largest) with
+/// a value inside `range`, the selected utxos are inside `selected`.
+fn branch_and_bound(
+    values: &[u64],
+    index: usize,
+    current: u64,
+    remaining: u64,
+    range: (u64, u64),
+    selected: &mut Vec<usize>,
+    tries: &mut usize,
+) -> bool {
+    *tries += 1;
+    if *tries > BNB_MAX_TRIES || current > range.1 {
+        return false;
+    }
+    if current >= range.0 {
+        return true;
+    }
+    if index == values.len() || current + remaining < range.0 {
+        return false;
+    }
+    let remaining = remaining - values[index];
+    selected.push(index);
+    if branch_and_bound(
+        values,
+        index + 1,
+        current + values[index],
+        remaining,
+        range,
+        selected,
+        tries,
+    ) {
+        return true;
+    }
+    selected.pop();
+    branch_and_bound(
+        values,
+        index + 1,
+        current,
+        remaining,
+        range,
+        selected,
+        tries,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::bitcoin::hashes::Hash;
+    use crate::bitcoin::{OutPoint, ScriptBuf, TxOut, Txid};
+
+    use super::{select_coins, CoinSelection};
+
+    /// 1 sat/vbyte, spending an input costs 272 sat.
+    const FEE_RATE: u32 = 1000;
+
+    fn utxos(values: &[u64]) -> Vec<(OutPoint, TxOut)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(vout, value)| {
+                let outpoint = OutPoint::new(Txid::all_zeros(), vout as u32);
+                let output = TxOut {
+                    value: *value,
+                    script_pubkey: ScriptBuf::new(),
+                };
+                (outpoint, output)
+            })
+            .collect()
+    }
+
+    fn vouts(selected: Vec<OutPoint>) -> Vec<u32> {
+        selected.into_iter().map(|outpoint| outpoint.vout).collect()
+    }
+
+    #[test]
+    fn bnb_without_change() {
+        // the base weight of the transaction costs 42 sat
+        let utxos = utxos(&[10_272, 50_272, 30_272]);
+        let selected =
+            select_coins(CoinSelection::BranchAndBound, &utxos, 39_958, 0, FEE_RATE).unwrap();
+        assert_eq!(vouts(selected), vec![2, 0]);
+    }
+
+    #[test]
+    fn bnb_fallback_to_largest_first() {
+        let utxos = utxos(&[50_272, 100_272]);
+        let selected =
+            select_coins(CoinSelection::BranchAndBound, &utxos, 39_958, 0, FEE_RATE).unwrap();
+        assert_eq!(vouts(selected), vec![1]);
+    }
+
+    #[test]
+    fn largest_first() {
+        let utxos = utxos(&[10_272, 50_272, 30_272]);
+        let selected =
+            select_coins(CoinSelection::LargestFirst, &utxos, 55_000, 0, FEE_RATE).unwrap();
+        assert_eq!(vouts(selected), vec![1, 2]);
+    }
+
+    #[test]
+    fn avoid_dust() {
+        let utxos = utxos(&[500]);
+        assert!(select_coins(CoinSelection::LargestFirst, &utxos, 100, 0, FEE_RATE).is_ok());
+        assert!(select_coins(CoinSelection::AvoidDust, &utxos, 100, 0, FEE_RATE).is_err());
+    }
+
+    #[test]
+    fn not_enough_funds() {
+        // the utxo is worth less than the fee to spend it
+        let utxos = utxos(&[10_000, 200]);
+        let err =
+            select_coins(CoinSelection::BranchAndBound, &utxos, 9_700, 0, FEE_RATE).unwrap_err();
+        assert!(err.to_string().contains("not enough funds"), "{err}");
+    }
+
+    #[test]
+    fn strategy_from_str() {
+        for strategy in [
+            CoinSelection::LargestFirst,
+            CoinSelection::BranchAndBound,
+            CoinSelection::AvoidDust,
+        ] {
+            assert_eq!(
+                CoinSelection::from_str(&strategy.to_string()).unwrap(),
+                strategy
+            );
+        }
+        assert!(CoinSelection::from_str("random").is_err());
+    }
+}
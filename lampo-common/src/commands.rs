@@ -0,0 +1,383 @@
+//! Registry of the JSON RPC commands exposed by lampod.
+//!
+//! The registry is used by the clients to show the help and
+//! to complete the methods and the params, so when a new
+//! RPC method is added it should be added here too.
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommandInfo {
+    pub name: String,
+    pub description: String,
+    pub params: Vec<String>,
+}
+
+/// (name, description, params)
+const COMMANDS: &[(&str, &str, &[&str])] = &[
+    ("getinfo", "Show the information of the node", &[]),
+    (
+        "connect",
+        "Connect to a peer by node id, alias or node id prefix",
+        &["node_id", "addr", "port"],
+    ),
+    (
+        "sendonionmessage",
+        "Send a custom onion message to a node",
+        &["node_id", "tlv_type", "data"],
+    ),
+    (
+        "fundchannel",
+        "Open a channel with a peer",
+        &[
+            "node_id",
+            "amount",
+            "public",
+            "addr",
+            "port",
+            "external_funding",
+            "push_msat",
+            "feerate",
+            "utxos",
+        ],
+    ),
+    (
+        "openchannels",
+        "Open several channels funded by a single transaction",
+        &["channels"],
+    ),
+    (
+        "completefunding",
+        "Complete a channel with the funding transaction of an external wallet",
+        &["temporary_channel_id", "tx"],
+    ),
+    (
+        "newaddr",
+        "Generate a new on chain address",
+        &["qr", "label", "address_type"],
+    ),
+    ("channels", "List the channels of the node", &[]),
+    (
+        "funds",
+        "List the on chain funds and our side of the channels",
+        &[],
+    ),
+    (
+        "listtransactions",
+        "List the on chain transactions of the node",
+        &[],
+    ),
+    (
+        "setlabel",
+        "Attach a label to an address or a transaction",
+        &["id", "label"],
+    ),
+    (
+        "consolidate",
+        "Sweep the small wallet utxos into a single output",
+        &["max_amount_sat", "fee_rate", "preview"],
+    ),
+    (
+        "withdraw",
+        "Send the on chain funds to an address",
+        &[
+            "address",
+            "amount_sat",
+            "all",
+            "feerate",
+            "rbf",
+            "simulate",
+            "label",
+        ],
+    ),
+    (
+        "listsweeps",
+        "List the outputs of the closed channels still to sweep",
+        &[],
+    ),
+    (
+        "invoice",
+        "Create a bolt11 invoice",
+        &[
+            "amount_msat",
+            "fiat_amount",
+            "description",
+            "expiring_in",
+            "qr",
+            "fallback",
+        ],
+    ),
+    (
+        "batchinvoice",
+        "Create many labeled bolt11 invoices at once",
+        &["invoices"],
+    ),
+    (
+        "phantomhints",
+        "Show the route hints to share with the phantom cluster",
+        &[],
+    ),
+    (
+        "phantominvoice",
+        "Create an invoice claimable by any node of the phantom cluster",
+        &["amount_msat", "description", "expiring_in", "hints"],
+    ),
+    (
+        "holdinvoice",
+        "Create an invoice whose payment is held until it is settled",
+        &[
+            "payment_hash",
+            "amount_msat",
+            "description",
+            "expiring_in",
+            "label",
+        ],
+    ),
+    (
+        "settleinvoice",
+        "Claim the payment of a hold invoice with its preimage",
+        &["preimage"],
+    ),
+    (
+        "cancelinvoice",
+        "Cancel an unpaid invoice, late payments are rejected",
+        &["payment_hash", "label"],
+    ),
+    (
+        "delinvoice",
+        "Alias of cancelinvoice",
+        &["payment_hash", "label"],
+    ),
+    (
+        "lookupinvoice",
+        "Show an invoice with the payment secret and the settlement",
+        &["payment_hash", "label"],
+    ),
+    (
+        "listinvoices",
+        "List the invoices, with the unpaid ones past the expiry as expired",
+        &["label", "status", "offset", "limit"],
+    ),
+    (
+        "lspinfo",
+        "Show the fees asked by the LSP to open a JIT channel",
+        &[],
+    ),
+    (
+        "lspinvoice",
+        "Create an invoice paid through a JIT channel opened by the LSP",
+        &["amount_msat", "description", "expiring_in", "label"],
+    ),
+    (
+        "buyinbound",
+        "Buy a channel with inbound liquidity from the LSP",
+        &[
+            "lsp_balance_sat",
+            "channel_expiry_blocks",
+            "announce_channel",
+            "max_fee_sat",
+        ],
+    ),
+    (
+        "listlsporders",
+        "List the channels bought from the LSP with their state",
+        &[],
+    ),
+    (
+        "offer",
+        "Create a bolt12 offer",
+        &["amount_msat", "description", "issuer"],
+    ),
+    (
+        "listoffers",
+        "List the bolt12 offers created by the node",
+        &[],
+    ),
+    (
+        "refund",
+        "Create a bolt12 refund to be paid back",
+        &["amount_msat", "description", "expiry"],
+    ),
+    (
+        "claimrefund",
+        "Send an invoice to the payer of a bolt12 refund",
+        &["refund"],
+    ),
+    ("decode", "Decode an invoice or an offer", &["invoice_str"]),
+    (
+        "pay",
+        "Pay an invoice or an offer",
+        &[
+            "invoice_str",
+            "amount",
+            "simulate",
+            "out_channels",
+            "out_peer",
+            "exclude_channels",
+            "route_hints",
+            "replace_route_hints",
+            "route",
+        ],
+    ),
+    (
+        "payoffer",
+        "Fetch the invoice of a bolt12 offer and pay it",
+        &["offer", "amount_msat", "payer_note", "timeout"],
+    ),
+    (
+        "keysend",
+        "Send a spontaneous payment",
+        &[
+            "destination",
+            "amount_msat",
+            "out_channels",
+            "out_peer",
+            "exclude_channels",
+        ],
+    ),
+    (
+        "lookuppayment",
+        "Show a payment, with the preimage when settled",
+        &["payment_hash", "direction"],
+    ),
+    (
+        "schedulepay",
+        "Pay an offer or a keysend on a recurring schedule",
+        &[
+            "id",
+            "offer",
+            "destination",
+            "amount_msat",
+            "schedule",
+            "budget_msat",
+        ],
+    ),
+    ("listschedules", "List the recurring payments", &[]),
+    ("delschedule", "Remove a recurring payment", &["id"]),
+    (
+        "queuepay",
+        "Add an invoice or a keysend to the outgoing payment queue",
+        &["id", "invoice", "destination", "amount_msat"],
+    ),
+    (
+        "listqueue",
+        "List the payments of the outgoing payment queue",
+        &["status"],
+    ),
+    (
+        "rebalancereport",
+        "Show the actions taken by the rebalancer",
+        &[],
+    ),
+    ("fees", "Show the estimated fees", &[]),
+    (
+        "close",
+        "Close a channel",
+        &["node_id", "channel_id", "simulate"],
+    ),
+    (
+        "forceclose",
+        "Close a channel broadcasting the latest commitment",
+        &["node_id", "channel_id"],
+    ),
+    (
+        "listforcecloses",
+        "List the force closed channels with funds still to sweep",
+        &[],
+    ),
+    (
+        "setchannel",
+        "Update the forwarding fees and the cltv delta of a channel",
+        &[
+            "channel_id",
+            "fee_base_msat",
+            "fee_ppm",
+            "cltv_expiry_delta",
+            "max_dust_htlc_exposure_msat",
+        ],
+    ),
+    (
+        "listforwards",
+        "List the forwarded payments with the fee earned",
+        &["since", "until"],
+    ),
+    (
+        "interceptscid",
+        "Generate a short channel id whose HTLCs are held by the node",
+        &[],
+    ),
+    (
+        "forwardintercepted",
+        "Forward an intercepted HTLC over a channel",
+        &["intercept_id", "channel_id", "amount_msat"],
+    ),
+    (
+        "failintercepted",
+        "Fail back an intercepted HTLC",
+        &["intercept_id"],
+    ),
+    (
+        "exportchannelbackup",
+        "Export the encrypted static backup of the channels",
+        &[],
+    ),
+    (
+        "traceroute",
+        "Probe each hop of the route to a node",
+        &["destination", "amount_msat", "timeout"],
+    ),
+    (
+        "probe",
+        "Probe the liquidity toward a node without moving funds",
+        &["destination", "amount_msat", "timeout"],
+    ),
+    (
+        "getroute",
+        "Show the route to a node without sending anything",
+        &["destination", "amount_msat", "first_hop"],
+    ),
+    ("stats", "Show the statistics of the node", &[]),
+    (
+        "metrics",
+        "Show the latency of the RPC methods and of the ldk events",
+        &[],
+    ),
+    (
+        "exportgraph",
+        "Export the network graph as a snapshot",
+        &["path"],
+    ),
+    ("importgraph", "Import a network graph snapshot", &["path"]),
+    (
+        "listnodes",
+        "List the nodes of the network graph",
+        &["node_id"],
+    ),
+    (
+        "listgossipchannels",
+        "List the announced channels of the network graph",
+        &["short_channel_id", "node_id"],
+    ),
+    #[cfg(debug_assertions)]
+    (
+        "dev-pendingevents",
+        "List the ldk events that the handler is still processing",
+        &[],
+    ),
+    ("configdump", "Show the effective configuration", &[]),
+    ("commands", "List the available commands", &[]),
+];
+
+pub fn commands() -> Vec<CommandInfo> {
+    COMMANDS
+        .iter()
+        .map(|(name, description, params)| CommandInfo {
+            name: name.to_string(),
+            description: description.to_string(),
+            params: params.iter().map(|param| param.to_string()).collect(),
+        })
+        .collect()
+}
+
+pub fn command(name: &str) -> Option<CommandInfo> {
+    commands().into_iter().find(|command| command.name == name)
+}
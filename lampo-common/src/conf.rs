@@ -1,14 +1,35 @@
+mod channel_types;
+mod file;
+
+use std::net::SocketAddr;
 use std::str::FromStr;
 
 use clightningrpc_conf::{CLNConf, SyncCLNConf};
 
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::secp256k1::PublicKey;
+use lightning::ln::msgs::SocketAddress;
+
 pub use bitcoin::Network;
 pub use lightning::util::config::UserConfig;
 
+use crate::coin_selection::CoinSelection;
+
+pub use crate::conf::channel_types::*;
+pub use crate::conf::file::*;
+
+/// The challenge of the default signet.
+pub const DEFAULT_SIGNET_CHALLENGE: &str = "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+
 #[derive(Clone, Debug)]
 pub struct LampoConf {
     pub inner: Option<CLNConf>,
     pub network: Network,
+    /// Hex encoded challenge of a custom signet, all the signets share
+    /// the genesis block so the network parameters are the one of
+    /// `signet`, only the challenge (and the magic) changes.
+    pub signet_challenge: Option<String>,
     pub ldk_conf: UserConfig,
     pub port: u64,
     pub root_path: String,
@@ -17,12 +38,139 @@ pub struct LampoConf {
     pub core_url: Option<String>,
     pub core_user: Option<String>,
     pub core_pass: Option<String>,
+    /// Peers that serve the compact block filters to the `cbf`
+    /// backend, in the format `<host>:<port>`. When empty the
+    /// peers are found with the DNS seeds.
+    pub cbf_peers: Vec<String>,
+    /// Backends used when the `node` backend is not healthy, in
+    /// order of priority.
+    pub backend_fallbacks: Vec<String>,
+    /// Max number of blocks that the active backend can be behind
+    /// the other backends before that we switch to another one.
+    pub backend_max_lag: u32,
+    /// Min fee rate of our transactions in sat per 1000 weight, also
+    /// when the backend estimates a lower one. `None` use the min fee
+    /// rate of ldk.
+    pub min_fee_rate: Option<u32>,
+    /// Esplora API used to estimate the fees before the backend,
+    /// e.g: `https://mempool.space/api`.
+    pub fee_source: Option<String>,
+    /// How the utxos that fund the channels are chosen.
+    pub coin_selection: CoinSelection,
     pub private_key: Option<String>,
     pub channels_keys: Option<String>,
     pub log_file: Option<String>,
     pub log_level: String,
     pub alias: Option<String>,
     pub announce_addr: Option<String>,
+    /// Path of the JSON RPC unix socket, `None` use the default one.
+    pub rpc_socket: Option<String>,
+    /// Seconds between two public ip lookups, `None` disable the discovery.
+    pub public_ip_interval: Option<u64>,
+    /// HTTP service that returns the public ip as plain text.
+    pub public_ip_url: String,
+    /// Exchange rate provider used to create invoices with a fiat
+    /// amount, `None` disable the fiat amounts.
+    pub exchange_rate_provider: Option<String>,
+    /// Max number of invoices that each source can create every
+    /// minute, `None` disable the limit.
+    pub invoice_rate_limit: Option<u64>,
+    /// Min amount accepted for the invoices without an amount.
+    pub min_invoice_amount_msat: Option<u64>,
+    /// Hex encoded seed shared by the nodes of a phantom cluster.
+    pub phantom_seed: Option<String>,
+    /// Static channel backup used to recover the funds of the
+    /// channels, it is set only from the command line.
+    pub recover_from_scb: Option<String>,
+    /// Seconds between two background probes, `None` disable the prober.
+    pub probe_interval: Option<u64>,
+    pub probe_amount_msat: u64,
+    /// Max fees that we are willing to risk for each probe.
+    pub probe_max_fee_msat: u64,
+    /// Destinations to probe, if empty a random node from the graph is used.
+    pub probe_destinations: Vec<String>,
+    /// Seconds between two rebalance checks, `None` disable the rebalancer.
+    pub rebalance_interval: Option<u64>,
+    /// The channels with an outbound ratio (in percent) outside
+    /// the range `[rebalance_low_pct, rebalance_high_pct]` are rebalanced.
+    pub rebalance_low_pct: u64,
+    pub rebalance_high_pct: u64,
+    /// Max fees for each rebalance in part per million of the amount.
+    pub rebalance_max_fee_ppm: u64,
+    /// Max number of payments of the queue that are in flight at
+    /// the same time.
+    pub payment_queue_concurrency: u64,
+    /// Attempts for each payment of the queue before giving up.
+    pub payment_queue_max_attempts: u64,
+    /// Seconds to wait before retrying a failed payment of the queue.
+    pub payment_queue_retry_delay: u64,
+    /// Max number of parts of a multi part payment, `None`
+    /// use the ldk default.
+    pub payment_max_parts: Option<u8>,
+    /// Seconds to keep retrying the failed parts of a payment,
+    /// `None` retries a fixed number of times.
+    pub payment_part_timeout: Option<u64>,
+    /// Load the persisted network graph in background, so the node
+    /// is ready before that the graph is fully loaded.
+    pub lazy_graph: bool,
+    /// Keep in memory only the routable channels and drop the node
+    /// announcements, useful for constrained devices.
+    pub compact_graph: bool,
+    /// Nodes before us inside the blinded paths of the offers and of
+    /// the refunds, `0` reveals our node id.
+    pub blinded_path_hops: u8,
+    /// Hold the HTLCs sent to the intercept scids, so a plugin
+    /// can open a channel with the payee before forwarding them.
+    pub accept_intercept_htlcs: bool,
+    /// Where the watchdog stores the last channel state seen by the
+    /// node, by default inside the data dir.
+    pub watchdog_path: Option<String>,
+    /// Start even if the channel state looks older than the last
+    /// one seen by the node.
+    pub allow_stale_state: bool,
+    /// Address where the rapid gossip sync snapshots of our graph
+    /// are served over HTTP, disabled by default.
+    pub rgs_server: Option<String>,
+    /// Rapid gossip sync server where we download the snapshots
+    /// of the network graph at startup, disabled by default.
+    pub rgs_url: Option<String>,
+    /// Channel types that we propose and accept (e.g:
+    /// `static-remotekey,anchors-zero-fee-htlc`), `None` use the
+    /// ldk defaults and accept all the inbound channels.
+    pub channel_types: Option<String>,
+    /// Channel types for a single peer, in the
+    /// format `<node_id>:<channel types>`.
+    pub peer_channel_types: Vec<String>,
+    /// Reject the inbound channels smaller than this amount.
+    pub inbound_channel_min_sat: Option<u64>,
+    /// Reject the inbound channels bigger than this amount.
+    pub inbound_channel_max_sat: Option<u64>,
+    /// Max number of channels with the same peer, the inbound
+    /// channels over the limit are rejected.
+    pub max_channels_per_peer: Option<u64>,
+    /// Accept the inbound channels only from these nodes, all
+    /// the nodes are accepted when empty.
+    pub inbound_channel_allow: Vec<String>,
+    /// Reject the inbound channels from these nodes.
+    pub inbound_channel_deny: Vec<String>,
+    /// The LSP that opens the JIT channels, in the
+    /// format `<node_id>@<host>:<port>`.
+    pub lsp: Option<String>,
+    /// Token given by the LSP, if it requires one.
+    pub lsp_token: Option<String>,
+    /// Sell channels to the other nodes as a LSP (LSPS1 and LSPS2).
+    pub lsp_server: bool,
+    /// Smallest channel opened by the LSP server.
+    pub lsp_server_min_channel_sat: u64,
+    /// Biggest channel opened by the LSP server.
+    pub lsp_server_max_channel_sat: u64,
+    /// How long the LSP server keeps open the channels sold.
+    pub lsp_server_max_expiry_blocks: u32,
+    /// Fixed part of the fee asked to open a channel.
+    pub lsp_server_fee_base_sat: u64,
+    /// Proportional part of the fee asked to open a channel, in
+    /// part per million of the channel amount.
+    pub lsp_server_fee_ppm: u64,
 }
 
 impl Default for LampoConf {
@@ -37,6 +185,7 @@ impl Default for LampoConf {
             inner: None,
             // default network is testnet
             network: Network::Testnet,
+            signet_challenge: None,
             ldk_conf: UserConfig::default(),
             // default port is 19735 for testnet
             port: 19735,
@@ -45,12 +194,62 @@ impl Default for LampoConf {
             core_url: None,
             core_user: None,
             core_pass: None,
+            cbf_peers: Vec::new(),
+            backend_fallbacks: Vec::new(),
+            backend_max_lag: 2,
+            min_fee_rate: None,
+            fee_source: None,
+            coin_selection: CoinSelection::default(),
             private_key: None,
             channels_keys: None,
             log_level: "info".to_string(),
             log_file: None,
             alias: None,
             announce_addr: None,
+            rpc_socket: None,
+            public_ip_interval: None,
+            public_ip_url: "https://api.ipify.org".to_owned(),
+            exchange_rate_provider: None,
+            invoice_rate_limit: None,
+            min_invoice_amount_msat: None,
+            phantom_seed: None,
+            recover_from_scb: None,
+            probe_interval: None,
+            probe_amount_msat: 50_000_000,
+            probe_max_fee_msat: 50_000,
+            probe_destinations: Vec::new(),
+            rebalance_interval: None,
+            rebalance_low_pct: 20,
+            rebalance_high_pct: 80,
+            rebalance_max_fee_ppm: 500,
+            payment_queue_concurrency: 4,
+            payment_queue_max_attempts: 3,
+            payment_queue_retry_delay: 600,
+            payment_max_parts: None,
+            payment_part_timeout: None,
+            lazy_graph: false,
+            blinded_path_hops: 1,
+            compact_graph: false,
+            accept_intercept_htlcs: false,
+            watchdog_path: None,
+            allow_stale_state: false,
+            rgs_server: None,
+            rgs_url: None,
+            channel_types: None,
+            peer_channel_types: Vec::new(),
+            inbound_channel_min_sat: None,
+            inbound_channel_max_sat: None,
+            max_channels_per_peer: None,
+            inbound_channel_allow: Vec::new(),
+            inbound_channel_deny: Vec::new(),
+            lsp: None,
+            lsp_token: None,
+            lsp_server: false,
+            lsp_server_min_channel_sat: 100_000,
+            lsp_server_max_channel_sat: 10_000_000,
+            lsp_server_max_expiry_blocks: 13_140,
+            lsp_server_fee_base_sat: 1_000,
+            lsp_server_fee_ppm: 10_000,
         }
     }
 }
@@ -107,13 +306,23 @@ impl LampoConf {
         root
     }
 
+    /// Build the configuration with the following precedence
+    /// CLI > env > file > defaults, where the CLI arguments are
+    /// the one passed to this function.
+    ///
+    /// The file can be the structured `lampo.toml` or the
+    /// legacy `lampo.conf`.
     pub fn new(
         path: Option<String>,
         network: Option<Network>,
         port: Option<u64>,
     ) -> Result<Self, anyhow::Error> {
         let mut conf = Self::default();
-        conf.network = network.unwrap_or(conf.network);
+        let env_network = std::env::var("LAMPO_NETWORK")
+            .ok()
+            .map(|network| parse_network(&network))
+            .transpose()?;
+        conf.network = network.or(env_network).unwrap_or(conf.network);
         conf.port = port.unwrap_or(conf.port);
         conf.root_path = path.clone().unwrap_or(conf.root_path);
         Self::prepare_directories(&conf.root_path, Some(conf.network))?;
@@ -122,17 +331,53 @@ impl LampoConf {
         conf.root_path = path.clone();
 
         let lampo_file = format!("{}/lampo.conf", conf.path());
+        let toml_file = format!("{}/{LAMPO_TOML}", conf.path());
+        let toml_exists = std::path::Path::new(&toml_file).exists();
+        let conf_exists = std::path::Path::new(&lampo_file).exists();
 
-        if std::fs::File::open(lampo_file.clone()).is_ok() {
-            let mut conf = Self::try_from(conf.path())?;
-            conf.network = network.unwrap_or(conf.network);
-            conf.port = port.unwrap_or(conf.port);
-            conf.root_path = input_path.unwrap_or(conf.root_path);
-            return Ok(conf);
-        }
-
+        let mut conf = match (toml_exists, conf_exists) {
+            (true, true) => anyhow::bail!(
+                "Both `{toml_file}` and `{lampo_file}` found, please keep only one of them"
+            ),
+            (true, false) => {
+                LampoConfFile::load(&toml_file)?.apply(&mut conf)?;
+                conf.root_path = input_path.unwrap_or(conf.root_path);
+                conf
+            }
+            (false, true) => {
+                let mut conf = Self::try_from(conf.path())?;
+                conf.root_path = input_path.unwrap_or(conf.root_path);
+                conf
+            }
+            (false, false) => conf,
+        };
+        conf.apply_env()?;
+        conf.network = network.or(env_network).unwrap_or(conf.network);
+        conf.port = port.unwrap_or(conf.port);
         Ok(conf)
     }
+
+    /// Override the configuration with the `LAMPO_*` environment variables.
+    pub fn apply_env(&mut self) -> Result<(), anyhow::Error> {
+        let env = |key: &str| std::env::var(key).ok();
+        if let Some(port) = env("LAMPO_PORT") {
+            self.port = u64::from_str(&port)?;
+        }
+        if let Some(node) = env("LAMPO_BACKEND") {
+            self.node = node;
+        }
+        self.core_url = env("LAMPO_CORE_URL").or(self.core_url.take());
+        self.core_user = env("LAMPO_CORE_USER").or(self.core_user.take());
+        self.core_pass = env("LAMPO_CORE_PASS").or(self.core_pass.take());
+        self.alias = env("LAMPO_ALIAS").or(self.alias.take());
+        self.announce_addr = env("LAMPO_ANNOUNCE_ADDR").or(self.announce_addr.take());
+        self.rpc_socket = env("LAMPO_RPC_SOCKET").or(self.rpc_socket.take());
+        self.log_file = env("LAMPO_LOG_FILE").or(self.log_file.take());
+        if let Some(level) = env("LAMPO_LOG_LEVEL") {
+            self.log_level = level;
+        }
+        Ok(())
+    }
 }
 
 impl TryFrom<String> for LampoConf {
@@ -172,11 +417,35 @@ impl TryFrom<String> for LampoConf {
             .unwrap_or("nakamoto".to_owned());
         // Strip the value of whitespace
         let node = node.to_trimmed();
+        let backend_fallbacks = conf
+            .get_confs("backend-fallback")
+            .into_iter()
+            .map(|backend| backend.to_trimmed())
+            .collect::<Vec<_>>();
+        let backend_max_lag = conf
+            .get_conf("backend-max-lag")
+            .unwrap_or(None)
+            .map(|lag| u32::from_str(&lag))
+            .transpose()?
+            .unwrap_or(2);
+        // the configuration is in sat/vB, like the wallets.
+        let min_fee_rate = conf
+            .get_conf("min-fee-rate")
+            .unwrap_or(None)
+            .map(|fee_rate| u32::from_str(&fee_rate))
+            .transpose()?
+            .map(|fee_rate| fee_rate * 250);
+        let coin_selection = conf
+            .get_conf("coin-selection")
+            .unwrap_or(None)
+            .map(|strategy| CoinSelection::from_str(&strategy))
+            .transpose()?
+            .unwrap_or_default();
 
         let mut core_url = None;
         let mut core_user = None;
         let mut core_pass = None;
-        if node == "core" {
+        if node == "core" || backend_fallbacks.iter().any(|backend| backend == "core") {
             core_url = conf
                 .get_conf("core-url")
                 .map_err(|err| anyhow::anyhow!("{err}"))?;
@@ -210,7 +479,7 @@ impl TryFrom<String> for LampoConf {
                 .map_err(|err| anyhow::anyhow!("{err}"))?;
         }
 
-        let network = Network::from_str(&network)?;
+        let network = parse_network(&network)?;
         let root_path = Self::normalize_root_dir(&value, network);
         let log_level = conf.get_conf("log-level");
         let level = match log_level {
@@ -221,22 +490,255 @@ impl TryFrom<String> for LampoConf {
         let alias = conf.get_conf("alias").unwrap_or(None);
         let announce_addr = conf.get_conf("announce-addr").unwrap_or(None);
 
+        let probe_interval = conf
+            .get_conf("probe-interval")
+            .unwrap_or(None)
+            .map(|interval| u64::from_str(&interval))
+            .transpose()?;
+        let probe_amount_msat = conf
+            .get_conf("probe-amount-msat")
+            .unwrap_or(None)
+            .map(|amount| u64::from_str(&amount))
+            .transpose()?
+            .unwrap_or(50_000_000);
+        let probe_max_fee_msat = conf
+            .get_conf("probe-max-fee-msat")
+            .unwrap_or(None)
+            .map(|fee| u64::from_str(&fee))
+            .transpose()?
+            .unwrap_or(50_000);
+        let probe_destinations = conf.get_confs("probe-destination");
+
+        let rebalance_interval = conf
+            .get_conf("rebalance-interval")
+            .unwrap_or(None)
+            .map(|interval| u64::from_str(&interval))
+            .transpose()?;
+        let rebalance_low_pct = conf
+            .get_conf("rebalance-low-pct")
+            .unwrap_or(None)
+            .map(|pct| u64::from_str(&pct))
+            .transpose()?
+            .unwrap_or(20);
+        let rebalance_high_pct = conf
+            .get_conf("rebalance-high-pct")
+            .unwrap_or(None)
+            .map(|pct| u64::from_str(&pct))
+            .transpose()?
+            .unwrap_or(80);
+        let rebalance_max_fee_ppm = conf
+            .get_conf("rebalance-max-fee-ppm")
+            .unwrap_or(None)
+            .map(|ppm| u64::from_str(&ppm))
+            .transpose()?
+            .unwrap_or(500);
+        let payment_queue_concurrency = conf
+            .get_conf("payment-queue-concurrency")
+            .unwrap_or(None)
+            .map(|concurrency| u64::from_str(&concurrency))
+            .transpose()?
+            .unwrap_or(4);
+        let payment_queue_max_attempts = conf
+            .get_conf("payment-queue-max-attempts")
+            .unwrap_or(None)
+            .map(|attempts| u64::from_str(&attempts))
+            .transpose()?
+            .unwrap_or(3);
+        let payment_queue_retry_delay = conf
+            .get_conf("payment-queue-retry-delay")
+            .unwrap_or(None)
+            .map(|delay| u64::from_str(&delay))
+            .transpose()?
+            .unwrap_or(600);
+        let payment_max_parts = conf
+            .get_conf("payment-max-parts")
+            .unwrap_or(None)
+            .map(|parts| u8::from_str(&parts))
+            .transpose()?;
+        let payment_part_timeout = conf
+            .get_conf("payment-part-timeout")
+            .unwrap_or(None)
+            .map(|timeout| u64::from_str(&timeout))
+            .transpose()?;
+        let lazy_graph = conf
+            .get_conf("lazy-graph")
+            .unwrap_or(None)
+            .map(|lazy| bool::from_str(&lazy))
+            .transpose()?
+            .unwrap_or(false);
+        let compact_graph = conf
+            .get_conf("compact-graph")
+            .unwrap_or(None)
+            .map(|compact| bool::from_str(&compact))
+            .transpose()?
+            .unwrap_or(false);
+        let blinded_path_hops = conf
+            .get_conf("blinded-path-hops")
+            .unwrap_or(None)
+            .map(|hops| u8::from_str(&hops))
+            .transpose()?
+            .unwrap_or(1);
+        let accept_intercept_htlcs = conf
+            .get_conf("accept-intercept-htlcs")
+            .unwrap_or(None)
+            .map(|accept| bool::from_str(&accept))
+            .transpose()?
+            .unwrap_or(false);
+        let allow_stale_state = conf
+            .get_conf("allow-stale-state")
+            .unwrap_or(None)
+            .map(|allow| bool::from_str(&allow))
+            .transpose()?
+            .unwrap_or(false);
+
+        let public_ip_interval = conf
+            .get_conf("public-ip-interval")
+            .unwrap_or(None)
+            .map(|interval| u64::from_str(&interval))
+            .transpose()?;
+        let invoice_rate_limit = conf
+            .get_conf("invoice-rate-limit")
+            .unwrap_or(None)
+            .map(|limit| u64::from_str(&limit))
+            .transpose()?;
+        let min_invoice_amount_msat = conf
+            .get_conf("min-invoice-amount-msat")
+            .unwrap_or(None)
+            .map(|amount| u64::from_str(&amount))
+            .transpose()?;
+        let public_ip_url = conf
+            .get_conf("public-ip-url")
+            .unwrap_or(None)
+            .unwrap_or("https://api.ipify.org".to_owned());
+
+        let inbound_channel_min_sat = conf
+            .get_conf("inbound-channel-min-sat")
+            .unwrap_or(None)
+            .map(|amount| u64::from_str(&amount))
+            .transpose()?;
+        let inbound_channel_max_sat = conf
+            .get_conf("inbound-channel-max-sat")
+            .unwrap_or(None)
+            .map(|amount| u64::from_str(&amount))
+            .transpose()?;
+        let max_channels_per_peer = conf
+            .get_conf("max-channels-per-peer")
+            .unwrap_or(None)
+            .map(|max| u64::from_str(&max))
+            .transpose()?;
+        let lsp_server = conf
+            .get_conf("lsp-server")
+            .unwrap_or(None)
+            .map(|server| bool::from_str(&server))
+            .transpose()?
+            .unwrap_or(false);
+        let lsp_server_min_channel_sat = conf
+            .get_conf("lsp-server-min-channel-sat")
+            .unwrap_or(None)
+            .map(|amount| u64::from_str(&amount))
+            .transpose()?
+            .unwrap_or(100_000);
+        let lsp_server_max_channel_sat = conf
+            .get_conf("lsp-server-max-channel-sat")
+            .unwrap_or(None)
+            .map(|amount| u64::from_str(&amount))
+            .transpose()?
+            .unwrap_or(10_000_000);
+        let lsp_server_max_expiry_blocks = conf
+            .get_conf("lsp-server-max-expiry-blocks")
+            .unwrap_or(None)
+            .map(|blocks| u32::from_str(&blocks))
+            .transpose()?
+            .unwrap_or(13_140);
+        let lsp_server_fee_base_sat = conf
+            .get_conf("lsp-server-fee-base-sat")
+            .unwrap_or(None)
+            .map(|fee| u64::from_str(&fee))
+            .transpose()?
+            .unwrap_or(1_000);
+        let lsp_server_fee_ppm = conf
+            .get_conf("lsp-server-fee-ppm")
+            .unwrap_or(None)
+            .map(|ppm| u64::from_str(&ppm))
+            .transpose()?
+            .unwrap_or(10_000);
+
+        // Inbound fees (blip-0018) are not supported by the ldk version
+        // that we are using, so fail loudly instead of silently ignoring
+        // the user policy.
+        for key in ["inbound-fee-base-msat", "inbound-fee-ppm"] {
+            if conf.get_conf(key).unwrap_or(None).is_some() {
+                anyhow::bail!("`{key}` is not supported yet: inbound fees are not available in the ldk version used by lampo");
+            }
+        }
+
         Ok(Self {
-            inner: Some(conf),
             root_path,
             network,
+            signet_challenge: conf.get_conf("signet-challenge").unwrap_or(None),
             ldk_conf: UserConfig::default(),
             port: u64::from_str(&port)?,
             node,
             core_url,
             core_user,
             core_pass,
+            cbf_peers: conf.get_confs("cbf-peer"),
+            backend_fallbacks,
+            backend_max_lag,
+            min_fee_rate,
+            fee_source: conf.get_conf("fee-source").unwrap_or(None),
+            coin_selection,
             private_key,
             channels_keys,
             log_file,
             log_level: level,
             alias,
             announce_addr,
+            rpc_socket: conf.get_conf("rpc-socket").unwrap_or(None),
+            public_ip_interval,
+            public_ip_url,
+            exchange_rate_provider: conf.get_conf("exchange-rate-provider").unwrap_or(None),
+            invoice_rate_limit,
+            min_invoice_amount_msat,
+            phantom_seed: conf.get_conf("phantom-seed").unwrap_or(None),
+            recover_from_scb: None,
+            probe_interval,
+            probe_amount_msat,
+            probe_max_fee_msat,
+            probe_destinations,
+            rebalance_interval,
+            rebalance_low_pct,
+            rebalance_high_pct,
+            rebalance_max_fee_ppm,
+            payment_queue_concurrency,
+            payment_queue_max_attempts,
+            payment_queue_retry_delay,
+            payment_max_parts,
+            payment_part_timeout,
+            lazy_graph,
+            blinded_path_hops,
+            compact_graph,
+            accept_intercept_htlcs,
+            watchdog_path: conf.get_conf("watchdog-path").unwrap_or(None),
+            allow_stale_state,
+            rgs_server: conf.get_conf("rgs-server").unwrap_or(None),
+            rgs_url: conf.get_conf("rgs-url").unwrap_or(None),
+            channel_types: conf.get_conf("channel-types").unwrap_or(None),
+            peer_channel_types: conf.get_confs("peer-channel-types"),
+            inbound_channel_min_sat,
+            inbound_channel_max_sat,
+            max_channels_per_peer,
+            inbound_channel_allow: conf.get_confs("inbound-channel-allow"),
+            inbound_channel_deny: conf.get_confs("inbound-channel-deny"),
+            lsp: conf.get_conf("lsp").unwrap_or(None),
+            lsp_token: conf.get_conf("lsp-token").unwrap_or(None),
+            lsp_server,
+            lsp_server_min_channel_sat,
+            lsp_server_max_channel_sat,
+            lsp_server_max_expiry_blocks,
+            lsp_server_fee_base_sat,
+            lsp_server_fee_ppm,
+            inner: Some(conf),
         })
     }
 }
@@ -246,6 +748,434 @@ impl LampoConf {
         format!("{}/{}", self.root_path, self.network)
     }
 
+    /// Validate the options of the `backend` implementation.
+    fn validate_backend(&self, backend: &str, errors: &mut Vec<String>) {
+        match backend {
+            "core" => {
+                match &self.core_url {
+                    // FIXME: the bitcoincore_rpc do not support the https protocol.
+                    Some(url) if url.contains("://") && !url.starts_with("http://") => {
+                        errors.push(format!(
+                            "`core-url`: `{url}` must be an http url, e.g: `http://127.0.0.1:8332`"
+                        ))
+                    }
+                    None => errors.push("`core-url`: required by the `core` backend".to_owned()),
+                    _ => {}
+                }
+                if self.core_user.is_none() {
+                    errors.push("`core-user`: required by the `core` backend".to_owned());
+                }
+                if self.core_pass.is_none() {
+                    errors.push("`core-pass`: required by the `core` backend".to_owned());
+                }
+            }
+            "cbf" => {
+                if self.cbf_peers.is_empty() && self.network == Network::Regtest {
+                    errors
+                        .push("`cbf-peer`: required by the `cbf` backend on `regtest`".to_owned());
+                }
+                // the peers do not serve the fee rates, without a fee source
+                // ldk would build all the transactions with the min fee rate.
+                if self.fee_source.is_none() && self.network != Network::Regtest {
+                    errors.push(format!(
+                        "`fee-source`: required by the `cbf` backend on `{}`",
+                        self.network
+                    ));
+                }
+                for peer in &self.cbf_peers {
+                    let port = peer.rsplit_once(':').map(|(_, port)| u16::from_str(port));
+                    if !matches!(port, Some(Ok(_))) {
+                        errors.push(format!(
+                            "`cbf-peer`: `{peer}` is not a valid address, e.g: `127.0.0.1:8333`"
+                        ));
+                    }
+                }
+            }
+            backend => errors.push(format!(
+                "`backend`: `{backend}` is not supported, the supported backends are `core` and `cbf`"
+            )),
+        }
+    }
+
+    /// Validate the full configuration and report all the
+    /// invalid fields at once, so the user can fix them before
+    /// that lampo starts.
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        self.validate_options(true)
+    }
+
+    /// Validate the configuration without the backend options, for
+    /// a node that runs with a backend that is not built from the
+    /// configuration (e.g: injected with the `LampoBuilder`).
+    pub fn validate_without_backend(&self) -> Result<(), anyhow::Error> {
+        self.validate_options(false)
+    }
+
+    fn validate_options(&self, backend: bool) -> Result<(), anyhow::Error> {
+        let mut errors = Vec::new();
+
+        if !std::path::Path::new(&self.path()).is_dir() {
+            errors.push(format!(
+                "`data-dir`: directory `{}` does not exist",
+                self.path()
+            ));
+        }
+        if let Some(challenge) = &self.signet_challenge {
+            if self.network != Network::Signet {
+                errors.push(format!(
+                    "`signet-challenge`: can be used only with the `signet` network, not `{}`",
+                    self.network
+                ));
+            }
+            if Vec::<u8>::from_hex(challenge).is_err() {
+                errors.push(format!(
+                    "`signet-challenge`: `{challenge}` is not a hex encoded script"
+                ));
+            }
+        }
+        if self.port == 0 || self.port > u16::MAX as u64 {
+            errors.push(format!("`port`: `{}` is not a valid port", self.port));
+        }
+        if log::LevelFilter::from_str(&self.log_level).is_err() {
+            errors.push(format!(
+                "`log-level`: `{}` is not a valid level",
+                self.log_level
+            ));
+        }
+        if backend {
+            for (idx, backend) in self.backend_fallbacks.iter().enumerate() {
+                if *backend == self.node || self.backend_fallbacks[..idx].contains(backend) {
+                    errors.push(format!(
+                        "`backend-fallback`: `{backend}` is already used as backend"
+                    ));
+                }
+            }
+            for backend in std::iter::once(&self.node).chain(&self.backend_fallbacks) {
+                self.validate_backend(backend, &mut errors);
+            }
+        }
+        if let Some(alias) = &self.alias {
+            if alias.len() > 32 {
+                errors.push(format!("`alias`: `{alias}` is longer than 32 bytes"));
+            }
+        }
+        if let Some(addr) = &self.announce_addr {
+            // an IPv6 address can not be joined to the port with a `:`
+            let valid = std::net::IpAddr::from_str(addr).is_ok()
+                || SocketAddress::from_str(&format!("{addr}:{}", self.port)).is_ok();
+            if !valid {
+                errors.push(format!("`announce-addr`: `{addr}` is not a valid address"));
+            }
+        }
+        if let Some(socket) = &self.rpc_socket {
+            let parent = std::path::Path::new(socket).parent();
+            if !parent.map(|dir| dir.is_dir()).unwrap_or(false) {
+                errors.push(format!(
+                    "`rpc-socket`: the directory of `{socket}` does not exist"
+                ));
+            }
+        }
+        if self.public_ip_interval == Some(0) {
+            errors.push("`public-ip-interval`: must be greater than zero".to_owned());
+        }
+        if let Some(url) = &self.fee_source {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                errors.push(format!(
+                    "`fee-source`: `{url}` must be an http url, e.g: `https://mempool.space/api`"
+                ));
+            }
+        }
+        if self.public_ip_interval.is_some() && !self.public_ip_url.starts_with("http") {
+            errors.push(format!(
+                "`public-ip-url`: `{}` is not an http url",
+                self.public_ip_url
+            ));
+        }
+        if let Some(provider) = &self.exchange_rate_provider {
+            if provider != "coingecko" {
+                errors.push(format!(
+                    "`exchange-rate-provider`: unknown provider `{provider}`, supported: coingecko"
+                ));
+            }
+        }
+        if self.phantom_seed.is_some() {
+            if let Err(err) = self.phantom_seed() {
+                errors.push(format!("`phantom-seed`: {err}"));
+            }
+        }
+        if let Some(addr) = &self.rgs_server {
+            if std::net::SocketAddr::from_str(addr).is_err() {
+                errors.push(format!(
+                    "`rgs-server`: `{addr}` is not a valid address, e.g: `127.0.0.1:8011`"
+                ));
+            }
+        }
+        if let Some(url) = &self.rgs_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                errors.push(format!("`rgs-url`: `{url}` is not an http url"));
+            }
+        }
+        if self.invoice_rate_limit == Some(0) {
+            errors.push("`invoice-rate-limit`: must be greater than zero".to_owned());
+        }
+        if self.payment_queue_concurrency == 0 {
+            errors.push("`payment-queue-concurrency`: must be greater than zero".to_owned());
+        }
+        if self.probe_interval == Some(0) {
+            errors.push("`probe-interval`: must be greater than zero".to_owned());
+        }
+        if self.probe_amount_msat == 0 {
+            errors.push("`probe-amount-msat`: must be greater than zero".to_owned());
+        }
+        for destination in &self.probe_destinations {
+            if PublicKey::from_str(destination).is_err() {
+                errors.push(format!(
+                    "`probe-destination`: `{destination}` is not a valid node id"
+                ));
+            }
+        }
+        if self.rebalance_interval == Some(0) {
+            errors.push("`rebalance-interval`: must be greater than zero".to_owned());
+        }
+        if self.rebalance_low_pct >= self.rebalance_high_pct || self.rebalance_high_pct > 100 {
+            errors.push(format!(
+                "`rebalance-low-pct` and `rebalance-high-pct`: invalid range `{}-{}`",
+                self.rebalance_low_pct, self.rebalance_high_pct
+            ));
+        }
+        if self.payment_queue_concurrency == 0 {
+            errors.push("`payment-queue-concurrency`: must be greater than zero".to_owned());
+        }
+        if self.payment_queue_max_attempts == 0 {
+            errors.push("`payment-queue-max-attempts`: must be greater than zero".to_owned());
+        }
+        if self.payment_max_parts == Some(0) {
+            errors.push("`payment-max-parts`: must be greater than zero".to_owned());
+        }
+        if self.payment_part_timeout == Some(0) {
+            errors.push("`payment-part-timeout`: must be greater than zero".to_owned());
+        }
+        if self.blinded_path_hops > 3 {
+            errors.push("`blinded-path-hops`: must be at most 3".to_owned());
+        }
+        if let Some(types) = &self.channel_types {
+            if let Err(err) = ChannelTypes::from_str(types) {
+                errors.push(format!("`channel-types`: {err}"));
+            }
+        }
+        for peer in &self.peer_channel_types {
+            if let Err(err) = parse_peer_channel_types(peer) {
+                errors.push(format!("`peer-channel-types`: `{peer}` {err}"));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.inbound_channel_min_sat, self.inbound_channel_max_sat)
+        {
+            if min > max {
+                errors.push(format!(
+                    "`inbound-channel-min-sat` and `inbound-channel-max-sat`: invalid range `{min}-{max}`"
+                ));
+            }
+        }
+        if self.max_channels_per_peer == Some(0) {
+            errors.push("`max-channels-per-peer`: must be greater than zero".to_owned());
+        }
+        for (key, nodes) in [
+            ("inbound-channel-allow", &self.inbound_channel_allow),
+            ("inbound-channel-deny", &self.inbound_channel_deny),
+        ] {
+            for node_id in nodes {
+                if PublicKey::from_str(node_id).is_err() {
+                    errors.push(format!("`{key}`: `{node_id}` is not a valid node id"));
+                }
+            }
+        }
+        if let Err(err) = self.lsp() {
+            errors.push(format!("`lsp`: {err}"));
+        }
+        if self.lsp_server_min_channel_sat > self.lsp_server_max_channel_sat {
+            errors.push(format!(
+                "`lsp-server-min-channel-sat` and `lsp-server-max-channel-sat`: invalid range `{}-{}`",
+                self.lsp_server_min_channel_sat, self.lsp_server_max_channel_sat
+            ));
+        }
+        if self.lsp_server_fee_ppm > 1_000_000 {
+            errors.push(
+                "`lsp-server-fee-ppm`: must be at most 1000000 (100% of the channel)".to_owned(),
+            );
+        }
+        // the JIT channels are sold with the intercept scids
+        if self.lsp_server && !self.accept_intercept_htlcs {
+            errors.push("`lsp-server`: requires `accept-intercept-htlcs=true`".to_owned());
+        }
+        if self.private_key.is_none() && self.channels_keys.is_some() {
+            errors.push(
+                "`dev-force-channel-secrets`: can be used only with `dev-private-key`".to_owned(),
+            );
+        }
+
+        if !errors.is_empty() {
+            anyhow::bail!("Invalid configuration:\n  {}", errors.join("\n  "));
+        }
+        Ok(())
+    }
+
+    /// The challenge of the signet, the default signet one when
+    /// a custom signet is not specified.
+    pub fn signet_challenge(&self) -> String {
+        self.signet_challenge
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SIGNET_CHALLENGE.to_owned())
+    }
+
+    /// The network magic of the signet, the first 4 bytes of the
+    /// double sha256 of the challenge (BIP 325).
+    pub fn signet_magic(&self) -> Result<[u8; 4], anyhow::Error> {
+        let challenge = Vec::<u8>::from_hex(&self.signet_challenge())?;
+        let hash = sha256d::Hash::hash(&bitcoin::consensus::serialize(&challenge));
+        let mut magic = [0; 4];
+        magic.copy_from_slice(&hash.to_byte_array()[..4]);
+        Ok(magic)
+    }
+
+    /// Decode the seed shared by the phantom nodes.
+    pub fn phantom_seed(&self) -> Result<Option<[u8; 32]>, anyhow::Error> {
+        let Some(seed) = &self.phantom_seed else {
+            return Ok(None);
+        };
+        let seed = Vec::<u8>::from_hex(seed)?;
+        let seed: [u8; 32] = seed
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("the seed must be 32 bytes hex encoded"))?;
+        Ok(Some(seed))
+    }
+
+    /// The channel types allowed with `node_id`, `None` when
+    /// there is no policy for the peer.
+    pub fn channel_types(&self, node_id: &str) -> Result<Option<ChannelTypes>, anyhow::Error> {
+        for peer in &self.peer_channel_types {
+            let (peer, types) = parse_peer_channel_types(peer)?;
+            if peer.to_string() == node_id {
+                return Ok(Some(types));
+            }
+        }
+        // the LSP forwards the payment before that
+        // the JIT channel is confirmed.
+        if let Some((lsp, _)) = self.lsp()? {
+            if lsp.to_string() == node_id {
+                return Ok(Some(ChannelTypes {
+                    scid_alias: true,
+                    zero_conf: true,
+                    ..ChannelTypes::default()
+                }));
+            }
+        }
+        self.channel_types
+            .as_ref()
+            .map(|types| ChannelTypes::from_str(types))
+            .transpose()
+    }
+
+    /// The node id and the address of the LSP.
+    pub fn lsp(&self) -> Result<Option<(PublicKey, SocketAddr)>, anyhow::Error> {
+        let Some(lsp) = &self.lsp else {
+            return Ok(None);
+        };
+        let Some((node_id, addr)) = lsp.split_once('@') else {
+            anyhow::bail!("`{lsp}` must be in the format `<node_id>@<host>:<port>`");
+        };
+        let node_id = PublicKey::from_str(node_id)
+            .map_err(|_| anyhow::anyhow!("`{node_id}` is not a valid node id"))?;
+        let addr = SocketAddr::from_str(addr)
+            .map_err(|_| anyhow::anyhow!("`{addr}` is not a valid address"))?;
+        Ok(Some((node_id, addr)))
+    }
+
+    /// The ldk configuration of the node, the inbound channels are
+    /// accepted manually so the acceptance policy is applied, and
+    /// the features allowed with at least one peer are advertised.
+    pub fn user_config(&self) -> Result<UserConfig, anyhow::Error> {
+        let mut conf = self.ldk_conf;
+        conf.manually_accept_inbound_channels = true;
+        conf.accept_intercept_htlcs = self.accept_intercept_htlcs;
+        // the LSP takes the opening fee of the JIT channel from the
+        // payment, the fee taken is checked when the payment is claimed.
+        conf.channel_config.accept_underpaying_htlcs = self.lsp.is_some();
+        let mut policies = self
+            .peer_channel_types
+            .iter()
+            .map(|peer| parse_peer_channel_types(peer).map(|(_, types)| types))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(types) = &self.channel_types {
+            policies.push(ChannelTypes::from_str(types)?);
+        }
+        let handshake = &mut conf.channel_handshake_config;
+        handshake.negotiate_anchors_zero_fee_htlc_tx |=
+            policies.iter().any(|types| types.anchors_zero_fee_htlc);
+        Ok(conf)
+    }
+
+    /// Check the inbound channel of `node_id` against the acceptance
+    /// policy, `channels` is the number of channels with the peer.
+    pub fn check_inbound_channel(
+        &self,
+        node_id: &str,
+        funding_sat: u64,
+        channels: usize,
+    ) -> Result<(), anyhow::Error> {
+        if self.inbound_channel_deny.iter().any(|node| node == node_id) {
+            anyhow::bail!("the node is inside `inbound-channel-deny`");
+        }
+        if !self.inbound_channel_allow.is_empty()
+            && !self
+                .inbound_channel_allow
+                .iter()
+                .any(|node| node == node_id)
+        {
+            anyhow::bail!("the node is not inside `inbound-channel-allow`");
+        }
+        if let Some(min) = self.inbound_channel_min_sat {
+            if funding_sat < min {
+                anyhow::bail!(
+                    "the channel of {funding_sat} sats is below `inbound-channel-min-sat` {min}"
+                );
+            }
+        }
+        if let Some(max) = self.inbound_channel_max_sat {
+            if funding_sat > max {
+                anyhow::bail!(
+                    "the channel of {funding_sat} sats is above `inbound-channel-max-sat` {max}"
+                );
+            }
+        }
+        if let Some(max) = self.max_channels_per_peer {
+            if channels as u64 >= max {
+                anyhow::bail!(
+                    "the node has already {channels} channels, `max-channels-per-peer` is {max}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The ldk configuration used to open a channel with `node_id`.
+    pub fn channel_config(&self, node_id: &str) -> Result<UserConfig, anyhow::Error> {
+        let mut conf = self.user_config()?;
+        match self.channel_types(node_id)? {
+            Some(types) => types.apply(&mut conf),
+            None => {
+                conf.channel_handshake_config = self.ldk_conf.channel_handshake_config;
+                conf.channel_handshake_limits = self.ldk_conf.channel_handshake_limits;
+            }
+        }
+        Ok(conf)
+    }
+
+    pub fn rpc_socket(&self) -> String {
+        self.rpc_socket
+            .clone()
+            .unwrap_or_else(|| format!("{}/lampod.socket", self.path()))
+    }
+
     pub fn get_values(&self, key: &str) -> Option<Vec<String>> {
         self.inner.as_ref().map(|conf| conf.get_confs(key))
     }
@@ -263,11 +1193,32 @@ impl LampoConf {
     }
 
     pub fn set_network(&mut self, network: &str) -> anyhow::Result<()> {
-        self.network = Network::from_str(network)?;
+        self.network = parse_network(network)?;
         Ok(())
     }
 }
 
+/// Parse the network name, testnet4 is rejected with a clear
+/// error because the bitcoin and ldk versions used by lampo do
+/// not know its genesis block, so the channels would be opened
+/// on the testnet3 chain.
+pub fn parse_network(network: &str) -> Result<Network, anyhow::Error> {
+    if network == "testnet4" {
+        anyhow::bail!("`testnet4` is not supported yet: the bitcoin and ldk versions used by lampo do not support it");
+    }
+    Ok(Network::from_str(network)?)
+}
+
+/// Parse `<node_id>:<channel types>`.
+fn parse_peer_channel_types(peer: &str) -> Result<(PublicKey, ChannelTypes), anyhow::Error> {
+    let Some((node_id, types)) = peer.split_once(':') else {
+        anyhow::bail!("must be in the format `<node_id>:<channel types>`");
+    };
+    let node_id = PublicKey::from_str(node_id.trim())
+        .map_err(|_| anyhow::anyhow!("`{node_id}` is not a valid node id"))?;
+    Ok((node_id, ChannelTypes::from_str(types)?))
+}
+
 // A trait to trim a String
 trait TrimmedString {
     fn to_trimmed(self) -> String;
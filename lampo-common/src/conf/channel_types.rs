@@ -0,0 +1,69 @@
+//! Channel type negotiation preferences.
+//!
+//! The channel types are specified as a comma separated list,
+//! e.g: `static-remotekey,anchors-zero-fee-htlc,zero-conf`. The
+//! static remote key is always used, so `static-remotekey` alone
+//! means that only the plain channels are proposed and accepted.
+use std::str::FromStr;
+
+use lightning::ln::features::ChannelTypeFeatures;
+use lightning::util::config::UserConfig;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChannelTypes {
+    pub anchors_zero_fee_htlc: bool,
+    /// Use an alias instead of the real short channel id
+    /// for the private channels.
+    pub scid_alias: bool,
+    /// Trust the peer and use the channel before that the
+    /// funding transaction is confirmed.
+    pub zero_conf: bool,
+}
+
+impl ChannelTypes {
+    /// Set the channel types that we propose when opening a channel.
+    pub fn apply(&self, conf: &mut UserConfig) {
+        conf.channel_handshake_config
+            .negotiate_anchors_zero_fee_htlc_tx = self.anchors_zero_fee_htlc;
+        conf.channel_handshake_config.negotiate_scid_privacy = self.scid_alias;
+        conf.channel_handshake_limits.trust_own_funding_0conf = self.zero_conf;
+    }
+
+    /// Check the channel type proposed by the peer, and return
+    /// `true` when the channel can be used without confirmations.
+    pub fn accept(&self, channel_type: &ChannelTypeFeatures) -> Result<bool, anyhow::Error> {
+        if channel_type.requires_anchors_zero_fee_htlc_tx() && !self.anchors_zero_fee_htlc {
+            anyhow::bail!("anchors-zero-fee-htlc channels are not accepted");
+        }
+        if channel_type.requires_scid_privacy() && !self.scid_alias {
+            anyhow::bail!("scid-alias channels are not accepted");
+        }
+        if channel_type.requires_zero_conf() && !self.zero_conf {
+            anyhow::bail!("zero-conf channels are not accepted");
+        }
+        Ok(self.zero_conf)
+    }
+}
+
+impl FromStr for ChannelTypes {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut types = ChannelTypes::default();
+        for kind in s.split(',').map(|kind| kind.trim()) {
+            match kind {
+                "static-remotekey" => {}
+                "anchors-zero-fee-htlc" => types.anchors_zero_fee_htlc = true,
+                "scid-alias" => types.scid_alias = true,
+                "zero-conf" => types.zero_conf = true,
+                "anchors" => anyhow::bail!(
+                    "ldk supports only the `anchors-zero-fee-htlc` anchors channels"
+                ),
+                kind => anyhow::bail!(
+                    "unknown channel type `{kind}`, supported: static-remotekey, anchors-zero-fee-htlc, scid-alias, zero-conf"
+                ),
+            }
+        }
+        Ok(types)
+    }
+}
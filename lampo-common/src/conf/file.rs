@@ -0,0 +1,523 @@
+//! Structured TOML configuration file.
+//!
+//! The `lampo.toml` lives in the same directory of the
+//! `lampo.conf`, and it is applied on top of the default
+//! configuration. The environment variables and the command
+//! line arguments are applied after the file, so the precedence
+//! is CLI > env > file > defaults.
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::{parse_network, LampoConf};
+use crate::coin_selection::CoinSelection;
+
+pub const LAMPO_TOML: &str = "lampo.toml";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LampoConfFile {
+    pub network: Option<String>,
+    pub signet_challenge: Option<String>,
+    pub port: Option<u64>,
+    pub alias: Option<String>,
+    pub announce_addr: Option<String>,
+    pub public_ip_interval: Option<u64>,
+    pub public_ip_url: Option<String>,
+    pub exchange_rate_provider: Option<String>,
+    pub invoice_rate_limit: Option<u64>,
+    pub min_invoice_amount_msat: Option<u64>,
+    pub phantom_seed: Option<String>,
+    pub lazy_graph: Option<bool>,
+    pub compact_graph: Option<bool>,
+    pub blinded_path_hops: Option<u8>,
+    pub accept_intercept_htlcs: Option<bool>,
+    pub watchdog_path: Option<String>,
+    pub allow_stale_state: Option<bool>,
+    pub rgs_server: Option<String>,
+    pub rgs_url: Option<String>,
+    pub lsp: Option<String>,
+    pub lsp_token: Option<String>,
+    pub lsp_server: Option<bool>,
+    /// Min fee rate of our transactions in sat/vB.
+    pub min_fee_rate: Option<u32>,
+    pub fee_source: Option<String>,
+    /// `largest-first`, `bnb` or `avoid-dust`.
+    pub coin_selection: Option<String>,
+    #[serde(default)]
+    pub backend: BackendSection,
+    #[serde(default)]
+    pub wallet: WalletSection,
+    #[serde(default)]
+    pub rpc: RpcSection,
+    #[serde(default)]
+    pub log: LogSection,
+    #[serde(default)]
+    pub policy: PolicySection,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BackendSection {
+    /// The backend implementation, e.g: `core`.
+    pub kind: Option<String>,
+    pub core_url: Option<String>,
+    pub core_user: Option<String>,
+    pub core_pass: Option<String>,
+    pub cbf_peers: Option<Vec<String>>,
+    /// Backends used when `kind` is not healthy, in order of priority.
+    pub fallback: Option<Vec<String>>,
+    pub max_lag: Option<u32>,
+}
+
+/// Dev options, they are ignored in release builds.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WalletSection {
+    pub dev_private_key: Option<String>,
+    pub dev_force_channel_secrets: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RpcSection {
+    /// Path of the unix socket, by default `<data-dir>/lampod.socket`.
+    pub socket: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LogSection {
+    pub level: Option<String>,
+    pub file: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PolicySection {
+    pub probe_interval: Option<u64>,
+    pub probe_amount_msat: Option<u64>,
+    pub probe_max_fee_msat: Option<u64>,
+    pub probe_destinations: Option<Vec<String>>,
+    pub rebalance_interval: Option<u64>,
+    pub rebalance_low_pct: Option<u64>,
+    pub rebalance_high_pct: Option<u64>,
+    pub rebalance_max_fee_ppm: Option<u64>,
+    pub payment_queue_concurrency: Option<u64>,
+    pub payment_queue_max_attempts: Option<u64>,
+    pub payment_queue_retry_delay: Option<u64>,
+    pub payment_max_parts: Option<u8>,
+    pub payment_part_timeout: Option<u64>,
+    pub channel_types: Option<String>,
+    pub peer_channel_types: Option<Vec<String>>,
+    pub inbound_channel_min_sat: Option<u64>,
+    pub inbound_channel_max_sat: Option<u64>,
+    pub max_channels_per_peer: Option<u64>,
+    pub inbound_channel_allow: Option<Vec<String>>,
+    pub inbound_channel_deny: Option<Vec<String>>,
+    pub lsp_server_min_channel_sat: Option<u64>,
+    pub lsp_server_max_channel_sat: Option<u64>,
+    pub lsp_server_max_expiry_blocks: Option<u32>,
+    pub lsp_server_fee_base_sat: Option<u64>,
+    pub lsp_server_fee_ppm: Option<u64>,
+}
+
+impl LampoConfFile {
+    pub fn load(path: &str) -> Result<Self, anyhow::Error> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("impossible read `{path}`: {err}"))?;
+        let file = toml::from_str::<Self>(&content)
+            .map_err(|err| anyhow::anyhow!("invalid configuration file `{path}`: {err}"))?;
+        Ok(file)
+    }
+
+    /// Override the values of `conf` with the one specified inside the file.
+    pub fn apply(self, conf: &mut LampoConf) -> Result<(), anyhow::Error> {
+        if let Some(network) = self.network {
+            conf.network = parse_network(&network)?;
+        }
+        conf.signet_challenge = self.signet_challenge.or(conf.signet_challenge.take());
+        conf.port = self.port.unwrap_or(conf.port);
+        conf.alias = self.alias.or(conf.alias.take());
+        conf.announce_addr = self.announce_addr.or(conf.announce_addr.take());
+        conf.public_ip_interval = self.public_ip_interval.or(conf.public_ip_interval);
+        conf.public_ip_url = self.public_ip_url.unwrap_or(conf.public_ip_url.clone());
+        conf.exchange_rate_provider = self
+            .exchange_rate_provider
+            .or(conf.exchange_rate_provider.take());
+        conf.invoice_rate_limit = self.invoice_rate_limit.or(conf.invoice_rate_limit);
+        conf.min_invoice_amount_msat = self
+            .min_invoice_amount_msat
+            .or(conf.min_invoice_amount_msat);
+        conf.phantom_seed = self.phantom_seed.or(conf.phantom_seed.take());
+        conf.lazy_graph = self.lazy_graph.unwrap_or(conf.lazy_graph);
+        conf.compact_graph = self.compact_graph.unwrap_or(conf.compact_graph);
+        conf.blinded_path_hops = self.blinded_path_hops.unwrap_or(conf.blinded_path_hops);
+        conf.accept_intercept_htlcs = self
+            .accept_intercept_htlcs
+            .unwrap_or(conf.accept_intercept_htlcs);
+        conf.watchdog_path = self.watchdog_path.or(conf.watchdog_path.take());
+        conf.allow_stale_state = self.allow_stale_state.unwrap_or(conf.allow_stale_state);
+        conf.rgs_server = self.rgs_server.or(conf.rgs_server.take());
+        conf.rgs_url = self.rgs_url.or(conf.rgs_url.take());
+        conf.lsp = self.lsp.or(conf.lsp.take());
+        conf.lsp_token = self.lsp_token.or(conf.lsp_token.take());
+        conf.lsp_server = self.lsp_server.unwrap_or(conf.lsp_server);
+        conf.min_fee_rate = self
+            .min_fee_rate
+            .map(|fee_rate| fee_rate * 250)
+            .or(conf.min_fee_rate.take());
+        conf.fee_source = self.fee_source.or(conf.fee_source.take());
+        if let Some(strategy) = self.coin_selection {
+            conf.coin_selection = CoinSelection::from_str(&strategy)?;
+        }
+
+        conf.node = self.backend.kind.unwrap_or(conf.node.clone());
+        conf.core_url = self.backend.core_url.or(conf.core_url.take());
+        conf.core_user = self.backend.core_user.or(conf.core_user.take());
+        conf.core_pass = self.backend.core_pass.or(conf.core_pass.take());
+        if let Some(peers) = self.backend.cbf_peers {
+            conf.cbf_peers = peers;
+        }
+        if let Some(fallbacks) = self.backend.fallback {
+            conf.backend_fallbacks = fallbacks;
+        }
+        conf.backend_max_lag = self.backend.max_lag.unwrap_or(conf.backend_max_lag);
+
+        #[cfg(debug_assertions)]
+        {
+            conf.private_key = self.wallet.dev_private_key.or(conf.private_key.take());
+            conf.channels_keys = self
+                .wallet
+                .dev_force_channel_secrets
+                .or(conf.channels_keys.take());
+        }
+
+        conf.rpc_socket = self.rpc.socket.or(conf.rpc_socket.take());
+
+        conf.log_level = self.log.level.unwrap_or(conf.log_level.clone());
+        conf.log_file = self.log.file.or(conf.log_file.take());
+
+        conf.probe_interval = self.policy.probe_interval.or(conf.probe_interval);
+        conf.probe_amount_msat = self
+            .policy
+            .probe_amount_msat
+            .unwrap_or(conf.probe_amount_msat);
+        conf.probe_max_fee_msat = self
+            .policy
+            .probe_max_fee_msat
+            .unwrap_or(conf.probe_max_fee_msat);
+        if let Some(destinations) = self.policy.probe_destinations {
+            conf.probe_destinations = destinations;
+        }
+        conf.rebalance_interval = self.policy.rebalance_interval.or(conf.rebalance_interval);
+        conf.rebalance_low_pct = self
+            .policy
+            .rebalance_low_pct
+            .unwrap_or(conf.rebalance_low_pct);
+        conf.rebalance_high_pct = self
+            .policy
+            .rebalance_high_pct
+            .unwrap_or(conf.rebalance_high_pct);
+        conf.rebalance_max_fee_ppm = self
+            .policy
+            .rebalance_max_fee_ppm
+            .unwrap_or(conf.rebalance_max_fee_ppm);
+        conf.payment_queue_concurrency = self
+            .policy
+            .payment_queue_concurrency
+            .unwrap_or(conf.payment_queue_concurrency);
+        conf.payment_queue_max_attempts = self
+            .policy
+            .payment_queue_max_attempts
+            .unwrap_or(conf.payment_queue_max_attempts);
+        conf.payment_queue_retry_delay = self
+            .policy
+            .payment_queue_retry_delay
+            .unwrap_or(conf.payment_queue_retry_delay);
+        conf.payment_max_parts = self.policy.payment_max_parts.or(conf.payment_max_parts);
+        conf.payment_part_timeout = self
+            .policy
+            .payment_part_timeout
+            .or(conf.payment_part_timeout);
+        conf.channel_types = self.policy.channel_types.or(conf.channel_types.take());
+        if let Some(peers) = self.policy.peer_channel_types {
+            conf.peer_channel_types = peers;
+        }
+        conf.inbound_channel_min_sat = self
+            .policy
+            .inbound_channel_min_sat
+            .or(conf.inbound_channel_min_sat);
+        conf.inbound_channel_max_sat = self
+            .policy
+            .inbound_channel_max_sat
+            .or(conf.inbound_channel_max_sat);
+        conf.max_channels_per_peer = self
+            .policy
+            .max_channels_per_peer
+            .or(conf.max_channels_per_peer);
+        if let Some(nodes) = self.policy.inbound_channel_allow {
+            conf.inbound_channel_allow = nodes;
+        }
+        if let Some(nodes) = self.policy.inbound_channel_deny {
+            conf.inbound_channel_deny = nodes;
+        }
+        conf.lsp_server_min_channel_sat = self
+            .policy
+            .lsp_server_min_channel_sat
+            .unwrap_or(conf.lsp_server_min_channel_sat);
+        conf.lsp_server_max_channel_sat = self
+            .policy
+            .lsp_server_max_channel_sat
+            .unwrap_or(conf.lsp_server_max_channel_sat);
+        conf.lsp_server_max_expiry_blocks = self
+            .policy
+            .lsp_server_max_expiry_blocks
+            .unwrap_or(conf.lsp_server_max_expiry_blocks);
+        conf.lsp_server_fee_base_sat = self
+            .policy
+            .lsp_server_fee_base_sat
+            .unwrap_or(conf.lsp_server_fee_base_sat);
+        conf.lsp_server_fee_ppm = self
+            .policy
+            .lsp_server_fee_ppm
+            .unwrap_or(conf.lsp_server_fee_ppm);
+        Ok(())
+    }
+}
+
+/// Build the file from the effective configuration, this is what
+/// `configdump` shows to the user.
+impl From<&LampoConf> for LampoConfFile {
+    fn from(conf: &LampoConf) -> Self {
+        Self {
+            network: Some(conf.network.to_string()),
+            signet_challenge: conf.signet_challenge.clone(),
+            port: Some(conf.port),
+            alias: conf.alias.clone(),
+            announce_addr: conf.announce_addr.clone(),
+            public_ip_interval: conf.public_ip_interval,
+            public_ip_url: Some(conf.public_ip_url.clone()),
+            exchange_rate_provider: conf.exchange_rate_provider.clone(),
+            invoice_rate_limit: conf.invoice_rate_limit,
+            min_invoice_amount_msat: conf.min_invoice_amount_msat,
+            // never leak the seed
+            phantom_seed: conf.phantom_seed.as_ref().map(|_| "****".to_owned()),
+            lazy_graph: Some(conf.lazy_graph),
+            compact_graph: Some(conf.compact_graph),
+            blinded_path_hops: Some(conf.blinded_path_hops),
+            accept_intercept_htlcs: Some(conf.accept_intercept_htlcs),
+            watchdog_path: conf.watchdog_path.clone(),
+            allow_stale_state: Some(conf.allow_stale_state),
+            rgs_server: conf.rgs_server.clone(),
+            rgs_url: conf.rgs_url.clone(),
+            lsp: conf.lsp.clone(),
+            // never leak the token
+            lsp_token: conf.lsp_token.as_ref().map(|_| "****".to_owned()),
+            lsp_server: Some(conf.lsp_server),
+            min_fee_rate: conf.min_fee_rate.map(|fee_rate| fee_rate / 250),
+            fee_source: conf.fee_source.clone(),
+            coin_selection: Some(conf.coin_selection.to_string()),
+            backend: BackendSection {
+                kind: Some(conf.node.clone()),
+                core_url: conf.core_url.clone(),
+                core_user: conf.core_user.clone(),
+                // never leak the password
+                core_pass: conf.core_pass.as_ref().map(|_| "****".to_owned()),
+                cbf_peers: Some(conf.cbf_peers.clone()),
+                fallback: Some(conf.backend_fallbacks.clone()),
+                max_lag: Some(conf.backend_max_lag),
+            },
+            wallet: WalletSection::default(),
+            rpc: RpcSection {
+                socket: Some(conf.rpc_socket()),
+            },
+            log: LogSection {
+                level: Some(conf.log_level.clone()),
+                file: conf.log_file.clone(),
+            },
+            policy: PolicySection {
+                probe_interval: conf.probe_interval,
+                probe_amount_msat: Some(conf.probe_amount_msat),
+                probe_max_fee_msat: Some(conf.probe_max_fee_msat),
+                probe_destinations: Some(conf.probe_destinations.clone()),
+                rebalance_interval: conf.rebalance_interval,
+                rebalance_low_pct: Some(conf.rebalance_low_pct),
+                rebalance_high_pct: Some(conf.rebalance_high_pct),
+                rebalance_max_fee_ppm: Some(conf.rebalance_max_fee_ppm),
+                payment_queue_concurrency: Some(conf.payment_queue_concurrency),
+                payment_queue_max_attempts: Some(conf.payment_queue_max_attempts),
+                payment_queue_retry_delay: Some(conf.payment_queue_retry_delay),
+                payment_max_parts: conf.payment_max_parts,
+                payment_part_timeout: conf.payment_part_timeout,
+                channel_types: conf.channel_types.clone(),
+                peer_channel_types: Some(conf.peer_channel_types.clone()),
+                inbound_channel_min_sat: conf.inbound_channel_min_sat,
+                inbound_channel_max_sat: conf.inbound_channel_max_sat,
+                max_channels_per_peer: conf.max_channels_per_peer,
+                inbound_channel_allow: Some(conf.inbound_channel_allow.clone()),
+                inbound_channel_deny: Some(conf.inbound_channel_deny.clone()),
+                lsp_server_min_channel_sat: Some(conf.lsp_server_min_channel_sat),
+                lsp_server_max_channel_sat: Some(conf.lsp_server_max_channel_sat),
+                lsp_server_max_expiry_blocks: Some(conf.lsp_server_max_expiry_blocks),
+                lsp_server_fee_base_sat: Some(conf.lsp_server_fee_base_sat),
+                lsp_server_fee_ppm: Some(conf.lsp_server_fee_ppm),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bitcoin::Network;
+    use crate::coin_selection::CoinSelection;
+    use crate::conf::LampoConf;
+
+    use super::LampoConfFile;
+
+    /// A configuration with the data dir of `network` inside
+    /// a temporary directory.
+    fn conf(network: Network) -> LampoConf {
+        let root = std::env::temp_dir().join(format!("lampo-conf-{}", std::process::id()));
+        std::fs::create_dir_all(root.join(network.to_string())).unwrap();
+        LampoConf {
+            root_path: root.to_str().unwrap().to_owned(),
+            network,
+            ..LampoConf::default()
+        }
+    }
+
+    #[test]
+    fn example_file() {
+        let file =
+            toml::from_str::<LampoConfFile>(include_str!("../../../lampo.example.toml")).unwrap();
+        let mut conf = conf(Network::Signet);
+        file.apply(&mut conf).unwrap();
+        assert_eq!(conf.network, Network::Signet);
+        assert_eq!(conf.port, 39736);
+        assert_eq!(conf.node, "core");
+        assert_eq!(conf.core_url.as_deref(), Some("http://127.0.0.1:38332"));
+        assert!(conf.validate().is_ok(), "{:?}", conf.validate().err());
+    }
+
+    #[test]
+    fn apply_on_top_of_the_conf() {
+        let file = toml::from_str::<LampoConfFile>(
+            r#"
+            alias = "lampo"
+            min-fee-rate = 2
+            coin-selection = "avoid-dust"
+
+            [policy]
+            rebalance-low-pct = 10
+            "#,
+        )
+        .unwrap();
+        let mut conf = conf(Network::Regtest);
+        conf.port = 9735;
+        file.apply(&mut conf).unwrap();
+        // the values that are not in the file are kept
+        assert_eq!(conf.network, Network::Regtest);
+        assert_eq!(conf.port, 9735);
+        assert_eq!(conf.alias.as_deref(), Some("lampo"));
+        assert_eq!(conf.min_fee_rate, Some(500));
+        assert_eq!(conf.coin_selection, CoinSelection::AvoidDust);
+        assert_eq!(conf.rebalance_low_pct, 10);
+        assert_eq!(conf.rebalance_high_pct, 80);
+    }
+
+    #[test]
+    fn invalid_file() {
+        assert!(toml::from_str::<LampoConfFile>("unknown-option = 1").is_err());
+        assert!(toml::from_str::<LampoConfFile>("[backend]\nport = 1").is_err());
+        assert!(toml::from_str::<LampoConfFile>("port = \"9735\"").is_err());
+
+        let file = toml::from_str::<LampoConfFile>("coin-selection = \"random\"").unwrap();
+        assert!(file.apply(&mut conf(Network::Regtest)).is_err());
+        let file = toml::from_str::<LampoConfFile>("network = \"testnet4\"").unwrap();
+        assert!(file.apply(&mut conf(Network::Regtest)).is_err());
+    }
+
+    #[test]
+    fn validate_reports_all_the_errors() {
+        let file = toml::from_str::<LampoConfFile>(
+            r#"
+            port = 0
+
+            [backend]
+            kind = "core"
+            core-url = "https://127.0.0.1:18443"
+
+            [log]
+            level = "loud"
+            "#,
+        )
+        .unwrap();
+        let mut conf = conf(Network::Regtest);
+        file.apply(&mut conf).unwrap();
+        let err = conf.validate().unwrap_err().to_string();
+        for field in [
+            "`port`",
+            "`core-url`",
+            "`core-user`",
+            "`core-pass`",
+            "`log-level`",
+        ] {
+            assert!(err.contains(field), "{field} not in {err}");
+        }
+        // the backend options are ignored
+        let err = conf.validate_without_backend().unwrap_err().to_string();
+        assert!(!err.contains("`core-user`"), "{err}");
+    }
+
+    #[test]
+    fn payment_queue_concurrency() {
+        let file = toml::from_str::<LampoConfFile>(
+            r#"
+            [policy]
+            payment-queue-concurrency = 0
+            "#,
+        )
+        .unwrap();
+        let mut conf = conf(Network::Regtest);
+        file.apply(&mut conf).unwrap();
+        let err = conf.validate_without_backend().unwrap_err().to_string();
+        assert!(err.contains("`payment-queue-concurrency`"), "{err}");
+    }
+
+    #[test]
+    fn cbf_requires_fee_source() {
+        let mut conf = conf(Network::Signet);
+        conf.node = "cbf".to_owned();
+        let err = conf.validate().unwrap_err().to_string();
+        assert!(err.contains("`fee-source`"), "{err}");
+
+        conf.fee_source = Some("https://mempool.space/signet/api".to_owned());
+        assert!(conf.validate().is_ok(), "{:?}", conf.validate().err());
+
+        // the regtest has no fees to estimate
+        let mut regtest = conf(Network::Regtest);
+        regtest.node = "cbf".to_owned();
+        regtest.cbf_peers = vec!["127.0.0.1:18444".to_owned()];
+        assert!(regtest.validate().is_ok(), "{:?}", regtest.validate().err());
+    }
+
+    #[test]
+    fn dump_roundtrip() {
+        let mut conf = conf(Network::Regtest);
+        conf.node = "core".to_owned();
+        conf.core_pass = Some("secret".to_owned());
+        let dump = toml::to_string(&LampoConfFile::from(&conf)).unwrap();
+        assert!(!dump.contains("secret"), "{dump}");
+
+        let mut loaded = LampoConf::default();
+        toml::from_str::<LampoConfFile>(&dump)
+            .unwrap()
+            .apply(&mut loaded)
+            .unwrap();
+        assert_eq!(loaded.network, Network::Regtest);
+        assert_eq!(loaded.port, conf.port);
+        assert_eq!(loaded.node, "core");
+        assert_eq!(loaded.coin_selection, conf.coin_selection);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::bitcoin::{OutPoint, Transaction};
 use crate::ldk::ln::features::ChannelTypeFeatures;
-use crate::model::response::{PaymentHop, PaymentState};
+use crate::model::response::{
+    InterceptedHtlc, InvoiceStatus, PaymentFailure, PaymentHop, PaymentState,
+};
 use crate::types::{ChannelId, ChannelState, NodeId};
 
 #[derive(Clone, Debug)]
@@ -33,6 +35,58 @@ pub enum LightningEvent {
         state: PaymentState,
         payment_hash: Option<String>,
         path: Vec<PaymentHop>,
+        /// Why the payment failed, `None` for a successful payment.
+        reason: Option<String>,
+    },
+    /// A part of a payment reached the destination or failed,
+    /// the failed parts are retried by ldk.
+    PaymentPartEvent {
+        state: PaymentState,
+        payment_hash: String,
+        path: Vec<PaymentHop>,
+        failure: Option<PaymentFailure>,
+    },
+    /// A payment was claimed by the node, `label` is the label
+    /// of the invoice paid, if any.
+    PaymentReceived {
+        payment_hash: String,
+        amount_msat: u64,
+        label: Option<String>,
+    },
+    /// The status of an invoice generated by the node changed.
+    InvoiceEvent {
+        payment_hash: String,
+        label: Option<String>,
+        status: InvoiceStatus,
+    },
+    /// A recurring payment was not sent, or the schedule was
+    /// stopped because the budget is exhausted.
+    ScheduledPaymentFailed {
+        id: String,
+        reason: String,
+    },
+    /// A payment of the queue failed all the attempts.
+    QueuedPaymentFailed {
+        id: String,
+        reason: String,
+    },
+    /// An HTLC for an intercept scid is waiting to be
+    /// forwarded or failed.
+    HtlcIntercepted {
+        htlc: InterceptedHtlc,
+    },
+    ProbeEvent {
+        state: PaymentState,
+        payment_hash: String,
+        /// The channel that was not able to forward the probe.
+        short_channel_id: Option<u64>,
+        path: Vec<PaymentHop>,
+    },
+    /// A custom onion message was received, the sender
+    /// is unknown. `data` is hex encoded.
+    OnionMessage {
+        tlv_type: u64,
+        data: String,
     },
     ChannelEvent {
         state: ChannelState,
@@ -3,7 +3,7 @@ use std::fmt::Debug;
 
 use crate::bitcoin::absolute::Height;
 use crate::bitcoin::block::Header;
-use crate::bitcoin::{Block, Transaction, Txid};
+use crate::bitcoin::{Block, BlockHash, Transaction, Txid};
 
 #[derive(Clone)]
 pub enum OnChainEvent {
@@ -14,6 +14,10 @@ pub enum OnChainEvent {
     ConfirmedTransaction((Transaction, u32, Header, Height)),
     DiscardedTransaction(Txid),
     UnconfirmedTransaction(Txid),
+    /// A block removed from the best chain by a reorg.
+    BlockDisconnected((BlockHash, Height)),
+    /// The chain backend is changed, the name of the new one.
+    BackendSwitched(String),
 }
 
 impl Debug for OnChainEvent {
@@ -31,6 +35,10 @@ impl Debug for OnChainEvent {
             Self::NewBlock(block) => write!(f, "NewBlock({})", block.block_hash()),
             Self::SendRawTransaction(tx) => write!(f, "SendRawTransaction({})", tx.txid()),
             Self::UnconfirmedTransaction(tx) => write!(f, "UnconfirmedTransaction({})", tx),
+            Self::BlockDisconnected((hash, height)) => {
+                write!(f, "BlockDisconnected({hash}, {height})")
+            }
+            Self::BackendSwitched(backend) => write!(f, "BackendSwitched({backend})"),
             _ => write!(f, "Debug fmt not unsupported"),
         }
     }
@@ -1,9 +1,11 @@
-use std::{sync::Arc, time::SystemTime};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
+use bitcoin::hashes::{sha256, Hash, HashEngine};
 use bitcoin::secp256k1::{Secp256k1, SecretKey};
 use lightning::sign::{InMemorySigner, NodeSigner, OutputSpender, SignerProvider};
 
-use crate::ldk::sign::{EntropySource, KeysManager};
+use crate::ldk::sign::{EntropySource, KeysManager, PhantomKeysManager};
 
 /// Lampo keys implementations
 pub struct LampoKeys {
@@ -50,6 +52,16 @@ impl LampoKeys {
         }
     }
 
+    /// Enable the phantom payments, all the nodes of the cluster
+    /// must use the same `cross_node_seed`.
+    pub fn with_phantom_seed(mut self, cross_node_seed: [u8; 32]) -> Self {
+        // SAFETY: the keys manager is not shared until the keys are built.
+        let manager =
+            Arc::get_mut(&mut self.keys_manager).expect("the keys manager is already shared");
+        manager.set_phantom_seed(&cross_node_seed);
+        self
+    }
+
     pub fn inner(&self) -> Arc<LampoKeysManager> {
         self.keys_manager.clone()
     }
@@ -57,6 +69,15 @@ impl LampoKeys {
 
 pub struct LampoKeysManager {
     pub(crate) inner: KeysManager,
+    /// Used to build the phantom keys manager.
+    seed: [u8; 32],
+    starting_time: (u64, u32),
+    /// Keys shared with the other nodes of the cluster, when
+    /// it is set the node is able to claim the phantom payments.
+    phantom: Option<PhantomKeysManager>,
+    /// The channel keys ids generated since the start, with the value
+    /// of the channel, the static channel backup stores them.
+    channel_keys_ids: Mutex<Vec<([u8; 32], u64)>>,
 
     funding_key: Option<SecretKey>,
     revocation_base_secret: Option<SecretKey>,
@@ -71,6 +92,10 @@ impl LampoKeysManager {
         let inner = KeysManager::new(seed, starting_time_secs, starting_time_nanos);
         Self {
             inner,
+            seed: *seed,
+            starting_time: (starting_time_secs, starting_time_nanos),
+            phantom: None,
+            channel_keys_ids: Mutex::new(Vec::new()),
             funding_key: None,
             revocation_base_secret: None,
             payment_base_secret: None,
@@ -80,6 +105,38 @@ impl LampoKeysManager {
         }
     }
 
+    pub fn set_phantom_seed(&mut self, cross_node_seed: &[u8; 32]) {
+        let (secs, nanos) = self.starting_time;
+        self.phantom = Some(PhantomKeysManager::new(
+            &self.seed,
+            secs,
+            nanos,
+            cross_node_seed,
+        ));
+    }
+
+    pub fn is_phantom(&self) -> bool {
+        self.phantom.is_some()
+    }
+
+    /// Key used to encrypt the static channel backup, it is derived
+    /// from the seed so a node restored from the seed can read it.
+    pub fn backup_key(&self) -> [u8; 32] {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&self.seed);
+        engine.input(b"lampo/static-channel-backup");
+        sha256::Hash::from_engine(engine).to_byte_array()
+    }
+
+    /// Take the channel keys ids generated since the last call, with
+    /// the value of the channel.
+    ///
+    /// With the keys id we derive the key of our balance inside the
+    /// commitment of the peer, so it must survive the channel monitor.
+    pub fn take_channel_keys_ids(&self) -> Vec<([u8; 32], u64)> {
+        std::mem::take(&mut *self.channel_keys_ids.lock().unwrap())
+    }
+
     // FIXME: put this under a debug a feature flag like `unsafe_channel_keys`
     #[cfg(debug_assertions)]
     pub fn set_channels_keys(
@@ -116,18 +173,28 @@ impl NodeSigner for LampoKeysManager {
         other_key: &bitcoin::secp256k1::PublicKey,
         tweak: Option<&bitcoin::secp256k1::Scalar>,
     ) -> Result<bitcoin::secp256k1::ecdh::SharedSecret, ()> {
-        self.inner.ecdh(recipient, other_key, tweak)
+        match &self.phantom {
+            Some(phantom) => phantom.ecdh(recipient, other_key, tweak),
+            None => self.inner.ecdh(recipient, other_key, tweak),
+        }
     }
 
     fn get_inbound_payment_key_material(&self) -> lightning::sign::KeyMaterial {
-        self.inner.get_inbound_payment_key_material()
+        // the phantom nodes must share the inbound payment key
+        match &self.phantom {
+            Some(phantom) => phantom.get_inbound_payment_key_material(),
+            None => self.inner.get_inbound_payment_key_material(),
+        }
     }
 
     fn get_node_id(
         &self,
         recipient: lightning::sign::Recipient,
     ) -> Result<bitcoin::secp256k1::PublicKey, ()> {
-        self.inner.get_node_id(recipient)
+        match &self.phantom {
+            Some(phantom) => phantom.get_node_id(recipient),
+            None => self.inner.get_node_id(recipient),
+        }
     }
 
     fn sign_bolt12_invoice(
@@ -157,7 +224,10 @@ impl NodeSigner for LampoKeysManager {
         invoice_data: &[bitcoin::bech32::u5],
         recipient: lightning::sign::Recipient,
     ) -> Result<bitcoin::secp256k1::ecdsa::RecoverableSignature, ()> {
-        self.inner.sign_invoice(hrp_bytes, invoice_data, recipient)
+        match &self.phantom {
+            Some(phantom) => phantom.sign_invoice(hrp_bytes, invoice_data, recipient),
+            None => self.inner.sign_invoice(hrp_bytes, invoice_data, recipient),
+        }
     }
 }
 
@@ -220,8 +290,14 @@ impl SignerProvider for LampoKeysManager {
         channel_value_satoshis: u64,
         user_channel_id: u128,
     ) -> [u8; 32] {
-        self.inner
-            .generate_channel_keys_id(inbound, channel_value_satoshis, user_channel_id)
+        let keys_id =
+            self.inner
+                .generate_channel_keys_id(inbound, channel_value_satoshis, user_channel_id);
+        self.channel_keys_ids
+            .lock()
+            .unwrap()
+            .push((keys_id, channel_value_satoshis));
+        keys_id
     }
 
     fn get_destination_script(&self, channel_keys_id: [u8; 32]) -> Result<bitcoin::ScriptBuf, ()> {
@@ -1,4 +1,6 @@
 pub mod backend;
+pub mod coin_selection;
+pub mod commands;
 pub mod conf;
 pub mod event;
 pub mod handler;
@@ -15,6 +17,7 @@ pub mod ldk {
     pub use lightning_invoice as invoice;
     pub use lightning_net_tokio as net;
     pub use lightning_persister as persister;
+    pub use lightning_rapid_gossip_sync as rapid_gossip_sync;
 }
 
 pub mod error {
@@ -81,3 +81,12 @@ pub fn init(level: &str, file: Option<PathBuf>) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Initialize the global logger with a custom implementation,
+/// e.g: when lampo is embedded inside another application.
+pub fn init_with(logger: Box<dyn Log>, level: &str) -> anyhow::Result<()> {
+    let level = Level::from_str(level).map_err(|err| anyhow::anyhow!("{err}"))?;
+    log::set_boxed_logger(logger).map_err(|err| anyhow::anyhow!("{err}"))?;
+    log::set_max_level(level.to_level_filter());
+    Ok(())
+}
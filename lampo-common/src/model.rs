@@ -1,37 +1,73 @@
+mod backup;
+mod channel_config;
 mod close_channel;
 mod connect;
+mod forward;
 mod getinfo;
+mod intercept;
 mod invoice;
 mod keysend;
+mod lsp;
 mod network;
 mod new_addr;
 mod on_chain;
+mod onion_message;
 mod open_channel;
+mod payment;
+mod probe;
+mod queue;
+mod rebalance;
+mod schedule;
+mod stats;
+mod transaction;
+mod watchdog;
 
 pub use connect::Connect;
 pub use getinfo::GetInfo;
 
 pub mod request {
+    pub use crate::model::channel_config::request::*;
     pub use crate::model::close_channel::request::*;
     pub use crate::model::connect::Connect;
+    pub use crate::model::forward::request::*;
     pub use crate::model::getinfo::*;
+    pub use crate::model::intercept::request::*;
     pub use crate::model::invoice::request::*;
     pub use crate::model::keysend::request::*;
+    pub use crate::model::lsp::request::*;
     pub use crate::model::network::request::*;
     pub use crate::model::new_addr::request::*;
-    #[allow(unused_imports)]
     pub use crate::model::on_chain::request::*;
+    pub use crate::model::onion_message::request::*;
     pub use crate::model::open_channel::request::*;
+    pub use crate::model::payment::request::*;
+    pub use crate::model::probe::request::*;
+    pub use crate::model::queue::request::*;
+    pub use crate::model::schedule::request::*;
 }
 
 pub mod response {
+    pub use crate::model::backup::response::*;
+    pub use crate::model::channel_config::response::*;
     pub use crate::model::close_channel::response::*;
     pub use crate::model::connect::Connect;
+    pub use crate::model::forward::response::*;
     pub use crate::model::getinfo::*;
+    pub use crate::model::intercept::response::*;
     pub use crate::model::invoice::response::*;
     pub use crate::model::keysend::response::*;
+    pub use crate::model::lsp::response::*;
     pub use crate::model::network::response::*;
     pub use crate::model::new_addr::response::*;
     pub use crate::model::on_chain::response::*;
+    pub use crate::model::onion_message::response::*;
     pub use crate::model::open_channel::response::*;
+    pub use crate::model::payment::response::*;
+    pub use crate::model::probe::response::*;
+    pub use crate::model::queue::response::*;
+    pub use crate::model::rebalance::response::*;
+    pub use crate::model::schedule::response::*;
+    pub use crate::model::stats::response::*;
+    pub use crate::model::transaction::response::*;
+    pub use crate::model::watchdog::response::*;
 }
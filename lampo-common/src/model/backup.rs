@@ -0,0 +1,48 @@
+//! Static channel backup model
+
+pub mod request {}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    /// The minimal data to ask the peer to close the channel
+    /// when the channel state is lost.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct ChannelBackup {
+        pub channel_id: String,
+        pub peer_id: String,
+        /// Addresses where we can reach the peer.
+        pub peer_addresses: Vec<String>,
+        pub funding_utxo: Option<String>,
+        pub amount: u64,
+    }
+
+    /// The keys id of a channel, it derives the key of our balance
+    /// inside the commitment of the peer.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct ChannelKeys {
+        /// Hex encoded.
+        pub channel_keys_id: String,
+        pub channel_value_satoshis: u64,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct StaticChannelBackup {
+        pub node_id: String,
+        pub network: String,
+        pub created_at: u64,
+        pub channels: Vec<ChannelBackup>,
+        /// The keys of all the channels opened by the node, the
+        /// keys of a channel are found by the channel value.
+        #[serde(default)]
+        pub channel_keys: Vec<ChannelKeys>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct ExportedChannelBackup {
+        pub path: String,
+        pub channels: usize,
+        /// Hex encoded encrypted backup.
+        pub backup: String,
+    }
+}
@@ -0,0 +1,35 @@
+//! Channel forwarding policy model
+
+pub mod request {
+    use serde::{Deserialize, Serialize};
+
+    /// Update the forwarding policy of a channel, only the
+    /// specified fields are changed.
+    ///
+    /// The htlc limits are negotiated when the channel is opened,
+    /// so ldk does not allow to change them later.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct SetChannel {
+        /// Channel id or short channel id.
+        pub channel_id: String,
+        pub fee_base_msat: Option<u32>,
+        pub fee_ppm: Option<u32>,
+        pub cltv_expiry_delta: Option<u16>,
+        /// Max amount of the dust HTLCs in flight.
+        pub max_dust_htlc_exposure_msat: Option<u64>,
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct ChannelConfig {
+        pub channel_id: String,
+        pub short_channel_id: Option<u64>,
+        pub peer_id: String,
+        pub fee_base_msat: u32,
+        pub fee_ppm: u32,
+        pub cltv_expiry_delta: u16,
+    }
+}
@@ -1,5 +1,4 @@
 pub mod request {
-    use std::num::ParseIntError;
     use std::str::FromStr;
 
     use bitcoin::secp256k1::PublicKey;
@@ -13,6 +12,8 @@ pub mod request {
         pub node_id: String,
         // Hex of the channel
         pub channel_id: Option<String>,
+        /// Report what the close would do without closing the channel.
+        pub simulate: Option<bool>,
     }
 
     impl CloseChannel {
@@ -27,22 +28,7 @@ pub mod request {
                 .channel_id
                 .as_ref()
                 .ok_or(error::anyhow!("`channel_id` not found"))?;
-            let result = self.decode_hex(id)?;
-            let mut result_array: [u8; 32] = [0; 32];
-            result_array.copy_from_slice(&result);
-            Ok(ChannelId::from_bytes(result_array))
-        }
-
-        /// This converts hex to bytes array.
-        /// Stolen from https://stackoverflow.com/a/52992629
-        /// It takes two values every in each iteration from the hex
-        /// then convert the formed hexdecimal digit to u8, collects it in a vector
-        /// and return it (redix = 16 for hexadecimal)
-        fn decode_hex(&self, s: &str) -> Result<Vec<u8>, ParseIntError> {
-            (0..s.len())
-                .step_by(2)
-                .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
-                .collect()
+            parse_channel_id(id)
         }
     }
 }
@@ -57,6 +43,36 @@ pub mod response {
         pub peer_id: String,
         pub funding_utxo: String,
     }
+
+    /// What we would get back on chain by closing the channel,
+    /// the closing fee is not included because it is negotiated
+    /// with the peer.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct CloseSimulation {
+        pub channel_id: String,
+        pub peer_id: String,
+        pub funding_utxo: Option<String>,
+        pub channel_value_sat: u64,
+        pub to_self_sat: u64,
+    }
+
+    /// A channel closed by broadcasting our latest commitment.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct ForceClosedChannel {
+        pub channel_id: String,
+        pub peer_id: String,
+        pub funding_utxo: Option<String>,
+        /// `None` when the broadcast of the commitment was not seen.
+        pub commitment_txid: Option<String>,
+        pub closed_at: u64,
+        /// Funds that are not yet swept back to our wallet.
+        pub claimable_sat: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct ForceClosedChannels {
+        pub channels: Vec<ForceClosedChannel>,
+    }
 }
 
 pub mod tests {
@@ -72,6 +88,7 @@ pub mod tests {
         let req = crate::model::request::CloseChannel {
             node_id: node_id.clone(),
             channel_id: channel_hex,
+            simulate: None,
         };
         let channel_bytes = [
             10, 68, 103, 117, 38, 172, 140, 96, 118, 22, 189, 145, 37, 141, 126, 93, 241, 216, 111,
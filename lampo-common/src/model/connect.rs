@@ -7,40 +7,50 @@ use super::request::OpenChannel;
 use crate::error;
 use crate::types::NodeId;
 
+/// Default port of the lightning protocol.
+pub const DEFAULT_PORT: u64 = 9735;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Connect {
+    /// The node id, `node_id@host:port`, a prefix of the node
+    /// id or the node alias. When the address is not specified
+    /// the one inside the node announcement is used.
     pub node_id: String,
-    pub addr: String,
-    pub port: u64,
+    pub addr: Option<String>,
+    pub port: Option<u64>,
 }
 
 impl Connect {
+    /// The node id part of the request, without the `@host:port`.
+    pub fn query(&self) -> &str {
+        self.node_id
+            .split_once('@')
+            .map(|(node_id, _)| node_id)
+            .unwrap_or(&self.node_id)
+    }
+
     pub fn node_id(&self) -> error::Result<NodeId> {
-        Ok(NodeId::from_str(&self.node_id)?)
+        Ok(NodeId::from_str(self.query())?)
     }
 
-    pub fn addr(&self) -> error::Result<SocketAddr> {
-        let addr = format!("{}:{}", self.addr, self.port);
-        let result = SocketAddr::from_str(&addr);
-        match result {
-            Ok(res) => Ok(res),
-            Err(e) => Err(e.into()),
-        }
+    /// The address specified by the user, if any.
+    pub fn addr(&self) -> error::Result<Option<SocketAddr>> {
+        let addr = match (self.node_id.split_once('@'), &self.addr) {
+            (Some((_, host)), _) if host.contains(':') => host.to_owned(),
+            (Some((_, host)), _) => format!("{host}:{DEFAULT_PORT}"),
+            (None, Some(addr)) => format!("{}:{}", addr, self.port.unwrap_or(DEFAULT_PORT)),
+            (None, None) => return Ok(None),
+        };
+        Ok(Some(SocketAddr::from_str(&addr)?))
     }
 }
 
-impl TryFrom<OpenChannel> for Connect {
-    type Error = error::Error;
-
-    fn try_from(value: OpenChannel) -> Result<Self, Self::Error> {
-        Ok(Connect {
+impl From<OpenChannel> for Connect {
+    fn from(value: OpenChannel) -> Self {
+        Connect {
             node_id: value.node_id,
-            addr: value
-                .addr
-                .ok_or(error::anyhow!("The `addr` must be specified"))?,
-            port: value
-                .port
-                .ok_or(error::anyhow!("The `port` must be specifed"))?,
-        })
+            addr: value.addr,
+            port: value.port,
+        }
     }
 }
@@ -0,0 +1,35 @@
+//! Forwarding history model
+
+pub mod request {
+    use serde::{Deserialize, Serialize};
+
+    /// List the forwards inside a time range, in unix seconds.
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct ListForwards {
+        pub since: Option<u64>,
+        pub until: Option<u64>,
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    /// HTLC forwarded by the node.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct Forward {
+        pub in_channel_id: Option<String>,
+        pub out_channel_id: Option<String>,
+        pub amount_in_msat: Option<u64>,
+        pub amount_out_msat: Option<u64>,
+        pub fee_msat: Option<u64>,
+        /// The HTLC was claimed on chain after a force close.
+        pub claimed_on_chain: bool,
+        pub forwarded_at: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Forwards {
+        pub forwards: Vec<Forward>,
+        pub total_fee_msat: u64,
+    }
+}
@@ -10,6 +10,10 @@ pub struct GetInfo {
     pub blockheight: u32,
     pub lampo_dir: String,
     pub address: Vec<NetworkInfo>,
+    /// True while the network graph is still loading, the
+    /// routing is limited until the graph is loaded.
+    #[serde(default)]
+    pub graph_warming: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -0,0 +1,43 @@
+//! HTLC interception model
+
+pub mod request {
+    use serde::{Deserialize, Serialize};
+
+    /// Forward an intercepted HTLC over a channel, usually
+    /// the channel just opened with the payee.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct ForwardIntercepted {
+        pub intercept_id: String,
+        /// Channel id or short channel id.
+        pub channel_id: String,
+        /// The amount to forward, the difference with the inbound
+        /// amount is the fee taken by the node.
+        pub amount_msat: u64,
+    }
+
+    /// Fail back an intercepted HTLC.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct FailIntercepted {
+        pub intercept_id: String,
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    /// HTLC held by the node because the next hop is a
+    /// short channel id generated with `interceptscid`.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct InterceptedHtlc {
+        pub intercept_id: String,
+        pub requested_next_hop_scid: u64,
+        pub payment_hash: String,
+        pub inbound_amount_msat: u64,
+        pub expected_outbound_amount_msat: u64,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct InterceptScid {
+        pub short_channel_id: u64,
+    }
+}
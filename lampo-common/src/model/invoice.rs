@@ -3,17 +3,100 @@
 pub mod request {
     use serde::{Deserialize, Serialize};
 
+    use crate::model::request::{FirstHops, HintOverrides, PayRouteHop};
+    use crate::model::response::InvoiceStatus;
+
     #[derive(Serialize, Deserialize, Debug)]
     pub struct GenerateInvoice {
+        pub amount_msat: Option<u64>,
+        /// Amount in fiat, e.g. `5 USD`, converted at creation time.
+        pub fiat_amount: Option<String>,
+        pub description: String,
+        pub expiring_in: Option<u32>,
+        /// Include the QR code of the invoice inside the response.
+        pub qr: Option<bool>,
+        /// Include a fresh wallet address as on chain fallback.
+        pub fallback: Option<bool>,
+    }
+
+    /// Generate many invoices with a single call.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct GenerateInvoices {
+        pub invoices: Vec<BatchInvoice>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct BatchInvoice {
+        /// Unique label of the invoice, used as description if the
+        /// description is not specified.
+        pub label: String,
+        pub amount_msat: Option<u64>,
+        pub description: Option<String>,
+        pub expiring_in: Option<u32>,
+    }
+
+    /// Generate an invoice that can be claimed by any node of the
+    /// phantom cluster, `hints` are the `phantomhints` of the other nodes.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct GeneratePhantomInvoice {
         pub amount_msat: Option<u64>,
         pub description: String,
         pub expiring_in: Option<u32>,
+        #[serde(default)]
+        pub hints: Vec<String>,
+    }
+
+    /// Generate an invoice for a `payment_hash` whose preimage is not
+    /// known by the node, the payment is held until `settleinvoice`
+    /// or `cancelinvoice` is called.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct GenerateHoldInvoice {
+        pub payment_hash: String,
+        pub amount_msat: Option<u64>,
+        pub description: String,
+        pub expiring_in: Option<u32>,
+        pub label: Option<String>,
+    }
+
+    /// Claim the payment held by a hold invoice.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct SettleInvoice {
+        pub preimage: String,
+    }
+
+    /// Cancel an unpaid invoice, identified by payment hash or label.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct CancelInvoice {
+        pub payment_hash: Option<String>,
+        pub label: Option<String>,
+    }
+
+    /// List the invoices generated by the node, from the oldest.
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct ListInvoices {
+        /// Only the invoices with a label that starts with `label`.
+        pub label: Option<String>,
+        pub status: Option<InvoiceStatus>,
+        /// Number of invoices to skip.
+        pub offset: Option<usize>,
+        /// Max number of invoices returned, all by default.
+        pub limit: Option<usize>,
+    }
+
+    /// Look up an invoice generated by the node, identified
+    /// by payment hash or label.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct LookupInvoice {
+        pub payment_hash: Option<String>,
+        pub label: Option<String>,
     }
 
     #[derive(Serialize, Deserialize, Debug)]
     pub struct GenerateOffer {
         pub amount_msat: Option<u64>,
         pub description: Option<String>,
+        /// Who is asking to be paid, e.g. the name of the shop.
+        pub issuer: Option<String>,
     }
 
     #[derive(Debug, Serialize, Deserialize)]
@@ -25,6 +108,44 @@ pub mod request {
     pub struct Pay {
         pub invoice_str: String,
         pub amount: Option<u64>,
+        /// Compute the route and the fees without sending the payment.
+        pub simulate: Option<bool>,
+        #[serde(flatten)]
+        pub first_hops: FirstHops,
+        #[serde(flatten)]
+        pub hints: HintOverrides,
+        /// Pay along this route instead of asking it to the router,
+        /// the first hop is one of our channels and the last hop
+        /// is the destination of the invoice.
+        pub route: Option<Vec<PayRouteHop>>,
+    }
+
+    /// Fetch the invoice of a bolt12 offer and pay it.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct PayOffer {
+        pub offer: String,
+        /// Required when the offer has no amount.
+        pub amount_msat: Option<u64>,
+        /// Note for the recipient, included inside the invoice request.
+        pub payer_note: Option<String>,
+        /// Seconds to wait the invoice, and then the outcome of the
+        /// payment, by default 60.
+        pub timeout: Option<u64>,
+    }
+
+    /// Ask to be paid back, the receiver of the refund
+    /// sends us an invoice that is paid by the node.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct GenerateRefund {
+        pub amount_msat: u64,
+        pub description: Option<String>,
+        /// Seconds before the refund expires, by default one hour.
+        pub expiry: Option<u64>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct ClaimRefund {
+        pub refund: String,
     }
 }
 
@@ -36,17 +157,92 @@ pub mod response {
     use serde::{Deserialize, Serialize};
 
     use crate::ldk;
+    use crate::utils::qr::QrCode;
 
     #[derive(Serialize, Deserialize, Debug)]
     pub struct Invoice {
         pub bolt11: String,
+        /// The exchange rate used when the invoice was created with a fiat amount.
+        pub fiat: Option<FiatRate>,
+        pub qr: Option<QrCode>,
+        pub fallback_address: Option<String>,
     }
 
+    /// Route hints of the node (hex encoded) used by the
+    /// other nodes of the cluster to build the phantom invoices.
     #[derive(Serialize, Deserialize, Debug)]
+    pub struct PhantomHints {
+        pub node_id: String,
+        pub hints: String,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+    pub enum InvoiceStatus {
+        Unpaid,
+        /// The payment of a hold invoice is waiting to be settled.
+        Accepted,
+        Paid,
+        Cancelled,
+        /// Not paid before the expiry, this is never stored.
+        Expired,
+    }
+
+    /// Invoice generated by the node.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct InvoiceRecord {
+        pub bolt11: String,
+        pub payment_hash: String,
+        /// `None` for the invoices stored by the older releases.
+        pub payment_secret: Option<String>,
+        pub label: Option<String>,
+        pub amount_msat: Option<u64>,
+        /// On chain fallback address included inside the invoice.
+        pub fallback_address: Option<String>,
+        /// The transaction that paid the fallback address.
+        pub fallback_txid: Option<String>,
+        /// Amount received, for the invoices without an amount
+        /// this is the amount chosen by the payer.
+        pub amount_received_msat: Option<u64>,
+        /// The payment is held until the invoice is settled or cancelled.
+        #[serde(default)]
+        pub hold: bool,
+        /// Block height before which the held payment must be settled.
+        pub claim_deadline: Option<u32>,
+        /// Max fee that the LSP can take from the payment
+        /// to open the JIT channel.
+        pub lsp_fee_msat: Option<u64>,
+        /// The exchange rate used when the invoice was created with a fiat amount.
+        pub fiat: Option<FiatRate>,
+        pub status: InvoiceStatus,
+        pub created_at: u64,
+        pub expires_at: u64,
+        pub settled_at: Option<u64>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Invoices {
+        pub invoices: Vec<InvoiceRecord>,
+    }
+
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct FiatRate {
+        pub currency: String,
+        pub amount: f64,
+        /// Price of one bitcoin in `currency`.
+        pub btc_price: f64,
+        pub amount_msat: u64,
+        pub provider: String,
+        pub timestamp: u64,
+    }
+
+    #[derive(Clone, Serialize, Deserialize, Debug)]
     pub struct Offer {
         pub bolt12: String,
         pub metadata: Option<String>,
         pub metadata_pubkey: Option<PublicKey>,
+        pub amount_msat: Option<u64>,
+        pub description: Option<String>,
+        pub issuer: Option<String>,
     }
 
     impl From<ldk::offers::offer::Offer> for Offer {
@@ -55,6 +251,40 @@ pub mod response {
                 bolt12: value.to_string(),
                 metadata: value.metadata().map(hex::encode),
                 metadata_pubkey: value.signing_pubkey(),
+                amount_msat: match value.amount() {
+                    Some(ldk::offers::offer::Amount::Bitcoin { amount_msats }) => {
+                        Some(amount_msats.clone())
+                    }
+                    _ => None,
+                },
+                description: value.description().map(|desc| desc.to_string()),
+                issuer: value.issuer().map(|issuer| issuer.to_string()),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Offers {
+        pub offers: Vec<Offer>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Refund {
+        pub bolt12: String,
+        pub amount_msat: u64,
+        /// The node that is paid back.
+        pub payer_id: PublicKey,
+        /// Unix time in seconds when the refund expires.
+        pub absolute_expiry: Option<u64>,
+    }
+
+    impl From<ldk::offers::refund::Refund> for Refund {
+        fn from(value: ldk::offers::refund::Refund) -> Self {
+            Self {
+                bolt12: value.to_string(),
+                amount_msat: value.amount_msats(),
+                payer_id: value.payer_id(),
+                absolute_expiry: value.absolute_expiry().map(|expiry| expiry.as_secs()),
             }
         }
     }
@@ -75,7 +305,140 @@ pub mod response {
         pub path: Vec<PaymentHop>,
         pub payment_hash: Option<String>,
         pub state: PaymentState,
-        // FIXME: missing payment preimage
+        /// The proof of payment, `None` if the payment failed.
+        pub payment_preimage: Option<String>,
+        /// Fee paid to the route, `None` if the payment failed.
+        pub fee_msat: Option<u64>,
+        /// The parts of a multi part payment, the failed parts
+        /// are retried by the node.
+        pub parts: Vec<PaymentPart>,
+        /// Why ldk gave up, e.g. `RetriesExhausted` or `RouteNotFound`.
+        pub failure_reason: Option<String>,
+        /// The failure of the last part that failed.
+        pub failure: Option<PaymentFailure>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct PaymentPart {
+        pub state: PaymentState,
+        /// Amount delivered to the destination by this part.
+        pub amount_msat: u64,
+        pub fee_msat: u64,
+        pub path: Vec<PaymentHop>,
+        pub failure: Option<PaymentFailure>,
+    }
+
+    impl PaymentPart {
+        pub fn new(
+            state: PaymentState,
+            path: Vec<PaymentHop>,
+            failure: Option<PaymentFailure>,
+        ) -> Self {
+            // the fee of the last hop is the amount delivered
+            let amount_msat = path.last().map(|hop| hop.hop_fee_msat).unwrap_or_default();
+            let fee_msat = path.iter().map(|hop| hop.hop_fee_msat).sum::<u64>() - amount_msat;
+            Self {
+                state,
+                amount_msat,
+                fee_msat,
+                path,
+                failure,
+            }
+        }
+    }
+
+    /// Why a part of a payment failed, decoded from the onion error.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct PaymentFailure {
+        pub message: String,
+        /// The channel that was not able to forward the payment.
+        pub short_channel_id: Option<u64>,
+        /// The node that reported the failure, `None` when it is our node.
+        pub failing_node: Option<String>,
+        /// Retrying does not help, e.g. the destination rejected the payment.
+        pub permanent: bool,
+    }
+
+    impl PaymentFailure {
+        pub fn new(
+            failure: &ldk::events::PathFailure,
+            path: &ldk::routing::router::Path,
+            short_channel_id: Option<u64>,
+            permanent: bool,
+        ) -> Self {
+            use ldk::events::PathFailure;
+            use ldk::routing::gossip::NetworkUpdate;
+
+            // the node at the start of the channel is the one that failed to forward
+            let forwarding_node = |short_channel_id: u64| {
+                let position = path
+                    .hops
+                    .iter()
+                    .position(|hop| hop.short_channel_id == short_channel_id)?;
+                position
+                    .checked_sub(1)
+                    .map(|prev| path.hops[prev].pubkey.to_string())
+            };
+            let (message, failing_node) = match failure {
+                PathFailure::InitialSend { err } => (format!("{err:?}"), None),
+                PathFailure::OnPath {
+                    network_update: Some(NetworkUpdate::ChannelUpdateMessage { .. }),
+                } => (
+                    "the channel policy changed, the next attempts use the new one".to_owned(),
+                    short_channel_id.and_then(forwarding_node),
+                ),
+                PathFailure::OnPath {
+                    network_update:
+                        Some(NetworkUpdate::ChannelFailure {
+                            short_channel_id,
+                            is_permanent,
+                        }),
+                } => (
+                    if *is_permanent {
+                        format!("the channel `{short_channel_id}` is closed")
+                    } else {
+                        format!("the channel `{short_channel_id}` can not forward the payment")
+                    },
+                    forwarding_node(*short_channel_id),
+                ),
+                PathFailure::OnPath {
+                    network_update: Some(NetworkUpdate::NodeFailure { node_id, .. }),
+                } => (
+                    format!("the node `{node_id}` can not forward the payment"),
+                    Some(node_id.to_string()),
+                ),
+                PathFailure::OnPath {
+                    network_update: None,
+                } if permanent => (
+                    "the destination rejected the payment".to_owned(),
+                    path.hops.last().map(|hop| hop.pubkey.to_string()),
+                ),
+                PathFailure::OnPath {
+                    network_update: None,
+                } => (
+                    "the failure can not be decoded".to_owned(),
+                    short_channel_id.and_then(forwarding_node),
+                ),
+            };
+            Self {
+                message,
+                short_channel_id,
+                failing_node,
+                permanent,
+            }
+        }
+    }
+
+    /// What the node would do to pay an invoice, nothing is sent.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct PaySimulation {
+        pub payment_hash: String,
+        pub amount_msat: u64,
+        pub fee_msat: u64,
+        pub paths: Vec<Vec<PaymentHop>>,
+        /// Sum of the outbound capacity of our usable channels.
+        pub outbound_capacity_msat: u64,
+        pub outbound_capacity_after_msat: u64,
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
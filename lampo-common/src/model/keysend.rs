@@ -4,10 +4,14 @@ pub mod request {
     use bitcoin::secp256k1::PublicKey;
     use serde::{Deserialize, Serialize};
 
+    use crate::model::request::FirstHops;
+
     #[derive(Serialize, Deserialize)]
     pub struct KeySend {
         pub destination: PublicKey,
         pub amount_msat: u64,
+        #[serde(flatten)]
+        pub first_hops: FirstHops,
     }
 }
 
@@ -0,0 +1,290 @@
+//! LSP model, the channels bought from a LSP with LSPS1
+//! and the JIT channels bought with LSPS2, or sold by the
+//! node when it is a LSP.
+//!
+//! The types that are sent to the LSP follow the LSPS
+//! specifications, so the amounts are strings.
+
+pub mod request {
+    use serde::{Deserialize, Serialize};
+
+    use super::response::OpeningFeeParams;
+
+    /// Generate an invoice that can be paid also without inbound
+    /// liquidity, the LSP opens a channel with us when it is paid.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct LspInvoice {
+        /// Amount paid by the payer, the opening fee is taken
+        /// by the LSP from this amount.
+        pub amount_msat: u64,
+        pub description: String,
+        pub expiring_in: Option<u32>,
+        pub label: Option<String>,
+    }
+
+    /// `lsps1.get_info` and `lsps2.get_info` request.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct LspsGetInfo {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub token: Option<String>,
+    }
+
+    /// `lsps2.buy` request.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Lsps2Buy {
+        pub opening_fee_params: OpeningFeeParams,
+        pub payment_size_msat: String,
+    }
+
+    /// Buy from the LSP a channel with `lsp_balance_sat` of
+    /// inbound liquidity, the order is paid with lightning.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct BuyInbound {
+        pub lsp_balance_sat: u64,
+        /// How long the LSP keeps the channel open, by
+        /// default the max allowed by the LSP.
+        pub channel_expiry_blocks: Option<u32>,
+        pub announce_channel: Option<bool>,
+        /// The order is not paid if the LSP asks more.
+        pub max_fee_sat: Option<u64>,
+    }
+
+    /// `lsps1.create_order` request.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct Lsps1CreateOrder {
+        pub lsp_balance_sat: String,
+        pub client_balance_sat: String,
+        pub required_channel_confirmations: u16,
+        pub funding_confirms_within_blocks: u16,
+        pub channel_expiry_blocks: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub token: Option<String>,
+        pub announce_channel: bool,
+    }
+
+    /// `lsps1.get_order` request.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Lsps1GetOrder {
+        pub order_id: String,
+    }
+}
+
+pub mod response {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{Deserialize, Serialize};
+
+    use super::request::Lsps1CreateOrder;
+
+    /// The LSPS format (ISO 8601) of a unix timestamp.
+    pub fn lsps_datetime(timestamp: u64) -> String {
+        DateTime::<Utc>::from(UNIX_EPOCH + Duration::from_secs(timestamp))
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+
+    /// Fees asked by the LSP to open a JIT channel, they are
+    /// sent back unchanged when the channel is bought.
+    #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+    pub struct OpeningFeeParams {
+        pub min_fee_msat: String,
+        pub proportional: u32,
+        pub valid_until: String,
+        pub min_lifetime: u32,
+        pub max_client_to_self_delay: u32,
+        pub min_payment_size_msat: String,
+        pub max_payment_size_msat: String,
+        pub promise: String,
+    }
+
+    impl OpeningFeeParams {
+        /// The opening fee for a payment of `payment_size_msat`, `None`
+        /// when the LSP does not accept the payment size.
+        pub fn fee_msat(&self, payment_size_msat: u64) -> Option<u64> {
+            let min_payment = self.min_payment_size_msat.parse::<u64>().ok()?;
+            let max_payment = self.max_payment_size_msat.parse::<u64>().ok()?;
+            if payment_size_msat < min_payment || payment_size_msat > max_payment {
+                return None;
+            }
+            let proportional =
+                (payment_size_msat as u128 * self.proportional as u128 + 999_999) / 1_000_000;
+            let proportional = u64::try_from(proportional).ok()?;
+            let fee = self.min_fee_msat.parse::<u64>().ok()?.max(proportional);
+            // the fee must leave something to forward
+            (fee < payment_size_msat).then_some(fee)
+        }
+    }
+
+    /// `lsps2.get_info` response.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct Lsps2Info {
+        pub opening_fee_params_menu: Vec<OpeningFeeParams>,
+    }
+
+    /// `lsps2.buy` response.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct Lsps2Channel {
+        /// The short channel id to use inside the route hint,
+        /// in the format `<block>x<tx>x<output>`.
+        pub jit_channel_scid: String,
+        pub lsp_cltv_expiry_delta: u32,
+        #[serde(default)]
+        pub client_trusts_lsp: bool,
+    }
+
+    impl Lsps2Channel {
+        pub fn short_channel_id(&self) -> crate::error::Result<u64> {
+            let parts = self
+                .jit_channel_scid
+                .split('x')
+                .map(|part| part.parse::<u64>())
+                .collect::<Result<Vec<_>, _>>()?;
+            let [block, tx, output] = parts[..] else {
+                crate::error::bail!("`{}` is not a short channel id", self.jit_channel_scid);
+            };
+            Ok(block << 40 | tx << 16 | output)
+        }
+    }
+
+    /// Channels that the LSP accepts to sell, from `lsps1.get_info`.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct Lsps1Options {
+        pub min_required_channel_confirmations: u16,
+        pub min_funding_confirms_within_blocks: u16,
+        pub supports_zero_channel_reserve: bool,
+        pub max_channel_expiry_blocks: u32,
+        pub min_initial_client_balance_sat: String,
+        pub max_initial_client_balance_sat: String,
+        pub min_initial_lsp_balance_sat: String,
+        pub max_initial_lsp_balance_sat: String,
+        pub min_channel_balance_sat: String,
+        pub max_channel_balance_sat: String,
+    }
+
+    /// `lsps1.get_info` response.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct Lsps1Info {
+        pub options: Lsps1Options,
+    }
+
+    #[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    pub enum OrderState {
+        Created,
+        Completed,
+        Failed,
+    }
+
+    #[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    pub enum OrderPaymentState {
+        ExpectPayment,
+        /// The payment is held by the LSP until the channel is open.
+        Hold,
+        Paid,
+        Refunded,
+    }
+
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct Lsps1Bolt11Payment {
+        pub state: OrderPaymentState,
+        pub expires_at: String,
+        pub fee_total_sat: String,
+        pub order_total_sat: String,
+        pub invoice: String,
+    }
+
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct Lsps1Payment {
+        pub bolt11: Option<Lsps1Bolt11Payment>,
+    }
+
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct Lsps1Channel {
+        pub funded_at: String,
+        pub funding_outpoint: String,
+        pub expires_at: String,
+    }
+
+    /// Order returned by `lsps1.create_order` and `lsps1.get_order`,
+    /// only the fields that we use are decoded.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct Lsps1Order {
+        pub order_id: String,
+        pub order_state: OrderState,
+        pub payment: Lsps1Payment,
+        pub channel: Option<Lsps1Channel>,
+    }
+
+    /// Channel bought from the LSP with `buyinbound`.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct LspOrder {
+        pub order_id: String,
+        pub lsp_id: String,
+        pub lsp_balance_sat: u64,
+        pub fee_sat: u64,
+        pub order_state: OrderState,
+        pub payment_state: Option<OrderPaymentState>,
+        pub payment_hash: Option<String>,
+        pub funding_outpoint: Option<String>,
+        /// When the LSP can close the channel.
+        pub channel_expires_at: Option<String>,
+        pub created_at: u64,
+    }
+
+    impl LspOrder {
+        /// Update the order with the last state known by the LSP.
+        pub fn update(&mut self, order: &Lsps1Order) {
+            self.order_state = order.order_state;
+            self.payment_state = order.payment.bolt11.as_ref().map(|payment| payment.state);
+            if let Some(channel) = &order.channel {
+                self.funding_outpoint = Some(channel.funding_outpoint.clone());
+                self.channel_expires_at = Some(channel.expires_at.clone());
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct LspOrders {
+        pub orders: Vec<LspOrder>,
+    }
+
+    /// LSPS1 order sold by the node, as it is sent to the client.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct Lsps1SoldOrder {
+        pub order_id: String,
+        #[serde(flatten)]
+        pub request: Lsps1CreateOrder,
+        pub created_at: String,
+        pub order_state: OrderState,
+        pub payment: Lsps1Payment,
+        pub channel: Option<Lsps1Channel>,
+    }
+
+    /// Channel sold by the node with `lsp-server`.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct LspSale {
+        pub client_id: String,
+        pub payment_hash: String,
+        /// Preimage of the hold invoice of the order, the payment
+        /// is claimed when the channel is open.
+        pub preimage: String,
+        pub order: Lsps1SoldOrder,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct LspInfo {
+        pub lsp_id: String,
+        pub opening_fee_params_menu: Vec<OpeningFeeParams>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct LspInvoice {
+        pub bolt11: String,
+        pub payment_hash: String,
+        pub amount_msat: u64,
+        /// Max fee taken by the LSP when the invoice is paid.
+        pub opening_fee_msat: u64,
+        pub jit_channel_scid: String,
+    }
+}
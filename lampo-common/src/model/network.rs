@@ -1,7 +1,31 @@
-pub mod request {}
+pub mod request {
+    use bitcoin::secp256k1::PublicKey;
+    use serde::{Deserialize, Serialize};
+
+    /// Export or import a snapshot of the network graph.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct GraphSnapshot {
+        /// Path of the snapshot, by default `<data-dir>/graph_snapshot`.
+        pub path: Option<String>,
+    }
+
+    /// List the nodes of the network graph, or only `node_id`.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct ListNodes {
+        pub node_id: Option<PublicKey>,
+    }
+
+    /// List the announced channels of the network graph, filtered
+    /// by short channel id or by one of the two nodes.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct ListGossipChannels {
+        pub short_channel_id: Option<u64>,
+        pub node_id: Option<PublicKey>,
+    }
+}
 
 pub mod response {
-    use lightning::routing::gossip::ChannelInfo;
+    use lightning::routing::gossip::{ChannelInfo, ChannelUpdateInfo, NodeId, NodeInfo};
     use serde::{Deserialize, Serialize};
 
     #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -9,6 +33,15 @@ pub mod response {
         pub channels: Vec<NetworkChannel>,
     }
 
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct GraphSnapshotInfo {
+        pub path: String,
+        pub size: u64,
+        /// Channels and nodes of the live graph, after the import.
+        pub channels: usize,
+        pub nodes: usize,
+    }
+
     #[derive(Clone, Serialize, Deserialize, Debug)]
     pub struct NetworkChannel {
         pub node_one: String,
@@ -23,4 +56,101 @@ pub mod response {
             }
         }
     }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct NetworkNodes {
+        pub nodes: Vec<NetworkNode>,
+    }
+
+    /// Node of the network graph, the alias and the addresses
+    /// are known only after its node announcement.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct NetworkNode {
+        pub node_id: String,
+        pub alias: Option<String>,
+        pub color: Option<String>,
+        pub addresses: Vec<String>,
+        pub last_update: Option<u32>,
+        pub channels: usize,
+    }
+
+    impl NetworkNode {
+        pub fn new(node_id: &NodeId, node: &NodeInfo) -> Self {
+            let announcement = node.announcement_info.as_ref();
+            Self {
+                node_id: node_id.to_string(),
+                alias: announcement.map(|info| info.alias.to_string()),
+                color: announcement.map(|info| {
+                    let [r, g, b] = info.rgb;
+                    format!("{r:02x}{g:02x}{b:02x}")
+                }),
+                addresses: announcement
+                    .map(|info| {
+                        info.addresses()
+                            .iter()
+                            .map(|addr| addr.to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                last_update: announcement.map(|info| info.last_update),
+                channels: node.channels.len(),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct GossipChannels {
+        pub channels: Vec<GossipChannel>,
+    }
+
+    /// Forwarding policy announced by one side of the channel.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct ChannelPolicy {
+        pub enabled: bool,
+        pub fee_base_msat: u32,
+        pub fee_proportional_millionths: u32,
+        pub cltv_expiry_delta: u16,
+        pub htlc_minimum_msat: u64,
+        pub htlc_maximum_msat: u64,
+        pub last_update: u32,
+    }
+
+    impl From<&ChannelUpdateInfo> for ChannelPolicy {
+        fn from(value: &ChannelUpdateInfo) -> Self {
+            Self {
+                enabled: value.enabled,
+                fee_base_msat: value.fees.base_msat,
+                fee_proportional_millionths: value.fees.proportional_millionths,
+                cltv_expiry_delta: value.cltv_expiry_delta,
+                htlc_minimum_msat: value.htlc_minimum_msat,
+                htlc_maximum_msat: value.htlc_maximum_msat,
+                last_update: value.last_update,
+            }
+        }
+    }
+
+    /// Announced channel of the network graph, the policies are
+    /// `None` until we receive the channel update of that direction.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct GossipChannel {
+        pub short_channel_id: u64,
+        pub node_one: String,
+        pub node_two: String,
+        pub capacity_sat: Option<u64>,
+        pub one_to_two: Option<ChannelPolicy>,
+        pub two_to_one: Option<ChannelPolicy>,
+    }
+
+    impl GossipChannel {
+        pub fn new(short_channel_id: u64, channel: &ChannelInfo) -> Self {
+            Self {
+                short_channel_id,
+                node_one: channel.node_one.to_string(),
+                node_two: channel.node_two.to_string(),
+                capacity_sat: channel.capacity_sats,
+                one_to_two: channel.one_to_two.as_ref().map(ChannelPolicy::from),
+                two_to_one: channel.two_to_one.as_ref().map(ChannelPolicy::from),
+            }
+        }
+    }
 }
@@ -1,16 +1,42 @@
 //! New address model
 pub mod request {
+    use std::str::FromStr;
+
     use serde::{Deserialize, Serialize};
 
+    use crate::error;
+    use crate::types::AddressType;
+
     #[derive(Serialize, Deserialize)]
-    pub struct NewAddress;
+    pub struct NewAddress {
+        /// Include the QR code of the address inside the response.
+        pub qr: Option<bool>,
+        pub label: Option<String>,
+        /// Type of the address: `bech32` or `bech32m`, by default `bech32`.
+        pub address_type: Option<String>,
+    }
+
+    impl NewAddress {
+        pub fn address_type(&self) -> error::Result<AddressType> {
+            let address_type = self
+                .address_type
+                .as_deref()
+                .map(AddressType::from_str)
+                .transpose()?;
+            Ok(address_type.unwrap_or_default())
+        }
+    }
 }
 
 pub mod response {
     use serde::{Deserialize, Serialize};
 
+    use crate::utils::qr::QrCode;
+
     #[derive(Serialize, Deserialize)]
     pub struct NewAddress {
         pub address: String,
+        pub qr: Option<QrCode>,
+        pub label: Option<String>,
     }
 }
@@ -1,8 +1,85 @@
-pub mod request {}
+pub mod request {
+    use std::str::FromStr;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::bitcoin::{Address, Network};
+    use crate::error;
+    use crate::types::FeeRate;
+
+    /// Attach a label to an address or a transaction, an empty
+    /// label removes it.
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct SetLabel {
+        /// The address or the txid.
+        pub id: String,
+        pub label: Option<String>,
+    }
+
+    /// Sweep the small utxos of the wallet into a single output.
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Consolidate {
+        /// Consolidate only the utxos with an amount up to this value,
+        /// by default all of them.
+        pub max_amount_sat: Option<u64>,
+        /// Fee rate in sat/vB, by default the economical estimation
+        /// of the backend.
+        pub fee_rate: Option<u32>,
+        /// Return the transaction without broadcasting it.
+        pub preview: Option<bool>,
+    }
+
+    /// Send the funds of the wallet to an address.
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Withdraw {
+        pub address: String,
+        pub amount_sat: Option<u64>,
+        /// Send all the confirmed funds, the fee is paid by the output.
+        pub all: Option<bool>,
+        /// Fee rate: `slow`, `normal`, `urgent` or the sat/vB,
+        /// by default `normal`.
+        pub feerate: Option<String>,
+        /// Signal that the transaction can be replaced with a
+        /// higher fee (BIP 125), by default true.
+        pub rbf: Option<bool>,
+        /// Build the transaction without broadcasting it.
+        pub simulate: Option<bool>,
+        /// Label of the transaction.
+        pub label: Option<String>,
+    }
+
+    impl Withdraw {
+        pub fn address(&self, network: Network) -> error::Result<Address> {
+            let address = Address::from_str(&self.address)?
+                .require_network(network)
+                .map_err(|_| {
+                    error::anyhow!("`{}` is not an address of `{network}`", self.address)
+                })?;
+            Ok(address)
+        }
+
+        /// The amount to send, `None` if all the funds are sent.
+        pub fn amount_sat(&self) -> error::Result<Option<u64>> {
+            match (self.amount_sat, self.all.unwrap_or(false)) {
+                (Some(_), true) => error::bail!("`amount_sat` can not be used with `all`"),
+                (None, false) => error::bail!("`amount_sat` or `all` are required"),
+                (Some(0), false) => error::bail!("`amount_sat` must be greater than zero"),
+                (amount_sat, _) => Ok(amount_sat),
+            }
+        }
+
+        pub fn feerate(&self) -> error::Result<FeeRate> {
+            let feerate = self.feerate.as_deref().map(FeeRate::from_str).transpose()?;
+            Ok(feerate.unwrap_or(FeeRate::Normal))
+        }
+    }
+}
 
 pub mod response {
     use serde::{Deserialize, Serialize};
 
+    use crate::types::ChannelState;
+
     #[derive(Debug, Serialize, Deserialize)]
     pub struct Utxo {
         pub txid: String,
@@ -10,10 +87,96 @@ pub mod response {
         pub reserved: bool,
         pub confirmed: u32,
         pub amount_msat: u64,
+        pub address: Option<String>,
+        pub label: Option<String>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Consolidation {
+        pub txid: String,
+        pub inputs: Vec<Utxo>,
+        /// Amount of the consolidated output, the fee is already removed.
+        pub amount_sat: u64,
+        pub fee_sat: u64,
+        /// Fee rate in sat/vB.
+        pub fee_rate: u32,
+        /// False if the transaction is just a preview.
+        pub broadcast: bool,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Withdrawal {
+        pub txid: String,
+        /// Amount received by the address.
+        pub amount_sat: u64,
+        pub fee_sat: u64,
+        /// Fee rate in sat/vB.
+        pub fee_rate: u32,
+        pub rbf: bool,
+        /// False if the transaction is just a simulation.
+        pub broadcast: bool,
+        pub label: Option<String>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Label {
+        pub id: String,
+        pub label: Option<String>,
+    }
+
+    /// Our side of a channel inside the funds of the node.
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct ChannelFunds {
+        pub channel_id: String,
+        pub short_channel_id: Option<u64>,
+        pub peer_id: String,
+        pub state: ChannelState,
+        /// Capacity of the channel.
+        pub amount_sat: u64,
+        /// What we can send right now inside the channel.
+        pub our_amount_msat: u64,
+        pub our_reserve_sat: Option<u64>,
     }
 
     #[derive(Debug, Serialize, Deserialize)]
     pub struct Utxos {
         pub transactions: Vec<Utxo>,
+        pub channels: Vec<ChannelFunds>,
+        /// Confirmed on chain funds that are not reserved.
+        pub onchain_sat: u64,
+        /// What we can send right now inside all the channels.
+        pub channels_msat: u64,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum SweepStatus {
+        /// Waiting for the timelock or for the first broadcast.
+        Pending,
+        Broadcast,
+        /// Confirmed, waiting the confirmations to forget the output.
+        Confirmed,
+    }
+
+    /// Output of a closed channel that is swept to the wallet.
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Sweep {
+        pub outpoint: String,
+        pub amount_sat: u64,
+        pub channel_id: Option<String>,
+        pub status: SweepStatus,
+        /// The last sweep broadcast, replaced with a higher fee
+        /// at every block until it confirms.
+        pub txid: Option<String>,
+        /// Height of the timelock for the pending outputs, of the last
+        /// broadcast or of the confirmation for the other ones.
+        pub height: Option<u32>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Sweeps {
+        pub sweeps: Vec<Sweep>,
+        /// Amount not yet confirmed inside the wallet.
+        pub pending_sat: u64,
     }
 }
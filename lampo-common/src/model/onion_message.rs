@@ -0,0 +1,27 @@
+//! onion message model
+
+pub mod request {
+    use serde::{Deserialize, Serialize};
+
+    /// Send a custom onion message, the destination can be
+    /// a node id, an alias or a node id prefix.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct SendOnionMessage {
+        pub node_id: String,
+        /// Type of the message, at least 64 and not one
+        /// of the bolt12 types (64, 66 and 68).
+        pub tlv_type: u64,
+        /// Hex encoded content of the message.
+        pub data: String,
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct SendOnionMessage {
+        pub node_id: String,
+        pub tlv_type: u64,
+    }
+}
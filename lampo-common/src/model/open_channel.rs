@@ -5,8 +5,9 @@ pub mod request {
 
     use serde::{Deserialize, Serialize};
 
+    use crate::bitcoin::OutPoint;
     use crate::error;
-    use crate::types::NodeId;
+    use crate::types::{FeeRate, NodeId};
 
     #[derive(Clone, Serialize, Deserialize)]
     pub struct OpenChannel {
@@ -15,6 +16,17 @@ pub mod request {
         pub port: Option<u64>,
         pub amount: u64,
         pub public: bool,
+        /// The funding transaction is built, signed and broadcast by an
+        /// external wallet, the channel is completed with `completefunding`.
+        pub external_funding: Option<bool>,
+        /// Amount given to the peer when the channel is opened.
+        pub push_msat: Option<u64>,
+        /// Fee rate of the funding transaction: `slow`, `normal`,
+        /// `urgent` or the sat/vB, by default `normal`.
+        pub feerate: Option<String>,
+        /// The wallet utxos (`<txid>:<vout>`) that fund the channel,
+        /// by default they are chosen by the coin selection.
+        pub utxos: Option<Vec<String>>,
     }
 
     impl OpenChannel {
@@ -22,6 +34,57 @@ pub mod request {
             let node_id = NodeId::from_str(&self.node_id)?;
             Ok(node_id)
         }
+
+        pub fn feerate(&self) -> error::Result<Option<FeeRate>> {
+            self.feerate.as_deref().map(FeeRate::from_str).transpose()
+        }
+
+        pub fn utxos(&self) -> error::Result<Option<Vec<OutPoint>>> {
+            let Some(utxos) = &self.utxos else {
+                return Ok(None);
+            };
+            if utxos.is_empty() {
+                error::bail!("`utxos` can not be empty");
+            }
+            let mut outpoints = Vec::with_capacity(utxos.len());
+            for utxo in utxos {
+                let outpoint = OutPoint::from_str(utxo).map_err(|err| {
+                    error::anyhow!("`{utxo}` is not an utxo, use `<txid>:<vout>`: {err}")
+                })?;
+                if outpoints.contains(&outpoint) {
+                    error::bail!("the utxo `{utxo}` is repeated");
+                }
+                outpoints.push(outpoint);
+            }
+            Ok(Some(outpoints))
+        }
+
+        pub fn push_msat(&self) -> error::Result<u64> {
+            let push_msat = self.push_msat.unwrap_or_default();
+            if push_msat > self.amount * 1000 {
+                error::bail!(
+                    "`push_msat` {push_msat} exceeds the channel amount of {} sats",
+                    self.amount
+                );
+            }
+            Ok(push_msat)
+        }
+    }
+
+    /// Complete a channel opened with `external_funding`, with the
+    /// funding transaction signed by the external wallet.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct CompleteFunding {
+        pub temporary_channel_id: String,
+        /// The hex encoded signed transaction, it must pay the
+        /// channel amount to the funding script.
+        pub tx: String,
+    }
+
+    /// Open several channels funded by a single transaction.
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct OpenChannels {
+        pub channels: Vec<OpenChannel>,
     }
 }
 
@@ -32,7 +95,7 @@ pub mod response {
 
     use crate::bitcoin::{Transaction, Txid};
     use crate::error;
-    use crate::types::NodeId;
+    use crate::types::{ChannelState, NodeId};
 
     #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct Channels {
@@ -48,6 +111,33 @@ pub mod response {
         pub to_self_delay: u64,
         pub tx: Option<Transaction>,
         pub txid: Option<Txid>,
+        /// Where the external wallet must send the funds.
+        pub external_funding: Option<ExternalFunding>,
+    }
+
+    /// The channels funded by the same transaction.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct OpenChannels {
+        pub txid: Txid,
+        pub channels: Vec<BatchChannel>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct BatchChannel {
+        pub node_id: String,
+        pub temporary_channel_id: String,
+        pub amount: u64,
+    }
+
+    /// Funding output that the external wallet must create.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct ExternalFunding {
+        pub temporary_channel_id: String,
+        pub counterparty_node_id: String,
+        pub channel_value_satoshis: u64,
+        /// Hex encoded script of the funding output.
+        pub output_script: String,
+        pub address: String,
     }
 
     impl OpenChannel {
@@ -65,10 +155,95 @@ pub mod response {
         pub peer_id: String,
         pub peer_alias: Option<String>,
         pub ready: bool,
+        pub state: ChannelState,
         pub amount: u64,
         pub amount_msat: u64,
         pub public: bool,
+        /// What can be sent right now, after the reserve, the
+        /// pending HTLCs and the commitment fee.
         pub available_balance_for_send_msat: u64,
         pub available_balance_for_recv_msat: u64,
+        /// HTLCs in flight, including the ones below the dust limit.
+        pub pending_incoming_htlc_sat: u64,
+        pub pending_outgoing_htlc_sat: u64,
+        /// Funds locked in the HTLCs in flight, in both directions.
+        pub unsettled_balance_sat: u64,
+        /// Reserve that we must keep in the channel, `None` until
+        /// the counterparty accepts the channel.
+        pub our_reserve_sat: Option<u64>,
+        pub counterparty_reserve_sat: u64,
+    }
+
+    /// Last state of a channel seen by the node.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct ChannelStateRecord {
+        pub channel_id: String,
+        pub peer_id: String,
+        pub state: ChannelState,
+        pub updated_at: u64,
+    }
+
+    /// Funding transaction that is waiting for the confirmation.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct PendingFunding {
+        pub txid: String,
+        pub counterparty_node_id: String,
+        /// The temporary channel id until the channel is pending.
+        pub channel_id: String,
+        /// The outpoints spent by the funding transaction.
+        pub inputs: Vec<String>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::request::OpenChannel;
+
+    fn open_channel(utxos: Option<Vec<&str>>) -> OpenChannel {
+        OpenChannel {
+            node_id: "02a1633cafcc01ebfb6d78e39f687a1f0995c62fc95f51ead10a02ee0be551b5dc"
+                .to_owned(),
+            addr: None,
+            port: None,
+            amount: 100_000,
+            public: true,
+            external_funding: None,
+            push_msat: None,
+            feerate: None,
+            utxos: utxos.map(|utxos| utxos.into_iter().map(str::to_owned).collect()),
+        }
+    }
+
+    #[test]
+    fn utxos() {
+        let first = format!("{}:0", "11".repeat(32));
+        let second = format!("{}:1", "11".repeat(32));
+        let outpoints = open_channel(Some(vec![&first, &second]))
+            .utxos()
+            .unwrap()
+            .unwrap();
+        assert_eq!(outpoints.len(), 2);
+        assert_eq!(outpoints[0].to_string(), first);
+        assert_eq!(outpoints[1].vout, 1);
+    }
+
+    #[test]
+    fn utxos_by_coin_selection() {
+        assert!(open_channel(None).utxos().unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_utxos() {
+        let utxo = format!("{}:0", "11".repeat(32));
+        let err = open_channel(Some(vec![])).utxos().unwrap_err();
+        assert!(err.to_string().contains("can not be empty"), "{err}");
+        let err = open_channel(Some(vec![&utxo, &utxo])).utxos().unwrap_err();
+        assert!(err.to_string().contains("is repeated"), "{err}");
+        let err = open_channel(Some(vec!["11:0"])).utxos().unwrap_err();
+        assert!(err.to_string().contains("is not an utxo"), "{err}");
+        let err = open_channel(Some(vec![&"11".repeat(32)]))
+            .utxos()
+            .unwrap_err();
+        assert!(err.to_string().contains("is not an utxo"), "{err}");
     }
 }
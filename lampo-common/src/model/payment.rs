@@ -0,0 +1,94 @@
+//! Payment model
+
+pub mod request {
+    use serde::{Deserialize, Serialize};
+
+    use crate::model::response::PaymentDirection;
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct LookupPayment {
+        pub payment_hash: String,
+        /// `Sent` or `Received`, a circular payment is both, by
+        /// default the sent payment is returned when it exists.
+        pub direction: Option<PaymentDirection>,
+    }
+
+    /// Constraints on the channels used as first hop of a payment,
+    /// the channels are identified by channel id or short channel id.
+    #[derive(Clone, Serialize, Deserialize, Debug, Default)]
+    pub struct FirstHops {
+        /// Send the payment only through these channels.
+        pub out_channels: Option<Vec<String>>,
+        /// Send the payment only through the channels with this peer.
+        pub out_peer: Option<String>,
+        /// Never send the payment through these channels.
+        pub exclude_channels: Option<Vec<String>>,
+    }
+
+    /// A hop of a route hint, `node_id` forwards the payment
+    /// to the next hop over the channel `short_channel_id`.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct RouteHintHop {
+        pub node_id: String,
+        pub short_channel_id: u64,
+        pub fee_base_msat: u32,
+        pub fee_proportional_millionths: u32,
+        pub cltv_expiry_delta: u16,
+        pub htlc_minimum_msat: Option<u64>,
+        pub htlc_maximum_msat: Option<u64>,
+    }
+
+    /// A hop of a route chosen by the payer, the payment reaches
+    /// `node_id` over the channel `short_channel_id`.
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct PayRouteHop {
+        pub node_id: String,
+        pub short_channel_id: u64,
+    }
+
+    /// Route hints supplied by the payer, useful when the hints of the
+    /// invoice are stale. Each hint is a path of channels that ends at
+    /// the destination, so a hint with a single hop is a last hop constraint.
+    #[derive(Clone, Serialize, Deserialize, Debug, Default)]
+    pub struct HintOverrides {
+        pub route_hints: Option<Vec<Vec<RouteHintHop>>>,
+        /// Ignore the route hints of the invoice.
+        pub replace_route_hints: Option<bool>,
+    }
+
+    impl FirstHops {
+        pub fn is_empty(&self) -> bool {
+            self.out_channels.is_none()
+                && self.out_peer.is_none()
+                && self.exclude_channels.is_none()
+        }
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    use crate::model::response::PaymentState;
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+    pub enum PaymentDirection {
+        Sent,
+        Received,
+    }
+
+    /// Payment sent or received by the node, the preimage is
+    /// available when the payment is settled and it is the
+    /// proof of payment.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct PaymentRecord {
+        pub payment_hash: String,
+        pub direction: PaymentDirection,
+        pub state: PaymentState,
+        pub amount_msat: Option<u64>,
+        pub fee_msat: Option<u64>,
+        pub preimage: Option<String>,
+        pub bolt11: Option<String>,
+        pub created_at: u64,
+        pub settled_at: Option<u64>,
+    }
+}
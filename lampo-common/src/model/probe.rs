@@ -0,0 +1,105 @@
+//! Probe model
+pub mod request {
+    use bitcoin::secp256k1::PublicKey;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Traceroute {
+        pub destination: PublicKey,
+        pub amount_msat: u64,
+        /// How many seconds we wait for the result of each probe
+        pub timeout: Option<u64>,
+    }
+
+    /// Probe the liquidity toward `destination`, the probes
+    /// are never claimed so no funds are moved.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Probe {
+        pub destination: PublicKey,
+        pub amount_msat: u64,
+        /// How many seconds we wait for the result of the probes.
+        pub timeout: Option<u64>,
+    }
+
+    /// Ask the router a route without sending anything.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct GetRoute {
+        pub destination: PublicKey,
+        pub amount_msat: u64,
+        /// Short channel id of our channel used as first hop.
+        pub first_hop: Option<u64>,
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    use crate::model::response::{PaymentHop, PaymentState};
+
+    #[derive(Clone, Serialize, Deserialize, Debug)]
+    pub struct TracerouteHop {
+        pub node_id: String,
+        pub short_channel_id: u64,
+        /// The amount that the hop has to receive to be able
+        /// to forward the payment to the destination.
+        pub amount_msat: u64,
+        /// The cltv expiry delta that the hop has to receive to be able
+        /// to forward the payment to the destination.
+        pub cltv_expiry_delta: u32,
+        pub state: PaymentState,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Traceroute {
+        pub destination: String,
+        pub amount_msat: u64,
+        pub reached: bool,
+        pub hops: Vec<TracerouteHop>,
+        /// The first channel that failed to forward the probe, if any.
+        pub failed_short_channel_id: Option<u64>,
+    }
+
+    /// A path of the route, the route is split in more paths
+    /// when a single channel can not carry the whole amount.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct RoutePath {
+        /// Amount received by the destination on this path.
+        pub amount_msat: u64,
+        pub fee_msat: u64,
+        /// Sum of the cltv expiry deltas of the hops.
+        pub cltv_expiry_delta: u32,
+        pub hops: Vec<PaymentHop>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct GetRoute {
+        pub destination: String,
+        pub amount_msat: u64,
+        pub fee_msat: u64,
+        pub paths: Vec<RoutePath>,
+    }
+
+    /// A path of the route probed by `probe`.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct ProbePath {
+        /// `None` for a direct channel, that is checked
+        /// without sending a probe.
+        pub payment_hash: Option<String>,
+        /// `Pending` when the result did not arrive in time.
+        pub state: PaymentState,
+        pub hops: Vec<PaymentHop>,
+        /// The channel that was not able to forward the probe.
+        pub failed_short_channel_id: Option<u64>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct Probe {
+        pub destination: String,
+        pub amount_msat: u64,
+        /// Fees that the payment would pay along the probed route.
+        pub fee_msat: u64,
+        /// True when all the paths can carry the amount.
+        pub reached: bool,
+        pub paths: Vec<ProbePath>,
+    }
+}
@@ -0,0 +1,61 @@
+//! Payment queue model
+
+pub mod request {
+    use serde::{Deserialize, Serialize};
+
+    use crate::model::response::QueueStatus;
+
+    /// Add a payment to the queue, pay an `invoice` or send
+    /// a keysend to `destination`.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct QueuePay {
+        /// Unique id of the payment, e.g: the id of the withdrawal,
+        /// by default the payment hash or a random id for keysend.
+        pub id: Option<String>,
+        pub invoice: Option<String>,
+        pub destination: Option<String>,
+        pub amount_msat: Option<u64>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct ListQueue {
+        pub status: Option<QueueStatus>,
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum QueueStatus {
+        Queued,
+        Inflight,
+        Succeeded,
+        Failed,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct QueuedPayment {
+        pub id: String,
+        /// Position inside the queue.
+        pub seq: u64,
+        pub invoice: Option<String>,
+        /// Node id of the recipient, the payments to the same
+        /// destination are sent one at time in queue order.
+        pub destination: String,
+        pub amount_msat: Option<u64>,
+        pub status: QueueStatus,
+        pub attempts: u64,
+        pub payment_hash: Option<String>,
+        pub last_error: Option<String>,
+        pub created_at: u64,
+        /// Do not try the payment before this timestamp.
+        pub next_attempt: u64,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct PaymentQueue {
+        pub payments: Vec<QueuedPayment>,
+    }
+}
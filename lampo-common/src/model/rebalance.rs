@@ -0,0 +1,25 @@
+//! Rebalancer model
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    use crate::model::response::PaymentState;
+
+    /// Circular payment made by the rebalancer.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct RebalanceAction {
+        pub timestamp: u64,
+        pub from_channel_id: String,
+        pub to_channel_id: String,
+        pub amount_msat: u64,
+        pub fee_msat: Option<u64>,
+        pub payment_hash: Option<String>,
+        pub state: PaymentState,
+        pub message: Option<String>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct RebalanceReport {
+        pub actions: Vec<RebalanceAction>,
+    }
+}
@@ -0,0 +1,51 @@
+//! Recurring payments model
+
+pub mod request {
+    use serde::{Deserialize, Serialize};
+
+    /// Pay an offer, or send a keysend to `destination`,
+    /// every time that the `schedule` expires.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct SchedulePay {
+        pub id: String,
+        pub offer: Option<String>,
+        pub destination: Option<String>,
+        pub amount_msat: Option<u64>,
+        /// `@hourly`, `@daily`, `@weekly`, `@monthly` or an
+        /// interval like `30m`, `12h`, `7d`.
+        pub schedule: String,
+        /// Stop the schedule when the total spent reaches the budget.
+        pub budget_msat: Option<u64>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct DelSchedule {
+        pub id: String,
+    }
+}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Schedule {
+        pub id: String,
+        pub offer: Option<String>,
+        pub destination: Option<String>,
+        pub amount_msat: u64,
+        pub schedule: String,
+        /// Seconds between two payments.
+        pub interval: u64,
+        pub budget_msat: Option<u64>,
+        pub spent_msat: u64,
+        pub next_run: u64,
+        /// Failures since the last successful payment.
+        pub failures: u32,
+        pub active: bool,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Schedules {
+        pub schedules: Vec<Schedule>,
+    }
+}
@@ -0,0 +1,70 @@
+//! Node statistics model
+pub mod response {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+    pub struct Counters {
+        pub payments_sent: u64,
+        pub payments_received: u64,
+        pub payments_failed: u64,
+        pub forwards: u64,
+        pub forward_failures: u64,
+        /// Failed payments grouped by the failure reason.
+        pub failures: HashMap<String, u64>,
+        pub peer_disconnects: u64,
+        /// Number of sent payments where we were able to measure the latency.
+        pub timed_payments: u64,
+        pub total_payment_latency_ms: u64,
+        pub avg_payment_latency_ms: Option<u64>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Stats {
+        pub since_startup: Counters,
+        pub lifetime: Counters,
+    }
+
+    /// Number of calls that took at most `le_ms`, `None` is the
+    /// bucket for all the calls.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct LatencyBucket {
+        pub le_ms: Option<u64>,
+        pub count: u64,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CallMetrics {
+        pub count: u64,
+        pub errors: u64,
+        pub in_flight: u64,
+        pub avg_ms: Option<u64>,
+        pub max_ms: u64,
+        pub buckets: Vec<LatencyBucket>,
+    }
+
+    /// Ldk event that the handler is still processing.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct PendingEvent {
+        pub id: u64,
+        pub event: String,
+        pub description: String,
+        /// When the handler started to process the event.
+        pub timestamp: u64,
+        pub age_ms: u64,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct PendingEvents {
+        pub events: Vec<PendingEvent>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Metrics {
+        /// Metrics of the JSON RPC methods, by method name.
+        pub rpc: BTreeMap<String, CallMetrics>,
+        /// Metrics of the ldk event handler, by event type.
+        pub events: BTreeMap<String, CallMetrics>,
+    }
+}
@@ -0,0 +1,35 @@
+pub mod request {}
+
+pub mod response {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum TransactionKind {
+        Send,
+        Receive,
+        Funding,
+        Close,
+        Sweep,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct TransactionRecord {
+        pub txid: String,
+        pub kind: TransactionKind,
+        /// Amount moved by the transaction without the fee,
+        /// unknown for the channels funded by the peer.
+        pub amount_sat: Option<u64>,
+        pub fee_sat: Option<u64>,
+        pub channel_id: Option<String>,
+        pub label: Option<String>,
+        pub block_height: Option<u32>,
+        pub confirmations: u32,
+        pub timestamp: u64,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Transactions {
+        pub transactions: Vec<TransactionRecord>,
+    }
+}
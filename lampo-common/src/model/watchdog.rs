@@ -0,0 +1,16 @@
+pub mod request {}
+
+pub mod response {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    /// Last channel state seen by the node.
+    #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+    pub struct StateWatermark {
+        /// Funding outpoint -> latest update id of the channel monitor.
+        pub update_ids: BTreeMap<String, u64>,
+        /// Why the node is in safe mode.
+        pub stale_reason: Option<String>,
+    }
+}
@@ -1,13 +1,157 @@
 //! Lampo Common Types
+use serde::{Deserialize, Serialize};
+
 use crate::bitcoin::secp256k1::PublicKey;
 use crate::ldk;
 
 pub type NodeId = PublicKey;
 pub type ChannelId = ldk::ln::ChannelId;
 
-#[derive(Debug, Clone)]
+/// Parse the hex encoded 32 bytes of a channel id.
+pub fn parse_channel_id(channel_id: &str) -> crate::error::Result<ChannelId> {
+    let bytes = hex::decode(channel_id)
+        .map_err(|err| crate::error::anyhow!("`{channel_id}` is not a channel id: {err}"))?;
+    let bytes = <[u8; 32]>::try_from(bytes).map_err(|_| {
+        crate::error::anyhow!("`{channel_id}` is not a channel id, it must be 32 bytes")
+    })?;
+    Ok(ChannelId::from_bytes(bytes))
+}
+
+/// Lifecycle of a channel, a channel moves from `opening` to
+/// `closed` and it never goes back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ChannelState {
+    /// The channel is negotiated with the peer.
     Opening,
+    /// The funding transaction is waiting for the confirmations.
+    Pending,
     Ready,
+    /// The shutdown is started or the commitment is broadcasted.
+    Closing,
+    Closed,
     OpeningError,
+    /// The funding transaction was double spent.
+    Abandoned,
+}
+
+impl ChannelState {
+    fn step(&self) -> u8 {
+        match self {
+            Self::Opening => 0,
+            Self::Pending => 1,
+            Self::Ready => 2,
+            Self::Closing => 3,
+            Self::Closed | Self::OpeningError | Self::Abandoned => 4,
+        }
+    }
+
+    /// Return true if the channel can move from this state to `next`.
+    pub fn can_move_to(&self, next: &ChannelState) -> bool {
+        match self {
+            Self::Closed | Self::OpeningError | Self::Abandoned => false,
+            _ => next.step() >= self.step(),
+        }
+    }
+}
+
+/// Fee rate of a transaction, as a confirmation target or in sat/vB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeRate {
+    /// Confirmation in about 100 blocks.
+    Slow,
+    /// Confirmation in about 6 blocks.
+    Normal,
+    /// Confirmation in the next block.
+    Urgent,
+    SatPerVByte(u64),
+}
+
+impl std::str::FromStr for FeeRate {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "slow" => Ok(Self::Slow),
+            "normal" => Ok(Self::Normal),
+            "urgent" => Ok(Self::Urgent),
+            fee_rate => {
+                let fee_rate = u64::from_str(fee_rate).map_err(|_| {
+                    crate::error::anyhow!(
+                        "`{fee_rate}` is not a fee rate, use `slow`, `normal`, `urgent` or the sat/vB"
+                    )
+                })?;
+                if fee_rate == 0 {
+                    crate::error::bail!("the fee rate must be at least 1 sat/vB");
+                }
+                Ok(Self::SatPerVByte(fee_rate))
+            }
+        }
+    }
+}
+
+/// Output type of an on chain address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressType {
+    /// Segwit v0 address (BIP 173).
+    #[default]
+    Bech32,
+    /// Taproot address (BIP 350).
+    Bech32m,
+}
+
+impl std::str::FromStr for AddressType {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bech32" => Ok(Self::Bech32),
+            "bech32m" => Ok(Self::Bech32m),
+            _ => crate::error::bail!("`{s}` is not an address type, use `bech32` or `bech32m`"),
+        }
+    }
+}
+
+impl std::fmt::Display for AddressType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bech32 => write!(f, "bech32"),
+            Self::Bech32m => write!(f, "bech32m"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{parse_channel_id, FeeRate};
+
+    #[test]
+    fn fee_rate_from_str() {
+        assert_eq!(FeeRate::from_str("slow").unwrap(), FeeRate::Slow);
+        assert_eq!(FeeRate::from_str("normal").unwrap(), FeeRate::Normal);
+        assert_eq!(FeeRate::from_str("urgent").unwrap(), FeeRate::Urgent);
+        assert_eq!(FeeRate::from_str("12").unwrap(), FeeRate::SatPerVByte(12));
+    }
+
+    #[test]
+    fn invalid_fee_rate() {
+        let err = FeeRate::from_str("0").unwrap_err();
+        assert!(err.to_string().contains("at least 1 sat/vB"), "{err}");
+        for fee_rate in ["fast", "-1", "1.5", ""] {
+            let err = FeeRate::from_str(fee_rate).unwrap_err();
+            assert!(err.to_string().contains("is not a fee rate"), "{err}");
+        }
+    }
+
+    #[test]
+    fn channel_id_from_hex() {
+        let channel_id = parse_channel_id(&"0a".repeat(32)).unwrap();
+        assert_eq!(channel_id.0, [10; 32]);
+        for channel_id in ["0a", "0g", "abc", &"0a".repeat(33)] {
+            let err = parse_channel_id(channel_id).unwrap_err();
+            assert!(err.to_string().contains("is not a channel id"), "{err}");
+        }
+    }
 }
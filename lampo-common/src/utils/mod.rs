@@ -1,2 +1,3 @@
 //! Utils module implementation
 pub mod logger;
+pub mod qr;
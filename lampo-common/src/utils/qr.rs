@@ -0,0 +1,11 @@
+//! QR code of an invoice or an address, the code
+//! is rendered by the daemon.
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QrCode {
+    /// QR code that can be printed inside a terminal.
+    pub ascii: String,
+    /// PNG image encoded in base64.
+    pub png: String,
+}
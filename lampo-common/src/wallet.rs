@@ -1,10 +1,12 @@
 use std::sync::Arc;
 
-use crate::bitcoin::{ScriptBuf, Transaction};
+use crate::bitcoin::psbt::PartiallySignedTransaction;
+use crate::bitcoin::{OutPoint, ScriptBuf, Transaction, TxOut, Txid};
 use crate::conf::LampoConf;
 use crate::error;
 use crate::keys::LampoKeys;
-use crate::model::response::{NewAddress, Utxo};
+use crate::model::response::{NewAddress, TransactionRecord, Utxo};
+use crate::types::AddressType;
 
 /// Wallet manager trait that define a generic interface
 /// over Wallet implementation!
@@ -25,6 +27,16 @@ pub trait WalletManager: Send + Sync {
     /// return an on chain address
     fn get_onchain_address(&self) -> error::Result<NewAddress>;
 
+    /// Return an on chain address of the `address_type`.
+    fn get_onchain_address_of(&self, address_type: AddressType) -> error::Result<NewAddress> {
+        match address_type {
+            AddressType::Bech32 => self.get_onchain_address(),
+            AddressType::Bech32m => {
+                error::bail!("the wallet does not support the `{address_type}` addresses")
+            }
+        }
+    }
+
     /// Get the current balance of the wallet.
     fn get_onchain_balance(&self) -> error::Result<u64>;
 
@@ -37,9 +49,89 @@ pub trait WalletManager: Send + Sync {
         fee_rate: u32,
     ) -> error::Result<Transaction>;
 
+    /// Create the transaction that pays all the `outputs` (script
+    /// and amount), e.g: the funding of a batch of channels.
+    fn create_batch_transaction(
+        &self,
+        _outputs: &[(ScriptBuf, u64)],
+        _fee_rate: u32,
+    ) -> error::Result<Transaction> {
+        error::bail!("the wallet does not support the batch transactions")
+    }
+
+    /// Create the transaction that pays all the `outputs` (script and
+    /// amount) with the `inputs` chosen by the coin selection, the
+    /// change goes back to the wallet. The inputs are locked until
+    /// `unlock_inputs`, so the wallet does not spend them again.
+    fn create_transaction_with_inputs(
+        &self,
+        _inputs: &[OutPoint],
+        _outputs: &[(ScriptBuf, u64)],
+        _fee_rate: u32,
+    ) -> error::Result<Transaction> {
+        error::bail!("the wallet does not support the coin selection")
+    }
+
+    /// Unlock the inputs of a transaction that will never be broadcast.
+    fn unlock_inputs(&self, _inputs: &[OutPoint]) -> error::Result<()> {
+        Ok(())
+    }
+
+    /// Create the transaction that sends `amount_sat` to `script`, when
+    /// `amount_sat` is `None` all the confirmed funds of the wallet are
+    /// sent and the fee is paid by the output.
+    fn create_withdrawal(
+        &self,
+        _script: ScriptBuf,
+        _amount_sat: Option<u64>,
+        _fee_rate: u32,
+        _rbf: bool,
+    ) -> error::Result<Transaction> {
+        error::bail!("the wallet does not support the withdrawals")
+    }
+
+    /// Create the transaction that spends all the `inputs` into a
+    /// single wallet output, the fee is paid by the output.
+    fn create_consolidation(
+        &self,
+        _inputs: &[OutPoint],
+        _fee_rate: u32,
+    ) -> error::Result<Transaction> {
+        error::bail!("the wallet does not support the consolidation of the utxos")
+    }
+
+    /// Forget a transaction that will never be confirmed, so its
+    /// inputs can be spent again.
+    fn abandon_transaction(&self, _txid: &Txid) -> error::Result<()> {
+        Ok(())
+    }
+
+    /// Return the confirmed utxos that can pay the fees of a
+    /// fee bump (e.g: the anchor of a force close).
+    fn list_confirmed_utxos(&self) -> error::Result<Vec<(OutPoint, TxOut)>> {
+        error::bail!("the wallet does not support the fee bumping")
+    }
+
+    /// Return the script where the change of a fee bump is sent.
+    fn get_change_script(&self) -> error::Result<ScriptBuf> {
+        error::bail!("the wallet does not support the fee bumping")
+    }
+
+    /// Sign the wallet inputs of the psbt, the other inputs
+    /// are signed by ldk.
+    fn sign_psbt(&self, _psbt: PartiallySignedTransaction) -> error::Result<Transaction> {
+        error::bail!("the wallet does not support the fee bumping")
+    }
+
     /// Return the list of transaction stored inside the wallet
     fn list_transactions(&self) -> error::Result<Vec<Utxo>>;
 
+    /// Return the history of the transactions sent and received
+    /// by the wallet.
+    fn wallet_transactions(&self) -> error::Result<Vec<TransactionRecord>> {
+        error::bail!("the wallet does not support the transaction history")
+    }
+
     /// Sync the wallet.
     fn sync(&self) -> error::Result<()>;
 }
@@ -1,5 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::Not;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use bdk::bitcoin::Amount;
@@ -10,9 +11,10 @@ use bdk::keys::DerivableKey;
 use bdk::keys::ExtendedKey;
 use bdk::keys::GeneratableKey;
 use bdk::keys::GeneratedKey;
-use bdk::template::Bip84;
+use bdk::template::{Bip84, Bip86};
 use bdk::KeychainKind;
 use bitcoin_hashes::hex::HexIterator;
+use bitcoincore_rpc::json::GetTransactionResultDetailCategory;
 use bitcoincore_rpc::{Auth, Client, RpcApi};
 
 #[cfg(debug_assertions)]
@@ -25,7 +27,8 @@ use lampo_common::error;
 use lampo_common::json;
 use lampo_common::json::Deserialize;
 use lampo_common::keys::LampoKeys;
-use lampo_common::model::response::{NewAddress, Utxo};
+use lampo_common::model::response::{NewAddress, TransactionKind, TransactionRecord, Utxo};
+use lampo_common::types::AddressType;
 use lampo_common::wallet::WalletManager;
 
 pub struct CoreWalletManager {
@@ -39,7 +42,7 @@ impl CoreWalletManager {
     fn build_wallet(
         conf: Arc<LampoConf>,
         mnemonic_words: &str,
-    ) -> error::Result<(bdk::Wallet, LampoKeys)> {
+    ) -> error::Result<(Vec<bdk::Wallet>, LampoKeys)> {
         // Parse a mnemonic
         let mnemonic = Mnemonic::parse(mnemonic_words).map_err(|err| error::anyhow!("{err}"))?;
         // Generate the extended key
@@ -56,7 +59,10 @@ impl CoreWalletManager {
             .into_xprv(network)
             .ok_or(error::anyhow!("impossible cast the private key"))?;
 
-        let ldk_keys = LampoKeys::new(xprv.private_key.secret_bytes());
+        let mut ldk_keys = LampoKeys::new(xprv.private_key.secret_bytes());
+        if let Some(seed) = conf.phantom_seed()? {
+            ldk_keys = ldk_keys.with_phantom_seed(seed);
+        }
         // Create a BDK wallet structure using BIP 84 descriptor ("m/84h/1h/0h/0" and "m/84h/1h/0h/1")
         let wallet = bdk::Wallet::new(
             Bip84(xprv, KeychainKind::External),
@@ -64,14 +70,21 @@ impl CoreWalletManager {
             (),
             network,
         )?;
-        Ok((wallet, ldk_keys))
+        // BIP 86 descriptor ("m/86h/1h/0h/0" and "m/86h/1h/0h/1") for the bech32m addresses
+        let taproot_wallet = bdk::Wallet::new(
+            Bip86(xprv, KeychainKind::External),
+            Some(Bip86(xprv, KeychainKind::Internal)),
+            (),
+            network,
+        )?;
+        Ok((vec![wallet, taproot_wallet], ldk_keys))
     }
 
     #[cfg(debug_assertions)]
     fn build_from_private_key(
         xprv: lampo_common::bitcoin::PrivateKey,
         channel_keys: Option<String>,
-    ) -> error::Result<(bdk::Wallet, LampoKeys)> {
+    ) -> error::Result<(Vec<bdk::Wallet>, LampoKeys)> {
         use bdk::bitcoin::bip32::Xpriv;
 
         let ldk_keys = if let Some(channel_keys) = channel_keys {
@@ -90,13 +103,13 @@ impl CoreWalletManager {
         let key = ExtendedKey::from(key);
         let wallet = bdk::Wallet::new(Bip84(key, KeychainKind::External), None, (), network)
             .map_err(|err| error::anyhow!(err.to_string()))?;
-        Ok((wallet, ldk_keys))
+        Ok((vec![wallet], ldk_keys))
     }
 
     fn configure_bitcoin_wallet(
         rpc: &Client,
         conf: Arc<LampoConf>,
-        wallet: bdk::Wallet,
+        wallets: Vec<bdk::Wallet>,
     ) -> error::Result<String> {
         // FIXME: allow to support multiple wallets for the same chain, so
         // we should make a suffix in the following name
@@ -122,33 +135,35 @@ impl CoreWalletManager {
             if result.is_err() {
                 let _ = rpc.load_wallet(&name_wallet)?;
             } else {
-                let external_signer = wallet.get_signers(KeychainKind::External);
-                let external_signer = external_signer.as_key_map(wallet.secp_ctx());
-                let external_descriptor =
-                    wallet.get_descriptor_for_keychain(KeychainKind::External);
-                let external_descriptor =
-                    external_descriptor.to_string_with_secret(&external_signer);
-                let internal_signer = wallet.get_signers(KeychainKind::Internal);
-                let internal_signer = internal_signer.as_key_map(wallet.secp_ctx());
-                let internal_descriptor =
-                    wallet.get_descriptor_for_keychain(KeychainKind::Internal);
-                let internal_descriptor =
-                    internal_descriptor.to_string_with_secret(&internal_signer);
-
-                let options = vec![
-                    json::json!({
+                // one active descriptor for each type of address
+                let mut options = Vec::new();
+                for wallet in &wallets {
+                    let external_signer = wallet.get_signers(KeychainKind::External);
+                    let external_signer = external_signer.as_key_map(wallet.secp_ctx());
+                    let external_descriptor =
+                        wallet.get_descriptor_for_keychain(KeychainKind::External);
+                    let external_descriptor =
+                        external_descriptor.to_string_with_secret(&external_signer);
+                    let internal_signer = wallet.get_signers(KeychainKind::Internal);
+                    let internal_signer = internal_signer.as_key_map(wallet.secp_ctx());
+                    let internal_descriptor =
+                        wallet.get_descriptor_for_keychain(KeychainKind::Internal);
+                    let internal_descriptor =
+                        internal_descriptor.to_string_with_secret(&internal_signer);
+
+                    options.push(json::json!({
                         "desc": external_descriptor,
                         "active": true,
                         "timestamp": "now",
                         "internal": false,
-                    }),
-                    json::json!({
+                    }));
+                    options.push(json::json!({
                         "desc": internal_descriptor,
                         "active": true,
                         "timestamp": "now",
                         "internal": true,
-                    }),
-                ];
+                    }));
+                }
 
                 let rpc = Self::build_bitcoin_rpc(conf.clone(), Some(&name_wallet))?;
                 log::trace!(target: "core", "import descriptor options: {:?}", options);
@@ -208,10 +223,10 @@ impl WalletManager for CoreWalletManager {
             Mnemonic::generate((WordCount::Words12, Language::English))
                 .map_err(|err| error::anyhow!("{:?}", err))?;
 
-        let (wallet, keymanager) =
+        let (wallets, keymanager) =
             CoreWalletManager::build_wallet(conf.clone(), &mnemonic.to_string())?;
         let rpc = Self::build_bitcoin_rpc(conf.clone(), None)?;
-        let wallet_name = Self::configure_bitcoin_wallet(&rpc, conf.clone(), wallet)?;
+        let wallet_name = Self::configure_bitcoin_wallet(&rpc, conf.clone(), wallets)?;
         let rpc = Self::build_bitcoin_rpc(conf.clone(), Some(&wallet_name))?;
         Ok((
             Self {
@@ -229,19 +244,29 @@ impl WalletManager for CoreWalletManager {
         amount_sat: u64,
         fee_rate: u32,
     ) -> error::Result<bitcoin::Transaction> {
-        let addr = bitcoin_bech32::WitnessProgram::from_scriptpubkey(
-            script.as_bytes(),
-            match self.network {
-                Network::Bitcoin => bitcoin_bech32::constants::Network::Bitcoin,
-                Network::Testnet => bitcoin_bech32::constants::Network::Testnet,
-                Network::Regtest => bitcoin_bech32::constants::Network::Regtest,
-                Network::Signet => bitcoin_bech32::constants::Network::Signet,
-                _ => error::bail!("network `{}` not supported", self.network),
-            },
-        )?
-        .to_address();
+        self.create_batch_transaction(&[(script, amount_sat)], fee_rate)
+    }
+
+    fn create_batch_transaction(
+        &self,
+        outputs: &[(bitcoin::ScriptBuf, u64)],
+        fee_rate: u32,
+    ) -> error::Result<bitcoin::Transaction> {
         let mut map = HashMap::new();
-        map.insert(addr, Amount::from_sat(amount_sat).to_btc());
+        for (script, amount_sat) in outputs {
+            let addr = bitcoin_bech32::WitnessProgram::from_scriptpubkey(
+                script.as_bytes(),
+                match self.network {
+                    Network::Bitcoin => bitcoin_bech32::constants::Network::Bitcoin,
+                    Network::Testnet => bitcoin_bech32::constants::Network::Testnet,
+                    Network::Regtest => bitcoin_bech32::constants::Network::Regtest,
+                    Network::Signet => bitcoin_bech32::constants::Network::Signet,
+                    _ => error::bail!("network `{}` not supported", self.network),
+                },
+            )?
+            .to_address();
+            map.insert(addr, Amount::from_sat(*amount_sat).to_btc());
+        }
         let options = json::json!({
             // LDK gives us feerates in satoshis per KW but Bitcoin Core here expects fees
             // denominated in satoshis per vB. First we need to multiply by 4 to convert weight
@@ -277,10 +302,248 @@ impl WalletManager for CoreWalletManager {
         Ok(object)
     }
 
+    fn create_consolidation(
+        &self,
+        inputs: &[bitcoin::OutPoint],
+        fee_rate: u32,
+    ) -> error::Result<bitcoin::Transaction> {
+        let utxos = self.rpc.list_unspent(None, None, None, Some(true), None)?;
+        let mut amount = Amount::from_sat(0);
+        for input in inputs {
+            let Some(utxo) = utxos
+                .iter()
+                .find(|utxo| utxo.txid == input.txid && utxo.vout == input.vout)
+            else {
+                error::bail!("utxo `{input}` not found inside the wallet");
+            };
+            amount += utxo.amount;
+        }
+        let addr: String = self
+            .rpc
+            .call("getnewaddress", &["lampo-consolidation".into()])?;
+        let inputs = inputs
+            .iter()
+            .map(|input| json::json!({ "txid": input.txid.to_string(), "vout": input.vout }))
+            .collect::<Vec<_>>();
+        let mut map = HashMap::new();
+        map.insert(addr, amount.to_btc());
+        let options = json::json!({
+            // see `create_transaction` for the conversion.
+            "fee_rate": fee_rate as f64 / 250.0,
+            "add_inputs": false,
+            "subtractFeeFromOutputs": [0],
+            "replaceable": true,
+        });
+
+        let hex: String = self.rpc.call(
+            "createrawtransaction",
+            &[json::json!(inputs), json::json!(&map), json::json!(0)],
+        )?;
+        let tx: Tx = self.rpc.call(
+            "fundrawtransaction",
+            &[json::json!(hex), json::json!(options)],
+        )?;
+        let hex: Tx = self
+            .rpc
+            .call("signrawtransactionwithwallet", &[json::json!(tx.hex)])?;
+        let hex = hex
+            .hex
+            .ok_or(error::anyhow!("impossible sign the consolidation"))?;
+        let mut reader = HexIterator::new(&hex)?;
+        let object = Decodable::consensus_decode(&mut reader)?;
+        Ok(object)
+    }
+
+    fn create_transaction_with_inputs(
+        &self,
+        inputs: &[bitcoin::OutPoint],
+        outputs: &[(bitcoin::ScriptBuf, u64)],
+        fee_rate: u32,
+    ) -> error::Result<bitcoin::Transaction> {
+        let inputs = inputs
+            .iter()
+            .map(|input| json::json!({ "txid": input.txid.to_string(), "vout": input.vout }))
+            .collect::<Vec<_>>();
+        let mut map = HashMap::new();
+        for (script, amount_sat) in outputs {
+            let addr = bitcoin::Address::from_script(script, self.network)?;
+            map.insert(addr.to_string(), Amount::from_sat(*amount_sat).to_btc());
+        }
+        let options = json::json!({
+            // see `create_transaction` for the conversion and the rbf.
+            "fee_rate": fee_rate as f64 / 250.0,
+            "replaceable": false,
+            "add_inputs": false,
+            // so the withdrawals and the consolidations do not spend them
+            "lockUnspents": true,
+        });
+
+        let hex: String = self.rpc.call(
+            "createrawtransaction",
+            &[json::json!(inputs), json::json!(&map), json::json!(0)],
+        )?;
+        let tx: Tx = self.rpc.call(
+            "fundrawtransaction",
+            &[json::json!(hex), json::json!(options)],
+        )?;
+        let hex: Tx = self
+            .rpc
+            .call("signrawtransactionwithwallet", &[json::json!(tx.hex)])?;
+        let hex = hex
+            .hex
+            .ok_or(error::anyhow!("impossible sign the funding transaction"))?;
+        let mut reader = HexIterator::new(&hex)?;
+        let object = Decodable::consensus_decode(&mut reader)?;
+        Ok(object)
+    }
+
+    fn unlock_inputs(&self, inputs: &[bitcoin::OutPoint]) -> error::Result<()> {
+        let inputs = inputs
+            .iter()
+            .map(|input| json::json!({ "txid": input.txid.to_string(), "vout": input.vout }))
+            .collect::<Vec<_>>();
+        let _: json::Value = self
+            .rpc
+            .call("lockunspent", &[true.into(), json::json!(inputs)])?;
+        Ok(())
+    }
+
+    fn create_withdrawal(
+        &self,
+        script: bitcoin::ScriptBuf,
+        amount_sat: Option<u64>,
+        fee_rate: u32,
+        rbf: bool,
+    ) -> error::Result<bitcoin::Transaction> {
+        let addr = bitcoin::Address::from_script(&script, self.network)?;
+        let (inputs, amount, add_inputs) = match amount_sat {
+            Some(amount_sat) => (vec![], Amount::from_sat(amount_sat), true),
+            None => {
+                // drain all the confirmed funds, the fee is paid by the output.
+                let utxos = self
+                    .rpc
+                    .list_unspent(Some(1), None, None, Some(false), None)?
+                    .into_iter()
+                    .filter(|utxo| utxo.spendable)
+                    .collect::<Vec<_>>();
+                if utxos.is_empty() {
+                    error::bail!("no confirmed funds inside the wallet");
+                }
+                let amount = utxos
+                    .iter()
+                    .fold(Amount::from_sat(0), |amount, utxo| amount + utxo.amount);
+                let inputs = utxos
+                    .iter()
+                    .map(|utxo| json::json!({ "txid": utxo.txid.to_string(), "vout": utxo.vout }))
+                    .collect::<Vec<_>>();
+                (inputs, amount, false)
+            }
+        };
+        let mut map = HashMap::new();
+        map.insert(addr.to_string(), amount.to_btc());
+        let mut options = json::json!({
+            // see `create_transaction` for the conversion.
+            "fee_rate": fee_rate as f64 / 250.0,
+            "add_inputs": add_inputs,
+            "replaceable": rbf,
+        });
+        if !add_inputs {
+            options["subtractFeeFromOutputs"] = json::json!([0]);
+        }
+
+        let hex: String = self.rpc.call(
+            "createrawtransaction",
+            &[json::json!(inputs), json::json!(&map), json::json!(0)],
+        )?;
+        let tx: Tx = self.rpc.call(
+            "fundrawtransaction",
+            &[json::json!(hex), json::json!(options)],
+        )?;
+        let hex: Tx = self
+            .rpc
+            .call("signrawtransactionwithwallet", &[json::json!(tx.hex)])?;
+        let hex = hex
+            .hex
+            .ok_or(error::anyhow!("impossible sign the withdrawal"))?;
+        let mut reader = HexIterator::new(&hex)?;
+        let object = Decodable::consensus_decode(&mut reader)?;
+        Ok(object)
+    }
+
+    fn abandon_transaction(&self, txid: &bitcoin::Txid) -> error::Result<()> {
+        let _: json::Value = self
+            .rpc
+            .call("abandontransaction", &[txid.to_string().into()])?;
+        Ok(())
+    }
+
+    fn list_confirmed_utxos(&self) -> error::Result<Vec<(bitcoin::OutPoint, bitcoin::TxOut)>> {
+        let utxos = self
+            .rpc
+            .list_unspent(Some(1), None, None, Some(false), None)?
+            .into_iter()
+            .filter(|utxo| utxo.spendable)
+            .map(|utxo| {
+                let outpoint = bitcoin::OutPoint {
+                    txid: utxo.txid,
+                    vout: utxo.vout,
+                };
+                let output = bitcoin::TxOut {
+                    value: utxo.amount.to_sat(),
+                    script_pubkey: utxo.script_pub_key,
+                };
+                (outpoint, output)
+            })
+            .collect();
+        Ok(utxos)
+    }
+
+    fn get_change_script(&self) -> error::Result<bitcoin::ScriptBuf> {
+        let addr: String = self.rpc.call("getrawchangeaddress", &["bech32".into()])?;
+        let addr = bitcoin::Address::from_str(&addr)?.require_network(self.network)?;
+        Ok(addr.script_pubkey())
+    }
+
+    fn sign_psbt(
+        &self,
+        psbt: bitcoin::psbt::PartiallySignedTransaction,
+    ) -> error::Result<bitcoin::Transaction> {
+        // the inputs that are not inside the wallet (e.g: the anchor)
+        // are left unsigned, so the transaction is never complete.
+        let tx = bitcoin::consensus::encode::serialize_hex(&psbt.extract_tx());
+        let hex: Tx = self
+            .rpc
+            .call("signrawtransactionwithwallet", &[json::json!(tx)])?;
+        let hex = hex
+            .hex
+            .ok_or(error::anyhow!("impossible sign the fee bump"))?;
+        let mut reader = HexIterator::new(&hex)?;
+        let object = Decodable::consensus_decode(&mut reader)?;
+        Ok(object)
+    }
+
     fn get_onchain_address(&self) -> error::Result<NewAddress> {
-        let addr = self.rpc.call("getnewaddress", &["lampo-addr".into()])?;
+        self.get_onchain_address_of(AddressType::Bech32)
+    }
+
+    fn get_onchain_address_of(&self, address_type: AddressType) -> error::Result<NewAddress> {
+        let addr: String = self
+            .rpc
+            .call(
+                "getnewaddress",
+                &["lampo-addr".into(), address_type.to_string().into()],
+            )
+            .map_err(|err| {
+                // the wallets created before the taproot descriptor
+                // do not have a descriptor for the bech32m addresses.
+                error::anyhow!("impossible generate a `{address_type}` address: {err}")
+            })?;
         log::debug!(target: "core-wallet", "addr generated: {addr}" );
-        Ok(NewAddress { address: addr })
+        Ok(NewAddress {
+            address: addr,
+            qr: None,
+            label: None,
+        })
     }
 
     fn get_onchain_balance(&self) -> error::Result<u64> {
@@ -303,16 +566,62 @@ impl WalletManager for CoreWalletManager {
                 reserved: utxo.spendable.not(),
                 confirmed: utxo.confirmations,
                 amount_msat: utxo.amount.to_sat() * 1000,
+                address: utxo
+                    .address
+                    .as_ref()
+                    .map(|addr| addr.clone().assume_checked().to_string()),
+                label: None,
             })
             .collect::<Vec<_>>();
         Ok(unspend)
     }
 
+    fn wallet_transactions(&self) -> error::Result<Vec<TransactionRecord>> {
+        let entries = self
+            .rpc
+            .list_transactions(None, Some(1000), None, Some(true))?;
+        // core returns one entry for each output, so we group them by txid.
+        let mut records: BTreeMap<String, TransactionRecord> = BTreeMap::new();
+        for entry in entries {
+            let kind = match entry.detail.category {
+                GetTransactionResultDetailCategory::Send => TransactionKind::Send,
+                _ => TransactionKind::Receive,
+            };
+            let txid = entry.info.txid.to_string();
+            let record = records
+                .entry(txid.clone())
+                .or_insert_with(|| TransactionRecord {
+                    txid,
+                    kind: kind.clone(),
+                    amount_sat: Some(0),
+                    fee_sat: None,
+                    channel_id: None,
+                    label: None,
+                    block_height: entry.info.blockheight,
+                    confirmations: entry.info.confirmations.max(0) as u32,
+                    timestamp: entry.info.time,
+                });
+            // a payment to ourself has both the entries, the send wins.
+            if kind == TransactionKind::Send && record.kind != TransactionKind::Send {
+                record.kind = TransactionKind::Send;
+                record.amount_sat = Some(0);
+            }
+            if record.kind == kind {
+                let amount = entry.detail.amount.to_sat().unsigned_abs();
+                record.amount_sat = record.amount_sat.map(|sum| sum + amount);
+            }
+            if let Some(fee) = entry.detail.fee {
+                record.fee_sat = Some(fee.to_sat().unsigned_abs());
+            }
+        }
+        Ok(records.into_values().collect())
+    }
+
     fn restore(conf: Arc<LampoConf>, mnemonic_words: &str) -> error::Result<Self>
     where
         Self: Sized,
     {
-        let (wallet, keymanager) = CoreWalletManager::build_wallet(conf.clone(), mnemonic_words)?;
+        let (wallets, keymanager) = CoreWalletManager::build_wallet(conf.clone(), mnemonic_words)?;
 
         let rpc = Client::new(
             conf.core_url
@@ -329,7 +638,7 @@ impl WalletManager for CoreWalletManager {
             ),
         )?;
 
-        Self::configure_bitcoin_wallet(&rpc, conf.clone(), wallet)?;
+        Self::configure_bitcoin_wallet(&rpc, conf.clone(), wallets)?;
         Ok(Self {
             rpc,
             keymanager: keymanager.into(),
@@ -348,9 +657,9 @@ impl TryFrom<(PrivateKey, Option<String>, Arc<LampoConf>)> for CoreWalletManager
 
     fn try_from(value: (PrivateKey, Option<String>, Arc<LampoConf>)) -> Result<Self, Self::Error> {
         let conf = value.2;
-        let (wallet, keymanager) = Self::build_from_private_key(value.0, value.1)?;
+        let (wallets, keymanager) = Self::build_from_private_key(value.0, value.1)?;
         let rpc = Self::build_bitcoin_rpc(conf.clone(), None)?;
-        let wallet_name = Self::configure_bitcoin_wallet(&rpc, conf.clone(), wallet)?;
+        let wallet_name = Self::configure_bitcoin_wallet(&rpc, conf.clone(), wallets)?;
         let rpc = Self::build_bitcoin_rpc(conf.clone(), Some(&wallet_name))?;
 
         Ok(Self {
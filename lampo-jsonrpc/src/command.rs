@@ -1,4 +1,5 @@
 //! Command Core Definition
+use std::time::Duration;
 
 /// Context Interface used to pass around any
 /// kind of state that the RPC command will use.
@@ -6,4 +7,11 @@ pub trait Context: Send + Sync {
     type Ctx;
 
     fn ctx(&self) -> &Self::Ctx;
+
+    /// Called before running the RPC `method`.
+    fn on_call_started(&self, _method: &str) {}
+
+    /// Called when the RPC `method` returns, `ok` is false
+    /// if the method returned an error.
+    fn on_call_finished(&self, _method: &str, _elapsed: Duration, _ok: bool) {}
 }
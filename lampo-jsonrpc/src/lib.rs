@@ -10,6 +10,7 @@ use std::os::unix::net::UnixListener;
 use std::os::unix::net::UnixStream;
 use std::sync::Arc;
 use std::thread::JoinHandle;
+use std::time::Instant;
 
 // FIXME: use mio for a better platform support.
 use popol::{Event, Sources, Timeout};
@@ -77,7 +78,11 @@ impl<T: Send + Sync + 'static> Handler<T> {
             }
             .into()));
         };
+        self.ctx.on_call_started(&req.method);
+        let start = Instant::now();
         let resp = callback(self.ctx(), &req.params);
+        self.ctx
+            .on_call_finished(&req.method, start.elapsed(), resp.is_ok());
         Some(resp)
     }
 
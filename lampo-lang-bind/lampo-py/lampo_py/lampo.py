@@ -24,21 +24,44 @@ ffi.cdef(
     int64_t add_jsonrpc_on_unixsocket(struct LampoDaemon *lampod);
 
     /**
-    * Allow to create a lampo daemon from a configuration patch!
+    * Release the lampo daemon created with `new_lampod`.
     */
     void free_lampod(struct LampoDaemon *lampod);
 
     /**
-    * Add a JSON RPC 2.0 Sever that listen on a unixsocket, and return a error code
-    * < 0 is an error happens, or 0 is all goes well.
+    * Return the version of the C ABI exposed by the library.
+    */
+    uint32_t lampo_ffi_version(void);
+
+    /**
+    * Release a string returned by the library.
+    */
+    void lampo_free_string(char *value);
+
+    /**
+    * Return the last error happened inside the library, or null. The
+    * error must be released with `lampo_free_string`.
     */
     const char *lampo_last_errror(void);
 
     /**
-    * Allow to create a lampo daemon from a configuration patch!
+    * Start the node in background, without blocking the
+    * binding language.
     */
     void lampo_listen(struct LampoDaemon *lampod);
 
+    /**
+    * Stop the node started with `lampo_listen`, and return a error code
+    * < 0 is an error happens, or 0 is all goes well.
+    */
+    int64_t lampo_stop(struct LampoDaemon *lampod);
+
+    /**
+    * Call a method of the daemon with a JSON payload, and return the
+    * JSON response or null in case of error, the error can be
+    * read with `lampo_last_errror`. The response must be released
+    * with `lampo_free_string`.
+    */
     const char *lampod_call(struct LampoDaemon *lampod, const char *method, const char *buffer);
 
     /**
@@ -55,6 +78,23 @@ import logging
 from typing import Dict, Any
 
 
+def take_string(value) -> str:
+    """
+    Decode a string returned by the library and release it.
+    """
+    try:
+        return ffi.string(value).decode("utf-8")
+    finally:
+        lampod.lampo_free_string(ffi.cast("char *", value))
+
+
+def last_error() -> Exception:
+    err = lampod.lampo_last_errror()
+    if err == ffi.NULL:
+        return Exception("unknown error")
+    return Exception(take_string(err))
+
+
 class LampoDaemon:
     """
     Python Wrapper around the Lampo Lightning Network Node
@@ -68,8 +108,7 @@ class LampoDaemon:
         logging.info(f'home path {ffi.string(home_path)}')
         self.__inner = lampod.new_lampod(home_path)
         if self.__inner == ffi.NULL:
-            err = ffi.string(lampod.lampo_last_errror())
-            raise Exception(err)
+            raise last_error()
 
     def register_unix_rpc(self):
         """ ""
@@ -91,10 +130,8 @@ class LampoDaemon:
         result = lampod.lampod_call(self.__inner, bytes(method, "utf-8"), bytes(payload, "utf-8"))
         if result == ffi.NULL:
             logging.error("response is null")
-            err = lampod.lampo_last_errror()
-            raise Exception(ffi.string(err))
-        logging.debug(f"raw data {result}")
-        result = ffi.string(result).decode("utf-8")
+            raise last_error()
+        result = take_string(result)
         logging.debug(f"data {result}")
         assert result is not None
         result = json.loads(result)
@@ -102,6 +139,13 @@ class LampoDaemon:
         logging.debug(f"call to `{method}` return {result}")
         return result
 
+    def stop(self):
+        """ ""
+        Stop the lightning node!
+        """
+        if lampod.lampo_stop(self.__inner) < 0:
+            raise last_error()
+
     def __exit__(self, exc_type, exc_value, traceback):
         lampod.free_lampod(self.__inner)
         self.__inner = None
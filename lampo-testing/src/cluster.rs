@@ -0,0 +1,56 @@
+//! Cluster of lampo nodes that share the same bitcoind
+//! regtest node, useful to write cross node integration tests.
+use std::sync::Arc;
+
+use clightning_testing::btc::BtcNode;
+use lampo_common::error;
+use lampod::async_run;
+
+use crate::LampoTesting;
+
+pub struct LampoCluster {
+    pub btc: Arc<BtcNode>,
+    nodes: Vec<Arc<LampoTesting>>,
+}
+
+impl LampoCluster {
+    /// Run a new bitcoind regtest node and `size` lampo nodes, each
+    /// one with its own temporary data dir.
+    pub fn new(size: usize) -> error::Result<Self> {
+        let btc = async_run!(BtcNode::tmp("regtest"))?;
+        Self::with_btc(Arc::new(btc), size)
+    }
+
+    pub fn with_btc(btc: Arc<BtcNode>, size: usize) -> error::Result<Self> {
+        let nodes = (0..size)
+            .map(|_| LampoTesting::new(btc.clone()).map(Arc::new))
+            .collect::<error::Result<Vec<_>>>()?;
+        Ok(Self { btc, nodes })
+    }
+
+    pub fn node(&self, idx: usize) -> Arc<LampoTesting> {
+        self.nodes[idx].clone()
+    }
+
+    pub fn nodes(&self) -> Vec<Arc<LampoTesting>> {
+        self.nodes.clone()
+    }
+
+    /// Fund the wallet of all the nodes, the first one gets
+    /// the mature coinbase.
+    pub fn fund_all(&self) -> error::Result<()> {
+        for node in self.nodes.iter() {
+            node.fund_wallet(101)?;
+        }
+        Ok(())
+    }
+
+    /// Open a channel of `amount` sats between each node and
+    /// the next one, so the nodes are connected in a line.
+    pub fn open_line(&self, amount: u64) -> error::Result<()> {
+        for pair in self.nodes.windows(2) {
+            pair[0].open_channel(&pair[1], amount)?;
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
 //! Lampo test framework.
+pub mod cluster;
+pub mod mock_chain;
+
 pub mod prelude {
     pub use clightning_testing::prelude::*;
     pub use clightning_testing::*;
@@ -13,8 +16,8 @@ use std::time::Duration;
 use clightning_testing::btc::BtcNode;
 use clightning_testing::prelude::*;
 use lampo_common::json;
-use lampo_common::model::response;
 use lampo_common::model::response::NewAddress;
+use lampo_common::model::{request, response};
 use lampod::jsonrpc::channels::json_close_channel;
 use lampod::jsonrpc::inventory::json_network_channels;
 use lampod::jsonrpc::offchain::json_keysend;
@@ -85,6 +88,7 @@ impl LampoTesting {
             Some(port.into()),
         )?;
         let core_url = format!("127.0.0.1:{}", btc.port);
+        lampo_conf.node = "core".to_owned();
         lampo_conf.core_pass = Some(btc.pass.clone());
         lampo_conf.core_url = Some(core_url);
         lampo_conf.core_user = Some(btc.user.clone());
@@ -94,7 +98,7 @@ impl LampoTesting {
             .force_announced_channel_preference = false;
         let (wallet, mnemonic) = CoreWalletManager::new(Arc::new(lampo_conf.clone()))?;
         let wallet = Arc::new(wallet);
-        let mut lampo = LampoDaemon::new(lampo_conf.clone(), wallet.clone());
+        let mut lampo = LampoDaemon::new(lampo_conf.clone(), wallet.clone())?;
         let node = BitcoinCore::new(
             &format!("127.0.0.1:{}", btc.port),
             &btc.user,
@@ -173,6 +177,99 @@ impl LampoTesting {
         Ok(address)
     }
 
+    /// Mine `blocks` to a new address of the node without
+    /// waiting that the node sees them.
+    pub fn generate(&self, blocks: u64) -> error::Result<()> {
+        use clightning_testing::prelude::bitcoincore_rpc::RpcApi;
+
+        let address: NewAddress = self.lampod().call("newaddr", json::json!({}))?;
+        let address =
+            bitcoincore_rpc::bitcoin::Address::from_str(&address.address)?.assume_checked();
+        self.btc.rpc().generate_to_address(blocks, &address)?;
+        Ok(())
+    }
+
+    pub fn connect(&self, other: &LampoTesting) -> error::Result<response::Connect> {
+        self.lampod().call(
+            "connect",
+            request::Connect {
+                node_id: other.info.node_id.clone(),
+                addr: Some("127.0.0.1".to_owned()),
+                port: Some(other.port),
+            },
+        )
+    }
+
+    /// Open a channel with `other` and wait until both the
+    /// nodes see the channel ready, the wallet must be already funded.
+    pub fn open_channel(&self, other: &LampoTesting, amount: u64) -> error::Result<()> {
+        let _: json::Value = self.lampod().call(
+            "fundchannel",
+            request::OpenChannel {
+                node_id: other.info.node_id.clone(),
+                amount,
+                public: true,
+                addr: Some("127.0.0.1".to_owned()),
+                port: Some(other.port),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
+            },
+        )?;
+        wait!(|| {
+            let ready = [self, other].iter().all(|node| {
+                let Ok(channels) = node
+                    .lampod()
+                    .call::<_, response::Channels>("channels", json::json!({}))
+                else {
+                    return false;
+                };
+                channels.channels.iter().any(|channel| {
+                    channel.ready
+                        && (channel.peer_id == self.info.node_id
+                            || channel.peer_id == other.info.node_id)
+                })
+            });
+            if ready {
+                return Ok(());
+            }
+            self.generate(6).map_err(|_| ())?;
+            Err(())
+        });
+        Ok(())
+    }
+
+    /// Ask to `other` an invoice of `amount_msat` and pay it.
+    pub fn pay_invoice(
+        &self,
+        other: &LampoTesting,
+        amount_msat: u64,
+    ) -> error::Result<response::PayResult> {
+        let invoice: response::Invoice = other.lampod().call(
+            "invoice",
+            request::GenerateInvoice {
+                description: format!("payment from {}", self.info.node_id),
+                amount_msat: Some(amount_msat),
+                fiat_amount: None,
+                expiring_in: None,
+                qr: None,
+                fallback: None,
+            },
+        )?;
+        self.lampod().call(
+            "pay",
+            request::Pay {
+                invoice_str: invoice.bolt11,
+                amount: None,
+                simulate: None,
+                first_hops: Default::default(),
+                hints: Default::default(),
+                route: None,
+            },
+        )
+    }
+
     pub fn lampod(&self) -> Arc<LampoHandler> {
         self.inner.clone()
     }
@@ -0,0 +1,504 @@
+//! Deterministic chain backend for tests.
+//!
+//! The mock chain never polls anything, the blocks are produced
+//! only when the test calls `mine`, `confirm` or `reorg`, and the
+//! on chain events are emitted to the handler in the same order
+//! every time, so the channel manager sees always the same chain.
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use lampo_common::backend::{AsyncBlockSourceResult, BlockData, BlockHeaderData};
+use lampo_common::backend::{Backend, BackendKind, UtxoResult, WatchedOutput};
+use lampo_common::backend::{BlockSourceError, TxResult};
+use lampo_common::bitcoin::absolute::{Height, LockTime};
+use lampo_common::bitcoin::block::{Header, Version};
+use lampo_common::bitcoin::blockdata::constants::genesis_block;
+use lampo_common::bitcoin::blockdata::script::Builder;
+use lampo_common::bitcoin::hash_types::TxMerkleNode;
+use lampo_common::bitcoin::hashes::Hash;
+use lampo_common::bitcoin::{Block, BlockHash, CompactTarget, Network, OutPoint, Script};
+use lampo_common::bitcoin::{ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use lampo_common::error;
+use lampo_common::event::onchain::OnChainEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::ldk::routing::utxo::UtxoLookupError;
+
+#[derive(Default)]
+struct ChainState {
+    /// The active chain, the genesis block is at index 0.
+    blocks: Vec<Block>,
+    /// Blocks that were removed by a reorg, we keep them to
+    /// be able to answer `get_block` for stale hashes.
+    stale: Vec<Block>,
+    mempool: Vec<Transaction>,
+    /// Number of blocks that we produced, used to make
+    /// unique the blocks mined at the same height after a reorg.
+    mined: u32,
+}
+
+pub struct MockChain {
+    state: Mutex<ChainState>,
+    handler: Mutex<Option<Arc<dyn Handler>>>,
+    fee_rate: Mutex<u32>,
+}
+
+impl Default for MockChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockChain {
+    pub fn new() -> Self {
+        let state = ChainState {
+            blocks: vec![genesis_block(Network::Regtest)],
+            ..Default::default()
+        };
+        Self {
+            state: Mutex::new(state),
+            handler: Mutex::new(None),
+            fee_rate: Mutex::new(253),
+        }
+    }
+
+    fn handler(&self) -> error::Result<Arc<dyn Handler>> {
+        self.handler
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(error::anyhow!("handler is not set"))
+    }
+
+    /// Set the fee rate (sats per 1000 weight unit) returned by the estimation.
+    pub fn set_fee_rate(&self, fee_rate: u32) {
+        *self.fee_rate.lock().unwrap() = fee_rate;
+    }
+
+    pub fn height(&self) -> u32 {
+        self.state.lock().unwrap().blocks.len() as u32 - 1
+    }
+
+    pub fn mempool(&self) -> Vec<Transaction> {
+        self.state.lock().unwrap().mempool.clone()
+    }
+
+    /// Add a transaction inside the mempool without notify
+    /// the node, useful to simulate a transaction made by a peer.
+    pub fn add_transaction(&self, tx: Transaction) {
+        self.state.lock().unwrap().mempool.push(tx);
+    }
+
+    /// Mine `blocks` new blocks, the first one contains all
+    /// the transactions inside the mempool.
+    pub fn mine(&self, blocks: u32) -> error::Result<Vec<BlockHash>> {
+        // fail before touching the mempool
+        self.handler()?;
+        let mut hashes = Vec::new();
+        for _ in 0..blocks {
+            let txs = std::mem::take(&mut self.state.lock().unwrap().mempool);
+            hashes.push(self.mine_block(txs)?);
+        }
+        Ok(hashes)
+    }
+
+    /// Mine a new block that contains only the transaction with `txid`,
+    /// the transaction must be inside the mempool.
+    pub fn confirm(&self, txid: &Txid) -> error::Result<BlockHash> {
+        let tx = {
+            let mut state = self.state.lock().unwrap();
+            let Some(idx) = state.mempool.iter().position(|tx| tx.txid() == *txid) else {
+                error::bail!("transaction `{txid}` is not inside the mempool");
+            };
+            state.mempool.remove(idx)
+        };
+        self.mine_block(vec![tx])
+    }
+
+    /// Disconnect the last `depth` blocks and mine `depth + 1` empty
+    /// blocks on top of the fork point, so the new chain is the
+    /// longest one.
+    ///
+    /// The transactions of the disconnected blocks go back inside
+    /// the mempool, so the test can decide when (and if) confirm them again.
+    pub fn reorg(&self, depth: u32) -> error::Result<Vec<BlockHash>> {
+        let handler = self.handler()?;
+        let (disconnected, tip, height) = {
+            let mut state = self.state.lock().unwrap();
+            if depth as usize >= state.blocks.len() {
+                error::bail!(
+                    "impossible reorg `{depth}` blocks, the chain height is `{}`",
+                    state.blocks.len() - 1
+                );
+            }
+            let fork_point = state.blocks.len() - depth as usize;
+            let disconnected = state.blocks.split_off(fork_point);
+            let mut txs = disconnected
+                .iter()
+                .flat_map(|block| block.txdata.iter().skip(1).cloned())
+                .collect::<Vec<_>>();
+            txs.append(&mut state.mempool);
+            state.mempool = txs;
+            state.stale.extend(disconnected.iter().cloned());
+            // SAFETY: we never disconnect the genesis block.
+            let tip = state.blocks.last().unwrap().header;
+            (disconnected, tip, state.blocks.len() as u32 - 1)
+        };
+        // notify the disconnection from the tip to the fork point
+        for block in disconnected.iter().rev() {
+            for tx in block.txdata.iter().skip(1) {
+                handler.emit(Event::OnChain(OnChainEvent::UnconfirmedTransaction(
+                    tx.txid(),
+                )));
+            }
+        }
+        handler.emit(Event::OnChain(OnChainEvent::NewBestBlock((
+            tip,
+            Height::from_consensus(height)?,
+        ))));
+
+        let mut hashes = Vec::new();
+        for _ in 0..depth + 1 {
+            hashes.push(self.mine_block(vec![])?);
+        }
+        Ok(hashes)
+    }
+
+    fn mine_block(&self, txs: Vec<Transaction>) -> error::Result<BlockHash> {
+        let handler = self.handler()?;
+        let (block, height) = {
+            let mut state = self.state.lock().unwrap();
+            state.mined += 1;
+            // SAFETY: the chain contains always the genesis block.
+            let prev = state.blocks.last().unwrap().header;
+            let height = state.blocks.len() as u32;
+            let mut txdata = vec![Self::coinbase(height)];
+            txdata.extend(txs);
+            let mut block = Block {
+                header: Header {
+                    version: Version::TWO,
+                    prev_blockhash: prev.block_hash(),
+                    merkle_root: TxMerkleNode::all_zeros(),
+                    time: prev.time + 600,
+                    bits: CompactTarget::from_consensus(0x207fffff),
+                    nonce: state.mined,
+                },
+                txdata,
+            };
+            // SAFETY: the block contains always the coinbase.
+            block.header.merkle_root = block.compute_merkle_root().unwrap();
+            state.blocks.push(block.clone());
+            (block, height)
+        };
+
+        // ldk wants the transactions confirmed before the best block update
+        let height = Height::from_consensus(height)?;
+        for (idx, tx) in block.txdata.iter().enumerate().skip(1) {
+            handler.emit(Event::OnChain(OnChainEvent::ConfirmedTransaction((
+                tx.clone(),
+                idx as u32,
+                block.header,
+                height,
+            ))));
+        }
+        handler.emit(Event::OnChain(OnChainEvent::NewBestBlock((
+            block.header,
+            height,
+        ))));
+        handler.emit(Event::OnChain(OnChainEvent::NewBlock(block.clone())));
+        Ok(block.block_hash())
+    }
+
+    fn coinbase(height: u32) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Builder::new().push_int(height as i64).into_script(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 50 * 100_000_000,
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    fn find_transaction(&self, txid: &Txid) -> Option<TxResult> {
+        let state = self.state.lock().unwrap();
+        for (height, block) in state.blocks.iter().enumerate() {
+            if let Some((idx, tx)) = block
+                .txdata
+                .iter()
+                .enumerate()
+                .find(|(_, tx)| tx.txid() == *txid)
+            {
+                return Some(TxResult::Confirmed((
+                    tx.clone(),
+                    idx as u32,
+                    block.header,
+                    Height::from_consensus(height as u32).ok()?,
+                )));
+            }
+        }
+        state
+            .mempool
+            .iter()
+            .find(|tx| tx.txid() == *txid)
+            .map(|tx| TxResult::Unconfirmed(tx.clone()))
+    }
+}
+
+impl Backend for MockChain {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Mock
+    }
+
+    fn network(&self) -> error::Result<Network> {
+        Ok(Network::Regtest)
+    }
+
+    fn fee_rate_estimation(&self, _: u64) -> error::Result<u32> {
+        Ok(*self.fee_rate.lock().unwrap())
+    }
+
+    fn minimum_mempool_fee(&self) -> error::Result<u32> {
+        Ok(253)
+    }
+
+    fn brodcast_tx(&self, tx: &Transaction) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.mempool.iter().any(|inner| inner.txid() == tx.txid()) {
+                return;
+            }
+            state.mempool.push(tx.clone());
+        }
+        if let Ok(handler) = self.handler() {
+            handler.emit(Event::OnChain(OnChainEvent::SendRawTransaction(tx.clone())));
+        }
+    }
+
+    fn is_lightway(&self) -> bool {
+        false
+    }
+
+    // We feed full blocks, so there is nothing to watch.
+    fn watch_utxo(&self, _: &Txid, _: &Script) {}
+
+    fn register_output(&self, _: WatchedOutput) -> Option<(usize, Transaction)> {
+        None
+    }
+
+    fn get_header<'a>(
+        &'a self,
+        _header_hash: &'a BlockHash,
+        _height_hint: Option<u32>,
+    ) -> AsyncBlockSourceResult<'a, BlockHeaderData> {
+        Box::pin(async {
+            Err(BlockSourceError::persistent(
+                "`get_header` is called only for lightway nodes",
+            ))
+        })
+    }
+
+    fn get_block<'a>(&'a self, header_hash: &'a BlockHash) -> error::Result<BlockData> {
+        let state = self.state.lock().unwrap();
+        let block = state
+            .blocks
+            .iter()
+            .chain(state.stale.iter())
+            .find(|block| block.block_hash() == *header_hash)
+            .ok_or(error::anyhow!("block `{header_hash}` not found"))?;
+        Ok(BlockData::FullBlock(block.clone()))
+    }
+
+    fn get_best_block(&self) -> error::Result<(BlockHash, Option<u32>)> {
+        let state = self.state.lock().unwrap();
+        // SAFETY: the chain contains always the genesis block.
+        let tip = state.blocks.last().unwrap();
+        Ok((tip.block_hash(), Some(state.blocks.len() as u32 - 1)))
+    }
+
+    fn get_utxo(&self, _: &BlockHash, _: u64) -> UtxoResult {
+        UtxoResult::Sync(Err(UtxoLookupError::UnknownTx))
+    }
+
+    fn get_utxo_by_txid(&self, txid: &Txid, script: &Script) -> error::Result<TxResult> {
+        let result = self.get_transaction(txid)?;
+        let TxResult::Confirmed((tx, _, header, height)) = result else {
+            return Ok(result);
+        };
+        let Some(idx) = tx
+            .output
+            .iter()
+            .position(|out| out.script_pubkey.as_script() == script)
+        else {
+            error::bail!("transaction `{txid}` do not contains the script `{script}`");
+        };
+        Ok(TxResult::Confirmed((tx, idx as u32, header, height)))
+    }
+
+    fn set_handler(&self, handler: Arc<dyn Handler>) {
+        *self.handler.lock().unwrap() = Some(handler);
+    }
+
+    // All the transactions are inside the blocks that we emit,
+    // so there is no need to track them.
+    fn manage_transactions(&self, _: &mut Vec<Txid>) -> error::Result<()> {
+        Ok(())
+    }
+
+    fn listen(self: Arc<Self>) -> error::Result<JoinHandle<()>> {
+        // the blocks are produced by the test, so there is nothing to poll.
+        Ok(std::thread::spawn(|| {}))
+    }
+
+    fn get_transaction(&self, txid: &Txid) -> error::Result<TxResult> {
+        self.find_transaction(txid)
+            .ok_or(error::anyhow!("transaction `{txid}` not found"))
+    }
+
+    fn process_transactions(&self) -> error::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use lampo_common::backend::{Backend, BlockData, TxResult};
+    use lampo_common::bitcoin::absolute::LockTime;
+    use lampo_common::bitcoin::{ScriptBuf, Transaction, TxOut};
+    use lampo_common::chan;
+    use lampo_common::event::onchain::OnChainEvent;
+    use lampo_common::event::Event;
+    use lampo_common::handler::Handler;
+
+    use super::MockChain;
+
+    /// Keep the on chain events emitted by the mock chain.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<OnChainEvent>>);
+
+    impl Recorder {
+        fn take(&self) -> Vec<OnChainEvent> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    impl Handler for Recorder {
+        fn events(&self) -> chan::Receiver<Event> {
+            chan::unbounded().1
+        }
+
+        fn emit(&self, event: Event) {
+            if let Event::OnChain(event) = event {
+                self.0.lock().unwrap().push(event);
+            }
+        }
+    }
+
+    fn chain() -> (MockChain, Arc<Recorder>) {
+        let chain = MockChain::new();
+        let recorder = Arc::new(Recorder::default());
+        chain.set_handler(recorder.clone());
+        (chain, recorder)
+    }
+
+    fn transaction(value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value,
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    fn best_heights(events: &[OnChainEvent]) -> Vec<u32> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                OnChainEvent::NewBestBlock((_, height)) => Some(height.to_consensus_u32()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mine() {
+        let (chain, recorder) = chain();
+        let hashes = chain.mine(3).unwrap();
+        assert_eq!(chain.height(), 3);
+        let (tip, height) = chain.get_best_block().unwrap();
+        assert_eq!(tip, hashes[2]);
+        assert_eq!(height, Some(3));
+        assert_eq!(best_heights(&recorder.take()), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn confirm() {
+        let (chain, recorder) = chain();
+        let tx = transaction(1_000);
+        chain.add_transaction(tx.clone());
+        chain.add_transaction(transaction(2_000));
+        chain.confirm(&tx.txid()).unwrap();
+
+        // the other transaction is still inside the mempool
+        assert_eq!(chain.mempool().len(), 1);
+        let events = recorder.take();
+        assert!(matches!(
+            &events[0],
+            OnChainEvent::ConfirmedTransaction((confirmed, 1, _, height))
+                if confirmed.txid() == tx.txid() && height.to_consensus_u32() == 1
+        ));
+        assert_eq!(best_heights(&events), vec![1]);
+        assert!(matches!(
+            chain.get_transaction(&tx.txid()).unwrap(),
+            TxResult::Confirmed(_)
+        ));
+        assert!(chain.confirm(&tx.txid()).is_err());
+    }
+
+    #[test]
+    fn reorg() {
+        let (chain, recorder) = chain();
+        let tx = transaction(1_000);
+        chain.add_transaction(tx.clone());
+        let stale = chain.mine(2).unwrap();
+        recorder.take();
+
+        chain.reorg(2).unwrap();
+        assert_eq!(chain.height(), 3);
+        let events = recorder.take();
+        assert!(matches!(
+            &events[0],
+            OnChainEvent::UnconfirmedTransaction(txid) if *txid == tx.txid()
+        ));
+        // back to the fork point, then the new blocks
+        assert_eq!(best_heights(&events), vec![0, 1, 2, 3]);
+
+        // the transaction goes back inside the mempool
+        assert!(matches!(
+            chain.get_transaction(&tx.txid()).unwrap(),
+            TxResult::Unconfirmed(_)
+        ));
+        // the stale blocks are still known
+        assert!(matches!(
+            chain.get_block(&stale[0]).unwrap(),
+            BlockData::FullBlock(_)
+        ));
+        let (tip, _) = chain.get_best_block().unwrap();
+        assert_ne!(tip, stale[1]);
+
+        chain.confirm(&tx.txid()).unwrap();
+        assert_eq!(chain.height(), 4);
+        assert!(chain.reorg(5).is_err());
+    }
+}
@@ -0,0 +1,289 @@
+//! UniFFI bindings for lampo, they expose the core
+//! operations of the node to Swift and Kotlin apps
+//! on top of the `LampoBuilder`.
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use lampo_bitcoind::BitcoinCore;
+use lampo_common::conf::{LampoConf, Network};
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::Event;
+use lampo_common::json;
+use lampo_common::model::request;
+use lampo_common::model::response::PaymentState;
+use lampo_common::wallet::WalletManager;
+use lampo_core_wallet::CoreWalletManager;
+use lampod::builder::{EventSink, LampoBuilder};
+
+uniffi::setup_scaffolding!();
+
+#[derive(Debug, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum LampoError {
+    Config(String),
+    Node(String),
+}
+
+impl Display for LampoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Config(err) => write!(f, "configuration error: {err}"),
+            Self::Node(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<lampo_common::error::Error> for LampoError {
+    fn from(err: lampo_common::error::Error) -> Self {
+        Self::Node(format!("{err}"))
+    }
+}
+
+/// Simplified view of the events emitted by the node.
+#[derive(Debug, uniffi::Enum)]
+pub enum NodeEvent {
+    PeerConnected {
+        node_id: String,
+    },
+    ChannelReady {
+        node_id: String,
+        channel_id: String,
+    },
+    ChannelClosed {
+        channel_id: String,
+        message: String,
+    },
+    PaymentSucceeded {
+        payment_hash: Option<String>,
+    },
+    PaymentFailed {
+        payment_hash: Option<String>,
+    },
+    PaymentReceived {
+        payment_hash: String,
+        amount_msat: u64,
+    },
+    Other {
+        description: String,
+    },
+}
+
+impl From<Event> for NodeEvent {
+    fn from(event: Event) -> Self {
+        match event {
+            Event::Lightning(LightningEvent::PeerConnect {
+                counterparty_node_id,
+            }) => Self::PeerConnected {
+                node_id: counterparty_node_id.to_string(),
+            },
+            Event::Lightning(LightningEvent::ChannelReady {
+                counterparty_node_id,
+                channel_id,
+                ..
+            }) => Self::ChannelReady {
+                node_id: counterparty_node_id.to_string(),
+                channel_id: channel_id.to_string(),
+            },
+            Event::Lightning(LightningEvent::CloseChannelEvent {
+                channel_id,
+                message,
+                ..
+            }) => Self::ChannelClosed {
+                channel_id,
+                message,
+            },
+            Event::Lightning(LightningEvent::PaymentEvent {
+                state: PaymentState::Success,
+                payment_hash,
+                ..
+            }) => Self::PaymentSucceeded { payment_hash },
+            Event::Lightning(LightningEvent::PaymentEvent { payment_hash, .. }) => {
+                Self::PaymentFailed { payment_hash }
+            }
+            Event::Lightning(LightningEvent::PaymentReceived {
+                payment_hash,
+                amount_msat,
+                ..
+            }) => Self::PaymentReceived {
+                payment_hash,
+                amount_msat,
+            },
+            event => Self::Other {
+                description: format!("{:?}", event),
+            },
+        }
+    }
+}
+
+#[uniffi::export(callback_interface)]
+pub trait EventListener: Send + Sync {
+    fn on_event(&self, event: NodeEvent);
+}
+
+struct ListenerSink(Box<dyn EventListener>);
+
+impl EventSink for ListenerSink {
+    fn on_event(&self, event: Event) {
+        self.0.on_event(NodeEvent::from(event));
+    }
+}
+
+#[derive(uniffi::Object)]
+pub struct LampoNode {
+    inner: Mutex<Option<lampod::builder::LampoNode>>,
+    /// The mnemonic of the wallet generated by `start`.
+    mnemonic: Mutex<Option<String>>,
+}
+
+impl LampoNode {
+    fn with_node<T, F>(&self, f: F) -> Result<T, LampoError>
+    where
+        F: FnOnce(&lampod::builder::LampoNode) -> lampo_common::error::Result<T>,
+    {
+        let node = self.inner.lock().unwrap();
+        let Some(node) = node.as_ref() else {
+            return Err(LampoError::Node("the node is stopped".to_owned()));
+        };
+        Ok(f(node)?)
+    }
+}
+
+#[uniffi::export]
+impl LampoNode {
+    /// Start a new node, the configuration is read from `data_dir/network`,
+    /// if the `mnemonic` is specified the wallet is restored from it,
+    /// otherwise a new wallet is generated and its mnemonic is returned
+    /// by `take_mnemonic`.
+    #[uniffi::constructor]
+    pub fn start(
+        data_dir: String,
+        network: String,
+        mnemonic: Option<String>,
+        listener: Option<Box<dyn EventListener>>,
+    ) -> Result<Arc<Self>, LampoError> {
+        let network =
+            Network::from_str(&network).map_err(|err| LampoError::Config(format!("{err}")))?;
+        let conf = LampoConf::new(Some(data_dir), Some(network), None)
+            .map_err(|err| LampoError::Config(format!("{err}")))?;
+        conf.validate()
+            .map_err(|err| LampoError::Config(format!("{err}")))?;
+
+        // SAFETY: the validation checks that the core options are specified
+        let backend = BitcoinCore::new(
+            conf.core_url.as_ref().unwrap(),
+            conf.core_user.as_ref().unwrap(),
+            conf.core_pass.as_ref().unwrap(),
+            Arc::new(false),
+            None,
+        )
+        .map_err(|err| LampoError::Node(format!("{err}")))?;
+        let (wallet, mnemonic) = match mnemonic {
+            Some(mnemonic) => (
+                CoreWalletManager::restore(Arc::new(conf.clone()), &mnemonic)?,
+                None,
+            ),
+            None => {
+                let (wallet, mnemonic) = CoreWalletManager::new(Arc::new(conf.clone()))?;
+                (wallet, Some(mnemonic))
+            }
+        };
+
+        let mut builder = LampoBuilder::new(conf)
+            .with_backend(Arc::new(backend))
+            .with_wallet_manager(Arc::new(wallet));
+        if let Some(listener) = listener {
+            builder = builder.with_event_sink(Arc::new(ListenerSink(listener)));
+        }
+        let node = builder.build()?;
+        Ok(Arc::new(Self {
+            inner: Mutex::new(Some(node)),
+            mnemonic: Mutex::new(mnemonic),
+        }))
+    }
+
+    /// Return the mnemonic of the wallet generated by `start`, only
+    /// the first time, so the app must store it in a safe way. It is
+    /// `None` when the wallet was restored.
+    pub fn take_mnemonic(&self) -> Option<String> {
+        self.mnemonic.lock().unwrap().take()
+    }
+
+    /// Stop the node and wait that the state is persisted.
+    pub fn stop(&self) -> Result<(), LampoError> {
+        let Some(node) = self.inner.lock().unwrap().take() else {
+            return Err(LampoError::Node("the node is already stopped".to_owned()));
+        };
+        node.stop()?;
+        node.join()?;
+        Ok(())
+    }
+
+    /// Return the node info encoded as JSON.
+    pub fn get_info(&self) -> Result<String, LampoError> {
+        let info = self.with_node(|node| node.get_info())?;
+        json::to_string(&info).map_err(|err| LampoError::Node(format!("{err}")))
+    }
+
+    pub fn new_address(&self) -> Result<String, LampoError> {
+        self.with_node(|node| Ok(node.new_address()?.address))
+    }
+
+    /// Return the bolt11 invoice.
+    pub fn invoice(
+        &self,
+        amount_msat: Option<u64>,
+        description: String,
+        expiring_in: Option<u32>,
+    ) -> Result<String, LampoError> {
+        let request = request::GenerateInvoice {
+            amount_msat,
+            fiat_amount: None,
+            description,
+            expiring_in,
+            qr: None,
+            fallback: None,
+        };
+        self.with_node(|node| Ok(node.invoice(request)?.bolt11))
+    }
+
+    /// Pay a bolt11 invoice or a bolt12 offer, and return the
+    /// result encoded as JSON.
+    pub fn pay(&self, invoice: String, amount_msat: Option<u64>) -> Result<String, LampoError> {
+        let request = request::Pay {
+            invoice_str: invoice,
+            amount: amount_msat,
+            simulate: None,
+            first_hops: Default::default(),
+            hints: Default::default(),
+            route: None,
+        };
+        let result = self.with_node(|node| node.pay(request))?;
+        json::to_string(&result).map_err(|err| LampoError::Node(format!("{err}")))
+    }
+
+    /// Open a channel and return the result encoded as JSON.
+    pub fn open_channel(
+        &self,
+        node_id: String,
+        addr: Option<String>,
+        port: Option<u64>,
+        amount_sat: u64,
+        public: bool,
+    ) -> Result<String, LampoError> {
+        let request = request::OpenChannel {
+            node_id,
+            addr,
+            port,
+            amount: amount_sat,
+            public,
+            external_funding: None,
+            push_msat: None,
+            feerate: None,
+            utxos: None,
+        };
+        let result = self.with_node(|node| node.open_channel(request))?;
+        json::to_string(&result).map_err(|err| LampoError::Node(format!("{err}")))
+    }
+}
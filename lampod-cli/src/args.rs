@@ -1,6 +1,6 @@
 use radicle_term as term;
 
-use lampo_common::conf::{LampoConf, Network};
+use lampo_common::conf::{parse_network, LampoConf, Network};
 use lampo_common::error;
 
 struct Help {
@@ -22,7 +22,8 @@ Usage
 Options
 
     -d | --data-dir    Override the default path of the config field
-    -n | --network     Set the network for lampo
+    -n | --network     Set the network for lampo, it can be repeated to run
+                       one node instance for each network in the same process
     -h | --help        Print help
 
     --log-file         Redirect the lampo logs on the file
@@ -32,15 +33,25 @@ Options
     --core-user        Set the username of the bitcoin core backend
     --core-pass        Set the password of the bitcoin core backend
     --restore-wallet   Restore a wallet from a mnemonic 
+    --recover-from-scb Ask the peers to close the channels stored
+                       inside the static channel backup
+
+Environment
+
+    The `LAMPO_*` variables (e.g: `LAMPO_NETWORK`, `LAMPO_CORE_URL`)
+    override the configuration file, the options override both.
 "#,
 };
 
 #[derive(Debug)]
 pub struct LampoCliArgs {
     pub data_dir: Option<String>,
-    pub network: Option<String>,
+    /// One node instance is started for each network.
+    pub networks: Vec<String>,
     pub client: Option<String>,
     pub restore_wallet: bool,
+    /// Path of the static channel backup to recover.
+    pub recover_from_scb: Option<String>,
     pub log_level: Option<String>,
     pub log_file: Option<String>,
     pub bitcoind_url: Option<String>,
@@ -48,47 +59,67 @@ pub struct LampoCliArgs {
     pub bitcoind_pass: Option<String>,
 }
 
-impl TryInto<LampoConf> for LampoCliArgs {
-    type Error = error::Error;
-
-    fn try_into(self) -> Result<LampoConf, Self::Error> {
-        let mut conf = LampoConf::default();
+impl LampoCliArgs {
+    /// Build the configuration of each node instance that lampod
+    /// should run. Each instance lives inside `<data-dir>/<network>`
+    /// and it has its own JSON RPC socket.
+    pub fn confs(&self) -> error::Result<Vec<LampoConf>> {
+        if self.networks.is_empty() {
+            return Ok(vec![self.conf(None)?]);
+        }
+        let mut confs: Vec<LampoConf> = Vec::new();
+        for network in &self.networks {
+            let conf = self.conf(Some(network))?;
+            if confs.iter().any(|other| other.path() == conf.path()) {
+                error::bail!("Network `{network}` specified more than once");
+            }
+            confs.push(conf);
+        }
+        Ok(confs)
+    }
 
-        // if network is not specified, set the testnet dir
-        let network = self.network.unwrap_or(String::from("testnet"));
-        conf.network = match network.as_str() {
-            "bitcoin" => Network::Bitcoin,
-            "testnet" => Network::Testnet,
-            "regtest" => Network::Regtest,
-            "signet" => Network::Signet,
-            _ => error::bail!("Invalid network {network}"),
+    fn conf(&self, network: Option<&str>) -> error::Result<LampoConf> {
+        let conf = LampoConf::default();
+
+        // if network is not specified, the `LAMPO_NETWORK` env or
+        // the testnet is used.
+        let network = match network {
+            Some("bitcoin") => Some(Network::Bitcoin),
+            Some("testnet") => Some(Network::Testnet),
+            Some("regtest") => Some(Network::Regtest),
+            Some("signet") => Some(Network::Signet),
+            Some("testnet4") => Some(parse_network("testnet4")?),
+            Some(network) => error::bail!("Invalid network {network}"),
+            None => None,
         };
 
-        let path = self.data_dir.unwrap_or(conf.root_path);
-        // FIXME: this override the full configuration, we should merge the two
-        conf = LampoConf::new(Some(path), Some(conf.network), None)?;
+        let path = self.data_dir.clone().unwrap_or(conf.root_path);
+        let mut conf = LampoConf::new(Some(path), network, None)?;
         conf.prepare_dirs()?;
 
         log::debug!(target: "lampod-cli", "lampo data dir `{}`", conf.path());
         log::debug!(target: "lampod-cli", "client from args {:?}", self.client);
         // Override the lampo conf with the args from the cli
-        if let Some(node) = self.client {
+        if let Some(node) = &self.client {
             conf.node = node.clone();
         }
         if self.bitcoind_url.is_some() {
-            conf.core_url = self.bitcoind_url;
+            conf.core_url = self.bitcoind_url.clone();
         }
         if self.bitcoind_user.is_some() {
-            conf.core_user = self.bitcoind_user;
+            conf.core_user = self.bitcoind_user.clone();
         }
         if self.bitcoind_pass.is_some() {
-            conf.core_pass = self.bitcoind_pass;
+            conf.core_pass = self.bitcoind_pass.clone();
         }
         if self.log_file.is_some() {
-            conf.log_file = self.log_file;
+            conf.log_file = self.log_file.clone();
+        }
+        if let Some(level) = &self.log_level {
+            conf.log_level = level.clone();
         }
-        if self.log_level.is_some() {
-            conf.log_level = self.log_level.unwrap();
+        if self.recover_from_scb.is_some() {
+            conf.recover_from_scb = self.recover_from_scb.clone();
         }
         Ok(conf)
     }
@@ -100,12 +131,13 @@ pub fn parse_args() -> Result<LampoCliArgs, lexopt::Error> {
     let mut data_dir: Option<String> = None;
     let mut log_file: Option<String> = None;
     let mut level: Option<String> = None;
-    let mut network: Option<String> = None;
+    let mut networks: Vec<String> = Vec::new();
     let mut client: Option<String> = None;
     let mut bitcoind_url: Option<String> = None;
     let mut bitcoind_user: Option<String> = None;
     let mut bitcoind_pass: Option<String> = None;
     let mut restore_wallet = false;
+    let mut recover_from_scb: Option<String> = None;
 
     let mut parser = lexopt::Parser::from_env();
     while let Some(arg) = parser.next()? {
@@ -124,7 +156,7 @@ pub fn parse_args() -> Result<LampoCliArgs, lexopt::Error> {
             }
             Short('n') | Long("network") => {
                 let val: String = parser.value()?.parse()?;
-                network = Some(val);
+                networks.push(val);
             }
             Long("client") => {
                 let var: String = parser.value()?.parse()?;
@@ -145,6 +177,10 @@ pub fn parse_args() -> Result<LampoCliArgs, lexopt::Error> {
             Long("restore-wallet") => {
                 restore_wallet = true;
             }
+            Long("recover-from-scb") => {
+                let var: String = parser.value()?.parse()?;
+                recover_from_scb = Some(var);
+            }
             Long("help") => {
                 let _ = print_help();
                 std::process::exit(0);
@@ -155,9 +191,10 @@ pub fn parse_args() -> Result<LampoCliArgs, lexopt::Error> {
 
     Ok(LampoCliArgs {
         data_dir,
-        network,
+        networks,
         client,
         restore_wallet,
+        recover_from_scb,
         log_file,
         bitcoind_url,
         bitcoind_pass,
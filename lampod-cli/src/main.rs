@@ -11,6 +11,7 @@ use std::thread::JoinHandle;
 use radicle_term as term;
 
 use lampo_bitcoind::BitcoinCore;
+use lampo_cbf::LampoCbf;
 use lampo_common::backend::Backend;
 use lampo_common::conf::LampoConf;
 use lampo_common::error;
@@ -18,20 +19,56 @@ use lampo_common::logger;
 use lampo_core_wallet::CoreWalletManager;
 use lampo_jsonrpc::Handler;
 use lampo_jsonrpc::JSONRPCv2;
-use lampod::chain::WalletManager;
+use lampod::chain::{LampoBackendFailover, WalletManager};
 use lampod::jsonrpc::channels::json_close_channel;
 use lampod::jsonrpc::channels::json_list_channels;
+use lampod::jsonrpc::channels::json_list_forwards;
+use lampod::jsonrpc::channels::{json_export_channel_backup, json_set_channel};
+use lampod::jsonrpc::channels::{
+    json_fail_intercepted, json_forward_intercepted, json_intercept_scid,
+};
+use lampod::jsonrpc::channels::{json_force_close_channel, json_list_force_closes};
 use lampod::jsonrpc::inventory::get_info;
+use lampod::jsonrpc::inventory::json_commands;
+use lampod::jsonrpc::inventory::json_configdump;
+#[cfg(debug_assertions)]
+use lampod::jsonrpc::inventory::json_dev_pending_events;
+use lampod::jsonrpc::inventory::{json_export_graph, json_import_graph};
+use lampod::jsonrpc::inventory::{json_list_gossip_channels, json_list_nodes};
+use lampod::jsonrpc::inventory::{json_metrics, json_stats};
+use lampod::jsonrpc::lsp::{
+    json_buy_inbound, json_list_lsp_orders, json_lsp_info, json_lsp_invoice,
+};
+use lampod::jsonrpc::offchain::json_batch_invoice;
+use lampod::jsonrpc::offchain::json_cancel_invoice;
 use lampod::jsonrpc::offchain::json_decode_invoice;
+use lampod::jsonrpc::offchain::json_get_route;
+use lampod::jsonrpc::offchain::json_hold_invoice;
 use lampod::jsonrpc::offchain::json_invoice;
 use lampod::jsonrpc::offchain::json_keysend;
-use lampod::jsonrpc::offchain::json_offer;
+use lampod::jsonrpc::offchain::json_list_invoices;
+use lampod::jsonrpc::offchain::json_lookup_invoice;
+use lampod::jsonrpc::offchain::json_lookup_payment;
 use lampod::jsonrpc::offchain::json_pay;
+use lampod::jsonrpc::offchain::json_probe;
+use lampod::jsonrpc::offchain::json_settle_invoice;
+use lampod::jsonrpc::offchain::json_traceroute;
+use lampod::jsonrpc::offchain::{json_claim_refund, json_refund};
+use lampod::jsonrpc::offchain::{json_list_offers, json_offer, json_pay_offer};
+use lampod::jsonrpc::offchain::{json_phantom_hints, json_phantom_invoice};
+use lampod::jsonrpc::onchain::json_consolidate;
 use lampod::jsonrpc::onchain::json_estimate_fees;
 use lampod::jsonrpc::onchain::json_funds;
+use lampod::jsonrpc::onchain::json_list_sweeps;
+use lampod::jsonrpc::onchain::json_list_transactions;
 use lampod::jsonrpc::onchain::json_new_addr;
-use lampod::jsonrpc::open_channel::json_open_channel;
-use lampod::jsonrpc::peer_control::json_connect;
+use lampod::jsonrpc::onchain::json_set_label;
+use lampod::jsonrpc::onchain::json_withdraw;
+use lampod::jsonrpc::open_channel::{json_complete_funding, json_open_channel, json_open_channels};
+use lampod::jsonrpc::peer_control::{json_connect, json_send_onion_message};
+use lampod::jsonrpc::queue::{json_list_queue, json_queue_pay};
+use lampod::jsonrpc::rebalancer::json_rebalance_report;
+use lampod::jsonrpc::scheduler::{json_del_schedule, json_list_schedules, json_schedule_pay};
 use lampod::jsonrpc::CommandHandler;
 use lampod::LampoDaemon;
 
@@ -46,6 +83,29 @@ fn main() -> error::Result<()> {
 
 /// Return the root directory.
 fn run(args: LampoCliArgs) -> error::Result<()> {
+    // After this point the configuration is ready!
+    let confs = args.confs()?;
+    if confs.len() > 1 && args.restore_wallet {
+        error::bail!("`--restore-wallet` can be used only with a single node instance");
+    }
+    if confs.len() > 1 && args.recover_from_scb.is_some() {
+        error::bail!("`--recover-from-scb` can be used only with a single node instance");
+    }
+    for (idx, conf) in confs.iter().enumerate() {
+        // fail fast before touching the backend or the wallet
+        conf.validate()?;
+        if let Some(other) = confs[..idx]
+            .iter()
+            .find(|other| other.port == conf.port || other.rpc_socket() == conf.rpc_socket())
+        {
+            error::bail!(
+                "node instances `{}` and `{}` must use a different `port` and `rpc-socket`",
+                other.network,
+                conf.network
+            );
+        }
+    }
+
     let mnemonic = if args.restore_wallet {
         let inputs: String = term::input(
             "BIP 39 Mnemonic",
@@ -57,19 +117,81 @@ fn run(args: LampoCliArgs) -> error::Result<()> {
         None
     };
 
-    // After this point the configuration is ready!
-    let mut lampo_conf: LampoConf = args.try_into()?;
-    log::debug!(target: "lampod-cli", "init wallet ..");
-    // init the logger here
+    // The logger is global, so the first instance decides where to log.
+    let log_conf = &confs[0];
     logger::init(
-        &lampo_conf.log_level,
-        lampo_conf
+        &log_conf.log_level,
+        log_conf
             .log_file
             .as_ref()
             .and_then(|path| Some(PathBuf::from_str(&path).unwrap())),
     )
     .expect("unable to init the logger for the first time");
 
+    let single_instance = confs.len() == 1;
+    let mut instances = Vec::new();
+    for conf in confs {
+        log::info!(target: "lampod-cli", "starting the node instance on `{}`", conf.network);
+        instances.push(run_instance(conf, mnemonic.clone(), single_instance)?);
+    }
+
+    let nodes = instances
+        .iter()
+        .map(|instance| (instance.lampod.clone(), instance.handler.clone()))
+        .collect::<Vec<_>>();
+    ctrlc::set_handler(move || {
+        log::info!("Shutdown...");
+        for (lampod, handler) in &nodes {
+            handler.stop();
+            // the node persists its state before stopping, so
+            // only a second ctrl-c exits without waiting it.
+            if let Err(err) = lampod.stop() {
+                log::warn!("{err}, exiting without waiting the persistence");
+                std::process::exit(1);
+            }
+        }
+    })?;
+
+    let mut workers = Vec::new();
+    for instance in instances {
+        let worker = instance.lampod.clone().listen().unwrap();
+        workers.push((worker, instance));
+    }
+    log::info!(target: "lampod-cli", "------------ Starting Server ------------");
+    for (worker, instance) in workers {
+        match worker.join() {
+            Ok(Ok(())) => {
+                log::info!(target: "lampod-cli", "the node on `{}` persisted its state", instance.lampod.conf().network)
+            }
+            Ok(Err(err)) => {
+                log::error!(target: "lampod-cli", "impossible persist the state of the node: {err}")
+            }
+            Err(err) => log::error!(target: "lampod-cli", "the node panicked: {:?}", err),
+        }
+        // the json rpc server is waiting for a new request, so
+        // we join it only when it stopped on its own.
+        if instance.jsonrpc_worker.is_finished() {
+            let _ = instance.jsonrpc_worker.join();
+        }
+    }
+    Ok(())
+}
+
+/// A node instance running inside the process.
+struct Instance {
+    lampod: Arc<LampoDaemon>,
+    handler: Arc<Handler<LampoDaemon>>,
+    jsonrpc_worker: JoinHandle<io::Result<()>>,
+    // keep the lock on the `lampod.pid` file until the instance is alive
+    _pid: filelock_rs::pid::Pid,
+}
+
+fn run_instance(
+    mut lampo_conf: LampoConf,
+    mnemonic: Option<String>,
+    single_instance: bool,
+) -> error::Result<Instance> {
+    log::debug!(target: "lampod-cli", "init wallet ..");
     lampo_conf
         .ldk_conf
         .channel_handshake_limits
@@ -77,24 +199,16 @@ fn run(args: LampoCliArgs) -> error::Result<()> {
     // Prepare the backend
     let client = lampo_conf.node.clone();
     log::debug!(target: "lampod-cli", "lampo running with `{client}` backend");
-    let client: Arc<dyn Backend> = match client.as_str() {
-        "core" => Arc::new(BitcoinCore::new(
-            &lampo_conf
-                .core_url
-                .clone()
-                .ok_or(error::anyhow!("Miss the bitcoin url"))?,
-            &lampo_conf
-                .core_user
-                .clone()
-                .ok_or(error::anyhow!("Miss the bitcoin user for auth"))?,
-            &lampo_conf
-                .core_pass
-                .clone()
-                .ok_or(error::anyhow!("Miss the bitcoin password for auth"))?,
-            Arc::new(false),
-            Some(60),
-        )?),
-        _ => error::bail!("client {:?} not supported", client),
+    let client = if lampo_conf.backend_fallbacks.is_empty() {
+        new_backend(&client, &lampo_conf)?
+    } else {
+        let mut backends = vec![(client.clone(), new_backend(&client, &lampo_conf)?)];
+        for fallback in &lampo_conf.backend_fallbacks {
+            log::debug!(target: "lampod-cli", "using `{fallback}` as fallback backend");
+            backends.push((fallback.clone(), new_backend(fallback, &lampo_conf)?));
+        }
+        let failover = LampoBackendFailover::new(backends, lampo_conf.backend_max_lag)?;
+        Arc::new(failover) as Arc<dyn Backend>
     };
 
     let wallet = if let Some(ref _private_key) = lampo_conf.private_key {
@@ -107,6 +221,12 @@ fn run(args: LampoCliArgs) -> error::Result<()> {
             lampo_common::backend::BackendKind::Nakamoto => {
                 error::bail!("wallet is not implemented for nakamoto")
             }
+            lampo_common::backend::BackendKind::Cbf => {
+                error::bail!("wallet is not implemented for the compact block filters")
+            }
+            lampo_common::backend::BackendKind::Mock => {
+                error::bail!("wallet is not implemented for the mock chain")
+            }
         };
 
         radicle_term::success!("Wallet Generated, please store these words in a safe way");
@@ -125,10 +245,16 @@ fn run(args: LampoCliArgs) -> error::Result<()> {
             lampo_common::backend::BackendKind::Nakamoto => {
                 error::bail!("wallet is not implemented for nakamoto")
             }
+            lampo_common::backend::BackendKind::Cbf => {
+                error::bail!("wallet is not implemented for the compact block filters")
+            }
+            lampo_common::backend::BackendKind::Mock => {
+                error::bail!("wallet is not implemented for the mock chain")
+            }
         }
     };
     log::debug!(target: "lampod-cli", "wallet created with success");
-    let mut lampod = LampoDaemon::new(lampo_conf.clone(), Arc::new(wallet));
+    let mut lampod = LampoDaemon::new(lampo_conf.clone(), Arc::new(wallet))?;
 
     // Init the lampod
     lampod.init(client)?;
@@ -137,54 +263,168 @@ fn run(args: LampoCliArgs) -> error::Result<()> {
     lampod.add_external_handler(rpc_handler.clone())?;
 
     log::debug!(target: "lampod-cli", "Lampo directory `{}`", lampo_conf.path());
-    let mut _pid = filelock_rs::pid::Pid::new(lampo_conf.path(), "lampod".to_owned())
+    let pid = filelock_rs::pid::Pid::new(lampo_conf.path(), "lampod".to_owned())
         .map_err(|err| {
             log::error!("{err}");
             error::anyhow!("impossible take a lock on the `lampod.pid` file, maybe there is another instance running?")
         })?;
 
     let lampod = Arc::new(lampod);
-    let (jsorpc_worker, handler) = run_jsonrpc(lampod.clone()).unwrap();
+    let (jsonrpc_worker, handler) = run_jsonrpc(lampod.clone(), single_instance)?;
     rpc_handler.set_handler(handler.clone());
 
-    ctrlc::set_handler(move || {
-        use std::time::Duration;
-        log::info!("Shutdown...");
-        handler.stop();
-        std::thread::sleep(Duration::from_secs(5));
-        std::process::exit(0);
-    })?;
+    Ok(Instance {
+        lampod,
+        handler,
+        jsonrpc_worker,
+        _pid: pid,
+    })
+}
 
-    let workder = lampod.listen().unwrap();
-    log::info!(target: "lampod-cli", "------------ Starting Server ------------");
-    let _ = workder.join();
-    let _ = jsorpc_worker.join().unwrap();
-    Ok(())
+fn new_backend(client: &str, lampo_conf: &LampoConf) -> error::Result<Arc<dyn Backend>> {
+    let backend: Arc<dyn Backend> = match client {
+        "core" => Arc::new(BitcoinCore::new(
+            &lampo_conf
+                .core_url
+                .clone()
+                .ok_or(error::anyhow!("Miss the bitcoin url"))?,
+            &lampo_conf
+                .core_user
+                .clone()
+                .ok_or(error::anyhow!("Miss the bitcoin user for auth"))?,
+            &lampo_conf
+                .core_pass
+                .clone()
+                .ok_or(error::anyhow!("Miss the bitcoin password for auth"))?,
+            Arc::new(false),
+            Some(60),
+        )?),
+        "cbf" => Arc::new(LampoCbf::new(lampo_conf)?),
+        _ => error::bail!("client {:?} not supported", client),
+    };
+    Ok(backend)
 }
 
 fn run_jsonrpc(
     lampod: Arc<LampoDaemon>,
+    single_instance: bool,
 ) -> error::Result<(JoinHandle<io::Result<()>>, Arc<Handler<LampoDaemon>>)> {
-    let socket_path = format!("{}/lampod.socket", lampod.root_path());
+    let socket_path = lampod.conf().rpc_socket();
     // we take the lock with the pid file so if we are at this point
     // we can delete the socket because there is no other process
     // that it is running.
     let _ = std::fs::remove_file(socket_path.clone());
-    env::set_var("LAMPO_UNIX", socket_path.clone());
+    // the env is global, so it is not possible to export
+    // the socket of every instance.
+    if single_instance {
+        env::set_var("LAMPO_UNIX", socket_path.clone());
+    }
     let server = JSONRPCv2::new(lampod, &socket_path)?;
     server.add_rpc("getinfo", get_info).unwrap();
     server.add_rpc("connect", json_connect).unwrap();
+    server
+        .add_rpc("sendonionmessage", json_send_onion_message)
+        .unwrap();
     server.add_rpc("fundchannel", json_open_channel).unwrap();
+    server.add_rpc("openchannels", json_open_channels).unwrap();
+    server
+        .add_rpc("completefunding", json_complete_funding)
+        .unwrap();
     server.add_rpc("newaddr", json_new_addr).unwrap();
     server.add_rpc("channels", json_list_channels).unwrap();
     server.add_rpc("funds", json_funds).unwrap();
+    server
+        .add_rpc("listtransactions", json_list_transactions)
+        .unwrap();
+    server.add_rpc("setlabel", json_set_label).unwrap();
+    server.add_rpc("consolidate", json_consolidate).unwrap();
+    server.add_rpc("listsweeps", json_list_sweeps).unwrap();
+    server.add_rpc("withdraw", json_withdraw).unwrap();
     server.add_rpc("invoice", json_invoice).unwrap();
+    server.add_rpc("batchinvoice", json_batch_invoice).unwrap();
+    server.add_rpc("holdinvoice", json_hold_invoice).unwrap();
+    server
+        .add_rpc("settleinvoice", json_settle_invoice)
+        .unwrap();
+    server.add_rpc("phantomhints", json_phantom_hints).unwrap();
+    server
+        .add_rpc("phantominvoice", json_phantom_invoice)
+        .unwrap();
+    server
+        .add_rpc("cancelinvoice", json_cancel_invoice)
+        .unwrap();
+    // `delinvoice` is kept as alias for the users coming from cln
+    server.add_rpc("delinvoice", json_cancel_invoice).unwrap();
+    server
+        .add_rpc("lookupinvoice", json_lookup_invoice)
+        .unwrap();
+    server.add_rpc("listinvoices", json_list_invoices).unwrap();
+    server.add_rpc("lspinfo", json_lsp_info).unwrap();
+    server.add_rpc("lspinvoice", json_lsp_invoice).unwrap();
+    server.add_rpc("buyinbound", json_buy_inbound).unwrap();
+    server
+        .add_rpc("listlsporders", json_list_lsp_orders)
+        .unwrap();
     server.add_rpc("offer", json_offer).unwrap();
+    server.add_rpc("listoffers", json_list_offers).unwrap();
+    server.add_rpc("refund", json_refund).unwrap();
+    server.add_rpc("claimrefund", json_claim_refund).unwrap();
     server.add_rpc("decode", json_decode_invoice).unwrap();
     server.add_rpc("pay", json_pay).unwrap();
+    server.add_rpc("payoffer", json_pay_offer).unwrap();
     server.add_rpc("keysend", json_keysend).unwrap();
+    server
+        .add_rpc("lookuppayment", json_lookup_payment)
+        .unwrap();
+    server.add_rpc("schedulepay", json_schedule_pay).unwrap();
+    server
+        .add_rpc("listschedules", json_list_schedules)
+        .unwrap();
+    server.add_rpc("delschedule", json_del_schedule).unwrap();
+    server.add_rpc("queuepay", json_queue_pay).unwrap();
+    server.add_rpc("listqueue", json_list_queue).unwrap();
+    server
+        .add_rpc("rebalancereport", json_rebalance_report)
+        .unwrap();
     server.add_rpc("fees", json_estimate_fees).unwrap();
     server.add_rpc("close", json_close_channel).unwrap();
+    server
+        .add_rpc("forceclose", json_force_close_channel)
+        .unwrap();
+    server
+        .add_rpc("listforcecloses", json_list_force_closes)
+        .unwrap();
+    server.add_rpc("setchannel", json_set_channel).unwrap();
+    server.add_rpc("listforwards", json_list_forwards).unwrap();
+    server
+        .add_rpc("interceptscid", json_intercept_scid)
+        .unwrap();
+    server
+        .add_rpc("forwardintercepted", json_forward_intercepted)
+        .unwrap();
+    server
+        .add_rpc("failintercepted", json_fail_intercepted)
+        .unwrap();
+    server
+        .add_rpc("exportchannelbackup", json_export_channel_backup)
+        .unwrap();
+    server.add_rpc("traceroute", json_traceroute).unwrap();
+    server.add_rpc("probe", json_probe).unwrap();
+    server.add_rpc("getroute", json_get_route).unwrap();
+    server.add_rpc("stats", json_stats).unwrap();
+    server.add_rpc("metrics", json_metrics).unwrap();
+    server.add_rpc("exportgraph", json_export_graph).unwrap();
+    server.add_rpc("importgraph", json_import_graph).unwrap();
+    server.add_rpc("listnodes", json_list_nodes).unwrap();
+    server
+        .add_rpc("listgossipchannels", json_list_gossip_channels)
+        .unwrap();
+    #[cfg(debug_assertions)]
+    server
+        .add_rpc("dev-pendingevents", json_dev_pending_events)
+        .unwrap();
+    server.add_rpc("configdump", json_configdump).unwrap();
+    server.add_rpc("commands", json_commands).unwrap();
     let handler = server.handler();
     Ok((server.spawn(), handler))
 }
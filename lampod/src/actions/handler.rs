@@ -10,16 +10,24 @@ use lampo_common::event::{Emitter, Event, Subscriber};
 use lampo_common::handler::Handler as EventHandler;
 use lampo_common::json;
 use lampo_common::ldk;
+use lampo_common::model::response::InterceptedHtlc;
+use lampo_common::model::response::InvoiceStatus;
+use lampo_common::model::response::PaymentFailure;
 use lampo_common::model::response::PaymentHop;
 use lampo_common::model::response::PaymentState;
 use lampo_common::types::ChannelState;
 use lampo_jsonrpc::json_rpc2::Request;
 
-use crate::chain::{LampoChainManager, WalletManager};
+use crate::chain::{bump_handler, LampoBumpHandler, LampoChainManager, LampoSweeper};
 use crate::command::Command;
 use crate::handler::external_handler::ExternalHandler;
-use crate::ln::events::PeerEvents;
+use crate::forwards::LampoForwardStore;
+use crate::invoices::LampoInvoiceStore;
+use crate::ln::events::{ChangeStateChannelEvent, ChannelEvents, PeerEvents};
+use crate::ln::{BATCH_FUNDING_CHANNEL, EXTERNAL_FUNDING_CHANNEL};
 use crate::ln::{LampoChannelManager, LampoInventoryManager, LampoPeerManager};
+use crate::payments::LampoPaymentStore;
+use crate::stats::LampoStats;
 use crate::{async_run, LampoDaemon};
 
 use super::{Handler, InventoryHandler};
@@ -28,8 +36,17 @@ pub struct LampoHandler {
     channel_manager: Arc<LampoChannelManager>,
     peer_manager: Arc<LampoPeerManager>,
     inventory_manager: Arc<LampoInventoryManager>,
-    wallet_manager: Arc<dyn WalletManager>,
     chain_manager: Arc<LampoChainManager>,
+    /// Pay the fees of the anchor channels force closed.
+    bump_handler: LampoBumpHandler,
+    /// Sweep the outputs of the closed channels to the wallet.
+    sweeper: Arc<LampoSweeper>,
+    stats: Arc<LampoStats>,
+    invoices: Arc<LampoInvoiceStore>,
+    payments: Arc<LampoPaymentStore>,
+    forwards: Arc<LampoForwardStore>,
+    /// Min amount accepted for the invoices without an amount.
+    min_invoice_amount_msat: Option<u64>,
     external_handlers: RefCell<Vec<Arc<dyn ExternalHandler>>>,
     #[allow(dead_code)]
     emitter: Emitter<Event>,
@@ -47,8 +64,18 @@ impl LampoHandler {
             channel_manager: lampod.channel_manager(),
             peer_manager: lampod.peer_manager(),
             inventory_manager: lampod.inventory_manager(),
-            wallet_manager: lampod.wallet_manager(),
             chain_manager: lampod.onchain_manager(),
+            bump_handler: bump_handler(
+                lampod.onchain_manager(),
+                lampod.wallet_manager(),
+                lampod.channel_manager().logger.clone(),
+            ),
+            sweeper: lampod.sweeper(),
+            stats: lampod.stats(),
+            invoices: lampod.invoices(),
+            payments: lampod.payments(),
+            forwards: lampod.forwards(),
+            min_invoice_amount_msat: lampod.conf().min_invoice_amount_msat,
             external_handlers: RefCell::new(Vec::new()),
             emitter,
             subscriber,
@@ -131,7 +158,13 @@ impl Handler for LampoHandler {
                 push_msat,
                 channel_type,
             } => {
-                Err(error::anyhow!("Request for open a channel received, unfortunatly we do not support this feature yet."))
+                log::info!("request to open a channel of {funding_satoshis} sats from `{counterparty_node_id}` with channel type {channel_type}");
+                self.channel_manager.accept_inbound_channel(
+                    &temporary_channel_id,
+                    &counterparty_node_id,
+                    funding_satoshis,
+                    &channel_type,
+                )
             }
             ldk::events::Event::ChannelReady {
                 channel_id,
@@ -140,6 +173,13 @@ impl Handler for LampoHandler {
                 channel_type,
             } => {
                 log::info!("channel ready with node `{counterparty_node_id}`, and channel type {channel_type}");
+                self.channel_manager.change_state_channel(ChangeStateChannelEvent {
+                    channel_id,
+                    former_temporary_channel_id: None,
+                    node_id: counterparty_node_id,
+                    channel_type: Some(channel_type.clone()),
+                    state: ChannelState::Ready,
+                })?;
                 self.emit(Event::Lightning(LightningEvent::ChannelReady {
                     counterparty_node_id,
                     channel_id,
@@ -158,6 +198,17 @@ impl Handler for LampoHandler {
                 if let Some(node_id) = counterparty_node_id {
                     log::warn!("closing channels with `{node_id}`");
                 }
+                // a channel closed before the funding has still the temporary id
+                self.channel_manager.forget_funding(&channel_id);
+                if let Some(node_id) = counterparty_node_id {
+                    self.channel_manager.change_state_channel(ChangeStateChannelEvent {
+                        channel_id,
+                        former_temporary_channel_id: None,
+                        node_id,
+                        channel_type: None,
+                        state: ChannelState::Closed,
+                    })?;
+                }
                 let node_id = counterparty_node_id.map(|id| id.to_string());
                 let txo = channel_funding_txo.map(|txo| txo.to_string());
                 self.emit(Event::Lightning(LightningEvent::CloseChannelEvent { channel_id: channel_id.to_string(), message: reason.to_string(), counterparty_node_id : node_id, funding_utxo : txo}));
@@ -169,27 +220,49 @@ impl Handler for LampoHandler {
                 counterparty_node_id,
                 channel_value_satoshis,
                 output_script,
+                user_channel_id,
                 ..
             } => {
+                // the funding of these channels is built outside the handler
+                let funded_later = matches!(user_channel_id, EXTERNAL_FUNDING_CHANNEL | BATCH_FUNDING_CHANNEL);
+                if funded_later {
+                    let funding = self.channel_manager.external_funding_ready(
+                        &temporary_channel_id,
+                        &counterparty_node_id,
+                        channel_value_satoshis,
+                        &output_script,
+                    )?;
+                    log::info!("waiting the funding of `{}` to `{}`", funding.temporary_channel_id, funding.address);
+                }
+                self.channel_manager.change_state_channel(ChangeStateChannelEvent {
+                    channel_id: temporary_channel_id,
+                    former_temporary_channel_id: None,
+                    node_id: counterparty_node_id,
+                    channel_type: None,
+                    state: ChannelState::Opening,
+                })?;
                 self.emit(Event::Lightning(LightningEvent::FundingChannelStart {
                     counterparty_node_id,
                     temporary_channel_id,
                     channel_value_satoshis,
                 }));
+                if funded_later {
+                    // the transaction is provided with `completefunding`
+                    // or by the batch open.
+                    return Ok(());
+                }
 
                 log::info!("propagate funding transaction for open a channel with `{counterparty_node_id}`");
-                // FIXME: estimate the fee rate with a callback
-                let fee = self.chain_manager.backend.fee_rate_estimation(6).map_err(|err| {
+                // take the utxos first, so they are not left behind when the fee rate fails
+                let utxos = self.channel_manager.funding_utxos(&temporary_channel_id);
+                let fee = self.channel_manager.funding_fee_rate(&temporary_channel_id).map_err(|err| {
                     let msg = format!("Channel Opening Error: {err}");
                     self.emit(Event::Lightning(LightningEvent::ChannelEvent { state: ChannelState::OpeningError, message : msg}));
                     err
                 })?;
                 log::info!("fee estimated {:?} sats", fee);
-                let transaction = self.wallet_manager.create_transaction(
-                    output_script,
-                    channel_value_satoshis,
-                    fee,
-                )?;
+                let funder = self.chain_manager.funder();
+                let transaction = funder.fund(&[(output_script, channel_value_satoshis)], fee, utxos.as_deref())?;
                 log::info!("funding transaction created `{}`", transaction.txid());
                 log::info!(
                     "transaction hex `{}`",
@@ -201,25 +274,39 @@ impl Handler for LampoHandler {
                     channel_value_satoshis,
                     funding_transaction: transaction.clone(),
                 }));
-                self.channel_manager
+                let txid = transaction.txid();
+                if let Err(err) = self.channel_manager
                     .manager()
                     .funding_transaction_generated(
                         &temporary_channel_id,
                         &counterparty_node_id,
                         transaction,
-                    )
-                    .map_err(|err| error::anyhow!("{:?}", err))?;
+                    ) {
+                    funder.release(&txid)?;
+                    error::bail!("{:?}", err);
+                }
                 Ok(())
             }
             ldk::events::Event::ChannelPending {
+                channel_id,
+                former_temporary_channel_id,
                 counterparty_node_id,
                 funding_txo,
                 ..
             } => {
+                self.channel_manager.change_state_channel(ChangeStateChannelEvent {
+                    channel_id,
+                    former_temporary_channel_id,
+                    node_id: counterparty_node_id,
+                    channel_type: None,
+                    state: ChannelState::Pending,
+                })?;
                 log::info!(
                     "channel pending with node `{}` with funding `{funding_txo}`",
                     counterparty_node_id.to_string()
                 );
+                // the funding transaction is broadcast, so its inputs are spent
+                self.chain_manager.funder().funding_broadcast(&funding_txo.txid);
                 self.emit(Event::Lightning(LightningEvent::ChannelPending { counterparty_node_id, funding_transaction: funding_txo }));
                 Ok(())
             }
@@ -248,9 +335,51 @@ impl Handler for LampoHandler {
                     ldk::events::PaymentPurpose::Bolt12RefundPayment { payment_preimage, .. } => payment_preimage,
                     ldk::events::PaymentPurpose::SpontaneousPayment(preimage) => Some(preimage),
                 };
+                if let Some(invoice) = self.invoices.get(&payment_hash.to_string()) {
+                    let reject = if invoice.status == InvoiceStatus::Cancelled {
+                        Some("the invoice was cancelled".to_owned())
+                    } else if counterparty_skimmed_fee_msat > invoice.lsp_fee_msat.unwrap_or_default() {
+                        // only the LSP can take a fee, and not more than the opening fee
+                        Some(format!("the peer took `{counterparty_skimmed_fee_msat}` msat of fee"))
+                    } else {
+                        // ldk checks the amount of the invoices with an amount,
+                        // we check the min amount for the one without.
+                        match (invoice.amount_msat, self.min_invoice_amount_msat) {
+                            (None, Some(min)) if amount_msat < min => {
+                                Some(format!("`{amount_msat}` msat is below the minimum `{min}` msat"))
+                            }
+                            _ => None,
+                        }
+                    };
+                    if let Some(reason) = reject {
+                        log::info!("rejecting payment `{payment_hash}`, {reason}");
+                        self.channel_manager
+                            .manager()
+                            .fail_htlc_backwards(&payment_hash);
+                        return Ok(());
+                    }
+                    // the payment is claimed by `settleinvoice`
+                    if invoice.hold {
+                        log::info!("holding payment `{payment_hash}` until the invoice is settled");
+                        let invoice = self.invoices.accept(&invoice.payment_hash, amount_msat, claim_deadline)?;
+                        self.emit(Event::Lightning(LightningEvent::InvoiceEvent {
+                            payment_hash: invoice.payment_hash,
+                            label: invoice.label,
+                            status: invoice.status,
+                        }));
+                        return Ok(());
+                    }
+                }
+                let Some(preimage) = preimage else {
+                    log::warn!("rejecting payment `{payment_hash}`, the preimage is unknown");
+                    self.channel_manager
+                        .manager()
+                        .fail_htlc_backwards(&payment_hash);
+                    return Ok(());
+                };
                 self.channel_manager
                     .manager()
-                    .claim_funds(preimage.unwrap());
+                    .claim_funds(preimage);
                 Ok(())
             }
             ldk::events::Event::PaymentClaimed {
@@ -270,20 +399,130 @@ impl Handler for LampoHandler {
                     ldk::events::PaymentPurpose::Bolt12RefundPayment { payment_preimage, payment_secret, .. } => (payment_preimage, Some(payment_secret)),
                     ldk::events::PaymentPurpose::SpontaneousPayment(preimage) => (Some(preimage), None),
                 };
-                log::warn!("please note the payments are not make persistent for the moment");
-                // FIXME: make peristent these information
+                self.stats.payment_received();
+                let payment_hash = payment_hash.to_string();
+                self.payments.payment_received(
+                    &payment_hash,
+                    payment_preimage.map(|preimage| preimage.to_string()),
+                    amount_msat,
+                )?;
+                let mut label = None;
+                if self.invoices.get(&payment_hash).is_some() {
+                    let invoice = self.invoices.mark_paid(&payment_hash, amount_msat)?;
+                    label = invoice.label.clone();
+                    self.emit(Event::Lightning(LightningEvent::InvoiceEvent {
+                        payment_hash: payment_hash.clone(),
+                        label: invoice.label,
+                        status: invoice.status,
+                    }));
+                }
+                self.emit(Event::Lightning(LightningEvent::PaymentReceived {
+                    payment_hash,
+                    amount_msat,
+                    label,
+                }));
                 Ok(())
             }
-            ldk::events::Event::PaymentSent { .. } => {
+            ldk::events::Event::PaymentSent { payment_id, payment_hash, payment_preimage, fee_paid_msat, .. } => {
                 log::info!("payment sent: `{:?}`", event);
+                self.stats.payment_sent(payment_id.map(|id| id.0));
+                self.payments.payment_sent(&payment_hash.to_string(), &payment_preimage.to_string(), fee_paid_msat)?;
+                Ok(())
+            },
+            ldk::events::Event::PaymentFailed { payment_id, payment_hash, reason, .. } => {
+                log::warn!("payment failed: `{:?}`", event);
+                let reason = reason.map(|reason| format!("{:?}", reason)).unwrap_or("Unknown".to_owned());
+                self.stats.payment_failed(payment_id.0, &reason);
+                self.payments.payment_failed(&payment_hash.to_string())?;
+                self.emit(Event::Lightning(LightningEvent::PaymentEvent { state: PaymentState::Failure, payment_hash: Some(payment_hash.to_string()), path: vec![], reason: Some(reason) }));
+                Ok(())
+            },
+            ldk::events::Event::PaymentForwarded { prev_channel_id, next_channel_id, total_fee_earned_msat, outbound_amount_forwarded_msat, claim_from_onchain_tx, .. } => {
+                log::info!("payment forwarded: `{:?}`", event);
+                self.stats.forwarded();
+                self.forwards.record(prev_channel_id, next_channel_id, outbound_amount_forwarded_msat, total_fee_earned_msat, claim_from_onchain_tx)?;
+                Ok(())
+            },
+            ldk::events::Event::HTLCHandlingFailed { failed_next_destination, .. } => {
+                log::warn!("htlc handling failed: `{:?}`", event);
+                if !matches!(failed_next_destination, ldk::events::HTLCDestination::FailedPayment { .. }) {
+                    self.stats.forward_failed();
+                }
                 Ok(())
             },
             ldk::events::Event::PaymentPathSuccessful { payment_hash, path, .. } => {
                 let path = path.hops.iter().map(|hop| PaymentHop::from(hop.clone())).collect::<Vec<PaymentHop>>();
-                let hop = LightningEvent::PaymentEvent { state: PaymentState::Success, payment_hash: payment_hash.map(|hash| hash.to_string()), path };
+                if let Some(payment_hash) = payment_hash {
+                    self.emit(Event::Lightning(LightningEvent::PaymentPartEvent { state: PaymentState::Success, payment_hash: payment_hash.to_string(), path: path.clone(), failure: None }));
+                }
+                let hop = LightningEvent::PaymentEvent { state: PaymentState::Success, payment_hash: payment_hash.map(|hash| hash.to_string()), path, reason: None };
                 self.emit(Event::Lightning(hop));
                 Ok(())
             },
+            ldk::events::Event::InvoiceRequestFailed { payment_id } => {
+                log::warn!("invoice request failed: `{:?}`", event);
+                self.stats.payment_failed(payment_id.0, "invoice request failed");
+                self.emit(Event::Lightning(LightningEvent::PaymentEvent { state: PaymentState::Failure, payment_hash: None, path: vec![], reason: Some("the invoice request failed".to_owned()) }));
+                Ok(())
+            },
+            ldk::events::Event::PaymentPathFailed { payment_hash, ref path, ref failure, short_channel_id, payment_failed_permanently, .. } => {
+                log::warn!("payment part failed: `{:?}`", event);
+                // the scorer learns from the failure inside the background processor
+                let failure = PaymentFailure::new(failure, path, short_channel_id, payment_failed_permanently);
+                log::info!("payment `{payment_hash}` part failed: {}", failure.message);
+                let path = path.hops.iter().map(|hop| PaymentHop::from(hop.clone())).collect::<Vec<PaymentHop>>();
+                self.emit(Event::Lightning(LightningEvent::PaymentPartEvent { state: PaymentState::Failure, payment_hash: payment_hash.to_string(), path, failure: Some(failure) }));
+                Ok(())
+            },
+            ldk::events::Event::ProbeSuccessful { payment_hash, path, .. } => {
+                // the scorer learns from the probes inside the background processor
+                let path = path.hops.iter().map(|hop| PaymentHop::from(hop.clone())).collect::<Vec<PaymentHop>>();
+                let probe = LightningEvent::ProbeEvent { state: PaymentState::Success, payment_hash: payment_hash.to_string(), short_channel_id: None, path };
+                self.emit(Event::Lightning(probe));
+                Ok(())
+            },
+            ldk::events::Event::ProbeFailed { payment_hash, path, short_channel_id, .. } => {
+                let path = path.hops.iter().map(|hop| PaymentHop::from(hop.clone())).collect::<Vec<PaymentHop>>();
+                let probe = LightningEvent::ProbeEvent { state: PaymentState::Failure, payment_hash: payment_hash.to_string(), short_channel_id, path };
+                self.emit(Event::Lightning(probe));
+                Ok(())
+            },
+            ldk::events::Event::BumpTransaction(event) => {
+                log::info!("bumping the fees of the force close: {:?}", event);
+                self.bump_handler.handle_event(&event);
+                Ok(())
+            }
+            ldk::events::Event::HTLCIntercepted { intercept_id, requested_next_hop_scid, payment_hash, inbound_amount_msat, expected_outbound_amount_msat } => {
+                log::info!("HTLC intercepted for scid `{requested_next_hop_scid}` with hash `{payment_hash}`");
+                let htlc = InterceptedHtlc {
+                    intercept_id: hex::encode(intercept_id.0),
+                    requested_next_hop_scid,
+                    payment_hash: payment_hash.to_string(),
+                    inbound_amount_msat,
+                    expected_outbound_amount_msat,
+                };
+                // the plugins open the channel with the payee and then
+                // forward the HTLC, so the response is not used.
+                let request = Request::new("htlc_intercepted", json::to_value(&htlc)?);
+                for handler in self.external_handlers.borrow().iter() {
+                    if let Err(err) = handler.handle(&request) {
+                        log::error!("external handler failed on the intercepted HTLC: {err}");
+                    }
+                }
+                self.emit(Event::Lightning(LightningEvent::HtlcIntercepted { htlc }));
+                Ok(())
+            }
+            ldk::events::Event::SpendableOutputs { outputs, channel_id } => {
+                log::info!("sweeping {} outputs of channel `{:?}` to the wallet", outputs.len(), channel_id);
+                self.sweeper
+                    .track_spendable_outputs(outputs, channel_id, false, None)
+                    .map_err(|_| error::anyhow!("impossible store the outputs to sweep"))?;
+                Ok(())
+            }
+            ldk::events::Event::DiscardFunding { channel_id, transaction } => {
+                log::warn!("the funding transaction `{}` of channel `{channel_id}` will never be broadcast", transaction.txid());
+                self.chain_manager.funder().release(&transaction.txid())
+            }
             _ => Err(error::anyhow!("unexpected ldk event: {:?}", event)),
         }
     }
@@ -0,0 +1,221 @@
+//! Builder API to embed lampo inside another application
+//! without spawning the `lampod` binary.
+//!
+//! The builder takes all the dependencies that the application
+//! want to inject (wallet, chain backend, persister, logger, event sink)
+//! and returns a running node with typed methods.
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use lampo_common::backend::Backend;
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::json;
+use lampo_common::logger;
+use lampo_common::logger::Log;
+use lampo_common::model::{request, response};
+use lampo_common::wallet::WalletManager;
+use lampo_jsonrpc::errors::Error;
+
+use crate::handler::external_handler::ExternalHandler;
+use crate::jsonrpc::channels::{json_close_channel, json_list_channels};
+use crate::jsonrpc::inventory::{get_info, json_stats};
+use crate::jsonrpc::offchain::{json_invoice, json_keysend, json_pay};
+use crate::jsonrpc::onchain::{json_funds, json_new_addr};
+use crate::jsonrpc::open_channel::json_open_channel;
+use crate::jsonrpc::peer_control::json_connect;
+use crate::persistence::LampoPersistence;
+use crate::LampoDaemon;
+
+/// Receive all the events emitted by the node.
+pub trait EventSink: Send + Sync {
+    fn on_event(&self, event: Event);
+}
+
+pub struct LampoBuilder {
+    conf: LampoConf,
+    wallet_manager: Option<Arc<dyn WalletManager>>,
+    backend: Option<Arc<dyn Backend>>,
+    persister: Option<Arc<LampoPersistence>>,
+    logger: Option<Box<dyn Log>>,
+    event_sink: Option<Arc<dyn EventSink>>,
+    external_handlers: Vec<Arc<dyn ExternalHandler>>,
+}
+
+impl LampoBuilder {
+    pub fn new(conf: LampoConf) -> Self {
+        Self {
+            conf,
+            wallet_manager: None,
+            backend: None,
+            persister: None,
+            logger: None,
+            event_sink: None,
+            external_handlers: Vec::new(),
+        }
+    }
+
+    pub fn with_wallet_manager(mut self, wallet_manager: Arc<dyn WalletManager>) -> Self {
+        self.wallet_manager = Some(wallet_manager);
+        self
+    }
+
+    pub fn with_backend(mut self, backend: Arc<dyn Backend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// By default the persister is stored inside the lampo data dir.
+    pub fn with_persister(mut self, persister: Arc<LampoPersistence>) -> Self {
+        self.persister = Some(persister);
+        self
+    }
+
+    /// Install the logger as global logger, if it is not specified
+    /// the application is in charge of init the logger.
+    pub fn with_logger(mut self, logger: Box<dyn Log>) -> Self {
+        self.logger = Some(logger);
+        self
+    }
+
+    pub fn with_event_sink(mut self, event_sink: Arc<dyn EventSink>) -> Self {
+        self.event_sink = Some(event_sink);
+        self
+    }
+
+    pub fn with_external_handler(mut self, handler: Arc<dyn ExternalHandler>) -> Self {
+        self.external_handlers.push(handler);
+        self
+    }
+
+    /// Init all the lampo components and start the node.
+    pub fn build(self) -> error::Result<LampoNode> {
+        if let Some(logger) = self.logger {
+            logger::init_with(logger, &self.conf.log_level)?;
+        }
+        let Some(wallet_manager) = self.wallet_manager else {
+            error::bail!("a wallet manager is required to build lampo");
+        };
+        let Some(backend) = self.backend else {
+            error::bail!("a chain backend is required to build lampo");
+        };
+        self.conf.prepare_dirs()?;
+
+        let mut lampod = match self.persister {
+            Some(persister) => LampoDaemon::with_persister(self.conf, wallet_manager, persister)?,
+            None => LampoDaemon::new(self.conf, wallet_manager)?,
+        };
+        lampod.init(backend)?;
+        for handler in self.external_handlers {
+            lampod.add_external_handler(handler)?;
+        }
+        let lampod = Arc::new(lampod);
+
+        // subscribe before start listening, so we do not lose any event
+        if let Some(sink) = self.event_sink {
+            let events = lampod.handler().events();
+            std::thread::spawn(move || {
+                while let Ok(event) = events.recv() {
+                    sink.on_event(event);
+                }
+            });
+        }
+        let worker = lampod.clone().listen()?;
+        Ok(LampoNode { lampod, worker })
+    }
+}
+
+/// Handle of a running lampo node.
+pub struct LampoNode {
+    lampod: Arc<LampoDaemon>,
+    worker: JoinHandle<std::io::Result<()>>,
+}
+
+impl LampoNode {
+    /// Access to the daemon to use the API that are not exposed
+    /// by the node handle.
+    pub fn lampod(&self) -> Arc<LampoDaemon> {
+        self.lampod.clone()
+    }
+
+    fn call<Req: json::Serialize, Resp: json::DeserializeOwned>(
+        &self,
+        method: fn(&LampoDaemon, &json::Value) -> Result<json::Value, Error>,
+        request: Req,
+    ) -> error::Result<Resp> {
+        let request = json::to_value(request)?;
+        let response = method(&self.lampod, &request).map_err(|err| error::anyhow!("{err}"))?;
+        Ok(json::from_value(response)?)
+    }
+
+    pub fn get_info(&self) -> error::Result<response::GetInfo> {
+        self.call(get_info, json::json!({}))
+    }
+
+    pub fn connect(&self, request: request::Connect) -> error::Result<response::Connect> {
+        self.call(json_connect, request)
+    }
+
+    pub fn new_address(&self) -> error::Result<response::NewAddress> {
+        self.call(json_new_addr, json::json!({}))
+    }
+
+    pub fn funds(&self) -> error::Result<response::Utxos> {
+        self.call(json_funds, json::json!({}))
+    }
+
+    pub fn channels(&self) -> error::Result<response::Channels> {
+        self.call(json_list_channels, json::json!({}))
+    }
+
+    pub fn open_channel(
+        &self,
+        request: request::OpenChannel,
+    ) -> error::Result<response::OpenChannel> {
+        self.call(json_open_channel, request)
+    }
+
+    pub fn close_channel(&self, request: request::CloseChannel) -> error::Result<json::Value> {
+        self.call(json_close_channel, request)
+    }
+
+    pub fn invoice(&self, request: request::GenerateInvoice) -> error::Result<response::Invoice> {
+        self.call(json_invoice, request)
+    }
+
+    pub fn pay(&self, request: request::Pay) -> error::Result<response::PayResult> {
+        self.call(json_pay, request)
+    }
+
+    /// Compute the route and the fees to pay the invoice without sending it.
+    pub fn simulate_pay(
+        &self,
+        mut request: request::Pay,
+    ) -> error::Result<response::PaySimulation> {
+        request.simulate = Some(true);
+        self.call(json_pay, request)
+    }
+
+    pub fn keysend(&self, request: request::KeySend) -> error::Result<json::Value> {
+        self.call(json_keysend, request)
+    }
+
+    pub fn stats(&self) -> error::Result<response::Stats> {
+        self.call(json_stats, json::json!({}))
+    }
+
+    /// Stop the node, use `join` to wait that the state is persisted.
+    pub fn stop(&self) -> error::Result<()> {
+        self.lampod.stop()
+    }
+
+    /// Wait until the node stops.
+    pub fn join(self) -> error::Result<()> {
+        self.worker
+            .join()
+            .map_err(|err| error::anyhow!("{:?}", err))??;
+        Ok(())
+    }
+}
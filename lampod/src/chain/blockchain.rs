@@ -1,10 +1,13 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
 
-use lampo_common::backend::Backend;
+use lampo_common::backend::{Backend, BlockData};
 use lampo_common::bitcoin;
+use lampo_common::bitcoin::block::Header;
 use lampo_common::bitcoin::blockdata::constants::ChainHash;
-use lampo_common::bitcoin::Transaction;
+use lampo_common::bitcoin::{BlockHash, Transaction};
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
 use lampo_common::ldk;
 use lampo_common::ldk::chain::chaininterface::{
     BroadcasterInterface, ConfirmationTarget, FeeEstimator,
@@ -13,23 +16,73 @@ use lampo_common::ldk::chain::Filter;
 use lampo_common::ldk::routing::utxo::UtxoLookup;
 use lampo_common::wallet::WalletManager;
 
+use super::fees::{LampoFeeEstimator, CONFIRMATION_TARGETS};
+use super::funding::LampoFunder;
+
+/// Number of recent blocks that we remember to find
+/// where the chain forked during a reorg.
+const REORG_DEPTH: u32 = 144;
+
 #[derive(Clone)]
 pub struct LampoChainManager {
     pub backend: Arc<dyn Backend>,
     pub wallet_manager: Arc<dyn WalletManager>,
+    fees: Arc<LampoFeeEstimator>,
+    funder: Arc<LampoFunder>,
+    /// Hash of the recent blocks of the best chain by height.
+    recent_blocks: Arc<Mutex<BTreeMap<u32, BlockHash>>>,
 }
 
 /// Personal Lampo implementation
 impl LampoChainManager {
     /// Create a new instance of LampoFeeEstimator with the specified
     /// Backend.
-    pub fn new(client: Arc<dyn Backend>, wallet_manager: Arc<dyn WalletManager>) -> Self {
+    pub fn new(
+        client: Arc<dyn Backend>,
+        wallet_manager: Arc<dyn WalletManager>,
+        conf: &LampoConf,
+    ) -> Self {
         LampoChainManager {
+            fees: Arc::new(LampoFeeEstimator::new(client.clone(), conf)),
+            funder: Arc::new(LampoFunder::new(wallet_manager.clone(), conf)),
             backend: client,
             wallet_manager,
+            recent_blocks: Arc::new(Mutex::new(BTreeMap::new())),
         }
     }
 
+    /// Connect the new best block to the recent blocks, and return the
+    /// blocks that are not inside the best chain anymore because of a
+    /// reorg, from the highest one.
+    pub fn connect_block(
+        &self,
+        header: &Header,
+        height: u32,
+    ) -> error::Result<Vec<(BlockHash, u32)>> {
+        let mut blocks = self.recent_blocks.lock().unwrap();
+        connect_recent_block(&mut blocks, header, height, |hash| {
+            match self.backend.get_block(hash)? {
+                BlockData::FullBlock(block) => Ok(block.header),
+                BlockData::HeaderOnly(header) => Ok(header),
+            }
+        })
+    }
+
+    /// A known block of the best chain below the tip, e.g: a block
+    /// sent again by a backend that is catching up with us.
+    pub fn is_stale_block(&self, hash: &BlockHash, height: u32) -> bool {
+        let blocks = self.recent_blocks.lock().unwrap();
+        blocks.get(&height) == Some(hash) && blocks.range(height + 1..).next().is_some()
+    }
+
+    pub fn fees(&self) -> Arc<LampoFeeEstimator> {
+        self.fees.clone()
+    }
+
+    pub fn funder(&self) -> Arc<LampoFunder> {
+        self.funder.clone()
+    }
+
     pub fn is_lightway(&self) -> bool {
         self.backend.is_lightway()
     }
@@ -51,48 +104,71 @@ impl LampoChainManager {
     }
 
     pub fn estimated_fees(&self) -> HashMap<String, Option<u32>> {
-        let fees_targets = vec![
-            ConfirmationTarget::OnChainSweep,
-            ConfirmationTarget::MinAllowedNonAnchorChannelRemoteFee,
-            ConfirmationTarget::NonAnchorChannelFee,
-            ConfirmationTarget::MinAllowedAnchorChannelRemoteFee,
-            ConfirmationTarget::AnchorChannelFee,
-            ConfirmationTarget::ChannelCloseMinimum,
-            ConfirmationTarget::OutputSpendingFee,
-        ];
         let mut map: HashMap<String, Option<u32>> = HashMap::new();
-        for target in fees_targets {
-            let fee = self.get_est_sat_per_1000_weight(target);
-            let value = if fee == 0 { None } else { Some(fee) };
+        for target in CONFIRMATION_TARGETS {
+            let value = self.fees.estimate(target).ok();
             map.insert(self.print_ldk_target_to_string(target), value);
         }
         map
     }
 }
 
+/// Connect the new best block to the `blocks` of the best chain by
+/// height, `get_header` fetches the headers of the blocks that we do
+/// not know.
+fn connect_recent_block<F>(
+    blocks: &mut BTreeMap<u32, BlockHash>,
+    header: &Header,
+    height: u32,
+    get_header: F,
+) -> error::Result<Vec<(BlockHash, u32)>>
+where
+    F: Fn(&BlockHash) -> error::Result<Header>,
+{
+    // the blocks of the best chain that we do not know,
+    // we walk back from the tip until a known block.
+    let mut connected = vec![(header.block_hash(), height)];
+    let mut prev_hash = header.prev_blockhash;
+    while let Some(prev_height) = connected.last().unwrap().1.checked_sub(1) {
+        match blocks.get(&prev_height) {
+            Some(hash) if *hash == prev_hash => break,
+            // there is nothing to compare with before the first block that we know
+            None if blocks.range(prev_height..).next().is_none() => break,
+            _ => {
+                if connected.len() as u32 > REORG_DEPTH {
+                    error::bail!(
+                        "the reorg at height {height} is deeper than {REORG_DEPTH} blocks"
+                    );
+                }
+                let prev_header = get_header(&prev_hash)?;
+                connected.push((prev_hash, prev_height));
+                prev_hash = prev_header.prev_blockhash;
+            }
+        }
+    }
+    let first_height = connected
+        .last()
+        .expect("`connected` contains at least the new tip")
+        .1;
+    let disconnected = blocks
+        .range(first_height..)
+        .rev()
+        .map(|(height, hash)| (*hash, *height))
+        .filter(|block| !connected.contains(block))
+        .collect::<Vec<_>>();
+    blocks.retain(|block_height, _| {
+        *block_height < first_height && *block_height + REORG_DEPTH > height
+    });
+    for (hash, height) in connected {
+        blocks.insert(height, hash);
+    }
+    Ok(disconnected)
+}
+
 /// Rust lightning FeeEstimator implementation
 impl FeeEstimator for LampoChainManager {
     fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
-        //FIXME: use cache to avoid return default value (that is 0) on u32
-        match confirmation_target {
-            ConfirmationTarget::OnChainSweep => {
-                self.backend.fee_rate_estimation(1).unwrap_or_default()
-            }
-            ConfirmationTarget::MinAllowedNonAnchorChannelRemoteFee
-            | ConfirmationTarget::AnchorChannelFee
-            | ConfirmationTarget::NonAnchorChannelFee => {
-                self.backend.fee_rate_estimation(6).unwrap_or_default()
-            }
-            ConfirmationTarget::MinAllowedAnchorChannelRemoteFee => {
-                self.backend.minimum_mempool_fee().unwrap()
-            }
-            ConfirmationTarget::ChannelCloseMinimum => {
-                self.backend.fee_rate_estimation(100).unwrap_or_default()
-            }
-            ConfirmationTarget::OutputSpendingFee => {
-                self.backend.fee_rate_estimation(12).unwrap_or_default()
-            }
-        }
+        self.fees.estimate_or_min(confirmation_target)
     }
 }
 
@@ -123,3 +199,128 @@ impl UtxoLookup for LampoChainManager {
 // SAFETY: there is no reason why this should not be send and sync
 unsafe impl Send for LampoChainManager {}
 unsafe impl Sync for LampoChainManager {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use lampo_common::bitcoin::block::{Header, Version};
+    use lampo_common::bitcoin::hash_types::TxMerkleNode;
+    use lampo_common::bitcoin::hashes::Hash;
+    use lampo_common::bitcoin::{BlockHash, CompactTarget};
+    use lampo_common::error;
+
+    use super::{connect_recent_block, REORG_DEPTH};
+
+    /// A chain of `len` headers on top of `prev_blockhash`, `fork`
+    /// makes the headers different from the ones of another fork.
+    fn chain(prev_blockhash: BlockHash, len: u32, fork: u32) -> Vec<Header> {
+        let mut headers: Vec<Header> = Vec::new();
+        for _ in 0..len {
+            let header = Header {
+                version: Version::ONE,
+                prev_blockhash: headers
+                    .last()
+                    .map(|header| header.block_hash())
+                    .unwrap_or(prev_blockhash),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: fork,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce: 0,
+            };
+            headers.push(header);
+        }
+        headers
+    }
+
+    /// Connect `headers` from `height` one by one, and return the
+    /// blocks disconnected by the last one.
+    fn connect_all(
+        blocks: &mut BTreeMap<u32, BlockHash>,
+        headers: &[Header],
+        height: u32,
+    ) -> error::Result<Vec<(BlockHash, u32)>> {
+        let mut disconnected = vec![];
+        for (idx, header) in headers.iter().enumerate() {
+            disconnected = connect_recent_block(blocks, header, height + idx as u32, |_| {
+                error::bail!("the headers are connected in order")
+            })?;
+        }
+        Ok(disconnected)
+    }
+
+    #[test]
+    fn connect_best_chain() {
+        let mut blocks = BTreeMap::new();
+        let headers = chain(BlockHash::all_zeros(), 10, 0);
+        let disconnected = connect_all(&mut blocks, &headers, 1).unwrap();
+        assert!(disconnected.is_empty());
+        assert_eq!(blocks.len(), 10);
+        assert_eq!(blocks.get(&10), Some(&headers[9].block_hash()));
+    }
+
+    #[test]
+    fn reorg() {
+        let mut blocks = BTreeMap::new();
+        let headers = chain(BlockHash::all_zeros(), 5, 0);
+        connect_all(&mut blocks, &headers, 1).unwrap();
+
+        // a fork of 3 blocks on top of the block at height 2, only
+        // its tip is notified.
+        let fork = chain(headers[1].block_hash(), 3, 1);
+        let by_hash = fork
+            .iter()
+            .map(|header| (header.block_hash(), *header))
+            .collect::<HashMap<_, _>>();
+        let disconnected = connect_recent_block(&mut blocks, &fork[2], 5, |hash| {
+            by_hash
+                .get(hash)
+                .copied()
+                .ok_or_else(|| error::anyhow!("unknown block `{hash}`"))
+        })
+        .unwrap();
+        assert_eq!(
+            disconnected,
+            vec![
+                (headers[4].block_hash(), 5),
+                (headers[3].block_hash(), 4),
+                (headers[2].block_hash(), 3),
+            ]
+        );
+        assert_eq!(blocks.get(&3), Some(&fork[0].block_hash()));
+        assert_eq!(blocks.get(&5), Some(&fork[2].block_hash()));
+    }
+
+    #[test]
+    fn reorg_of_the_tip() {
+        let mut blocks = BTreeMap::new();
+        let headers = chain(BlockHash::all_zeros(), 3, 0);
+        connect_all(&mut blocks, &headers, 1).unwrap();
+
+        let fork = chain(headers[1].block_hash(), 1, 1);
+        let disconnected = connect_all(&mut blocks, &fork, 3).unwrap();
+        assert_eq!(disconnected, vec![(headers[2].block_hash(), 3)]);
+    }
+
+    #[test]
+    fn reorg_too_deep() {
+        let mut blocks = BTreeMap::new();
+        let headers = chain(BlockHash::all_zeros(), REORG_DEPTH + 10, 0);
+        connect_all(&mut blocks, &headers, 1).unwrap();
+
+        let fork = chain(headers[0].block_hash(), REORG_DEPTH + 10, 1);
+        let by_hash = fork
+            .iter()
+            .map(|header| (header.block_hash(), *header))
+            .collect::<HashMap<_, _>>();
+        let tip = fork.last().unwrap();
+        let err = connect_recent_block(&mut blocks, tip, REORG_DEPTH + 11, |hash| {
+            by_hash
+                .get(hash)
+                .copied()
+                .ok_or_else(|| error::anyhow!("unknown block `{hash}`"))
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("deeper than"), "{err}");
+    }
+}
@@ -0,0 +1,93 @@
+//! Fee bumping of the anchor channels.
+//!
+//! The commitment and the HTLC transactions of the anchor channels
+//! pay a minimal fee, so ldk asks to bump them with a CPFP when the
+//! channel is force closed. The fees are paid with the confirmed
+//! utxos of the on chain wallet.
+use std::sync::Arc;
+
+use lampo_common::bitcoin::psbt::PartiallySignedTransaction;
+use lampo_common::bitcoin::{ScriptBuf, Transaction};
+use lampo_common::keys::LampoKeysManager;
+use lampo_common::ldk::events::bump_transaction::{
+    BumpTransactionEventHandler, Utxo, Wallet, WalletSource,
+};
+use lampo_common::wallet::WalletManager;
+
+use super::LampoChainManager;
+use crate::utils::logger::LampoLogger;
+
+/// Weight of an empty script sig.
+const EMPTY_SCRIPT_SIG_WEIGHT: u64 = 4;
+/// Witness weight to spend a P2WPKH output.
+const P2WPKH_WITNESS_WEIGHT: u64 = 1 + 1 + 73 + 1 + 33;
+/// Witness weight to spend a P2TR output with the key path.
+const P2TR_KEY_PATH_WITNESS_WEIGHT: u64 = 1 + 1 + 64;
+
+pub type LampoBumpHandler = BumpTransactionEventHandler<
+    Arc<LampoChainManager>,
+    Arc<Wallet<Arc<LampoWalletSource>, Arc<LampoLogger>>>,
+    Arc<LampoKeysManager>,
+    Arc<LampoLogger>,
+>;
+
+pub struct LampoWalletSource {
+    wallet_manager: Arc<dyn WalletManager>,
+}
+
+impl LampoWalletSource {
+    pub fn new(wallet_manager: Arc<dyn WalletManager>) -> Self {
+        Self { wallet_manager }
+    }
+}
+
+impl WalletSource for LampoWalletSource {
+    fn list_confirmed_utxos(&self) -> Result<Vec<Utxo>, ()> {
+        let utxos = self.wallet_manager.list_confirmed_utxos().map_err(|err| {
+            log::error!(target: "bump", "impossible list the utxos: {err}");
+        })?;
+        // ldk needs the weight to spend the utxo, so we use only
+        // the script types that we know.
+        let utxos = utxos
+            .into_iter()
+            .filter_map(|(outpoint, output)| {
+                let witness_weight = if output.script_pubkey.is_v0_p2wpkh() {
+                    P2WPKH_WITNESS_WEIGHT
+                } else if output.script_pubkey.is_v1_p2tr() {
+                    P2TR_KEY_PATH_WITNESS_WEIGHT
+                } else {
+                    return None;
+                };
+                Some(Utxo {
+                    outpoint,
+                    output,
+                    satisfaction_weight: EMPTY_SCRIPT_SIG_WEIGHT + witness_weight,
+                })
+            })
+            .collect();
+        Ok(utxos)
+    }
+
+    fn get_change_script(&self) -> Result<ScriptBuf, ()> {
+        self.wallet_manager.get_change_script().map_err(|err| {
+            log::error!(target: "bump", "impossible get the change script: {err}");
+        })
+    }
+
+    fn sign_psbt(&self, psbt: PartiallySignedTransaction) -> Result<Transaction, ()> {
+        self.wallet_manager.sign_psbt(psbt).map_err(|err| {
+            log::error!(target: "bump", "impossible sign the fee bump: {err}");
+        })
+    }
+}
+
+pub fn bump_handler(
+    chain_manager: Arc<LampoChainManager>,
+    wallet_manager: Arc<dyn WalletManager>,
+    logger: Arc<LampoLogger>,
+) -> LampoBumpHandler {
+    let source = Arc::new(LampoWalletSource::new(wallet_manager.clone()));
+    let wallet = Arc::new(Wallet::new(source, logger.clone()));
+    let keys = wallet_manager.ldk_keys().keys_manager.clone();
+    BumpTransactionEventHandler::new(chain_manager, wallet, keys, logger)
+}
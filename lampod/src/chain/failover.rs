@@ -0,0 +1,305 @@
+//! Failover between the chain backends.
+//!
+//! The backends are ordered by priority and the requests go to the
+//! active one, while a monitor checks the health of all of them. When
+//! the active backend fails too many requests in a row, or its tip is
+//! behind the tip of the others, the first healthy backend becomes the
+//! active one and the `BackendSwitched` event is emitted, so the channel
+//! manager can resync with the new backend.
+//!
+//! A backend starts to listen the first time that it becomes active,
+//! and its events are dropped when it is not the active one anymore.
+//! So on a switch the channel manager connects again all the blocks
+//! after its best block with the new backend.
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use lampo_common::backend::{AsyncBlockSourceResult, BlockData, BlockHeaderData, TxResult};
+use lampo_common::backend::{Backend, BackendKind, UtxoResult, WatchedOutput};
+use lampo_common::backend::{BlockHash, Script, Transaction, Txid};
+use lampo_common::bitcoin::Network;
+use lampo_common::chan;
+use lampo_common::error;
+use lampo_common::event::onchain::OnChainEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+
+/// Seconds between two health checks of the backends.
+const HEALTH_INTERVAL: Duration = Duration::from_secs(30);
+/// Number of failed requests in a row after that
+/// a backend is not healthy.
+const MAX_ERRORS: usize = 5;
+
+/// Handler of a single backend, it forwards the
+/// events only when the backend is the active one.
+struct BackendHandler {
+    index: usize,
+    active: Arc<AtomicUsize>,
+    inner: Arc<dyn Handler>,
+}
+
+impl Handler for BackendHandler {
+    fn events(&self) -> chan::Receiver<Event> {
+        self.inner.events()
+    }
+
+    fn emit(&self, event: Event) {
+        if self.active.load(Ordering::SeqCst) == self.index {
+            self.inner.emit(event);
+        }
+    }
+}
+
+pub struct LampoBackendFailover {
+    /// The backends with their name, in order of priority.
+    backends: Vec<(String, Arc<dyn Backend>)>,
+    active: Arc<AtomicUsize>,
+    /// Failed requests in a row of each backend.
+    errors: Vec<AtomicUsize>,
+    listening: Vec<AtomicBool>,
+    max_lag: u32,
+    handler: Mutex<Option<Arc<dyn Handler>>>,
+}
+
+impl LampoBackendFailover {
+    pub fn new(backends: Vec<(String, Arc<dyn Backend>)>, max_lag: u32) -> error::Result<Self> {
+        if backends.is_empty() {
+            error::bail!("at least one chain backend is required");
+        }
+        Ok(Self {
+            errors: backends.iter().map(|_| AtomicUsize::new(0)).collect(),
+            listening: backends.iter().map(|_| AtomicBool::new(false)).collect(),
+            backends,
+            active: Arc::new(AtomicUsize::new(0)),
+            max_lag,
+            handler: Mutex::new(None),
+        })
+    }
+
+    fn handler(&self) -> error::Result<Arc<dyn Handler>> {
+        self.handler
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(error::anyhow!("handler is not set"))
+    }
+
+    fn active(&self) -> &Arc<dyn Backend> {
+        &self.backends[self.active.load(Ordering::SeqCst)].1
+    }
+
+    /// The name of the active backend.
+    pub fn active_backend(&self) -> String {
+        self.backends[self.active.load(Ordering::SeqCst)].0.clone()
+    }
+
+    /// Run `request` on the active backend, when the backend fails too
+    /// many requests we switch backend and the request is run again.
+    fn request<T>(&self, request: impl Fn(&dyn Backend) -> error::Result<T>) -> error::Result<T> {
+        let index = self.active.load(Ordering::SeqCst);
+        let err = match request(self.backends[index].1.as_ref()) {
+            Ok(result) => {
+                self.errors[index].store(0, Ordering::SeqCst);
+                return Ok(result);
+            }
+            Err(err) => err,
+        };
+        let errors = self.errors[index].fetch_add(1, Ordering::SeqCst) + 1;
+        log::warn!(target: "failover", "the backend `{}` failed {errors} requests in a row: {err}", self.backends[index].0);
+        if errors < MAX_ERRORS {
+            return Err(err);
+        }
+        self.check_health();
+        let active = self.active.load(Ordering::SeqCst);
+        if active == index {
+            return Err(err);
+        }
+        request(self.backends[active].1.as_ref())
+    }
+
+    /// Look at the tip of all the backends and switch to the
+    /// first healthy one, if it is not already the active one.
+    fn check_health(&self) {
+        let tips = self
+            .backends
+            .iter()
+            .map(|(name, backend)| match backend.get_best_block() {
+                Ok((_, height)) => height,
+                Err(err) => {
+                    log::warn!(target: "failover", "the backend `{name}` is not reachable: {err}");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let Some(best) = tips.iter().flatten().max().copied() else {
+            log::error!(target: "failover", "all the chain backends are not reachable");
+            return;
+        };
+        let active = self.active.load(Ordering::SeqCst);
+        let mut healthy = None;
+        for (index, tip) in tips.into_iter().enumerate() {
+            let Some(tip) = tip else {
+                continue;
+            };
+            // a backend that failed is used again only after that
+            // it passed some health checks.
+            if index != active {
+                let _ =
+                    self.errors[index].fetch_update(Ordering::SeqCst, Ordering::SeqCst, |errors| {
+                        Some(errors.saturating_sub(1))
+                    });
+            }
+            if tip + self.max_lag < best {
+                log::debug!(target: "failover", "the backend `{}` is at height {tip}, the best tip is at height {best}", self.backends[index].0);
+                continue;
+            }
+            if self.errors[index].load(Ordering::SeqCst) < MAX_ERRORS {
+                healthy = Some(index);
+                break;
+            }
+        }
+        match healthy {
+            Some(index) if index != active => {
+                if let Err(err) = self.switch(index) {
+                    log::error!(target: "failover", "impossible switch to the backend `{}`: {err}", self.backends[index].0);
+                }
+            }
+            Some(_) => {}
+            None => {
+                log::error!(target: "failover", "no chain backend is healthy, we keep `{}`", self.active_backend())
+            }
+        }
+    }
+
+    fn switch(&self, index: usize) -> error::Result<()> {
+        let handler = self.handler()?;
+        let previous = self.active.swap(index, Ordering::SeqCst);
+        let name = self.backends[index].0.clone();
+        log::warn!(target: "failover", "switching from the backend `{}` to `{name}`", self.backends[previous].0);
+        if !self.listening[index].swap(true, Ordering::SeqCst) {
+            // the thread keeps running also when the backend is
+            // not active anymore, its events are dropped.
+            let _ = self.backends[index].1.clone().listen()?;
+        }
+        handler.emit(Event::OnChain(OnChainEvent::BackendSwitched(name)));
+        Ok(())
+    }
+}
+
+impl Backend for LampoBackendFailover {
+    fn kind(&self) -> BackendKind {
+        self.active().kind()
+    }
+
+    fn network(&self) -> error::Result<Network> {
+        self.request(|backend| backend.network())
+    }
+
+    fn signet_challenge(&self) -> error::Result<Option<String>> {
+        self.request(|backend| backend.signet_challenge())
+    }
+
+    fn fee_rate_estimation(&self, blocks: u64) -> error::Result<u32> {
+        self.request(|backend| backend.fee_rate_estimation(blocks))
+    }
+
+    fn minimum_mempool_fee(&self) -> error::Result<u32> {
+        self.request(|backend| backend.minimum_mempool_fee())
+    }
+
+    fn brodcast_tx(&self, tx: &Transaction) {
+        // all the backends send the transaction, so it reaches
+        // the network also when the active backend is failing.
+        for (_, backend) in &self.backends {
+            backend.brodcast_tx(tx);
+        }
+    }
+
+    fn is_lightway(&self) -> bool {
+        self.backends
+            .iter()
+            .any(|(_, backend)| backend.is_lightway())
+    }
+
+    fn watch_utxo(&self, txid: &Txid, script: &Script) {
+        // all the backends must know what to watch, so they
+        // can replace the active one at any time.
+        for (_, backend) in &self.backends {
+            backend.watch_utxo(txid, script);
+        }
+    }
+
+    fn register_output(&self, output: WatchedOutput) -> Option<(usize, Transaction)> {
+        let active = self.active.load(Ordering::SeqCst);
+        let mut spent = None;
+        for (index, (_, backend)) in self.backends.iter().enumerate() {
+            let result = backend.register_output(output.clone());
+            if index == active {
+                spent = result;
+            }
+        }
+        spent
+    }
+
+    fn get_header<'a>(
+        &'a self,
+        header_hash: &'a BlockHash,
+        height_hint: Option<u32>,
+    ) -> AsyncBlockSourceResult<'a, BlockHeaderData> {
+        self.active().get_header(header_hash, height_hint)
+    }
+
+    fn get_block<'a>(&'a self, header_hash: &'a BlockHash) -> error::Result<BlockData> {
+        self.request(|backend| backend.get_block(header_hash))
+    }
+
+    fn get_best_block(&self) -> error::Result<(BlockHash, Option<u32>)> {
+        self.request(|backend| backend.get_best_block())
+    }
+
+    fn get_utxo(&self, block: &BlockHash, idx: u64) -> UtxoResult {
+        self.active().get_utxo(block, idx)
+    }
+
+    fn get_utxo_by_txid(&self, txid: &Txid, script: &Script) -> error::Result<TxResult> {
+        self.request(|backend| backend.get_utxo_by_txid(txid, script))
+    }
+
+    fn set_handler(&self, handler: Arc<dyn Handler>) {
+        for (index, (_, backend)) in self.backends.iter().enumerate() {
+            backend.set_handler(Arc::new(BackendHandler {
+                index,
+                active: self.active.clone(),
+                inner: handler.clone(),
+            }));
+        }
+        *self.handler.lock().unwrap() = Some(handler);
+    }
+
+    fn manage_transactions(&self, txs: &mut Vec<Txid>) -> error::Result<()> {
+        self.request(|backend| backend.manage_transactions(&mut txs.clone()))?;
+        txs.clear();
+        Ok(())
+    }
+
+    fn listen(self: Arc<Self>) -> error::Result<JoinHandle<()>> {
+        let active = self.active.load(Ordering::SeqCst);
+        self.listening[active].store(true, Ordering::SeqCst);
+        let _ = self.active().clone().listen()?;
+        log::info!(target: "failover", "Starting the health monitor of the chain backends ...");
+        Ok(std::thread::spawn(move || loop {
+            std::thread::sleep(HEALTH_INTERVAL);
+            self.check_health();
+        }))
+    }
+
+    fn get_transaction(&self, txid: &Txid) -> error::Result<TxResult> {
+        self.request(|backend| backend.get_transaction(txid))
+    }
+
+    fn process_transactions(&self) -> error::Result<()> {
+        self.request(|backend| backend.process_transactions())
+    }
+}
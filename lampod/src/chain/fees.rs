@@ -0,0 +1,207 @@
+//! Fee estimation of the node.
+//!
+//! The fee rates of all the ldk confirmation targets are asked to the
+//! backend and cached, so ldk does not wait the backend every time that
+//! it needs a fee rate, and the cache is refreshed at every new block.
+//! The same estimations are used to fund the channels, to sweep the
+//! outputs of the closed channels and to force close them.
+//!
+//! When `fee-source` is configured the fee rates are asked to an esplora
+//! compatible API (e.g: mempool.space) before the backend, the backend
+//! is used when the API fails or when it returns an absurd fee rate.
+//!
+//! The fee rates are in sat per 1000 weight, like inside ldk.
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use lampo_common::backend::Backend;
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::event::onchain::OnChainEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::json;
+use lampo_common::ldk::chain::chaininterface::{ConfirmationTarget, FEERATE_FLOOR_SATS_PER_KW};
+use lampo_common::types::FeeRate;
+
+use crate::actions::handler::LampoHandler;
+
+/// All the targets that ldk can ask for.
+pub const CONFIRMATION_TARGETS: [ConfirmationTarget; 7] = [
+    ConfirmationTarget::OnChainSweep,
+    ConfirmationTarget::MinAllowedNonAnchorChannelRemoteFee,
+    ConfirmationTarget::NonAnchorChannelFee,
+    ConfirmationTarget::MinAllowedAnchorChannelRemoteFee,
+    ConfirmationTarget::AnchorChannelFee,
+    ConfirmationTarget::ChannelCloseMinimum,
+    ConfirmationTarget::OutputSpendingFee,
+];
+/// A fee rate higher than 1000 sat/vB is not an estimation,
+/// e.g: the API is broken or it is lying to us.
+const MAX_FEE_RATE: u32 = 250_000;
+
+/// Fee source with the esplora API, e.g: `https://mempool.space/api`.
+pub struct EsploraFeeSource {
+    url: String,
+}
+
+impl EsploraFeeSource {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    /// The fee rate to confirm a transaction inside `blocks` blocks.
+    pub fn fee_rate_estimation(&self, blocks: u64) -> error::Result<u32> {
+        let url = format!("{}/fee-estimates", self.url);
+        let response = minreq::get(&url).with_timeout(30).send()?;
+        if response.status_code != 200 {
+            error::bail!("`{url}` returned status code `{}`", response.status_code);
+        }
+        // the targets in blocks with the fee rate in sat/vB
+        let estimates: HashMap<String, f64> = json::from_str(response.as_str()?)?;
+        let (_, fee_rate) = estimates
+            .iter()
+            .filter_map(|(target, fee_rate)| Some((u64::from_str(target).ok()?, *fee_rate)))
+            .filter(|(target, _)| *target <= blocks)
+            .max_by_key(|(target, _)| *target)
+            .ok_or(error::anyhow!(
+                "`{url}` does not estimate the fee rate for {blocks} blocks"
+            ))?;
+        Ok((fee_rate * 250.0).ceil() as u32)
+    }
+}
+
+pub struct LampoFeeEstimator {
+    backend: Arc<dyn Backend>,
+    source: Option<EsploraFeeSource>,
+    /// The min fee rate that we use, also when the
+    /// backend estimates a lower one.
+    min_fee_rate: u32,
+    cache: Mutex<HashMap<ConfirmationTarget, u32>>,
+}
+
+impl LampoFeeEstimator {
+    pub fn new(backend: Arc<dyn Backend>, conf: &LampoConf) -> Self {
+        Self {
+            backend,
+            source: conf.fee_source.as_deref().map(EsploraFeeSource::new),
+            min_fee_rate: conf
+                .min_fee_rate
+                .unwrap_or_default()
+                .max(FEERATE_FLOOR_SATS_PER_KW),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn min_fee_rate(&self) -> u32 {
+        self.min_fee_rate
+    }
+
+    /// The fee rate to confirm a transaction inside `blocks` blocks,
+    /// from the fee source when it gives us a sane fee rate.
+    fn fee_rate_estimation(&self, blocks: u64) -> error::Result<u32> {
+        if let Some(source) = &self.source {
+            match source.fee_rate_estimation(blocks) {
+                Ok(fee_rate) if fee_rate > 0 && fee_rate <= MAX_FEE_RATE => return Ok(fee_rate),
+                Ok(fee_rate) => {
+                    log::warn!(target: "fees", "the fee source returned an absurd fee rate of {fee_rate} sat/kw for {blocks} blocks, using the backend")
+                }
+                Err(err) => {
+                    log::warn!(target: "fees", "impossible use the fee source, using the backend: {err}")
+                }
+            }
+        }
+        self.backend.fee_rate_estimation(blocks)
+    }
+
+    /// Ask the fee rate of `target` to the fee source or to the backend.
+    fn fetch(&self, target: ConfirmationTarget) -> error::Result<u32> {
+        let fee_rate = match target {
+            ConfirmationTarget::OnChainSweep => self.fee_rate_estimation(1)?,
+            ConfirmationTarget::MinAllowedNonAnchorChannelRemoteFee
+            | ConfirmationTarget::AnchorChannelFee
+            | ConfirmationTarget::NonAnchorChannelFee => self.fee_rate_estimation(6)?,
+            ConfirmationTarget::MinAllowedAnchorChannelRemoteFee => {
+                self.backend.minimum_mempool_fee()?
+            }
+            ConfirmationTarget::ChannelCloseMinimum => self.fee_rate_estimation(100)?,
+            ConfirmationTarget::OutputSpendingFee => self.fee_rate_estimation(12)?,
+        };
+        Ok(fee_rate.max(self.min_fee_rate))
+    }
+
+    /// Refresh the fee rates of all the targets, the targets that
+    /// the backend can not estimate keep the previous fee rate.
+    pub fn refresh(&self) {
+        for target in CONFIRMATION_TARGETS {
+            match self.fetch(target) {
+                Ok(fee_rate) => {
+                    self.cache.lock().unwrap().insert(target, fee_rate);
+                }
+                Err(err) => {
+                    log::warn!(target: "fees", "impossible estimate the fee rate for `{target:?}`: {err}")
+                }
+            }
+        }
+    }
+
+    /// The fee rate of `target`, from the cache when we have it.
+    pub fn estimate(&self, target: ConfirmationTarget) -> error::Result<u32> {
+        if let Some(fee_rate) = self.cache.lock().unwrap().get(&target) {
+            return Ok(*fee_rate);
+        }
+        let fee_rate = self.fetch(target)?;
+        self.cache.lock().unwrap().insert(target, fee_rate);
+        Ok(fee_rate)
+    }
+
+    /// The fee rate in sat per 1000 weight of a fee rate
+    /// chosen by the user.
+    pub fn fee_rate(&self, fee_rate: FeeRate) -> error::Result<u32> {
+        match fee_rate {
+            FeeRate::Slow => self.estimate(ConfirmationTarget::ChannelCloseMinimum),
+            FeeRate::Normal => self.estimate(ConfirmationTarget::NonAnchorChannelFee),
+            FeeRate::Urgent => self.estimate(ConfirmationTarget::OnChainSweep),
+            FeeRate::SatPerVByte(fee_rate) => {
+                let fee_rate = fee_rate.saturating_mul(250);
+                if fee_rate > MAX_FEE_RATE as u64 {
+                    error::bail!(
+                        "the fee rate of {} sat/vB is higher than the max of {} sat/vB",
+                        fee_rate / 250,
+                        MAX_FEE_RATE / 250
+                    );
+                }
+                Ok((fee_rate as u32).max(FEERATE_FLOOR_SATS_PER_KW))
+            }
+        }
+    }
+
+    /// The fee rate of `target`, or the min fee rate when
+    /// the backend is not able to estimate it.
+    pub fn estimate_or_min(&self, target: ConfirmationTarget) -> u32 {
+        self.estimate(target).unwrap_or_else(|err| {
+            log::warn!(target: "fees", "using the min fee rate for `{target:?}`: {err}");
+            self.min_fee_rate
+        })
+    }
+}
+
+/// Refresh the fee rates at every new block.
+pub fn watch_fees(fees: Arc<LampoFeeEstimator>, handler: Arc<LampoHandler>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let events = handler.events();
+        loop {
+            let Ok(Event::OnChain(OnChainEvent::NewBestBlock(_))) = events.recv() else {
+                continue;
+            };
+            fees.refresh();
+            if let Ok(fee_rate) = fees.estimate(ConfirmationTarget::NonAnchorChannelFee) {
+                handler.emit(Event::OnChain(OnChainEvent::FeeEstimation(fee_rate)));
+            }
+        }
+    })
+}
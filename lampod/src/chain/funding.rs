@@ -0,0 +1,148 @@
+//! Funding of the channels with the wallet utxos.
+//!
+//! The utxos are chosen by the user or with the configured coin
+//! selection, and they are reserved until ldk broadcasts the funding
+//! transaction or until ldk discards it, so the channels that are
+//! opened at the same time never spend the same utxo.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use lampo_common::bitcoin::{OutPoint, ScriptBuf, Transaction, TxOut, Txid};
+use lampo_common::coin_selection::{self, CoinSelection};
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::wallet::WalletManager;
+
+pub struct LampoFunder {
+    wallet_manager: Arc<dyn WalletManager>,
+    strategy: CoinSelection,
+    /// The reserved utxos with the txid of the
+    /// funding transaction that spends them.
+    reserved: Mutex<HashMap<OutPoint, Txid>>,
+}
+
+impl LampoFunder {
+    pub fn new(wallet_manager: Arc<dyn WalletManager>, conf: &LampoConf) -> Self {
+        Self {
+            wallet_manager,
+            strategy: conf.coin_selection,
+            reserved: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The confirmed utxos of the wallet that are not reserved.
+    fn available_utxos(
+        &self,
+        reserved: &HashMap<OutPoint, Txid>,
+    ) -> error::Result<Vec<(OutPoint, TxOut)>> {
+        let utxos = self
+            .wallet_manager
+            .list_confirmed_utxos()?
+            .into_iter()
+            .filter(|(outpoint, _)| !reserved.contains_key(outpoint))
+            .collect();
+        Ok(utxos)
+    }
+
+    /// Check that the `utxos` chosen by the user are confirmed
+    /// utxos of the wallet that are not reserved, and return
+    /// their amount.
+    pub fn check_utxos(&self, utxos: &[OutPoint]) -> error::Result<u64> {
+        let reserved = self.reserved.lock().unwrap();
+        let available = self.available_utxos(&reserved)?;
+        let mut amount_sat = 0;
+        for utxo in utxos {
+            if reserved.contains_key(utxo) {
+                error::bail!("the utxo `{utxo}` is reserved by another funding");
+            }
+            let Some((_, output)) = available.iter().find(|(outpoint, _)| outpoint == utxo) else {
+                error::bail!("the utxo `{utxo}` is not a confirmed utxo of the wallet");
+            };
+            amount_sat += output.value;
+        }
+        Ok(amount_sat)
+    }
+
+    /// Build the transaction that pays all the `outputs` (script and
+    /// amount) with the `utxos` chosen by the user, or with the ones
+    /// chosen by the coin selection. Its inputs are reserved.
+    pub fn fund(
+        &self,
+        outputs: &[(ScriptBuf, u64)],
+        fee_rate: u32,
+        utxos: Option<&[OutPoint]>,
+    ) -> error::Result<Transaction> {
+        // the lock is kept until the inputs are reserved, so
+        // two fundings do not choose the same utxos.
+        let mut reserved = self.reserved.lock().unwrap();
+        let available = self.available_utxos(&reserved)?;
+        let inputs = match utxos {
+            Some(utxos) => {
+                // the utxos can be spent or reserved after that
+                // the channel was opened.
+                if let Some(utxo) = utxos
+                    .iter()
+                    .find(|utxo| !available.iter().any(|(outpoint, _)| outpoint == *utxo))
+                {
+                    error::bail!("the utxo `{utxo}` can not fund the channel anymore");
+                }
+                utxos.to_vec()
+            }
+            None => {
+                let amount_sat = outputs.iter().map(|(_, amount)| amount).sum();
+                let outputs_weight = outputs
+                    .iter()
+                    .map(|(script, _)| coin_selection::output_weight(script.len()))
+                    .sum();
+                coin_selection::select_coins(
+                    self.strategy,
+                    &available,
+                    amount_sat,
+                    outputs_weight,
+                    fee_rate,
+                )?
+            }
+        };
+        let tx = self
+            .wallet_manager
+            .create_transaction_with_inputs(&inputs, outputs, fee_rate)?;
+        let txid = tx.txid();
+        for input in &tx.input {
+            reserved.insert(input.previous_output, txid);
+        }
+        log::debug!(target: "funding", "reserved {} utxos for the funding `{txid}`", tx.input.len());
+        Ok(tx)
+    }
+
+    /// Forget the reservations of a funding transaction that
+    /// is broadcast, its inputs are spent now.
+    pub fn funding_broadcast(&self, txid: &Txid) {
+        self.reserved
+            .lock()
+            .unwrap()
+            .retain(|_, funding| funding != txid);
+    }
+
+    /// Release the inputs of a funding transaction that will
+    /// never be broadcast.
+    pub fn release(&self, txid: &Txid) -> error::Result<()> {
+        let mut reserved = self.reserved.lock().unwrap();
+        let inputs = reserved
+            .iter()
+            .filter(|(_, funding)| *funding == txid)
+            .map(|(outpoint, _)| *outpoint)
+            .collect::<Vec<_>>();
+        if inputs.is_empty() {
+            // e.g: the transaction of an external wallet
+            return Ok(());
+        }
+        reserved.retain(|_, funding| funding != txid);
+        log::info!(target: "funding", "releasing {} utxos of the funding `{txid}`", inputs.len());
+        self.wallet_manager.unlock_inputs(&inputs)
+    }
+
+    /// The utxos that are reserved by a funding transaction.
+    pub fn reserved(&self) -> Vec<OutPoint> {
+        self.reserved.lock().unwrap().keys().copied().collect()
+    }
+}
@@ -1,7 +1,17 @@
 //! Chain module implementation that contains all the code related to the blockchain communication.
 mod blockchain;
+mod bump;
+mod failover;
+mod fees;
+mod funding;
+mod sweep;
 
 pub use lampo_common::bitcoin::Network;
 pub use lampo_common::wallet::WalletManager;
 
 pub use blockchain::LampoChainManager;
+pub use bump::{bump_handler, LampoBumpHandler};
+pub use failover::LampoBackendFailover;
+pub use fees::{watch_fees, LampoFeeEstimator};
+pub use funding::LampoFunder;
+pub use sweep::{list_sweeps, sweeper, watch_sweeper, LampoSweeper};
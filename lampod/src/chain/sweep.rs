@@ -0,0 +1,208 @@
+//! Sweep of the outputs of the closed channels.
+//!
+//! When a channel is closed ldk gives us the outputs that we can spend
+//! (our balance, the HTLCs claimed on chain) with `SpendableOutputs`.
+//! The ldk output sweeper spends them to a fresh wallet address, and
+//! at every block it broadcasts the sweep again with a higher fee until
+//! the sweep is confirmed. The state of the sweeper is stored, so the
+//! outputs are swept also after a restart.
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use lampo_common::bitcoin::{Address, ScriptBuf};
+use lampo_common::error;
+use lampo_common::event::onchain::OnChainEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::keys::LampoKeysManager;
+use lampo_common::ldk::chain::{BestBlock, Confirm};
+use lampo_common::ldk::sign::{ChangeDestinationSource, SpendableOutputDescriptor};
+use lampo_common::ldk::util::persist::{
+    KVStore, OUTPUT_SWEEPER_PERSISTENCE_KEY, OUTPUT_SWEEPER_PERSISTENCE_PRIMARY_NAMESPACE,
+    OUTPUT_SWEEPER_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use lampo_common::ldk::util::ser::ReadableArgs;
+use lampo_common::ldk::util::sweep::{OutputSpendStatus, OutputSweeper};
+use lampo_common::model::response::{Sweep, SweepStatus, Sweeps};
+use lampo_common::wallet::WalletManager;
+
+use super::LampoChainManager;
+use crate::actions::handler::LampoHandler;
+use crate::persistence::LampoPersistence;
+use crate::utils::logger::LampoLogger;
+
+pub type LampoSweeper = OutputSweeper<
+    Arc<LampoChainManager>,
+    Arc<LampoChangeDestination>,
+    Arc<LampoChainManager>,
+    Arc<LampoChainManager>,
+    Arc<LampoPersistence>,
+    Arc<LampoLogger>,
+    Arc<LampoKeysManager>,
+>;
+
+/// Give to the sweeper a fresh address of the wallet.
+pub struct LampoChangeDestination {
+    wallet_manager: Arc<dyn WalletManager>,
+}
+
+impl LampoChangeDestination {
+    pub fn new(wallet_manager: Arc<dyn WalletManager>) -> Self {
+        Self { wallet_manager }
+    }
+}
+
+impl ChangeDestinationSource for LampoChangeDestination {
+    fn get_change_destination_script(&self) -> Result<ScriptBuf, ()> {
+        let address = self.wallet_manager.get_onchain_address().map_err(|err| {
+            log::error!(target: "sweep", "impossible get a wallet address: {err}");
+        })?;
+        let address = Address::from_str(&address.address).map_err(|err| {
+            log::error!(target: "sweep", "invalid wallet address `{}`: {err}", address.address);
+        })?;
+        Ok(address.assume_checked().script_pubkey())
+    }
+}
+
+/// Build the sweeper, the outputs that were not swept
+/// before the last shutdown are loaded from the persistence.
+pub fn sweeper(
+    best_block: BestBlock,
+    chain_manager: Arc<LampoChainManager>,
+    wallet_manager: Arc<dyn WalletManager>,
+    persister: Arc<LampoPersistence>,
+    logger: Arc<LampoLogger>,
+) -> error::Result<LampoSweeper> {
+    let change = Arc::new(LampoChangeDestination::new(wallet_manager.clone()));
+    let keys = wallet_manager.ldk_keys().keys_manager.clone();
+    let state = persister.read(
+        OUTPUT_SWEEPER_PERSISTENCE_PRIMARY_NAMESPACE,
+        OUTPUT_SWEEPER_PERSISTENCE_SECONDARY_NAMESPACE,
+        OUTPUT_SWEEPER_PERSISTENCE_KEY,
+    );
+    let sweeper = match state {
+        Ok(state) => LampoSweeper::read(
+            &mut state.as_slice(),
+            (
+                chain_manager.clone(),
+                chain_manager.clone(),
+                Some(chain_manager),
+                keys,
+                change,
+                persister,
+                logger,
+            ),
+        )
+        .map_err(|err| error::anyhow!("impossible read the output sweeper: {:?}", err))?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => OutputSweeper::new(
+            best_block,
+            chain_manager.clone(),
+            chain_manager.clone(),
+            Some(chain_manager),
+            keys,
+            change,
+            persister,
+            logger,
+        ),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(sweeper)
+}
+
+/// The outputs that the sweeper is still tracking.
+pub fn list_sweeps(sweeper: &LampoSweeper) -> Sweeps {
+    let sweeps = sweeper
+        .tracked_spendable_outputs()
+        .into_iter()
+        .map(|tracked| {
+            let (outpoint, output) = match &tracked.descriptor {
+                SpendableOutputDescriptor::StaticOutput {
+                    outpoint, output, ..
+                } => (outpoint, output),
+                SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => {
+                    (&descriptor.outpoint, &descriptor.output)
+                }
+                SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => {
+                    (&descriptor.outpoint, &descriptor.output)
+                }
+            };
+            let (status, txid, height) = match &tracked.status {
+                OutputSpendStatus::PendingInitialBroadcast {
+                    delayed_until_height,
+                } => (SweepStatus::Pending, None, *delayed_until_height),
+                OutputSpendStatus::PendingFirstConfirmation {
+                    latest_broadcast_height,
+                    latest_spending_tx,
+                    ..
+                } => (
+                    SweepStatus::Broadcast,
+                    Some(latest_spending_tx.txid().to_string()),
+                    Some(*latest_broadcast_height),
+                ),
+                OutputSpendStatus::PendingThresholdConfirmations {
+                    latest_spending_tx,
+                    confirmation_height,
+                    ..
+                } => (
+                    SweepStatus::Confirmed,
+                    Some(latest_spending_tx.txid().to_string()),
+                    Some(*confirmation_height),
+                ),
+            };
+            Sweep {
+                outpoint: format!("{}:{}", outpoint.txid, outpoint.index),
+                amount_sat: output.value,
+                channel_id: tracked.channel_id.map(|id| id.to_string()),
+                status,
+                txid,
+                height,
+            }
+        })
+        .collect::<Vec<_>>();
+    let pending_sat = sweeps
+        .iter()
+        .filter(|sweep| !matches!(sweep.status, SweepStatus::Confirmed))
+        .map(|sweep| sweep.amount_sat)
+        .sum();
+    Sweeps {
+        sweeps,
+        pending_sat,
+    }
+}
+
+/// Keep the sweeper in sync with the chain, a new block
+/// is also the moment when the sweep is broadcast again.
+pub fn watch_sweeper(sweeper: Arc<LampoSweeper>, handler: Arc<LampoHandler>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let events = handler.events();
+        loop {
+            let Ok(Event::OnChain(event)) = events.recv() else {
+                continue;
+            };
+            match event {
+                OnChainEvent::NewBestBlock((header, height)) => {
+                    sweeper.best_block_updated(&header, height.to_consensus_u32());
+                }
+                OnChainEvent::ConfirmedTransaction((tx, idx, header, height)) => {
+                    sweeper.transactions_confirmed(
+                        &header,
+                        &[(idx as usize, &tx)],
+                        height.to_consensus_u32(),
+                    );
+                }
+                OnChainEvent::UnconfirmedTransaction(txid) => {
+                    sweeper.transaction_unconfirmed(&txid);
+                }
+                OnChainEvent::BlockDisconnected((_, height)) => {
+                    for (txid, confirmed_at, _) in sweeper.get_relevant_txids() {
+                        if confirmed_at >= height.to_consensus_u32() {
+                            sweeper.transaction_unconfirmed(&txid);
+                        }
+                    }
+                }
+                _ => continue,
+            }
+        }
+    })
+}
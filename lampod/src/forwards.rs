@@ -0,0 +1,69 @@
+//! Forwarding history.
+//!
+//! Ldk reports each forwarded HTLC only once, so we keep a ledger of
+//! the forwards with the fee earned, each forward is stored under the
+//! time when it was settled.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lampo_common::error;
+use lampo_common::model::response::Forward;
+use lampo_common::types::ChannelId;
+
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const FORWARDS_NAMESPACE: &str = "forwards";
+
+pub struct LampoForwardStore {
+    persister: Arc<LampoPersistence>,
+    // more forwards can be settled in the same millisecond
+    seq: AtomicU64,
+}
+
+impl LampoForwardStore {
+    pub fn new(persister: Arc<LampoPersistence>) -> Self {
+        Self {
+            persister,
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(
+        &self,
+        in_channel_id: Option<ChannelId>,
+        out_channel_id: Option<ChannelId>,
+        amount_out_msat: Option<u64>,
+        fee_msat: Option<u64>,
+        claimed_on_chain: bool,
+    ) -> error::Result<Forward> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let forward = Forward {
+            in_channel_id: in_channel_id.map(|id| id.to_string()),
+            out_channel_id: out_channel_id.map(|id| id.to_string()),
+            amount_in_msat: amount_out_msat.zip(fee_msat).map(|(out, fee)| out + fee),
+            amount_out_msat,
+            fee_msat,
+            claimed_on_chain,
+            forwarded_at: now.as_secs(),
+        };
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let key = format!("{:016}-{seq}", now.as_millis());
+        self.persister
+            .write_json(FORWARDS_NAMESPACE, &key, &forward)?;
+        Ok(forward)
+    }
+
+    /// The forwards settled between `since` and `until` (unix seconds).
+    pub fn list(&self, since: Option<u64>, until: Option<u64>) -> error::Result<Vec<Forward>> {
+        let mut forwards = self
+            .persister
+            .list_json::<Forward>(FORWARDS_NAMESPACE)?
+            .into_iter()
+            .filter(|forward| since.map_or(true, |since| forward.forwarded_at >= since))
+            .filter(|forward| until.map_or(true, |until| forward.forwarded_at <= until))
+            .collect::<Vec<_>>();
+        forwards.sort_by_key(|forward| forward.forwarded_at);
+        Ok(forwards)
+    }
+}
@@ -0,0 +1,443 @@
+//! Invoice store implementation.
+//!
+//! Ldk does not store the invoices that we generate, so we keep
+//! track of them (with their label and status) inside the persistence.
+//!
+//! All the invoices are stored under the same key, so each update
+//! is a single write and a batch of invoices is stored all or nothing.
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lampo_common::bitcoin::{Address, Block, Txid};
+use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::onchain::OnChainEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::ldk::invoice::Bolt11Invoice;
+use lampo_common::model::response::{InvoiceRecord, InvoiceStatus};
+
+use crate::actions::handler::LampoHandler;
+use crate::ln::OffchainManager;
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const INVOICES_NAMESPACE: &str = "invoices";
+const INVOICES_KEY: &str = "index";
+/// Blocks before the claim deadline when a held payment is cancelled,
+/// after the deadline ldk fails it back and the peer may force close.
+const HOLD_CANCEL_BLOCKS: u32 = 6;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+pub struct LampoInvoiceStore {
+    persister: Arc<LampoPersistence>,
+    // payment hash -> invoice
+    invoices: Mutex<BTreeMap<String, InvoiceRecord>>,
+}
+
+impl LampoInvoiceStore {
+    pub fn new(persister: Arc<LampoPersistence>) -> error::Result<Self> {
+        let invoices = persister
+            .read_json::<BTreeMap<String, InvoiceRecord>>(INVOICES_NAMESPACE, INVOICES_KEY)?
+            .unwrap_or_default();
+        Ok(Self {
+            persister,
+            invoices: Mutex::new(invoices),
+        })
+    }
+
+    /// Build the record of an invoice that we just generated.
+    pub fn record(invoice: &Bolt11Invoice, label: Option<String>) -> InvoiceRecord {
+        let created_at = invoice.duration_since_epoch().as_secs();
+        InvoiceRecord {
+            bolt11: invoice.to_string(),
+            payment_hash: invoice.payment_hash().to_string(),
+            payment_secret: Some(hex::encode(invoice.payment_secret().0)),
+            label,
+            amount_msat: invoice.amount_milli_satoshis(),
+            fallback_address: None,
+            fallback_txid: None,
+            amount_received_msat: None,
+            hold: false,
+            claim_deadline: None,
+            lsp_fee_msat: None,
+            fiat: None,
+            status: InvoiceStatus::Unpaid,
+            created_at,
+            expires_at: created_at + invoice.expiry_time().as_secs(),
+            settled_at: None,
+        }
+    }
+
+    pub fn insert(&self, record: InvoiceRecord) -> error::Result<()> {
+        self.insert_batch(vec![record])
+    }
+
+    /// Store all the invoices with a single write, if one label is
+    /// already used nothing is stored.
+    pub fn insert_batch(&self, records: Vec<InvoiceRecord>) -> error::Result<()> {
+        let mut invoices = self.invoices.lock().unwrap();
+        let mut updated = invoices.clone();
+        for record in records {
+            if let Some(label) = &record.label {
+                if updated
+                    .values()
+                    .any(|invoice| invoice.label.as_ref() == Some(label))
+                {
+                    error::bail!("an invoice with label `{label}` already exists");
+                }
+            }
+            updated.insert(record.payment_hash.clone(), record);
+        }
+        self.persister
+            .write_json(INVOICES_NAMESPACE, INVOICES_KEY, &updated)?;
+        *invoices = updated;
+        Ok(())
+    }
+
+    /// Move the invoice with `payment_hash` to `status`, only the
+    /// unpaid and accepted invoices can change status.
+    pub fn set_status(
+        &self,
+        payment_hash: &str,
+        status: InvoiceStatus,
+    ) -> error::Result<InvoiceRecord> {
+        self.transition(payment_hash, status, |_| {})
+    }
+
+    /// Mark the invoice as paid with `amount_msat` received.
+    pub fn mark_paid(&self, payment_hash: &str, amount_msat: u64) -> error::Result<InvoiceRecord> {
+        self.transition(payment_hash, InvoiceStatus::Paid, |invoice| {
+            invoice.amount_received_msat = Some(amount_msat);
+            invoice.settled_at = Some(now());
+        })
+    }
+
+    /// Mark the hold invoice as accepted, the payment of `amount_msat`
+    /// must be settled before the `claim_deadline` block.
+    pub fn accept(
+        &self,
+        payment_hash: &str,
+        amount_msat: u64,
+        claim_deadline: Option<u32>,
+    ) -> error::Result<InvoiceRecord> {
+        self.transition(payment_hash, InvoiceStatus::Accepted, |invoice| {
+            invoice.amount_received_msat = Some(amount_msat);
+            invoice.claim_deadline = claim_deadline;
+        })
+    }
+
+    /// Mark the invoice as paid by the on chain transaction `txid`
+    /// to the fallback address.
+    pub fn settle_on_chain(
+        &self,
+        payment_hash: &str,
+        txid: &Txid,
+        amount_msat: u64,
+    ) -> error::Result<InvoiceRecord> {
+        self.transition(payment_hash, InvoiceStatus::Paid, |invoice| {
+            invoice.fallback_txid = Some(txid.to_string());
+            invoice.amount_received_msat = Some(amount_msat);
+            invoice.settled_at = Some(now());
+        })
+    }
+
+    fn transition<F: FnOnce(&mut InvoiceRecord)>(
+        &self,
+        payment_hash: &str,
+        status: InvoiceStatus,
+        update: F,
+    ) -> error::Result<InvoiceRecord> {
+        let mut invoices = self.invoices.lock().unwrap();
+        let Some(invoice) = invoices.get(payment_hash) else {
+            error::bail!("invoice with payment hash `{payment_hash}` not found");
+        };
+        if !matches!(
+            invoice.status,
+            InvoiceStatus::Unpaid | InvoiceStatus::Accepted
+        ) {
+            error::bail!(
+                "invoice `{payment_hash}` is `{:?}`, impossible mark it as `{:?}`",
+                invoice.status,
+                status
+            );
+        }
+        let mut updated = invoices.clone();
+        let invoice = updated
+            .get_mut(payment_hash)
+            .expect("we checked that the invoice exists");
+        invoice.status = status;
+        update(invoice);
+        let invoice = invoice.clone();
+        self.persister
+            .write_json(INVOICES_NAMESPACE, INVOICES_KEY, &updated)?;
+        *invoices = updated;
+        Ok(invoice)
+    }
+
+    pub fn get(&self, payment_hash: &str) -> Option<InvoiceRecord> {
+        self.invoices.lock().unwrap().get(payment_hash).cloned()
+    }
+
+    pub fn get_by_label(&self, label: &str) -> Option<InvoiceRecord> {
+        self.invoices
+            .lock()
+            .unwrap()
+            .values()
+            .find(|invoice| invoice.label.as_deref() == Some(label))
+            .cloned()
+    }
+
+    pub fn list(&self) -> Vec<InvoiceRecord> {
+        self.invoices.lock().unwrap().values().cloned().collect()
+    }
+
+    /// The invoices sorted by creation time, the unpaid invoices
+    /// after the expiry are reported as expired.
+    pub fn list_by_status(&self, status: Option<&InvoiceStatus>) -> Vec<InvoiceRecord> {
+        let now = now();
+        let mut invoices = self
+            .list()
+            .into_iter()
+            .map(|mut invoice| {
+                if invoice.status == InvoiceStatus::Unpaid && invoice.expires_at <= now {
+                    invoice.status = InvoiceStatus::Expired;
+                }
+                invoice
+            })
+            .filter(|invoice| status.map_or(true, |status| invoice.status == *status))
+            .collect::<Vec<_>>();
+        invoices.sort_by_key(|invoice| invoice.created_at);
+        invoices
+    }
+
+    /// Look for the payments to the fallback addresses of the unpaid
+    /// invoices inside the `block`, and return the invoices settled.
+    pub fn settle_fallbacks(&self, block: &Block) -> Vec<InvoiceRecord> {
+        let unpaid = self
+            .list()
+            .into_iter()
+            .filter(|invoice| invoice.status == InvoiceStatus::Unpaid)
+            .filter_map(|invoice| {
+                let address = invoice.fallback_address.as_ref()?;
+                let script = Address::from_str(address)
+                    .ok()?
+                    .assume_checked()
+                    .script_pubkey();
+                Some((invoice, script))
+            })
+            .collect::<Vec<_>>();
+        let mut settled = Vec::new();
+        if unpaid.is_empty() {
+            return settled;
+        }
+        for tx in block.txdata.iter() {
+            for (invoice, script) in unpaid.iter() {
+                let amount_sat = tx
+                    .output
+                    .iter()
+                    .filter(|out| out.script_pubkey == *script)
+                    .map(|out| out.value)
+                    .sum::<u64>();
+                if amount_sat == 0 || amount_sat * 1000 < invoice.amount_msat.unwrap_or_default() {
+                    continue;
+                }
+                match self.settle_on_chain(&invoice.payment_hash, &tx.txid(), amount_sat * 1000) {
+                    Ok(invoice) => settled.push(invoice),
+                    Err(err) => log::debug!(target: "invoices", "{err}"),
+                }
+            }
+        }
+        settled
+    }
+
+    /// Watch the new blocks to correlate the on chain payments
+    /// to the fallback addresses of our invoices.
+    pub fn watch_fallbacks(self: Arc<Self>, handler: Arc<LampoHandler>) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let events = handler.events();
+            loop {
+                let Ok(Event::OnChain(OnChainEvent::NewBlock(block))) = events.recv() else {
+                    continue;
+                };
+                for invoice in self.settle_fallbacks(&block) {
+                    log::info!(target: "invoices", "invoice `{}` paid on chain with `{:?}`", invoice.payment_hash, invoice.fallback_txid);
+                    handler.emit(Event::Lightning(LightningEvent::InvoiceEvent {
+                        payment_hash: invoice.payment_hash,
+                        label: invoice.label,
+                        status: invoice.status,
+                    }));
+                }
+            }
+        })
+    }
+
+    /// Cancel the held payments that are close to the claim deadline.
+    pub fn watch_holds(
+        self: Arc<Self>,
+        handler: Arc<LampoHandler>,
+        offchain: Arc<OffchainManager>,
+    ) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let events = handler.events();
+            loop {
+                let Ok(Event::OnChain(OnChainEvent::NewBestBlock((_, height)))) = events.recv()
+                else {
+                    continue;
+                };
+                let height = height.to_consensus_u32();
+                let expiring = self
+                    .list()
+                    .into_iter()
+                    .filter(|invoice| invoice.status == InvoiceStatus::Accepted)
+                    .filter(|invoice| {
+                        invoice
+                            .claim_deadline
+                            .map_or(false, |deadline| height + HOLD_CANCEL_BLOCKS >= deadline)
+                    });
+                for invoice in expiring {
+                    log::warn!(target: "invoices", "cancelling hold invoice `{}`, the claim deadline is close", invoice.payment_hash);
+                    let result = offchain
+                        .cancel_hold_invoice(&invoice.payment_hash)
+                        .and_then(|_| {
+                            self.set_status(&invoice.payment_hash, InvoiceStatus::Cancelled)
+                        });
+                    match result {
+                        Ok(invoice) => {
+                            handler.emit(Event::Lightning(LightningEvent::InvoiceEvent {
+                                payment_hash: invoice.payment_hash,
+                                label: invoice.label,
+                                status: invoice.status,
+                            }))
+                        }
+                        Err(err) => log::error!(target: "invoices", "{err}"),
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use lampo_common::model::response::{InvoiceRecord, InvoiceStatus};
+
+    use super::{now, LampoInvoiceStore};
+    use crate::persistence::LampoPersistence;
+
+    fn store(name: &str) -> (Arc<LampoPersistence>, LampoInvoiceStore) {
+        let path =
+            std::env::temp_dir().join(format!("lampo-invoices-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let persister = Arc::new(LampoPersistence::new(path));
+        let store = LampoInvoiceStore::new(persister.clone()).unwrap();
+        (persister, store)
+    }
+
+    fn invoice(payment_hash: &str, label: Option<&str>, expires_at: u64) -> InvoiceRecord {
+        InvoiceRecord {
+            bolt11: format!("lnbcrt{payment_hash}"),
+            payment_hash: payment_hash.to_owned(),
+            payment_secret: None,
+            label: label.map(str::to_owned),
+            amount_msat: Some(1_000),
+            fallback_address: None,
+            fallback_txid: None,
+            amount_received_msat: None,
+            hold: false,
+            claim_deadline: None,
+            lsp_fee_msat: None,
+            fiat: None,
+            status: InvoiceStatus::Unpaid,
+            created_at: now(),
+            expires_at,
+            settled_at: None,
+        }
+    }
+
+    #[test]
+    fn pay_hold_invoice() {
+        let (_, store) = store("hold");
+        store.insert(invoice("01", None, now() + 3600)).unwrap();
+        let accepted = store.accept("01", 1_000, Some(200)).unwrap();
+        assert_eq!(accepted.status, InvoiceStatus::Accepted);
+        assert_eq!(accepted.claim_deadline, Some(200));
+        let paid = store.mark_paid("01", 1_000).unwrap();
+        assert_eq!(paid.status, InvoiceStatus::Paid);
+        assert_eq!(paid.amount_received_msat, Some(1_000));
+        assert!(paid.settled_at.is_some());
+    }
+
+    #[test]
+    fn final_status() {
+        let (_, store) = store("final");
+        store.insert(invoice("01", None, now() + 3600)).unwrap();
+        store.insert(invoice("02", None, now() + 3600)).unwrap();
+        store.mark_paid("01", 1_000).unwrap();
+        store.set_status("02", InvoiceStatus::Cancelled).unwrap();
+
+        let err = store
+            .set_status("01", InvoiceStatus::Cancelled)
+            .unwrap_err();
+        assert!(err.to_string().contains("is `Paid`"), "{err}");
+        // a cancelled invoice can not be paid anymore
+        let err = store.mark_paid("02", 1_000).unwrap_err();
+        assert!(err.to_string().contains("is `Cancelled`"), "{err}");
+        assert!(store.accept("02", 1_000, None).is_err());
+        assert_eq!(store.get("02").unwrap().status, InvoiceStatus::Cancelled);
+
+        assert!(store.mark_paid("03", 1_000).is_err());
+    }
+
+    #[test]
+    fn status_is_persisted() {
+        let (persister, store) = store("persisted");
+        store
+            .insert(invoice("01", Some("coffee"), now() + 3600))
+            .unwrap();
+        store.mark_paid("01", 1_000).unwrap();
+
+        let store = LampoInvoiceStore::new(persister).unwrap();
+        let invoice = store.get_by_label("coffee").unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Paid);
+    }
+
+    #[test]
+    fn duplicated_label() {
+        let (_, store) = store("label");
+        store
+            .insert(invoice("01", Some("coffee"), now() + 3600))
+            .unwrap();
+        let err = store
+            .insert_batch(vec![
+                invoice("02", Some("tea"), now() + 3600),
+                invoice("03", Some("coffee"), now() + 3600),
+            ])
+            .unwrap_err();
+        assert!(err.to_string().contains("`coffee`"), "{err}");
+        // the batch is stored all or nothing
+        assert!(store.get("02").is_none());
+    }
+
+    #[test]
+    fn expired_invoice() {
+        let (_, store) = store("expired");
+        store.insert(invoice("01", None, now() - 1)).unwrap();
+        store.insert(invoice("02", None, now() + 3600)).unwrap();
+        let expired = store.list_by_status(Some(&InvoiceStatus::Expired));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].payment_hash, "01");
+        // the expiry is never stored
+        assert_eq!(store.get("01").unwrap().status, InvoiceStatus::Unpaid);
+    }
+}
@@ -1,13 +1,18 @@
 //! JSON RPC 2.0 implementation
 pub mod channels;
 pub mod inventory;
+pub mod lsp;
 pub mod offchain;
 pub mod onchain;
 pub mod open_channel;
 pub mod peer_control;
+pub mod queue;
+pub mod rebalancer;
+pub mod scheduler;
 
 use std::cell::RefCell;
 use std::sync::Arc;
+use std::time::Duration;
 
 use lampo_common::conf::LampoConf;
 use lampo_common::error;
@@ -80,4 +85,12 @@ impl Context for LampoDaemon {
     fn ctx(&self) -> &Self::Ctx {
         self
     }
+
+    fn on_call_started(&self, method: &str) {
+        self.metrics().rpc_started(method);
+    }
+
+    fn on_call_finished(&self, method: &str, elapsed: Duration, ok: bool) {
+        self.metrics().rpc_finished(method, elapsed, ok);
+    }
 }
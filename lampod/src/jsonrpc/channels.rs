@@ -8,6 +8,7 @@ use lampo_jsonrpc::errors::Error;
 use lampo_jsonrpc::errors::RpcError;
 
 use crate::ln::events::ChannelEvents;
+use crate::ln::LampoChannelBackup;
 
 use crate::rpc_error;
 use crate::LampoDaemon;
@@ -18,11 +19,12 @@ pub fn json_list_channels(ctx: &LampoDaemon, request: &json::Value) -> Result<js
     Ok(json::to_value(resp)?)
 }
 
-pub fn json_close_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
-    log::info!("call for `closechannel` with request {:?}", request);
-    let mut request: request::CloseChannel = json::from_value(request.clone())?;
-    let events = ctx.handler().events();
-    // This gives all the channels with associated peer
+/// Find the channel to close, the `channel_id` can be omitted
+/// when there is only one channel with the peer.
+fn channel_to_close(
+    ctx: &LampoDaemon,
+    mut request: request::CloseChannel,
+) -> Result<request::CloseChannel, Error> {
     let channels: response::Channels = ctx.handler().call(
         "channels",
         json::json!({
@@ -48,6 +50,18 @@ pub fn json_close_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<js
         // No channels with the given peer.
         return Err(rpc_error!("No channels with associated peer"));
     };
+    Ok(res)
+}
+
+pub fn json_close_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `closechannel` with request {:?}", request);
+    let request: request::CloseChannel = json::from_value(request.clone())?;
+    let events = ctx.handler().events();
+    let res = channel_to_close(ctx, request)?;
+    if res.simulate.unwrap_or(false) {
+        let simulation = ctx.channel_manager().simulate_close(&res)?;
+        return Ok(json::to_value(simulation)?);
+    }
     ctx.channel_manager().close_channel(res)?;
 
     // FIXME: would be good to have some sort of macros, because
@@ -82,3 +96,86 @@ pub fn json_close_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<js
         "funding_utxo" : funding_utxo,
     }))
 }
+
+pub fn json_force_close_channel(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `forceclose` with request {:?}", request);
+    let request: request::CloseChannel = json::from_value(request.clone())?;
+    let request = channel_to_close(ctx, request)?;
+    let closed = ctx.channel_manager().force_close_channel(&request)?;
+    Ok(json::to_value(closed)?)
+}
+
+pub fn json_list_force_closes(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `listforcecloses` with request {:?}", request);
+    let channels = ctx.channel_manager().list_force_closes()?;
+    Ok(json::to_value(response::ForceClosedChannels { channels })?)
+}
+
+pub fn json_set_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `setchannel` with request {:?}", request);
+    let request: request::SetChannel = json::from_value(request.clone())?;
+    let config = ctx.channel_manager().set_channel_config(&request)?;
+    Ok(json::to_value(config)?)
+}
+
+pub fn json_export_channel_backup(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `exportchannelbackup` with request {:?}", request);
+    let backup = ctx.channel_backup();
+    let (snapshot, encrypted) = backup.export()?;
+    Ok(json::to_value(response::ExportedChannelBackup {
+        path: LampoChannelBackup::path(ctx.conf()),
+        channels: snapshot.channels.len(),
+        backup: hex::encode(encrypted),
+    })?)
+}
+
+pub fn json_list_forwards(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listforwards` with request {:?}", request);
+    let request = match request {
+        json::Value::Null => request::ListForwards::default(),
+        request => json::from_value::<request::ListForwards>(request.clone())?,
+    };
+    let forwards = ctx.forwards().list(request.since, request.until)?;
+    let total_fee_msat = forwards.iter().filter_map(|forward| forward.fee_msat).sum();
+    Ok(json::to_value(response::Forwards {
+        forwards,
+        total_fee_msat,
+    })?)
+}
+
+pub fn json_intercept_scid(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `interceptscid` with request {:?}", request);
+    let short_channel_id = ctx.channel_manager().intercept_scid()?;
+    Ok(json::to_value(response::InterceptScid {
+        short_channel_id,
+    })?)
+}
+
+pub fn json_forward_intercepted(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `forwardintercepted` with request {:?}", request);
+    let request: request::ForwardIntercepted = json::from_value(request.clone())?;
+    ctx.channel_manager().forward_intercepted_htlc(&request)?;
+    Ok(json::json!({}))
+}
+
+pub fn json_fail_intercepted(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `failintercepted` with request {:?}", request);
+    let request: request::FailIntercepted = json::from_value(request.clone())?;
+    ctx.channel_manager().fail_intercepted_htlc(&request)?;
+    Ok(json::json!({}))
+}
@@ -1,8 +1,18 @@
 //! Inventory method implementation
+use std::path::PathBuf;
+
+use lampo_common::commands;
+use lampo_common::conf::LampoConfFile;
 use lampo_common::json;
-use lampo_common::model::response::{NetworkChannel, NetworkChannels};
-use lampo_jsonrpc::errors::Error;
+use lampo_common::ldk::routing::gossip::NodeId;
+use lampo_common::model::request;
+use lampo_common::model::response::{
+    GossipChannel, GossipChannels, GraphSnapshotInfo, NetworkChannel, NetworkChannels, NetworkNode,
+    NetworkNodes, PendingEvents,
+};
+use lampo_jsonrpc::errors::{Error, RpcError};
 
+use crate::rpc_error;
 use crate::LampoDaemon;
 
 pub fn get_info(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
@@ -11,6 +21,47 @@ pub fn get_info(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value,
     Ok(result)
 }
 
+/// Show the effective configuration, after that the file,
+/// the env and the cli arguments are applied.
+pub fn json_configdump(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `configdump` with request `{:?}`", request);
+    Ok(json::to_value(LampoConfFile::from(ctx.conf()))?)
+}
+
+/// List the commands that the node exposes, with their params.
+pub fn json_commands(_: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `commands` with request `{:?}`", request);
+    Ok(json::json!({
+        "commands": commands::commands(),
+    }))
+}
+
+pub fn json_stats(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `stats` with request `{:?}`", request);
+    Ok(json::to_value(ctx.stats().stats())?)
+}
+
+pub fn json_metrics(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `metrics` with request `{:?}`", request);
+    Ok(json::to_value(ctx.metrics().metrics())?)
+}
+
+/// List the ldk events that the handler is still processing, an old
+/// event here means that the handler is stuck on it.
+///
+/// Ldk gives us the next event only when the previous one is handled,
+/// so the events still queued inside ldk are not listed.
+pub fn json_dev_pending_events(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `dev-pendingevents` with request `{:?}`", request);
+    let events = PendingEvents {
+        events: ctx.metrics().pending_events(),
+    };
+    Ok(json::to_value(events)?)
+}
+
 // FIXME: check the request
 pub fn json_network_channels(ctx: &LampoDaemon, _: &json::Value) -> Result<json::Value, Error> {
     let network_graph = ctx.channel_manager().graph();
@@ -27,3 +78,117 @@ pub fn json_network_channels(ctx: &LampoDaemon, _: &json::Value) -> Result<json:
         channels: network_channels,
     })?)
 }
+
+/// List the nodes of the network graph, with the alias and
+/// the addresses of their node announcement.
+pub fn json_list_nodes(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listnodes` with request `{:?}`", request);
+    let request: request::ListNodes = json::from_value(request.clone())?;
+    let graph = ctx.channel_manager().graph();
+    let graph = graph.read_only();
+    let nodes = match request.node_id {
+        Some(node_id) => {
+            let node_id = NodeId::from_pubkey(&node_id);
+            let node = graph.node(&node_id).ok_or(rpc_error!(
+                "node `{node_id}` not found in the network graph"
+            ))?;
+            vec![NetworkNode::new(&node_id, node)]
+        }
+        None => graph
+            .nodes()
+            .unordered_iter()
+            .map(|(node_id, node)| NetworkNode::new(node_id, node))
+            .collect(),
+    };
+    Ok(json::to_value(NetworkNodes { nodes })?)
+}
+
+/// List the announced channels of the network graph with their
+/// capacity and the forwarding policy of both directions.
+pub fn json_list_gossip_channels(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `listgossipchannels` with request `{:?}`", request);
+    let request: request::ListGossipChannels = json::from_value(request.clone())?;
+    let graph = ctx.channel_manager().graph();
+    let graph = graph.read_only();
+    let mut short_channel_ids = match (request.short_channel_id, request.node_id) {
+        (Some(short_channel_id), _) => vec![short_channel_id],
+        (None, Some(node_id)) => graph
+            .node(&NodeId::from_pubkey(&node_id))
+            .ok_or(rpc_error!(
+                "node `{node_id}` not found in the network graph"
+            ))?
+            .channels
+            .clone(),
+        (None, None) => graph.channels().unordered_keys().copied().collect(),
+    };
+    short_channel_ids.sort();
+    let mut channels = Vec::new();
+    for short_channel_id in short_channel_ids {
+        let Some(channel) = graph.channel(short_channel_id) else {
+            if request.short_channel_id.is_some() {
+                return Err(rpc_error!(
+                    "channel `{short_channel_id}` not found in the network graph"
+                ));
+            }
+            continue;
+        };
+        if let Some(node_id) = request.node_id {
+            let node_id = NodeId::from_pubkey(&node_id);
+            if channel.node_one != node_id && channel.node_two != node_id {
+                continue;
+            }
+        }
+        channels.push(GossipChannel::new(short_channel_id, channel));
+    }
+    Ok(json::to_value(GossipChannels { channels })?)
+}
+
+fn snapshot_path(ctx: &LampoDaemon, request: &json::Value) -> Result<PathBuf, Error> {
+    let request: request::GraphSnapshot = json::from_value(request.clone())?;
+    Ok(request
+        .path
+        .map(PathBuf::from)
+        .unwrap_or(PathBuf::from(ctx.root_path()).join("graph_snapshot")))
+}
+
+fn snapshot_info(ctx: &LampoDaemon, path: PathBuf, size: u64) -> GraphSnapshotInfo {
+    let graph = ctx.channel_manager().graph();
+    let graph = graph.read_only();
+    GraphSnapshotInfo {
+        path: path.display().to_string(),
+        size,
+        channels: graph.channels().len(),
+        nodes: graph.nodes().len(),
+    }
+}
+
+/// Write the network graph inside a snapshot, used to bootstrap
+/// new nodes without waiting the gossip.
+pub fn json_export_graph(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `exportgraph` with request `{:?}`", request);
+    let path = snapshot_path(ctx, request)?;
+    let snapshot = ctx.channel_manager().export_graph();
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, &snapshot)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(json::to_value(snapshot_info(
+        ctx,
+        path,
+        snapshot.len() as u64,
+    ))?)
+}
+
+pub fn json_import_graph(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `importgraph` with request `{:?}`", request);
+    let path = snapshot_path(ctx, request)?;
+    let snapshot = std::fs::read(&path)?;
+    ctx.channel_manager().import_graph(&snapshot)?;
+    Ok(json::to_value(snapshot_info(
+        ctx,
+        path,
+        snapshot.len() as u64,
+    ))?)
+}
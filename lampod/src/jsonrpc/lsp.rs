@@ -0,0 +1,185 @@
+//! LSP RPC methods
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lampo_common::json;
+use lampo_common::model::request;
+use lampo_common::model::response::{LspInfo, LspInvoice, LspOrder, LspOrders};
+use lampo_jsonrpc::errors::{Error, RpcError};
+
+use crate::invoices::LampoInvoiceStore;
+use crate::ln::events::PeerEvents;
+use crate::rpc_error;
+use crate::LampoDaemon;
+
+/// Connect to the LSP if we are not already connected.
+fn connect_lsp(ctx: &LampoDaemon) -> Result<(), Error> {
+    let (node_id, addr) = ctx.peer_manager().lsp_client().lsp()?;
+    if !ctx.peer_manager().is_connected_with(node_id) {
+        ctx.rt.block_on(ctx.peer_manager().connect(node_id, addr))?;
+    }
+    Ok(())
+}
+
+pub fn json_lsp_info(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `lspinfo` with request `{:?}`", request);
+    connect_lsp(ctx)?;
+    let client = ctx.peer_manager().lsp_client();
+    let info = client.lsps2_get_info(&ctx.peer_manager())?;
+    Ok(json::to_value(LspInfo {
+        lsp_id: client.lsp()?.0.to_string(),
+        opening_fee_params_menu: info.opening_fee_params_menu,
+    })?)
+}
+
+pub fn json_lsp_invoice(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `lspinvoice` with request `{:?}`", request);
+    let request: request::LspInvoice = json::from_value(request.clone())?;
+    connect_lsp(ctx)?;
+    let peer_manager = ctx.peer_manager();
+    let client = peer_manager.lsp_client();
+    let (lsp_id, _) = client.lsp()?;
+    // the menu goes from the cheapest to the most expensive fees
+    let (params, opening_fee_msat) = client
+        .lsps2_get_info(&peer_manager)?
+        .opening_fee_params_menu
+        .into_iter()
+        .find_map(|params| {
+            let fee = params.fee_msat(request.amount_msat)?;
+            Some((params, fee))
+        })
+        .ok_or(rpc_error!(
+            "the LSP does not open a channel for a payment of `{}` msat",
+            request.amount_msat
+        ))?;
+    let channel = client.lsps2_buy(&peer_manager, params, request.amount_msat)?;
+    let cltv_expiry_delta = u16::try_from(channel.lsp_cltv_expiry_delta).map_err(|_| {
+        rpc_error!(
+            "the LSP asked an invalid cltv delta `{}`",
+            channel.lsp_cltv_expiry_delta
+        )
+    })?;
+    let invoice = ctx.offchain_manager().generate_jit_invoice(
+        request.amount_msat,
+        &request.description,
+        request.expiring_in.unwrap_or(10000),
+        lsp_id,
+        channel.short_channel_id()?,
+        cltv_expiry_delta,
+    )?;
+    let mut record = LampoInvoiceStore::record(&invoice, request.label);
+    record.lsp_fee_msat = Some(opening_fee_msat);
+    ctx.invoices().insert(record)?;
+    Ok(json::to_value(LspInvoice {
+        bolt11: invoice.to_string(),
+        payment_hash: invoice.payment_hash().to_string(),
+        amount_msat: request.amount_msat,
+        opening_fee_msat,
+        jit_channel_scid: channel.jit_channel_scid,
+    })?)
+}
+
+/// Parse an amount sent by the LSP.
+fn sat(field: &str, amount: &str) -> Result<u64, Error> {
+    amount
+        .parse::<u64>()
+        .map_err(|_| rpc_error!("the LSP sent an invalid `{field}`: `{amount}`"))
+}
+
+pub fn json_buy_inbound(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `buyinbound` with request `{:?}`", request);
+    let request: request::BuyInbound = json::from_value(request.clone())?;
+    connect_lsp(ctx)?;
+    let peer_manager = ctx.peer_manager();
+    let client = peer_manager.lsp_client();
+    let (lsp_id, _) = client.lsp()?;
+    let options = client.lsps1_get_info(&peer_manager)?.options;
+    let min_balance = sat(
+        "min_initial_lsp_balance_sat",
+        &options.min_initial_lsp_balance_sat,
+    )?;
+    let max_balance = sat(
+        "max_initial_lsp_balance_sat",
+        &options.max_initial_lsp_balance_sat,
+    )?;
+    if request.lsp_balance_sat < min_balance || request.lsp_balance_sat > max_balance {
+        return Err(rpc_error!(
+            "the LSP sells channels with an inbound liquidity between `{min_balance}` and `{max_balance}` sat"
+        ));
+    }
+    let channel_expiry_blocks = request
+        .channel_expiry_blocks
+        .unwrap_or(options.max_channel_expiry_blocks);
+    if channel_expiry_blocks > options.max_channel_expiry_blocks {
+        return Err(rpc_error!(
+            "the LSP keeps the channels open for at most `{}` blocks",
+            options.max_channel_expiry_blocks
+        ));
+    }
+    let order = client.lsps1_create_order(
+        &peer_manager,
+        request.lsp_balance_sat,
+        options.min_required_channel_confirmations,
+        options.min_funding_confirms_within_blocks,
+        channel_expiry_blocks,
+        request.announce_channel.unwrap_or(false),
+    )?;
+    let Some(payment) = &order.payment.bolt11 else {
+        return Err(rpc_error!(
+            "the LSP does not accept a lightning payment for the order `{}`",
+            order.order_id
+        ));
+    };
+    let fee_sat = sat("fee_total_sat", &payment.fee_total_sat)?;
+    if let Some(max_fee_sat) = request.max_fee_sat {
+        if fee_sat > max_fee_sat {
+            return Err(rpc_error!(
+                "the LSP asks `{fee_sat}` sat for the order `{}`, more than `{max_fee_sat}` sat",
+                order.order_id
+            ));
+        }
+    }
+    let invoice = ctx.offchain_manager().decode_invoice(&payment.invoice)?;
+    let order_total_sat = sat("order_total_sat", &payment.order_total_sat)?;
+    if invoice.amount_milli_satoshis() != Some(order_total_sat * 1000) {
+        return Err(rpc_error!(
+            "the invoice of the order `{}` does not ask `{order_total_sat}` sat",
+            order.order_id
+        ));
+    }
+    let mut lsp_order = LspOrder {
+        order_id: order.order_id.clone(),
+        lsp_id: lsp_id.to_string(),
+        lsp_balance_sat: request.lsp_balance_sat,
+        fee_sat,
+        order_state: order.order_state,
+        payment_state: None,
+        payment_hash: Some(invoice.payment_hash().to_string()),
+        funding_outpoint: None,
+        channel_expires_at: None,
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or_default(),
+    };
+    lsp_order.update(&order);
+    // the LSP holds the payment until the channel is open
+    ctx.offchain_manager().pay_invoice(&payment.invoice, None)?;
+    ctx.lsp_orders().store(&lsp_order)?;
+    Ok(json::to_value(lsp_order)?)
+}
+
+pub fn json_list_lsp_orders(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `listlsporders` with request `{:?}`", request);
+    if let Ok((lsp_id, _)) = ctx.peer_manager().lsp_client().lsp() {
+        if ctx.peer_manager().is_connected_with(lsp_id) {
+            if let Err(err) = ctx.lsp_orders().refresh(&ctx.peer_manager()) {
+                log::warn!(target: "lsp", "impossible refresh the LSP orders: {err}");
+            }
+        }
+    }
+    let orders = ctx.lsp_orders().list()?;
+    Ok(json::to_value(LspOrders { orders })?)
+}
@@ -2,6 +2,9 @@
 use std::str::FromStr;
 use std::time::Duration;
 
+use lampo_common::bitcoin::hashes::sha256::Hash as Sha256;
+use lampo_common::bitcoin::hashes::Hash;
+use lampo_common::chan;
 use lampo_common::conf::Network;
 use lampo_common::event::ln::LightningEvent;
 use lampo_common::event::Event;
@@ -9,32 +12,306 @@ use lampo_common::handler::Handler;
 use lampo_common::ldk;
 use lampo_common::ldk::offers::offer;
 use lampo_common::ldk::offers::offer::Amount;
+use lampo_common::ldk::util::ser::{Readable, Writeable};
+use lampo_common::model::request::GenerateHoldInvoice;
 use lampo_common::model::request::GenerateInvoice;
+use lampo_common::model::request::GenerateInvoices;
 use lampo_common::model::request::GenerateOffer;
+use lampo_common::model::request::GeneratePhantomInvoice;
+use lampo_common::model::request::GetRoute;
 use lampo_common::model::request::KeySend;
-use lampo_common::model::request::Pay;
+use lampo_common::model::request::LookupPayment;
+use lampo_common::model::request::Probe;
+use lampo_common::model::request::SettleInvoice;
+use lampo_common::model::request::Traceroute;
+use lampo_common::model::request::{CancelInvoice, ListInvoices, LookupInvoice};
+use lampo_common::model::request::{ClaimRefund, GenerateRefund};
+use lampo_common::model::request::{Pay, PayOffer};
 use lampo_common::model::response;
 use lampo_common::model::response::PayResult;
-use lampo_common::model::response::{Invoice, InvoiceInfo};
+use lampo_common::model::response::{Invoice, InvoiceInfo, InvoiceStatus, Invoices, PhantomHints};
+use lampo_common::model::response::{PaymentDirection, PaymentHop, PaymentPart};
+use lampo_common::model::response::{PaymentState, TracerouteHop};
 use lampo_common::{json, model::request::DecodeInvoice};
 use lampo_jsonrpc::errors::{Error, RpcError};
 
+use crate::invoices::LampoInvoiceStore;
+use crate::ln::OffchainManager;
+use crate::persistence::JsonStore;
+use crate::qr;
+use crate::rates::FiatAmount;
 use crate::LampoDaemon;
 
+/// Source used for the rate limit of the invoices created by the JSON RPC.
+const RPC_SOURCE: &str = "jsonrpc";
+/// Namespace of the offers created by the node.
+const OFFERS_NAMESPACE: &str = "offers";
+
 pub fn json_invoice(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
     log::info!("call for `invoice` with request `{:?}`", request);
     let request: GenerateInvoice = json::from_value(request.clone())?;
-    let invoice = ctx.offchain_manager().generate_invoice(
+    ctx.invoice_limiter().check(RPC_SOURCE, 1)?;
+    let (amount_msat, fiat) = match request.fiat_amount {
+        Some(_) if request.amount_msat.is_some() => {
+            return Err(crate::rpc_error!(
+                "`amount_msat` and `fiat_amount` can not be used together"
+            ));
+        }
+        Some(fiat_amount) => {
+            let provider = ctx.exchange_rate().ok_or(crate::rpc_error!(
+                "fiat amounts are disabled, set the `exchange-rate-provider` option"
+            ))?;
+            let rate = FiatAmount::from_str(&fiat_amount)?.convert(provider.as_ref())?;
+            (Some(rate.amount_msat), Some(rate))
+        }
+        None => (request.amount_msat, None),
+    };
+    let expiring_in = request.expiring_in.unwrap_or(10000);
+    let (invoice, fallback_address) = match request.fallback {
+        Some(true) => {
+            let address = ctx.wallet_manager().get_onchain_address()?.address;
+            let invoice = ctx.offchain_manager().generate_invoice_with_fallback(
+                amount_msat,
+                &request.description,
+                expiring_in,
+                &address,
+            )?;
+            (invoice, Some(address))
+        }
+        _ => {
+            let invoice = ctx.offchain_manager().generate_invoice(
+                amount_msat,
+                &request.description,
+                expiring_in,
+            )?;
+            (invoice, None)
+        }
+    };
+    let mut record = LampoInvoiceStore::record(&invoice, None);
+    record.fallback_address = fallback_address.clone();
+    record.fiat = fiat.clone();
+    ctx.invoices().insert(record)?;
+    let qr = match request.qr {
+        Some(true) => Some(qr::bolt11(&invoice.to_string())?),
+        _ => None,
+    };
+    let invoice = Invoice {
+        bolt11: invoice.to_string(),
+        fiat,
+        qr,
+        fallback_address,
+    };
+    Ok(json::to_value(&invoice)?)
+}
+
+pub fn json_hold_invoice(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `holdinvoice` with request `{:?}`", request);
+    let request: GenerateHoldInvoice = json::from_value(request.clone())?;
+    ctx.invoice_limiter().check(RPC_SOURCE, 1)?;
+    let invoice = ctx.offchain_manager().generate_hold_invoice(
+        &request.payment_hash,
+        request.amount_msat,
+        &request.description,
+        request.expiring_in.unwrap_or(10000),
+    )?;
+    let mut record = LampoInvoiceStore::record(&invoice, request.label);
+    record.hold = true;
+    ctx.invoices().insert(record)?;
+    let invoice = Invoice {
+        bolt11: invoice.to_string(),
+        fiat: None,
+        qr: None,
+        fallback_address: None,
+    };
+    Ok(json::to_value(&invoice)?)
+}
+
+pub fn json_settle_invoice(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `settleinvoice` with request `{:?}`", request);
+    let request: SettleInvoice = json::from_value(request.clone())?;
+    let preimage = hex::decode(&request.preimage)
+        .map_err(|err| crate::rpc_error!("invalid preimage: {err}"))?;
+    let payment_hash = Sha256::hash(&preimage).to_string();
+    let invoice = ctx
+        .invoices()
+        .get(&payment_hash)
+        .ok_or(crate::rpc_error!("invoice not found"))?;
+    if invoice.status != InvoiceStatus::Accepted {
+        return Err(crate::rpc_error!(
+            "invoice `{payment_hash}` is `{:?}`, there is no payment to settle",
+            invoice.status
+        ));
+    }
+    // the invoice is marked as paid when ldk claims the payment.
+    ctx.offchain_manager()
+        .settle_hold_invoice(&request.preimage)?;
+    Ok(json::to_value(&invoice)?)
+}
+
+pub fn json_batch_invoice(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `batchinvoice` with request `{:?}`", request);
+    let request: GenerateInvoices = json::from_value(request.clone())?;
+    if request.invoices.is_empty() {
+        return Err(crate::rpc_error!("the list of invoices is empty"));
+    }
+    ctx.invoice_limiter()
+        .check(RPC_SOURCE, request.invoices.len() as u64)?;
+    let mut labels = std::collections::HashSet::new();
+    for invoice in request.invoices.iter() {
+        if !labels.insert(invoice.label.as_str()) {
+            return Err(crate::rpc_error!(
+                "label `{}` is used more than once",
+                invoice.label
+            ));
+        }
+        if ctx.invoices().get_by_label(&invoice.label).is_some() {
+            return Err(crate::rpc_error!(
+                "an invoice with label `{}` already exists",
+                invoice.label
+            ));
+        }
+    }
+
+    let mut records = Vec::with_capacity(request.invoices.len());
+    for invoice in request.invoices.iter() {
+        let description = invoice.description.as_ref().unwrap_or(&invoice.label);
+        let bolt11 = ctx.offchain_manager().generate_invoice(
+            invoice.amount_msat,
+            description,
+            invoice.expiring_in.unwrap_or(10000),
+        )?;
+        records.push(LampoInvoiceStore::record(
+            &bolt11,
+            Some(invoice.label.clone()),
+        ));
+    }
+    // all the invoices are stored with a single write
+    ctx.invoices().insert_batch(records.clone())?;
+    Ok(json::to_value(&Invoices { invoices: records })?)
+}
+
+pub fn json_phantom_hints(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `phantomhints` with request `{:?}`", request);
+    let hints = ctx.offchain_manager().phantom_route_hints()?;
+    let hints = PhantomHints {
+        node_id: hints.real_node_pubkey.to_string(),
+        hints: hex::encode(hints.encode()),
+    };
+    Ok(json::to_value(&hints)?)
+}
+
+pub fn json_phantom_invoice(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `phantominvoice` with request `{:?}`", request);
+    let request: GeneratePhantomInvoice = json::from_value(request.clone())?;
+    ctx.invoice_limiter().check(RPC_SOURCE, 1)?;
+    let mut hints = Vec::with_capacity(request.hints.len());
+    for hint in request.hints.iter() {
+        let bytes = hex::decode(hint)
+            .map_err(|err| crate::rpc_error!("invalid phantom hints `{hint}`: {err}"))?;
+        let hint = ldk::ln::channelmanager::PhantomRouteHints::read(&mut bytes.as_slice())
+            .map_err(|err| crate::rpc_error!("invalid phantom hints `{hint}`: {err}"))?;
+        hints.push(hint);
+    }
+    let invoice = ctx.offchain_manager().generate_phantom_invoice(
         request.amount_msat,
         &request.description,
         request.expiring_in.unwrap_or(10000),
+        hints,
     )?;
+    ctx.invoices()
+        .insert(LampoInvoiceStore::record(&invoice, None))?;
     let invoice = Invoice {
         bolt11: invoice.to_string(),
+        fiat: None,
+        qr: None,
+        fallback_address: None,
     };
     Ok(json::to_value(&invoice)?)
 }
 
+/// Mark an unpaid invoice as cancelled, so a late payment
+/// is rejected instead of claimed.
+pub fn json_cancel_invoice(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `cancelinvoice` with request `{:?}`", request);
+    let request: CancelInvoice = json::from_value(request.clone())?;
+    let invoice = match (request.payment_hash, request.label) {
+        (Some(payment_hash), None) => ctx.invoices().get(&payment_hash),
+        (None, Some(label)) => ctx.invoices().get_by_label(&label),
+        _ => {
+            return Err(crate::rpc_error!(
+                "one of `payment_hash` or `label` must be specified"
+            ))
+        }
+    }
+    .ok_or(crate::rpc_error!("invoice not found"))?;
+    // the held payment is failed back before storing the status,
+    // so the invoice is not cancelled while the payment is still held.
+    if invoice.status == InvoiceStatus::Accepted {
+        ctx.offchain_manager()
+            .cancel_hold_invoice(&invoice.payment_hash)?;
+    }
+    let invoice = ctx
+        .invoices()
+        .set_status(&invoice.payment_hash, InvoiceStatus::Cancelled)?;
+    ctx.handler()
+        .emit(Event::Lightning(LightningEvent::InvoiceEvent {
+            payment_hash: invoice.payment_hash.clone(),
+            label: invoice.label.clone(),
+            status: invoice.status.clone(),
+        }));
+    Ok(json::to_value(&invoice)?)
+}
+
+pub fn json_list_invoices(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listinvoices` with request `{:?}`", request);
+    let request = match request {
+        json::Value::Null => ListInvoices::default(),
+        request => json::from_value::<ListInvoices>(request.clone())?,
+    };
+    let invoices = ctx
+        .invoices()
+        .list_by_status(request.status.as_ref())
+        .into_iter()
+        .filter(|invoice| match &request.label {
+            Some(prefix) => invoice
+                .label
+                .as_ref()
+                .map_or(false, |label| label.starts_with(prefix.as_str())),
+            None => true,
+        })
+        .skip(request.offset.unwrap_or_default())
+        .take(request.limit.unwrap_or(usize::MAX))
+        .collect();
+    Ok(json::to_value(&Invoices { invoices })?)
+}
+
+/// Return the stored invoice with the payment secret and the
+/// settlement details, so the orders can be reconciled.
+///
+/// The `payment_hash` is also looked up as label, in this way
+/// `lookupinvoice <hash|label>` works from the command line.
+pub fn json_lookup_invoice(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `lookupinvoice` with request `{:?}`", request);
+    let request: LookupInvoice = json::from_value(request.clone())?;
+    let invoices = ctx.invoices();
+    let invoice = match (request.payment_hash, request.label) {
+        (Some(payment_hash), None) => invoices
+            .get(&payment_hash)
+            .or_else(|| invoices.get_by_label(&payment_hash)),
+        (None, Some(label)) => invoices.get_by_label(&label),
+        _ => {
+            return Err(crate::rpc_error!(
+                "one of `payment_hash` or `label` must be specified"
+            ))
+        }
+    }
+    .ok_or(crate::rpc_error!("invoice not found"))?;
+    Ok(json::to_value(&invoice)?)
+}
+
 pub fn json_offer(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
     log::info!("call for `offer` with request `{:?}`", request);
     let request: GenerateOffer = json::from_value(request.clone())?;
@@ -51,14 +328,46 @@ pub fn json_offer(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Valu
         offer_builder = offer_builder.amount_msats(amount_msat);
     }
 
+    if let Some(issuer) = request.issuer {
+        offer_builder = offer_builder.issuer(issuer);
+    }
+
     let offer: response::Offer = offer_builder
         .build()
         // FIXME: implement display error on top of the bolt12 error
         .map_err(|err| crate::rpc_error!("{:?}", err))?
         .into();
+    let key = Sha256::hash(offer.bolt12.as_bytes()).to_string();
+    ctx.persister().write_json(OFFERS_NAMESPACE, &key, &offer)?;
     Ok(json::to_value(&offer)?)
 }
 
+pub fn json_list_offers(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listoffers` with request `{:?}`", request);
+    let offers = ctx
+        .persister()
+        .list_json::<response::Offer>(OFFERS_NAMESPACE)?;
+    Ok(json::to_value(response::Offers { offers })?)
+}
+
+pub fn json_refund(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `refund` with request `{:?}`", request);
+    let request: GenerateRefund = json::from_value(request.clone())?;
+    let expiry = Duration::from_secs(request.expiry.unwrap_or(3600));
+    let refund: response::Refund = ctx
+        .offchain_manager()
+        .create_refund(request.amount_msat, request.description, expiry)?
+        .into();
+    Ok(json::to_value(&refund)?)
+}
+
+pub fn json_claim_refund(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `claimrefund` with request `{:?}`", request);
+    let request: ClaimRefund = json::from_value(request.clone())?;
+    let refund: response::Refund = ctx.offchain_manager().claim_refund(&request.refund)?.into();
+    Ok(json::to_value(&refund)?)
+}
+
 pub fn json_decode_invoice(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
     log::info!("call for `invoice` with request `{:?}`", request);
     let request: DecodeInvoice = json::from_value(request.clone())?;
@@ -119,47 +428,428 @@ pub fn json_decode_invoice(ctx: &LampoDaemon, request: &json::Value) -> Result<j
 pub fn json_pay(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
     log::info!("call for `pay` with request `{:?}`", request);
     let request: Pay = json::from_value(request.clone())?;
+    let is_offer = offer::Offer::from_str(&request.invoice_str).is_ok();
+    if is_offer && !request.first_hops.is_empty() {
+        return Err(crate::rpc_error!(
+            "first hop constraints are not supported for offers"
+        ));
+    }
+    if is_offer && request.hints.route_hints.is_some() {
+        return Err(crate::rpc_error!(
+            "route hints are not supported for offers, the paths are provided by the offer"
+        ));
+    }
+    if is_offer && request.route.is_some() {
+        return Err(crate::rpc_error!(
+            "a manual route is not supported for offers, the paths are provided by the offer"
+        ));
+    }
+    if request.route.is_some() && !request.first_hops.is_empty() {
+        return Err(crate::rpc_error!(
+            "first hop constraints can not be used with a manual route"
+        ));
+    }
+    if request.simulate.unwrap_or(false) {
+        if is_offer {
+            return Err(crate::rpc_error!(
+                "simulation is not supported for offers, the invoice is fetched from the peer"
+            ));
+        }
+        let simulation = ctx.offchain_manager().simulate_invoice_payment(
+            &request.invoice_str,
+            request.amount,
+            &request.first_hops,
+            &request.hints,
+            request.route.as_deref(),
+        )?;
+        return Ok(json::to_value(simulation)?);
+    }
     let events = ctx.handler().events();
-    if let Ok(_) = offer::Offer::from_str(&request.invoice_str) {
+    // the payment hash of an offer is known when the invoice is received
+    let expected_hash = if is_offer {
         ctx.offchain_manager()
             .pay_offer(&request.invoice_str, request.amount)?;
+        None
     } else {
-        ctx.offchain_manager()
-            .pay_invoice(&request.invoice_str, request.amount)?;
-    }
-    // FIXME: this will loop when the Payment event is not generated
+        let invoice = ctx
+            .offchain_manager()
+            .decode_invoice(&request.invoice_str)?;
+        if let Some(route) = &request.route {
+            ctx.offchain_manager().pay_invoice_along(
+                &request.invoice_str,
+                request.amount,
+                route,
+                &request.hints,
+            )?;
+        } else if request.first_hops.is_empty() {
+            ctx.offchain_manager().pay_invoice_with_hints(
+                &request.invoice_str,
+                request.amount,
+                &request.hints,
+            )?;
+        } else {
+            ctx.offchain_manager().pay_invoice_through(
+                &request.invoice_str,
+                request.amount,
+                &request.first_hops,
+                &request.hints,
+            )?;
+        }
+        Some(invoice.payment_hash().to_string())
+    };
+    let result = wait_payment(ctx, &events, expected_hash, Duration::from_secs(30))?;
+    Ok(json::to_value(result)?)
+}
+
+pub fn json_pay_offer(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `payoffer` with request `{:?}`", request);
+    let request: PayOffer = json::from_value(request.clone())?;
+    let events = ctx.handler().events();
+    // ldk sends the invoice request over onion messages, waits
+    // the invoice and pays it retrying the failed parts.
+    ctx.offchain_manager().pay_offer_with_note(
+        &request.offer,
+        request.amount_msat,
+        request.payer_note,
+    )?;
+    let timeout = Duration::from_secs(request.timeout.unwrap_or(60));
+    let result = wait_payment(ctx, &events, None, timeout)?;
+    Ok(json::to_value(result)?)
+}
+
+/// Wait the outcome of the payment with `expected_hash`, when the
+/// hash is not known (e.g. offers) the first payment is reported.
+fn wait_payment(
+    ctx: &LampoDaemon,
+    events: &chan::Receiver<Event>,
+    expected_hash: Option<String>,
+    timeout: Duration,
+) -> Result<PayResult, Error> {
+    let is_ours = |payment_hash: Option<&String>| match (&expected_hash, payment_hash) {
+        (Some(expected), Some(payment_hash)) => expected == payment_hash,
+        _ => true,
+    };
+    let mut parts = Vec::new();
+    let mut settled: Option<(Option<String>, Vec<PaymentHop>)> = None;
     loop {
-        let event = events
-            .recv_timeout(Duration::from_secs(30))
+        let event = match events.recv_timeout(timeout) {
+            Ok(event) => event,
+            // the payment is settled, but some parts are not reported
+            Err(_) if settled.is_some() => break,
             // FIXME: this should be avoided, the `?` should be used here
-            .map_err(|err| {
-                Error::Rpc(RpcError {
+            Err(err) => {
+                return Err(Error::Rpc(RpcError {
                     code: -1,
                     message: format!("{err}"),
                     data: None,
-                })
-            })?;
-
-        if let Event::Lightning(LightningEvent::PaymentEvent {
-            payment_hash,
-            path,
-            state,
-        }) = event
-        {
-            return Ok(json::to_value(PayResult {
+                }))
+            }
+        };
+        match event {
+            Event::Lightning(LightningEvent::PaymentPartEvent {
                 state,
+                payment_hash,
                 path,
+                failure,
+            }) if is_ours(Some(&payment_hash)) => {
+                parts.push(PaymentPart::new(state, path, failure))
+            }
+            Event::Lightning(LightningEvent::PaymentEvent {
+                payment_hash,
+                path,
+                state: PaymentState::Success,
+                ..
+            }) if is_ours(payment_hash.as_ref()) => {
+                if settled.is_none() {
+                    settled = Some((payment_hash, path));
+                }
+            }
+            Event::Lightning(LightningEvent::PaymentEvent {
                 payment_hash,
-            })?);
+                path,
+                state,
+                reason,
+            }) if is_ours(payment_hash.as_ref()) => {
+                let failure = parts.iter().rev().find_map(|part| part.failure.clone());
+                return Ok(PayResult {
+                    state,
+                    path,
+                    payment_hash,
+                    payment_preimage: None,
+                    fee_msat: None,
+                    parts,
+                    failure_reason: reason,
+                    failure,
+                });
+            }
+            _ => continue,
+        }
+        // wait that all the parts reach the destination
+        match &settled {
+            Some((Some(payment_hash), _)) => {
+                let delivered = parts
+                    .iter()
+                    .filter(|part| matches!(part.state, PaymentState::Success))
+                    .map(|part| part.amount_msat)
+                    .sum::<u64>();
+                let amount_msat = ctx
+                    .payments()
+                    .get(payment_hash, &PaymentDirection::Sent)?
+                    .and_then(|record| record.amount_msat);
+                if amount_msat.map_or(true, |amount_msat| delivered >= amount_msat) {
+                    break;
+                }
+            }
+            Some((None, _)) => break,
+            None => {}
         }
     }
+    let (payment_hash, path) = settled.expect("the loop ends only when the payment is settled");
+    // the payment is stored with the preimage before that
+    // the path is reported as successful.
+    let record = match &payment_hash {
+        Some(payment_hash) => ctx.payments().get(payment_hash, &PaymentDirection::Sent)?,
+        None => None,
+    };
+    Ok(PayResult {
+        state: PaymentState::Success,
+        path,
+        payment_hash,
+        payment_preimage: record.as_ref().and_then(|record| record.preimage.clone()),
+        fee_msat: record.and_then(|record| record.fee_msat),
+        parts,
+        failure_reason: None,
+        failure: None,
+    })
 }
 
 pub fn json_keysend(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
     log::debug!("call for `keysend` with request `{:?}`", request);
     let request: KeySend = json::from_value(request.clone())?;
-    ctx.offchain_manager()
-        .keysend(request.destination, request.amount_msat)?;
+    if request.first_hops.is_empty() {
+        ctx.offchain_manager()
+            .keysend(request.destination, request.amount_msat)?;
+    } else {
+        ctx.offchain_manager().keysend_through(
+            request.destination,
+            request.amount_msat,
+            &request.first_hops,
+        )?;
+    }
     // FIXME: return a better response
     Ok(json::json!({}))
 }
+
+/// Return the details of a payment sent or received, the
+/// preimage is included when the payment is settled.
+pub fn json_lookup_payment(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `lookuppayment` with request `{:?}`", request);
+    let request: LookupPayment = json::from_value(request.clone())?;
+    let payments = ctx.payments();
+    let payment = match &request.direction {
+        Some(direction) => payments.get(&request.payment_hash, direction)?,
+        // a circular payment is also received, the outgoing
+        // one has the fee that we paid.
+        None => match payments.get(&request.payment_hash, &PaymentDirection::Sent)? {
+            Some(payment) => Some(payment),
+            None => payments.get(&request.payment_hash, &PaymentDirection::Received)?,
+        },
+    };
+    let payment = payment.ok_or(crate::rpc_error!(
+        "payment with hash `{}` not found",
+        request.payment_hash
+    ))?;
+    Ok(json::to_value(&payment)?)
+}
+
+pub fn json_traceroute(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `traceroute` with request `{:?}`", request);
+    let request: Traceroute = json::from_value(request.clone())?;
+    let timeout = Duration::from_secs(request.timeout.unwrap_or(30));
+    let offchain_manager = ctx.offchain_manager();
+    let route = offchain_manager.find_route(request.destination, request.amount_msat)?;
+    let path = route.paths.first().ok_or(crate::rpc_error!(
+        "no path found to `{}`",
+        request.destination
+    ))?;
+
+    let first_hop = path.hops.first().ok_or(crate::rpc_error!(
+        "no path found to `{}`",
+        request.destination
+    ))?;
+
+    let events = ctx.handler().events();
+    let mut hops = Vec::new();
+    let mut failed_short_channel_id = None;
+    // LDK does not probe our own channel, so the first hop
+    // is checked with the local details of the channel.
+    let mut probes = OffchainManager::traceroute_paths(path);
+    let state = if offchain_manager.first_hop_reaches(path) {
+        PaymentState::Success
+    } else {
+        failed_short_channel_id = Some(first_hop.short_channel_id);
+        probes.clear();
+        PaymentState::Failure
+    };
+    hops.push(TracerouteHop {
+        node_id: first_hop.pubkey.to_string(),
+        short_channel_id: first_hop.short_channel_id,
+        amount_msat: path.hops.iter().map(|hop| hop.fee_msat).sum(),
+        cltv_expiry_delta: path.hops.iter().map(|hop| hop.cltv_expiry_delta).sum(),
+        state,
+    });
+    for probe in probes {
+        let last_hop = probe
+            .hops
+            .last()
+            .expect("each prefix contains two hops")
+            .clone();
+        let payment_hash = offchain_manager.send_probe(probe)?.to_string();
+        let (state, short_channel_id) = loop {
+            let Ok(event) = events.recv_timeout(timeout) else {
+                log::warn!("probe `{payment_hash}` timed out");
+                break (PaymentState::Pending, None);
+            };
+            if let Event::Lightning(LightningEvent::ProbeEvent {
+                state,
+                payment_hash: hash,
+                short_channel_id,
+                ..
+            }) = event
+            {
+                if hash == payment_hash {
+                    break (state, short_channel_id);
+                }
+            }
+        };
+        let reached = matches!(state, PaymentState::Success);
+        hops.push(TracerouteHop {
+            node_id: last_hop.pubkey.to_string(),
+            short_channel_id: last_hop.short_channel_id,
+            amount_msat: last_hop.fee_msat,
+            cltv_expiry_delta: last_hop.cltv_expiry_delta,
+            state,
+        });
+        if !reached {
+            failed_short_channel_id = short_channel_id;
+            break;
+        }
+    }
+
+    let reached = hops.len() == path.hops.len()
+        && hops
+            .iter()
+            .all(|hop| matches!(hop.state, PaymentState::Success));
+    Ok(json::to_value(response::Traceroute {
+        destination: request.destination.to_string(),
+        amount_msat: request.amount_msat,
+        reached,
+        hops,
+        failed_short_channel_id,
+    })?)
+}
+
+/// Show the route that the router would use to pay `destination`,
+/// nothing is sent, so it is useful to understand why a payment fails.
+pub fn json_get_route(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `getroute` with request `{:?}`", request);
+    let request: GetRoute = json::from_value(request.clone())?;
+    let route = ctx.offchain_manager().find_route_through(
+        request.destination,
+        request.amount_msat,
+        request.first_hop,
+    )?;
+    let paths = route
+        .paths
+        .iter()
+        .map(|path| response::RoutePath {
+            amount_msat: path.final_value_msat(),
+            fee_msat: path.fee_msat(),
+            cltv_expiry_delta: path.hops.iter().map(|hop| hop.cltv_expiry_delta).sum(),
+            hops: path.hops.iter().cloned().map(PaymentHop::from).collect(),
+        })
+        .collect();
+    Ok(json::to_value(response::GetRoute {
+        destination: request.destination.to_string(),
+        amount_msat: request.amount_msat,
+        fee_msat: route.get_total_fees(),
+        paths,
+    })?)
+}
+
+/// Send a probe along each path of the route to `destination` and
+/// wait the results, the scorer learns from them as from a payment.
+pub fn json_probe(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `probe` with request `{:?}`", request);
+    let request: Probe = json::from_value(request.clone())?;
+    let timeout = Duration::from_secs(request.timeout.unwrap_or(60));
+    let offchain_manager = ctx.offchain_manager();
+    let route = offchain_manager.find_route(request.destination, request.amount_msat)?;
+    let events = ctx.handler().events();
+    let mut paths = Vec::new();
+    for path in route.paths.iter() {
+        let hops = path.hops.iter().cloned().map(PaymentHop::from).collect();
+        // LDK does not probe a path with less than two hops, a
+        // direct channel is checked with the local details.
+        if path.hops.len() < 2 {
+            let (state, failed_short_channel_id) = if offchain_manager.first_hop_reaches(path) {
+                (PaymentState::Success, None)
+            } else {
+                let first_hop = path.hops.first().map(|hop| hop.short_channel_id);
+                (PaymentState::Failure, first_hop)
+            };
+            paths.push(response::ProbePath {
+                payment_hash: None,
+                state,
+                hops,
+                failed_short_channel_id,
+            });
+            continue;
+        }
+        let payment_hash = offchain_manager.send_probe(path.clone())?.to_string();
+        paths.push(response::ProbePath {
+            payment_hash: Some(payment_hash),
+            state: PaymentState::Pending,
+            hops,
+            failed_short_channel_id: None,
+        });
+    }
+
+    let deadline = std::time::Instant::now() + timeout;
+    while paths
+        .iter()
+        .any(|path| matches!(path.state, PaymentState::Pending))
+    {
+        let Ok(event) = events.recv_deadline(deadline) else {
+            log::warn!("probes to `{}` timed out", request.destination);
+            break;
+        };
+        let Event::Lightning(LightningEvent::ProbeEvent {
+            state,
+            payment_hash,
+            short_channel_id,
+            ..
+        }) = event
+        else {
+            continue;
+        };
+        if let Some(path) = paths
+            .iter_mut()
+            .find(|path| path.payment_hash.as_ref() == Some(&payment_hash))
+        {
+            path.state = state;
+            path.failed_short_channel_id = short_channel_id;
+        }
+    }
+
+    let reached = paths
+        .iter()
+        .all(|path| matches!(path.state, PaymentState::Success));
+    Ok(json::to_value(response::Probe {
+        destination: request.destination.to_string(),
+        amount_msat: request.amount_msat,
+        fee_msat: route.get_total_fees(),
+        reached,
+        paths,
+    })?)
+}
@@ -1,21 +1,80 @@
 //! On Chain RPC methods
+use std::str::FromStr;
+
+use lampo_common::bitcoin::{OutPoint, Txid};
+use lampo_common::error;
 use lampo_common::json;
-use lampo_jsonrpc::errors::Error;
+use lampo_common::model::request;
+use lampo_common::model::response::{ChannelFunds, Consolidation, Label, Transactions};
+use lampo_common::model::response::{Utxos, Withdrawal};
+use lampo_jsonrpc::errors::{Error, RpcError};
 
+use crate::chain;
+use crate::qr;
 use crate::LampoDaemon;
 
 pub fn json_new_addr(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
     log::info!("call for `new_addr` with request {:?}", request);
-    let resp = ctx.wallet_manager().get_onchain_address()?;
+    let request: request::NewAddress = json::from_value(request.clone())?;
+    let address_type = request.address_type()?;
+    let mut resp = ctx.wallet_manager().get_onchain_address_of(address_type)?;
+    if request.qr.unwrap_or(false) {
+        resp.qr = Some(qr::address(&resp.address)?);
+    }
+    if request.label.is_some() {
+        ctx.labels().set(&resp.address, request.label.clone())?;
+        resp.label = request.label;
+    }
     Ok(json::to_value(resp)?)
 }
 
+/// The utxos of the wallet together with our side of the channels.
 pub fn json_funds(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
     log::info!("call for `funds` with request `{:?}`", request);
-    let txs = ctx.wallet_manager().list_transactions()?;
-    Ok(json::json!({
-        "transactions": txs,
-    }))
+    let labels = ctx.labels();
+    // the utxos of the funding transactions that are not yet broadcast
+    let reserved = ctx.onchain_manager().funder().reserved();
+    let transactions = ctx
+        .wallet_manager()
+        .list_transactions()?
+        .into_iter()
+        .map(|mut utxo| {
+            utxo.reserved |= reserved.iter().any(|outpoint| {
+                outpoint.txid.to_string() == utxo.txid && outpoint.vout == utxo.vout
+            });
+            utxo.label = labels
+                .get(&utxo.txid)
+                .or_else(|| utxo.address.as_ref().and_then(|addr| labels.get(addr)));
+            utxo
+        })
+        .collect::<Vec<_>>();
+    let channels = ctx
+        .channel_manager()
+        .list_channels()
+        .channels
+        .into_iter()
+        .map(|channel| ChannelFunds {
+            channel_id: channel.channel_id,
+            short_channel_id: channel.short_channel_id,
+            peer_id: channel.peer_id,
+            state: channel.state,
+            amount_sat: channel.amount,
+            our_amount_msat: channel.available_balance_for_send_msat,
+            our_reserve_sat: channel.our_reserve_sat,
+        })
+        .collect::<Vec<_>>();
+    let onchain_sat = transactions
+        .iter()
+        .filter(|utxo| !utxo.reserved && utxo.confirmed > 0)
+        .map(|utxo| utxo.amount_msat / 1000)
+        .sum();
+    let channels_msat = channels.iter().map(|channel| channel.our_amount_msat).sum();
+    Ok(json::to_value(Utxos {
+        transactions,
+        channels,
+        onchain_sat,
+        channels_msat,
+    })?)
 }
 
 pub fn json_estimate_fees(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
@@ -23,3 +82,149 @@ pub fn json_estimate_fees(ctx: &LampoDaemon, request: &json::Value) -> Result<js
     let response = ctx.onchain_manager().estimated_fees();
     Ok(json::to_value(response)?)
 }
+
+pub fn json_list_transactions(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `listtransactions` with request `{:?}`", request);
+    let wallet = ctx.wallet_manager().wallet_transactions()?;
+    let (_, tip) = ctx.onchain_manager().backend.get_best_block()?;
+    let labels = ctx.labels();
+    let transactions = ctx
+        .transactions()
+        .list(wallet, tip.unwrap_or_default())
+        .into_iter()
+        .map(|mut tx| {
+            tx.label = labels.get(&tx.txid);
+            tx
+        })
+        .collect();
+    Ok(json::to_value(Transactions { transactions })?)
+}
+
+pub fn json_set_label(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `setlabel` with request `{:?}`", request);
+    let request: request::SetLabel = json::from_value(request.clone())?;
+    ctx.labels().set(&request.id, request.label)?;
+    let label = ctx.labels().get(request.id.trim());
+    Ok(json::to_value(Label {
+        id: request.id.trim().to_owned(),
+        label,
+    })?)
+}
+
+pub fn json_consolidate(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `consolidate` with request `{:?}`", request);
+    let request: request::Consolidate = json::from_value(request.clone())?;
+    let max_amount_msat = request.max_amount_sat.map(|amount| amount * 1000);
+    let inputs = ctx
+        .wallet_manager()
+        .list_transactions()?
+        .into_iter()
+        .filter(|utxo| !utxo.reserved && utxo.confirmed > 0)
+        .filter(|utxo| max_amount_msat.map_or(true, |max| utxo.amount_msat <= max))
+        .collect::<Vec<_>>();
+    if inputs.len() < 2 {
+        return Err(crate::rpc_error!(
+            "at least two confirmed utxos are needed for the consolidation, found `{}`",
+            inputs.len()
+        ));
+    }
+    // the wallet works with sat/kw, like ldk.
+    let fee_rate = match request.fee_rate {
+        Some(fee_rate) => fee_rate * 250,
+        None => ctx.onchain_manager().backend.fee_rate_estimation(144)?,
+    };
+    let outpoints = inputs
+        .iter()
+        .map(|utxo| {
+            Ok(OutPoint {
+                txid: Txid::from_str(&utxo.txid)?,
+                vout: utxo.vout,
+            })
+        })
+        .collect::<error::Result<Vec<_>>>()?;
+    let tx = ctx
+        .wallet_manager()
+        .create_consolidation(&outpoints, fee_rate)?;
+    let input_sat = inputs
+        .iter()
+        .map(|utxo| utxo.amount_msat / 1000)
+        .sum::<u64>();
+    let amount_sat = tx.output.iter().map(|output| output.value).sum::<u64>();
+    let broadcast = !request.preview.unwrap_or(false);
+    if broadcast {
+        log::info!(target: "onchain", "broadcast consolidation `{}` of {} utxos", tx.txid(), inputs.len());
+        ctx.onchain_manager().backend.brodcast_tx(&tx);
+    }
+    Ok(json::to_value(Consolidation {
+        txid: tx.txid().to_string(),
+        inputs,
+        amount_sat,
+        fee_sat: input_sat.saturating_sub(amount_sat),
+        fee_rate: (fee_rate / 250).max(1),
+        broadcast,
+    })?)
+}
+
+pub fn json_withdraw(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `withdraw` with request `{:?}`", request);
+    let request: request::Withdraw = json::from_value(request.clone())?;
+    let address = request.address(ctx.conf().network)?;
+    let amount_sat = request.amount_sat()?;
+    let fee_rate = ctx.onchain_manager().fees().fee_rate(request.feerate()?)?;
+    let rbf = request.rbf.unwrap_or(true);
+    // the utxos before the transaction, so we know the amount of its inputs.
+    let utxos = ctx.wallet_manager().list_transactions()?;
+    let tx = ctx.wallet_manager().create_withdrawal(
+        address.script_pubkey(),
+        amount_sat,
+        fee_rate,
+        rbf,
+    )?;
+    let input_sat = tx
+        .input
+        .iter()
+        .filter_map(|input| {
+            utxos.iter().find(|utxo| {
+                utxo.txid == input.previous_output.txid.to_string()
+                    && utxo.vout == input.previous_output.vout
+            })
+        })
+        .map(|utxo| utxo.amount_msat / 1000)
+        .sum::<u64>();
+    let output_sat = tx.output.iter().map(|output| output.value).sum::<u64>();
+    let amount_sat = tx
+        .output
+        .iter()
+        .filter(|output| output.script_pubkey == address.script_pubkey())
+        .map(|output| output.value)
+        .sum::<u64>();
+    let txid = tx.txid().to_string();
+    let broadcast = !request.simulate.unwrap_or(false);
+    if broadcast {
+        log::info!(target: "onchain", "broadcast withdrawal `{txid}` of {amount_sat} sat to `{address}`");
+        ctx.onchain_manager().backend.brodcast_tx(&tx);
+        if request.label.is_some() {
+            ctx.labels().set(&txid, request.label.clone())?;
+        }
+    }
+    Ok(json::to_value(Withdrawal {
+        txid,
+        amount_sat,
+        fee_sat: input_sat.saturating_sub(output_sat),
+        fee_rate: (fee_rate / 250).max(1),
+        rbf,
+        broadcast,
+        label: request.label,
+    })?)
+}
+
+/// The outputs of the closed channels that are
+/// not yet swept to the wallet.
+pub fn json_list_sweeps(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listsweeps` with request `{:?}`", request);
+    let sweeps = chain::list_sweeps(&ctx.sweeper());
+    Ok(json::to_value(sweeps)?)
+}
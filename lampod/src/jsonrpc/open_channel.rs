@@ -3,27 +3,38 @@
 use lampo_common::json;
 use lampo_common::model::request;
 use lampo_jsonrpc::errors::Error;
+use lampo_jsonrpc::errors::RpcError;
 
+use crate::jsonrpc::peer_control::resolve_node_id;
 use crate::ln::events::ChannelEvents;
+use crate::rpc_error;
 use crate::LampoDaemon;
 
-pub fn json_open_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
-    log::info!("call for `openchannel` with request {:?}", request);
-    let request: request::OpenChannel = json::from_value(request.clone())?;
+/// Resolve the node id of the channel and connect to the peer.
+fn connect_peer(
+    ctx: &LampoDaemon,
+    mut request: request::OpenChannel,
+) -> Result<request::OpenChannel, Error> {
+    // the node id can be an alias or a prefix, so from now on
+    // we work with the full node id.
+    let conn = request::Connect::from(request.clone());
+    request.node_id = resolve_node_id(ctx, conn.query())?.to_string();
 
     // LDK's `create_channel()` doesn't check if you are currently connected
     // to the given peer so we need to check ourselves
-    // FIXME: remove unwrap!
-    if !ctx
-        .peer_manager()
-        .is_connected_with(request.node_id().unwrap())
-    {
+    if !ctx.peer_manager().is_connected_with(request.node_id()?) {
         log::trace!("we are not connected with the peer {}", request.node_id);
-        let conn = request::Connect::try_from(request.clone())?;
         let conn = json::to_value(conn)?;
         let _ = ctx.rt.enter();
         ctx.call("connect", conn)?;
     }
+    Ok(request)
+}
+
+pub fn json_open_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `openchannel` with request {:?}", request);
+    let request: request::OpenChannel = json::from_value(request.clone())?;
+    let request = connect_peer(ctx, request)?;
 
     // FIXME: there are use case there need to be covered, like
     // - When there is an error how we return back to the user?
@@ -32,3 +43,39 @@ pub fn json_open_channel(ctx: &LampoDaemon, request: &json::Value) -> Result<jso
     let resp = ctx.channel_manager().open_channel(request)?;
     Ok(json::to_value(resp)?)
 }
+
+/// Open several channels funded by a single transaction.
+pub fn json_open_channels(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `openchannels` with request {:?}", request);
+    let request: request::OpenChannels = json::from_value(request.clone())?;
+    if request
+        .channels
+        .iter()
+        .any(|channel| channel.external_funding.unwrap_or(false))
+    {
+        return Err(rpc_error!(
+            "`external_funding` is not supported by `openchannels`"
+        ));
+    }
+    let channels = request
+        .channels
+        .into_iter()
+        .map(|channel| connect_peer(ctx, channel))
+        .collect::<Result<Vec<_>, _>>()?;
+    let resp = ctx
+        .channel_manager()
+        .open_channels(request::OpenChannels { channels })?;
+    Ok(json::to_value(resp)?)
+}
+
+/// Complete a channel opened with `external_funding`, the transaction
+/// is the one built and signed by the external wallet.
+pub fn json_complete_funding(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `completefunding` with request {:?}", request);
+    let request: request::CompleteFunding = json::from_value(request.clone())?;
+    let tx = ctx.channel_manager().complete_external_funding(request)?;
+    Ok(json::json!({ "txid": tx.txid().to_string() }))
+}
@@ -1,16 +1,62 @@
 //! Peer Control JSON RPC Interface!
+use std::str::FromStr;
+
+use lampo_common::error;
 use lampo_common::json;
-use lampo_common::model::Connect;
+use lampo_common::model::{request, response, Connect};
+use lampo_common::types::NodeId;
 use lampo_jsonrpc::errors::Error;
 
 use crate::{ln::events::PeerEvents, LampoDaemon};
 
+/// Resolve the node id of the request, if it is not a full node
+/// id it is looked up by prefix or alias inside the network graph.
+pub fn resolve_node_id(ctx: &LampoDaemon, query: &str) -> error::Result<NodeId> {
+    if let Ok(node_id) = NodeId::from_str(query) {
+        return Ok(node_id);
+    }
+    ctx.channel_manager().lookup_node(query)
+}
+
 pub fn json_connect(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
     log::info!("call for `connect` with request `{:?}`", request);
     let input: Connect = json::from_value(request.clone())?;
-    let host = input.addr()?;
-    let node_id = input.node_id()?;
+    let node_id = resolve_node_id(ctx, input.query())?;
+    let host = match input.addr()? {
+        Some(host) => host,
+        None => ctx
+            .channel_manager()
+            .node_addresses(&node_id)
+            .into_iter()
+            .next()
+            .ok_or(error::anyhow!(
+                "no address known for `{node_id}`, please specify `addr` and `port`"
+            ))?,
+    };
 
     ctx.rt.block_on(ctx.peer_manager().connect(node_id, host))?;
-    Ok(request.clone())
+    Ok(json::to_value(Connect {
+        node_id: node_id.to_string(),
+        addr: Some(host.ip().to_string()),
+        port: Some(host.port() as u64),
+    })?)
+}
+
+pub fn json_send_onion_message(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `sendonionmessage` with request `{:?}`", request);
+    let input: request::SendOnionMessage = json::from_value(request.clone())?;
+    let node_id = resolve_node_id(ctx, &input.node_id)?;
+    let data = hex::decode(&input.data)
+        .map_err(|err| error::anyhow!("`data` is not hex encoded: {err}"))?;
+    let peer_manager = ctx.peer_manager();
+    peer_manager
+        .onion_messages()
+        .send(&peer_manager, node_id, input.tlv_type, data)?;
+    Ok(json::to_value(response::SendOnionMessage {
+        node_id: node_id.to_string(),
+        tlv_type: input.tlv_type,
+    })?)
 }
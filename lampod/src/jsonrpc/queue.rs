@@ -0,0 +1,26 @@
+//! Payment queue RPC methods
+use lampo_common::json;
+use lampo_common::model::request::{ListQueue, QueuePay};
+use lampo_common::model::response::PaymentQueue;
+use lampo_jsonrpc::errors::Error;
+
+use crate::LampoDaemon;
+
+pub fn json_queue_pay(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `queuepay` with request `{:?}`", request);
+    let request: QueuePay = json::from_value(request.clone())?;
+    let payment = ctx.payment_queue().add(&ctx.offchain_manager(), request)?;
+    Ok(json::to_value(&payment)?)
+}
+
+pub fn json_list_queue(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listqueue` with request `{:?}`", request);
+    let status = match request {
+        json::Value::Null => None,
+        request => json::from_value::<ListQueue>(request.clone())?.status,
+    };
+    let queue = PaymentQueue {
+        payments: ctx.payment_queue().list(status),
+    };
+    Ok(json::to_value(&queue)?)
+}
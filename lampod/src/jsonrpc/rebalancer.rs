@@ -0,0 +1,27 @@
+//! Rebalancer RPC methods
+use lampo_common::json;
+use lampo_common::model::response::{PaymentDirection, PaymentState, RebalanceReport};
+use lampo_jsonrpc::errors::Error;
+
+use crate::ln::LampoRebalancer;
+use crate::LampoDaemon;
+
+pub fn json_rebalance_report(
+    ctx: &LampoDaemon,
+    request: &json::Value,
+) -> Result<json::Value, Error> {
+    log::info!("call for `rebalancereport` with request `{:?}`", request);
+    let mut actions = LampoRebalancer::report(&ctx.persister())?;
+    // the report stores the state at the moment of the payment,
+    // so we look at the payment store for the pending ones.
+    for action in actions.iter_mut() {
+        let (PaymentState::Pending, Some(payment_hash)) = (&action.state, &action.payment_hash)
+        else {
+            continue;
+        };
+        if let Some(payment) = ctx.payments().get(payment_hash, &PaymentDirection::Sent)? {
+            action.state = payment.state;
+        }
+    }
+    Ok(json::to_value(&RebalanceReport { actions })?)
+}
@@ -0,0 +1,29 @@
+//! Recurring payments RPC methods
+use lampo_common::json;
+use lampo_common::model::request::{DelSchedule, SchedulePay};
+use lampo_common::model::response::Schedules;
+use lampo_jsonrpc::errors::Error;
+
+use crate::LampoDaemon;
+
+pub fn json_schedule_pay(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `schedulepay` with request `{:?}`", request);
+    let request: SchedulePay = json::from_value(request.clone())?;
+    let schedule = ctx.scheduler().add(request)?;
+    Ok(json::to_value(&schedule)?)
+}
+
+pub fn json_list_schedules(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `listschedules` with request `{:?}`", request);
+    let schedules = Schedules {
+        schedules: ctx.scheduler().list(),
+    };
+    Ok(json::to_value(&schedules)?)
+}
+
+pub fn json_del_schedule(ctx: &LampoDaemon, request: &json::Value) -> Result<json::Value, Error> {
+    log::info!("call for `delschedule` with request `{:?}`", request);
+    let request: DelSchedule = json::from_value(request.clone())?;
+    let schedule = ctx.scheduler().remove(&request.id)?;
+    Ok(json::to_value(&schedule)?)
+}
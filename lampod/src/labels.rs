@@ -0,0 +1,56 @@
+//! Labels of the on chain addresses and transactions.
+//!
+//! The labels are only used for the bookkeeping of the user,
+//! all of them are stored under the same key.
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use lampo_common::bitcoin::{Address, Txid};
+use lampo_common::error;
+
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const LABELS_NAMESPACE: &str = "labels";
+const LABELS_KEY: &str = "index";
+
+pub struct LampoLabels {
+    persister: Arc<LampoPersistence>,
+    // address or txid -> label
+    labels: Mutex<BTreeMap<String, String>>,
+}
+
+impl LampoLabels {
+    pub fn new(persister: Arc<LampoPersistence>) -> error::Result<Self> {
+        let labels = persister
+            .read_json::<BTreeMap<String, String>>(LABELS_NAMESPACE, LABELS_KEY)?
+            .unwrap_or_default();
+        Ok(Self {
+            persister,
+            labels: Mutex::new(labels),
+        })
+    }
+
+    /// Attach the `label` to the address or the transaction `id`,
+    /// a `None` (or empty) label removes the previous one.
+    pub fn set(&self, id: &str, label: Option<String>) -> error::Result<()> {
+        let id = id.trim();
+        if Txid::from_str(id).is_err() && Address::from_str(id).is_err() {
+            error::bail!("`{id}` is not an address or a txid");
+        }
+        let mut labels = self.labels.lock().unwrap();
+        let mut updated = labels.clone();
+        match label.filter(|label| !label.trim().is_empty()) {
+            Some(label) => updated.insert(id.to_owned(), label),
+            None => updated.remove(id),
+        };
+        self.persister
+            .write_json(LABELS_NAMESPACE, LABELS_KEY, &updated)?;
+        *labels = updated;
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<String> {
+        self.labels.lock().unwrap().get(id).cloned()
+    }
+}
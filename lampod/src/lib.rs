@@ -11,22 +11,40 @@
 //!
 //! Have fun exploring the code!
 pub mod actions;
+pub mod builder;
 mod builtin;
 pub mod chain;
 pub mod command;
+pub mod forwards;
 pub mod handler;
+pub mod invoices;
 pub mod jsonrpc;
+pub mod labels;
 pub mod ln;
+pub mod lsp_orders;
+pub mod lsp_server;
+pub mod metrics;
+pub mod migration;
+pub mod payments;
 pub mod persistence;
+pub mod qr;
+pub mod queue;
+pub mod ratelimit;
+pub mod rates;
+pub mod scheduler;
+pub mod stats;
+pub mod transactions;
 
 use std::cell::Cell;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
 
 use tokio::runtime::Runtime;
 
 use lampo_common::backend::Backend;
 use lampo_common::bitcoin::absolute::Height;
+use lampo_common::bitcoin::{BlockHash, Network};
+use lampo_common::chan;
 use lampo_common::conf::LampoConf;
 use lampo_common::error;
 use lampo_common::json;
@@ -38,11 +56,30 @@ use lampo_common::wallet::WalletManager;
 
 use crate::actions::handler::LampoHandler;
 use crate::actions::Handler;
-use crate::chain::LampoChainManager;
+use crate::chain::{LampoChainManager, LampoSweeper};
+use crate::forwards::LampoForwardStore;
 use crate::handler::external_handler::ExternalHandler;
-use crate::ln::OffchainManager;
-use crate::ln::{LampoChannelManager, LampoInventoryManager, LampoPeerManager};
+use crate::invoices::LampoInvoiceStore;
+use crate::labels::LampoLabels;
+use crate::ln::{
+    LampoChannelBackup, LampoChannelManager, LampoFundingWatcher, LampoInventoryManager,
+    LampoPeerManager,
+};
+use crate::ln::{
+    LampoIpDiscovery, LampoProber, LampoRebalancer, LampoRgsServer, LampoWatchdog, OffchainManager,
+};
+use crate::lsp_orders::LampoLspOrders;
+use crate::lsp_server::LampoLspServer;
+use crate::metrics::LampoMetrics;
+use crate::migration::LampoMigration;
+use crate::payments::LampoPaymentStore;
 use crate::persistence::LampoPersistence;
+use crate::queue::LampoPaymentQueue;
+use crate::ratelimit::LampoRateLimiter;
+use crate::rates::ExchangeRateProvider;
+use crate::scheduler::LampoScheduler;
+use crate::stats::LampoStats;
+use crate::transactions::LampoTransactionIndex;
 use crate::utils::logger::LampoLogger;
 
 /// LampoDaemon is the main data structure that uses the facade
@@ -60,13 +97,29 @@ pub struct LampoDaemon {
     peer_manager: Option<Arc<LampoPeerManager>>,
     onchain_manager: Option<Arc<LampoChainManager>>,
     channel_manager: Option<Arc<LampoChannelManager>>,
+    channel_backup: Option<Arc<LampoChannelBackup>>,
+    sweeper: Option<Arc<LampoSweeper>>,
     inventory_manager: Option<Arc<LampoInventoryManager>>,
     wallet_manager: Arc<dyn WalletManager>,
     offchain_manager: Option<Arc<OffchainManager>>,
     logger: Arc<LampoLogger>,
     persister: Arc<LampoPersistence>,
+    stats: Arc<LampoStats>,
+    metrics: Arc<LampoMetrics>,
+    invoices: Arc<LampoInvoiceStore>,
+    payments: Arc<LampoPaymentStore>,
+    forwards: Arc<LampoForwardStore>,
+    lsp_orders: Arc<LampoLspOrders>,
+    scheduler: Arc<LampoScheduler>,
+    payment_queue: Arc<LampoPaymentQueue>,
+    transactions: Arc<LampoTransactionIndex>,
+    labels: Arc<LampoLabels>,
+    watchdog: Arc<LampoWatchdog>,
+    invoice_limiter: Arc<LampoRateLimiter>,
+    exchange_rate: Option<Arc<dyn ExchangeRateProvider>>,
     handler: Option<Arc<LampoHandler>>,
     process: Cell<Option<BackgroundProcessor>>,
+    shutdown: Mutex<Option<chan::Sender<()>>>,
 
     // FIXME: remove this
     rt: Runtime,
@@ -76,25 +129,65 @@ unsafe impl Send for LampoDaemon {}
 unsafe impl Sync for LampoDaemon {}
 
 impl LampoDaemon {
-    pub fn new(config: LampoConf, wallet_manager: Arc<dyn WalletManager>) -> Self {
+    pub fn new(config: LampoConf, wallet_manager: Arc<dyn WalletManager>) -> error::Result<Self> {
         let root_path = config.path();
+        let persister = Arc::new(LampoPersistence::new(root_path.into()));
+        Self::with_persister(config, wallet_manager, persister)
+    }
+
+    /// Build the daemon with a custom persister, useful when lampo
+    /// is embedded inside another application.
+    pub fn with_persister(
+        config: LampoConf,
+        wallet_manager: Arc<dyn WalletManager>,
+        persister: Arc<LampoPersistence>,
+    ) -> error::Result<Self> {
         //FIXME: sync some where else
         let wallet = wallet_manager.clone();
         let _ = std::thread::spawn(move || wallet.sync().unwrap());
-        LampoDaemon {
+        let exchange_rate = rates::provider(&config);
+        let invoices = Arc::new(LampoInvoiceStore::new(persister.clone())?);
+        let scheduler = Arc::new(LampoScheduler::new(persister.clone())?);
+        let payments = Arc::new(LampoPaymentStore::new(persister.clone()));
+        let payment_queue = Arc::new(LampoPaymentQueue::new(
+            &config,
+            persister.clone(),
+            payments.clone(),
+        )?);
+        let transactions = Arc::new(LampoTransactionIndex::new(persister.clone())?);
+        let labels = Arc::new(LampoLabels::new(persister.clone())?);
+        let watchdog = Arc::new(LampoWatchdog::new(&config));
+        let invoice_limiter = Arc::new(LampoRateLimiter::new(config.invoice_rate_limit));
+        Ok(LampoDaemon {
             conf: config,
             logger: Arc::new(LampoLogger {}),
-            persister: Arc::new(LampoPersistence::new(root_path.into())),
+            stats: Arc::new(LampoStats::new(persister.clone())),
+            metrics: Arc::new(LampoMetrics::new()),
+            invoices,
+            payments,
+            forwards: Arc::new(LampoForwardStore::new(persister.clone())),
+            lsp_orders: Arc::new(LampoLspOrders::new(persister.clone())),
+            scheduler,
+            payment_queue,
+            transactions,
+            labels,
+            watchdog,
+            invoice_limiter,
+            exchange_rate,
+            persister,
             peer_manager: None,
             onchain_manager: None,
             channel_manager: None,
+            channel_backup: None,
+            sweeper: None,
             inventory_manager: None,
             wallet_manager,
             offchain_manager: None,
             handler: None,
             process: Cell::new(None),
+            shutdown: Mutex::new(None),
             rt: Runtime::new().unwrap(),
-        }
+        })
     }
 
     pub fn root_path(&self) -> String {
@@ -105,9 +198,69 @@ impl LampoDaemon {
         &self.conf
     }
 
+    pub fn stats(&self) -> Arc<LampoStats> {
+        self.stats.clone()
+    }
+
+    pub fn metrics(&self) -> Arc<LampoMetrics> {
+        self.metrics.clone()
+    }
+
+    pub fn invoices(&self) -> Arc<LampoInvoiceStore> {
+        self.invoices.clone()
+    }
+
+    pub fn payments(&self) -> Arc<LampoPaymentStore> {
+        self.payments.clone()
+    }
+
+    pub fn forwards(&self) -> Arc<LampoForwardStore> {
+        self.forwards.clone()
+    }
+
+    pub fn lsp_orders(&self) -> Arc<LampoLspOrders> {
+        self.lsp_orders.clone()
+    }
+
+    pub fn scheduler(&self) -> Arc<LampoScheduler> {
+        self.scheduler.clone()
+    }
+
+    pub fn payment_queue(&self) -> Arc<LampoPaymentQueue> {
+        self.payment_queue.clone()
+    }
+
+    pub fn transactions(&self) -> Arc<LampoTransactionIndex> {
+        self.transactions.clone()
+    }
+
+    pub fn labels(&self) -> Arc<LampoLabels> {
+        self.labels.clone()
+    }
+
+    /// Rate limiter for the invoice creation, the integrations
+    /// should use a different source for each client.
+    pub fn invoice_limiter(&self) -> Arc<LampoRateLimiter> {
+        self.invoice_limiter.clone()
+    }
+
+    pub fn persister(&self) -> Arc<LampoPersistence> {
+        self.persister.clone()
+    }
+
+    pub fn exchange_rate(&self) -> Option<Arc<dyn ExchangeRateProvider>> {
+        self.exchange_rate.clone()
+    }
+
+    /// Replace the exchange rate provider specified inside the configuration.
+    pub fn set_exchange_rate(&mut self, provider: Arc<dyn ExchangeRateProvider>) {
+        self.exchange_rate = Some(provider);
+    }
+
     pub fn init_onchaind(&mut self, client: Arc<dyn Backend>) -> error::Result<()> {
         log::debug!(target: "lampod", "init onchaind ..");
-        let onchain_manager = LampoChainManager::new(client, self.wallet_manager.clone());
+        let onchain_manager =
+            LampoChainManager::new(client, self.wallet_manager.clone(), &self.conf);
         self.onchain_manager = Some(Arc::new(onchain_manager));
         Ok(())
     }
@@ -125,23 +278,59 @@ impl LampoDaemon {
             self.wallet_manager.clone(),
             self.persister.clone(),
         );
+        let restarting = manager.is_restarting()?;
+        let migration = LampoMigration::new(&self.conf, self.persister.clone());
+        let migrate = migration.prepare()?;
+
+        // The graph, the scorer and the channel monitors are read from
+        // disk and they do not depend on each other, so we load them
+        // while we ask the backend for the chain tip. The channel
+        // manager needs all of them, so it is built after.
+        let (router, monitors, tip) = std::thread::scope(|scope| {
+            let router = scope.spawn(|| manager.load_router());
+            let monitors = scope.spawn(|| {
+                if restarting {
+                    manager.get_channel_monitors()
+                } else {
+                    Ok(vec![])
+                }
+            });
+            let tip = self.chain_tip();
+            (router.join(), monitors.join(), tip)
+        });
+        let (graph, scorer) =
+            router.map_err(|_| error::anyhow!("impossible load the network graph"))?;
+        let monitors =
+            monitors.map_err(|_| error::anyhow!("impossible read the channel monitors"))??;
+        let (block_hash, height, timestamp) = tip?;
+        manager.set_router(graph, scorer);
+
+        // never start the channel manager on top of an old state
+        self.watchdog.check(&monitors)?;
+        if restarting {
+            manager.restart_with_monitors(monitors)?;
+            if migrate {
+                manager.rewrite_state()?;
+            }
+        } else {
+            manager.start(block_hash, Height::from_consensus(height)?, timestamp)?;
+        }
+        migration.finish()?;
+
+        self.channel_manager = Some(Arc::new(manager));
+        Ok(())
+    }
+
+    /// Return the hash, the height and the timestamp of the best block.
+    fn chain_tip(&self) -> error::Result<(BlockHash, u32, u32)> {
         let (block_hash, height) = self.onchain_manager().backend.get_best_block()?;
         let block = self.onchain_manager().backend.get_block(&block_hash)?;
         let timestamp = match block {
             lampo_common::backend::BlockData::FullBlock(block) => block.header.time,
             lampo_common::backend::BlockData::HeaderOnly(header) => header.time,
         };
-
         let height = height.ok_or(error::anyhow!("height not present"))?;
-
-        if manager.is_restarting()? {
-            manager.restart()?;
-        } else {
-            manager.start(block_hash, Height::from_consensus(height)?, timestamp)?;
-        }
-
-        self.channel_manager = Some(Arc::new(manager));
-        Ok(())
+        Ok((block_hash, height, timestamp))
     }
 
     pub fn channel_manager(&self) -> Arc<LampoChannelManager> {
@@ -160,6 +349,8 @@ impl LampoDaemon {
             self.logger.clone(),
             Arc::new(self.conf.clone()),
             self.onchain_manager(),
+            self.stats(),
+            self.payments(),
         )?;
         self.offchain_manager = Some(Arc::new(manager));
         Ok(())
@@ -167,7 +358,7 @@ impl LampoDaemon {
 
     pub fn init_peer_manager(&mut self) -> error::Result<()> {
         log::debug!(target: "lampo", "init peer manager ...");
-        let mut peer_manager = LampoPeerManager::new(&self.conf, self.logger.clone());
+        let mut peer_manager = LampoPeerManager::new(&self.conf, self.logger.clone(), self.stats());
         peer_manager.init(
             self.onchain_manager(),
             self.wallet_manager.clone(),
@@ -181,6 +372,44 @@ impl LampoDaemon {
         self.peer_manager.clone().unwrap()
     }
 
+    fn init_channel_backup(&mut self) -> error::Result<()> {
+        log::debug!(target: "lampod", "init channel backup ...");
+        let backup = LampoChannelBackup::new(
+            &self.conf,
+            self.wallet_manager.ldk_keys().keys_manager.clone(),
+            self.channel_manager(),
+            self.peer_manager(),
+            self.persister.clone(),
+        );
+        // fail fast when the backup to recover is not readable
+        if let Some(path) = &self.conf.recover_from_scb {
+            backup.read(path)?;
+        }
+        self.channel_backup = Some(Arc::new(backup));
+        Ok(())
+    }
+
+    pub fn channel_backup(&self) -> Arc<LampoChannelBackup> {
+        self.channel_backup.clone().unwrap()
+    }
+
+    fn init_sweeper(&mut self) -> error::Result<()> {
+        log::debug!(target: "lampod", "init output sweeper ...");
+        let sweeper = chain::sweeper(
+            self.channel_manager().manager().current_best_block(),
+            self.onchain_manager(),
+            self.wallet_manager.clone(),
+            self.persister.clone(),
+            self.logger.clone(),
+        )?;
+        self.sweeper = Some(Arc::new(sweeper));
+        Ok(())
+    }
+
+    pub fn sweeper(&self) -> Arc<LampoSweeper> {
+        self.sweeper.clone().unwrap()
+    }
+
     fn init_inventory_manager(&mut self) -> error::Result<()> {
         log::debug!(target: "lampod", "init inventory manager ...");
         let manager = LampoInventoryManager::new(self.peer_manager(), self.channel_manager());
@@ -216,14 +445,39 @@ impl LampoDaemon {
 
     pub fn init(&mut self, client: Arc<dyn Backend>) -> error::Result<()> {
         log::debug!(target: "lampod", "init lampod ...");
+        // the backend is already built, e.g: it can be a custom
+        // backend of the `LampoBuilder`.
+        self.conf.validate_without_backend()?;
+        let network = client.network()?;
+        if network != self.conf.network {
+            error::bail!(
+                "`network`: lampo is configured for `{}` but the backend is running on `{network}`",
+                self.conf.network
+            );
+        }
+        if network == Network::Signet {
+            let challenge = self.conf.signet_challenge();
+            match client.signet_challenge()? {
+                Some(backend) if backend != challenge => error::bail!(
+                    "`signet-challenge`: lampo is configured for the signet `{challenge}` but the backend is running on the signet `{backend}`"
+                ),
+                _ => {}
+            }
+            log::info!(target: "lampod", "running on the signet with magic `{}`", hex::encode(self.conf.signet_magic()?));
+        }
         self.init_onchaind(client.clone())?;
         self.init_channeld()?;
         self.init_offchain_manager()?;
         self.init_peer_manager()?;
+        self.init_channel_backup()?;
+        self.init_sweeper()?;
         self.init_inventory_manager()?;
         self.init_event_handler()?;
         client.set_handler(self.handler());
         self.channel_manager().set_handler(self.handler());
+        self.peer_manager()
+            .onion_messages()
+            .set_handler(self.handler());
         Ok(())
     }
 
@@ -250,9 +504,14 @@ impl LampoDaemon {
         ));
 
         let handler = self.handler();
+        let metrics = self.metrics();
         let event_handler = move |event: Event| {
-            log::info!(target: "lampo", "ldk event {:?}", event);
-            if let Err(err) = handler.handle(event) {
+            let description = format!("{:?}", event);
+            log::info!(target: "lampo", "ldk event {description}");
+            let id = metrics.event_started(event_name(&description), description.clone());
+            let result = handler.handle(event);
+            metrics.event_finished(id, result.is_ok());
+            if let Err(err) = result {
                 log::error!("{err}");
             }
         };
@@ -263,6 +522,89 @@ impl LampoDaemon {
         let _ = self.peer_manager().run();
         log::info!(target: "lampo", "Starting channel manager");
         let _ = self.channel_manager().listen();
+        let _ = self.channel_backup().watch(self.handler());
+        if let Some(path) = &self.conf.recover_from_scb {
+            log::info!(target: "lampo", "Recovering the channels from `{path}`");
+            let _ = self
+                .channel_backup()
+                .recover(path, self.sweeper(), self.handler())?;
+        }
+        let _ = chain::watch_sweeper(self.sweeper(), self.handler());
+        let _ = chain::watch_fees(self.onchain_manager().fees(), self.handler());
+        let _ = self.invoices().watch_fallbacks(self.handler());
+        let _ = self
+            .invoices()
+            .watch_holds(self.handler(), self.offchain_manager());
+        let _ = self.transactions().watch(self.handler());
+        let funding_watcher = LampoFundingWatcher::new(
+            self.channel_manager(),
+            self.wallet_manager(),
+            self.persister.clone(),
+        )?;
+        let _ = Arc::new(funding_watcher).watch(self.handler());
+        self.watchdog.clone().install_panic_hook();
+        let _ = self
+            .watchdog
+            .clone()
+            .watch(self.channel_manager(), self.handler());
+        log::info!(target: "lampo", "Starting recurring payments scheduler");
+        let _ = self
+            .scheduler()
+            .run(self.offchain_manager(), self.handler());
+        log::info!(target: "lampo", "Starting payment queue");
+        let _ = self
+            .payment_queue()
+            .run(self.offchain_manager(), self.handler());
+        if self.conf.public_ip_interval.is_some() {
+            log::info!(target: "lampo", "Starting public ip discovery");
+            let discovery = LampoIpDiscovery::new(&self.conf, self.peer_manager());
+            let _ = Arc::new(discovery).run();
+        }
+        if self.conf.lsp.is_some() {
+            log::info!(target: "lampo", "Starting the LSP orders watcher");
+            let _ = self
+                .lsp_orders()
+                .watch(self.handler(), self.peer_manager())?;
+        }
+        if self.conf.lsp_server {
+            log::info!(target: "lampo", "Starting the LSP server");
+            let server = Arc::new(LampoLspServer::new(
+                &self.conf,
+                self.channel_manager(),
+                self.offchain_manager(),
+                self.peer_manager(),
+                self.invoices(),
+                self.wallet_manager().ldk_keys().keys_manager.clone(),
+                self.persister(),
+            ));
+            let _ = server.clone().watch(self.handler());
+            let _ = server.serve();
+        }
+        if self.conf.rgs_server.is_some() {
+            log::info!(target: "lampo", "Starting rapid gossip sync server");
+            let server = LampoRgsServer::new(&self.conf, self.channel_manager());
+            let _ = Arc::new(server).run()?;
+        }
+        if self.conf.probe_interval.is_some() {
+            log::info!(target: "lampo", "Starting prober");
+            let prober = LampoProber::new(
+                &self.conf,
+                self.channel_manager(),
+                self.offchain_manager(),
+                self.wallet_manager(),
+            );
+            let _ = Arc::new(prober).run();
+        }
+        if self.conf.rebalance_interval.is_some() {
+            log::info!(target: "lampo", "Starting rebalancer");
+            let rebalancer = LampoRebalancer::new(
+                &self.conf,
+                self.channel_manager(),
+                self.offchain_manager(),
+                self.persister(),
+            );
+            let _ = Arc::new(rebalancer).run();
+        }
 
         let background_processor = BackgroundProcessor::start(
             self.persister.clone(),
@@ -275,12 +617,26 @@ impl LampoDaemon {
             Some(self.channel_manager().scorer()),
         );
 
+        let (shutdown, stop) = chan::bounded(1);
+        *self.shutdown.lock().unwrap() = Some(shutdown);
         Ok(std::thread::spawn(move || {
-            let _ = background_processor.join();
-            Ok(())
+            let _ = stop.recv();
+            log::info!(target: "lampo", "Stopping the background processor");
+            // stopping the background processor persist the latest state
+            background_processor.stop()
         }))
     }
 
+    /// Stop the node started with `listen`, the thread returned
+    /// by `listen` terminates when the state is persisted.
+    pub fn stop(&self) -> error::Result<()> {
+        let Some(shutdown) = self.shutdown.lock().unwrap().take() else {
+            error::bail!("lampo is not running");
+        };
+        shutdown.send(())?;
+        Ok(())
+    }
+
     /// Call any method supported by the lampod configuration. This includes
     /// a lot of handler code. This function serves as a broker pattern in some ways,
     /// but it may also function as a chain of responsibility pattern in certain cases.
@@ -295,3 +651,11 @@ impl LampoDaemon {
         handler.call::<json::Value, json::Value>(method, args)
     }
 }
+
+/// The name of the event variant from its debug format, e.g: `PaymentSent`.
+fn event_name(description: &str) -> &str {
+    description
+        .split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default()
+}
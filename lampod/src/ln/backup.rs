@@ -0,0 +1,611 @@
+//! Static channel backup.
+//!
+//! The channel monitors are the only way to get the funds back when
+//! the peer cheats, but they change at every payment. The static
+//! backup stores only the channels and the peers, so it changes when
+//! a channel is opened or closed and it can be copied somewhere else.
+//!
+//! When the node state is lost, the node is restored from the seed and
+//! started with `--recover-from-scb`: we connect to the peers of the
+//! backup, ldk answers to the `channel_reestablish` of a channel that
+//! it does not know and the peer force closes it. The channels still
+//! known by the node are force closed by us.
+//!
+//! The peer pays our balance to the `to_remote` output of its commitment,
+//! and the key of that output is derived from the channel keys id, that
+//! is stored inside the channel monitor. So the backup stores also the
+//! keys ids generated by the node with the value of the channel: when the
+//! commitment of the peer is confirmed, the output that pays us is found
+//! with them and it is given to the sweeper.
+//!
+//! The backup is encrypted with a key derived from the seed.
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use lampo_common::bitcoin;
+use lampo_common::bitcoin::hashes::Hash;
+use lampo_common::bitcoin::{ScriptBuf, Transaction, WPubkeyHash};
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::onchain::OnChainEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::json;
+use lampo_common::keys::LampoKeysManager;
+use lampo_common::ldk::chain::transaction::OutPoint;
+use lampo_common::ldk::ln::chan_utils::{
+    self, ChannelTransactionParameters, CounterpartyChannelTransactionParameters,
+};
+use lampo_common::ldk::ln::features::ChannelTypeFeatures;
+use lampo_common::ldk::sign::{ChannelSigner, EntropySource, InMemorySigner, NodeSigner};
+use lampo_common::ldk::sign::{Recipient, SignerProvider};
+use lampo_common::ldk::sign::{SpendableOutputDescriptor, StaticPaymentOutputDescriptor};
+use lampo_common::model::response::{ChannelBackup, ChannelKeys, StaticChannelBackup};
+use lampo_common::types::{parse_channel_id, NodeId};
+
+use crate::actions::handler::LampoHandler;
+use crate::async_run;
+use crate::chain::LampoSweeper;
+use crate::ln::events::PeerEvents;
+use crate::ln::{LampoChannelManager, LampoPeerManager};
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const NONCE_LEN: usize = 12;
+const CHANNEL_KEYS_NAMESPACE: &str = "channel_keys";
+
+pub struct LampoChannelBackup {
+    path: String,
+    network: String,
+    keys: Arc<LampoKeysManager>,
+    channel_manager: Arc<LampoChannelManager>,
+    peer_manager: Arc<LampoPeerManager>,
+    persister: Arc<LampoPersistence>,
+}
+
+impl LampoChannelBackup {
+    pub fn new(
+        conf: &LampoConf,
+        keys: Arc<LampoKeysManager>,
+        channel_manager: Arc<LampoChannelManager>,
+        peer_manager: Arc<LampoPeerManager>,
+        persister: Arc<LampoPersistence>,
+    ) -> Self {
+        Self {
+            path: Self::path(conf),
+            network: conf.network.to_string(),
+            keys,
+            channel_manager,
+            peer_manager,
+            persister,
+        }
+    }
+
+    /// Where the backup of the node is written.
+    pub fn path(conf: &LampoConf) -> String {
+        format!("{}/channels.scb", conf.path())
+    }
+
+    fn node_id(&self) -> error::Result<NodeId> {
+        self.keys
+            .get_node_id(Recipient::Node)
+            .map_err(|_| error::anyhow!("impossible get the node id"))
+    }
+
+    /// The keys of all the channels opened by the node, the keys
+    /// manager gives only the ones generated since the start, so
+    /// they are stored.
+    fn channel_keys(&self) -> error::Result<Vec<ChannelKeys>> {
+        for (keys_id, channel_value_satoshis) in self.keys.take_channel_keys_ids() {
+            let keys = ChannelKeys {
+                channel_keys_id: hex::encode(keys_id),
+                channel_value_satoshis,
+            };
+            self.persister
+                .write_json(CHANNEL_KEYS_NAMESPACE, &keys.channel_keys_id, &keys)?;
+        }
+        self.persister.list_json(CHANNEL_KEYS_NAMESPACE)
+    }
+
+    fn snapshot(&self) -> error::Result<StaticChannelBackup> {
+        let peers = self.peer_manager.manager().list_peers();
+        let channels = self
+            .channel_manager
+            .manager()
+            .list_channels()
+            .into_iter()
+            .map(|channel| {
+                let node_id = channel.counterparty.node_id;
+                let mut peer_addresses = peers
+                    .iter()
+                    .filter(|peer| peer.counterparty_node_id == node_id)
+                    .filter_map(|peer| peer.socket_address.as_ref())
+                    .map(|addr| addr.to_string())
+                    .collect::<Vec<_>>();
+                for addr in self.channel_manager.node_addresses(&node_id) {
+                    let addr = addr.to_string();
+                    if !peer_addresses.contains(&addr) {
+                        peer_addresses.push(addr);
+                    }
+                }
+                ChannelBackup {
+                    channel_id: channel.channel_id.to_string(),
+                    peer_id: node_id.to_string(),
+                    peer_addresses,
+                    funding_utxo: channel
+                        .funding_txo
+                        .map(|txo| format!("{}:{}", txo.txid, txo.index)),
+                    amount: channel.channel_value_satoshis,
+                }
+            })
+            .collect();
+        Ok(StaticChannelBackup {
+            node_id: self.node_id()?.to_string(),
+            network: self.network.clone(),
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_secs())
+                .unwrap_or_default(),
+            channels,
+            channel_keys: self.channel_keys()?,
+        })
+    }
+
+    /// Return the encrypted backup of the channels.
+    pub fn export(&self) -> error::Result<(StaticChannelBackup, Vec<u8>)> {
+        let backup = self.snapshot()?;
+        let nonce = self.keys.get_secure_random_bytes();
+        let encrypted = encrypt(&self.keys.backup_key(), &nonce[..NONCE_LEN], &backup)?;
+        Ok((backup, encrypted))
+    }
+
+    /// Write the backup, the previous one is replaced only
+    /// when the new one is on disk.
+    pub fn write(&self) -> error::Result<usize> {
+        let (backup, encrypted) = self.export()?;
+        let tmp = format!("{}.tmp", self.path);
+        std::fs::write(&tmp, encrypted)?;
+        std::fs::rename(&tmp, &self.path)?;
+        log::debug!(target: "backup", "channel backup with {} channels written", backup.channels.len());
+        Ok(backup.channels.len())
+    }
+
+    pub fn read(&self, path: &str) -> error::Result<StaticChannelBackup> {
+        let encrypted = std::fs::read(path)?;
+        decrypt(
+            &self.keys.backup_key(),
+            &encrypted,
+            &self.node_id()?.to_string(),
+            &self.network,
+        )
+        .map_err(|err| error::anyhow!("`{path}`: {err}"))
+    }
+
+    /// Close all the channels of the backup, the channels that we
+    /// still know are force closed, the other ones are closed by the
+    /// peer when we connect to it and our balance is swept.
+    pub fn recover(
+        self: Arc<Self>,
+        path: &str,
+        sweeper: Arc<LampoSweeper>,
+        handler: Arc<LampoHandler>,
+    ) -> error::Result<JoinHandle<()>> {
+        let backup = self.read(path)?;
+        let manager = self.channel_manager.manager();
+        let known = manager.list_channels();
+        let mut peers = Vec::new();
+        let mut closed_by_peer = Vec::new();
+        for channel in &backup.channels {
+            let node_id = NodeId::from_str(&channel.peer_id)?;
+            if let Some(details) = known
+                .iter()
+                .find(|details| details.channel_id.to_string() == channel.channel_id)
+            {
+                log::info!(target: "backup", "force closing channel `{}`", channel.channel_id);
+                if let Err(err) =
+                    manager.force_close_broadcasting_latest_txn(&details.channel_id, &node_id)
+                {
+                    log::error!(target: "backup", "impossible close channel `{}`: {err:?}", channel.channel_id);
+                }
+                continue;
+            }
+            if let Some(funding_utxo) = &channel.funding_utxo {
+                let funding = bitcoin::OutPoint::from_str(funding_utxo)?;
+                closed_by_peer.push((funding, channel.clone()));
+            }
+            let addrs = channel
+                .peer_addresses
+                .iter()
+                .filter_map(|addr| SocketAddr::from_str(addr).ok())
+                .collect::<Vec<_>>();
+            if addrs.is_empty() {
+                log::warn!(target: "backup", "no address to reach `{node_id}`, channel `{}` must be closed by the peer", channel.channel_id);
+                continue;
+            }
+            if !peers.iter().any(|(peer, _)| *peer == node_id) {
+                peers.push((node_id, addrs));
+            }
+        }
+        log::info!(target: "backup", "recovering {} channels with {} peers", backup.channels.len(), peers.len());
+        let _ = self.clone().sweep_closed_by_peer(
+            closed_by_peer,
+            backup.channel_keys,
+            sweeper,
+            handler,
+        );
+        Ok(std::thread::spawn(move || {
+            async_run!(futures::future::join_all(peers.into_iter().map(
+                |(node_id, addrs)| {
+                    let peer_manager = self.peer_manager.clone();
+                    async move {
+                        for addr in addrs {
+                            // the connection is kept open until the peer
+                            // disconnects, the channel is closed meanwhile.
+                            match peer_manager.connect(node_id, addr).await {
+                                Ok(()) => return,
+                                Err(err) => {
+                                    log::warn!(target: "backup", "impossible connect to `{node_id}` at `{addr}`: {err}")
+                                }
+                            }
+                        }
+                    }
+                }
+            )));
+        }))
+    }
+
+    /// Sweep our balance of the `channels` closed by the peer, when the
+    /// commitment of the peer is confirmed the output that pays us is
+    /// found with the `channel_keys` of the backup.
+    fn sweep_closed_by_peer(
+        self: Arc<Self>,
+        mut channels: Vec<(bitcoin::OutPoint, ChannelBackup)>,
+        channel_keys: Vec<ChannelKeys>,
+        sweeper: Arc<LampoSweeper>,
+        handler: Arc<LampoHandler>,
+    ) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let events = handler.events();
+            while !channels.is_empty() {
+                let Ok(Event::OnChain(OnChainEvent::NewBlock(block))) = events.recv() else {
+                    continue;
+                };
+                for tx in &block.txdata {
+                    let Some(idx) = channels.iter().position(|(funding, _)| {
+                        tx.input
+                            .iter()
+                            .any(|input| input.previous_output == *funding)
+                    }) else {
+                        continue;
+                    };
+                    let (funding, channel) = channels.remove(idx);
+                    if let Err(err) =
+                        self.sweep_commitment(&channel, funding, tx, &channel_keys, &sweeper)
+                    {
+                        log::error!(target: "backup", "impossible sweep the channel `{}`: {err}", channel.channel_id);
+                    }
+                }
+            }
+        })
+    }
+
+    fn sweep_commitment(
+        &self,
+        channel: &ChannelBackup,
+        funding: bitcoin::OutPoint,
+        tx: &Transaction,
+        channel_keys: &[ChannelKeys],
+        sweeper: &LampoSweeper,
+    ) -> error::Result<()> {
+        let descriptors = remote_balance(&self.keys, tx, funding, channel.amount, channel_keys)?;
+        if descriptors.is_empty() {
+            log::warn!(target: "backup", "the channel `{}` is closed by `{}` without an output that pays us", channel.channel_id, tx.txid());
+            return Ok(());
+        }
+        log::info!(target: "backup", "sweeping our balance of the channel `{}` from `{}`", channel.channel_id, tx.txid());
+        let channel_id = parse_channel_id(&channel.channel_id)?;
+        sweeper
+            .track_spendable_outputs(descriptors, Some(channel_id), false, None)
+            .map_err(|_| error::anyhow!("impossible store the outputs to sweep"))
+    }
+
+    /// Write the backup again every time that a channel
+    /// is opened or closed.
+    pub fn watch(self: Arc<Self>, handler: Arc<LampoHandler>) -> JoinHandle<()> {
+        if let Err(err) = self.write() {
+            log::error!(target: "backup", "impossible write the channel backup: {err}");
+        }
+        std::thread::spawn(move || {
+            let events = handler.events();
+            loop {
+                let Ok(event) = events.recv() else {
+                    continue;
+                };
+                match event {
+                    Event::Lightning(LightningEvent::ChannelPending { .. })
+                    | Event::Lightning(LightningEvent::ChannelReady { .. })
+                    | Event::Lightning(LightningEvent::CloseChannelEvent { .. }) => {}
+                    _ => continue,
+                }
+                if let Err(err) = self.write() {
+                    log::error!(target: "backup", "impossible write the channel backup: {err}");
+                }
+            }
+        })
+    }
+}
+
+/// The outputs of the commitment `tx` of the peer that pay our balance,
+/// they are found with the `channel_keys` of the same channel value.
+fn remote_balance(
+    keys: &LampoKeysManager,
+    tx: &Transaction,
+    funding: bitcoin::OutPoint,
+    channel_value_satoshis: u64,
+    channel_keys: &[ChannelKeys],
+) -> error::Result<Vec<SpendableOutputDescriptor>> {
+    let funding = OutPoint {
+        txid: funding.txid,
+        index: u16::try_from(funding.vout)?,
+    };
+    let mut descriptors = Vec::new();
+    for keys_id in channel_keys
+        .iter()
+        .filter(|keys| keys.channel_value_satoshis == channel_value_satoshis)
+    {
+        let channel_keys_id: [u8; 32] = hex::decode(&keys_id.channel_keys_id)?
+            .try_into()
+            .map_err(|_| error::anyhow!("invalid keys id `{}`", keys_id.channel_keys_id))?;
+        let signer = keys.derive_channel_signer(channel_value_satoshis, channel_keys_id);
+        let payment_point = signer.pubkeys().payment_point;
+        let script = ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::hash(&payment_point.serialize()));
+        let anchors_script =
+            chan_utils::get_to_countersignatory_with_anchors_redeemscript(&payment_point)
+                .to_v0_p2wsh();
+        for (idx, output) in tx.output.iter().enumerate() {
+            let channel_transaction_parameters = if output.script_pubkey == script {
+                None
+            } else if output.script_pubkey == anchors_script {
+                Some(anchors_parameters(&signer, funding))
+            } else {
+                continue;
+            };
+            descriptors.push(SpendableOutputDescriptor::StaticPaymentOutput(
+                StaticPaymentOutputDescriptor {
+                    outpoint: OutPoint {
+                        txid: tx.txid(),
+                        index: u16::try_from(idx)?,
+                    },
+                    output: output.clone(),
+                    channel_keys_id,
+                    channel_value_satoshis,
+                    channel_transaction_parameters,
+                },
+            ));
+        }
+    }
+    Ok(descriptors)
+}
+
+/// The parameters to spend the output of an anchor channel, only the
+/// channel type is used to spend it but ldk wants all the parameters,
+/// so the keys of the peer, that are lost, are replaced with ours.
+fn anchors_parameters(signer: &InMemorySigner, funding: OutPoint) -> ChannelTransactionParameters {
+    ChannelTransactionParameters {
+        holder_pubkeys: signer.pubkeys().clone(),
+        holder_selected_contest_delay: 0,
+        is_outbound_from_holder: false,
+        counterparty_parameters: Some(CounterpartyChannelTransactionParameters {
+            pubkeys: signer.pubkeys().clone(),
+            selected_contest_delay: 0,
+        }),
+        funding_outpoint: Some(funding),
+        channel_type_features: ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies(),
+    }
+}
+
+/// Encrypt the backup with the `key` derived from the seed, the
+/// encrypted backup starts with the nonce.
+fn encrypt(key: &[u8; 32], nonce: &[u8], backup: &StaticChannelBackup) -> error::Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let mut encrypted = nonce.to_vec();
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(nonce), json::to_vec(backup)?.as_slice())
+        .map_err(|_| error::anyhow!("impossible encrypt the channel backup"))?;
+    encrypted.extend(ciphertext);
+    Ok(encrypted)
+}
+
+/// Decrypt the backup of the node `node_id` on `network`.
+fn decrypt(
+    key: &[u8; 32],
+    encrypted: &[u8],
+    node_id: &str,
+    network: &str,
+) -> error::Result<StaticChannelBackup> {
+    if encrypted.len() < NONCE_LEN {
+        error::bail!("it is not a channel backup");
+    }
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_LEN);
+    let backup = ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| error::anyhow!("impossible decrypt the backup, it belongs to another seed"))?;
+    let backup: StaticChannelBackup = json::from_slice(&backup)?;
+    if backup.node_id != node_id || backup.network != network {
+        error::bail!(
+            "it is a backup of node `{}` on `{}`",
+            backup.node_id,
+            backup.network
+        );
+    }
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use lampo_common::bitcoin::absolute::LockTime;
+    use lampo_common::bitcoin::hashes::Hash;
+    use lampo_common::bitcoin::{OutPoint, ScriptBuf, Transaction, TxIn, TxOut, WPubkeyHash};
+    use lampo_common::keys::LampoKeysManager;
+    use lampo_common::ldk::ln::chan_utils;
+    use lampo_common::ldk::sign::{ChannelSigner, SignerProvider, SpendableOutputDescriptor};
+    use lampo_common::model::response::{ChannelBackup, ChannelKeys, StaticChannelBackup};
+
+    use super::{decrypt, encrypt, NONCE_LEN};
+
+    const KEY: [u8; 32] = [1; 32];
+    const NODE_ID: &str = "02a1633cafcc01ebfb6d78e39f687a1f0995c62fc95f51ead10a02ee0be551b5dc";
+
+    fn backup() -> StaticChannelBackup {
+        StaticChannelBackup {
+            node_id: NODE_ID.to_owned(),
+            network: "regtest".to_owned(),
+            created_at: 1_700_000_000,
+            channels: vec![ChannelBackup {
+                channel_id: "00".repeat(32),
+                peer_id: NODE_ID.to_owned(),
+                peer_addresses: vec!["127.0.0.1:9735".to_owned()],
+                funding_utxo: Some(format!("{}:0", "11".repeat(32))),
+                amount: 100_000,
+            }],
+            channel_keys: vec![ChannelKeys {
+                channel_keys_id: "22".repeat(32),
+                channel_value_satoshis: 100_000,
+            }],
+        }
+    }
+
+    /// A commitment of the peer that spends `funding` and pays
+    /// our balance to `script`.
+    fn commitment(funding: OutPoint, script: ScriptBuf) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: funding,
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: 30_000,
+                    script_pubkey: ScriptBuf::new(),
+                },
+                TxOut {
+                    value: 70_000,
+                    script_pubkey: script,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let encrypted = encrypt(&KEY, &[7; NONCE_LEN], &backup()).unwrap();
+        let decrypted = decrypt(&KEY, &encrypted, NODE_ID, "regtest").unwrap();
+        assert_eq!(decrypted.node_id, NODE_ID);
+        assert_eq!(decrypted.created_at, 1_700_000_000);
+        assert_eq!(decrypted.channels.len(), 1);
+        assert_eq!(decrypted.channels[0].amount, 100_000);
+        assert_eq!(decrypted.channel_keys.len(), 1);
+        assert_eq!(
+            decrypted.channels[0].peer_addresses,
+            vec!["127.0.0.1:9735".to_owned()]
+        );
+    }
+
+    #[test]
+    fn remote_balance() {
+        let keys = LampoKeysManager::new(&[3; 32], 0, 0);
+        let channel_keys_id = keys.generate_channel_keys_id(false, 100_000, 0);
+        assert_eq!(
+            keys.take_channel_keys_ids(),
+            vec![(channel_keys_id, 100_000)]
+        );
+        assert!(keys.take_channel_keys_ids().is_empty());
+
+        let payment_point = keys
+            .derive_channel_signer(100_000, channel_keys_id)
+            .pubkeys()
+            .payment_point;
+        let channel_keys = vec![ChannelKeys {
+            channel_keys_id: hex::encode(channel_keys_id),
+            channel_value_satoshis: 100_000,
+        }];
+        let funding = OutPoint::from_str(&format!("{}:0", "11".repeat(32))).unwrap();
+
+        let script = ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::hash(&payment_point.serialize()));
+        let tx = commitment(funding, script);
+        let descriptors =
+            super::remote_balance(&keys, &tx, funding, 100_000, &channel_keys).unwrap();
+        assert_eq!(descriptors.len(), 1);
+        let SpendableOutputDescriptor::StaticPaymentOutput(descriptor) = &descriptors[0] else {
+            panic!("unexpected descriptor {:?}", descriptors[0]);
+        };
+        assert_eq!(descriptor.outpoint.index, 1);
+        assert_eq!(descriptor.output.value, 70_000);
+        assert_eq!(descriptor.channel_keys_id, channel_keys_id);
+        assert!(descriptor.channel_transaction_parameters.is_none());
+
+        // the keys of another channel value are not used
+        let descriptors =
+            super::remote_balance(&keys, &tx, funding, 200_000, &channel_keys).unwrap();
+        assert!(descriptors.is_empty());
+    }
+
+    #[test]
+    fn remote_balance_with_anchors() {
+        let keys = LampoKeysManager::new(&[3; 32], 0, 0);
+        let channel_keys_id = keys.generate_channel_keys_id(true, 100_000, 0);
+        let payment_point = keys
+            .derive_channel_signer(100_000, channel_keys_id)
+            .pubkeys()
+            .payment_point;
+        let channel_keys = vec![ChannelKeys {
+            channel_keys_id: hex::encode(channel_keys_id),
+            channel_value_satoshis: 100_000,
+        }];
+        let funding = OutPoint::from_str(&format!("{}:0", "11".repeat(32))).unwrap();
+
+        let script = chan_utils::get_to_countersignatory_with_anchors_redeemscript(&payment_point)
+            .to_v0_p2wsh();
+        let tx = commitment(funding, script);
+        let descriptors =
+            super::remote_balance(&keys, &tx, funding, 100_000, &channel_keys).unwrap();
+        assert_eq!(descriptors.len(), 1);
+        let SpendableOutputDescriptor::StaticPaymentOutput(descriptor) = &descriptors[0] else {
+            panic!("unexpected descriptor {:?}", descriptors[0]);
+        };
+        let parameters = descriptor.channel_transaction_parameters.as_ref().unwrap();
+        assert!(parameters.is_populated());
+        assert!(parameters
+            .channel_type_features
+            .supports_anchors_zero_fee_htlc_tx());
+    }
+
+    #[test]
+    fn wrong_seed() {
+        let encrypted = encrypt(&KEY, &[7; NONCE_LEN], &backup()).unwrap();
+        let err = decrypt(&[2; 32], &encrypted, NODE_ID, "regtest").unwrap_err();
+        assert!(err.to_string().contains("another seed"), "{err}");
+    }
+
+    #[test]
+    fn wrong_network() {
+        let encrypted = encrypt(&KEY, &[7; NONCE_LEN], &backup()).unwrap();
+        let err = decrypt(&KEY, &encrypted, NODE_ID, "bitcoin").unwrap_err();
+        assert!(err.to_string().contains("on `regtest`"), "{err}");
+    }
+
+    #[test]
+    fn not_a_backup() {
+        assert!(decrypt(&KEY, &[0; 4], NODE_ID, "regtest").is_err());
+    }
+}
@@ -1,41 +1,64 @@
 //! Channel Manager Implementation
 use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::BufReader;
+use std::net::SocketAddr;
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
 
+use lampo_common::backend::BlockData;
+use lampo_common::bitcoin;
 use lampo_common::bitcoin::absolute::Height;
-use lampo_common::bitcoin::{BlockHash, Transaction};
+use lampo_common::bitcoin::block::Header;
+use lampo_common::bitcoin::blockdata::constants::ChainHash;
+use lampo_common::bitcoin::{Address, BlockHash, Script, ScriptBuf, Transaction};
+use lampo_common::coin_selection;
 use lampo_common::conf::LampoConf;
 use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
 use lampo_common::event::onchain::OnChainEvent;
 use lampo_common::event::Event;
 use lampo_common::handler::Handler;
 use lampo_common::keys::LampoKeysManager;
+use lampo_common::ldk::chain::chaininterface::ConfirmationTarget;
 use lampo_common::ldk::chain::chainmonitor::ChainMonitor;
 use lampo_common::ldk::chain::channelmonitor::ChannelMonitor;
+use lampo_common::ldk::chain::transaction::OutPoint;
 use lampo_common::ldk::chain::{BestBlock, Confirm, Filter, Watch};
 use lampo_common::ldk::ln::channelmanager::{
-    ChainParameters, ChannelManager, ChannelManagerReadArgs,
+    ChainParameters, ChannelDetails, ChannelManager, ChannelManagerReadArgs, InterceptId,
 };
+use lampo_common::ldk::ln::features::ChannelTypeFeatures;
+use lampo_common::ldk::ln::msgs::{SocketAddress, UnsignedChannelUpdate};
 use lampo_common::ldk::persister::fs_store::FilesystemStore;
-use lampo_common::ldk::routing::gossip::{NetworkGraph, ReadOnlyNetworkGraph};
-use lampo_common::ldk::routing::router::DefaultRouter;
+use lampo_common::ldk::rapid_gossip_sync::RapidGossipSync;
+use lampo_common::ldk::routing::gossip::{
+    ChannelInfo, NetworkGraph, NodeId as GraphNodeId, ReadOnlyNetworkGraph,
+};
 use lampo_common::ldk::routing::scoring::{
-    ProbabilisticScorer, ProbabilisticScoringDecayParameters, ProbabilisticScoringFeeParameters,
+    ProbabilisticScorer, ProbabilisticScoringDecayParameters,
 };
 use lampo_common::ldk::sign::InMemorySigner;
-use lampo_common::ldk::util::persist::read_channel_monitors;
-use lampo_common::ldk::util::ser::ReadableArgs;
+use lampo_common::ldk::util::config::{ChannelConfigUpdate, MaxDustHTLCExposure};
+use lampo_common::ldk::util::persist::{
+    read_channel_monitors, KVStore, CHANNEL_MANAGER_PERSISTENCE_KEY,
+    CHANNEL_MANAGER_PERSISTENCE_PRIMARY_NAMESPACE, CHANNEL_MANAGER_PERSISTENCE_SECONDARY_NAMESPACE,
+    CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE, CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use lampo_common::ldk::util::ser::{ReadableArgs, Writeable};
 use lampo_common::model::request;
 use lampo_common::model::response::{self, Channel, Channels};
+use lampo_common::types::{parse_channel_id, ChannelId, ChannelState, NodeId};
 
+use super::router::LampoRouter;
 use crate::actions::handler::LampoHandler;
 use crate::chain::{LampoChainManager, WalletManager};
 use crate::ln::events::{ChangeStateChannelEvent, ChannelEvents};
-use crate::persistence::LampoPersistence;
+use crate::persistence::{JsonStore, LampoPersistence};
 use crate::utils::logger::LampoLogger;
 
 pub type LampoChainMonitor = ChainMonitor<
@@ -63,23 +86,44 @@ type LampoChannel =
 
 pub type LampoGraph = NetworkGraph<Arc<LampoLogger>>;
 pub type LampoScorer = ProbabilisticScorer<Arc<LampoGraph>, Arc<LampoLogger>>;
-pub type LampoRouter = DefaultRouter<
-    Arc<LampoGraph>,
-    Arc<LampoLogger>,
-    Arc<LampoKeysManager>,
-    Arc<Mutex<LampoScorer>>,
-    ProbabilisticScoringFeeParameters,
-    LampoScorer,
->;
+
+/// Namespace of the forwarding policies set with `setchannel`.
+const CHANNEL_CONFIG_NAMESPACE: &str = "channel_config";
+/// Namespace of the last state seen for each channel.
+const CHANNEL_STATES_NAMESPACE: &str = "channel_states";
+/// Namespace of the channels that we force closed.
+const FORCE_CLOSES_NAMESPACE: &str = "force_closes";
+/// Namespace of the timestamp of the last rapid gossip sync.
+const RGS_NAMESPACE: &str = "rgs";
+/// Number of blocks between two prunes of the graph in compact mode.
+const COMPACT_GRAPH_INTERVAL: u32 = 144;
+/// User channel id of the channels funded by an external wallet.
+pub(crate) const EXTERNAL_FUNDING_CHANNEL: u128 = 1;
+/// User channel id of the channels funded by a batch transaction.
+pub(crate) const BATCH_FUNDING_CHANNEL: u128 = 2;
 
 pub struct LampoChannelManager {
     monitor: Option<Arc<LampoChainMonitor>>,
     wallet_manager: Arc<dyn WalletManager>,
     persister: Arc<LampoPersistence>,
     graph: Option<Arc<LampoGraph>>,
+    /// Monitors read from disk during the restart that are not
+    /// yet watched by the chain monitor, so we parse them once.
+    monitor_cache: Mutex<Option<Vec<ChannelMonitor<InMemorySigner>>>>,
+    /// True while the persisted graph is loaded in background.
+    graph_warming: Arc<AtomicBool>,
     score: Option<Arc<Mutex<LampoScorer>>>,
     handler: RefCell<Option<Arc<LampoHandler>>>,
     router: Option<Arc<LampoRouter>>,
+    /// Channels waiting for the funding transaction of the
+    /// external wallet, by temporary channel id.
+    external_fundings: Mutex<BTreeMap<String, response::ExternalFunding>>,
+    /// Fee rates of the funding transactions chosen by the
+    /// user, by temporary channel id.
+    funding_fee_rates: Mutex<HashMap<ChannelId, u32>>,
+    /// Utxos of the funding transactions chosen by the
+    /// user, by temporary channel id.
+    funding_utxos: Mutex<HashMap<ChannelId, Vec<bitcoin::OutPoint>>>,
 
     pub(crate) onchain: Arc<LampoChainManager>,
     pub(crate) conf: LampoConf,
@@ -113,8 +157,13 @@ impl LampoChannelManager {
             persister,
             handler: RefCell::new(None),
             graph: None,
+            monitor_cache: Mutex::new(None),
+            graph_warming: Arc::new(AtomicBool::new(false)),
             score: None,
             router: None,
+            external_fundings: Mutex::new(BTreeMap::new()),
+            funding_fee_rates: Mutex::new(HashMap::new()),
+            funding_utxos: Mutex::new(HashMap::new()),
         }
     }
 
@@ -130,6 +179,9 @@ impl LampoChannelManager {
         if self.is_restarting().unwrap() {
             self.resume_channels().unwrap();
             self.load_channel_monitors(true).unwrap();
+            if let Err(err) = self.restore_channel_configs() {
+                log::error!(target: "manager", "impossible restore the channel policies: {err}");
+            }
         }
         std::thread::spawn(move || {
             log::info!(target: "manager", "listening on chain event on the channel manager");
@@ -142,10 +194,15 @@ impl LampoChannelManager {
                 match event {
                     OnChainEvent::NewBestBlock((hash, height)) => {
                         log::info!(target: "channel_manager", "new best block with hash `{}` at height `{height}`", hash.block_hash());
-                        self.chain_monitor()
-                            .best_block_updated(&hash, height.to_consensus_u32());
-                        self.manager()
-                            .best_block_updated(&hash, height.to_consensus_u32());
+                        self.connect_best_block(&hash, height.to_consensus_u32(), &[]);
+                        if self.conf.compact_graph
+                            && height.to_consensus_u32() % COMPACT_GRAPH_INTERVAL == 0
+                        {
+                            self.prune_graph();
+                        }
+                        if let Err(err) = self.prune_force_closes() {
+                            log::error!(target: "channel_manager", "impossible prune the force closed channels: {err}");
+                        }
                     }
                     OnChainEvent::ConfirmedTransaction((tx, idx, header, height)) => {
                         log::info!(target: "channel_manager", "confirmed transaction with txid `{}` at height `{height}`", tx.txid());
@@ -168,6 +225,12 @@ impl LampoChannelManager {
                     OnChainEvent::DiscardedTransaction(txid) => {
                         log::warn!(target: "channel_manager", "transaction with txid `{txid}` discarded");
                     }
+                    OnChainEvent::BackendSwitched(backend) => {
+                        log::warn!(target: "channel_manager", "chain backend switched to `{backend}`, resyncing the channels");
+                        if let Err(err) = self.resync_backend() {
+                            log::error!(target: "channel_manager", "impossible resync with the backend `{backend}`: {err}");
+                        }
+                    }
                     _ => continue,
                 }
             }
@@ -197,26 +260,732 @@ impl LampoChannelManager {
             .manager()
             .list_channels()
             .into_iter()
-            .map(|channel| Channel {
-                channel_id: channel.channel_id.to_string(),
-                short_channel_id: channel.short_channel_id,
-                peer_id: channel.counterparty.node_id.to_string(),
-                peer_alias: None,
-                ready: channel.is_channel_ready,
-                amount: channel.channel_value_satoshis,
-                amount_msat: channel.next_outbound_htlc_limit_msat,
-                public: channel.is_public,
-                available_balance_for_send_msat: channel.outbound_capacity_msat,
-                available_balance_for_recv_msat: channel.inbound_capacity_msat,
+            .map(|channel| {
+                let (incoming, outgoing) = Self::pending_htlcs(&channel);
+                let state = match self.channel_state(&channel.channel_id) {
+                    Some(state) => state,
+                    None if channel.is_channel_ready => ChannelState::Ready,
+                    None if channel.funding_txo.is_some() => ChannelState::Pending,
+                    None => ChannelState::Opening,
+                };
+                Channel {
+                    channel_id: channel.channel_id.to_string(),
+                    short_channel_id: channel.short_channel_id,
+                    peer_id: channel.counterparty.node_id.to_string(),
+                    peer_alias: None,
+                    ready: channel.is_channel_ready,
+                    state,
+                    amount: channel.channel_value_satoshis,
+                    amount_msat: channel.next_outbound_htlc_limit_msat,
+                    public: channel.is_public,
+                    available_balance_for_send_msat: channel.next_outbound_htlc_limit_msat,
+                    available_balance_for_recv_msat: channel.inbound_capacity_msat,
+                    pending_incoming_htlc_sat: incoming,
+                    pending_outgoing_htlc_sat: outgoing,
+                    unsettled_balance_sat: incoming + outgoing,
+                    our_reserve_sat: channel.unspendable_punishment_reserve,
+                    counterparty_reserve_sat: channel.counterparty.unspendable_punishment_reserve,
+                }
             })
             .collect();
         Channels { channels }
     }
 
+    /// The last state of the channel stored by `change_state_channel`.
+    pub fn channel_state(&self, channel_id: &ChannelId) -> Option<ChannelState> {
+        self.persister
+            .read_json::<response::ChannelStateRecord>(
+                CHANNEL_STATES_NAMESPACE,
+                &channel_id.to_string(),
+            )
+            .ok()
+            .flatten()
+            .map(|record| record.state)
+    }
+
+    /// The amount of the incoming and outgoing HTLCs in flight on
+    /// the channel, in satoshis.
+    ///
+    /// The monitor reports the HTLCs only after that a commitment is
+    /// confirmed on chain, so for a live channel they are taken from
+    /// the channel details.
+    fn pending_htlcs(channel: &ChannelDetails) -> (u64, u64) {
+        let incoming = channel
+            .pending_inbound_htlcs
+            .iter()
+            .map(|htlc| htlc.amount_msat)
+            .sum::<u64>();
+        let outgoing = channel
+            .pending_outbound_htlcs
+            .iter()
+            .map(|htlc| htlc.amount_msat)
+            .sum::<u64>();
+        (incoming / 1000, outgoing / 1000)
+    }
+
+    /// Accept or reject the channel proposed by the peer, following
+    /// the acceptance policy and the channel types allowed with it.
+    pub fn accept_inbound_channel(
+        &self,
+        temporary_channel_id: &ChannelId,
+        counterparty_node_id: &NodeId,
+        funding_satoshis: u64,
+        channel_type: &ChannelTypeFeatures,
+    ) -> error::Result<()> {
+        let manager = self.manager();
+        let node_id = counterparty_node_id.to_string();
+        let channels = manager
+            .list_channels_with_counterparty(counterparty_node_id)
+            .len();
+        let accepted = self
+            .conf
+            .check_inbound_channel(&node_id, funding_satoshis, channels)
+            .and_then(|_| match self.conf.channel_types(&node_id)? {
+                Some(types) => types.accept(channel_type),
+                None if channel_type.requires_zero_conf() => {
+                    Err(error::anyhow!("zero-conf channels are not accepted"))
+                }
+                None => Ok(false),
+            });
+        let opening = accepted.is_ok();
+        match accepted {
+            Ok(true) => manager.accept_inbound_channel_from_trusted_peer_0conf(
+                temporary_channel_id,
+                counterparty_node_id,
+                0,
+            ),
+            Ok(false) => {
+                manager.accept_inbound_channel(temporary_channel_id, counterparty_node_id, 0)
+            }
+            Err(err) => {
+                log::info!("rejecting the channel `{temporary_channel_id}` from `{counterparty_node_id}`: {err}");
+                manager.force_close_without_broadcasting_txn(
+                    temporary_channel_id,
+                    counterparty_node_id,
+                )
+            }
+        }
+        .map_err(|err| error::anyhow!("{:?}", err))?;
+        if opening {
+            self.change_state_channel(ChangeStateChannelEvent {
+                channel_id: *temporary_channel_id,
+                former_temporary_channel_id: None,
+                node_id: *counterparty_node_id,
+                channel_type: Some(channel_type.clone()),
+                state: ChannelState::Opening,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The peer accepted a channel funded by an external wallet, so
+    /// we keep the funding output until the transaction is provided.
+    pub fn external_funding_ready(
+        &self,
+        temporary_channel_id: &ChannelId,
+        counterparty_node_id: &NodeId,
+        channel_value_satoshis: u64,
+        output_script: &Script,
+    ) -> error::Result<response::ExternalFunding> {
+        let address = Address::from_script(output_script, self.conf.network)
+            .map_err(|err| error::anyhow!("invalid funding script: {err}"))?;
+        let funding = response::ExternalFunding {
+            temporary_channel_id: temporary_channel_id.to_string(),
+            counterparty_node_id: counterparty_node_id.to_string(),
+            channel_value_satoshis,
+            output_script: hex::encode(output_script.as_bytes()),
+            address: address.to_string(),
+        };
+        self.external_fundings
+            .lock()
+            .unwrap()
+            .insert(funding.temporary_channel_id.clone(), funding.clone());
+        Ok(funding)
+    }
+
+    /// Give to ldk the funding transaction signed by the external wallet,
+    /// ldk broadcasts it when the peer sends the signature of our
+    /// commitment, so the external wallet can broadcast it too.
+    pub fn complete_external_funding(
+        &self,
+        request: request::CompleteFunding,
+    ) -> error::Result<Transaction> {
+        let Some(funding) = self
+            .external_fundings
+            .lock()
+            .unwrap()
+            .get(&request.temporary_channel_id)
+            .cloned()
+        else {
+            error::bail!(
+                "no channel `{}` is waiting for an external funding",
+                request.temporary_channel_id
+            );
+        };
+        let tx: Transaction =
+            lampo_common::bitcoin::consensus::deserialize(&hex::decode(&request.tx)?)?;
+        let output_script = hex::decode(&funding.output_script)?;
+        if !tx.output.iter().any(|output| {
+            output.script_pubkey.as_bytes() == output_script.as_slice()
+                && output.value == funding.channel_value_satoshis
+        }) {
+            error::bail!(
+                "the transaction `{}` does not pay `{}` sats to `{}`",
+                tx.txid(),
+                funding.channel_value_satoshis,
+                funding.address
+            );
+        }
+        let temporary_channel_id = parse_channel_id(&funding.temporary_channel_id)?;
+        let counterparty_node_id = NodeId::from_str(&funding.counterparty_node_id)?;
+        self.manager()
+            .funding_transaction_generated(&temporary_channel_id, &counterparty_node_id, tx.clone())
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        self.external_fundings
+            .lock()
+            .unwrap()
+            .remove(&request.temporary_channel_id);
+        self.handler()
+            .emit(Event::Lightning(LightningEvent::FundingChannelEnd {
+                counterparty_node_id,
+                temporary_channel_id,
+                channel_value_satoshis: funding.channel_value_satoshis,
+                funding_transaction: tx.clone(),
+            }));
+        Ok(tx)
+    }
+
+    /// The fee rate of the funding transaction of a channel, the
+    /// one chosen by the user or the estimated one.
+    pub fn funding_fee_rate(&self, temporary_channel_id: &ChannelId) -> error::Result<u32> {
+        let fee_rate = self
+            .funding_fee_rates
+            .lock()
+            .unwrap()
+            .remove(temporary_channel_id);
+        self.fee_rate_or_estimate(fee_rate)
+    }
+
+    /// The fee rate chosen by the user, or the estimated one.
+    fn fee_rate_or_estimate(&self, fee_rate: Option<u32>) -> error::Result<u32> {
+        match fee_rate {
+            Some(fee_rate) => Ok(fee_rate),
+            None => self
+                .onchain
+                .fees()
+                .estimate(ConfirmationTarget::NonAnchorChannelFee),
+        }
+    }
+
+    /// The utxos that fund a channel, when they are chosen by the user.
+    pub fn funding_utxos(
+        &self,
+        temporary_channel_id: &ChannelId,
+    ) -> Option<Vec<bitcoin::OutPoint>> {
+        self.funding_utxos
+            .lock()
+            .unwrap()
+            .remove(temporary_channel_id)
+    }
+
+    /// Forget the funding options of a channel that will never
+    /// be funded, e.g: the peer refused it.
+    pub fn forget_funding(&self, temporary_channel_id: &ChannelId) {
+        self.funding_fee_rates
+            .lock()
+            .unwrap()
+            .remove(temporary_channel_id);
+        self.funding_utxos
+            .lock()
+            .unwrap()
+            .remove(temporary_channel_id);
+    }
+
+    /// Check the utxos chosen by the user to fund `channels` channels
+    /// of `amount_sat` in total, with the fee at `fee_rate`.
+    fn check_funding_utxos(
+        &self,
+        utxos: &[bitcoin::OutPoint],
+        amount_sat: u64,
+        channels: usize,
+        fee_rate: u32,
+    ) -> error::Result<()> {
+        let value = self.onchain.funder().check_utxos(utxos)?;
+        // the funding outputs pay to a P2WSH script
+        let outputs_weight = channels as u64 * coin_selection::output_weight(34);
+        let fee = coin_selection::transaction_fee(utxos.len(), outputs_weight, fee_rate);
+        if value < amount_sat + fee {
+            error::bail!(
+                "the utxos have {value} sats, that is not enough for {amount_sat} sats plus {fee} sats of fee"
+            );
+        }
+        Ok(())
+    }
+
+    /// Open all the channels and fund them with a single transaction
+    /// of the wallet, the peers must be all different.
+    pub fn open_channels(
+        &self,
+        request: request::OpenChannels,
+    ) -> error::Result<response::OpenChannels> {
+        if request.channels.is_empty() {
+            error::bail!("no channels to open");
+        }
+        let mut pending = BTreeMap::new();
+        // the channels share the transaction, so it pays
+        // the highest fee rate between the chosen ones.
+        let mut fee_rate = None;
+        // the utxos chosen by the channels fund the whole transaction
+        let mut utxos: Option<Vec<bitcoin::OutPoint>> = None;
+        for channel in &request.channels {
+            channel.push_msat()?;
+            if let Some(channel_utxos) = channel.utxos()? {
+                let utxos = utxos.get_or_insert_with(Vec::new);
+                for utxo in channel_utxos {
+                    if utxos.contains(&utxo) {
+                        error::bail!("the utxo `{utxo}` is used by more than one channel");
+                    }
+                    utxos.push(utxo);
+                }
+            }
+            if let Some(channel_fee_rate) = channel.feerate()? {
+                let channel_fee_rate = self.onchain.fees().fee_rate(channel_fee_rate)?;
+                fee_rate = fee_rate.max(Some(channel_fee_rate));
+            }
+            if pending.insert(channel.node_id()?, channel).is_some() {
+                error::bail!("more than one channel with `{}`", channel.node_id);
+            }
+        }
+        let fee_rate = self.fee_rate_or_estimate(fee_rate)?;
+        if let Some(utxos) = &utxos {
+            let amount_sat = request.channels.iter().map(|channel| channel.amount).sum();
+            self.check_funding_utxos(utxos, amount_sat, request.channels.len(), fee_rate)?;
+        }
+        // the channels created so far, they are closed if the batch fails,
+        // so ldk does not keep them pending.
+        let mut created = Vec::new();
+        let result = self.fund_batch(&pending, fee_rate, utxos.as_deref(), &mut created);
+        if result.is_err() {
+            self.abort_batch(&created);
+        }
+        result
+    }
+
+    /// Create the channels of the batch, wait that all the peers accept
+    /// them and fund them with a single transaction.
+    fn fund_batch(
+        &self,
+        pending: &BTreeMap<NodeId, &request::OpenChannel>,
+        fee_rate: u32,
+        utxos: Option<&[bitcoin::OutPoint]>,
+        created: &mut Vec<(ChannelId, NodeId)>,
+    ) -> error::Result<response::OpenChannels> {
+        let events = self.handler().events();
+        for (node_id, channel) in pending {
+            let conf = self.conf.channel_config(&node_id.to_string())?;
+            let temporary_channel_id = ChannelId::temporary_from_entropy_source(
+                &self.wallet_manager.ldk_keys().keys_manager,
+            );
+            self.manager()
+                .create_channel(
+                    *node_id,
+                    channel.amount,
+                    channel.push_msat()?,
+                    BATCH_FUNDING_CHANNEL,
+                    Some(temporary_channel_id),
+                    Some(conf),
+                )
+                .map_err(|err| error::anyhow!("{:?}", err))?;
+            created.push((temporary_channel_id, *node_id));
+        }
+
+        // Wait that all the peers accept the channel, so we know the funding scripts
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(30);
+        let mut accepted = Vec::new();
+        while accepted.len() < created.len() {
+            let event = events.recv_deadline(deadline)?;
+            match event {
+                Event::Lightning(LightningEvent::FundingChannelStart {
+                    counterparty_node_id,
+                    temporary_channel_id,
+                    ..
+                }) if created.contains(&(temporary_channel_id, counterparty_node_id)) => {
+                    accepted.push(temporary_channel_id);
+                }
+                // a peer that refuses the channel fails the whole batch
+                Event::Lightning(LightningEvent::CloseChannelEvent {
+                    channel_id,
+                    message,
+                    ..
+                }) if created.iter().any(|(id, _)| id.to_string() == channel_id) => {
+                    error::bail!("the channel `{channel_id}` was closed: {message}");
+                }
+                _ => continue,
+            }
+        }
+        let fundings = &*created;
+        let outputs = fundings
+            .iter()
+            .map(|(temporary_channel_id, _)| {
+                let funding = self
+                    .external_fundings
+                    .lock()
+                    .unwrap()
+                    .remove(&temporary_channel_id.to_string())
+                    .ok_or(error::anyhow!(
+                        "funding of `{temporary_channel_id}` not found"
+                    ))?;
+                let script = ScriptBuf::from_bytes(hex::decode(&funding.output_script)?);
+                Ok((script, funding.channel_value_satoshis))
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+        let funder = self.onchain.funder();
+        let tx = funder.fund(&outputs, fee_rate, utxos)?;
+        let channels = fundings
+            .iter()
+            .map(|(temporary_channel_id, node_id)| (temporary_channel_id, node_id))
+            .collect::<Vec<_>>();
+        if let Err(err) = self
+            .manager()
+            .batch_funding_transaction_generated(&channels, tx.clone())
+        {
+            funder.release(&tx.txid())?;
+            error::bail!("{:?}", err);
+        }
+        for ((temporary_channel_id, counterparty_node_id), (_, amount)) in
+            fundings.iter().zip(outputs.iter())
+        {
+            self.handler()
+                .emit(Event::Lightning(LightningEvent::FundingChannelEnd {
+                    counterparty_node_id: *counterparty_node_id,
+                    temporary_channel_id: *temporary_channel_id,
+                    channel_value_satoshis: *amount,
+                    funding_transaction: tx.clone(),
+                }));
+        }
+        Ok(response::OpenChannels {
+            txid: tx.txid(),
+            channels: fundings
+                .iter()
+                .zip(outputs.iter())
+                .map(
+                    |((temporary_channel_id, node_id), (_, amount))| response::BatchChannel {
+                        node_id: node_id.to_string(),
+                        temporary_channel_id: temporary_channel_id.to_string(),
+                        amount: *amount,
+                    },
+                )
+                .collect(),
+        })
+    }
+
+    /// Close the `channels` of a batch that failed, and forget the
+    /// funding scripts, so they can not be funded from outside.
+    fn abort_batch(&self, channels: &[(ChannelId, NodeId)]) {
+        for (temporary_channel_id, node_id) in channels {
+            let _ = self
+                .manager()
+                .force_close_without_broadcasting_txn(temporary_channel_id, node_id);
+            self.external_fundings
+                .lock()
+                .unwrap()
+                .remove(&temporary_channel_id.to_string());
+        }
+    }
+
+    /// Update the forwarding policy of the channel, the update is
+    /// stored and applied again at every restart.
+    pub fn set_channel_config(
+        &self,
+        request: &request::SetChannel,
+    ) -> error::Result<response::ChannelConfig> {
+        let config = self.apply_channel_config(request)?;
+        // merge with the previous update, so all the fields set survive the restart
+        let previous = self
+            .persister
+            .read_json::<request::SetChannel>(CHANNEL_CONFIG_NAMESPACE, &config.channel_id)?;
+        let update = request::SetChannel {
+            channel_id: config.channel_id.clone(),
+            fee_base_msat: request
+                .fee_base_msat
+                .or(previous.as_ref().and_then(|update| update.fee_base_msat)),
+            fee_ppm: request
+                .fee_ppm
+                .or(previous.as_ref().and_then(|update| update.fee_ppm)),
+            cltv_expiry_delta: request.cltv_expiry_delta.or(previous
+                .as_ref()
+                .and_then(|update| update.cltv_expiry_delta)),
+            max_dust_htlc_exposure_msat: request.max_dust_htlc_exposure_msat.or(previous
+                .as_ref()
+                .and_then(|update| update.max_dust_htlc_exposure_msat)),
+        };
+        self.persister
+            .write_json(CHANNEL_CONFIG_NAMESPACE, &update.channel_id, &update)?;
+        Ok(config)
+    }
+
+    fn apply_channel_config(
+        &self,
+        request: &request::SetChannel,
+    ) -> error::Result<response::ChannelConfig> {
+        let manager = self.manager();
+        let Some(channel) = manager.list_channels().into_iter().find(|channel| {
+            channel.channel_id.to_string() == request.channel_id
+                || channel.short_channel_id.map(|scid| scid.to_string())
+                    == Some(request.channel_id.clone())
+        }) else {
+            error::bail!("channel `{}` not found", request.channel_id);
+        };
+        let update = ChannelConfigUpdate {
+            forwarding_fee_proportional_millionths: request.fee_ppm,
+            forwarding_fee_base_msat: request.fee_base_msat,
+            cltv_expiry_delta: request.cltv_expiry_delta,
+            max_dust_htlc_exposure_msat: request
+                .max_dust_htlc_exposure_msat
+                .map(MaxDustHTLCExposure::FixedLimitMsat),
+            force_close_avoidance_max_fee_satoshis: None,
+        };
+        manager
+            .update_partial_channel_config(
+                &channel.counterparty.node_id,
+                &[channel.channel_id],
+                &update,
+            )
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        let Some(config) = manager
+            .list_channels()
+            .into_iter()
+            .find(|details| details.channel_id == channel.channel_id)
+            .and_then(|details| details.config)
+        else {
+            error::bail!("channel `{}` not found", request.channel_id);
+        };
+        Ok(response::ChannelConfig {
+            channel_id: channel.channel_id.to_string(),
+            short_channel_id: channel.short_channel_id,
+            peer_id: channel.counterparty.node_id.to_string(),
+            fee_base_msat: config.forwarding_fee_base_msat,
+            fee_ppm: config.forwarding_fee_proportional_millionths,
+            cltv_expiry_delta: config.cltv_expiry_delta,
+        })
+    }
+
+    /// Apply again the forwarding policies set with `setchannel`, the
+    /// policies of the channels that no longer exist are removed.
+    fn restore_channel_configs(&self) -> error::Result<()> {
+        let updates = self
+            .persister
+            .list_json::<request::SetChannel>(CHANNEL_CONFIG_NAMESPACE)?;
+        for update in updates {
+            if let Err(err) = self.apply_channel_config(&update) {
+                log::info!(target: "manager", "dropping the policy of `{}`: {err}", update.channel_id);
+                self.persister
+                    .remove_json(CHANNEL_CONFIG_NAMESPACE, &update.channel_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A fake short channel id, the HTLCs sent to it are
+    /// held until they are forwarded or failed.
+    pub fn intercept_scid(&self) -> error::Result<u64> {
+        if !self.conf.accept_intercept_htlcs {
+            error::bail!("the HTLC interception is disabled, enable `accept-intercept-htlcs`");
+        }
+        Ok(self.manager().get_intercept_scid())
+    }
+
+    /// Forward an intercepted HTLC, the channel must be usable
+    /// but it does not need to be announced.
+    pub fn forward_intercepted_htlc(
+        &self,
+        request: &request::ForwardIntercepted,
+    ) -> error::Result<()> {
+        let intercept_id = intercept_id(&request.intercept_id)?;
+        let manager = self.manager();
+        let Some(channel) = manager.list_channels().into_iter().find(|channel| {
+            channel.channel_id.to_string() == request.channel_id
+                || channel.short_channel_id.map(|scid| scid.to_string())
+                    == Some(request.channel_id.clone())
+        }) else {
+            error::bail!("channel `{}` not found", request.channel_id);
+        };
+        manager
+            .forward_intercepted_htlc(
+                intercept_id,
+                &channel.channel_id,
+                channel.counterparty.node_id,
+                request.amount_msat,
+            )
+            .map_err(|err| error::anyhow!("{:?}", err))
+    }
+
+    pub fn fail_intercepted_htlc(&self, request: &request::FailIntercepted) -> error::Result<()> {
+        let intercept_id = intercept_id(&request.intercept_id)?;
+        self.manager()
+            .fail_intercepted_htlc(intercept_id)
+            .map_err(|err| error::anyhow!("{:?}", err))
+    }
+
+    /// Report what we would get back on chain by closing the channel,
+    /// the channel is not touched.
+    pub fn simulate_close(
+        &self,
+        channel: &request::CloseChannel,
+    ) -> error::Result<response::CloseSimulation> {
+        let channel_id = channel.channel_id()?;
+        let Some(details) = self
+            .manager()
+            .list_channels()
+            .into_iter()
+            .find(|details| details.channel_id == channel_id)
+        else {
+            error::bail!("channel `{channel_id}` not found");
+        };
+        // a channel without a funding transaction has nothing on chain
+        let to_self_sat = details
+            .funding_txo
+            .map(|funding_txo| self.claimable_sat(funding_txo))
+            .unwrap_or_default();
+        Ok(response::CloseSimulation {
+            channel_id: channel_id.to_string(),
+            peer_id: details.counterparty.node_id.to_string(),
+            funding_utxo: details
+                .funding_txo
+                .map(|txo| format!("{}:{}", txo.txid, txo.index)),
+            channel_value_sat: details.channel_value_satoshis,
+            to_self_sat,
+        })
+    }
+
+    /// Close the channel broadcasting our latest commitment, the close
+    /// is stored so the user can follow the funds until they are swept.
+    pub fn force_close_channel(
+        &self,
+        channel: &request::CloseChannel,
+    ) -> error::Result<response::ForceClosedChannel> {
+        let channel_id = channel.channel_id()?;
+        let node_id = channel.counterpart_node_id()?;
+        let Some(details) = self
+            .manager()
+            .list_channels()
+            .into_iter()
+            .find(|details| details.channel_id == channel_id)
+        else {
+            error::bail!("channel `{channel_id}` not found");
+        };
+        let events = self.handler().events();
+        self.manager()
+            .force_close_broadcasting_latest_txn(&channel_id, &node_id)
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        // the monitor broadcasts the commitment while the channel is closed
+        let commitment_txid = details.funding_txo.and_then(|funding_txo| {
+            let funding = funding_txo.into_bitcoin_outpoint();
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+            loop {
+                let event = events.recv_deadline(deadline).ok()?;
+                if let Event::OnChain(OnChainEvent::SendRawTransaction(tx)) = event {
+                    if tx
+                        .input
+                        .iter()
+                        .any(|input| input.previous_output == funding)
+                    {
+                        return Some(tx.txid().to_string());
+                    }
+                }
+            }
+        });
+        let closed_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let closed = response::ForceClosedChannel {
+            channel_id: channel_id.to_string(),
+            peer_id: node_id.to_string(),
+            funding_utxo: details
+                .funding_txo
+                .map(|txo| format!("{}:{}", txo.txid, txo.index)),
+            commitment_txid,
+            closed_at,
+            claimable_sat: details
+                .funding_txo
+                .map(|txo| self.claimable_sat(txo))
+                .unwrap_or_default(),
+        };
+        self.persister
+            .write_json(FORCE_CLOSES_NAMESPACE, &closed.channel_id, &closed)?;
+        Ok(closed)
+    }
+
+    /// The channels that we force closed, with the funds still to sweep.
+    pub fn list_force_closes(&self) -> error::Result<Vec<response::ForceClosedChannel>> {
+        let mut closes = Vec::new();
+        for mut closed in self
+            .persister
+            .list_json::<response::ForceClosedChannel>(FORCE_CLOSES_NAMESPACE)?
+        {
+            closed.claimable_sat = self.force_close_claimable_sat(&closed)?;
+            // the close is removed at the next block
+            if closed.claimable_sat == 0 {
+                continue;
+            }
+            closes.push(closed);
+        }
+        closes.sort_by_key(|closed| closed.closed_at);
+        Ok(closes)
+    }
+
+    /// Forget the force closes where the monitor has nothing left
+    /// to claim, all the funds are back inside the wallet.
+    fn prune_force_closes(&self) -> error::Result<()> {
+        for closed in self
+            .persister
+            .list_json::<response::ForceClosedChannel>(FORCE_CLOSES_NAMESPACE)?
+        {
+            if self.force_close_claimable_sat(&closed)? > 0 {
+                continue;
+            }
+            log::info!(target: "channel_manager", "the funds of the force closed channel `{}` are swept", closed.channel_id);
+            self.persister
+                .remove_json(FORCE_CLOSES_NAMESPACE, &closed.channel_id)?;
+        }
+        Ok(())
+    }
+
+    fn force_close_claimable_sat(
+        &self,
+        closed: &response::ForceClosedChannel,
+    ) -> error::Result<u64> {
+        let Some(funding_utxo) = &closed.funding_utxo else {
+            return Ok(closed.claimable_sat);
+        };
+        let funding = lampo_common::bitcoin::OutPoint::from_str(funding_utxo)?;
+        Ok(self.claimable_sat(OutPoint {
+            txid: funding.txid,
+            index: funding.vout as u16,
+        }))
+    }
+
+    /// Funds of the channel that the monitor still has to claim on chain.
+    fn claimable_sat(&self, funding_txo: OutPoint) -> u64 {
+        self.chain_monitor()
+            .get_monitor(funding_txo)
+            .map(|monitor| {
+                monitor
+                    .get_claimable_balances()
+                    .iter()
+                    .map(|balance| balance.claimable_amount_satoshis())
+                    .sum::<u64>()
+            })
+            .unwrap_or_default()
+    }
+
     pub fn load_channel_monitors(&self, watch: bool) -> error::Result<()> {
-        let keys = self.wallet_manager.ldk_keys().inner();
-        let mut monitors = read_channel_monitors(self.persister.clone(), keys.clone(), keys)?;
-        for (_, chan_mon) in monitors.drain(..) {
+        let cached = self.monitor_cache.lock().unwrap().take();
+        let monitors = match cached {
+            Some(monitors) => monitors,
+            None => self.get_channel_monitors()?,
+        };
+        for chan_mon in monitors {
             chan_mon.load_outputs_to_watch(&self.onchain, &self.logger);
             if watch {
                 let monitor = self
@@ -245,46 +1014,129 @@ impl LampoChannelManager {
         self.graph.clone().unwrap()
     }
 
+    pub fn is_graph_warming(&self) -> bool {
+        self.graph_warming.load(Ordering::SeqCst)
+    }
+
+    /// Remove from the graph the channels that are disabled in all
+    /// the directions that we know, used by the compact graph mode.
+    pub fn prune_graph(&self) {
+        let graph = self.graph();
+        let disabled = graph
+            .read_only()
+            .channels()
+            .unordered_iter()
+            .filter(|(_, channel)| channel.one_to_two.is_some() || channel.two_to_one.is_some())
+            .filter(|(_, channel)| !is_routable(channel))
+            .map(|(scid, _)| *scid)
+            .collect::<Vec<_>>();
+        log::debug!(target: "channel_manager", "pruning `{}` disabled channels from the graph", disabled.len());
+        for scid in disabled {
+            graph.channel_failed_permanent(scid);
+        }
+    }
+
+    /// Find a node inside the network graph by alias or node id prefix,
+    /// the lookup fails if more than one node matches.
+    pub fn lookup_node(&self, query: &str) -> error::Result<NodeId> {
+        let graph = self.graph();
+        let graph = graph.read_only();
+        let prefix = query.to_lowercase();
+        let matches = graph
+            .nodes()
+            .unordered_iter()
+            .filter(|(node_id, info)| {
+                let alias = info
+                    .announcement_info
+                    .as_ref()
+                    .map(|announcement| announcement.alias.to_string());
+                node_id.to_string().starts_with(&prefix) || alias.as_deref() == Some(query)
+            })
+            .filter_map(|(node_id, _)| node_id.as_pubkey().ok())
+            .collect::<Vec<_>>();
+        match matches.as_slice() {
+            [node_id] => Ok(*node_id),
+            [] => error::bail!("no node matches `{query}` inside the network graph"),
+            _ => error::bail!(
+                "`{query}` is ambiguous, `{}` nodes match it inside the network graph",
+                matches.len()
+            ),
+        }
+    }
+
+    /// The addresses announced by the node inside the network graph.
+    pub fn node_addresses(&self, node_id: &NodeId) -> Vec<SocketAddr> {
+        let graph = self.graph();
+        let graph = graph.read_only();
+        let Some(announcement) = graph
+            .node(&GraphNodeId::from_pubkey(node_id))
+            .and_then(|info| info.announcement_info.as_ref())
+        else {
+            return vec![];
+        };
+        announcement
+            .addresses()
+            .iter()
+            .filter_map(|addr| match addr {
+                SocketAddress::TcpIpV4 { addr, port } => Some(SocketAddr::from((*addr, *port))),
+                SocketAddress::TcpIpV6 { addr, port } => Some(SocketAddr::from((*addr, *port))),
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn scorer(&self) -> Arc<Mutex<LampoScorer>> {
         self.score.clone().unwrap()
     }
 
+    pub fn router(&self) -> Arc<LampoRouter> {
+        self.router.clone().unwrap()
+    }
+
     // FIXME: Step 11: Optional: Initialize the NetGraphMsgHandler
-    pub fn network_graph(
-        &mut self,
-    ) -> Arc<
-        DefaultRouter<
-            Arc<LampoGraph>,
-            Arc<LampoLogger>,
-            Arc<LampoKeysManager>,
-            Arc<Mutex<LampoScorer>>,
-            ProbabilisticScoringFeeParameters,
-            LampoScorer,
-        >,
-    > {
+    pub fn network_graph(&mut self) -> Arc<LampoRouter> {
         if self.router.is_none() {
-            // Step 9: Initialize routing ProbabilisticScorer
-            let network_graph_path = format!("{}/network_graph", self.conf.path());
-            let network_graph = self.read_network(Path::new(&network_graph_path));
-
-            let scorer_path = format!("{}/scorer", self.conf.path());
-            let scorer = Arc::new(Mutex::new(
-                self.read_scorer(Path::new(&scorer_path), &network_graph),
-            ));
-
-            self.graph = Some(network_graph.clone());
-            self.score = Some(scorer.clone());
-            self.router = Some(Arc::new(DefaultRouter::new(
-                network_graph,
-                self.logger.clone(),
-                self.wallet_manager.ldk_keys().keys_manager.clone(),
-                scorer,
-                ProbabilisticScoringFeeParameters::default(),
-            )))
+            let (network_graph, scorer) = self.load_router();
+            self.set_router(network_graph, scorer);
         }
         self.router.clone().unwrap()
     }
 
+    /// Read the network graph and the scorer from disk, it does not
+    /// modify the manager so it can run while we load the other components.
+    pub(crate) fn load_router(&self) -> (Arc<LampoGraph>, Arc<Mutex<LampoScorer>>) {
+        // Step 9: Initialize routing ProbabilisticScorer
+        let network_graph_path = format!("{}/network_graph", self.conf.path());
+        let network_graph = if self.conf.lazy_graph {
+            self.warm_network(Path::new(&network_graph_path))
+        } else {
+            self.read_network(Path::new(&network_graph_path))
+        };
+        if self.conf.rgs_url.is_some() {
+            if let Err(err) = self.rgs_sync(&network_graph) {
+                log::warn!(target: "lampo", "rapid gossip sync failed, waiting the gossip from the peers: {err}");
+            }
+        }
+
+        let scorer_path = format!("{}/scorer", self.conf.path());
+        let scorer = Arc::new(Mutex::new(
+            self.read_scorer(Path::new(&scorer_path), &network_graph),
+        ));
+        (network_graph, scorer)
+    }
+
+    pub(crate) fn set_router(&mut self, graph: Arc<LampoGraph>, scorer: Arc<Mutex<LampoScorer>>) {
+        self.graph = Some(graph.clone());
+        self.score = Some(scorer.clone());
+        self.router = Some(Arc::new(LampoRouter::new(
+            graph,
+            self.logger.clone(),
+            self.wallet_manager.ldk_keys().keys_manager.clone(),
+            scorer,
+            self.conf.blinded_path_hops,
+        )))
+    }
+
     pub(crate) fn read_scorer(
         &self,
         path: &Path,
@@ -303,12 +1155,102 @@ impl LampoChannelManager {
     pub(crate) fn read_network(&self, path: &Path) -> Arc<LampoGraph> {
         if let Ok(file) = File::open(path) {
             if let Ok(graph) = NetworkGraph::read(&mut BufReader::new(file), self.logger.clone()) {
-                return Arc::new(graph);
+                if !self.conf.compact_graph {
+                    return Arc::new(graph);
+                }
+                let compact = NetworkGraph::new(self.conf.network, self.logger.clone());
+                let chain_hash = ChainHash::using_genesis_block(self.conf.network);
+                copy_graph(&graph, &compact, chain_hash, true);
+                return Arc::new(compact);
             }
         }
         Arc::new(NetworkGraph::new(self.conf.network, self.logger.clone()))
     }
 
+    /// Return an empty graph that is filled in background with the
+    /// persisted one, so we do not block the startup on mainnet.
+    pub(crate) fn warm_network(&self, path: &Path) -> Arc<LampoGraph> {
+        let graph = Arc::new(NetworkGraph::new(self.conf.network, self.logger.clone()));
+        // Read the file now, otherwise the background processor
+        // may override it with the partial graph meanwhile.
+        let Ok(bytes) = std::fs::read(path) else {
+            return graph;
+        };
+        self.graph_warming.store(true, Ordering::SeqCst);
+        let warming = self.graph_warming.clone();
+        let logger = self.logger.clone();
+        let network = self.conf.network;
+        let compact = self.conf.compact_graph;
+        let live = graph.clone();
+        std::thread::spawn(move || {
+            log::info!(target: "lampo", "loading the network graph in background");
+            match NetworkGraph::read(&mut bytes.as_slice(), logger) {
+                Ok(persisted) => {
+                    let chain_hash = ChainHash::using_genesis_block(network);
+                    copy_graph(&persisted, &live, chain_hash, compact);
+                    let graph = live.read_only();
+                    log::info!(target: "lampo", "network graph loaded with `{}` channels and `{}` nodes", graph.channels().len(), graph.nodes().len());
+                }
+                Err(err) => {
+                    log::warn!(target: "lampo", "impossible load the network graph: {:?}", err)
+                }
+            }
+            warming.store(false, Ordering::SeqCst);
+        });
+        graph
+    }
+
+    /// Apply to `graph` the rapid gossip sync snapshot with what
+    /// changed after the last sync, or the full graph when it is empty.
+    pub(crate) fn rgs_sync(&self, graph: &Arc<LampoGraph>) -> error::Result<()> {
+        let Some(url) = &self.conf.rgs_url else {
+            error::bail!("the rapid gossip sync is disabled, set `rgs-url` to enable it");
+        };
+        let last_sync = if graph.read_only().channels().is_empty() {
+            0
+        } else {
+            self.persister
+                .read_json::<u32>(RGS_NAMESPACE, "last_sync")?
+                .unwrap_or_default()
+        };
+        let url = format!("{}/{last_sync}", url.trim_end_matches('/'));
+        log::info!(target: "lampo", "downloading the rapid gossip sync snapshot from `{url}`");
+        let response = minreq::get(&url).with_timeout(60).send()?;
+        if response.status_code != 200 {
+            error::bail!("`{url}` returned status code `{}`", response.status_code);
+        }
+        let sync = RapidGossipSync::new(graph.clone(), self.logger.clone());
+        let timestamp = sync
+            .update_network_graph(response.as_bytes())
+            .map_err(|err| error::anyhow!("invalid rapid gossip sync snapshot: {:?}", err))?;
+        self.persister
+            .write_json(RGS_NAMESPACE, "last_sync", &timestamp)?;
+        let graph = graph.read_only();
+        log::info!(target: "lampo", "rapid gossip sync completed, the network graph has `{}` channels and `{}` nodes", graph.channels().len(), graph.nodes().len());
+        Ok(())
+    }
+
+    /// Serialize the network graph, the snapshot can be imported
+    /// by another node with `import_graph`.
+    pub fn export_graph(&self) -> Vec<u8> {
+        self.graph().encode()
+    }
+
+    /// Merge the snapshot inside the live graph, the channels that
+    /// we already know are kept.
+    pub fn import_graph(&self, snapshot: &[u8]) -> error::Result<()> {
+        let snapshot = NetworkGraph::read(&mut &snapshot[..], self.logger.clone())
+            .map_err(|err| error::anyhow!("invalid graph snapshot: {:?}", err))?;
+        let chain_hash = ChainHash::using_genesis_block(self.conf.network);
+        copy_graph(
+            &snapshot,
+            &self.graph(),
+            chain_hash,
+            self.conf.compact_graph,
+        );
+        Ok(())
+    }
+
     pub fn is_restarting(&self) -> error::Result<bool> {
         Ok(Path::exists(Path::new(&format!(
             "{}/manager",
@@ -317,11 +1259,19 @@ impl LampoChannelManager {
     }
 
     pub fn restart(&mut self) -> error::Result<()> {
+        let monitors = self.get_channel_monitors()?;
+        self.restart_with_monitors(monitors)
+    }
+
+    /// Restart the channel manager with the channel monitors already
+    /// read from disk.
+    pub fn restart_with_monitors(
+        &mut self,
+        mut monitors: Vec<ChannelMonitor<InMemorySigner>>,
+    ) -> error::Result<()> {
         let monitor = self.build_channel_monitor();
         self.monitor = Some(Arc::new(monitor));
         let _ = self.network_graph();
-        let mut monitors = self.get_channel_monitors()?;
-        let monitors = monitors.iter_mut().collect::<Vec<_>>();
         let read_args = ChannelManagerReadArgs::new(
             self.wallet_manager.ldk_keys().keys_manager.clone(),
             self.wallet_manager.ldk_keys().keys_manager.clone(),
@@ -331,14 +1281,38 @@ impl LampoChannelManager {
             self.onchain.clone(),
             self.router.clone().unwrap(),
             self.logger.clone(),
-            self.conf.ldk_conf,
-            monitors,
+            self.conf.user_config()?,
+            monitors.iter_mut().collect::<Vec<_>>(),
         );
         let mut channel_manager_file = File::open(format!("{}/manager", self.conf.path()))?;
         let (_, channel_manager) =
             <(BlockHash, LampoChannel)>::read(&mut channel_manager_file, read_args)
                 .map_err(|err| error::anyhow!("{err}"))?;
         self.channeld = Some(channel_manager.into());
+        // the monitors are watched later in `listen`
+        *self.monitor_cache.lock().unwrap() = Some(monitors);
+        Ok(())
+    }
+
+    /// Write again the channel manager and the monitors read during
+    /// the restart, so they are stored with the current ldk format.
+    pub fn rewrite_state(&self) -> error::Result<()> {
+        let monitors = self.monitor_cache.lock().unwrap();
+        for monitor in monitors.iter().flatten() {
+            let (txo, _) = monitor.get_funding_txo();
+            self.persister.write(
+                CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+                CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+                &format!("{}_{}", txo.txid, txo.index),
+                &monitor.encode(),
+            )?;
+        }
+        self.persister.write(
+            CHANNEL_MANAGER_PERSISTENCE_PRIMARY_NAMESPACE,
+            CHANNEL_MANAGER_PERSISTENCE_SECONDARY_NAMESPACE,
+            CHANNEL_MANAGER_PERSISTENCE_KEY,
+            &self.manager().encode(),
+        )?;
         Ok(())
     }
 
@@ -373,6 +1347,114 @@ impl LampoChannelManager {
         Ok(())
     }
 
+    /// Unconfirm the transactions of the blocks removed by a reorg, this
+    /// must happen before that the new best block is connected. The
+    /// transactions are checked again because they can be also inside
+    /// the new blocks.
+    fn disconnect_blocks(&self, blocks: &[(BlockHash, u32)]) -> error::Result<()> {
+        let Some(fork_height) = blocks.iter().map(|(_, height)| *height).min() else {
+            return Ok(());
+        };
+        log::warn!(target: "channel_manager", "reorg of {} blocks from height {fork_height}", blocks.len());
+        let mut unconfirmed = Vec::new();
+        let relevant_txids = self
+            .manager()
+            .get_relevant_txids()
+            .into_iter()
+            .chain(self.chain_monitor().get_relevant_txids());
+        for (txid, height, _) in relevant_txids {
+            if height < fork_height || unconfirmed.contains(&txid) {
+                continue;
+            }
+            log::warn!(target: "channel_manager", "transaction `{txid}` confirmed at height {height} is not in the best chain anymore");
+            self.chain_monitor().transaction_unconfirmed(&txid);
+            self.manager().transaction_unconfirmed(&txid);
+            unconfirmed.push(txid);
+        }
+        for (hash, height) in blocks {
+            let height = Height::from_consensus(*height)?;
+            self.handler()
+                .emit(Event::OnChain(OnChainEvent::BlockDisconnected((
+                    *hash, height,
+                ))));
+        }
+        if unconfirmed.is_empty() {
+            return Ok(());
+        }
+        self.onchain.backend.manage_transactions(&mut unconfirmed)?;
+        self.onchain.backend.process_transactions()
+    }
+
+    /// Connect the new best block to the channels, the blocks removed
+    /// by a reorg are disconnected before, and `txdata` are the
+    /// transactions of the block that are confirmed with it.
+    fn connect_best_block(&self, header: &Header, height: u32, txdata: &[(usize, &Transaction)]) {
+        if self.onchain.is_stale_block(&header.block_hash(), height) {
+            // e.g: a new backend that is still catching up with our tip
+            log::debug!(target: "channel_manager", "block `{}` at height {height} is already connected", header.block_hash());
+            return;
+        }
+        match self.onchain.connect_block(header, height) {
+            Ok(disconnected) if !disconnected.is_empty() => {
+                if let Err(err) = self.disconnect_blocks(&disconnected) {
+                    log::error!(target: "channel_manager", "impossible recheck the transactions after the reorg: {err}");
+                }
+            }
+            Ok(_) => {}
+            Err(err) => {
+                log::error!(target: "channel_manager", "impossible check for reorgs: {err}")
+            }
+        }
+        if !txdata.is_empty() {
+            self.chain_monitor()
+                .transactions_confirmed(header, txdata, height);
+            self.manager()
+                .transactions_confirmed(header, txdata, height);
+        }
+        self.chain_monitor().best_block_updated(header, height);
+        self.manager().best_block_updated(header, height);
+    }
+
+    /// Resync the channels with a new chain backend. The events of the
+    /// blocks between our best block and the tip of the new backend
+    /// were never delivered to us (the previous backend failed, and
+    /// the events of a backend that is not active are dropped), so
+    /// these blocks are connected again with all their transactions.
+    /// The new backend does not know which transactions we are
+    /// watching too.
+    fn resync_backend(&self) -> error::Result<()> {
+        let (mut hash, height) = self.onchain.backend.get_best_block()?;
+        let mut height = height.ok_or(error::anyhow!("height not present"))?;
+        let best_height = self.manager().current_best_block().height;
+        // a backend that is behind reaches us with the next blocks, we
+        // walk back from its tip to collect the blocks that we missed.
+        let mut missed = Vec::new();
+        while height > best_height {
+            let header = match self.onchain.backend.get_block(&hash)? {
+                BlockData::FullBlock(block) => block.header,
+                BlockData::HeaderOnly(header) => header,
+            };
+            missed.push((hash, height));
+            hash = header.prev_blockhash;
+            height -= 1;
+        }
+        if !missed.is_empty() {
+            log::info!(target: "channel_manager", "connecting the {} blocks after height {best_height} with the new backend", missed.len());
+        }
+        // the blocks are downloaded again to keep only one of them in memory
+        for (hash, height) in missed.into_iter().rev() {
+            match self.onchain.backend.get_block(&hash)? {
+                BlockData::FullBlock(block) => {
+                    let txdata = block.txdata.iter().enumerate().collect::<Vec<_>>();
+                    self.connect_best_block(&block.header, height, &txdata);
+                }
+                // the relevant transactions are checked by `resume_channels`
+                BlockData::HeaderOnly(header) => self.connect_best_block(&header, height, &[]),
+            }
+        }
+        self.resume_channels()
+    }
+
     pub fn start(
         &mut self,
         block: BlockHash,
@@ -397,7 +1479,7 @@ impl LampoChannelManager {
             keymanagers.clone(),
             keymanagers.clone(),
             keymanagers,
-            self.conf.ldk_conf,
+            self.conf.user_config()?,
             chain_params,
             block_timestamp,
         )));
@@ -405,26 +1487,197 @@ impl LampoChannelManager {
     }
 }
 
+fn intercept_id(id: &str) -> error::Result<InterceptId> {
+    let id: [u8; 32] = hex::decode(id)?
+        .try_into()
+        .map_err(|_| error::anyhow!("the intercept id must be 32 bytes"))?;
+    Ok(InterceptId(id))
+}
+
+/// A channel is routable if at least one direction is enabled.
+fn is_routable(channel: &ChannelInfo) -> bool {
+    [&channel.one_to_two, &channel.two_to_one]
+        .iter()
+        .any(|update| update.as_ref().is_some_and(|update| update.enabled))
+}
+
+/// Copy the persisted graph inside the live one, the entries that
+/// the live graph already learned from the gossip are kept.
+///
+/// The messages were already verified when received, so we use the
+/// unsigned version of the updates to avoid verifying them again, this
+/// also means that the signed messages are not kept in memory.
+///
+/// With `compact` the channels that are not routable and the node
+/// announcements are not copied.
+fn copy_graph(persisted: &LampoGraph, live: &LampoGraph, chain_hash: ChainHash, compact: bool) {
+    let persisted = persisted.read_only();
+    for (scid, channel) in persisted.channels().unordered_iter() {
+        if compact && !is_routable(channel) {
+            continue;
+        }
+        let updates = [(0, &channel.one_to_two), (1, &channel.two_to_one)];
+        let result = match &channel.announcement_message {
+            Some(announcement) => live.update_channel_from_unsigned_announcement(
+                &announcement.contents,
+                &None::<Arc<LampoChainManager>>,
+            ),
+            None => {
+                let (Ok(node_one), Ok(node_two)) =
+                    (channel.node_one.as_pubkey(), channel.node_two.as_pubkey())
+                else {
+                    continue;
+                };
+                let timestamp = updates
+                    .iter()
+                    .filter_map(|(_, update)| update.as_ref())
+                    .map(|update| update.last_update as u64)
+                    .max()
+                    .unwrap_or_default();
+                live.add_channel_from_partial_announcement(
+                    *scid,
+                    timestamp,
+                    channel.features.clone(),
+                    node_one,
+                    node_two,
+                )
+            }
+        };
+        if let Err(err) = result {
+            log::trace!(target: "lampo", "skipping channel `{scid}`: {}", err.err);
+            continue;
+        }
+        for (direction, update) in updates {
+            let Some(update) = update else {
+                continue;
+            };
+            let msg = match &update.last_update_message {
+                Some(msg) => msg.contents.clone(),
+                None => UnsignedChannelUpdate {
+                    chain_hash,
+                    short_channel_id: *scid,
+                    timestamp: update.last_update,
+                    flags: direction | if update.enabled { 0 } else { 2 },
+                    cltv_expiry_delta: update.cltv_expiry_delta,
+                    htlc_minimum_msat: update.htlc_minimum_msat,
+                    htlc_maximum_msat: update.htlc_maximum_msat,
+                    fee_base_msat: update.fees.base_msat,
+                    fee_proportional_millionths: update.fees.proportional_millionths,
+                    excess_data: vec![],
+                },
+            };
+            let _ = live.update_channel_unsigned(&msg);
+        }
+    }
+    if compact {
+        return;
+    }
+    for (_, node) in persisted.nodes().unordered_iter() {
+        let Some(announcement) = node
+            .announcement_info
+            .as_ref()
+            .and_then(|info| info.announcement_message.as_ref())
+        else {
+            continue;
+        };
+        let _ = live.update_node_from_unsigned_announcement(&announcement.contents);
+    }
+}
+
 impl ChannelEvents for LampoChannelManager {
     fn open_channel(
         &self,
         open_channel: request::OpenChannel,
     ) -> error::Result<response::OpenChannel> {
-        self.manager()
-            .create_channel(
-                open_channel.node_id()?,
-                open_channel.amount,
-                0,
-                0,
-                None,
-                Some(self.conf.ldk_conf),
-            )
-            .map_err(|err| error::anyhow!("{:?}", err))?;
+        let external = open_channel.external_funding.unwrap_or(false);
+        let user_channel_id = if external {
+            EXTERNAL_FUNDING_CHANNEL
+        } else {
+            0
+        };
+        let node_id = open_channel.node_id()?;
+        let push_msat = open_channel.push_msat()?;
+        let fee_rate = open_channel
+            .feerate()?
+            .map(|fee_rate| self.onchain.fees().fee_rate(fee_rate))
+            .transpose()?;
+        if external && fee_rate.is_some() {
+            error::bail!("`feerate` can not be used with `external_funding`, the fee rate is chosen by the external wallet");
+        }
+        let utxos = open_channel.utxos()?;
+        if let Some(utxos) = &utxos {
+            if external {
+                error::bail!("`utxos` can not be used with `external_funding`, the utxos are chosen by the external wallet");
+            }
+            let fee_rate = self.fee_rate_or_estimate(fee_rate)?;
+            self.check_funding_utxos(utxos, open_channel.amount, 1, fee_rate)?;
+        }
+        let events = self.handler().events();
+        let conf = self.conf.channel_config(&node_id.to_string())?;
+        // the funding options must be there before that ldk
+        // asks for the funding transaction.
+        let temporary_channel_id =
+            ChannelId::temporary_from_entropy_source(&self.wallet_manager.ldk_keys().keys_manager);
+        if let Some(fee_rate) = fee_rate {
+            self.funding_fee_rates
+                .lock()
+                .unwrap()
+                .insert(temporary_channel_id, fee_rate);
+        }
+        if let Some(utxos) = utxos {
+            self.funding_utxos
+                .lock()
+                .unwrap()
+                .insert(temporary_channel_id, utxos);
+        }
+        if let Err(err) = self.manager().create_channel(
+            node_id,
+            open_channel.amount,
+            push_msat,
+            user_channel_id,
+            Some(temporary_channel_id),
+            Some(conf),
+        ) {
+            self.forget_funding(&temporary_channel_id);
+            error::bail!("{:?}", err);
+        }
+
+        if external {
+            // Wait that the peer accepts the channel, so we know the funding script
+            let temporary_channel_id = loop {
+                let event = events.recv_timeout(std::time::Duration::from_secs(30))?;
+                if let Event::Lightning(LightningEvent::FundingChannelStart {
+                    counterparty_node_id,
+                    temporary_channel_id,
+                    ..
+                }) = event
+                {
+                    if counterparty_node_id == node_id {
+                        break temporary_channel_id.to_string();
+                    }
+                }
+            };
+            let external_funding = self
+                .external_fundings
+                .lock()
+                .unwrap()
+                .get(&temporary_channel_id)
+                .cloned();
+            return Ok(response::OpenChannel {
+                node_id: open_channel.node_id,
+                amount: open_channel.amount,
+                public: open_channel.public,
+                push_msat,
+                to_self_delay: 2016,
+                tx: None,
+                txid: None,
+                external_funding,
+            });
+        }
 
         // Wait for SendRawTransaction to be received so to get the funding transaction
         // FIXME: we can loop forever here
         let tx: Option<Transaction> = loop {
-            let events = self.handler().events();
             let event = events.recv_timeout(std::time::Duration::from_secs(30))?;
 
             if let Event::OnChain(OnChainEvent::SendRawTransaction(tx)) = event {
@@ -438,10 +1691,11 @@ impl ChannelEvents for LampoChannelManager {
             node_id: open_channel.node_id,
             amount: open_channel.amount,
             public: open_channel.public,
-            push_msat: 0,
+            push_msat,
             to_self_delay: 2016,
             tx,
             txid,
+            external_funding: None,
         })
     }
 
@@ -452,9 +1706,50 @@ impl ChannelEvents for LampoChannelManager {
         self.manager()
             .close_channel(&channel_id, &node_id)
             .map_err(|err| error::anyhow!("{:?}", err))?;
-        Ok(())
+        self.change_state_channel(ChangeStateChannelEvent {
+            channel_id,
+            former_temporary_channel_id: None,
+            node_id,
+            channel_type: None,
+            state: ChannelState::Closing,
+        })
     }
-    fn change_state_channel(&self, _: ChangeStateChannelEvent) -> error::Result<()> {
-        unimplemented!()
+
+    fn change_state_channel(&self, event: ChangeStateChannelEvent) -> error::Result<()> {
+        let channel_id = event.channel_id.to_string();
+        let mut previous = self
+            .persister
+            .read_json::<response::ChannelStateRecord>(CHANNEL_STATES_NAMESPACE, &channel_id)?;
+        if let Some(temporary_channel_id) = event.former_temporary_channel_id {
+            // the channel is stored with the temporary id until it is pending
+            let temporary_channel_id = temporary_channel_id.to_string();
+            let temporary = self.persister.read_json::<response::ChannelStateRecord>(
+                CHANNEL_STATES_NAMESPACE,
+                &temporary_channel_id,
+            )?;
+            if temporary.is_some() {
+                self.persister
+                    .remove_json(CHANNEL_STATES_NAMESPACE, &temporary_channel_id)?;
+            }
+            previous = previous.or(temporary);
+        }
+        if let Some(previous) = &previous {
+            if !previous.state.can_move_to(&event.state) {
+                log::debug!(target: "manager", "channel `{channel_id}` can not move from {:?} to {:?}", previous.state, event.state);
+                return Ok(());
+            }
+        }
+        log::info!(target: "manager", "channel `{channel_id}` is now {:?}", event.state);
+        let record = response::ChannelStateRecord {
+            channel_id,
+            peer_id: event.node_id.to_string(),
+            state: event.state,
+            updated_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs(),
+        };
+        self.persister
+            .write_json(CHANNEL_STATES_NAMESPACE, &record.channel_id, &record)?;
+        Ok(())
     }
 }
@@ -17,8 +17,11 @@ pub struct OpenChannelResult {
 
 pub struct ChangeStateChannelEvent {
     pub channel_id: ChannelId,
+    /// The temporary channel id used before the channel is pending.
+    pub former_temporary_channel_id: Option<ChannelId>,
     pub node_id: NodeId,
-    pub channel_type: ChannelTypeFeatures,
+    /// Not known when the channel is closed.
+    pub channel_type: Option<ChannelTypeFeatures>,
     pub state: ChannelState,
 }
 
@@ -0,0 +1,209 @@
+//! Funding double-spend detection.
+//!
+//! While the funding transaction is not confirmed the inputs can
+//! be spent by another transaction (e.g: a fee bump done outside
+//! lampo), in this case the channel will never be opened, so we
+//! abandon it instead of leaving a zombie channel inside ldk.
+//!
+//! The pending fundings are stored under the funding txid.
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use lampo_common::bitcoin::hashes::Hash;
+use lampo_common::bitcoin::{Block, Transaction, Txid};
+use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::onchain::OnChainEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::model::response::PendingFunding;
+use lampo_common::types::{parse_channel_id, ChannelId, ChannelState, NodeId};
+use lampo_common::wallet::WalletManager;
+
+use super::LampoChannelManager;
+use crate::actions::handler::LampoHandler;
+use crate::ln::events::{ChangeStateChannelEvent, ChannelEvents};
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const FUNDINGS_NAMESPACE: &str = "pending_fundings";
+
+pub struct LampoFundingWatcher {
+    channel_manager: Arc<LampoChannelManager>,
+    wallet_manager: Arc<dyn WalletManager>,
+    persister: Arc<LampoPersistence>,
+    // funding txid -> funding
+    fundings: Mutex<BTreeMap<String, PendingFunding>>,
+}
+
+impl LampoFundingWatcher {
+    pub fn new(
+        channel_manager: Arc<LampoChannelManager>,
+        wallet_manager: Arc<dyn WalletManager>,
+        persister: Arc<LampoPersistence>,
+    ) -> error::Result<Self> {
+        let fundings = persister
+            .list_json::<PendingFunding>(FUNDINGS_NAMESPACE)?
+            .into_iter()
+            .map(|funding| (funding.txid.clone(), funding))
+            .collect();
+        Ok(Self {
+            channel_manager,
+            wallet_manager,
+            persister,
+            fundings: Mutex::new(fundings),
+        })
+    }
+
+    fn store(&self, funding: PendingFunding) -> error::Result<()> {
+        self.persister
+            .write_json(FUNDINGS_NAMESPACE, &funding.txid, &funding)?;
+        self.fundings
+            .lock()
+            .unwrap()
+            .insert(funding.txid.clone(), funding);
+        Ok(())
+    }
+
+    fn remove(&self, txid: &str) -> error::Result<()> {
+        self.persister.remove_json(FUNDINGS_NAMESPACE, txid)?;
+        self.fundings.lock().unwrap().remove(txid);
+        Ok(())
+    }
+
+    fn watch_funding(
+        &self,
+        counterparty_node_id: &NodeId,
+        temporary_channel_id: &ChannelId,
+        tx: &Transaction,
+    ) -> error::Result<()> {
+        self.store(PendingFunding {
+            txid: tx.txid().to_string(),
+            counterparty_node_id: counterparty_node_id.to_string(),
+            channel_id: temporary_channel_id.to_string(),
+            inputs: tx
+                .input
+                .iter()
+                .map(|input| input.previous_output.to_string())
+                .collect(),
+        })
+    }
+
+    /// The funding transaction was accepted by the peer, so the
+    /// channel id is derived from the funding outpoint.
+    fn funding_pending(&self, txid: &Txid, vout: u32) -> error::Result<()> {
+        let Some(mut funding) = self
+            .fundings
+            .lock()
+            .unwrap()
+            .get(&txid.to_string())
+            .cloned()
+        else {
+            return Ok(());
+        };
+        funding.channel_id =
+            ChannelId::v1_from_funding_txid(txid.as_byte_array(), vout as u16).to_string();
+        self.store(funding)
+    }
+
+    /// Check the block for the confirmation of the funding transactions,
+    /// or for a conflicting spend of their inputs.
+    fn check_block(&self, block: &Block, handler: &LampoHandler) -> error::Result<()> {
+        for tx in &block.txdata {
+            let txid = tx.txid().to_string();
+            let fundings = self.fundings.lock().unwrap().clone();
+            if fundings.contains_key(&txid) {
+                log::debug!(target: "funding-watcher", "funding transaction `{txid}` confirmed");
+                self.remove(&txid)?;
+                continue;
+            }
+            let spent = tx
+                .input
+                .iter()
+                .map(|input| input.previous_output.to_string())
+                .collect::<Vec<_>>();
+            for funding in fundings.values() {
+                if funding.inputs.iter().any(|input| spent.contains(input)) {
+                    self.abandon(funding, &txid, handler)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn abandon(
+        &self,
+        funding: &PendingFunding,
+        conflict: &str,
+        handler: &LampoHandler,
+    ) -> error::Result<()> {
+        log::warn!(target: "funding-watcher", "funding transaction `{}` double spent by `{conflict}`, abandoning channel `{}`", funding.txid, funding.channel_id);
+        let node_id = NodeId::from_str(&funding.counterparty_node_id)?;
+        let channel_id = parse_channel_id(&funding.channel_id)?;
+        if let Err(err) = self
+            .channel_manager
+            .manager()
+            .force_close_without_broadcasting_txn(&channel_id, &node_id)
+        {
+            log::warn!(target: "funding-watcher", "impossible close channel `{}`: {:?}", funding.channel_id, err);
+        }
+        self.channel_manager
+            .change_state_channel(ChangeStateChannelEvent {
+                channel_id,
+                former_temporary_channel_id: None,
+                node_id,
+                channel_type: None,
+                state: ChannelState::Abandoned,
+            })?;
+        if let Err(err) = self
+            .wallet_manager
+            .abandon_transaction(&Txid::from_str(&funding.txid)?)
+        {
+            log::warn!(target: "funding-watcher", "impossible release the inputs of `{}`: {err}", funding.txid);
+        }
+        self.remove(&funding.txid)?;
+        handler.emit(Event::Lightning(LightningEvent::ChannelEvent {
+            state: ChannelState::Abandoned,
+            message: format!(
+                "funding transaction `{}` of channel `{}` double spent by `{conflict}`",
+                funding.txid, funding.channel_id
+            ),
+        }));
+        Ok(())
+    }
+
+    pub fn watch(self: Arc<Self>, handler: Arc<LampoHandler>) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let events = handler.events();
+            loop {
+                let Ok(event) = events.recv() else {
+                    continue;
+                };
+                let result = match event {
+                    Event::Lightning(LightningEvent::FundingChannelEnd {
+                        counterparty_node_id,
+                        temporary_channel_id,
+                        funding_transaction,
+                        ..
+                    }) => self.watch_funding(
+                        &counterparty_node_id,
+                        &temporary_channel_id,
+                        &funding_transaction,
+                    ),
+                    Event::Lightning(LightningEvent::ChannelPending {
+                        funding_transaction,
+                        ..
+                    }) => self.funding_pending(&funding_transaction.txid, funding_transaction.vout),
+                    Event::OnChain(OnChainEvent::NewBlock(block)) => {
+                        self.check_block(&block, &handler)
+                    }
+                    _ => continue,
+                };
+                if let Err(err) = result {
+                    log::error!(target: "funding-watcher", "{err}");
+                }
+            }
+        })
+    }
+}
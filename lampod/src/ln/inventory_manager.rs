@@ -61,6 +61,7 @@ impl InventoryHandler for LampoInventoryManager {
                     blockheight,
                     lampo_dir,
                     address: address_vec,
+                    graph_warming: self.channel_manager.is_graph_warming(),
                 };
                 let getinfo = json::to_value(getinfo)?;
                 chan.send(getinfo)?;
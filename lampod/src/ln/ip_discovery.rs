@@ -0,0 +1,71 @@
+//! Public ip discovery implementation.
+//!
+//! Home nodes usually run behind a dynamic ip, so we periodically
+//! ask to an HTTP service what is our public ip, and we refresh
+//! the node announcement when it changes.
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+
+use super::LampoPeerManager;
+
+pub struct LampoIpDiscovery {
+    conf: LampoConf,
+    peer_manager: Arc<LampoPeerManager>,
+}
+
+impl LampoIpDiscovery {
+    pub fn new(conf: &LampoConf, peer_manager: Arc<LampoPeerManager>) -> Self {
+        Self {
+            conf: conf.clone(),
+            peer_manager,
+        }
+    }
+
+    /// Ask our public ip to the configured HTTP service.
+    pub fn public_ip(&self) -> error::Result<IpAddr> {
+        let response = minreq::get(&self.conf.public_ip_url)
+            .with_timeout(30)
+            .send()?;
+        if response.status_code != 200 {
+            error::bail!(
+                "`{}` returned status code `{}`",
+                self.conf.public_ip_url,
+                response.status_code
+            );
+        }
+        let ip = IpAddr::from_str(response.as_str()?.trim())?;
+        Ok(ip)
+    }
+
+    /// Refresh the node announcement if the public ip changed, and
+    /// return true if the announcement was updated.
+    pub fn refresh(&self) -> error::Result<bool> {
+        let ip = self.public_ip()?.to_string();
+        if ip == self.peer_manager.announced_addr() {
+            return Ok(false);
+        }
+        log::info!(target: "ip-discovery", "public ip changed to `{ip}`, refreshing the node announcement");
+        self.peer_manager.update_announced_addr(&ip)?;
+        Ok(true)
+    }
+
+    pub fn run(self: Arc<Self>) -> error::Result<JoinHandle<()>> {
+        let Some(interval) = self.conf.public_ip_interval else {
+            error::bail!(
+                "the public ip discovery is disabled, set `public-ip-interval` to enable it"
+            );
+        };
+        Ok(std::thread::spawn(move || loop {
+            if let Err(err) = self.refresh() {
+                log::warn!(target: "ip-discovery", "{err}");
+            }
+            std::thread::sleep(Duration::from_secs(interval));
+        }))
+    }
+}
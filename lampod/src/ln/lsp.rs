@@ -0,0 +1,322 @@
+//! LSP client, the channels bought from a LSP.
+//!
+//! The LSPS messages are json rpc 2.0 requests sent to the LSP
+//! with the custom peer message 37913 (LSPS0).
+//!
+//! With LSPS1 we buy a channel with inbound liquidity: we ask the
+//! channels that the LSP sells (`lsps1.get_info`), we create an
+//! order (`lsps1.create_order`) and we pay its invoice, then the LSP
+//! opens the channel and we follow the order (`lsps1.get_order`).
+//!
+//! With LSPS2 we ask to the LSP the fees to open a JIT channel
+//! (`lsps2.get_info`), then we buy a channel for a payment
+//! (`lsps2.buy`) and the LSP gives us a fake short channel id to
+//! put inside the route hint of the invoice. When the invoice is
+//! paid the LSP opens a zero conf channel with us and forwards the
+//! payment over it, taking the opening fee from the forwarded amount.
+//!
+//! When the node sells channels (`lsp-server`) the requests of the
+//! clients are delivered to the LSP server, and the LSPS support is
+//! advertised with the feature bit 729.
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lampo_common::bitcoin::secp256k1::PublicKey;
+use lampo_common::chan;
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::json;
+use lampo_common::ldk::io;
+use lampo_common::ldk::ln::features::{InitFeatures, NodeFeatures};
+use lampo_common::ldk::ln::msgs::{DecodeError, LightningError};
+use lampo_common::ldk::ln::peer_handler::CustomMessageHandler;
+use lampo_common::ldk::ln::wire::{CustomMessageReader, Type};
+use lampo_common::ldk::util::ser::{Writeable, Writer};
+use lampo_common::model::request::{Lsps1CreateOrder, Lsps1GetOrder, Lsps2Buy, LspsGetInfo};
+use lampo_common::model::response::{
+    Lsps1Info, Lsps1Order, Lsps2Channel, Lsps2Info, OpeningFeeParams,
+};
+use lampo_common::types::NodeId;
+
+use crate::ln::LampoPeerManager;
+
+/// Type of the custom message that carries the LSPS messages.
+pub const LSPS_MESSAGE_TYPE: u16 = 37913;
+/// Feature bit of the nodes that sell channels (LSPS0).
+pub const LSPS_FEATURE_BIT: usize = 729;
+/// How long we wait the response of the LSP.
+const LSPS_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A json rpc message exchanged with the LSP.
+#[derive(Clone, Debug)]
+pub struct LspsMessage {
+    pub payload: String,
+}
+
+impl Type for LspsMessage {
+    fn type_id(&self) -> u16 {
+        LSPS_MESSAGE_TYPE
+    }
+}
+
+impl Writeable for LspsMessage {
+    fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+        writer.write_all(self.payload.as_bytes())
+    }
+}
+
+pub struct LampoLspClient {
+    lsp: Option<(NodeId, SocketAddr)>,
+    token: Option<String>,
+    server: bool,
+    next_id: AtomicU64,
+    // request id -> who is waiting the response
+    pending: Mutex<HashMap<String, chan::Sender<json::Value>>>,
+    // where the requests of the clients are delivered
+    requests: Mutex<Option<chan::Sender<(NodeId, json::Value)>>>,
+    // messages that the peer manager must send
+    outbox: Mutex<Vec<(NodeId, LspsMessage)>>,
+}
+
+impl LampoLspClient {
+    pub fn new(conf: &LampoConf) -> error::Result<Self> {
+        Ok(Self {
+            lsp: conf.lsp()?,
+            token: conf.lsp_token.clone(),
+            server: conf.lsp_server,
+            next_id: AtomicU64::new(0),
+            pending: Mutex::new(HashMap::new()),
+            requests: Mutex::new(None),
+            outbox: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// The node id and the address of the LSP.
+    pub fn lsp(&self) -> error::Result<(NodeId, SocketAddr)> {
+        self.lsp.ok_or(error::anyhow!(
+            "the LSP is not configured, set the `lsp` option"
+        ))
+    }
+
+    /// Receive the requests sent by the clients, the requests are
+    /// ignored until the LSP server calls this.
+    pub fn serve(&self) -> chan::Receiver<(NodeId, json::Value)> {
+        let (sender, receiver) = chan::unbounded();
+        *self.requests.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    /// Send the `response` to a request of the client `node_id`.
+    pub fn respond(
+        &self,
+        peer_manager: &LampoPeerManager,
+        node_id: NodeId,
+        response: &json::Value,
+    ) -> error::Result<()> {
+        self.outbox.lock().unwrap().push((
+            node_id,
+            LspsMessage {
+                payload: json::to_string(response)?,
+            },
+        ));
+        peer_manager.manager().process_events();
+        Ok(())
+    }
+
+    /// Send `method` to the LSP and wait the response, we
+    /// must be already connected with the LSP.
+    fn request<P: json::Serialize, R: json::DeserializeOwned>(
+        &self,
+        peer_manager: &LampoPeerManager,
+        method: &str,
+        params: P,
+    ) -> error::Result<R> {
+        let (node_id, _) = self.lsp()?;
+        if !peer_manager.is_connected_with(node_id) {
+            error::bail!("not connected with the LSP `{node_id}`");
+        }
+        let id = format!("lampo-{}", self.next_id.fetch_add(1, Ordering::Relaxed));
+        let request = json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        let (sender, receiver) = chan::bounded(1);
+        self.pending.lock().unwrap().insert(id.clone(), sender);
+        self.outbox.lock().unwrap().push((
+            node_id,
+            LspsMessage {
+                payload: json::to_string(&request)?,
+            },
+        ));
+        peer_manager.manager().process_events();
+        let response = receiver.recv_timeout(LSPS_TIMEOUT);
+        self.pending.lock().unwrap().remove(&id);
+        let mut response =
+            response.map_err(|_| error::anyhow!("the LSP did not answer to `{method}`"))?;
+        if let Some(err) = response.get("error") {
+            error::bail!("the LSP refused `{method}`: {err}");
+        }
+        let Some(result) = response.get_mut("result") else {
+            error::bail!("the LSP sent an invalid response to `{method}`: {response}");
+        };
+        Ok(json::from_value(result.take())?)
+    }
+
+    /// The fees asked by the LSP to open a JIT channel,
+    /// from the cheapest to the most expensive.
+    pub fn lsps2_get_info(&self, peer_manager: &LampoPeerManager) -> error::Result<Lsps2Info> {
+        let request = LspsGetInfo {
+            token: self.token.clone(),
+        };
+        self.request(peer_manager, "lsps2.get_info", request)
+    }
+
+    /// Buy a JIT channel for a payment of `payment_size_msat`.
+    pub fn lsps2_buy(
+        &self,
+        peer_manager: &LampoPeerManager,
+        opening_fee_params: OpeningFeeParams,
+        payment_size_msat: u64,
+    ) -> error::Result<Lsps2Channel> {
+        let request = Lsps2Buy {
+            opening_fee_params,
+            payment_size_msat: payment_size_msat.to_string(),
+        };
+        self.request(peer_manager, "lsps2.buy", request)
+    }
+
+    /// The channels that the LSP sells.
+    pub fn lsps1_get_info(&self, peer_manager: &LampoPeerManager) -> error::Result<Lsps1Info> {
+        let request = LspsGetInfo {
+            token: self.token.clone(),
+        };
+        self.request(peer_manager, "lsps1.get_info", request)
+    }
+
+    /// Order a channel with `lsp_balance_sat` of inbound liquidity.
+    pub fn lsps1_create_order(
+        &self,
+        peer_manager: &LampoPeerManager,
+        lsp_balance_sat: u64,
+        required_channel_confirmations: u16,
+        funding_confirms_within_blocks: u16,
+        channel_expiry_blocks: u32,
+        announce_channel: bool,
+    ) -> error::Result<Lsps1Order> {
+        let request = Lsps1CreateOrder {
+            lsp_balance_sat: lsp_balance_sat.to_string(),
+            client_balance_sat: "0".to_owned(),
+            required_channel_confirmations,
+            funding_confirms_within_blocks,
+            channel_expiry_blocks,
+            token: self.token.clone(),
+            announce_channel,
+        };
+        self.request(peer_manager, "lsps1.create_order", request)
+    }
+
+    pub fn lsps1_get_order(
+        &self,
+        peer_manager: &LampoPeerManager,
+        order_id: &str,
+    ) -> error::Result<Lsps1Order> {
+        let request = Lsps1GetOrder {
+            order_id: order_id.to_owned(),
+        };
+        self.request(peer_manager, "lsps1.get_order", request)
+    }
+}
+
+impl CustomMessageReader for LampoLspClient {
+    type CustomMessage = LspsMessage;
+
+    fn read<R: io::Read>(
+        &self,
+        message_type: u16,
+        buffer: &mut R,
+    ) -> Result<Option<Self::CustomMessage>, DecodeError> {
+        if message_type != LSPS_MESSAGE_TYPE {
+            return Ok(None);
+        }
+        let mut payload = Vec::new();
+        buffer.read_to_end(&mut payload)?;
+        let payload = String::from_utf8(payload).map_err(|_| DecodeError::InvalidValue)?;
+        Ok(Some(LspsMessage { payload }))
+    }
+}
+
+impl CustomMessageHandler for LampoLspClient {
+    fn handle_custom_message(
+        &self,
+        msg: Self::CustomMessage,
+        sender_node_id: &PublicKey,
+    ) -> Result<(), LightningError> {
+        let response = match json::from_str::<json::Value>(&msg.payload) {
+            Ok(response) => response,
+            Err(err) => {
+                log::warn!(target: "lsp", "invalid LSPS message from `{sender_node_id}`: {err}");
+                return Ok(());
+            }
+        };
+        // a client is asking something to us
+        if response.get("method").is_some() {
+            match self.requests.lock().unwrap().as_ref() {
+                Some(requests) => {
+                    let _ = requests.send((*sender_node_id, response));
+                }
+                None => {
+                    log::debug!(target: "lsp", "ignoring LSPS request from `{sender_node_id}`, the LSP server is disabled")
+                }
+            }
+            return Ok(());
+        }
+        if self.lsp.map(|(lsp, _)| lsp) != Some(*sender_node_id) {
+            log::debug!(target: "lsp", "ignoring LSPS message from `{sender_node_id}`");
+            return Ok(());
+        }
+        let Some(id) = response
+            .get("id")
+            .and_then(|id| id.as_str())
+            .map(str::to_owned)
+        else {
+            log::debug!(target: "lsp", "ignoring LSPS message without id: {response}");
+            return Ok(());
+        };
+        match self.pending.lock().unwrap().remove(&id) {
+            Some(sender) => {
+                let _ = sender.send(response);
+            }
+            None => {
+                log::debug!(target: "lsp", "ignoring LSPS response `{id}`, nobody is waiting it")
+            }
+        }
+        Ok(())
+    }
+
+    fn get_and_clear_pending_msg(&self) -> Vec<(PublicKey, Self::CustomMessage)> {
+        std::mem::take(&mut *self.outbox.lock().unwrap())
+    }
+
+    fn provided_node_features(&self) -> NodeFeatures {
+        let mut features = NodeFeatures::empty();
+        if self.server {
+            // SAFETY: the bit is in the custom range.
+            features.set_optional_custom_bit(LSPS_FEATURE_BIT).unwrap();
+        }
+        features
+    }
+
+    fn provided_init_features(&self, _their_node_id: &PublicKey) -> InitFeatures {
+        let mut features = InitFeatures::empty();
+        if self.server {
+            // SAFETY: the bit is in the custom range.
+            features.set_optional_custom_bit(LSPS_FEATURE_BIT).unwrap();
+        }
+        features
+    }
+}
@@ -1,13 +1,34 @@
 //! Lampo Channel Manager
+mod backup;
 mod channel_manager;
+mod funding_watcher;
 mod inventory_manager;
+mod ip_discovery;
+mod lsp;
 mod offchain_manager;
+mod onion_messages;
 mod peer_manager;
+mod prober;
+mod rebalancer;
+mod rgs_server;
+mod router;
+mod watchdog;
 
 pub mod events;
 pub mod peer_event;
 
+pub use backup::LampoChannelBackup;
 pub use channel_manager::LampoChannelManager;
+pub(crate) use channel_manager::{BATCH_FUNDING_CHANNEL, EXTERNAL_FUNDING_CHANNEL};
+pub use funding_watcher::LampoFundingWatcher;
 pub use inventory_manager::LampoInventoryManager;
+pub use ip_discovery::LampoIpDiscovery;
+pub use lsp::LampoLspClient;
 pub use offchain_manager::OffchainManager;
+pub use onion_messages::LampoOnionMessages;
 pub use peer_manager::LampoPeerManager;
+pub use prober::LampoProber;
+pub use rebalancer::LampoRebalancer;
+pub use rgs_server::LampoRgsServer;
+pub use router::LampoRouter;
+pub use watchdog::LampoWatchdog;
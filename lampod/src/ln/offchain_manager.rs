@@ -12,25 +12,42 @@
 //! Author: Vincenzo Palazzo <vincenzopalazzo@member.fsf.org>
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use lampo_common::bitcoin::address::Payload;
+use lampo_common::bitcoin::bech32::ToBase32;
 use lampo_common::bitcoin::hashes::sha256::Hash as Sha256;
 use lampo_common::bitcoin::hashes::Hash;
 use lampo_common::bitcoin::secp256k1::PublicKey as pubkey;
+use lampo_common::bitcoin::Address;
 use lampo_common::conf::LampoConf;
 use lampo_common::error;
 use lampo_common::keys::LampoKeysManager;
 use lampo_common::ldk;
+use lampo_common::ldk::invoice::{Fallback, InvoiceBuilder};
 use lampo_common::ldk::ln::channelmanager::Retry;
-use lampo_common::ldk::ln::channelmanager::{PaymentId, RecipientOnionFields};
+use lampo_common::ldk::ln::channelmanager::MIN_FINAL_CLTV_EXPIRY_DELTA;
+use lampo_common::ldk::ln::channelmanager::{provided_channel_features, provided_node_features};
+use lampo_common::ldk::ln::channelmanager::{
+    ChannelDetails, PaymentId, PhantomRouteHints, RecipientOnionFields,
+};
 use lampo_common::ldk::ln::{PaymentHash, PaymentPreimage};
 use lampo_common::ldk::offers::offer::Amount;
 use lampo_common::ldk::offers::offer::Offer;
-use lampo_common::ldk::routing::router::{PaymentParameters, RouteParameters};
-use lampo_common::ldk::sign::EntropySource;
+use lampo_common::ldk::offers::refund::Refund;
+use lampo_common::ldk::routing::gossip::{NodeId, ReadOnlyNetworkGraph, RoutingFees};
+use lampo_common::ldk::routing::router::{
+    Path, Payee, PaymentParameters, Route, RouteHint, RouteHintHop, RouteHop, RouteParameters,
+    Router,
+};
+use lampo_common::ldk::sign::{EntropySource, NodeSigner, Recipient};
+use lampo_common::model::request::{FirstHops, HintOverrides, PayRouteHop};
+use lampo_common::model::response::{PaySimulation, PaymentHop};
 
 use super::LampoChannelManager;
 use crate::chain::LampoChainManager;
+use crate::payments::LampoPaymentStore;
+use crate::stats::LampoStats;
 use crate::utils::logger::LampoLogger;
 
 pub struct OffchainManager {
@@ -39,6 +56,8 @@ pub struct OffchainManager {
     logger: Arc<LampoLogger>,
     lampo_conf: Arc<LampoConf>,
     chain_manager: Arc<LampoChainManager>,
+    stats: Arc<LampoStats>,
+    payments: Arc<LampoPaymentStore>,
 }
 
 impl OffchainManager {
@@ -49,6 +68,8 @@ impl OffchainManager {
         logger: Arc<LampoLogger>,
         lampo_conf: Arc<LampoConf>,
         chain_manager: Arc<LampoChainManager>,
+        stats: Arc<LampoStats>,
+        payments: Arc<LampoPaymentStore>,
     ) -> error::Result<Self> {
         Ok(Self {
             channel_manager,
@@ -56,11 +77,17 @@ impl OffchainManager {
             logger,
             lampo_conf,
             chain_manager,
+            stats,
+            payments,
         })
     }
 
     /// Generate an invoice with a specific amount and a specific
     /// description.
+    ///
+    /// When all our channels are private, ldk adds the route hints
+    /// of the usable channels with the most inbound capacity (one for
+    /// each peer, at most 3), otherwise the payer uses the public ones.
     pub fn generate_invoice(
         &self,
         amount_msat: Option<u64>,
@@ -82,6 +109,198 @@ impl OffchainManager {
         Ok(invoice)
     }
 
+    /// Generate an invoice for `payment_hash`, the node does not know
+    /// the preimage so the payment is held until the invoice is settled.
+    pub fn generate_hold_invoice(
+        &self,
+        payment_hash: &str,
+        amount_msat: Option<u64>,
+        description: &str,
+        expiring_in: u32,
+    ) -> error::Result<ldk::invoice::Bolt11Invoice> {
+        let payment_hash = PaymentHash(Sha256::from_str(payment_hash)?.to_byte_array());
+        let currency = ldk::invoice::Currency::try_from(self.lampo_conf.network)?;
+        let invoice = ldk::invoice::utils::create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_hash(
+            &self.channel_manager.manager(),
+            self.keys_manager.clone(),
+            self.logger.clone(),
+            currency,
+            amount_msat,
+            description.to_string(),
+            SystemTime::now().duration_since(UNIX_EPOCH)?,
+            expiring_in,
+            payment_hash,
+            None,
+        )
+        .map_err(|err| error::anyhow!(err))?;
+        Ok(invoice)
+    }
+
+    /// Claim the payment held by a hold invoice, and return its payment hash.
+    pub fn settle_hold_invoice(&self, preimage: &str) -> error::Result<String> {
+        let preimage: [u8; 32] = hex::decode(preimage)?
+            .try_into()
+            .map_err(|_| error::anyhow!("the preimage must be 32 bytes"))?;
+        let payment_hash = PaymentHash(Sha256::hash(&preimage).to_byte_array());
+        self.channel_manager
+            .manager()
+            .claim_funds(PaymentPreimage(preimage));
+        Ok(payment_hash.to_string())
+    }
+
+    /// Fail back the payment held by a hold invoice.
+    pub fn cancel_hold_invoice(&self, payment_hash: &str) -> error::Result<()> {
+        let payment_hash = PaymentHash(Sha256::from_str(payment_hash)?.to_byte_array());
+        self.channel_manager
+            .manager()
+            .fail_htlc_backwards(&payment_hash);
+        Ok(())
+    }
+
+    /// Generate an invoice that contains the on chain `fallback`
+    /// address, so a payer without lightning can settle it on chain.
+    pub fn generate_invoice_with_fallback(
+        &self,
+        amount_msat: Option<u64>,
+        description: &str,
+        expiring_in: u32,
+        fallback: &str,
+    ) -> error::Result<ldk::invoice::Bolt11Invoice> {
+        let address = Address::from_str(fallback)?.require_network(self.lampo_conf.network)?;
+        let fallback = match &address.payload {
+            Payload::PubkeyHash(hash) => Fallback::PubKeyHash(*hash),
+            Payload::ScriptHash(hash) => Fallback::ScriptHash(*hash),
+            Payload::WitnessProgram(program) => Fallback::SegWitProgram {
+                version: program.version(),
+                program: program.program().as_bytes().to_vec(),
+            },
+            _ => error::bail!("unsupported fallback address `{address}`"),
+        };
+        // ldk does not allow to specify the fallback, so we generate the
+        // invoice (with the route hints) and we build it again with the fallback.
+        let invoice = self.generate_invoice(amount_msat, description, expiring_in)?;
+        let currency = ldk::invoice::Currency::try_from(self.lampo_conf.network)?;
+        let mut builder = InvoiceBuilder::new(currency)
+            .description(description.to_owned())
+            .payment_hash(*invoice.payment_hash())
+            .payment_secret(*invoice.payment_secret())
+            .duration_since_epoch(invoice.duration_since_epoch())
+            .min_final_cltv_expiry_delta(invoice.min_final_cltv_expiry_delta())
+            .expiry_time(invoice.expiry_time())
+            .basic_mpp()
+            .fallback(fallback);
+        if let Some(amount_msat) = invoice.amount_milli_satoshis() {
+            builder = builder.amount_milli_satoshis(amount_msat);
+        }
+        for hint in invoice.route_hints() {
+            builder = builder.private_route(hint);
+        }
+        let raw = builder.build_raw().map_err(|err| error::anyhow!("{err}"))?;
+        let hrp = raw.hrp.to_string();
+        let data = raw.data.to_base32();
+        let signed = raw
+            .sign(|_| {
+                self.keys_manager
+                    .sign_invoice(hrp.as_bytes(), &data, Recipient::Node)
+            })
+            .map_err(|_| error::anyhow!("impossible sign the invoice"))?;
+        let invoice = ldk::invoice::Bolt11Invoice::from_signed(signed)
+            .map_err(|err| error::anyhow!("{err}"))?;
+        Ok(invoice)
+    }
+
+    /// Generate an invoice that is paid through the JIT channel bought
+    /// from the LSP, the route hint is the fake channel `lsp_scid`.
+    pub fn generate_jit_invoice(
+        &self,
+        amount_msat: u64,
+        description: &str,
+        expiring_in: u32,
+        lsp_id: pubkey,
+        lsp_scid: u64,
+        lsp_cltv_expiry_delta: u16,
+    ) -> error::Result<ldk::invoice::Bolt11Invoice> {
+        let (payment_hash, payment_secret) = self
+            .channel_manager
+            .manager()
+            .create_inbound_payment(Some(amount_msat), expiring_in, None)
+            .map_err(|_| error::anyhow!("impossible create the inbound payment"))?;
+        let hint = RouteHint(vec![RouteHintHop {
+            src_node_id: lsp_id,
+            short_channel_id: lsp_scid,
+            // the LSP takes the opening fee, not a forwarding fee
+            fees: RoutingFees {
+                base_msat: 0,
+                proportional_millionths: 0,
+            },
+            cltv_expiry_delta: lsp_cltv_expiry_delta,
+            htlc_minimum_msat: None,
+            htlc_maximum_msat: None,
+        }]);
+        let currency = ldk::invoice::Currency::try_from(self.lampo_conf.network)?;
+        let raw = InvoiceBuilder::new(currency)
+            .description(description.to_owned())
+            .payment_hash(Sha256::from_byte_array(payment_hash.0))
+            .payment_secret(payment_secret)
+            .duration_since_epoch(SystemTime::now().duration_since(UNIX_EPOCH)?)
+            .min_final_cltv_expiry_delta(MIN_FINAL_CLTV_EXPIRY_DELTA.into())
+            .expiry_time(Duration::from_secs(expiring_in.into()))
+            .amount_milli_satoshis(amount_msat)
+            .basic_mpp()
+            .private_route(hint)
+            .build_raw()
+            .map_err(|err| error::anyhow!("{err}"))?;
+        let hrp = raw.hrp.to_string();
+        let data = raw.data.to_base32();
+        let signed = raw
+            .sign(|_| {
+                self.keys_manager
+                    .sign_invoice(hrp.as_bytes(), &data, Recipient::Node)
+            })
+            .map_err(|_| error::anyhow!("impossible sign the invoice"))?;
+        let invoice = ldk::invoice::Bolt11Invoice::from_signed(signed)
+            .map_err(|err| error::anyhow!("{err}"))?;
+        Ok(invoice)
+    }
+
+    /// Route hints that the other nodes of the phantom cluster
+    /// need to include inside the phantom invoices.
+    pub fn phantom_route_hints(&self) -> error::Result<PhantomRouteHints> {
+        if !self.keys_manager.is_phantom() {
+            error::bail!("phantom payments are disabled, set the `phantom-seed` option");
+        }
+        Ok(self.channel_manager.manager().get_phantom_route_hints())
+    }
+
+    /// Generate an invoice that can be claimed by any node of the
+    /// phantom cluster, `hints` must contain the route hints of the
+    /// other nodes, our hints are always included.
+    pub fn generate_phantom_invoice(
+        &self,
+        amount_msat: Option<u64>,
+        description: &str,
+        expiring_in: u32,
+        mut hints: Vec<PhantomRouteHints>,
+    ) -> error::Result<ldk::invoice::Bolt11Invoice> {
+        hints.push(self.phantom_route_hints()?);
+        let currency = ldk::invoice::Currency::try_from(self.lampo_conf.network)?;
+        let invoice = ldk::invoice::utils::create_phantom_invoice(
+            amount_msat,
+            None,
+            description.to_string(),
+            expiring_in,
+            hints,
+            self.keys_manager.clone(),
+            self.keys_manager.clone(),
+            self.logger.clone(),
+            currency,
+            None,
+            SystemTime::now().duration_since(UNIX_EPOCH)?,
+        )
+        .map_err(|err| error::anyhow!("{err}"))?;
+        Ok(invoice)
+    }
+
     pub fn decode_invoice(&self, invoice_str: &str) -> error::Result<ldk::invoice::Bolt11Invoice> {
         let invoice = invoice_str.parse::<ldk::invoice::Bolt11Invoice>()?;
         Ok(invoice)
@@ -95,9 +314,32 @@ impl OffchainManager {
     }
 
     pub fn pay_offer(&self, offer_str: &str, amount_msat: Option<u64>) -> error::Result<()> {
-        // check if it is an invoice or an offer
+        self.pay_offer_with_note(offer_str, amount_msat, None)
+    }
+
+    /// Pay the offer, the `payer_note` is included inside
+    /// the invoice request sent to the recipient.
+    pub fn pay_offer_with_note(
+        &self,
+        offer_str: &str,
+        amount_msat: Option<u64>,
+        payer_note: Option<String>,
+    ) -> error::Result<()> {
         let offer_hash = Sha256::hash(offer_str.as_bytes());
         let payment_id = PaymentId(*offer_hash.as_ref());
+        self.pay_offer_with_id(offer_str, amount_msat, payer_note, payment_id)
+    }
+
+    /// Pay the offer with the `payment_id` chosen by the caller, ldk
+    /// refuses a payment id that it still remembers, so an offer paid
+    /// more than once needs a new id for each payment.
+    pub fn pay_offer_with_id(
+        &self,
+        offer_str: &str,
+        amount_msat: Option<u64>,
+        payer_note: Option<String>,
+        payment_id: PaymentId,
+    ) -> error::Result<()> {
         let offer = Offer::from_str(offer_str).map_err(|err| error::anyhow!("{:?}", err))?;
 
         let amount = match offer.amount() {
@@ -115,39 +357,456 @@ impl OffchainManager {
                 &offer,
                 None,
                 Some(amount),
-                None,
+                payer_note,
                 payment_id,
-                Retry::Attempts(10),
+                self.retry(),
                 None,
             )
             .map_err(|err| error::anyhow!("{:?}", err))?;
+        self.stats.payment_started(payment_id.0);
         Ok(())
     }
 
-    pub fn pay_invoice(&self, invoice_str: &str, amount_msat: Option<u64>) -> error::Result<()> {
-        // check if it is an invoice or an offer
-        let invoice = self.decode_invoice(invoice_str)?;
-        let payment_id = PaymentId((*invoice.payment_hash()).to_byte_array());
-        let (payment_hash, onion, route) = if invoice.amount_milli_satoshis().is_none() {
+    /// Create a refund, ldk pays the invoice that the receiver
+    /// of the refund sends us over onion messages.
+    pub fn create_refund(
+        &self,
+        amount_msat: u64,
+        description: Option<String>,
+        expiry: Duration,
+    ) -> error::Result<Refund> {
+        let payment_id = PaymentId(self.keys_manager.get_secure_random_bytes());
+        let absolute_expiry = SystemTime::now().duration_since(UNIX_EPOCH)? + expiry;
+        let mut builder = self
+            .channel_manager
+            .manager()
+            .create_refund_builder(amount_msat, absolute_expiry, payment_id, self.retry(), None)
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        if let Some(description) = description {
+            builder = builder.description(description);
+        }
+        let refund = builder.build().map_err(|err| error::anyhow!("{:?}", err))?;
+        self.stats.payment_started(payment_id.0);
+        Ok(refund)
+    }
+
+    /// Send the invoice for the refund to the payer, the
+    /// payment is received as any other bolt12 payment.
+    pub fn claim_refund(&self, refund_str: &str) -> error::Result<Refund> {
+        let refund = Refund::from_str(refund_str).map_err(|err| error::anyhow!("{:?}", err))?;
+        if refund.is_expired() {
+            error::bail!("the refund is expired");
+        }
+        self.channel_manager
+            .manager()
+            .request_refund_payment(&refund)
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        Ok(refund)
+    }
+
+    /// How long ldk retries the failed parts of a payment.
+    fn retry(&self) -> Retry {
+        match self.lampo_conf.payment_part_timeout {
+            Some(timeout) => Retry::Timeout(Duration::from_secs(timeout)),
+            None => Retry::Attempts(10),
+        }
+    }
+
+    fn invoice_payment_parameters(
+        &self,
+        invoice: &ldk::invoice::Bolt11Invoice,
+        amount_msat: Option<u64>,
+        hints: &HintOverrides,
+    ) -> error::Result<(PaymentHash, RecipientOnionFields, RouteParameters)> {
+        let mut params = if invoice.amount_milli_satoshis().is_none() {
             ldk::invoice::payment::payment_parameters_from_zero_amount_invoice(
-                &invoice,
+                invoice,
                 amount_msat.ok_or(error::anyhow!(
                     "invoice with no amount, and amount must be specified"
                 ))?,
             )
             .map_err(|err| error::anyhow!("{:?}", err))?
         } else {
-            ldk::invoice::payment::payment_parameters_from_invoice(&invoice)
+            ldk::invoice::payment::payment_parameters_from_invoice(invoice)
                 .map_err(|err| error::anyhow!("{:?}", err))?
         };
+        Self::apply_hints(&mut params.2, hints)?;
+        if let Some(max_parts) = self.lampo_conf.payment_max_parts {
+            params.2.payment_params.max_path_count = max_parts;
+        }
+        Ok(params)
+    }
+
+    /// Add the route hints supplied by the payer to the payment, or
+    /// replace the hints of the invoice with them.
+    fn apply_hints(params: &mut RouteParameters, hints: &HintOverrides) -> error::Result<()> {
+        let Payee::Clear { route_hints, .. } = &mut params.payment_params.payee else {
+            error::bail!("route hints are not supported for blinded payments");
+        };
+        if hints.replace_route_hints.unwrap_or(false) {
+            route_hints.clear();
+        }
+        for hint in hints.route_hints.iter().flatten() {
+            if hint.is_empty() {
+                error::bail!("a route hint must contain at least one hop");
+            }
+            let hops = hint
+                .iter()
+                .map(|hop| {
+                    Ok(RouteHintHop {
+                        src_node_id: pubkey::from_str(&hop.node_id)?,
+                        short_channel_id: hop.short_channel_id,
+                        fees: RoutingFees {
+                            base_msat: hop.fee_base_msat,
+                            proportional_millionths: hop.fee_proportional_millionths,
+                        },
+                        cltv_expiry_delta: hop.cltv_expiry_delta,
+                        htlc_minimum_msat: hop.htlc_minimum_msat,
+                        htlc_maximum_msat: hop.htlc_maximum_msat,
+                    })
+                })
+                .collect::<error::Result<Vec<_>>>()?;
+            route_hints.push(RouteHint(hops));
+        }
+        Ok(())
+    }
+
+    pub fn pay_invoice(&self, invoice_str: &str, amount_msat: Option<u64>) -> error::Result<()> {
+        self.pay_invoice_with_hints(invoice_str, amount_msat, &HintOverrides::default())
+    }
+
+    /// Pay the invoice with the route hints supplied by the payer.
+    pub fn pay_invoice_with_hints(
+        &self,
+        invoice_str: &str,
+        amount_msat: Option<u64>,
+        hints: &HintOverrides,
+    ) -> error::Result<()> {
+        // check if it is an invoice or an offer
+        let invoice = self.decode_invoice(invoice_str)?;
+        let payment_id = PaymentId((*invoice.payment_hash()).to_byte_array());
+        let (payment_hash, onion, route) =
+            self.invoice_payment_parameters(&invoice, amount_msat, hints)?;
+        let amount_msat = route.final_value_msat;
         self.channel_manager
             .manager()
-            .send_payment(payment_hash, onion, payment_id, route, Retry::Attempts(10))
+            .send_payment(payment_hash, onion, payment_id, route, self.retry())
             .map_err(|err| error::anyhow!("{:?}", err))?;
+        self.stats.payment_started(payment_id.0);
+        self.payments.payment_started(
+            &payment_hash.to_string(),
+            Some(amount_msat),
+            Some(invoice_str.to_owned()),
+        )?;
         Ok(())
     }
 
-    pub fn keysend(&self, destination: pubkey, amount_msat: u64) -> error::Result<PaymentHash> {
+    /// Our usable channels that satisfy the first hop constraints.
+    fn first_hops(&self, constraints: &FirstHops) -> error::Result<Vec<ChannelDetails>> {
+        let out_peer = constraints
+            .out_peer
+            .as_ref()
+            .map(|peer| pubkey::from_str(peer))
+            .transpose()?;
+        let channels = self
+            .channel_manager
+            .manager()
+            .list_usable_channels()
+            .into_iter()
+            .filter(|channel| {
+                constraints
+                    .out_channels
+                    .as_ref()
+                    .map_or(true, |ids| channel_matches(channel, ids))
+            })
+            .filter(|channel| out_peer.map_or(true, |peer| channel.counterparty.node_id == peer))
+            .filter(|channel| {
+                !constraints
+                    .exclude_channels
+                    .as_ref()
+                    .map_or(false, |ids| channel_matches(channel, ids))
+            })
+            .collect::<Vec<_>>();
+        if channels.is_empty() && !constraints.is_empty() {
+            error::bail!("no usable channel satisfies the first hop constraints");
+        }
+        Ok(channels)
+    }
+
+    /// Find a route that leaves from one of the channels allowed
+    /// by the first hop constraints.
+    fn route_through(
+        &self,
+        route_params: &RouteParameters,
+        first_hops: &FirstHops,
+    ) -> error::Result<Route> {
+        let manager = self.channel_manager.manager();
+        let first_hops = self.first_hops(first_hops)?;
+        let route = self
+            .channel_manager
+            .router()
+            .find_route(
+                &manager.get_our_node_id(),
+                route_params,
+                Some(&first_hops.iter().collect::<Vec<_>>()),
+                manager.compute_inflight_htlcs(),
+            )
+            .map_err(|err| error::anyhow!("{}", err.err))?;
+        Ok(route)
+    }
+
+    /// Pay the invoice along a route that satisfies the first hop
+    /// constraints. The payment is not retried, because ldk would
+    /// look for the new route without the constraints.
+    pub fn pay_invoice_through(
+        &self,
+        invoice_str: &str,
+        amount_msat: Option<u64>,
+        first_hops: &FirstHops,
+        hints: &HintOverrides,
+    ) -> error::Result<()> {
+        let invoice = self.decode_invoice(invoice_str)?;
+        let payment_id = PaymentId((*invoice.payment_hash()).to_byte_array());
+        let (payment_hash, onion, route_params) =
+            self.invoice_payment_parameters(&invoice, amount_msat, hints)?;
+        let route = self.route_through(&route_params, first_hops)?;
+        self.channel_manager
+            .manager()
+            .send_payment_with_route(&route, payment_hash, onion, payment_id)
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        self.stats.payment_started(payment_id.0);
+        self.payments.payment_started(
+            &payment_hash.to_string(),
+            Some(route_params.final_value_msat),
+            Some(invoice_str.to_owned()),
+        )?;
+        Ok(())
+    }
+
+    /// The fees and the cltv delta asked by `source` to forward over
+    /// the channel `short_channel_id`, from the network graph or from
+    /// the route hints when the channel is not announced.
+    fn forwarding_policy(
+        graph: &ReadOnlyNetworkGraph,
+        route_hints: &[RouteHint],
+        source: &pubkey,
+        short_channel_id: u64,
+    ) -> error::Result<(RoutingFees, u16)> {
+        if let Some(channel) = graph.channel(short_channel_id) {
+            let node_id = NodeId::from_pubkey(source);
+            let update = if channel.node_one == node_id {
+                channel.one_to_two.as_ref()
+            } else if channel.node_two == node_id {
+                channel.two_to_one.as_ref()
+            } else {
+                error::bail!("`{source}` is not a node of the channel `{short_channel_id}`");
+            };
+            let Some(update) = update else {
+                error::bail!(
+                    "unknown forwarding policy of `{source}` for the channel `{short_channel_id}`"
+                );
+            };
+            return Ok((update.fees, update.cltv_expiry_delta));
+        }
+        route_hints
+            .iter()
+            .flat_map(|hint| hint.0.iter())
+            .find(|hop| hop.short_channel_id == short_channel_id && hop.src_node_id == *source)
+            .map(|hop| (hop.fees, hop.cltv_expiry_delta))
+            .ok_or(error::anyhow!(
+                "unknown channel `{short_channel_id}`, it is not announced and not inside the route hints"
+            ))
+    }
+
+    /// Build the route along the `hops` chosen by the payer, the
+    /// scorer is not used and the fees are the ones announced by
+    /// each hop for the amount that it forwards.
+    fn manual_route(
+        &self,
+        route_params: &RouteParameters,
+        hops: &[PayRouteHop],
+    ) -> error::Result<(Route, ChannelDetails)> {
+        let Some(first) = hops.first() else {
+            error::bail!("the route must contain at least one hop");
+        };
+        let Payee::Clear {
+            node_id: payee,
+            route_hints,
+            ..
+        } = &route_params.payment_params.payee
+        else {
+            error::bail!("a manual route is not supported for blinded payments");
+        };
+        let Some(channel) = self
+            .channel_manager
+            .manager()
+            .list_usable_channels()
+            .into_iter()
+            .find(|channel| channel.get_outbound_payment_scid() == Some(first.short_channel_id))
+        else {
+            error::bail!(
+                "the channel `{}` is not usable to send a payment",
+                first.short_channel_id
+            );
+        };
+        if channel.counterparty.node_id.to_string() != first.node_id {
+            error::bail!(
+                "the channel `{}` is not with `{}`",
+                first.short_channel_id,
+                first.node_id
+            );
+        }
+        let destination = &hops
+            .last()
+            .expect("the route contains at least one hop")
+            .node_id;
+        if payee.to_string() != *destination {
+            error::bail!("the route ends at `{destination}` and not at the destination `{payee}`");
+        }
+
+        let graph = self.channel_manager.graph();
+        let graph = graph.read_only();
+        let conf = &self.lampo_conf.ldk_conf;
+        let mut route_hops = Vec::new();
+        for hop in hops {
+            let node_id = pubkey::from_str(&hop.node_id)?;
+            let announced = graph.channel(hop.short_channel_id);
+            let node_features = graph
+                .node(&NodeId::from_pubkey(&node_id))
+                .and_then(|node| node.announcement_info.as_ref())
+                .map(|info| info.features.clone())
+                .unwrap_or_else(|| provided_node_features(conf));
+            route_hops.push(RouteHop {
+                pubkey: node_id,
+                node_features,
+                short_channel_id: hop.short_channel_id,
+                channel_features: announced
+                    .map(|channel| channel.features.clone())
+                    .unwrap_or_else(|| provided_channel_features(conf)),
+                fee_msat: 0,
+                cltv_expiry_delta: 0,
+                maybe_announced_channel: announced.is_some(),
+            });
+        }
+        // the first channel is ours, so we know it even when it is private
+        route_hops[0].maybe_announced_channel = channel.is_public;
+
+        // walk back from the destination, each hop asks a fee to
+        // forward the amount received by the next hop.
+        let last = route_hops.len() - 1;
+        route_hops[last].fee_msat = route_params.final_value_msat;
+        route_hops[last].cltv_expiry_delta = route_params.payment_params.final_cltv_expiry_delta;
+        let mut forwarded_msat = route_params.final_value_msat;
+        for idx in (0..last).rev() {
+            let (fees, cltv_expiry_delta) = Self::forwarding_policy(
+                &graph,
+                route_hints,
+                &route_hops[idx].pubkey,
+                route_hops[idx + 1].short_channel_id,
+            )?;
+            let fee_msat = fees.base_msat as u64
+                + forwarded_msat * fees.proportional_millionths as u64 / 1_000_000;
+            route_hops[idx].fee_msat = fee_msat;
+            route_hops[idx].cltv_expiry_delta = cltv_expiry_delta as u32;
+            forwarded_msat += fee_msat;
+        }
+        let route = Route {
+            paths: vec![Path {
+                hops: route_hops,
+                blinded_tail: None,
+            }],
+            route_params: Some(route_params.clone()),
+        };
+        Ok((route, channel))
+    }
+
+    /// Pay the invoice along the route chosen by the payer, the
+    /// payment is not retried because ldk would look for a new route.
+    pub fn pay_invoice_along(
+        &self,
+        invoice_str: &str,
+        amount_msat: Option<u64>,
+        hops: &[PayRouteHop],
+        hints: &HintOverrides,
+    ) -> error::Result<()> {
+        let invoice = self.decode_invoice(invoice_str)?;
+        let payment_id = PaymentId((*invoice.payment_hash()).to_byte_array());
+        let (payment_hash, onion, route_params) =
+            self.invoice_payment_parameters(&invoice, amount_msat, hints)?;
+        let (route, _) = self.manual_route(&route_params, hops)?;
+        self.channel_manager
+            .manager()
+            .send_payment_with_route(&route, payment_hash, onion, payment_id)
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        self.stats.payment_started(payment_id.0);
+        self.payments.payment_started(
+            &payment_hash.to_string(),
+            Some(route_params.final_value_msat),
+            Some(invoice_str.to_owned()),
+        )?;
+        Ok(())
+    }
+
+    /// Compute the route that we would use to pay the invoice
+    /// and the resulting outbound capacity, without sending any HTLC.
+    pub fn simulate_invoice_payment(
+        &self,
+        invoice_str: &str,
+        amount_msat: Option<u64>,
+        first_hops: &FirstHops,
+        hints: &HintOverrides,
+        route: Option<&[PayRouteHop]>,
+    ) -> error::Result<PaySimulation> {
+        let invoice = self.decode_invoice(invoice_str)?;
+        let (payment_hash, _, route_params) =
+            self.invoice_payment_parameters(&invoice, amount_msat, hints)?;
+        let manager = self.channel_manager.manager();
+        let (route, first_hops) = match route {
+            Some(hops) => {
+                let (route, channel) = self.manual_route(&route_params, hops)?;
+                (route, vec![channel])
+            }
+            None => {
+                let first_hops = self.first_hops(first_hops)?;
+                let route = self
+                    .channel_manager
+                    .router()
+                    .find_route(
+                        &manager.get_our_node_id(),
+                        &route_params,
+                        Some(&first_hops.iter().collect::<Vec<_>>()),
+                        manager.compute_inflight_htlcs(),
+                    )
+                    .map_err(|err| error::anyhow!("{}", err.err))?;
+                (route, first_hops)
+            }
+        };
+
+        let fee_msat = route.get_total_fees();
+        let outbound_capacity_msat = first_hops
+            .iter()
+            .map(|channel| channel.outbound_capacity_msat)
+            .sum::<u64>();
+        Ok(PaySimulation {
+            payment_hash: payment_hash.to_string(),
+            amount_msat: route_params.final_value_msat,
+            fee_msat,
+            paths: route
+                .paths
+                .iter()
+                .map(|path| path.hops.iter().cloned().map(PaymentHop::from).collect())
+                .collect(),
+            outbound_capacity_msat,
+            outbound_capacity_after_msat: outbound_capacity_msat
+                .saturating_sub(route.get_total_amount() + fee_msat),
+        })
+    }
+
+    fn keysend_parameters(
+        &self,
+        destination: pubkey,
+        amount_msat: u64,
+    ) -> (PaymentPreimage, PaymentHash, RouteParameters) {
         let payment_preimage = PaymentPreimage(
             self.chain_manager
                 .wallet_manager
@@ -165,6 +824,12 @@ impl OffchainManager {
             final_value_msat: amount_msat,
             max_total_routing_fee_msat: None,
         };
+        (payment_preimage, payment_hash, route_params)
+    }
+
+    pub fn keysend(&self, destination: pubkey, amount_msat: u64) -> error::Result<PaymentHash> {
+        let (payment_preimage, payment_hash, route_params) =
+            self.keysend_parameters(destination, amount_msat);
         log::info!("Initialised Keysend");
         let payment_result = self
             .channel_manager
@@ -177,7 +842,292 @@ impl OffchainManager {
                 Retry::Timeout(Duration::from_secs(10)),
             )
             .map_err(|err| error::anyhow!("{:?}", err))?;
+        self.stats.payment_started(payment_hash.0);
+        self.payments
+            .payment_started(&payment_hash.to_string(), Some(amount_msat), None)?;
         log::info!("Keysend successfully done!");
         Ok(payment_result)
     }
+
+    /// Send a keysend along a route that satisfies the first hop
+    /// constraints, like `pay_invoice_through` the payment is not retried.
+    pub fn keysend_through(
+        &self,
+        destination: pubkey,
+        amount_msat: u64,
+        first_hops: &FirstHops,
+    ) -> error::Result<PaymentHash> {
+        let (payment_preimage, payment_hash, route_params) =
+            self.keysend_parameters(destination, amount_msat);
+        let route = self.route_through(&route_params, first_hops)?;
+        self.channel_manager
+            .manager()
+            .send_spontaneous_payment(
+                &route,
+                Some(payment_preimage),
+                RecipientOnionFields::spontaneous_empty(),
+                PaymentId(payment_hash.0),
+            )
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        self.stats.payment_started(payment_hash.0);
+        self.payments
+            .payment_started(&payment_hash.to_string(), Some(amount_msat), None)?;
+        Ok(payment_hash)
+    }
+
+    /// Find a route to the `destination` for the `amount_msat` with
+    /// the same router used by the channel manager to pay.
+    pub fn find_route(&self, destination: pubkey, amount_msat: u64) -> error::Result<Route> {
+        self.find_route_through(destination, amount_msat, None)
+    }
+
+    /// Find a route to `destination`, when `first_hop` is set the
+    /// route leaves only from our channel with that short channel id.
+    pub fn find_route_through(
+        &self,
+        destination: pubkey,
+        amount_msat: u64,
+        first_hop: Option<u64>,
+    ) -> error::Result<Route> {
+        let manager = self.channel_manager.manager();
+        let payer = manager.get_our_node_id();
+        // The 40 here is the same final CLTV used by the keysend.
+        let route_params = RouteParameters::from_payment_params_and_value(
+            PaymentParameters::from_node_id(destination, 40),
+            amount_msat,
+        );
+        let mut first_hops = manager.list_usable_channels();
+        if let Some(short_channel_id) = first_hop {
+            first_hops
+                .retain(|channel| channel.get_outbound_payment_scid() == Some(short_channel_id));
+            if first_hops.is_empty() {
+                error::bail!("the channel `{short_channel_id}` is not usable to send a payment");
+            }
+        }
+        let route = self
+            .channel_manager
+            .router()
+            .find_route(
+                &payer,
+                &route_params,
+                Some(&first_hops.iter().collect::<Vec<_>>()),
+                manager.compute_inflight_htlcs(),
+            )
+            .map_err(|err| error::anyhow!("{}", err.err))?;
+        Ok(route)
+    }
+
+    /// Build a route that leaves from the channel `from` and comes back
+    /// to us from the channel `to`, in this way `amount_msat` of liquidity
+    /// is moved from `from` to `to`.
+    pub fn circular_route(
+        &self,
+        from: &ChannelDetails,
+        to: &ChannelDetails,
+        amount_msat: u64,
+        max_fee_msat: u64,
+    ) -> error::Result<Route> {
+        let manager = self.channel_manager.manager();
+        let our_node_id = manager.get_our_node_id();
+        let Some(scid) = to.get_inbound_payment_scid() else {
+            error::bail!("channel `{}` has no short channel id", to.channel_id);
+        };
+        let Some(forwarding) = to.counterparty.forwarding_info.as_ref() else {
+            error::bail!(
+                "unknown forwarding policy of `{}` for the channel `{}`",
+                to.counterparty.node_id,
+                to.channel_id
+            );
+        };
+        // the fee that the counterparty of `to` wants to forward the payment back to us.
+        let last_hop_fee = forwarding.fee_base_msat as u64
+            + amount_msat * forwarding.fee_proportional_millionths as u64 / 1_000_000;
+        if last_hop_fee > max_fee_msat {
+            error::bail!(
+                "the fee `{last_hop_fee}` msat of `{}` is above the budget `{max_fee_msat}` msat",
+                to.counterparty.node_id
+            );
+        }
+        // The 40 here is the same final CLTV used by the keysend.
+        let mut route_params = RouteParameters::from_payment_params_and_value(
+            PaymentParameters::from_node_id(to.counterparty.node_id, 40),
+            amount_msat + last_hop_fee,
+        );
+        route_params.max_total_routing_fee_msat = Some(max_fee_msat - last_hop_fee);
+        let mut route = self
+            .channel_manager
+            .router()
+            .find_route(
+                &our_node_id,
+                &route_params,
+                Some(&[from]),
+                manager.compute_inflight_htlcs(),
+            )
+            .map_err(|err| error::anyhow!("{}", err.err))?;
+        let conf = &self.lampo_conf.ldk_conf;
+        for path in route.paths.iter_mut() {
+            let last = path
+                .hops
+                .last_mut()
+                .expect("a path contains at least one hop");
+            let final_cltv = last.cltv_expiry_delta;
+            last.fee_msat = last_hop_fee;
+            last.cltv_expiry_delta = forwarding.cltv_expiry_delta as u32;
+            path.hops.push(RouteHop {
+                pubkey: our_node_id,
+                node_features: provided_node_features(conf),
+                short_channel_id: scid,
+                channel_features: provided_channel_features(conf),
+                fee_msat: amount_msat,
+                cltv_expiry_delta: final_cltv,
+                maybe_announced_channel: to.is_public,
+            });
+        }
+        Ok(route)
+    }
+
+    /// Pay ourself along the circular `route`.
+    pub fn pay_circular_route(
+        &self,
+        route: &Route,
+        amount_msat: u64,
+    ) -> error::Result<PaymentHash> {
+        let manager = self.channel_manager.manager();
+        let (payment_hash, payment_secret) = manager
+            .create_inbound_payment(Some(amount_msat), 3600, None)
+            .map_err(|_| error::anyhow!("impossible create the inbound payment"))?;
+        let payment_id = PaymentId(payment_hash.0);
+        manager
+            .send_payment_with_route(
+                route,
+                payment_hash,
+                RecipientOnionFields::secret_only(payment_secret),
+                payment_id,
+            )
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        self.stats.payment_started(payment_id.0);
+        self.payments
+            .payment_started(&payment_hash.to_string(), Some(amount_msat), None)?;
+        Ok(payment_hash)
+    }
+
+    /// Send a probe along the `path`, the probe will never be
+    /// claimed by the last hop so no funds are moved.
+    pub fn send_probe(&self, path: Path) -> error::Result<PaymentHash> {
+        let (payment_hash, _) = self
+            .channel_manager
+            .manager()
+            .send_probe(path)
+            .map_err(|err| error::anyhow!("{:?}", err))?;
+        Ok(payment_hash)
+    }
+
+    /// Check that our channel, the first hop of the `path`, can send
+    /// the amount of the path.
+    ///
+    /// LDK does not probe a path with less than two hops, so our
+    /// channel is checked with the local details.
+    pub fn first_hop_reaches(&self, path: &Path) -> bool {
+        let Some(first) = path.hops.first() else {
+            return false;
+        };
+        let amount_msat = path.hops.iter().map(|hop| hop.fee_msat).sum::<u64>();
+        self.channel_manager
+            .manager()
+            .list_usable_channels()
+            .iter()
+            .any(|channel| {
+                channel.get_outbound_payment_scid() == Some(first.short_channel_id)
+                    && channel.next_outbound_htlc_limit_msat >= amount_msat
+            })
+    }
+
+    /// Split the `path` in all the prefixes that end at each hop,
+    /// so each hop can be probed one by one.
+    ///
+    /// The last hop of each prefix receives the amount and the cltv
+    /// expiry that it would need to forward the payment to the final
+    /// destination, so each prefix is probing with an incrementing amount
+    /// and expiry.
+    ///
+    /// The prefixes start at two hops because LDK does not probe
+    /// our own channel, see [`Self::first_hop_reaches`].
+    pub fn traceroute_paths(path: &Path) -> Vec<Path> {
+        let mut paths = Vec::new();
+        for idx in 1..path.hops.len() {
+            let mut hops = path.hops[..idx + 1].to_vec();
+            let remaining = &path.hops[idx..];
+            let last = hops.last_mut().expect("the prefix contains two hops");
+            last.fee_msat = remaining.iter().map(|hop| hop.fee_msat).sum();
+            last.cltv_expiry_delta = remaining.iter().map(|hop| hop.cltv_expiry_delta).sum();
+            paths.push(Path {
+                hops,
+                blinded_tail: None,
+            });
+        }
+        paths
+    }
+}
+
+/// The `channel` is identified by one of the `ids`, as channel
+/// id or short channel id.
+fn channel_matches(channel: &ChannelDetails, ids: &[String]) -> bool {
+    let channel_id = channel.channel_id.to_string();
+    let short_channel_id = channel.short_channel_id.map(|scid| scid.to_string());
+    ids.iter()
+        .any(|id| *id == channel_id || Some(id) == short_channel_id.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use lampo_common::bitcoin::secp256k1::PublicKey;
+    use lampo_common::ldk::ln::features::{ChannelFeatures, NodeFeatures};
+    use lampo_common::ldk::routing::router::{Path, RouteHop};
+
+    use super::OffchainManager;
+
+    fn path(hops: u64) -> Path {
+        let pubkey = PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let hops = (1..=hops)
+            .map(|scid| RouteHop {
+                pubkey,
+                node_features: NodeFeatures::empty(),
+                short_channel_id: scid,
+                channel_features: ChannelFeatures::empty(),
+                fee_msat: scid * 1000,
+                cltv_expiry_delta: 40,
+                maybe_announced_channel: true,
+            })
+            .collect();
+        Path {
+            hops,
+            blinded_tail: None,
+        }
+    }
+
+    #[test]
+    fn traceroute_paths() {
+        let paths = OffchainManager::traceroute_paths(&path(3));
+        assert_eq!(paths.len(), 2);
+        assert!(paths.iter().all(|path| path.hops.len() >= 2));
+
+        let last = paths[0].hops.last().unwrap();
+        assert_eq!(last.short_channel_id, 2);
+        assert_eq!(last.fee_msat, 5000);
+        assert_eq!(last.cltv_expiry_delta, 80);
+        let last = paths[1].hops.last().unwrap();
+        assert_eq!(last.short_channel_id, 3);
+        assert_eq!(last.fee_msat, 3000);
+        assert_eq!(last.cltv_expiry_delta, 40);
+    }
+
+    #[test]
+    fn traceroute_direct_channel() {
+        assert!(OffchainManager::traceroute_paths(&path(1)).is_empty());
+    }
 }
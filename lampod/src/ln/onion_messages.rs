@@ -0,0 +1,135 @@
+//! Onion messages, the messages that are relayed through the
+//! network inside an onion like the payments.
+//!
+//! The bolt12 messages (invoice requests, invoices and invoice
+//! errors) are handled by the channel manager, the other messages
+//! are delivered to the external handlers with the `OnionMessage`
+//! event, and they can send their messages with `sendonionmessage`.
+//!
+//! The content of a custom message is opaque for us, we only
+//! check that its type does not collide with the bolt12 types.
+use std::sync::{Arc, Mutex};
+
+use lampo_common::bitcoin::secp256k1::PublicKey;
+use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::ldk::io;
+use lampo_common::ldk::ln::msgs::DecodeError;
+use lampo_common::ldk::onion_message::messenger::{
+    CustomOnionMessageHandler, Destination, PendingOnionMessage, Responder, ResponseInstruction,
+};
+use lampo_common::ldk::onion_message::packet::OnionMessageContents;
+use lampo_common::ldk::util::ser::{Writeable, Writer};
+
+use crate::actions::handler::LampoHandler;
+use crate::ln::LampoPeerManager;
+
+/// The custom onion messages must have a type greater or equal
+/// than this, the lower types are reserved by the spec.
+const MIN_CUSTOM_TLV_TYPE: u64 = 64;
+/// Types of the bolt12 messages: invoice request, invoice
+/// and invoice error.
+const OFFERS_TLV_TYPES: [u64; 3] = [64, 66, 68];
+
+/// A custom onion message, `data` is sent as it is.
+#[derive(Clone, Debug)]
+pub struct LampoOnionMessage {
+    pub tlv_type: u64,
+    pub data: Vec<u8>,
+}
+
+impl OnionMessageContents for LampoOnionMessage {
+    fn tlv_type(&self) -> u64 {
+        self.tlv_type
+    }
+
+    fn msg_type(&self) -> &'static str {
+        "Lampo Custom Message"
+    }
+}
+
+impl Writeable for LampoOnionMessage {
+    fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+        writer.write_all(&self.data)
+    }
+}
+
+#[derive(Default)]
+pub struct LampoOnionMessages {
+    handler: Mutex<Option<Arc<LampoHandler>>>,
+    // messages that the onion messenger must send
+    outbox: Mutex<Vec<PendingOnionMessage<LampoOnionMessage>>>,
+}
+
+impl LampoOnionMessages {
+    /// Set where the received messages are delivered, the
+    /// messages received before are dropped.
+    pub fn set_handler(&self, handler: Arc<LampoHandler>) {
+        *self.handler.lock().unwrap() = Some(handler);
+    }
+
+    /// Send a custom message to `node_id`, the message is relayed by
+    /// the network so the destination can be also a node that is not
+    /// our peer, if we know a path to it inside the network graph.
+    pub fn send(
+        &self,
+        peer_manager: &LampoPeerManager,
+        node_id: PublicKey,
+        tlv_type: u64,
+        data: Vec<u8>,
+    ) -> error::Result<()> {
+        if tlv_type < MIN_CUSTOM_TLV_TYPE || OFFERS_TLV_TYPES.contains(&tlv_type) {
+            error::bail!("`{tlv_type}` is not a custom onion message type");
+        }
+        self.outbox.lock().unwrap().push((
+            LampoOnionMessage { tlv_type, data },
+            Destination::Node(node_id),
+            None,
+        ));
+        peer_manager.manager().process_events();
+        Ok(())
+    }
+}
+
+impl CustomOnionMessageHandler for LampoOnionMessages {
+    type CustomMessage = LampoOnionMessage;
+
+    fn handle_custom_message(
+        &self,
+        message: Self::CustomMessage,
+        _responder: Option<Responder>,
+    ) -> ResponseInstruction<Self::CustomMessage> {
+        match self.handler.lock().unwrap().as_ref() {
+            Some(handler) => handler.emit(Event::Lightning(LightningEvent::OnionMessage {
+                tlv_type: message.tlv_type,
+                data: hex::encode(&message.data),
+            })),
+            None => {
+                log::debug!(target: "onion_messages", "ignoring onion message of type `{}`, the handler is not ready", message.tlv_type)
+            }
+        }
+        ResponseInstruction::NoResponse
+    }
+
+    fn read_custom_message<R: io::Read>(
+        &self,
+        message_type: u64,
+        buffer: &mut R,
+    ) -> Result<Option<Self::CustomMessage>, DecodeError> {
+        if message_type < MIN_CUSTOM_TLV_TYPE {
+            return Ok(None);
+        }
+        let mut data = Vec::new();
+        buffer.read_to_end(&mut data)?;
+        Ok(Some(LampoOnionMessage {
+            tlv_type: message_type,
+            data,
+        }))
+    }
+
+    fn release_pending_custom_messages(&self) -> Vec<PendingOnionMessage<Self::CustomMessage>> {
+        std::mem::take(&mut *self.outbox.lock().unwrap())
+    }
+}
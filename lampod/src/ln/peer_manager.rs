@@ -1,6 +1,6 @@
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
 
 use async_trait::async_trait;
@@ -9,12 +9,11 @@ use lampo_common::conf::LampoConf;
 use lampo_common::error;
 use lampo_common::keys::LampoKeysManager;
 use lampo_common::ldk;
-use lampo_common::ldk::blinded_path::EmptyNodeIdLookUp;
 use lampo_common::ldk::ln::peer_handler::MessageHandler;
-use lampo_common::ldk::ln::peer_handler::{IgnoringMessageHandler, PeerManager};
+use lampo_common::ldk::ln::peer_handler::PeerManager;
 use lampo_common::ldk::net;
 use lampo_common::ldk::net::SocketDescriptor;
-use lampo_common::ldk::onion_message::messenger::{DefaultMessageRouter, OnionMessenger};
+use lampo_common::ldk::onion_message::messenger::OnionMessenger;
 use lampo_common::ldk::routing::gossip::{NetworkGraph, P2PGossipSync};
 use lampo_common::model::Connect;
 use lampo_common::types::NodeId;
@@ -22,29 +21,36 @@ use lampo_common::types::NodeId;
 use crate::async_run;
 use crate::chain::{LampoChainManager, WalletManager};
 use crate::ln::LampoChannelManager;
+use crate::stats::LampoStats;
 use crate::utils::logger::LampoLogger;
 
-use super::channel_manager::{LampoArcChannelManager, LampoChainMonitor, LampoGraph};
+use super::channel_manager::{LampoArcChannelManager, LampoChainMonitor};
 use super::events::PeerEvents;
+use super::lsp::LampoLspClient;
+use super::onion_messages::LampoOnionMessages;
 use super::peer_event;
+use super::LampoRouter;
 
-pub type LampoArcOnionMessenger<L> = OnionMessenger<
+/// The channel manager handles the bolt12 messages, and it knows the
+/// short channel ids of our channels when we relay a message that is
+/// inside a blinded path.
+pub type LampoArcOnionMessenger<M, T, L> = OnionMessenger<
     Arc<LampoKeysManager>,
     Arc<LampoKeysManager>,
     Arc<L>,
-    Arc<EmptyNodeIdLookUp>,
-    Arc<DefaultMessageRouter<Arc<LampoGraph>, Arc<L>, Arc<LampoKeysManager>>>,
-    IgnoringMessageHandler,
-    IgnoringMessageHandler,
+    Arc<LampoArcChannelManager<M, T, T, L>>,
+    Arc<LampoRouter>,
+    Arc<LampoArcChannelManager<M, T, T, L>>,
+    Arc<LampoOnionMessages>,
 >;
 
 pub type SimpleArcPeerManager<M, T, L> = PeerManager<
     SocketDescriptor,
     Arc<LampoArcChannelManager<M, T, T, L>>,
     Arc<P2PGossipSync<Arc<NetworkGraph<Arc<L>>>, Arc<T>, Arc<L>>>,
-    Arc<LampoArcOnionMessenger<L>>,
+    Arc<LampoArcOnionMessenger<M, T, L>>,
     Arc<L>,
-    IgnoringMessageHandler,
+    Arc<LampoLspClient>,
     Arc<LampoKeysManager>,
 >;
 
@@ -53,18 +59,36 @@ type InnerLampoPeerManager =
 
 pub struct LampoPeerManager {
     peer_manager: Option<Arc<InnerLampoPeerManager>>,
+    lsp_client: Option<Arc<LampoLspClient>>,
+    onion_messages: Option<Arc<LampoOnionMessages>>,
     channel_manager: Option<Arc<LampoChannelManager>>,
     conf: LampoConf,
     logger: Arc<LampoLogger>,
+    stats: Arc<LampoStats>,
+    /// The address that we announce inside the node announcement,
+    /// it can change when the public ip discovery is enabled.
+    announced_addr: Arc<Mutex<String>>,
 }
 
 impl LampoPeerManager {
-    pub fn new(conf: &LampoConf, logger: Arc<LampoLogger>) -> LampoPeerManager {
+    pub fn new(
+        conf: &LampoConf,
+        logger: Arc<LampoLogger>,
+        stats: Arc<LampoStats>,
+    ) -> LampoPeerManager {
+        let announced_addr = conf
+            .announce_addr
+            .clone()
+            .unwrap_or_else(|| "127.0.0.1".to_string());
         LampoPeerManager {
             peer_manager: None,
+            lsp_client: None,
+            onion_messages: None,
             conf: conf.to_owned(),
             logger,
             channel_manager: None,
+            stats,
+            announced_addr: Arc::new(Mutex::new(announced_addr)),
         }
     }
 
@@ -72,6 +96,14 @@ impl LampoPeerManager {
         self.peer_manager.clone().unwrap()
     }
 
+    pub fn lsp_client(&self) -> Arc<LampoLspClient> {
+        self.lsp_client.clone().unwrap()
+    }
+
+    pub fn onion_messages(&self) -> Arc<LampoOnionMessages> {
+        self.onion_messages.clone().unwrap()
+    }
+
     pub fn init(
         &mut self,
         _onchain_manager: Arc<LampoChainManager>,
@@ -86,14 +118,16 @@ impl LampoPeerManager {
 
         let keys = wallet_manager.ldk_keys().keys_manager.clone();
         let graph = channel_manager.graph();
+        let channeld = channel_manager.manager();
+        let onion_messages = Arc::new(LampoOnionMessages::default());
         let onion_messenger = Arc::new(OnionMessenger::new(
             keys.clone(),
             keys.clone(),
             self.logger.clone(),
-            Arc::new(EmptyNodeIdLookUp {}),
-            Arc::new(DefaultMessageRouter::new(graph.clone(), keys.clone())),
-            IgnoringMessageHandler {},
-            IgnoringMessageHandler {},
+            channeld.clone(),
+            channel_manager.router(),
+            channeld,
+            onion_messages.clone(),
         ));
 
         let gossip_sync = Arc::new(P2PGossipSync::new(
@@ -102,11 +136,12 @@ impl LampoPeerManager {
             self.logger.clone(),
         ));
 
+        let lsp_client = Arc::new(LampoLspClient::new(&self.conf)?);
         let lightning_msg_handler = MessageHandler {
             chan_handler: channel_manager.channeld.clone().unwrap(),
             onion_message_handler: onion_messenger,
             route_handler: gossip_sync,
-            custom_message_handler: IgnoringMessageHandler {},
+            custom_message_handler: lsp_client.clone(),
         };
 
         let peer_manager = InnerLampoPeerManager::new(
@@ -117,12 +152,14 @@ impl LampoPeerManager {
             wallet_manager.ldk_keys().keys_manager.clone(),
         );
         self.peer_manager = Some(Arc::new(peer_manager));
+        self.lsp_client = Some(lsp_client);
+        self.onion_messages = Some(onion_messages);
         self.channel_manager = Some(channel_manager.clone());
         Ok(())
     }
 
     pub fn run(&self) -> error::Result<()> {
-        let listen_port = self.conf.port;
+        let listen_port = u16::try_from(self.conf.port)?;
         let Some(ref peer_manager) = self.peer_manager else {
             error::bail!("peer manager is None, at this point this should be not None");
         };
@@ -131,6 +168,8 @@ impl LampoPeerManager {
             .channel_manager
             .clone()
             .ok_or(error::anyhow!("channel manager is None"))?;
+        let stats = self.stats.clone();
+        let announced_addr = self.announced_addr.clone();
         let alias = self.conf.alias.clone().unwrap_or_default();
         let addr = self
             .conf
@@ -139,9 +178,9 @@ impl LampoPeerManager {
             .unwrap_or_else(|| "127.0.0.1".to_string());
         std::thread::spawn(move || {
             let result = async_run!(async move {
-                let bind_addr = format!("{addr}:{listen_port}");
-                log::info!(target: "lampo", "Listening for in-bound connection on {bind_addr}");
-                let listener = match tokio::net::TcpListener::bind(bind_addr.clone()).await {
+                let bind_addr = (addr.as_str(), listen_port);
+                log::info!(target: "lampo", "Listening for in-bound connection on {bind_addr:?}");
+                let listener = match tokio::net::TcpListener::bind(bind_addr).await {
                     Ok(listener) => listener,
                     Err(e) => {
                         return Err::<(), _>(error::anyhow!("Error binding to address: {}", e));
@@ -152,21 +191,25 @@ impl LampoPeerManager {
                     let alias = alias.clone();
                     let peer_manager = peer_manager.clone();
                     let chan_manager = chan_manager.clone();
+                    let stats = stats.clone();
+                    let announced_addr = announced_addr.clone();
                     let accept = listener.accept().await;
                     let accept = accept
                         .map_err(|err| error::anyhow!("Error accepting connection: {}", err))?;
                     match accept {
                         (tcp_stream, _) => {
                             log::info!(target: "lampo", "Got new connection {}", tcp_stream.peer_addr().unwrap());
-                            let addr = bind_addr.clone();
                             let _ = tokio::spawn(async move {
                                 // Use LDK's supplied networking battery to facilitate inbound
                                 // connections.
                                 net::setup_inbound(
                                     peer_manager.clone(),
-                                    tcp_stream.into_std().expect("impossible to convert a tpc_stream from tokio to std"),
+                                    tcp_stream.into_std().expect(
+                                        "impossible to convert a tpc_stream from tokio to std",
+                                    ),
                                 )
                                 .await;
+                                stats.peer_disconnected();
 
                                 // Then, update our announcement once an hour to keep it fresh but avoid unnecessary churn
                                 // in the global gossip network.
@@ -183,12 +226,15 @@ impl LampoPeerManager {
                                         .iter()
                                         .any(|chan| chan.is_public)
                                     {
-                                        peer_manager.broadcast_node_announcement(
-                                            [0; 3],
-                                            alias.as_bytes().try_into().unwrap_or([0u8; 32]),
-                                            vec![ldk::ln::msgs::SocketAddress::from_str(&addr)
-                                                .expect("impossible to convert an addr to ln socket addr (wire format)")],
-                                        );
+                                        let addr = announced_addr.lock().unwrap().clone();
+                                        match socket_address(&addr, listen_port) {
+                                            Ok(addr) => peer_manager.broadcast_node_announcement(
+                                                [0; 3],
+                                                node_alias(&alias),
+                                                vec![addr],
+                                            ),
+                                            Err(err) => log::warn!(target: "lampo", "{err}"),
+                                        }
                                     }
                                 }
                             })
@@ -206,6 +252,24 @@ impl LampoPeerManager {
         Ok(())
     }
 
+    pub fn announced_addr(&self) -> String {
+        self.announced_addr.lock().unwrap().clone()
+    }
+
+    /// Change the address that we announce to the network, and
+    /// broadcast the new node announcement.
+    pub fn update_announced_addr(&self, addr: &str) -> error::Result<()> {
+        let socket_addr = socket_address(addr, u16::try_from(self.conf.port)?)?;
+        *self.announced_addr.lock().unwrap() = addr.to_owned();
+
+        let alias = self.conf.alias.clone().unwrap_or_default();
+        // the node announcement is dropped by our peers if we
+        // do not have any public channel.
+        self.manager()
+            .broadcast_node_announcement([0; 3], node_alias(&alias), vec![socket_addr]);
+        Ok(())
+    }
+
     pub fn is_connected_with(&self, peer_id: NodeId) -> bool {
         let Some(ref manager) = self.peer_manager else {
             panic!("at this point the peer manager should be known");
@@ -214,6 +278,27 @@ impl LampoPeerManager {
     }
 }
 
+/// The address `addr` (an ip or a host name) with the `port`, an
+/// IPv6 address can not be joined to the port with a `:`.
+fn socket_address(addr: &str, port: u16) -> error::Result<ldk::ln::msgs::SocketAddress> {
+    if let Ok(ip) = IpAddr::from_str(addr) {
+        return Ok(SocketAddr::new(ip, port).into());
+    }
+    let addr = format!("{addr}:{port}");
+    ldk::ln::msgs::SocketAddress::from_str(&addr)
+        .map_err(|err| error::anyhow!("invalid address `{addr}`: {:?}", err))
+}
+
+/// Encode the alias in the 32 bytes of the node announcement,
+/// an alias that is too long is truncated.
+fn node_alias(alias: &str) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    let alias = alias.as_bytes();
+    let len = alias.len().min(32);
+    bytes[..len].copy_from_slice(&alias[..len]);
+    bytes
+}
+
 #[async_trait]
 impl PeerEvents for LampoPeerManager {
     async fn handle(&self, event: super::peer_event::PeerCommand) -> error::Result<()> {
@@ -221,8 +306,8 @@ impl PeerEvents for LampoPeerManager {
             peer_event::PeerCommand::Connect(node_id, addr, chan) => {
                 let connect = Connect {
                     node_id: node_id.to_string(),
-                    addr: addr.ip().to_string(),
-                    port: addr.port() as u64,
+                    addr: Some(addr.ip().to_string()),
+                    port: Some(addr.port() as u64),
                 };
                 self.connect(node_id, addr).await?;
                 chan.send(connect)?;
@@ -242,13 +327,23 @@ impl PeerEvents for LampoPeerManager {
             match futures::poll!(&mut connection_closed_future) {
                 std::task::Poll::Ready(_) => {
                     log::info!("node `{node_id}` disconnected");
+                    self.stats.peer_disconnected();
                     return Ok(());
                 }
                 std::task::Poll::Pending => {}
             }
             // Avoid blocking the tokio context by sleeping a bit
             match manager.peer_by_node_id(&node_id) {
-                Some(_) => return Ok(()),
+                Some(_) => {
+                    // Keep track of the disconnection when the connection is closed
+                    let stats = self.stats.clone();
+                    tokio::spawn(async move {
+                        connection_closed_future.await;
+                        log::info!("node `{node_id}` disconnected");
+                        stats.peer_disconnected();
+                    });
+                    return Ok(());
+                }
                 None => tokio::time::sleep(Duration::from_millis(10)).await,
             }
         }
@@ -0,0 +1,113 @@
+//! Background prober implementation.
+//!
+//! The prober is an opt-in component that periodically sends
+//! small probes toward some destination, in this way the scorer
+//! is able to learn about the liquidity of the network before
+//! we actually need to pay someone.
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::ldk::sign::EntropySource;
+use lampo_common::types::NodeId;
+
+use super::{LampoChannelManager, OffchainManager};
+use crate::chain::WalletManager;
+
+pub struct LampoProber {
+    conf: LampoConf,
+    channel_manager: Arc<LampoChannelManager>,
+    offchain_manager: Arc<OffchainManager>,
+    wallet_manager: Arc<dyn WalletManager>,
+}
+
+impl LampoProber {
+    pub fn new(
+        conf: &LampoConf,
+        channel_manager: Arc<LampoChannelManager>,
+        offchain_manager: Arc<OffchainManager>,
+        wallet_manager: Arc<dyn WalletManager>,
+    ) -> Self {
+        Self {
+            conf: conf.clone(),
+            channel_manager,
+            offchain_manager,
+            wallet_manager,
+        }
+    }
+
+    /// Pick the next destination to probe, this is one of the configured
+    /// destination or a random node from the network graph.
+    fn next_destination(&self) -> error::Result<NodeId> {
+        let random = self
+            .wallet_manager
+            .ldk_keys()
+            .keys_manager
+            .get_secure_random_bytes();
+        let mut idx = [0u8; 8];
+        idx.copy_from_slice(&random[..8]);
+        let idx = u64::from_le_bytes(idx) as usize;
+
+        if !self.conf.probe_destinations.is_empty() {
+            let destination =
+                &self.conf.probe_destinations[idx % self.conf.probe_destinations.len()];
+            return Ok(NodeId::from_str(destination)?);
+        }
+
+        let our_node_id = self.channel_manager.manager().get_our_node_id();
+        let graph = self.channel_manager.graph();
+        let graph = graph.read_only();
+        let nodes = graph
+            .nodes()
+            .unordered_keys()
+            .filter_map(|node_id| node_id.as_pubkey().ok())
+            .filter(|node_id| *node_id != our_node_id)
+            .collect::<Vec<_>>();
+        if nodes.is_empty() {
+            error::bail!("no nodes inside the network graph to probe");
+        }
+        Ok(nodes[idx % nodes.len()])
+    }
+
+    /// Send a single probe, the probe is skipped if the fees that
+    /// we are risking are above the configured budget.
+    pub fn probe(&self) -> error::Result<()> {
+        let destination = self.next_destination()?;
+        let route = self
+            .offchain_manager
+            .find_route(destination, self.conf.probe_amount_msat)?;
+        let fees = route.get_total_fees();
+        if fees > self.conf.probe_max_fee_msat {
+            error::bail!(
+                "probe to `{destination}` skipped, fees `{fees}` msat are above the budget of `{}` msat",
+                self.conf.probe_max_fee_msat
+            );
+        }
+        for path in route.paths {
+            // LDK does not probe a path with less than two hops,
+            // and our own channels are already known.
+            if path.hops.len() < 2 {
+                continue;
+            }
+            let payment_hash = self.offchain_manager.send_probe(path)?;
+            log::debug!(target: "prober", "probe `{payment_hash}` sent to `{destination}`");
+        }
+        Ok(())
+    }
+
+    pub fn run(self: Arc<Self>) -> error::Result<JoinHandle<()>> {
+        let Some(interval) = self.conf.probe_interval else {
+            error::bail!("the prober is disabled, set `probe-interval` to enable it");
+        };
+        log::info!(target: "prober", "starting the prober with an interval of `{interval}` seconds");
+        Ok(std::thread::spawn(move || loop {
+            std::thread::sleep(Duration::from_secs(interval));
+            if let Err(err) = self.probe() {
+                log::warn!(target: "prober", "{err}");
+            }
+        }))
+    }
+}
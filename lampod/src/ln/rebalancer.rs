@@ -0,0 +1,148 @@
+//! Background rebalancer implementation.
+//!
+//! The rebalancer is an opt-in component that periodically checks
+//! the outbound ratio of our channels, and moves the liquidity from
+//! the channel with the highest ratio to the one with the lowest
+//! with a circular payment, when the fees are inside the budget.
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::ldk::ln::channelmanager::ChannelDetails;
+use lampo_common::model::response::{PaymentState, RebalanceAction};
+
+use super::{LampoChannelManager, OffchainManager};
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const REBALANCER_NAMESPACE: &str = "rebalancer";
+const REPORT_KEY: &str = "report";
+/// Number of actions that we keep inside the report.
+const MAX_ACTIONS: usize = 100;
+
+pub struct LampoRebalancer {
+    conf: LampoConf,
+    channel_manager: Arc<LampoChannelManager>,
+    offchain_manager: Arc<OffchainManager>,
+    persister: Arc<LampoPersistence>,
+}
+
+impl LampoRebalancer {
+    pub fn new(
+        conf: &LampoConf,
+        channel_manager: Arc<LampoChannelManager>,
+        offchain_manager: Arc<OffchainManager>,
+        persister: Arc<LampoPersistence>,
+    ) -> Self {
+        Self {
+            conf: conf.clone(),
+            channel_manager,
+            offchain_manager,
+            persister,
+        }
+    }
+
+    /// The actions made by the rebalancer, the most recent last.
+    pub fn report(persister: &LampoPersistence) -> error::Result<Vec<RebalanceAction>> {
+        Ok(persister
+            .read_json(REBALANCER_NAMESPACE, REPORT_KEY)?
+            .unwrap_or_default())
+    }
+
+    fn record(&self, action: RebalanceAction) -> error::Result<()> {
+        let mut actions = Self::report(&self.persister)?;
+        actions.push(action);
+        if actions.len() > MAX_ACTIONS {
+            actions.drain(..actions.len() - MAX_ACTIONS);
+        }
+        self.persister
+            .write_json(REBALANCER_NAMESPACE, REPORT_KEY, &actions)
+    }
+
+    /// Outbound ratio of the channel in percent.
+    fn ratio(channel: &ChannelDetails) -> u64 {
+        if channel.channel_value_satoshis == 0 {
+            return 0;
+        }
+        channel.outbound_capacity_msat / (channel.channel_value_satoshis * 10)
+    }
+
+    /// Make a single rebalance if there is a channel below the
+    /// range and one above.
+    pub fn rebalance(&self) -> error::Result<()> {
+        let channels = self.channel_manager.manager().list_usable_channels();
+        let Some(to) = channels
+            .iter()
+            .filter(|channel| Self::ratio(channel) < self.conf.rebalance_low_pct)
+            .min_by_key(|channel| Self::ratio(channel))
+        else {
+            return Ok(());
+        };
+        let Some(from) = channels
+            .iter()
+            .filter(|channel| Self::ratio(channel) > self.conf.rebalance_high_pct)
+            .max_by_key(|channel| Self::ratio(channel))
+        else {
+            log::debug!(target: "rebalancer", "channel `{}` needs liquidity, but there is no channel to take it from", to.channel_id);
+            return Ok(());
+        };
+
+        // move both the channels toward the half of the range
+        let target = (self.conf.rebalance_low_pct + self.conf.rebalance_high_pct) / 2;
+        let excess = from
+            .outbound_capacity_msat
+            .saturating_sub(from.channel_value_satoshis * 10 * target);
+        let deficit =
+            (to.channel_value_satoshis * 10 * target).saturating_sub(to.outbound_capacity_msat);
+        let amount_msat = excess.min(deficit).min(from.next_outbound_htlc_limit_msat);
+        if amount_msat == 0 {
+            return Ok(());
+        }
+        let max_fee_msat = amount_msat * self.conf.rebalance_max_fee_ppm / 1_000_000;
+
+        let mut action = RebalanceAction {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            from_channel_id: from.channel_id.to_string(),
+            to_channel_id: to.channel_id.to_string(),
+            amount_msat,
+            fee_msat: None,
+            payment_hash: None,
+            state: PaymentState::Failure,
+            message: None,
+        };
+        let result = self
+            .offchain_manager
+            .circular_route(from, to, amount_msat, max_fee_msat)
+            .and_then(|route| {
+                action.fee_msat = Some(route.get_total_fees());
+                self.offchain_manager
+                    .pay_circular_route(&route, amount_msat)
+            });
+        match result {
+            Ok(payment_hash) => {
+                log::info!(target: "rebalancer", "moving `{amount_msat}` msat from `{}` to `{}` with payment `{payment_hash}`", from.channel_id, to.channel_id);
+                action.payment_hash = Some(payment_hash.to_string());
+                action.state = PaymentState::Pending;
+            }
+            Err(err) => {
+                log::warn!(target: "rebalancer", "rebalance from `{}` to `{}` failed: {err}", from.channel_id, to.channel_id);
+                action.message = Some(err.to_string());
+            }
+        }
+        self.record(action)
+    }
+
+    pub fn run(self: Arc<Self>) -> error::Result<JoinHandle<()>> {
+        let Some(interval) = self.conf.rebalance_interval else {
+            error::bail!("the rebalancer is disabled, set `rebalance-interval` to enable it");
+        };
+        log::info!(target: "rebalancer", "starting the rebalancer with an interval of `{interval}` seconds");
+        Ok(std::thread::spawn(move || loop {
+            std::thread::sleep(Duration::from_secs(interval));
+            if let Err(err) = self.rebalance() {
+                log::warn!(target: "rebalancer", "{err}");
+            }
+        }))
+    }
+}
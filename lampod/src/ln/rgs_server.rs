@@ -0,0 +1,177 @@
+//! Rapid gossip sync server.
+//!
+//! Serve the snapshots of our network graph in the rapid gossip
+//! sync format, so the light clients of the operator can sync the
+//! gossip from their own infrastructure instead of a public server.
+//!
+//! The snapshots are served at `/snapshot/<timestamp>`, like the
+//! reference server, and contain only what changed after the
+//! `timestamp` of the last sync of the client (`0` for a full sync).
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use lampo_common::bitcoin::blockdata::constants::ChainHash;
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::ldk::routing::gossip::NodeId;
+use lampo_common::ldk::util::ser::{BigSize, Writeable};
+
+use super::LampoChannelManager;
+
+/// `LDK` followed by the version of the format.
+const GOSSIP_PREFIX: [u8; 4] = [76, 68, 75, 1];
+/// All the fields of the update are sent, so the defaults are never used.
+const UPDATE_FIELDS: u8 = 0b0111_1100;
+
+pub struct LampoRgsServer {
+    conf: LampoConf,
+    channel_manager: Arc<LampoChannelManager>,
+}
+
+impl LampoRgsServer {
+    pub fn new(conf: &LampoConf, channel_manager: Arc<LampoChannelManager>) -> Self {
+        Self {
+            conf: conf.clone(),
+            channel_manager,
+        }
+    }
+
+    /// Build the snapshot with the channels and the updates received
+    /// after `since`.
+    pub fn snapshot(&self, since: u32) -> error::Result<Vec<u8>> {
+        let graph = self.channel_manager.graph();
+        let graph = graph.read_only();
+        let mut channels = graph
+            .channels()
+            .unordered_iter()
+            .map(|(scid, channel)| (*scid, channel))
+            .collect::<Vec<_>>();
+        channels.sort_by_key(|(scid, _)| *scid);
+
+        let announcements = channels
+            .iter()
+            .copied()
+            .filter(|(_, channel)| since == 0 || channel.announcement_received_time > since as u64)
+            .collect::<Vec<_>>();
+        let mut node_ids: Vec<NodeId> = Vec::new();
+        let mut node_index: HashMap<NodeId, u64> = HashMap::new();
+        for (_, channel) in announcements.iter() {
+            for node_id in [channel.node_one, channel.node_two] {
+                node_index.entry(node_id).or_insert_with(|| {
+                    node_ids.push(node_id);
+                    node_ids.len() as u64 - 1
+                });
+            }
+        }
+
+        let mut buff = Vec::new();
+        buff.extend_from_slice(&GOSSIP_PREFIX);
+        buff.extend_from_slice(ChainHash::using_genesis_block(self.conf.network).as_bytes());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32;
+        now.write(&mut buff)?;
+
+        (node_ids.len() as u32).write(&mut buff)?;
+        for node_id in &node_ids {
+            node_id.write(&mut buff)?;
+        }
+
+        (announcements.len() as u32).write(&mut buff)?;
+        let mut previous_scid = 0;
+        for (scid, channel) in announcements {
+            channel.features.write(&mut buff)?;
+            BigSize(scid - previous_scid).write(&mut buff)?;
+            BigSize(node_index[&channel.node_one]).write(&mut buff)?;
+            BigSize(node_index[&channel.node_two]).write(&mut buff)?;
+            previous_scid = scid;
+        }
+
+        let updates = channels
+            .iter()
+            .flat_map(|(scid, channel)| {
+                [(0u8, &channel.one_to_two), (1u8, &channel.two_to_one)]
+                    .into_iter()
+                    .filter_map(move |(direction, update)| {
+                        Some((*scid, direction, update.as_ref()?))
+                    })
+            })
+            .filter(|(_, _, update)| update.last_update > since)
+            .collect::<Vec<_>>();
+        (updates.len() as u32).write(&mut buff)?;
+        if updates.is_empty() {
+            return Ok(buff);
+        }
+        // the defaults: cltv expiry delta, htlc minimum, fee base,
+        // fee proportional and htlc maximum.
+        0u16.write(&mut buff)?;
+        0u64.write(&mut buff)?;
+        0u32.write(&mut buff)?;
+        0u32.write(&mut buff)?;
+        0u64.write(&mut buff)?;
+        let mut previous_scid = 0;
+        for (scid, direction, update) in updates {
+            BigSize(scid - previous_scid).write(&mut buff)?;
+            let disabled = if update.enabled { 0 } else { 0b10 };
+            (UPDATE_FIELDS | disabled | direction).write(&mut buff)?;
+            update.cltv_expiry_delta.write(&mut buff)?;
+            update.htlc_minimum_msat.write(&mut buff)?;
+            update.fees.base_msat.write(&mut buff)?;
+            update.fees.proportional_millionths.write(&mut buff)?;
+            update.htlc_maximum_msat.write(&mut buff)?;
+            previous_scid = scid;
+        }
+        Ok(buff)
+    }
+
+    fn serve(&self, mut stream: TcpStream) -> error::Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        // e.g: `GET /snapshot/1700000000 HTTP/1.1`
+        let mut request = line.split_whitespace();
+        let since = match (request.next(), request.next()) {
+            (Some("GET"), Some(path)) => path
+                .strip_prefix("/snapshot/")
+                .and_then(|since| u32::from_str(since).ok()),
+            _ => None,
+        };
+        let Some(since) = since else {
+            return respond(&mut stream, "404 Not Found", &[]);
+        };
+        let snapshot = self.snapshot(since)?;
+        log::debug!(target: "rgs-server", "serving snapshot since `{since}` of {} bytes", snapshot.len());
+        respond(&mut stream, "200 OK", &snapshot)
+    }
+
+    pub fn run(self: Arc<Self>) -> error::Result<JoinHandle<()>> {
+        let Some(addr) = self.conf.rgs_server.clone() else {
+            error::bail!("the rapid gossip sync server is disabled, set `rgs-server` to enable it");
+        };
+        let listener = TcpListener::bind(&addr)?;
+        log::info!(target: "rgs-server", "serving the rapid gossip sync snapshots on `{addr}`");
+        Ok(std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream
+                    .map_err(error::Error::from)
+                    .and_then(|stream| self.serve(stream));
+                if let Err(err) = result {
+                    log::warn!(target: "rgs-server", "{err}");
+                }
+            }
+        }))
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &[u8]) -> error::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    Ok(())
+}
@@ -0,0 +1,189 @@
+//! Router of the node.
+//!
+//! The payments are routed by the ldk `DefaultRouter`, we only change
+//! how the blinded paths of the offers and of the refunds are built:
+//! each path has `blinded-path-hops` nodes of the network graph before
+//! us, so the payer knows only the introduction node of the path.
+//!
+//! The blinded paths of the bolt12 invoices still start from one of
+//! our peers, bolt11 invoices can not contain blinded paths.
+use std::sync::{Arc, Mutex};
+
+use lampo_common::bitcoin::secp256k1::{self, PublicKey, Secp256k1};
+use lampo_common::keys::LampoKeysManager;
+use lampo_common::ldk::blinded_path::message::ForwardNode;
+use lampo_common::ldk::blinded_path::payment::ReceiveTlvs;
+use lampo_common::ldk::blinded_path::BlindedPath;
+use lampo_common::ldk::ln::channelmanager::ChannelDetails;
+use lampo_common::ldk::ln::msgs::LightningError;
+use lampo_common::ldk::offers::invoice::BlindedPayInfo;
+use lampo_common::ldk::onion_message::messenger::{Destination, MessageRouter, OnionMessagePath};
+use lampo_common::ldk::routing::gossip::NodeId;
+use lampo_common::ldk::routing::router::{
+    DefaultRouter, InFlightHtlcs, Route, RouteParameters, Router,
+};
+use lampo_common::ldk::routing::scoring::ProbabilisticScoringFeeParameters;
+
+use super::channel_manager::{LampoGraph, LampoScorer};
+use crate::utils::logger::LampoLogger;
+
+/// The nodes with less channels are not used inside the blinded
+/// paths, otherwise it is easy to guess who is the recipient.
+const MIN_NODE_CHANNELS: usize = 3;
+/// Max number of blinded paths built for an offer.
+const MAX_BLINDED_PATHS: usize = 3;
+
+type InnerRouter = DefaultRouter<
+    Arc<LampoGraph>,
+    Arc<LampoLogger>,
+    Arc<LampoKeysManager>,
+    Arc<Mutex<LampoScorer>>,
+    ProbabilisticScoringFeeParameters,
+    LampoScorer,
+>;
+
+pub struct LampoRouter {
+    inner: InnerRouter,
+    graph: Arc<LampoGraph>,
+    keys_manager: Arc<LampoKeysManager>,
+    blinded_path_hops: u8,
+}
+
+impl LampoRouter {
+    pub fn new(
+        graph: Arc<LampoGraph>,
+        logger: Arc<LampoLogger>,
+        keys_manager: Arc<LampoKeysManager>,
+        scorer: Arc<Mutex<LampoScorer>>,
+        blinded_path_hops: u8,
+    ) -> Self {
+        Self {
+            inner: DefaultRouter::new(
+                graph.clone(),
+                logger,
+                keys_manager.clone(),
+                scorer,
+                ProbabilisticScoringFeeParameters::default(),
+            ),
+            graph,
+            keys_manager,
+            blinded_path_hops,
+        }
+    }
+
+    /// The nodes of a blinded path that reaches us through `peer`, from
+    /// the introduction node to `peer`. At each step we move away from
+    /// us through the neighbour with the most channels, `None` when the
+    /// graph does not contain enough nodes around `peer`.
+    fn blinded_hops(&self, recipient: &PublicKey, peer: PublicKey) -> Option<Vec<PublicKey>> {
+        let graph = self.graph.read_only();
+        let mut hops = vec![peer];
+        while hops.len() < self.blinded_path_hops as usize {
+            let introduction = NodeId::from_pubkey(&hops[0]);
+            let (next, _) = graph
+                .node(&introduction)?
+                .channels
+                .iter()
+                .filter_map(|short_channel_id| graph.channel(*short_channel_id))
+                .map(|channel| {
+                    if channel.node_one == introduction {
+                        channel.node_two
+                    } else {
+                        channel.node_one
+                    }
+                })
+                .filter_map(|node_id| {
+                    let channels = graph.node(&node_id)?.channels.len();
+                    Some((node_id.as_pubkey().ok()?, channels))
+                })
+                .filter(|(node_id, channels)| {
+                    *channels >= MIN_NODE_CHANNELS
+                        && node_id != recipient
+                        && !hops.contains(node_id)
+                })
+                .max_by_key(|(_, channels)| *channels)?;
+            hops.insert(0, next);
+        }
+        Some(hops)
+    }
+}
+
+impl MessageRouter for LampoRouter {
+    fn find_path(
+        &self,
+        sender: PublicKey,
+        peers: Vec<PublicKey>,
+        destination: Destination,
+    ) -> Result<OnionMessagePath, ()> {
+        self.inner.find_path(sender, peers, destination)
+    }
+
+    fn create_blinded_paths<T: secp256k1::Signing + secp256k1::Verification>(
+        &self,
+        recipient: PublicKey,
+        peers: Vec<PublicKey>,
+        secp_ctx: &Secp256k1<T>,
+    ) -> Result<Vec<BlindedPath>, ()> {
+        if self.blinded_path_hops == 0 {
+            let path = BlindedPath::one_hop_for_message(recipient, &*self.keys_manager, secp_ctx)?;
+            return Ok(vec![path]);
+        }
+        // the peers with the most channels are the best introduction nodes
+        let mut candidates = {
+            let graph = self.graph.read_only();
+            peers
+                .iter()
+                .filter_map(|peer| {
+                    let channels = graph.node(&NodeId::from_pubkey(peer))?.channels.len();
+                    (channels >= MIN_NODE_CHANNELS).then_some((*peer, channels))
+                })
+                .collect::<Vec<_>>()
+        };
+        candidates.sort_by_key(|(_, channels)| std::cmp::Reverse(*channels));
+        let paths = candidates
+            .into_iter()
+            .filter_map(|(peer, _)| self.blinded_hops(&recipient, peer))
+            .take(MAX_BLINDED_PATHS)
+            .map(|hops| {
+                let hops = hops
+                    .into_iter()
+                    .map(|node_id| ForwardNode {
+                        node_id,
+                        short_channel_id: None,
+                    })
+                    .collect::<Vec<_>>();
+                BlindedPath::new_for_message(&hops, recipient, &*self.keys_manager, secp_ctx)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if paths.is_empty() {
+            log::debug!(target: "router", "not enough nodes around us for `{}` blinded hops", self.blinded_path_hops);
+            return self.inner.create_blinded_paths(recipient, peers, secp_ctx);
+        }
+        Ok(paths)
+    }
+}
+
+impl Router for LampoRouter {
+    fn find_route(
+        &self,
+        payer: &PublicKey,
+        route_params: &RouteParameters,
+        first_hops: Option<&[&ChannelDetails]>,
+        inflight_htlcs: InFlightHtlcs,
+    ) -> Result<Route, LightningError> {
+        self.inner
+            .find_route(payer, route_params, first_hops, inflight_htlcs)
+    }
+
+    fn create_blinded_payment_paths<T: secp256k1::Signing + secp256k1::Verification>(
+        &self,
+        recipient: PublicKey,
+        first_hops: Vec<ChannelDetails>,
+        tlvs: ReceiveTlvs,
+        amount_msats: u64,
+        secp_ctx: &Secp256k1<T>,
+    ) -> Result<Vec<(BlindedPayInfo, BlindedPath)>, ()> {
+        self.inner
+            .create_blinded_payment_paths(recipient, first_hops, tlvs, amount_msats, secp_ctx)
+    }
+}
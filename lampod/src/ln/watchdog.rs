@@ -0,0 +1,184 @@
+//! Stale state watchdog.
+//!
+//! Starting from an old copy of the channel state (e.g: a restored
+//! backup) is dangerous, because if we broadcast a revoked commitment
+//! the peer can claim all the funds of the channel.
+//!
+//! So we keep track of the latest update of each channel monitor,
+//! and before starting the channel manager we check that the monitors
+//! read from disk are not older than the one seen in the last run.
+//! Ldk also panics when the peer proves with the `channel_reestablish`
+//! that our state is old, in this case we remember it and at the
+//! next start the node stays in safe mode.
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::event::onchain::OnChainEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::json;
+use lampo_common::ldk::chain::channelmonitor::ChannelMonitor;
+use lampo_common::ldk::sign::InMemorySigner;
+use lampo_common::model::response::StateWatermark;
+
+use super::LampoChannelManager;
+use crate::actions::handler::LampoHandler;
+
+const WATCHDOG_FILE: &str = "watchdog.json";
+/// Prefix of the message used by ldk when the peer proves that
+/// our state is old.
+const FALLEN_BEHIND: &str = "We have fallen behind";
+
+pub struct LampoWatchdog {
+    path: PathBuf,
+    allow_stale_state: bool,
+    lock: Mutex<()>,
+}
+
+impl LampoWatchdog {
+    pub fn new(conf: &LampoConf) -> Self {
+        let path = match &conf.watchdog_path {
+            Some(path) => PathBuf::from(path),
+            None => PathBuf::from(conf.path()).join(WATCHDOG_FILE),
+        };
+        Self {
+            path,
+            allow_stale_state: conf.allow_stale_state,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn read(&self) -> error::Result<StateWatermark> {
+        if !self.path.exists() {
+            return Ok(StateWatermark::default());
+        }
+        let content = fs::read_to_string(&self.path)?;
+        Ok(json::from_str(&content)?)
+    }
+
+    fn write(&self, watermark: &StateWatermark) -> error::Result<()> {
+        // write and rename, so a crash does not leave a broken file
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, json::to_string(watermark)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn enter_safe_mode(&self, reason: &str) -> error::Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        let mut watermark = self.read()?;
+        watermark.stale_reason = Some(reason.to_owned());
+        self.write(&watermark)
+    }
+
+    /// Check the monitors read from disk before starting the channel
+    /// manager, and refuse to start when they look older than the
+    /// last state seen by the node.
+    pub fn check(&self, monitors: &[ChannelMonitor<InMemorySigner>]) -> error::Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        let mut watermark = self.read()?;
+        let mut stale = monitors
+            .iter()
+            .filter_map(|monitor| {
+                let (txo, _) = monitor.get_funding_txo();
+                let key = format!("{}:{}", txo.txid, txo.index);
+                let seen = *watermark.update_ids.get(&key)?;
+                let current = monitor.get_latest_update_id();
+                (current < seen).then(|| {
+                    format!("channel `{key}` is at update `{current}` but `{seen}` was seen")
+                })
+            })
+            .collect::<Vec<_>>();
+        if let Some(reason) = watermark.stale_reason.take() {
+            stale.insert(0, reason);
+        }
+        if stale.is_empty() {
+            return Ok(());
+        }
+
+        let reason = stale.join(", ");
+        if self.allow_stale_state {
+            log::warn!(target: "watchdog", "the channel state looks stale ({reason}), starting anyway because `allow-stale-state` is set");
+            // the monitors on disk are the new reference
+            watermark.update_ids.clear();
+            return self.write(&watermark);
+        }
+        watermark.stale_reason = Some(reason.clone());
+        self.write(&watermark)?;
+        error::bail!(
+            "safe mode: the channel state looks older than the last one seen by the node ({reason}), \
+             broadcasting it can lose all the channel funds. Restore the latest backup, or set \
+             `allow-stale-state` if you know what you are doing"
+        )
+    }
+
+    /// Store the latest update of the live monitors.
+    pub fn record(&self, channel_manager: &LampoChannelManager) -> error::Result<()> {
+        let chain_monitor = channel_manager.chain_monitor();
+        let update_ids = channel_manager
+            .manager()
+            .list_channels()
+            .into_iter()
+            .filter_map(|channel| {
+                let txo = channel.funding_txo?;
+                let monitor = chain_monitor.get_monitor(txo).ok()?;
+                Some((
+                    format!("{}:{}", txo.txid, txo.index),
+                    monitor.get_latest_update_id(),
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        let _lock = self.lock.lock().unwrap();
+        let mut watermark = self.read()?;
+        for (key, update_id) in update_ids {
+            let seen = watermark.update_ids.entry(key).or_default();
+            *seen = update_id.max(*seen);
+        }
+        self.write(&watermark)
+    }
+
+    /// Remember that the peer proved that our state is old, ldk
+    /// panics in this case so we look at the panic message.
+    pub fn install_panic_hook(self: Arc<Self>) {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let message = info
+                .payload()
+                .downcast_ref::<String>()
+                .map(|message| message.as_str())
+                .or_else(|| info.payload().downcast_ref::<&str>().copied());
+            if let Some(message) = message.filter(|message| message.contains(FALLEN_BEHIND)) {
+                if let Err(err) = self.enter_safe_mode(message) {
+                    log::error!(target: "watchdog", "impossible enter in safe mode: {err}");
+                }
+            }
+            previous(info);
+        }));
+    }
+
+    pub fn watch(
+        self: Arc<Self>,
+        channel_manager: Arc<LampoChannelManager>,
+        handler: Arc<LampoHandler>,
+    ) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            if let Err(err) = self.record(&channel_manager) {
+                log::error!(target: "watchdog", "{err}");
+            }
+            let events = handler.events();
+            loop {
+                let Ok(Event::OnChain(OnChainEvent::NewBestBlock(_))) = events.recv() else {
+                    continue;
+                };
+                if let Err(err) = self.record(&channel_manager) {
+                    log::error!(target: "watchdog", "{err}");
+                }
+            }
+        })
+    }
+}
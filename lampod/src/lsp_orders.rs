@@ -0,0 +1,96 @@
+//! Channels bought from the LSP.
+//!
+//! The LSP opens the channel of an order some blocks after that the
+//! order is paid, so we keep the orders and we ask again their state
+//! to the LSP every time that a channel with the LSP changes state,
+//! until the order is completed or failed.
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::model::response::{LspOrder, OrderState};
+
+use crate::actions::handler::LampoHandler;
+use crate::ln::LampoPeerManager;
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const LSP_ORDERS_NAMESPACE: &str = "lsp_orders";
+
+pub struct LampoLspOrders {
+    persister: Arc<LampoPersistence>,
+}
+
+impl LampoLspOrders {
+    pub fn new(persister: Arc<LampoPersistence>) -> Self {
+        Self { persister }
+    }
+
+    pub fn store(&self, order: &LspOrder) -> error::Result<()> {
+        self.persister
+            .write_json(LSP_ORDERS_NAMESPACE, &order.order_id, order)
+    }
+
+    pub fn list(&self) -> error::Result<Vec<LspOrder>> {
+        let mut orders = self.persister.list_json::<LspOrder>(LSP_ORDERS_NAMESPACE)?;
+        orders.sort_by_key(|order| order.created_at);
+        Ok(orders)
+    }
+
+    /// Ask to the LSP the state of the orders that are not
+    /// completed or failed, we must be connected with the LSP.
+    pub fn refresh(&self, peer_manager: &LampoPeerManager) -> error::Result<()> {
+        let client = peer_manager.lsp_client();
+        for mut order in self.list()? {
+            if order.order_state != OrderState::Created {
+                continue;
+            }
+            let update = client.lsps1_get_order(peer_manager, &order.order_id)?;
+            order.update(&update);
+            log::debug!(target: "lsp", "order `{}` is {:?}", order.order_id, order.order_state);
+            self.store(&order)?;
+        }
+        Ok(())
+    }
+
+    /// Refresh the orders when a channel with the LSP is
+    /// opened, confirmed or closed.
+    pub fn watch(
+        self: Arc<Self>,
+        handler: Arc<LampoHandler>,
+        peer_manager: Arc<LampoPeerManager>,
+    ) -> error::Result<JoinHandle<()>> {
+        let (lsp_id, _) = peer_manager.lsp_client().lsp()?;
+        Ok(std::thread::spawn(move || {
+            let events = handler.events();
+            loop {
+                let Ok(Event::Lightning(event)) = events.recv() else {
+                    continue;
+                };
+                let node_id = match event {
+                    LightningEvent::ChannelPending {
+                        counterparty_node_id,
+                        ..
+                    }
+                    | LightningEvent::ChannelReady {
+                        counterparty_node_id,
+                        ..
+                    } => counterparty_node_id.to_string(),
+                    LightningEvent::CloseChannelEvent {
+                        counterparty_node_id: Some(node_id),
+                        ..
+                    } => node_id,
+                    _ => continue,
+                };
+                if node_id != lsp_id.to_string() || !peer_manager.is_connected_with(lsp_id) {
+                    continue;
+                }
+                if let Err(err) = self.refresh(&peer_manager) {
+                    log::warn!(target: "lsp", "impossible refresh the LSP orders: {err}");
+                }
+            }
+        }))
+    }
+}
@@ -0,0 +1,714 @@
+//! LSP server, the channels sold by the node (`lsp-server`).
+//!
+//! With LSPS1 the client orders a channel with inbound liquidity
+//! and pays a hold invoice: when the payment arrives we open the
+//! channel, and the payment is claimed only when the funding
+//! transaction is broadcast, otherwise it is failed back.
+//!
+//! With LSPS2 the client buys a JIT channel for a payment and we
+//! give it an intercept scid: when the HTLCs of the payment arrive
+//! we open a zero conf channel with the client and we forward the
+//! HTLCs over it, taking the opening fee from the forwarded amount.
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lampo_common::bitcoin::hashes::sha256::Hash as Sha256;
+use lampo_common::bitcoin::hashes::Hash;
+use lampo_common::bitcoin::OutPoint;
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::json;
+use lampo_common::keys::LampoKeysManager;
+use lampo_common::ldk::sign::EntropySource;
+use lampo_common::model::request::{
+    FailIntercepted, ForwardIntercepted, Lsps1CreateOrder, Lsps1GetOrder, Lsps2Buy, LspsGetInfo,
+};
+use lampo_common::model::response::{
+    lsps_datetime, InterceptedHtlc, InvoiceStatus, LspSale, Lsps1Bolt11Payment, Lsps1Channel,
+    Lsps1Info, Lsps1Options, Lsps1Payment, Lsps1SoldOrder, Lsps2Channel, Lsps2Info,
+    OpeningFeeParams, OrderPaymentState, OrderState,
+};
+use lampo_common::types::{ChannelId, NodeId};
+
+use crate::actions::handler::LampoHandler;
+use crate::invoices::LampoInvoiceStore;
+use crate::ln::{LampoChannelManager, LampoPeerManager, OffchainManager};
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const LSP_SALES_NAMESPACE: &str = "lsp_sales";
+/// Seconds to pay the invoice of an order.
+const ORDER_INVOICE_EXPIRY: u32 = 3600;
+/// Seconds that the opening fees of the JIT channels are valid.
+const OPENING_FEE_VALIDITY: u64 = 3600;
+/// The funding transactions are built with the fee rate
+/// to confirm within 6 blocks.
+const FUNDING_CONFIRMS_WITHIN_BLOCKS: u16 = 6;
+/// Blocks that the JIT channels are kept open.
+const JIT_CHANNEL_MIN_LIFETIME: u32 = 1008;
+const MAX_CLIENT_TO_SELF_DELAY: u32 = 2016;
+
+// json rpc 2.0 and LSPS error codes
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+const LSPS1_OPTION_MISMATCH: i64 = 100;
+const LSPS1_NOT_FOUND: i64 = 101;
+const LSPS2_INVALID_OPENING_FEE_PARAMS: i64 = 201;
+const LSPS2_PAYMENT_SIZE_TOO_SMALL: i64 = 202;
+const LSPS2_PAYMENT_SIZE_TOO_LARGE: i64 = 203;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+/// Error sent back to the client.
+struct LspsError {
+    code: i64,
+    message: String,
+}
+
+impl LspsError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<error::Error> for LspsError {
+    fn from(err: error::Error) -> Self {
+        // the client does not need to know our internal errors
+        log::error!(target: "lsp", "{err}");
+        Self::new(INTERNAL_ERROR, "internal error")
+    }
+}
+
+impl From<json::Error> for LspsError {
+    fn from(err: json::Error) -> Self {
+        Self::from(error::Error::from(err))
+    }
+}
+
+fn parse_params<T: json::DeserializeOwned>(params: json::Value) -> Result<T, LspsError> {
+    json::from_value(params)
+        .map_err(|err| LspsError::new(INVALID_PARAMS, format!("invalid params: {err}")))
+}
+
+fn parse_amount(field: &str, amount: &str) -> Result<u64, LspsError> {
+    amount
+        .parse::<u64>()
+        .map_err(|_| LspsError::new(INVALID_PARAMS, format!("invalid `{field}`: `{amount}`")))
+}
+
+/// JIT channel bought with `lsps2.buy`.
+struct JitChannel {
+    client_id: NodeId,
+    payment_size_msat: u64,
+    opening_fee_msat: u64,
+    /// The HTLCs of the payment, held until the channel is ready.
+    htlcs: Vec<InterceptedHtlc>,
+    opening: bool,
+}
+
+pub struct LampoLspServer {
+    conf: LampoConf,
+    channel_manager: Arc<LampoChannelManager>,
+    offchain_manager: Arc<OffchainManager>,
+    peer_manager: Arc<LampoPeerManager>,
+    invoices: Arc<LampoInvoiceStore>,
+    keys_manager: Arc<LampoKeysManager>,
+    persister: Arc<LampoPersistence>,
+    // promise -> opening fee params and when they expire
+    fee_params: Mutex<HashMap<String, (OpeningFeeParams, u64)>>,
+    // intercept scid -> JIT channel
+    jit_channels: Mutex<HashMap<u64, JitChannel>>,
+}
+
+impl LampoLspServer {
+    pub fn new(
+        conf: &LampoConf,
+        channel_manager: Arc<LampoChannelManager>,
+        offchain_manager: Arc<OffchainManager>,
+        peer_manager: Arc<LampoPeerManager>,
+        invoices: Arc<LampoInvoiceStore>,
+        keys_manager: Arc<LampoKeysManager>,
+        persister: Arc<LampoPersistence>,
+    ) -> Self {
+        Self {
+            conf: conf.clone(),
+            channel_manager,
+            offchain_manager,
+            peer_manager,
+            invoices,
+            keys_manager,
+            persister,
+            fee_params: Mutex::new(HashMap::new()),
+            jit_channels: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Answer to the requests of the clients.
+    pub fn serve(self: Arc<Self>) -> JoinHandle<()> {
+        let client = self.peer_manager.lsp_client();
+        let requests = client.serve();
+        std::thread::spawn(move || {
+            while let Ok((client_id, request)) = requests.recv() {
+                let Some(id) = request.get("id").cloned() else {
+                    continue;
+                };
+                let method = request
+                    .get("method")
+                    .and_then(|method| method.as_str())
+                    .unwrap_or_default();
+                let params = request
+                    .get("params")
+                    .cloned()
+                    .unwrap_or_else(|| json::json!({}));
+                log::debug!(target: "lsp", "request `{method}` from `{client_id}`");
+                let response = match self.handle(client_id, method, params) {
+                    Ok(result) => json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": result,
+                    }),
+                    Err(err) => json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": err.code,
+                            "message": err.message,
+                        },
+                    }),
+                };
+                if let Err(err) = client.respond(&self.peer_manager, client_id, &response) {
+                    log::warn!(target: "lsp", "impossible answer to `{client_id}`: {err}");
+                }
+            }
+        })
+    }
+
+    fn handle(
+        &self,
+        client_id: NodeId,
+        method: &str,
+        params: json::Value,
+    ) -> Result<json::Value, LspsError> {
+        let result = match method {
+            "lsps0.list_protocols" => json::json!({ "protocols": [1, 2] }),
+            "lsps1.get_info" => {
+                let _: LspsGetInfo = parse_params(params)?;
+                json::to_value(self.lsps1_info())?
+            }
+            "lsps1.create_order" => {
+                let order = self.lsps1_create_order(client_id, parse_params(params)?)?;
+                json::to_value(order)?
+            }
+            "lsps1.get_order" => {
+                let order = self.lsps1_get_order(client_id, parse_params(params)?)?;
+                json::to_value(order)?
+            }
+            "lsps2.get_info" => {
+                let _: LspsGetInfo = parse_params(params)?;
+                json::to_value(self.lsps2_info())?
+            }
+            "lsps2.buy" => {
+                let channel = self.lsps2_buy(client_id, parse_params(params)?)?;
+                json::to_value(channel)?
+            }
+            _ => {
+                return Err(LspsError::new(
+                    METHOD_NOT_FOUND,
+                    format!("method `{method}` not found"),
+                ))
+            }
+        };
+        Ok(result)
+    }
+
+    /// The fee to open a channel of `amount_sat`.
+    fn fee_sat(&self, amount_sat: u64) -> u64 {
+        let proportional =
+            (amount_sat as u128 * self.conf.lsp_server_fee_ppm as u128 + 999_999) / 1_000_000;
+        self.conf.lsp_server_fee_base_sat + proportional as u64
+    }
+
+    fn lsps1_info(&self) -> Lsps1Info {
+        let min = self.conf.lsp_server_min_channel_sat.to_string();
+        let max = self.conf.lsp_server_max_channel_sat.to_string();
+        Lsps1Info {
+            options: Lsps1Options {
+                // the payment is claimed when the funding is broadcast
+                min_required_channel_confirmations: 0,
+                min_funding_confirms_within_blocks: FUNDING_CONFIRMS_WITHIN_BLOCKS,
+                supports_zero_channel_reserve: false,
+                max_channel_expiry_blocks: self.conf.lsp_server_max_expiry_blocks,
+                // we sell only inbound liquidity
+                min_initial_client_balance_sat: "0".to_owned(),
+                max_initial_client_balance_sat: "0".to_owned(),
+                min_initial_lsp_balance_sat: min.clone(),
+                max_initial_lsp_balance_sat: max.clone(),
+                min_channel_balance_sat: min,
+                max_channel_balance_sat: max,
+            },
+        }
+    }
+
+    fn lsps1_create_order(
+        &self,
+        client_id: NodeId,
+        request: Lsps1CreateOrder,
+    ) -> Result<Lsps1SoldOrder, LspsError> {
+        let lsp_balance_sat = parse_amount("lsp_balance_sat", &request.lsp_balance_sat)?;
+        let client_balance_sat = parse_amount("client_balance_sat", &request.client_balance_sat)?;
+        let mismatch = |option: &str| {
+            LspsError::new(
+                LSPS1_OPTION_MISMATCH,
+                format!("`{option}` is outside the options of the LSP"),
+            )
+        };
+        if lsp_balance_sat < self.conf.lsp_server_min_channel_sat
+            || lsp_balance_sat > self.conf.lsp_server_max_channel_sat
+        {
+            return Err(mismatch("lsp_balance_sat"));
+        }
+        if client_balance_sat != 0 {
+            return Err(mismatch("client_balance_sat"));
+        }
+        if request.channel_expiry_blocks > self.conf.lsp_server_max_expiry_blocks {
+            return Err(mismatch("channel_expiry_blocks"));
+        }
+        if request.funding_confirms_within_blocks < FUNDING_CONFIRMS_WITHIN_BLOCKS {
+            return Err(mismatch("funding_confirms_within_blocks"));
+        }
+        let fee_sat = self.fee_sat(lsp_balance_sat);
+        let order_id = hex::encode(&self.keys_manager.get_secure_random_bytes()[..16]);
+        let preimage = self.keys_manager.get_secure_random_bytes();
+        let payment_hash = Sha256::hash(&preimage).to_string();
+        let invoice = self.offchain_manager.generate_hold_invoice(
+            &payment_hash,
+            Some(fee_sat * 1000),
+            &format!("LSPS1 order {order_id}"),
+            ORDER_INVOICE_EXPIRY,
+        )?;
+        let mut record = LampoInvoiceStore::record(&invoice, Some(format!("lsps1-{order_id}")));
+        record.hold = true;
+        let expires_at = record.expires_at;
+        self.invoices.insert(record)?;
+        let sale = LspSale {
+            client_id: client_id.to_string(),
+            payment_hash,
+            preimage: hex::encode(preimage),
+            order: Lsps1SoldOrder {
+                order_id,
+                request,
+                created_at: lsps_datetime(now()),
+                order_state: OrderState::Created,
+                payment: Lsps1Payment {
+                    bolt11: Some(Lsps1Bolt11Payment {
+                        state: OrderPaymentState::ExpectPayment,
+                        expires_at: lsps_datetime(expires_at),
+                        fee_total_sat: fee_sat.to_string(),
+                        order_total_sat: fee_sat.to_string(),
+                        invoice: invoice.to_string(),
+                    }),
+                },
+                channel: None,
+            },
+        };
+        self.store(&sale)?;
+        log::info!(target: "lsp", "order `{}` of `{lsp_balance_sat}` sat created for `{client_id}`", sale.order.order_id);
+        Ok(sale.order)
+    }
+
+    fn lsps1_get_order(
+        &self,
+        client_id: NodeId,
+        request: Lsps1GetOrder,
+    ) -> Result<Lsps1SoldOrder, LspsError> {
+        let not_found = || {
+            LspsError::new(
+                LSPS1_NOT_FOUND,
+                format!("order `{}` not found", request.order_id),
+            )
+        };
+        // the order id is used as key inside the persistence
+        if !request.order_id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(not_found());
+        }
+        let sale = self
+            .persister
+            .read_json::<LspSale>(LSP_SALES_NAMESPACE, &request.order_id)?
+            .filter(|sale| sale.client_id == client_id.to_string())
+            .ok_or_else(not_found)?;
+        let mut order = sale.order;
+        // the orders not paid in time are failed
+        let expired = self
+            .invoices
+            .get(&sale.payment_hash)
+            .map_or(false, |invoice| {
+                invoice.status == InvoiceStatus::Unpaid && invoice.expires_at <= now()
+            });
+        if order.order_state == OrderState::Created && expired {
+            order.order_state = OrderState::Failed;
+        }
+        Ok(order)
+    }
+
+    fn lsps2_info(&self) -> Lsps2Info {
+        let valid_until = now() + OPENING_FEE_VALIDITY;
+        let params = OpeningFeeParams {
+            min_fee_msat: (self.conf.lsp_server_fee_base_sat * 1000).to_string(),
+            // checked when the configuration is loaded
+            proportional: self.conf.lsp_server_fee_ppm as u32,
+            valid_until: lsps_datetime(valid_until),
+            min_lifetime: JIT_CHANNEL_MIN_LIFETIME,
+            max_client_to_self_delay: MAX_CLIENT_TO_SELF_DELAY,
+            // the payment must pay at least the opening fee
+            min_payment_size_msat: ((self.conf.lsp_server_fee_base_sat + 1) * 1000).to_string(),
+            max_payment_size_msat: (self.conf.lsp_server_max_channel_sat * 1000).to_string(),
+            promise: hex::encode(self.keys_manager.get_secure_random_bytes()),
+        };
+        let now = now();
+        let mut fee_params = self.fee_params.lock().unwrap();
+        fee_params.retain(|_, (_, valid_until)| *valid_until > now);
+        fee_params.insert(params.promise.clone(), (params.clone(), valid_until));
+        Lsps2Info {
+            opening_fee_params_menu: vec![params],
+        }
+    }
+
+    fn lsps2_buy(&self, client_id: NodeId, request: Lsps2Buy) -> Result<Lsps2Channel, LspsError> {
+        let params = request.opening_fee_params;
+        let valid = self
+            .fee_params
+            .lock()
+            .unwrap()
+            .get(&params.promise)
+            .map_or(false, |(issued, valid_until)| {
+                *issued == params && *valid_until > now()
+            });
+        if !valid {
+            return Err(LspsError::new(
+                LSPS2_INVALID_OPENING_FEE_PARAMS,
+                "the opening fee params are expired or not issued by the LSP",
+            ));
+        }
+        let payment_size_msat = parse_amount("payment_size_msat", &request.payment_size_msat)?;
+        if payment_size_msat > self.conf.lsp_server_max_channel_sat * 1000 {
+            return Err(LspsError::new(
+                LSPS2_PAYMENT_SIZE_TOO_LARGE,
+                format!("`{payment_size_msat}` msat is too large"),
+            ));
+        }
+        let Some(opening_fee_msat) = params.fee_msat(payment_size_msat) else {
+            return Err(LspsError::new(
+                LSPS2_PAYMENT_SIZE_TOO_SMALL,
+                format!("`{payment_size_msat}` msat does not pay the opening fee"),
+            ));
+        };
+        let scid = self.channel_manager.intercept_scid()?;
+        self.jit_channels.lock().unwrap().insert(
+            scid,
+            JitChannel {
+                client_id,
+                payment_size_msat,
+                opening_fee_msat,
+                htlcs: Vec::new(),
+                opening: false,
+            },
+        );
+        log::info!(target: "lsp", "JIT channel `{scid}` sold to `{client_id}` for a payment of `{payment_size_msat}` msat");
+        Ok(Lsps2Channel {
+            jit_channel_scid: format!(
+                "{}x{}x{}",
+                scid >> 40,
+                (scid >> 16) & 0xFF_FFFF,
+                scid & 0xFFFF
+            ),
+            lsp_cltv_expiry_delta: u32::from(self.conf.ldk_conf.channel_config.cltv_expiry_delta),
+            // we broadcast the funding before that the payment is claimed
+            client_trusts_lsp: true,
+        })
+    }
+
+    fn store(&self, sale: &LspSale) -> error::Result<()> {
+        self.persister
+            .write_json(LSP_SALES_NAMESPACE, &sale.order.order_id, sale)
+    }
+
+    /// The orders waiting the payment or the channel.
+    fn pending_sales(&self) -> error::Result<Vec<LspSale>> {
+        let sales = self.persister.list_json::<LspSale>(LSP_SALES_NAMESPACE)?;
+        Ok(sales
+            .into_iter()
+            .filter(|sale| sale.order.order_state == OrderState::Created)
+            .collect())
+    }
+
+    fn payment_state(sale: &mut LspSale, state: OrderPaymentState) {
+        if let Some(payment) = sale.order.payment.bolt11.as_mut() {
+            payment.state = state;
+        }
+    }
+
+    fn is_held(sale: &LspSale) -> bool {
+        sale.order
+            .payment
+            .bolt11
+            .as_ref()
+            .map_or(false, |payment| payment.state == OrderPaymentState::Hold)
+    }
+
+    /// Open the channel of the order when its payment is held,
+    /// and fail the order when the payment is cancelled.
+    fn invoice_changed(
+        &self,
+        handler: &LampoHandler,
+        payment_hash: &str,
+        status: InvoiceStatus,
+    ) -> error::Result<()> {
+        if !matches!(status, InvoiceStatus::Accepted | InvoiceStatus::Cancelled) {
+            return Ok(());
+        }
+        let Some(mut sale) = self
+            .pending_sales()?
+            .into_iter()
+            .find(|sale| sale.payment_hash == payment_hash)
+        else {
+            return Ok(());
+        };
+        if status == InvoiceStatus::Cancelled {
+            log::warn!(target: "lsp", "the payment of the order `{}` was cancelled", sale.order.order_id);
+            if Self::is_held(&sale) {
+                Self::payment_state(&mut sale, OrderPaymentState::Refunded);
+            }
+            sale.order.order_state = OrderState::Failed;
+            return self.store(&sale);
+        }
+        let client_id = NodeId::from_str(&sale.client_id)?;
+        let lsp_balance_sat = sale.order.request.lsp_balance_sat.parse::<u64>()?;
+        let mut conf = self.conf.channel_config(&sale.client_id)?;
+        conf.channel_handshake_config.announced_channel = sale.order.request.announce_channel;
+        let result = self.channel_manager.manager().create_channel(
+            client_id,
+            lsp_balance_sat,
+            0,
+            0,
+            None,
+            Some(conf),
+        );
+        Self::payment_state(&mut sale, OrderPaymentState::Hold);
+        match result {
+            Ok(_) => {
+                log::info!(target: "lsp", "opening the channel of the order `{}` with `{client_id}`", sale.order.order_id);
+                self.store(&sale)
+            }
+            Err(err) => {
+                log::warn!(target: "lsp", "impossible open the channel of the order `{}`: {:?}", sale.order.order_id, err);
+                self.fail_sale(handler, sale)
+            }
+        }
+    }
+
+    /// Fail back the payment of the order.
+    fn fail_sale(&self, handler: &LampoHandler, mut sale: LspSale) -> error::Result<()> {
+        sale.order.order_state = OrderState::Failed;
+        Self::payment_state(&mut sale, OrderPaymentState::Refunded);
+        self.store(&sale)?;
+        let invoice = self
+            .invoices
+            .set_status(&sale.payment_hash, InvoiceStatus::Cancelled)?;
+        self.offchain_manager
+            .cancel_hold_invoice(&sale.payment_hash)?;
+        handler.emit(Event::Lightning(LightningEvent::InvoiceEvent {
+            payment_hash: invoice.payment_hash,
+            label: invoice.label,
+            status: invoice.status,
+        }));
+        Ok(())
+    }
+
+    /// Claim the payment of the order when its channel is broadcast.
+    fn channel_pending(&self, client_id: NodeId, funding: OutPoint) -> error::Result<()> {
+        let client_id = client_id.to_string();
+        let Some(mut sale) = self
+            .pending_sales()?
+            .into_iter()
+            .find(|sale| sale.client_id == client_id && Self::is_held(sale))
+        else {
+            return Ok(());
+        };
+        self.offchain_manager.settle_hold_invoice(&sale.preimage)?;
+        let now = now();
+        // the blocks are expected every 10 minutes
+        let expires_at = now + sale.order.request.channel_expiry_blocks as u64 * 600;
+        sale.order.order_state = OrderState::Completed;
+        sale.order.channel = Some(Lsps1Channel {
+            funded_at: lsps_datetime(now),
+            funding_outpoint: funding.to_string(),
+            expires_at: lsps_datetime(expires_at),
+        });
+        Self::payment_state(&mut sale, OrderPaymentState::Paid);
+        log::info!(target: "lsp", "order `{}` completed with the channel `{funding}`", sale.order.order_id);
+        self.store(&sale)
+    }
+
+    /// Fail the orders and the JIT channels of the client whose
+    /// channel was closed before that it was funded.
+    fn channel_failed(&self, handler: &LampoHandler, client_id: &str) -> error::Result<()> {
+        for sale in self.pending_sales()? {
+            if sale.client_id == client_id && Self::is_held(&sale) {
+                log::warn!(target: "lsp", "the channel of the order `{}` was not opened", sale.order.order_id);
+                self.fail_sale(handler, sale)?;
+            }
+        }
+        let mut jit_channels = self.jit_channels.lock().unwrap();
+        let failed = jit_channels
+            .iter()
+            .filter(|(_, channel)| channel.opening && channel.client_id.to_string() == client_id)
+            .map(|(scid, _)| *scid)
+            .collect::<Vec<_>>();
+        for scid in failed {
+            log::warn!(target: "lsp", "the JIT channel `{scid}` was not opened");
+            // SAFETY: the scid comes from the map.
+            let channel = jit_channels.remove(&scid).unwrap();
+            self.fail_htlcs(channel.htlcs);
+        }
+        Ok(())
+    }
+
+    fn fail_htlcs(&self, htlcs: Vec<InterceptedHtlc>) {
+        for htlc in htlcs {
+            let request = FailIntercepted {
+                intercept_id: htlc.intercept_id,
+            };
+            if let Err(err) = self.channel_manager.fail_intercepted_htlc(&request) {
+                log::warn!(target: "lsp", "{err}");
+            }
+        }
+    }
+
+    /// Open the JIT channel when all the payment is arrived.
+    fn htlc_intercepted(&self, htlc: InterceptedHtlc) -> error::Result<()> {
+        let mut jit_channels = self.jit_channels.lock().unwrap();
+        let scid = htlc.requested_next_hop_scid;
+        let Some(channel) = jit_channels.get_mut(&scid) else {
+            return Ok(());
+        };
+        channel.htlcs.push(htlc);
+        let received_msat = channel
+            .htlcs
+            .iter()
+            .map(|htlc| htlc.expected_outbound_amount_msat)
+            .sum::<u64>();
+        if channel.opening || received_msat < channel.payment_size_msat {
+            return Ok(());
+        }
+        // room for the payment and for the next ones
+        let amount_sat = (channel.payment_size_msat / 1000 * 2).clamp(
+            self.conf.lsp_server_min_channel_sat,
+            self.conf.lsp_server_max_channel_sat,
+        );
+        let mut conf = self.conf.channel_config(&channel.client_id.to_string())?;
+        conf.channel_handshake_config.announced_channel = false;
+        let result = self.channel_manager.manager().create_channel(
+            channel.client_id,
+            amount_sat,
+            0,
+            0,
+            None,
+            Some(conf),
+        );
+        match result {
+            Ok(_) => {
+                log::info!(target: "lsp", "opening the JIT channel `{scid}` with `{}`", channel.client_id);
+                channel.opening = true;
+            }
+            Err(err) => {
+                log::warn!(target: "lsp", "impossible open the JIT channel `{scid}`: {:?}", err);
+                // SAFETY: the scid comes from the map.
+                let channel = jit_channels.remove(&scid).unwrap();
+                self.fail_htlcs(channel.htlcs);
+            }
+        }
+        Ok(())
+    }
+
+    /// Forward the payment of a JIT channel over the channel
+    /// just opened, the opening fee is taken from the HTLCs.
+    fn channel_ready(&self, client_id: NodeId, channel_id: ChannelId) -> error::Result<()> {
+        let mut jit_channels = self.jit_channels.lock().unwrap();
+        let Some(scid) = jit_channels
+            .iter()
+            .find(|(_, channel)| channel.opening && channel.client_id == client_id)
+            .map(|(scid, _)| *scid)
+        else {
+            return Ok(());
+        };
+        // SAFETY: the scid comes from the map.
+        let channel = jit_channels.remove(&scid).unwrap();
+        let mut fee_msat = channel.opening_fee_msat;
+        for htlc in channel.htlcs {
+            let skimmed_msat = fee_msat.min(htlc.expected_outbound_amount_msat.saturating_sub(1));
+            fee_msat -= skimmed_msat;
+            let request = ForwardIntercepted {
+                intercept_id: htlc.intercept_id,
+                channel_id: channel_id.to_string(),
+                amount_msat: htlc.expected_outbound_amount_msat - skimmed_msat,
+            };
+            if let Err(err) = self.channel_manager.forward_intercepted_htlc(&request) {
+                log::warn!(target: "lsp", "impossible forward over the JIT channel `{scid}`: {err}");
+            }
+        }
+        log::info!(target: "lsp", "JIT channel `{scid}` ready with `{client_id}`");
+        Ok(())
+    }
+
+    /// Follow the payments and the channels of the sales.
+    pub fn watch(self: Arc<Self>, handler: Arc<LampoHandler>) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let events = handler.events();
+            loop {
+                let Ok(Event::Lightning(event)) = events.recv() else {
+                    continue;
+                };
+                let result = match event {
+                    LightningEvent::InvoiceEvent {
+                        payment_hash,
+                        status,
+                        ..
+                    } => self.invoice_changed(&handler, &payment_hash, status),
+                    LightningEvent::ChannelPending {
+                        counterparty_node_id,
+                        funding_transaction,
+                    } => self.channel_pending(counterparty_node_id, funding_transaction),
+                    LightningEvent::ChannelReady {
+                        counterparty_node_id,
+                        channel_id,
+                        ..
+                    } => self.channel_ready(counterparty_node_id, channel_id),
+                    LightningEvent::CloseChannelEvent {
+                        counterparty_node_id: Some(node_id),
+                        funding_utxo: None,
+                        ..
+                    } => self.channel_failed(&handler, &node_id),
+                    LightningEvent::HtlcIntercepted { htlc } => self.htlc_intercepted(htlc),
+                    _ => continue,
+                };
+                if let Err(err) = result {
+                    log::error!(target: "lsp", "{err}");
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,175 @@
+//! Latency metrics implementation.
+//!
+//! Track the latency and the in-flight calls of the JSON RPC
+//! methods and of the ldk event handler, so it is possible to
+//! find the slow handlers of a node in production.
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use lampo_common::model::response::{CallMetrics, LatencyBucket, Metrics, PendingEvent};
+
+/// Upper bounds in milliseconds of the latency histogram.
+const BUCKETS_MS: [u64; 8] = [1, 5, 10, 50, 100, 500, 1_000, 5_000];
+
+#[derive(Default)]
+struct Histogram {
+    count: u64,
+    errors: u64,
+    in_flight: u64,
+    total_ms: u64,
+    max_ms: u64,
+    // the last one is the bucket for the calls above the bounds
+    buckets: [u64; BUCKETS_MS.len() + 1],
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration, ok: bool) {
+        let elapsed_ms = elapsed.as_millis() as u64;
+        self.in_flight = self.in_flight.saturating_sub(1);
+        self.count += 1;
+        if !ok {
+            self.errors += 1;
+        }
+        self.total_ms += elapsed_ms;
+        self.max_ms = self.max_ms.max(elapsed_ms);
+        let idx = BUCKETS_MS
+            .iter()
+            .position(|bound| elapsed_ms <= *bound)
+            .unwrap_or(BUCKETS_MS.len());
+        self.buckets[idx] += 1;
+    }
+
+    fn metrics(&self) -> CallMetrics {
+        // the buckets are cumulative like the prometheus ones
+        let mut count = 0;
+        let buckets = self
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(idx, bucket)| {
+                count += bucket;
+                LatencyBucket {
+                    le_ms: BUCKETS_MS.get(idx).cloned(),
+                    count,
+                }
+            })
+            .collect();
+        CallMetrics {
+            count: self.count,
+            errors: self.errors,
+            in_flight: self.in_flight,
+            avg_ms: (self.count > 0).then(|| self.total_ms / self.count),
+            max_ms: self.max_ms,
+            buckets,
+        }
+    }
+}
+
+/// Event that the handler is still processing.
+struct InFlightEvent {
+    name: String,
+    description: String,
+    timestamp: u64,
+    start: Instant,
+}
+
+#[derive(Default)]
+pub struct LampoMetrics {
+    rpc: Mutex<BTreeMap<String, Histogram>>,
+    events: Mutex<BTreeMap<String, Histogram>>,
+    in_flight_events: Mutex<BTreeMap<u64, InFlightEvent>>,
+    next_event_id: AtomicU64,
+}
+
+impl LampoMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn started(histograms: &Mutex<BTreeMap<String, Histogram>>, name: &str) {
+        let mut histograms = histograms.lock().unwrap();
+        histograms.entry(name.to_owned()).or_default().in_flight += 1;
+    }
+
+    fn finished(
+        histograms: &Mutex<BTreeMap<String, Histogram>>,
+        name: &str,
+        elapsed: Duration,
+        ok: bool,
+    ) {
+        let mut histograms = histograms.lock().unwrap();
+        histograms
+            .entry(name.to_owned())
+            .or_default()
+            .observe(elapsed, ok);
+    }
+
+    pub fn rpc_started(&self, method: &str) {
+        Self::started(&self.rpc, method);
+    }
+
+    pub fn rpc_finished(&self, method: &str, elapsed: Duration, ok: bool) {
+        Self::finished(&self.rpc, method, elapsed, ok);
+    }
+
+    /// Track the event until `event_finished` is called with
+    /// the returned id.
+    pub fn event_started(&self, name: &str, description: String) -> u64 {
+        Self::started(&self.events, name);
+        let id = self.next_event_id.fetch_add(1, Ordering::SeqCst);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.in_flight_events.lock().unwrap().insert(
+            id,
+            InFlightEvent {
+                name: name.to_owned(),
+                description,
+                timestamp,
+                start: Instant::now(),
+            },
+        );
+        id
+    }
+
+    pub fn event_finished(&self, id: u64, ok: bool) {
+        let Some(event) = self.in_flight_events.lock().unwrap().remove(&id) else {
+            return;
+        };
+        Self::finished(&self.events, &event.name, event.start.elapsed(), ok);
+    }
+
+    /// The events that the handler did not finish to process, the oldest first.
+    pub fn pending_events(&self) -> Vec<PendingEvent> {
+        self.in_flight_events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, event)| PendingEvent {
+                id: *id,
+                event: event.name.clone(),
+                description: event.description.clone(),
+                timestamp: event.timestamp,
+                age_ms: event.start.elapsed().as_millis() as u64,
+            })
+            .collect()
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        let collect = |histograms: &Mutex<BTreeMap<String, Histogram>>| {
+            histograms
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(name, histogram)| (name.clone(), histogram.metrics()))
+                .collect()
+        };
+        Metrics {
+            rpc: collect(&self.rpc),
+            events: collect(&self.events),
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Data dir migration.
+//!
+//! The channel manager and the channel monitors are serialized by
+//! ldk with a version prefix, and ldk is able to read the data
+//! written by the older releases. So when the data dir was written
+//! by an older lampo we make a backup, we let ldk read it and then
+//! we write it again with the current format.
+//!
+//! The data written by a newer release is refused, because ldk
+//! does not support the downgrade.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::ldk::util::persist::{
+    KVStore, CHANNEL_MANAGER_PERSISTENCE_KEY, CHANNEL_MANAGER_PERSISTENCE_PRIMARY_NAMESPACE,
+    CHANNEL_MANAGER_PERSISTENCE_SECONDARY_NAMESPACE, CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+    CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE, NETWORK_GRAPH_PERSISTENCE_KEY,
+    SCORER_PERSISTENCE_KEY,
+};
+
+use crate::persistence::{JsonStore, LampoPersistence};
+
+/// Version of the lampo data dir, bump it when the data needs
+/// to be rewritten at the upgrade.
+pub const DATA_VERSION: u32 = 1;
+/// Max serialization version of the ldk objects that we are able to read.
+const LDK_SERIALIZATION_VERSION: u8 = 1;
+const MIGRATION_NAMESPACE: &str = "migration";
+const VERSION_KEY: &str = "version";
+
+pub struct LampoMigration {
+    path: PathBuf,
+    persister: Arc<LampoPersistence>,
+}
+
+impl LampoMigration {
+    pub fn new(conf: &LampoConf, persister: Arc<LampoPersistence>) -> Self {
+        Self {
+            path: PathBuf::from(conf.path()),
+            persister,
+        }
+    }
+
+    /// The version of the data dir, `0` for the data written
+    /// before the versioning.
+    pub fn data_version(&self) -> error::Result<u32> {
+        Ok(self
+            .persister
+            .read_json::<u32>(MIGRATION_NAMESPACE, VERSION_KEY)?
+            .unwrap_or(0))
+    }
+
+    /// Check the data dir before loading it, and make a backup
+    /// when it needs to be migrated.
+    ///
+    /// Return true if the state must be rewritten after the start.
+    pub fn prepare(&self) -> error::Result<bool> {
+        if !self.path.join(CHANNEL_MANAGER_PERSISTENCE_KEY).exists() {
+            // a new node, nothing to migrate
+            return Ok(false);
+        }
+        let version = self.data_version()?;
+        if version > DATA_VERSION {
+            error::bail!(
+                "the data dir was written by a newer lampo (data version `{version}`, supported `{DATA_VERSION}`), downgrade is not supported"
+            );
+        }
+
+        let manager = self.persister.read(
+            CHANNEL_MANAGER_PERSISTENCE_PRIMARY_NAMESPACE,
+            CHANNEL_MANAGER_PERSISTENCE_SECONDARY_NAMESPACE,
+            CHANNEL_MANAGER_PERSISTENCE_KEY,
+        )?;
+        check_header(CHANNEL_MANAGER_PERSISTENCE_KEY, &manager)?;
+        for key in self.persister.list(
+            CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+            CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+        )? {
+            let monitor = self.persister.read(
+                CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+                CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+                &key,
+            )?;
+            check_header(&format!("monitor `{key}`"), &monitor)?;
+        }
+
+        if version == DATA_VERSION {
+            return Ok(false);
+        }
+        let backup = self.backup(version)?;
+        log::info!(target: "migration", "migrating the data dir from version `{version}` to `{DATA_VERSION}`, backup stored in `{}`", backup.display());
+        Ok(true)
+    }
+
+    /// Copy the ldk state inside `backups/v<version>-<timestamp>`.
+    fn backup(&self, version: u32) -> error::Result<PathBuf> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let backup = self
+            .path
+            .join("backups")
+            .join(format!("v{version}-{timestamp}"));
+        fs::create_dir_all(&backup)?;
+        for key in [
+            CHANNEL_MANAGER_PERSISTENCE_KEY,
+            NETWORK_GRAPH_PERSISTENCE_KEY,
+            SCORER_PERSISTENCE_KEY,
+        ] {
+            let file = self.path.join(key);
+            if file.exists() {
+                fs::copy(&file, backup.join(key))?;
+            }
+        }
+        copy_dir(
+            &self
+                .path
+                .join(CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE),
+            &backup.join(CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE),
+        )?;
+        Ok(backup)
+    }
+
+    /// Store the current data version, called when the node
+    /// started (and the state was rewritten).
+    pub fn finish(&self) -> error::Result<()> {
+        if self.data_version()? == DATA_VERSION {
+            return Ok(());
+        }
+        self.persister
+            .write_json(MIGRATION_NAMESPACE, VERSION_KEY, &DATA_VERSION)
+    }
+}
+
+/// Ldk writes the serialization version and the min version
+/// required to read the object as the first two bytes.
+fn check_header(name: &str, buff: &[u8]) -> error::Result<()> {
+    let Some(min_version) = buff.get(1) else {
+        error::bail!("{name} is corrupted, it is too short");
+    };
+    if *min_version > LDK_SERIALIZATION_VERSION {
+        error::bail!(
+            "{name} requires the serialization version `{min_version}`, it was written by a newer release"
+        );
+    }
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> error::Result<()> {
+    if !from.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            fs::copy(entry.path(), to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_header, LDK_SERIALIZATION_VERSION};
+
+    #[test]
+    fn supported_header() {
+        assert!(check_header("manager", &[1, 1, 0xff]).is_ok());
+        // written by a newer release that we are still able to read
+        assert!(check_header("manager", &[LDK_SERIALIZATION_VERSION + 1, 1]).is_ok());
+    }
+
+    #[test]
+    fn newer_header() {
+        let err = check_header("manager", &[2, LDK_SERIALIZATION_VERSION + 1]).unwrap_err();
+        assert!(
+            err.to_string().contains("written by a newer release"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn corrupted_header() {
+        for buff in [&[][..], &[1][..]] {
+            let err = check_header("monitor `0000`", buff).unwrap_err();
+            assert!(err.to_string().contains("is corrupted"), "{err}");
+        }
+    }
+}
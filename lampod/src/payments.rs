@@ -0,0 +1,139 @@
+//! Payment store implementation.
+//!
+//! Ldk forgets the payments after they are resolved, so we store
+//! the details of each payment (sent or received) keyed by direction
+//! and payment hash, in this way the preimage can be used later as
+//! proof of payment. A circular payment is sent and received by us
+//! with the same payment hash, so it has both the records.
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lampo_common::error;
+use lampo_common::model::response::{PaymentDirection, PaymentRecord, PaymentState};
+
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const PAYMENTS_NAMESPACE: &str = "payments";
+
+pub struct LampoPaymentStore {
+    persister: Arc<LampoPersistence>,
+}
+
+impl LampoPaymentStore {
+    pub fn new(persister: Arc<LampoPersistence>) -> Self {
+        Self { persister }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or_default()
+    }
+
+    fn key(payment_hash: &str, direction: &PaymentDirection) -> String {
+        match direction {
+            PaymentDirection::Sent => format!("sent-{payment_hash}"),
+            PaymentDirection::Received => format!("received-{payment_hash}"),
+        }
+    }
+
+    pub fn get(
+        &self,
+        payment_hash: &str,
+        direction: &PaymentDirection,
+    ) -> error::Result<Option<PaymentRecord>> {
+        let key = Self::key(payment_hash, direction);
+        if let Some(record) = self.persister.read_json(PAYMENTS_NAMESPACE, &key)? {
+            return Ok(Some(record));
+        }
+        // the records stored before that the direction was inside the key
+        let record = self
+            .persister
+            .read_json::<PaymentRecord>(PAYMENTS_NAMESPACE, payment_hash)?
+            .filter(|record| record.direction == *direction);
+        Ok(record)
+    }
+
+    pub fn store(&self, record: &PaymentRecord) -> error::Result<()> {
+        let key = Self::key(&record.payment_hash, &record.direction);
+        self.persister.write_json(PAYMENTS_NAMESPACE, &key, record)
+    }
+
+    /// Store an outgoing payment that is not resolved yet.
+    pub fn payment_started(
+        &self,
+        payment_hash: &str,
+        amount_msat: Option<u64>,
+        bolt11: Option<String>,
+    ) -> error::Result<()> {
+        self.store(&PaymentRecord {
+            payment_hash: payment_hash.to_owned(),
+            direction: PaymentDirection::Sent,
+            state: PaymentState::Pending,
+            amount_msat,
+            fee_msat: None,
+            preimage: None,
+            bolt11,
+            created_at: Self::now(),
+            settled_at: None,
+        })
+    }
+
+    /// Mark the outgoing payment as settled, if the payment was
+    /// not started by us (e.g: an offer payment) a new record is created.
+    pub fn payment_sent(
+        &self,
+        payment_hash: &str,
+        preimage: &str,
+        fee_msat: Option<u64>,
+    ) -> error::Result<()> {
+        let now = Self::now();
+        let mut record =
+            self.get(payment_hash, &PaymentDirection::Sent)?
+                .unwrap_or(PaymentRecord {
+                    payment_hash: payment_hash.to_owned(),
+                    direction: PaymentDirection::Sent,
+                    state: PaymentState::Pending,
+                    amount_msat: None,
+                    fee_msat: None,
+                    preimage: None,
+                    bolt11: None,
+                    created_at: now,
+                    settled_at: None,
+                });
+        record.state = PaymentState::Success;
+        record.preimage = Some(preimage.to_owned());
+        record.fee_msat = fee_msat;
+        record.settled_at = Some(now);
+        self.store(&record)
+    }
+
+    pub fn payment_failed(&self, payment_hash: &str) -> error::Result<()> {
+        let Some(mut record) = self.get(payment_hash, &PaymentDirection::Sent)? else {
+            return Ok(());
+        };
+        record.state = PaymentState::Failure;
+        self.store(&record)
+    }
+
+    pub fn payment_received(
+        &self,
+        payment_hash: &str,
+        preimage: Option<String>,
+        amount_msat: u64,
+    ) -> error::Result<()> {
+        let now = Self::now();
+        self.store(&PaymentRecord {
+            payment_hash: payment_hash.to_owned(),
+            direction: PaymentDirection::Received,
+            state: PaymentState::Success,
+            amount_msat: Some(amount_msat),
+            fee_msat: None,
+            preimage,
+            bolt11: None,
+            created_at: now,
+            settled_at: Some(now),
+        })
+    }
+}
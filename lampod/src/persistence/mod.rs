@@ -3,10 +3,76 @@
 //! N.B: This is an experimental version of the persistence,
 //! please do not use it in production you can lost funds, or
 //! in others words you WILL lost funds, do not trush me!
+use std::io::ErrorKind;
+
+use lampo_common::error;
+use lampo_common::json;
 use lampo_common::ldk::persister::fs_store::FilesystemStore;
+use lampo_common::ldk::util::persist::KVStore;
 
 /// Lampo Persistence implementation.
 // FIME: it is a simple wrapper around the ldk file persister
 // giving more time to understand how to make a custom one without
 // lost funds :-P
 pub type LampoPersistence = FilesystemStore;
+
+/// Store the lampo specific information (that ldk knows nothing about)
+/// as JSON inside the same key value store used by ldk.
+pub trait JsonStore {
+    fn write_json<T: json::Serialize>(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &T,
+    ) -> error::Result<()>;
+
+    fn read_json<T: json::DeserializeOwned>(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> error::Result<Option<T>>;
+
+    fn list_json<T: json::DeserializeOwned>(&self, namespace: &str) -> error::Result<Vec<T>>;
+
+    fn remove_json(&self, namespace: &str, key: &str) -> error::Result<()>;
+}
+
+impl JsonStore for LampoPersistence {
+    fn write_json<T: json::Serialize>(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &T,
+    ) -> error::Result<()> {
+        let buff = json::to_vec(value)?;
+        self.write(namespace, "", key, &buff)?;
+        Ok(())
+    }
+
+    fn read_json<T: json::DeserializeOwned>(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> error::Result<Option<T>> {
+        match self.read(namespace, "", key) {
+            Ok(buff) => Ok(Some(json::from_slice::<T>(&buff)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn list_json<T: json::DeserializeOwned>(&self, namespace: &str) -> error::Result<Vec<T>> {
+        let mut values = Vec::new();
+        for key in self.list(namespace, "")? {
+            if let Some(value) = self.read_json::<T>(namespace, &key)? {
+                values.push(value);
+            }
+        }
+        Ok(values)
+    }
+
+    fn remove_json(&self, namespace: &str, key: &str) -> error::Result<()> {
+        self.remove(namespace, "", key, false)?;
+        Ok(())
+    }
+}
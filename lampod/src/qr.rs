@@ -0,0 +1,39 @@
+//! QR code rendering, useful for point of sale usage
+//! where the payer scans the invoice or the address.
+use std::io::Cursor;
+
+use base64::Engine;
+use qrcode::render::unicode;
+
+use lampo_common::error;
+use lampo_common::utils::qr::QrCode;
+
+pub fn encode(data: &str) -> error::Result<QrCode> {
+    let code = qrcode::QrCode::new(data.as_bytes())?;
+    let ascii = code
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build();
+    let image = code
+        .render::<image::Luma<u8>>()
+        .min_dimensions(256, 256)
+        .build();
+    let mut png = Vec::new();
+    image::DynamicImage::ImageLuma8(image)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)?;
+    Ok(QrCode {
+        ascii,
+        png: base64::engine::general_purpose::STANDARD.encode(png),
+    })
+}
+
+/// Encode a bolt11 invoice, the invoice is upper case to
+/// use the alphanumeric mode that produces a smaller code.
+pub fn bolt11(invoice: &str) -> error::Result<QrCode> {
+    encode(&format!("lightning:{invoice}").to_uppercase())
+}
+
+pub fn address(address: &str) -> error::Result<QrCode> {
+    encode(&format!("bitcoin:{address}"))
+}
@@ -0,0 +1,426 @@
+//! Outgoing payment queue.
+//!
+//! The payments are submitted in bulk (e.g: the withdrawals of an
+//! exchange) and sent in background, with a limited number of payments
+//! in flight, so the route finding is not overwhelmed. The payments to
+//! the same destination are sent one at time in queue order, and a
+//! failed payment is retried after a delay until it runs out of attempts.
+//!
+//! Each payment is stored under its id, and the outcome is read from
+//! the payment store.
+use std::collections::{BTreeMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use lampo_common::bitcoin::secp256k1::PublicKey;
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::model::request::QueuePay;
+use lampo_common::model::response::{PaymentDirection, PaymentState, QueueStatus, QueuedPayment};
+
+use crate::actions::handler::LampoHandler;
+use crate::ln::OffchainManager;
+use crate::payments::LampoPaymentStore;
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const QUEUE_NAMESPACE: &str = "payment_queue";
+/// How often we look at the queue.
+const TICK: Duration = Duration::from_secs(5);
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+pub struct LampoPaymentQueue {
+    concurrency: usize,
+    max_attempts: u64,
+    retry_delay: u64,
+    persister: Arc<LampoPersistence>,
+    payments: Arc<LampoPaymentStore>,
+    // seq -> payment
+    queue: Mutex<BTreeMap<u64, QueuedPayment>>,
+}
+
+impl LampoPaymentQueue {
+    pub fn new(
+        conf: &LampoConf,
+        persister: Arc<LampoPersistence>,
+        payments: Arc<LampoPaymentStore>,
+    ) -> error::Result<Self> {
+        let queue = persister
+            .list_json::<QueuedPayment>(QUEUE_NAMESPACE)?
+            .into_iter()
+            .map(|payment| (payment.seq, payment))
+            .collect();
+        Ok(Self {
+            concurrency: conf.payment_queue_concurrency as usize,
+            max_attempts: conf.payment_queue_max_attempts,
+            retry_delay: conf.payment_queue_retry_delay,
+            persister,
+            payments,
+            queue: Mutex::new(queue),
+        })
+    }
+
+    fn store(
+        &self,
+        queue: &mut BTreeMap<u64, QueuedPayment>,
+        payment: QueuedPayment,
+    ) -> error::Result<()> {
+        self.persister
+            .write_json(QUEUE_NAMESPACE, &payment.id, &payment)?;
+        queue.insert(payment.seq, payment);
+        Ok(())
+    }
+
+    pub fn add(
+        &self,
+        offchain: &OffchainManager,
+        request: QueuePay,
+    ) -> error::Result<QueuedPayment> {
+        let mut queue = self.queue.lock().unwrap();
+        let seq = queue.keys().last().map(|seq| seq + 1).unwrap_or_default();
+        let (id, destination) = match (&request.invoice, &request.destination) {
+            (Some(invoice), None) => {
+                let invoice = offchain.decode_invoice(invoice)?;
+                if invoice.amount_milli_satoshis().is_none() && request.amount_msat.is_none() {
+                    error::bail!("the invoice has no amount, specify `amount_msat`");
+                }
+                let destination = invoice
+                    .payee_pub_key()
+                    .cloned()
+                    .unwrap_or_else(|| invoice.recover_payee_pub_key());
+                let id = request
+                    .id
+                    .unwrap_or_else(|| invoice.payment_hash().to_string());
+                (id, destination.to_string())
+            }
+            (None, Some(destination)) => {
+                PublicKey::from_str(destination)?;
+                if request.amount_msat.is_none() {
+                    error::bail!("`amount_msat` is required for keysend");
+                }
+                let id = request.id.unwrap_or_else(|| format!("keysend-{seq}"));
+                (id, destination.clone())
+            }
+            _ => error::bail!("one of `invoice` or `destination` must be specified"),
+        };
+        if queue.values().any(|payment| payment.id == id) {
+            error::bail!("a payment with id `{id}` is already inside the queue");
+        }
+        let now = now();
+        let payment = QueuedPayment {
+            id,
+            seq,
+            invoice: request.invoice,
+            destination,
+            amount_msat: request.amount_msat,
+            status: QueueStatus::Queued,
+            attempts: 0,
+            payment_hash: None,
+            last_error: None,
+            created_at: now,
+            next_attempt: now,
+        };
+        self.store(&mut queue, payment.clone())?;
+        Ok(payment)
+    }
+
+    pub fn list(&self, status: Option<QueueStatus>) -> Vec<QueuedPayment> {
+        self.queue
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|payment| {
+                status
+                    .as_ref()
+                    .map_or(true, |status| payment.status == *status)
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn pay(offchain: &OffchainManager, payment: &QueuedPayment) -> error::Result<String> {
+        if let Some(invoice) = &payment.invoice {
+            offchain.pay_invoice(invoice, payment.amount_msat)?;
+            let invoice = offchain.decode_invoice(invoice)?;
+            return Ok(invoice.payment_hash().to_string());
+        }
+        let destination = PublicKey::from_str(&payment.destination)?;
+        let amount_msat = payment
+            .amount_msat
+            .expect("the amount is checked when the keysend is queued");
+        let payment_hash = offchain.keysend(destination, amount_msat)?;
+        Ok(payment_hash.to_string())
+    }
+
+    /// Schedule a retry of the payment, or give up when there
+    /// are no attempts left.
+    fn failed(&self, payment: &mut QueuedPayment, reason: String, now: u64) -> bool {
+        payment.last_error = Some(reason);
+        if payment.attempts >= self.max_attempts {
+            payment.status = QueueStatus::Failed;
+            return true;
+        }
+        payment.status = QueueStatus::Queued;
+        payment.next_attempt = now + self.retry_delay * payment.attempts;
+        false
+    }
+
+    /// Update the payments in flight and send the next ones, return
+    /// the payments that failed all the attempts.
+    ///
+    /// The queue is not locked while the payments are sent, because
+    /// sending a payment can block on the route finding.
+    fn tick(&self, offchain: &OffchainManager) -> error::Result<Vec<(String, String)>> {
+        self.tick_with(|payment| Self::pay(offchain, payment))
+    }
+
+    /// Same as `tick`, but the payments are sent with `pay`.
+    fn tick_with<F>(&self, mut pay: F) -> error::Result<Vec<(String, String)>>
+    where
+        F: FnMut(&QueuedPayment) -> error::Result<String>,
+    {
+        let now = now();
+        let mut alerts = Vec::new();
+        let due = {
+            let mut queue = self.queue.lock().unwrap();
+            let inflight = queue
+                .values()
+                .filter(|payment| payment.status == QueueStatus::Inflight)
+                .cloned()
+                .collect::<Vec<_>>();
+            for mut payment in inflight {
+                let payment_hash = payment
+                    .payment_hash
+                    .clone()
+                    .expect("a payment in flight has the payment hash");
+                let record = self.payments.get(&payment_hash, &PaymentDirection::Sent)?;
+                match record.map(|record| record.state) {
+                    Some(PaymentState::Success) => payment.status = QueueStatus::Succeeded,
+                    Some(PaymentState::Failure) => {
+                        if self.failed(&mut payment, "payment failed".to_owned(), now) {
+                            alerts.push((payment.id.clone(), "payment failed".to_owned()));
+                        }
+                    }
+                    _ => continue,
+                }
+                self.store(&mut queue, payment)?;
+            }
+
+            let mut inflight = queue
+                .values()
+                .filter(|payment| payment.status == QueueStatus::Inflight)
+                .count();
+            // the destinations with a payment that is not resolved,
+            // the following payments must wait for it.
+            let mut busy = HashSet::new();
+            let mut due = Vec::new();
+            for payment in queue.values() {
+                if !matches!(payment.status, QueueStatus::Queued | QueueStatus::Inflight) {
+                    continue;
+                }
+                if !busy.insert(payment.destination.clone()) {
+                    continue;
+                }
+                if payment.status != QueueStatus::Queued || payment.next_attempt > now {
+                    continue;
+                }
+                if inflight >= self.concurrency {
+                    break;
+                }
+                due.push(payment.clone());
+                inflight += 1;
+            }
+            due
+        };
+
+        for mut payment in due {
+            payment.attempts += 1;
+            match pay(&payment) {
+                Ok(payment_hash) => {
+                    log::info!(target: "payment-queue", "payment `{}` sent, attempt {}", payment.id, payment.attempts);
+                    payment.status = QueueStatus::Inflight;
+                    payment.payment_hash = Some(payment_hash);
+                }
+                Err(err) => {
+                    if self.failed(&mut payment, format!("{err}"), now) {
+                        alerts.push((payment.id.clone(), format!("{err}")));
+                    }
+                }
+            }
+            self.store(&mut self.queue.lock().unwrap(), payment)?;
+        }
+        Ok(alerts)
+    }
+
+    pub fn run(
+        self: Arc<Self>,
+        offchain: Arc<OffchainManager>,
+        handler: Arc<LampoHandler>,
+    ) -> JoinHandle<()> {
+        std::thread::spawn(move || loop {
+            match self.tick(&offchain) {
+                Ok(alerts) => {
+                    for (id, reason) in alerts {
+                        log::warn!(target: "payment-queue", "payment `{id}` failed all the attempts: {reason}");
+                        handler.emit(Event::Lightning(LightningEvent::QueuedPaymentFailed {
+                            id,
+                            reason,
+                        }));
+                    }
+                }
+                Err(err) => log::error!(target: "payment-queue", "{err}"),
+            }
+            std::thread::sleep(TICK);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    use lampo_common::error;
+    use lampo_common::model::response::{QueueStatus, QueuedPayment};
+
+    use super::{now, LampoPaymentQueue};
+    use crate::payments::LampoPaymentStore;
+    use crate::persistence::LampoPersistence;
+
+    fn queue(name: &str, concurrency: usize) -> LampoPaymentQueue {
+        let path = std::env::temp_dir().join(format!("lampo-queue-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let persister = Arc::new(LampoPersistence::new(path));
+        LampoPaymentQueue {
+            concurrency,
+            max_attempts: 2,
+            retry_delay: 60,
+            persister: persister.clone(),
+            payments: Arc::new(LampoPaymentStore::new(persister)),
+            queue: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn push(queue: &LampoPaymentQueue, id: &str, destination: &str) {
+        let mut inner = queue.queue.lock().unwrap();
+        let payment = QueuedPayment {
+            id: id.to_owned(),
+            seq: inner.len() as u64,
+            invoice: None,
+            destination: destination.to_owned(),
+            amount_msat: Some(1_000),
+            status: QueueStatus::Queued,
+            attempts: 0,
+            payment_hash: None,
+            last_error: None,
+            created_at: now(),
+            next_attempt: now(),
+        };
+        queue.store(&mut inner, payment).unwrap();
+    }
+
+    fn status(queue: &LampoPaymentQueue, id: &str) -> QueuedPayment {
+        queue
+            .list(None)
+            .into_iter()
+            .find(|payment| payment.id == id)
+            .unwrap()
+    }
+
+    /// Send the payments using the id as payment hash.
+    fn tick(queue: &LampoPaymentQueue, sent: &mut Vec<String>) -> Vec<(String, String)> {
+        queue
+            .tick_with(|payment| {
+                sent.push(payment.id.clone());
+                queue
+                    .payments
+                    .payment_started(&payment.id, payment.amount_msat, None)?;
+                Ok(payment.id.clone())
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn destination_order() {
+        let queue = queue("order", 10);
+        push(&queue, "01", "alice");
+        push(&queue, "02", "alice");
+        push(&queue, "03", "bob");
+
+        let mut sent = Vec::new();
+        tick(&queue, &mut sent);
+        // the second payment to alice waits for the first one
+        assert_eq!(sent, vec!["01", "03"]);
+        assert_eq!(status(&queue, "02").status, QueueStatus::Queued);
+
+        tick(&queue, &mut sent);
+        assert_eq!(sent, vec!["01", "03"]);
+
+        queue.payments.payment_sent("01", "00", None).unwrap();
+        tick(&queue, &mut sent);
+        assert_eq!(status(&queue, "01").status, QueueStatus::Succeeded);
+        assert_eq!(status(&queue, "02").status, QueueStatus::Inflight);
+        assert_eq!(sent, vec!["01", "03", "02"]);
+    }
+
+    #[test]
+    fn concurrency() {
+        let queue = queue("concurrency", 2);
+        push(&queue, "01", "alice");
+        push(&queue, "02", "bob");
+        push(&queue, "03", "carol");
+
+        let mut sent = Vec::new();
+        tick(&queue, &mut sent);
+        assert_eq!(sent, vec!["01", "02"]);
+        assert_eq!(status(&queue, "03").status, QueueStatus::Queued);
+
+        // a failed payment leaves room to the next one
+        queue.payments.payment_failed("02").unwrap();
+        tick(&queue, &mut sent);
+        assert_eq!(sent, vec!["01", "02", "03"]);
+        assert_eq!(status(&queue, "02").status, QueueStatus::Queued);
+    }
+
+    #[test]
+    fn retry() {
+        let queue = queue("retry", 2);
+        push(&queue, "01", "alice");
+
+        let mut attempts = 0;
+        let mut fail = |_: &QueuedPayment| -> error::Result<String> {
+            attempts += 1;
+            error::bail!("no route found")
+        };
+        assert!(queue.tick_with(&mut fail).unwrap().is_empty());
+        let payment = status(&queue, "01");
+        assert_eq!(payment.status, QueueStatus::Queued);
+        assert_eq!(payment.attempts, 1);
+        assert_eq!(payment.last_error.as_deref(), Some("no route found"));
+        assert!(payment.next_attempt >= now() + 59);
+
+        // the payment is not retried before the delay
+        queue.tick_with(&mut fail).unwrap();
+        assert_eq!(status(&queue, "01").attempts, 1);
+
+        let mut payment = status(&queue, "01");
+        payment.next_attempt = now();
+        queue
+            .store(&mut queue.queue.lock().unwrap(), payment)
+            .unwrap();
+        let alerts = queue.tick_with(&mut fail).unwrap();
+        assert_eq!(alerts, vec![("01".to_owned(), "no route found".to_owned())]);
+        assert_eq!(status(&queue, "01").status, QueueStatus::Failed);
+        assert_eq!(attempts, 2);
+    }
+}
@@ -0,0 +1,101 @@
+//! Rate limiter implementation.
+//!
+//! Used to limit the number of invoices that each source (the
+//! JSON RPC, or a public facing integration) can create, so
+//! a flood of requests can not fill the invoice store.
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lampo_common::error;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+pub struct LampoRateLimiter {
+    /// Max number of hits for each source inside the window,
+    /// `None` disable the limiter.
+    limit: Option<u64>,
+    hits: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl LampoRateLimiter {
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            limit,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register `count` hits for the `source`, if the source
+    /// is over the limit nothing is registered.
+    pub fn check(&self, source: &str, count: u64) -> error::Result<()> {
+        let Some(limit) = self.limit else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut hits = self.hits.lock().unwrap();
+        // forget about the sources that are quiet
+        hits.retain(|_, source_hits| {
+            while source_hits
+                .front()
+                .is_some_and(|hit| now.duration_since(*hit) >= WINDOW)
+            {
+                source_hits.pop_front();
+            }
+            !source_hits.is_empty()
+        });
+        let source_hits = hits.entry(source.to_owned()).or_default();
+        if source_hits.len() as u64 + count > limit {
+            error::bail!("rate limit reached for `{source}`: max `{limit}` invoices per minute");
+        }
+        source_hits.extend(std::iter::repeat(now).take(count as usize));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::{LampoRateLimiter, WINDOW};
+
+    #[test]
+    fn disabled() {
+        let limiter = LampoRateLimiter::new(None);
+        for _ in 0..100 {
+            assert!(limiter.check("rpc", 10).is_ok());
+        }
+    }
+
+    #[test]
+    fn limit_by_source() {
+        let limiter = LampoRateLimiter::new(Some(3));
+        assert!(limiter.check("rpc", 1).is_ok());
+        assert!(limiter.check("rpc", 2).is_ok());
+        let err = limiter.check("rpc", 1).unwrap_err();
+        assert!(err.to_string().contains("`rpc`"), "{err}");
+        // the other sources have their own limit
+        assert!(limiter.check("lnurl", 3).is_ok());
+    }
+
+    #[test]
+    fn refused_hits_are_not_registered() {
+        let limiter = LampoRateLimiter::new(Some(3));
+        assert!(limiter.check("rpc", 2).is_ok());
+        assert!(limiter.check("rpc", 2).is_err());
+        assert!(limiter.check("rpc", 1).is_ok());
+    }
+
+    #[test]
+    fn hits_expire() {
+        let limiter = LampoRateLimiter::new(Some(1));
+        let old = Instant::now().checked_sub(WINDOW).unwrap();
+        limiter
+            .hits
+            .lock()
+            .unwrap()
+            .insert("rpc".to_owned(), [old].into());
+        assert!(limiter.check("rpc", 1).is_ok());
+        assert!(limiter.check("rpc", 1).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+//! Exchange rate implementation.
+//!
+//! Used to create invoices with a fiat amount, the amount is
+//! converted at creation time and the rate used is stored
+//! inside the invoice record for the merchant accounting.
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lampo_common::conf::LampoConf;
+use lampo_common::error;
+use lampo_common::json;
+use lampo_common::model::response::FiatRate;
+
+const MSAT_PER_BTC: f64 = 100_000_000_000.0;
+
+pub trait ExchangeRateProvider: Send + Sync {
+    fn name(&self) -> String;
+
+    /// Price of one bitcoin in `currency`.
+    fn btc_price(&self, currency: &str) -> error::Result<f64>;
+}
+
+/// Build the provider specified inside the configuration.
+pub fn provider(conf: &LampoConf) -> Option<Arc<dyn ExchangeRateProvider>> {
+    match conf.exchange_rate_provider.as_deref() {
+        Some("coingecko") => Some(Arc::new(CoinGecko)),
+        Some(provider) => {
+            log::warn!(target: "rates", "unknown exchange rate provider `{provider}`, fiat amounts are disabled");
+            None
+        }
+        None => None,
+    }
+}
+
+pub struct CoinGecko;
+
+impl ExchangeRateProvider for CoinGecko {
+    fn name(&self) -> String {
+        "coingecko".to_owned()
+    }
+
+    fn btc_price(&self, currency: &str) -> error::Result<f64> {
+        let currency = currency.to_lowercase();
+        let url = format!(
+            "https://api.coingecko.com/api/v3/simple/price?ids=bitcoin&vs_currencies={currency}"
+        );
+        let response = minreq::get(url).with_timeout(30).send()?;
+        if response.status_code != 200 {
+            error::bail!("coingecko returned status code `{}`", response.status_code);
+        }
+        let body: json::Value = json::from_str(response.as_str()?)?;
+        body["bitcoin"][&currency].as_f64().ok_or(error::anyhow!(
+            "coingecko does not know the currency `{currency}`"
+        ))
+    }
+}
+
+/// Fiat amount in the format `5 USD`.
+#[derive(Debug, Clone)]
+pub struct FiatAmount {
+    pub amount: f64,
+    pub currency: String,
+}
+
+impl FromStr for FiatAmount {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((amount, currency)) = s.trim().split_once(' ') else {
+            error::bail!("invalid fiat amount `{s}`, expected something like `5 USD`");
+        };
+        let amount = f64::from_str(amount.trim())?;
+        if !amount.is_finite() || amount <= 0.0 {
+            error::bail!("the fiat amount must be positive, got `{amount}`");
+        }
+        Ok(Self {
+            amount,
+            currency: currency.trim().to_uppercase(),
+        })
+    }
+}
+
+impl FiatAmount {
+    /// Convert the amount with the current price of the provider.
+    pub fn convert(&self, provider: &dyn ExchangeRateProvider) -> error::Result<FiatRate> {
+        let btc_price = provider.btc_price(&self.currency)?;
+        if btc_price <= 0.0 {
+            error::bail!("invalid price `{btc_price}` for `{}`", self.currency);
+        }
+        let amount_msat = (self.amount / btc_price * MSAT_PER_BTC).round() as u64;
+        Ok(FiatRate {
+            currency: self.currency.clone(),
+            amount: self.amount,
+            btc_price,
+            amount_msat,
+            provider: provider.name(),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use lampo_common::error;
+
+    use super::{ExchangeRateProvider, FiatAmount};
+
+    struct FixedPrice(f64);
+
+    impl ExchangeRateProvider for FixedPrice {
+        fn name(&self) -> String {
+            "fixed".to_owned()
+        }
+
+        fn btc_price(&self, _: &str) -> error::Result<f64> {
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn fiat_amount_from_str() {
+        let fiat = FiatAmount::from_str("5 usd").unwrap();
+        assert_eq!(fiat.amount, 5.0);
+        assert_eq!(fiat.currency, "USD");
+        let fiat = FiatAmount::from_str(" 12.5  EUR ").unwrap();
+        assert_eq!(fiat.amount, 12.5);
+        assert_eq!(fiat.currency, "EUR");
+    }
+
+    #[test]
+    fn invalid_fiat_amount() {
+        for fiat in ["5USD", "five USD", ""] {
+            assert!(FiatAmount::from_str(fiat).is_err(), "{fiat}");
+        }
+        for fiat in ["0 USD", "-1 USD", "inf USD", "NaN USD"] {
+            let err = FiatAmount::from_str(fiat).unwrap_err();
+            assert!(err.to_string().contains("must be positive"), "{err}");
+        }
+    }
+
+    #[test]
+    fn convert() {
+        let fiat = FiatAmount::from_str("5 USD").unwrap();
+        let rate = fiat.convert(&FixedPrice(50_000.0)).unwrap();
+        assert_eq!(rate.amount_msat, 10_000_000);
+        assert_eq!(rate.currency, "USD");
+        assert_eq!(rate.provider, "fixed");
+        assert!(fiat.convert(&FixedPrice(0.0)).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Recurring payments scheduler.
+//!
+//! Pay a bolt12 offer (or send a keysend) every time that the
+//! schedule expires, until the budget is exhausted. The schedules
+//! are stored inside the persistence, so they survive a restart.
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use lampo_common::bitcoin::hashes::sha256::Hash as Sha256;
+use lampo_common::bitcoin::hashes::Hash;
+use lampo_common::bitcoin::secp256k1::PublicKey;
+use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::ldk::ln::channelmanager::PaymentId;
+use lampo_common::ldk::offers::offer::{Amount, Offer};
+use lampo_common::model::request::SchedulePay;
+use lampo_common::model::response::Schedule;
+
+use crate::actions::handler::LampoHandler;
+use crate::ln::OffchainManager;
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const SCHEDULES_NAMESPACE: &str = "schedules";
+const SCHEDULES_KEY: &str = "index";
+/// How often we check if some payment is due.
+const TICK: Duration = Duration::from_secs(30);
+/// After this number of consecutive failures the schedule is stopped.
+const MAX_FAILURES: u32 = 5;
+
+/// Parse the schedule expression and return the interval in seconds.
+pub fn parse_schedule(schedule: &str) -> error::Result<u64> {
+    let interval = match schedule.trim() {
+        "@hourly" => 60 * 60,
+        "@daily" => 24 * 60 * 60,
+        "@weekly" => 7 * 24 * 60 * 60,
+        "@monthly" => 30 * 24 * 60 * 60,
+        expr => {
+            let (value, unit) = expr.split_at(expr.len().saturating_sub(1));
+            let value = u64::from_str(value)
+                .map_err(|_| error::anyhow!("invalid schedule `{schedule}`"))?;
+            let unit = match unit {
+                "s" => 1,
+                "m" => 60,
+                "h" => 60 * 60,
+                "d" => 24 * 60 * 60,
+                _ => error::bail!("invalid schedule `{schedule}`, unknown unit `{unit}`"),
+            };
+            value * unit
+        }
+    };
+    if interval < 60 {
+        error::bail!("the schedule `{schedule}` is too frequent, the minimum is one minute");
+    }
+    Ok(interval)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+pub struct LampoScheduler {
+    persister: Arc<LampoPersistence>,
+    schedules: Mutex<Vec<Schedule>>,
+}
+
+impl LampoScheduler {
+    pub fn new(persister: Arc<LampoPersistence>) -> error::Result<Self> {
+        let schedules = persister
+            .read_json::<Vec<Schedule>>(SCHEDULES_NAMESPACE, SCHEDULES_KEY)?
+            .unwrap_or_default();
+        Ok(Self {
+            persister,
+            schedules: Mutex::new(schedules),
+        })
+    }
+
+    fn store(&self, schedules: &Vec<Schedule>) -> error::Result<()> {
+        self.persister
+            .write_json(SCHEDULES_NAMESPACE, SCHEDULES_KEY, schedules)
+    }
+
+    pub fn add(&self, request: SchedulePay) -> error::Result<Schedule> {
+        let amount_msat = match (&request.offer, &request.destination) {
+            (Some(offer), None) => {
+                let offer = Offer::from_str(offer).map_err(|err| error::anyhow!("{:?}", err))?;
+                match offer.amount() {
+                    Some(Amount::Bitcoin { amount_msats }) => *amount_msats,
+                    Some(_) => error::bail!("the offer is not denominated in bitcoin"),
+                    None => request.amount_msat.ok_or(error::anyhow!(
+                        "the offer has no amount, specify `amount_msat`"
+                    ))?,
+                }
+            }
+            (None, Some(destination)) => {
+                PublicKey::from_str(destination)?;
+                request
+                    .amount_msat
+                    .ok_or(error::anyhow!("`amount_msat` is required for keysend"))?
+            }
+            _ => error::bail!("one of `offer` or `destination` must be specified"),
+        };
+        let interval = parse_schedule(&request.schedule)?;
+        if let Some(budget) = request.budget_msat {
+            if budget < amount_msat {
+                error::bail!("the budget `{budget}` is lower than the amount `{amount_msat}`");
+            }
+        }
+
+        let mut schedules = self.schedules.lock().unwrap();
+        if schedules.iter().any(|schedule| schedule.id == request.id) {
+            error::bail!("a schedule with id `{}` already exists", request.id);
+        }
+        let schedule = Schedule {
+            id: request.id,
+            offer: request.offer,
+            destination: request.destination,
+            amount_msat,
+            schedule: request.schedule,
+            interval,
+            budget_msat: request.budget_msat,
+            spent_msat: 0,
+            // the first payment is made at the first tick
+            next_run: now(),
+            failures: 0,
+            active: true,
+        };
+        let mut updated = schedules.clone();
+        updated.push(schedule.clone());
+        self.store(&updated)?;
+        *schedules = updated;
+        Ok(schedule)
+    }
+
+    pub fn remove(&self, id: &str) -> error::Result<Schedule> {
+        let mut schedules = self.schedules.lock().unwrap();
+        let Some(idx) = schedules.iter().position(|schedule| schedule.id == id) else {
+            error::bail!("schedule `{id}` not found");
+        };
+        let mut updated = schedules.clone();
+        let schedule = updated.remove(idx);
+        self.store(&updated)?;
+        *schedules = updated;
+        Ok(schedule)
+    }
+
+    pub fn list(&self) -> Vec<Schedule> {
+        self.schedules.lock().unwrap().clone()
+    }
+
+    /// Make the payment of the `schedule` due at `run`.
+    fn pay(offchain: &OffchainManager, schedule: &Schedule, run: u64) -> error::Result<()> {
+        if let Some(offer) = &schedule.offer {
+            // each run has its own payment id, ldk refuses
+            // an id that it still remembers.
+            let payment_id = Sha256::hash(format!("{}:{run}", schedule.id).as_bytes());
+            return offchain.pay_offer_with_id(
+                offer,
+                Some(schedule.amount_msat),
+                None,
+                PaymentId(payment_id.to_byte_array()),
+            );
+        }
+        let Some(destination) = &schedule.destination else {
+            error::bail!("the schedule `{}` has no offer or destination", schedule.id);
+        };
+        offchain.keysend(PublicKey::from_str(destination)?, schedule.amount_msat)?;
+        Ok(())
+    }
+
+    /// Make all the payments that are due, and return the
+    /// alerts that should be notified to the user.
+    ///
+    /// The schedules are not locked while the payments are sent,
+    /// because sending a payment can block on the route finding.
+    fn tick(&self, offchain: &OffchainManager) -> error::Result<Vec<(String, String)>> {
+        let now = now();
+        let mut alerts = Vec::new();
+        let due = {
+            let mut schedules = self.schedules.lock().unwrap();
+            let mut updated = schedules.clone();
+            let mut due = Vec::new();
+            for schedule in updated
+                .iter_mut()
+                .filter(|schedule| schedule.active && schedule.next_run <= now)
+            {
+                if let Some(budget) = schedule.budget_msat {
+                    if schedule.spent_msat + schedule.amount_msat > budget {
+                        schedule.active = false;
+                        alerts.push((
+                            schedule.id.clone(),
+                            format!("budget of `{budget}` msat exhausted"),
+                        ));
+                        continue;
+                    }
+                }
+                due.push((schedule.clone(), schedule.next_run));
+                schedule.next_run = now + schedule.interval;
+            }
+            self.store(&updated)?;
+            *schedules = updated;
+            due
+        };
+
+        for (schedule, run) in due {
+            let result = Self::pay(offchain, &schedule, run);
+            let mut schedules = self.schedules.lock().unwrap();
+            let mut updated = schedules.clone();
+            // the schedule can be removed while it is paid
+            let Some(schedule) = updated.iter_mut().find(|other| other.id == schedule.id) else {
+                continue;
+            };
+            match result {
+                Ok(()) => {
+                    log::info!(target: "scheduler", "recurring payment `{}` sent", schedule.id);
+                    schedule.spent_msat += schedule.amount_msat;
+                    schedule.failures = 0;
+                }
+                Err(err) => {
+                    schedule.failures += 1;
+                    if schedule.failures >= MAX_FAILURES {
+                        schedule.active = false;
+                    }
+                    alerts.push((schedule.id.clone(), format!("{err}")));
+                }
+            }
+            self.store(&updated)?;
+            *schedules = updated;
+        }
+        Ok(alerts)
+    }
+
+    pub fn run(
+        self: Arc<Self>,
+        offchain: Arc<OffchainManager>,
+        handler: Arc<LampoHandler>,
+    ) -> JoinHandle<()> {
+        std::thread::spawn(move || loop {
+            match self.tick(&offchain) {
+                Ok(alerts) => {
+                    for (id, reason) in alerts {
+                        log::warn!(target: "scheduler", "recurring payment `{id}` failed: {reason}");
+                        handler.emit(Event::Lightning(LightningEvent::ScheduledPaymentFailed {
+                            id,
+                            reason,
+                        }));
+                    }
+                }
+                Err(err) => log::error!(target: "scheduler", "{err}"),
+            }
+            std::thread::sleep(TICK);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_schedule;
+
+    #[test]
+    fn aliases() {
+        assert_eq!(parse_schedule("@hourly").unwrap(), 60 * 60);
+        assert_eq!(parse_schedule("@daily").unwrap(), 24 * 60 * 60);
+        assert_eq!(parse_schedule("@weekly").unwrap(), 7 * 24 * 60 * 60);
+        assert_eq!(parse_schedule(" @monthly ").unwrap(), 30 * 24 * 60 * 60);
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(parse_schedule("60s").unwrap(), 60);
+        assert_eq!(parse_schedule("1m").unwrap(), 60);
+        assert_eq!(parse_schedule("2h").unwrap(), 2 * 60 * 60);
+        assert_eq!(parse_schedule("3d").unwrap(), 3 * 24 * 60 * 60);
+    }
+
+    #[test]
+    fn too_frequent() {
+        let err = parse_schedule("59s").unwrap_err();
+        assert!(err.to_string().contains("too frequent"), "{err}");
+        assert!(parse_schedule("0m").is_err());
+    }
+
+    #[test]
+    fn invalid_schedule() {
+        let err = parse_schedule("5w").unwrap_err();
+        assert!(err.to_string().contains("unknown unit `w`"), "{err}");
+        assert!(parse_schedule("").is_err());
+        assert!(parse_schedule("m").is_err());
+        assert!(parse_schedule("-1h").is_err());
+        assert!(parse_schedule("@yearly").is_err());
+    }
+}
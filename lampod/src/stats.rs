@@ -0,0 +1,107 @@
+//! Node statistics implementation.
+//!
+//! Keep track of the counters since the node started, and
+//! the lifetime counters that are stored inside the persistence.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use lampo_common::model::response::{Counters, Stats};
+
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const STATS_NAMESPACE: &str = "stats";
+const LIFETIME_KEY: &str = "lifetime";
+
+pub struct LampoStats {
+    persister: Arc<LampoPersistence>,
+    since_startup: Mutex<Counters>,
+    lifetime: Mutex<Counters>,
+    // payment id -> instant when the payment was sent
+    inflight_payments: Mutex<HashMap<[u8; 32], Instant>>,
+}
+
+impl LampoStats {
+    pub fn new(persister: Arc<LampoPersistence>) -> Self {
+        let lifetime = match persister.read_json::<Counters>(STATS_NAMESPACE, LIFETIME_KEY) {
+            Ok(lifetime) => lifetime.unwrap_or_default(),
+            Err(err) => {
+                log::warn!(target: "stats", "impossible read the lifetime stats, starting from zero: {err}");
+                Counters::default()
+            }
+        };
+        Self {
+            persister,
+            since_startup: Mutex::new(Counters::default()),
+            lifetime: Mutex::new(lifetime),
+            inflight_payments: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Apply the `update` to both the counters and store
+    /// the lifetime counters.
+    fn update<F: Fn(&mut Counters)>(&self, update: F) {
+        update(&mut self.since_startup.lock().unwrap());
+        let mut lifetime = self.lifetime.lock().unwrap();
+        update(&mut lifetime);
+        if let Err(err) = self
+            .persister
+            .write_json(STATS_NAMESPACE, LIFETIME_KEY, &*lifetime)
+        {
+            log::error!(target: "stats", "impossible store the lifetime stats: {err}");
+        }
+    }
+
+    pub fn payment_started(&self, payment_id: [u8; 32]) {
+        self.inflight_payments
+            .lock()
+            .unwrap()
+            .insert(payment_id, Instant::now());
+    }
+
+    pub fn payment_sent(&self, payment_id: Option<[u8; 32]>) {
+        let latency = payment_id
+            .and_then(|id| self.inflight_payments.lock().unwrap().remove(&id))
+            .map(|start| start.elapsed().as_millis() as u64);
+        self.update(|counters| {
+            counters.payments_sent += 1;
+            if let Some(latency) = latency {
+                counters.timed_payments += 1;
+                counters.total_payment_latency_ms += latency;
+                counters.avg_payment_latency_ms =
+                    Some(counters.total_payment_latency_ms / counters.timed_payments);
+            }
+        });
+    }
+
+    pub fn payment_failed(&self, payment_id: [u8; 32], reason: &str) {
+        self.inflight_payments.lock().unwrap().remove(&payment_id);
+        self.update(|counters| {
+            counters.payments_failed += 1;
+            *counters.failures.entry(reason.to_owned()).or_default() += 1;
+        });
+    }
+
+    pub fn payment_received(&self) {
+        self.update(|counters| counters.payments_received += 1);
+    }
+
+    pub fn forwarded(&self) {
+        self.update(|counters| counters.forwards += 1);
+    }
+
+    pub fn forward_failed(&self) {
+        self.update(|counters| counters.forward_failures += 1);
+    }
+
+    pub fn peer_disconnected(&self) {
+        self.update(|counters| counters.peer_disconnects += 1);
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            since_startup: self.since_startup.lock().unwrap().clone(),
+            lifetime: self.lifetime.lock().unwrap().clone(),
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! On chain transaction index.
+//!
+//! The wallet knows only the transactions that move its own coins,
+//! so we keep track of the transactions related to the channels
+//! (fundings, closes and the sweeps of the close outputs) to
+//! give the full history of the node.
+//!
+//! Each transaction is stored under its txid.
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lampo_common::bitcoin::hashes::Hash;
+use lampo_common::bitcoin::{OutPoint, Transaction};
+use lampo_common::error;
+use lampo_common::event::ln::LightningEvent;
+use lampo_common::event::onchain::OnChainEvent;
+use lampo_common::event::Event;
+use lampo_common::handler::Handler;
+use lampo_common::model::response::{TransactionKind, TransactionRecord};
+use lampo_common::types::ChannelId;
+
+use crate::actions::handler::LampoHandler;
+use crate::persistence::{JsonStore, LampoPersistence};
+
+const TRANSACTIONS_NAMESPACE: &str = "transactions";
+
+pub struct LampoTransactionIndex {
+    persister: Arc<LampoPersistence>,
+    // txid -> transaction
+    transactions: Mutex<BTreeMap<String, TransactionRecord>>,
+}
+
+impl LampoTransactionIndex {
+    pub fn new(persister: Arc<LampoPersistence>) -> error::Result<Self> {
+        let transactions = persister
+            .list_json::<TransactionRecord>(TRANSACTIONS_NAMESPACE)?
+            .into_iter()
+            .map(|record| (record.txid.clone(), record))
+            .collect();
+        Ok(Self {
+            persister,
+            transactions: Mutex::new(transactions),
+        })
+    }
+
+    fn store(&self, record: TransactionRecord) -> error::Result<()> {
+        self.persister
+            .write_json(TRANSACTIONS_NAMESPACE, &record.txid, &record)?;
+        self.transactions
+            .lock()
+            .unwrap()
+            .insert(record.txid.clone(), record);
+        Ok(())
+    }
+
+    fn get(&self, txid: &str) -> Option<TransactionRecord> {
+        self.transactions.lock().unwrap().get(txid).cloned()
+    }
+
+    /// Record the funding transaction created by our wallet.
+    pub fn record_funding(&self, tx: &Transaction, amount_sat: u64) -> error::Result<()> {
+        let txid = tx.txid().to_string();
+        if self.get(&txid).is_some() {
+            return Ok(());
+        }
+        self.store(TransactionRecord {
+            txid,
+            kind: TransactionKind::Funding,
+            amount_sat: Some(amount_sat),
+            fee_sat: None,
+            channel_id: None,
+            label: None,
+            block_height: None,
+            confirmations: 0,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        })
+    }
+
+    /// Link the funding transaction to its channel, the funding
+    /// of the channels opened by the peer are recorded here too.
+    pub fn record_channel(&self, funding: &OutPoint, channel_id: &ChannelId) -> error::Result<()> {
+        let txid = funding.txid.to_string();
+        let mut record = self.get(&txid).unwrap_or(TransactionRecord {
+            txid,
+            kind: TransactionKind::Funding,
+            amount_sat: None,
+            fee_sat: None,
+            channel_id: None,
+            label: None,
+            block_height: None,
+            confirmations: 0,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        });
+        if record.channel_id.as_deref() == Some(&channel_id.to_string()) {
+            return Ok(());
+        }
+        record.channel_id = Some(channel_id.to_string());
+        self.store(record)
+    }
+
+    /// Index the transaction if it is already known or if it spends
+    /// one of the channel transactions.
+    pub fn process_transaction(&self, tx: &Transaction, height: Option<u32>) -> error::Result<()> {
+        if let Some(mut record) = self.get(&tx.txid().to_string()) {
+            if height.is_none() || record.block_height == height {
+                return Ok(());
+            }
+            record.block_height = height;
+            return self.store(record);
+        }
+
+        let spent = tx
+            .input
+            .iter()
+            .filter_map(|input| self.get(&input.previous_output.txid.to_string()))
+            .collect::<Vec<_>>();
+        let Some(parent) = spent.first() else {
+            return Ok(());
+        };
+        let kind = match parent.kind {
+            TransactionKind::Funding => TransactionKind::Close,
+            TransactionKind::Close | TransactionKind::Sweep => TransactionKind::Sweep,
+            _ => return Ok(()),
+        };
+        let amount_sat = tx.output.iter().map(|output| output.value).sum::<u64>();
+        // the close spends only the funding output, so the fee
+        // is what is missing from the channel value.
+        let fee_sat = match (&kind, parent.amount_sat, tx.input.len()) {
+            (TransactionKind::Close, Some(funding), 1) => funding.checked_sub(amount_sat),
+            _ => None,
+        };
+        log::info!(target: "transactions", "indexing {:?} transaction `{}`", kind, tx.txid());
+        self.store(TransactionRecord {
+            txid: tx.txid().to_string(),
+            kind,
+            amount_sat: Some(amount_sat),
+            fee_sat,
+            channel_id: parent.channel_id.clone(),
+            label: None,
+            block_height: height,
+            confirmations: 0,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        })
+    }
+
+    /// Merge the transactions of the wallet with the one inside the
+    /// index, and compute the confirmations with the `tip`.
+    pub fn list(&self, wallet: Vec<TransactionRecord>, tip: u32) -> Vec<TransactionRecord> {
+        let mut transactions = self.transactions.lock().unwrap().clone();
+        for tx in wallet {
+            match transactions.get_mut(&tx.txid) {
+                Some(record) => {
+                    record.fee_sat = record.fee_sat.or(tx.fee_sat);
+                    record.block_height = record.block_height.or(tx.block_height);
+                    record.timestamp = record.timestamp.min(tx.timestamp);
+                }
+                None => {
+                    transactions.insert(tx.txid.clone(), tx);
+                }
+            }
+        }
+        let mut transactions = transactions
+            .into_values()
+            .map(|mut record| {
+                record.confirmations = record
+                    .block_height
+                    .map(|height| tip.saturating_sub(height) + 1)
+                    .unwrap_or(0);
+                record
+            })
+            .collect::<Vec<_>>();
+        transactions.sort_by_key(|record| record.timestamp);
+        transactions
+    }
+
+    pub fn watch(self: Arc<Self>, handler: Arc<LampoHandler>) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let events = handler.events();
+            loop {
+                let Ok(event) = events.recv() else {
+                    continue;
+                };
+                let result = match event {
+                    Event::Lightning(LightningEvent::FundingChannelEnd {
+                        channel_value_satoshis,
+                        funding_transaction,
+                        ..
+                    }) => self.record_funding(&funding_transaction, channel_value_satoshis),
+                    Event::Lightning(LightningEvent::ChannelPending {
+                        funding_transaction,
+                        ..
+                    }) => {
+                        let channel_id = ChannelId::v1_from_funding_txid(
+                            funding_transaction.txid.as_byte_array(),
+                            funding_transaction.vout as u16,
+                        );
+                        self.record_channel(&funding_transaction, &channel_id)
+                    }
+                    Event::OnChain(OnChainEvent::SendRawTransaction(tx)) => {
+                        self.process_transaction(&tx, None)
+                    }
+                    Event::OnChain(OnChainEvent::NewBlock(block)) => {
+                        let height = block.bip34_block_height().ok().map(|height| height as u32);
+                        block
+                            .txdata
+                            .iter()
+                            .try_for_each(|tx| self.process_transaction(tx, height))
+                    }
+                    _ => continue,
+                };
+                if let Err(err) = result {
+                    log::error!(target: "transactions", "impossible index the transaction: {err}");
+                }
+            }
+        })
+    }
+}
@@ -34,8 +34,8 @@ pub fn init_connection_test() -> error::Result<()> {
         "connect",
         Connect {
             node_id: info.id,
-            addr: "127.0.0.1".to_owned(),
-            port: cln.port.into(),
+            addr: Some("127.0.0.1".to_owned()),
+            port: Some(cln.port.into()),
         },
     )?;
     log::debug!("lampo connected with cln {:?}", response);
@@ -61,8 +61,8 @@ pub fn fund_a_simple_channel_from_lampo() {
             "connect",
             Connect {
                 node_id: info.id.clone(),
-                addr: "127.0.0.1".to_owned(),
-                port: cln.port.into(),
+                addr: Some("127.0.0.1".to_owned()),
+                port: Some(cln.port.into()),
             },
         )
         .unwrap();
@@ -90,6 +90,10 @@ pub fn fund_a_simple_channel_from_lampo() {
                 amount: 100000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         .unwrap();
@@ -298,6 +302,10 @@ pub fn decode_cln_offer_from_lampo() {
                 amount: 500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         // Wait a little bit that the open channel will finish!
@@ -405,6 +413,10 @@ pub fn decode_cln_offer_from_lampo_minimal_offer() {
                 amount: 500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         // Wait a little bit that the open channel will finish!
@@ -506,6 +518,10 @@ pub fn fetchinvoice_cln_offer_from_lampo() {
                 amount: 500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         // Wait a little bit that the open channel will finish!
@@ -713,6 +729,10 @@ pub fn pay_invoice_to_cln() {
                 amount: 500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         // Wait a little bit that the open channel will finish!
@@ -819,6 +839,10 @@ fn be_able_to_kesend_payments() {
                 amount: 1_500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         .unwrap();
@@ -872,6 +896,7 @@ fn be_able_to_kesend_payments() {
         request::KeySend {
             destination: PublicKey::from_str(info_cln.id.as_str()).unwrap(),
             amount_msat: 100_00_000,
+            first_hops: Default::default(),
         },
     );
     assert!(result.is_ok(), "{:?}", result);
@@ -913,6 +938,10 @@ fn test_closing_two_channels_without_channelid_fails() {
                 amount: 1_500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         .unwrap();
@@ -927,6 +956,10 @@ fn test_closing_two_channels_without_channelid_fails() {
                 amount: 1_000_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         .unwrap();
@@ -979,6 +1012,7 @@ fn test_closing_two_channels_without_channelid_fails() {
         request::CloseChannel {
             node_id: info_cln.id.to_string(),
             channel_id: None,
+            simulate: None,
         },
     );
 
@@ -1021,6 +1055,10 @@ fn test_lampo_to_cln_close_channel_with_channel_id_success() {
                 amount: 1_500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         .unwrap();
@@ -1074,6 +1112,7 @@ fn test_lampo_to_cln_close_channel_with_channel_id_success() {
         request::CloseChannel {
             node_id: info_cln.id.to_string(),
             channel_id: Some(channels.channels.first().unwrap().channel_id.to_string()),
+            simulate: None,
         },
     );
     assert!(result.is_ok(), "{:?}", result);
@@ -1123,6 +1162,10 @@ fn test_lampo_to_cln_close_channel_without_channel_id_success() {
                 amount: 1_500_000_000,
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         .unwrap();
@@ -1176,6 +1219,7 @@ fn test_lampo_to_cln_close_channel_without_channel_id_success() {
         request::CloseChannel {
             node_id: info_cln.id.to_string(),
             channel_id: None,
+            simulate: None,
         },
     );
     assert!(result.is_ok(), "{:?}", result);
@@ -1223,6 +1267,7 @@ fn test_close_channel_without_opening_a_channel_fails() {
         request::CloseChannel {
             node_id: info_cln.id.to_string(),
             channel_id: None,
+            simulate: None,
         },
     );
     assert!(result.is_err(), "{:?}", result);
@@ -1287,8 +1332,8 @@ fn test_sync_gossip_from_network_cln() {
             "connect",
             Connect {
                 node_id: cln1_info.id,
-                addr: "127.0.0.1".to_owned(),
-                port: cln1.port.into(),
+                addr: Some("127.0.0.1".to_owned()),
+                port: Some(cln1.port.into()),
             },
         )
         .unwrap();
@@ -4,6 +4,8 @@
 use std::sync::Arc;
 use std::time::Duration;
 
+use lampo_common::bitcoin::hashes::sha256::Hash as Sha256;
+use lampo_common::bitcoin::hashes::Hash;
 use lampo_common::error;
 use lampo_common::event::ln::LightningEvent;
 use lampo_common::event::onchain::OnChainEvent;
@@ -12,6 +14,7 @@ use lampo_common::handler::Handler;
 use lampo_common::json;
 use lampo_common::model::{request, response};
 
+use lampo_testing::cluster::LampoCluster;
 use lampo_testing::prelude::*;
 use lampo_testing::wait;
 use lampo_testing::LampoTesting;
@@ -31,8 +34,8 @@ pub fn init_connection_test_between_lampo() -> error::Result<()> {
             "connect",
             request::Connect {
                 node_id: node1.info.node_id,
-                addr: "127.0.0.1".to_owned(),
-                port: node1.port,
+                addr: Some("127.0.0.1".to_owned()),
+                port: Some(node1.port),
             },
         )
         .unwrap();
@@ -53,8 +56,8 @@ pub fn fund_a_simple_channel_from() -> error::Result<()> {
             "connect",
             request::Connect {
                 node_id: node1.info.node_id.clone(),
-                addr: "127.0.0.1".to_owned(),
-                port: node1.port,
+                addr: Some("127.0.0.1".to_owned()),
+                port: Some(node1.port),
             },
         )
         .unwrap();
@@ -84,6 +87,10 @@ pub fn fund_a_simple_channel_from() -> error::Result<()> {
                 public: true,
                 port: None,
                 addr: None,
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         .unwrap();
@@ -152,6 +159,10 @@ pub fn pay_invoice_simple_case_lampo() -> error::Result<()> {
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
                 port: Some(node2.port),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         .unwrap();
@@ -201,7 +212,10 @@ pub fn pay_invoice_simple_case_lampo() -> error::Result<()> {
         request::GenerateInvoice {
             description: "making sure that we can work betwen lampo version".to_owned(),
             amount_msat: Some(100_000_000),
+            fiat_amount: None,
             expiring_in: None,
+            qr: None,
+            fallback: None,
         },
     )?;
 
@@ -212,6 +226,10 @@ pub fn pay_invoice_simple_case_lampo() -> error::Result<()> {
         request::Pay {
             invoice_str: invoice.bolt11,
             amount: None,
+            simulate: None,
+            first_hops: Default::default(),
+            hints: Default::default(),
+            route: None,
         },
     )?;
     log::info!(target: &node1.info.node_id, "payment made `{:?}`", pay);
@@ -250,6 +268,10 @@ pub fn pay_offer_simple_case_lampo() -> error::Result<()> {
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
                 port: Some(node2.port),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         .unwrap();
@@ -299,6 +321,7 @@ pub fn pay_offer_simple_case_lampo() -> error::Result<()> {
         request::GenerateOffer {
             description: Some("making sure that we can work betwen lampo version".to_owned()),
             amount_msat: Some(100_000_000),
+            issuer: None,
         },
     )?;
 
@@ -309,6 +332,10 @@ pub fn pay_offer_simple_case_lampo() -> error::Result<()> {
         request::Pay {
             invoice_str: offer.bolt12,
             amount: None,
+            simulate: None,
+            first_hops: Default::default(),
+            hints: Default::default(),
+            route: None,
         },
     )?;
     log::info!(target: &node1.info.node_id, "payment made `{:?}`", pay);
@@ -347,6 +374,10 @@ pub fn pay_offer_minimal_offer() -> error::Result<()> {
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
                 port: Some(node2.port),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         .unwrap();
@@ -396,6 +427,7 @@ pub fn pay_offer_minimal_offer() -> error::Result<()> {
         request::GenerateOffer {
             description: None,
             amount_msat: None,
+            issuer: None,
         },
     )?;
 
@@ -406,6 +438,10 @@ pub fn pay_offer_minimal_offer() -> error::Result<()> {
         request::Pay {
             invoice_str: offer.bolt12,
             amount: Some(100_000_000),
+            simulate: None,
+            first_hops: Default::default(),
+            hints: Default::default(),
+            route: None,
         },
     )?;
     log::info!(target: &node1.info.node_id, "payment made `{:?}`", pay);
@@ -444,6 +480,10 @@ pub fn decode_offer() -> error::Result<()> {
                 public: true,
                 addr: Some("127.0.0.1".to_owned()),
                 port: Some(node2.port),
+                external_funding: None,
+                push_msat: None,
+                feerate: None,
+                utxos: None,
             },
         )
         .unwrap();
@@ -493,6 +533,7 @@ pub fn decode_offer() -> error::Result<()> {
         request::GenerateOffer {
             description: None,
             amount_msat: None,
+            issuer: None,
         },
     )?;
 
@@ -509,3 +550,123 @@ pub fn decode_offer() -> error::Result<()> {
     log::info!(target: &node2.info.node_id, "decode offer `{:?}`", decode);
     Ok(())
 }
+
+#[test]
+pub fn pay_invoice_with_cluster() -> error::Result<()> {
+    init();
+    let cluster = LampoCluster::new(2)?;
+    let node1 = cluster.node(0);
+    let node2 = cluster.node(1);
+
+    node1.fund_wallet(101)?;
+    node1.open_channel(&node2, 1_000_000)?;
+    let channels: response::Channels = node1.lampod().call("channels", json::json!({}))?;
+    let sendable = channels
+        .channels
+        .first()
+        .unwrap()
+        .available_balance_for_send_msat;
+
+    let pay = node1.pay_invoice(&node2, 100_000_000)?;
+    log::info!(target: &node1.info.node_id, "payment made `{:?}`", pay);
+    assert!(
+        matches!(pay.state, response::PaymentState::Success),
+        "{:?}",
+        pay
+    );
+    assert!(pay.payment_preimage.is_some());
+
+    // the claim of the htlc can land after the payment event.
+    wait!(|| {
+        let channels: response::Channels =
+            node1.lampod().call("channels", json::json!({})).unwrap();
+        let channel = channels.channels.first().unwrap();
+        if channel.available_balance_for_send_msat + 100_000_000 > sendable {
+            return Err(());
+        }
+        let channels: response::Channels =
+            node2.lampod().call("channels", json::json!({})).unwrap();
+        let channel = channels.channels.first().unwrap();
+        if channel.available_balance_for_send_msat == 0 {
+            return Err(());
+        }
+        Ok(())
+    });
+    Ok(())
+}
+
+#[test]
+pub fn pending_htlcs_of_a_hold_invoice() -> error::Result<()> {
+    init();
+    let cluster = LampoCluster::new(2)?;
+    let node1 = cluster.node(0);
+    let node2 = cluster.node(1);
+
+    node1.fund_wallet(101)?;
+    node1.open_channel(&node2, 1_000_000)?;
+
+    let preimage = "01".repeat(32);
+    let payment_hash = Sha256::hash(&[1u8; 32]).to_string();
+    let invoice: response::Invoice = node2.lampod().call(
+        "holdinvoice",
+        request::GenerateHoldInvoice {
+            payment_hash,
+            amount_msat: Some(100_000_000),
+            description: "hold invoice".to_owned(),
+            expiring_in: None,
+            label: None,
+        },
+    )?;
+    // the payment ends only when the invoice is settled.
+    let payer = node1.clone();
+    let pay = std::thread::spawn(move || {
+        payer.lampod().call::<_, response::PayResult>(
+            "pay",
+            request::Pay {
+                invoice_str: invoice.bolt11,
+                amount: None,
+                simulate: None,
+                first_hops: Default::default(),
+                hints: Default::default(),
+                route: None,
+            },
+        )
+    });
+
+    wait!(|| {
+        let channels: response::Channels =
+            node1.lampod().call("channels", json::json!({})).unwrap();
+        let channel = channels.channels.first().unwrap();
+        if channel.pending_outgoing_htlc_sat != 100_000 {
+            return Err(());
+        }
+        let channels: response::Channels =
+            node2.lampod().call("channels", json::json!({})).unwrap();
+        let channel = channels.channels.first().unwrap();
+        if channel.pending_incoming_htlc_sat != 100_000 {
+            return Err(());
+        }
+        assert_eq!(channel.unsettled_balance_sat, 100_000);
+        Ok(())
+    });
+
+    let _: json::Value = node2
+        .lampod()
+        .call("settleinvoice", request::SettleInvoice { preimage })?;
+    let pay = pay.join().unwrap()?;
+    assert!(
+        matches!(pay.state, response::PaymentState::Success),
+        "{:?}",
+        pay
+    );
+    wait!(|| {
+        let channels: response::Channels =
+            node1.lampod().call("channels", json::json!({})).unwrap();
+        let channel = channels.channels.first().unwrap();
+        if channel.unsettled_balance_sat != 0 {
+            return Err(());
+        }
+        Ok(())
+    });
+    Ok(())
+}